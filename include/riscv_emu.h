@@ -0,0 +1,134 @@
+#ifndef RISCV_EMU_H
+#define RISCV_EMU_H
+
+/* Generated by cbindgen from src/ffi.rs. Don't edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// Why [`riscv_emu_step`] or [`riscv_emu_run`] returned.
+typedef enum RiscvEmuStatus {
+  RISCV_EMU_STATUS_EXECUTED = 0,
+  RISCV_EMU_STATUS_BREAKPOINT = 1,
+  RISCV_EMU_STATUS_WATCHPOINT = 2,
+  RISCV_EMU_STATUS_REGISTER_WRITE = 3,
+  RISCV_EMU_STATUS_REACHED_PC = 4,
+  RISCV_EMU_STATUS_STEP_LIMIT = 5,
+  RISCV_EMU_STATUS_IDLE = 6,
+  // The guest exited through semihosting.
+  RISCV_EMU_STATUS_EXITED = 7,
+  // An exception was returned to the host; see
+  // [`riscv_emu_last_error`].
+  RISCV_EMU_STATUS_EXCEPTION = -1,
+} RiscvEmuStatus;
+
+// An RV32 hart and its bus.
+typedef struct RiscvEmu RiscvEmu;
+
+// Reads `size` bytes (1, 2 or 4) at `offset` into the region.
+typedef uint32_t (*RiscvEmuMmioRead)(void *ctx, uint32_t offset, uint32_t size);
+
+// Writes the low `size` bytes (1, 2 or 4) of `value` at `offset`.
+typedef void (*RiscvEmuMmioWrite)(void *ctx, uint32_t offset, uint32_t size, uint32_t value);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// A hart with `ram_size` bytes of RAM at `ram_base`, starting there. Null
+// if the RAM can't be allocated.
+struct RiscvEmu *riscv_emu_new(uint32_t ram_base, size_t ram_size);
+
+// # Safety
+//
+// `emu` must have come from [`riscv_emu_new`] and not been freed, or be
+// null.
+void riscv_emu_free(struct RiscvEmu *emu);
+
+// Why the last call that returned -1 or [`RiscvEmuStatus::Exception`]
+// failed. Valid until the next call that fails.
+//
+// # Safety
+//
+// `emu` must be a live handle.
+const char *riscv_emu_last_error(const struct RiscvEmu *emu);
+
+// Load an ELF, an Intel HEX file or else a raw binary at the start of
+// RAM, and jump to its entry point.
+//
+// # Safety
+//
+// `emu` must be a live handle and `data` must point to `len` bytes.
+int riscv_emu_load(struct RiscvEmu *emu, const uint8_t *data, size_t len);
+
+// Execute one instruction.
+//
+// # Safety
+//
+// `emu` must be a live handle.
+enum RiscvEmuStatus riscv_emu_step(struct RiscvEmu *emu);
+
+// Run up to `max_steps` instructions.
+//
+// # Safety
+//
+// `emu` must be a live handle.
+enum RiscvEmuStatus riscv_emu_run(struct RiscvEmu *emu, uint64_t max_steps);
+
+// Register `reg` (0 to 31), or 0 if there's no such register.
+//
+// # Safety
+//
+// `emu` must be a live handle.
+uint32_t riscv_emu_get_reg(const struct RiscvEmu *emu, uint32_t reg);
+
+// Set register `reg` (1 to 31). Writes to x0 and beyond x31 are ignored.
+//
+// # Safety
+//
+// `emu` must be a live handle.
+void riscv_emu_set_reg(struct RiscvEmu *emu, uint32_t reg, uint32_t value);
+
+// # Safety
+//
+// `emu` must be a live handle.
+uint32_t riscv_emu_get_pc(const struct RiscvEmu *emu);
+
+// # Safety
+//
+// `emu` must be a live handle.
+void riscv_emu_set_pc(struct RiscvEmu *emu, uint32_t pc);
+
+// Copy `len` bytes of RAM from `addr` to `buf`.
+//
+// # Safety
+//
+// `emu` must be a live handle and `buf` must have room for `len` bytes.
+int riscv_emu_read_mem(struct RiscvEmu *emu, uint32_t addr, uint8_t *buf, size_t len);
+
+// Copy `len` bytes from `data` to RAM at `addr`.
+//
+// # Safety
+//
+// `emu` must be a live handle and `data` must point to `len` bytes.
+int riscv_emu_write_mem(struct RiscvEmu *emu, uint32_t addr, const uint8_t *data, size_t len);
+
+// Serve loads and stores to `base..base + size` with `read` and `write`,
+// which are passed `ctx` and the offset from `base`.
+//
+// # Safety
+//
+// `emu` must be a live handle, and the callbacks must be safe to call
+// with `ctx` for as long as it lives.
+void riscv_emu_add_mmio(struct RiscvEmu *emu,
+                        uint32_t base,
+                        uint32_t size,
+                        RiscvEmuMmioRead read,
+                        RiscvEmuMmioWrite write,
+                        void *ctx);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RISCV_EMU_H */
@@ -0,0 +1,1356 @@
+//! A small two-pass assembler for RV32I/RV64I + Zicsr + Zalrsc + Zawrs + Zba +
+//! Zbkb + Zkne/Zknd/Zknh + F/D text, and the subset of V that the emulator
+//! implements.
+//!
+//! ```text
+//!         addi x1, x0, 5
+//! loop:   addi x1, x1, -1
+//!         bne  x1, zero, loop
+//!         ebreak
+//! ```
+//!
+//! Registers may be written as `x0`-`x31` or by ABI name, immediates in
+//! decimal, hex (`0x`) or binary (`0b`), and branch/jump targets as labels or
+//! raw byte offsets. Floating-point registers are `f0`-`f31` or their ABI
+//! names, and integer registers are accepted in their place for Zfinx.
+//! Vector registers are `v0`-`v31`, and a trailing `v0.t` masks an
+//! instruction.
+//! The usual pseudo-instructions expand to real ones: `nop`, `mv`, `not`,
+//! `neg`, `j`, `jr`, `ret`, and `li`, which loads a 32-bit value
+//! (sign-extended on RV64) in one or two instructions. `la` and `call` take
+//! a label or an absolute address and always expand to AUIPC and an ADDI
+//! or JALR.
+//! Comments start with `#` or `//`. RV64-only mnemonics
+//! and shift amounts above 31 are accepted; decoding them on an RV32 hart
+//! raises an illegal instruction.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::csr;
+use crate::encode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based source line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assemble `source` as if it will be loaded at address 0.
+pub fn assemble(source: &str) -> Result<Vec<u32>, AsmError> {
+    assemble_at(0, source)
+}
+
+/// Assemble `source` for loading at `base`. Labels resolve to absolute
+/// addresses, which only matters for `.word label`.
+pub fn assemble_at(base: u32, source: &str) -> Result<Vec<u32>, AsmError> {
+    let lines = parse(source)?;
+
+    let mut labels = HashMap::new();
+    let mut addr = base;
+    for line in &lines {
+        for label in &line.labels {
+            if labels.insert(label.clone(), addr).is_some() {
+                return Err(error(line.number, format!("duplicate label `{}`", label)));
+            }
+        }
+        if let Some((mnemonic, operands)) = &line.statement {
+            addr = addr.wrapping_add(4 * length(mnemonic, operands));
+        }
+    }
+
+    let mut words = Vec::new();
+    let mut addr = base;
+    for line in &lines {
+        if let Some((mnemonic, operands)) = &line.statement {
+            let ctx = Context {
+                line: line.number,
+                pc: addr,
+                labels: &labels,
+            };
+            let expanded = ctx.expand(mnemonic, operands)?;
+            addr = addr.wrapping_add(4 * expanded.len() as u32);
+            words.extend(expanded);
+        }
+    }
+
+    Ok(words)
+}
+
+struct Line {
+    number: usize,
+    labels: Vec<String>,
+    statement: Option<(String, Vec<String>)>,
+}
+
+fn error(line: usize, message: impl Into<String>) -> AsmError {
+    AsmError {
+        line,
+        message: message.into(),
+    }
+}
+
+fn parse(source: &str) -> Result<Vec<Line>, AsmError> {
+    let mut lines = Vec::new();
+
+    for (i, raw) in source.lines().enumerate() {
+        let number = i + 1;
+        let mut text = raw;
+        for marker in ["#", "//"] {
+            if let Some(idx) = text.find(marker) {
+                text = &text[..idx];
+            }
+        }
+        let mut text = text.trim();
+
+        let mut labels = Vec::new();
+        while let Some(idx) = text.find(':') {
+            let label = text[..idx].trim();
+            if !is_identifier(label) {
+                return Err(error(number, format!("invalid label `{}`", label)));
+            }
+            labels.push(label.to_string());
+            text = text[idx + 1..].trim();
+        }
+
+        let statement = if text.is_empty() {
+            None
+        } else {
+            let (mnemonic, rest) = match text.find(char::is_whitespace) {
+                Some(idx) => (&text[..idx], text[idx..].trim()),
+                None => (text, ""),
+            };
+            let mnemonic = mnemonic.to_ascii_lowercase();
+
+            // Section/symbol directives don't emit anything.
+            if mnemonic.starts_with('.') && mnemonic != ".word" {
+                None
+            } else {
+                let operands = if rest.is_empty() {
+                    Vec::new()
+                } else {
+                    rest.split(',').map(|s| s.trim().to_string()).collect()
+                };
+                Some((mnemonic, operands))
+            }
+        };
+
+        lines.push(Line {
+            number,
+            labels,
+            statement,
+        });
+    }
+
+    Ok(lines)
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Parse a register by number (`x5`) or ABI name (`t0`).
+pub fn parse_register(name: &str) -> Option<u8> {
+    let name = name.trim().to_ascii_lowercase();
+
+    if let Some(n) = name.strip_prefix('x') {
+        return n.parse::<u8>().ok().filter(|&n| n < 32);
+    }
+
+    let reg = match name.as_str() {
+        "zero" => 0,
+        "ra" => 1,
+        "sp" => 2,
+        "gp" => 3,
+        "tp" => 4,
+        "t0" => 5,
+        "t1" => 6,
+        "t2" => 7,
+        "s0" | "fp" => 8,
+        "s1" => 9,
+        _ => {
+            let (prefix, n) = name.split_at(1);
+            let n: u8 = n.parse().ok()?;
+            match (prefix, n) {
+                ("a", 0..=7) => 10 + n,
+                ("s", 2..=11) => 16 + n,
+                ("t", 3..=6) => 25 + n,
+                _ => return None,
+            }
+        }
+    };
+
+    Some(reg)
+}
+
+/// Parse a floating-point register by number (`f5`) or ABI name (`ft5`).
+pub fn parse_float_register(name: &str) -> Option<u8> {
+    let name = name.trim().to_ascii_lowercase();
+
+    let (prefix, n) = name.split_at(name.find(|c: char| c.is_ascii_digit())?);
+    let n: u8 = n.parse().ok()?;
+    let reg = match (prefix, n) {
+        ("f", 0..=31) => n,
+        ("ft", 0..=7) => n,
+        ("fs", 0..=1) => 8 + n,
+        ("fa", 0..=7) => 10 + n,
+        ("fs", 2..=11) => 16 + n,
+        ("ft", 8..=11) => 20 + n,
+        _ => return None,
+    };
+
+    Some(reg)
+}
+
+/// Parse a vector register, `v0`-`v31`.
+pub fn parse_vector_register(name: &str) -> Option<u8> {
+    let n = name.trim().to_ascii_lowercase();
+    n.strip_prefix('v')?.parse::<u8>().ok().filter(|&n| n < 32)
+}
+
+/// Parse a CSR by name, such as `mstatus` or `pmpaddr3`, or by number.
+pub fn parse_csr(name: &str) -> Option<u16> {
+    let addr = match name.to_ascii_lowercase().as_str() {
+        "fflags" => csr::FFLAGS,
+        "frm" => csr::FRM,
+        "fcsr" => csr::FCSR,
+        "vstart" => csr::VSTART,
+        "vl" => csr::VL,
+        "vtype" => csr::VTYPE,
+        "vlenb" => csr::VLENB,
+        "mstatus" => csr::MSTATUS,
+        "misa" => csr::MISA,
+        "mstatush" => csr::MSTATUSH,
+        "medeleg" => csr::MEDELEG,
+        "mideleg" => csr::MIDELEG,
+        "mie" => csr::MIE,
+        "mtvec" => csr::MTVEC,
+        "mscratch" => csr::MSCRATCH,
+        "mepc" => csr::MEPC,
+        "mcause" => csr::MCAUSE,
+        "mtval" => csr::MTVAL,
+        "mip" => csr::MIP,
+        "mhartid" => csr::MHARTID,
+        "tselect" => csr::TSELECT,
+        "tdata1" => csr::TDATA1,
+        "tdata2" => csr::TDATA2,
+        "tdata3" => csr::TDATA3,
+        "tinfo" => csr::TINFO,
+        "sstatus" => csr::SSTATUS,
+        "sie" => csr::SIE,
+        "stvec" => csr::STVEC,
+        "scounteren" => csr::SCOUNTEREN,
+        "sscratch" => csr::SSCRATCH,
+        "sepc" => csr::SEPC,
+        "scause" => csr::SCAUSE,
+        "stval" => csr::STVAL,
+        "sip" => csr::SIP,
+        "satp" => csr::SATP,
+        "vsstatus" => csr::VSSTATUS,
+        "vsie" => csr::VSIE,
+        "vstvec" => csr::VSTVEC,
+        "vsscratch" => csr::VSSCRATCH,
+        "vsepc" => csr::VSEPC,
+        "vscause" => csr::VSCAUSE,
+        "vstval" => csr::VSTVAL,
+        "vsip" => csr::VSIP,
+        "vsatp" => csr::VSATP,
+        "hstatus" => csr::HSTATUS,
+        "hedeleg" => csr::HEDELEG,
+        "hideleg" => csr::HIDELEG,
+        "hie" => csr::HIE,
+        "htimedelta" => csr::HTIMEDELTA,
+        "hcounteren" => csr::HCOUNTEREN,
+        "hgeie" => csr::HGEIE,
+        "htimedeltah" => csr::HTIMEDELTAH,
+        "htval" => csr::HTVAL,
+        "hip" => csr::HIP,
+        "hvip" => csr::HVIP,
+        "htinst" => csr::HTINST,
+        "hgatp" => csr::HGATP,
+        "hgeip" => csr::HGEIP,
+        "mtinst" => csr::MTINST,
+        "mtval2" => csr::MTVAL2,
+        "mcounteren" => csr::MCOUNTEREN,
+        "menvcfg" => csr::MENVCFG,
+        "menvcfgh" => csr::MENVCFGH,
+        "senvcfg" => csr::SENVCFG,
+        "mcountinhibit" => csr::MCOUNTINHIBIT,
+        "mvendorid" => csr::MVENDORID,
+        "marchid" => csr::MARCHID,
+        "mimpid" => csr::MIMPID,
+        "mconfigptr" => csr::MCONFIGPTR,
+        "mcycle" => csr::MCYCLE,
+        "minstret" => csr::MINSTRET,
+        "mcycleh" => csr::MCYCLEH,
+        "minstreth" => csr::MINSTRETH,
+        "cycle" => csr::CYCLE,
+        "time" => csr::TIME,
+        "instret" => csr::INSTRET,
+        "cycleh" => csr::CYCLEH,
+        "timeh" => csr::TIMEH,
+        "instreth" => csr::INSTRETH,
+        name => {
+            return parse_hpm_csr(name)
+                .or_else(|| parse_pmp_csr(name))
+                .or_else(|| parse_number(name).and_then(|n| u16::try_from(n).ok()));
+        }
+    };
+
+    Some(addr)
+}
+
+/// `mhpmcounter3` through `mhpmcounter31h`, their user-level shadows and
+/// `mhpmevent3` through `mhpmevent31`.
+fn parse_hpm_csr(name: &str) -> Option<u16> {
+    let (name, high) = match name.strip_suffix('h') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let (first, n) = [
+        ("mhpmcounter", csr::MHPMCOUNTER3, csr::MHPMCOUNTER3H),
+        ("hpmcounter", csr::HPMCOUNTER3, csr::HPMCOUNTER3H),
+        ("mhpmevent", csr::MHPMEVENT3, 0),
+    ]
+    .into_iter()
+    .find_map(|(prefix, low, upper)| {
+        let n: u16 = name.strip_prefix(prefix)?.parse().ok()?;
+        Some((if high { upper } else { low }, n))
+    })?;
+
+    ((3..=31).contains(&n) && first != 0).then(|| first + n - 3)
+}
+
+/// `pmpcfg0` through `pmpcfg15` and `pmpaddr0` through `pmpaddr63`.
+fn parse_pmp_csr(name: &str) -> Option<u16> {
+    if let Some(n) = name.strip_prefix("pmpcfg") {
+        let n: u16 = n.parse().ok()?;
+        return (n <= 15).then_some(csr::PMPCFG0 + n);
+    }
+    let n: u16 = name.strip_prefix("pmpaddr")?.parse().ok()?;
+    (n <= 63).then_some(csr::PMPADDR0 + n)
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(&hex.replace('_', ""), 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(&bin.replace('_', ""), 2).ok()?
+    } else {
+        digits.replace('_', "").parse::<i64>().ok()?
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+/// How many words a statement assembles to, for laying out labels before
+/// they can be resolved. Agrees with [`Context::expand`] on any statement
+/// that assembles.
+fn length(mnemonic: &str, operands: &[String]) -> u32 {
+    match mnemonic {
+        "la" | "call" => 2,
+        "li" => match operands.get(1).and_then(|imm| parse_number(imm)) {
+            Some(value) if (i32::MIN as i64..=u32::MAX as i64).contains(&value) => {
+                encode::li(0, value as i32).len() as u32
+            }
+            _ => 1,
+        },
+        _ => 1,
+    }
+}
+
+struct Context<'a> {
+    line: usize,
+    pc: u32,
+    labels: &'a HashMap<String, u32>,
+}
+
+impl Context<'_> {
+    fn err<T>(&self, message: impl Into<String>) -> Result<T, AsmError> {
+        Err(error(self.line, message))
+    }
+
+    fn reg(&self, operand: &str) -> Result<u32, AsmError> {
+        match parse_register(operand) {
+            Some(r) => Ok(r as u32),
+            None => self.err(format!("unknown register `{}`", operand)),
+        }
+    }
+
+    /// A floating-point operand: an `f` register, or an `x` register for
+    /// Zfinx.
+    fn freg(&self, operand: &str) -> Result<u32, AsmError> {
+        match parse_float_register(operand).or_else(|| parse_register(operand)) {
+            Some(r) => Ok(r as u32),
+            None => self.err(format!("unknown register `{}`", operand)),
+        }
+    }
+
+    fn vreg(&self, operand: &str) -> Result<u32, AsmError> {
+        match parse_vector_register(operand) {
+            Some(r) => Ok(r as u32),
+            None => self.err(format!("unknown vector register `{}`", operand)),
+        }
+    }
+
+    fn imm(&self, operand: &str, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = match parse_number(operand) {
+            Some(v) => v,
+            None => return self.err(format!("invalid immediate `{}`", operand)),
+        };
+
+        if value < min || value > max {
+            return self.err(format!(
+                "immediate {} out of range [{}, {}]",
+                value, min, max
+            ));
+        }
+
+        Ok(value)
+    }
+
+    /// A branch/jump target: either a label or a literal byte offset.
+    fn offset(&self, operand: &str, bits: u32) -> Result<i64, AsmError> {
+        let offset = match self.labels.get(operand) {
+            Some(&target) => target.wrapping_sub(self.pc) as i32 as i64,
+            None if is_identifier(operand) => {
+                return self.err(format!("undefined label `{}`", operand));
+            }
+            None => self.imm(operand, i64::MIN, i64::MAX)?,
+        };
+
+        let limit = 1i64 << (bits - 1);
+        if offset < -limit || offset >= limit {
+            return self.err(format!("target `{}` is out of range", operand));
+        }
+        if offset % 2 != 0 {
+            return self.err(format!("target `{}` is not 2-byte aligned", operand));
+        }
+
+        Ok(offset)
+    }
+
+    /// `imm(reg)` as used by loads, stores and JALR.
+    fn mem_operand(&self, operand: &str) -> Result<(i64, u32), AsmError> {
+        let (imm, rest) = match operand.find('(') {
+            Some(idx) => (&operand[..idx], &operand[idx + 1..]),
+            None => return self.err(format!("expected `offset(register)`, got `{}`", operand)),
+        };
+        let reg = match rest.strip_suffix(')') {
+            Some(r) => self.reg(r)?,
+            None => return self.err(format!("missing `)` in `{}`", operand)),
+        };
+        let imm = if imm.trim().is_empty() {
+            0
+        } else {
+            self.imm(imm, -2048, 2047)?
+        };
+
+        Ok((imm, reg))
+    }
+
+    fn expect(&self, operands: &[String], n: usize, mnemonic: &str) -> Result<(), AsmError> {
+        if operands.len() != n {
+            return self.err(format!(
+                "`{}` takes {} operand(s), got {}",
+                mnemonic,
+                n,
+                operands.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// An absolute address: a label, or a number.
+    fn address(&self, operand: &str) -> Result<u32, AsmError> {
+        match self.labels.get(operand) {
+            Some(&addr) => Ok(addr),
+            None if is_identifier(operand) => self.err(format!("undefined label `{}`", operand)),
+            None => Ok(self.imm(operand, i32::MIN as i64, u32::MAX as i64)? as u32),
+        }
+    }
+
+    /// The offset from here to `operand`'s address, split for AUIPC and the
+    /// instruction after it.
+    fn pc_relative(&self, operand: &str) -> Result<(u32, i64), AsmError> {
+        let (hi, lo) = encode::split_offset(self.address(operand)?.wrapping_sub(self.pc) as i32);
+        Ok((hi, lo as i64))
+    }
+
+    /// Assemble a statement, expanding pseudo-instructions into the real
+    /// instructions they stand for.
+    fn expand(&self, mnemonic: &str, ops: &[String]) -> Result<Vec<u32>, AsmError> {
+        let m = mnemonic;
+        let real = |mnemonic: &str, ops: &[&str]| {
+            let ops: Vec<String> = ops.iter().map(|op| op.to_string()).collect();
+            self.encode(mnemonic, &ops)
+        };
+
+        let word = match m {
+            "nop" => {
+                self.expect(ops, 0, m)?;
+                real("addi", &["zero", "zero", "0"])?
+            }
+            "mv" => {
+                self.expect(ops, 2, m)?;
+                real("addi", &[&ops[0], &ops[1], "0"])?
+            }
+            "not" => {
+                self.expect(ops, 2, m)?;
+                real("xori", &[&ops[0], &ops[1], "-1"])?
+            }
+            "neg" => {
+                self.expect(ops, 2, m)?;
+                real("sub", &[&ops[0], "zero", &ops[1]])?
+            }
+            "j" => {
+                self.expect(ops, 1, m)?;
+                real("jal", &["zero", &ops[0]])?
+            }
+            "jr" => {
+                self.expect(ops, 1, m)?;
+                real("jalr", &["zero", &ops[0], "0"])?
+            }
+            "ret" => {
+                self.expect(ops, 0, m)?;
+                real("jalr", &["zero", "ra", "0"])?
+            }
+            "li" => {
+                self.expect(ops, 2, m)?;
+                let value = self.imm(&ops[1], i32::MIN as i64, u32::MAX as i64)?;
+                return Ok(encode::li(self.reg(&ops[0])? as u8, value as i32));
+            }
+            "la" => {
+                self.expect(ops, 2, m)?;
+                let rd = self.reg(&ops[0])?;
+                let (hi, lo) = self.pc_relative(&ops[1])?;
+                return Ok(vec![
+                    (hi << 12) | (rd << 7) | 0x17,
+                    itype(lo, rd, 0x0, rd, 0x13),
+                ]);
+            }
+            "call" => {
+                self.expect(ops, 1, m)?;
+                let (hi, lo) = self.pc_relative(&ops[0])?;
+                return Ok(vec![
+                    (hi << 12) | (1 << 7) | 0x17,
+                    itype(lo, 1, 0x0, 1, 0x67),
+                ]);
+            }
+            _ => self.encode(m, ops)?,
+        };
+
+        Ok(vec![word])
+    }
+
+    fn encode(&self, mnemonic: &str, ops: &[String]) -> Result<u32, AsmError> {
+        let m = mnemonic;
+
+        let word = match m {
+            "add" | "sub" | "sll" | "slt" | "sltu" | "xor" | "srl" | "sra" | "or" | "and"
+            | "addw" | "subw" | "sllw" | "srlw" | "sraw" => {
+                self.expect(ops, 3, m)?;
+                let (funct3, funct7) = match m.trim_end_matches('w') {
+                    "add" => (0x0, 0x00),
+                    "sub" => (0x0, 0x20),
+                    "sll" => (0x1, 0x00),
+                    "slt" => (0x2, 0x00),
+                    "sltu" => (0x3, 0x00),
+                    "xor" => (0x4, 0x00),
+                    "srl" => (0x5, 0x00),
+                    "sra" => (0x5, 0x20),
+                    "or" => (0x6, 0x00),
+                    _ => (0x7, 0x00),
+                };
+                rtype(
+                    funct7,
+                    self.reg(&ops[2])?,
+                    self.reg(&ops[1])?,
+                    funct3,
+                    self.reg(&ops[0])?,
+                    if m.ends_with('w') { 0x3B } else { 0x33 },
+                )
+            }
+            "sh1add" | "sh2add" | "sh3add" | "add.uw" | "sh1add.uw" | "sh2add.uw" | "sh3add.uw" => {
+                self.expect(ops, 3, m)?;
+                let (funct3, funct7) = match m.trim_end_matches(".uw") {
+                    "sh1add" => (0x2, 0x10),
+                    "sh2add" => (0x4, 0x10),
+                    "sh3add" => (0x6, 0x10),
+                    _ => (0x0, 0x04),
+                };
+                rtype(
+                    funct7,
+                    self.reg(&ops[2])?,
+                    self.reg(&ops[1])?,
+                    funct3,
+                    self.reg(&ops[0])?,
+                    if m.ends_with(".uw") { 0x3B } else { 0x33 },
+                )
+            }
+            "aes32esi" | "aes32esmi" | "aes32dsi" | "aes32dsmi" => {
+                self.expect(ops, 4, m)?;
+                let funct5 = match m {
+                    "aes32esi" => 0x11,
+                    "aes32esmi" => 0x13,
+                    "aes32dsi" => 0x15,
+                    _ => 0x17,
+                };
+                rtype(
+                    ((self.imm(&ops[3], 0, 3)? as u32) << 5) | funct5,
+                    self.reg(&ops[2])?,
+                    self.reg(&ops[1])?,
+                    0x0,
+                    self.reg(&ops[0])?,
+                    0x33,
+                )
+            }
+            "pack" | "packh" | "packw" => {
+                self.expect(ops, 3, m)?;
+                let (funct3, opcode) = match m {
+                    "pack" => (0x4, 0x33),
+                    "packh" => (0x7, 0x33),
+                    _ => (0x4, 0x3B),
+                };
+                rtype(
+                    0x04,
+                    self.reg(&ops[2])?,
+                    self.reg(&ops[1])?,
+                    funct3,
+                    self.reg(&ops[0])?,
+                    opcode,
+                )
+            }
+            "brev8" | "zip" | "unzip" => {
+                self.expect(ops, 2, m)?;
+                let (imm, funct3) = match m {
+                    "brev8" => (0x687, 0x5),
+                    "zip" => (0x08F, 0x1),
+                    _ => (0x08F, 0x5),
+                };
+                itype(imm, self.reg(&ops[1])?, funct3, self.reg(&ops[0])?, 0x13)
+            }
+            "sha256sum0" | "sha256sum1" | "sha256sig0" | "sha256sig1" | "sha512sum0"
+            | "sha512sum1" | "sha512sig0" | "sha512sig1" => {
+                self.expect(ops, 2, m)?;
+                let function = match m {
+                    "sha256sum0" => 0x0,
+                    "sha256sum1" => 0x1,
+                    "sha256sig0" => 0x2,
+                    "sha256sig1" => 0x3,
+                    "sha512sum0" => 0x4,
+                    "sha512sum1" => 0x5,
+                    "sha512sig0" => 0x6,
+                    _ => 0x7,
+                };
+                rtype(
+                    0x08,
+                    function,
+                    self.reg(&ops[1])?,
+                    0x1,
+                    self.reg(&ops[0])?,
+                    0x13,
+                )
+            }
+            "sha512sum0r" | "sha512sum1r" | "sha512sig0l" | "sha512sig0h" | "sha512sig1l"
+            | "sha512sig1h" => {
+                self.expect(ops, 3, m)?;
+                let funct7 = match m {
+                    "sha512sum0r" => 0x28,
+                    "sha512sum1r" => 0x29,
+                    "sha512sig0l" => 0x2A,
+                    "sha512sig1l" => 0x2B,
+                    "sha512sig0h" => 0x2E,
+                    _ => 0x2F,
+                };
+                rtype(
+                    funct7,
+                    self.reg(&ops[2])?,
+                    self.reg(&ops[1])?,
+                    0x0,
+                    self.reg(&ops[0])?,
+                    0x33,
+                )
+            }
+            "addi" | "slti" | "sltiu" | "xori" | "ori" | "andi" => {
+                self.expect(ops, 3, m)?;
+                let funct3 = match m {
+                    "addi" => 0x0,
+                    "slti" => 0x2,
+                    "sltiu" => 0x3,
+                    "xori" => 0x4,
+                    "ori" => 0x6,
+                    _ => 0x7,
+                };
+                itype(
+                    self.imm(&ops[2], -2048, 2047)?,
+                    self.reg(&ops[1])?,
+                    funct3,
+                    self.reg(&ops[0])?,
+                    0x13,
+                )
+            }
+            "addiw" => {
+                self.expect(ops, 3, m)?;
+                itype(
+                    self.imm(&ops[2], -2048, 2047)?,
+                    self.reg(&ops[1])?,
+                    0x0,
+                    self.reg(&ops[0])?,
+                    0x1B,
+                )
+            }
+            "slli" | "srli" | "srai" | "slliw" | "srliw" | "sraiw" => {
+                self.expect(ops, 3, m)?;
+                let (funct3, funct7) = match m.trim_end_matches('w') {
+                    "slli" => (0x1, 0x00),
+                    "srli" => (0x5, 0x00),
+                    _ => (0x5, 0x20),
+                };
+                // A shamt of 32-63 spills into the low bit of funct7 (RV64).
+                let (max, opcode) = match m.ends_with('w') {
+                    true => (31, 0x1B),
+                    false => (63, 0x13),
+                };
+                let shamt = self.imm(&ops[2], 0, max)? as u32;
+                rtype(
+                    funct7,
+                    shamt,
+                    self.reg(&ops[1])?,
+                    funct3,
+                    self.reg(&ops[0])?,
+                    opcode,
+                )
+            }
+            "slli.uw" => {
+                self.expect(ops, 3, m)?;
+                rtype(
+                    0x04,
+                    self.imm(&ops[2], 0, 63)? as u32,
+                    self.reg(&ops[1])?,
+                    0x1,
+                    self.reg(&ops[0])?,
+                    0x1B,
+                )
+            }
+            "lb" | "lh" | "lw" | "ld" | "lbu" | "lhu" | "lwu" => {
+                self.expect(ops, 2, m)?;
+                let funct3 = match m {
+                    "lb" => 0x0,
+                    "lh" => 0x1,
+                    "lw" => 0x2,
+                    "ld" => 0x3,
+                    "lbu" => 0x4,
+                    "lhu" => 0x5,
+                    _ => 0x6,
+                };
+                let (imm, rs1) = self.mem_operand(&ops[1])?;
+                itype(imm, rs1, funct3, self.reg(&ops[0])?, 0x03)
+            }
+            "sb" | "sh" | "sw" | "sd" => {
+                self.expect(ops, 2, m)?;
+                let funct3 = match m {
+                    "sb" => 0x0,
+                    "sh" => 0x1,
+                    "sw" => 0x2,
+                    _ => 0x3,
+                };
+                let (imm, rs1) = self.mem_operand(&ops[1])?;
+                stype(imm, self.reg(&ops[0])?, rs1, funct3)
+            }
+            "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" => {
+                self.expect(ops, 3, m)?;
+                let funct3 = match m {
+                    "beq" => 0x0,
+                    "bne" => 0x1,
+                    "blt" => 0x4,
+                    "bge" => 0x5,
+                    "bltu" => 0x6,
+                    _ => 0x7,
+                };
+                btype(
+                    self.offset(&ops[2], 13)?,
+                    self.reg(&ops[0])?,
+                    self.reg(&ops[1])?,
+                    funct3,
+                )
+            }
+            "jal" => match ops.len() {
+                1 => jtype(self.offset(&ops[0], 21)?, 1),
+                _ => {
+                    self.expect(ops, 2, m)?;
+                    jtype(self.offset(&ops[1], 21)?, self.reg(&ops[0])?)
+                }
+            },
+            "jalr" => match ops.len() {
+                1 => itype(0, self.reg(&ops[0])?, 0x0, 1, 0x67),
+                2 => {
+                    let (imm, rs1) = self.mem_operand(&ops[1])?;
+                    itype(imm, rs1, 0x0, self.reg(&ops[0])?, 0x67)
+                }
+                _ => {
+                    self.expect(ops, 3, m)?;
+                    itype(
+                        self.imm(&ops[2], -2048, 2047)?,
+                        self.reg(&ops[1])?,
+                        0x0,
+                        self.reg(&ops[0])?,
+                        0x67,
+                    )
+                }
+            },
+            "lui" | "auipc" => {
+                self.expect(ops, 2, m)?;
+                let imm = self.imm(&ops[1], -(1 << 19), (1 << 20) - 1)? as u32;
+                let opcode = if m == "lui" { 0x37 } else { 0x17 };
+                ((imm & 0xFFFFF) << 12) | (self.reg(&ops[0])? << 7) | opcode
+            }
+            _ if m.starts_with("lr.") || m.starts_with("sc.") => self.encode_lrsc(m, ops)?,
+            _ if m.starts_with("hlv") || m.starts_with("hsv.") => self.encode_hlv_hsv(m, ops)?,
+            "flw" | "fld" | "fsw" | "fsd" => {
+                self.expect(ops, 2, m)?;
+                let funct3 = if m.ends_with('w') { 0x2 } else { 0x3 };
+                let (imm, rs1) = self.mem_operand(&ops[1])?;
+                match m.as_bytes()[1] {
+                    b'l' => itype(imm, rs1, funct3, self.freg(&ops[0])?, 0x07),
+                    _ => (stype(imm, self.freg(&ops[0])?, rs1, funct3) & !0x7F) | 0x27,
+                }
+            }
+            "csrrw" | "csrrs" | "csrrc" | "csrrwi" | "csrrsi" | "csrrci" => {
+                self.expect(ops, 3, m)?;
+                let funct3 = match m {
+                    "csrrw" => 0x1,
+                    "csrrs" => 0x2,
+                    "csrrc" => 0x3,
+                    "csrrwi" => 0x5,
+                    "csrrsi" => 0x6,
+                    _ => 0x7,
+                };
+                let csr = match parse_csr(&ops[1]) {
+                    Some(c) => c as u32,
+                    None => return self.err(format!("unknown CSR `{}`", ops[1])),
+                };
+                let src = if m.ends_with('i') {
+                    self.imm(&ops[2], 0, 31)? as u32
+                } else {
+                    self.reg(&ops[2])?
+                };
+                (csr << 20) | (src << 15) | (funct3 << 12) | (self.reg(&ops[0])? << 7) | 0x73
+            }
+            "ecall" | "ebreak" | "mret" | "sret" | "wfi" | "wrs.nto" | "wrs.sto" | "fence"
+            | "fence.i" => {
+                self.expect(ops, 0, m)?;
+                match m {
+                    "ecall" => 0x0000_0073,
+                    "ebreak" => 0x0010_0073,
+                    "mret" => 0x3020_0073,
+                    "sret" => 0x1020_0073,
+                    "wfi" => 0x1050_0073,
+                    "wrs.nto" => 0x00D0_0073,
+                    "wrs.sto" => 0x01D0_0073,
+                    "fence" => 0x0FF0_000F,
+                    _ => 0x0000_100F,
+                }
+            }
+            _ if m.starts_with('f') => self.encode_float(m, ops)?,
+            _ if m.starts_with('v') => self.encode_vector(m, ops)?,
+            "sfence.vma" => {
+                let (rs1, rs2) = match ops.len() {
+                    0 => (0, 0),
+                    _ => {
+                        self.expect(ops, 2, m)?;
+                        (self.reg(&ops[0])?, self.reg(&ops[1])?)
+                    }
+                };
+                rtype(0x09, rs2, rs1, 0x0, 0, 0x73)
+            }
+            "hfence.vvma" | "hfence.gvma" => {
+                let (rs1, rs2) = match ops.len() {
+                    0 => (0, 0),
+                    _ => {
+                        self.expect(ops, 2, m)?;
+                        (self.reg(&ops[0])?, self.reg(&ops[1])?)
+                    }
+                };
+                let funct7 = if m == "hfence.vvma" { 0x11 } else { 0x31 };
+                rtype(funct7, rs2, rs1, 0x0, 0, 0x73)
+            }
+            ".word" => {
+                self.expect(ops, 1, m)?;
+                self.address(&ops[0])?
+            }
+            _ => return self.err(format!("unknown instruction `{}`", m)),
+        };
+
+        Ok(word)
+    }
+
+    /// The F/D computational instructions, named `op.fmt` (`fadd.s`) or
+    /// `fcvt.to.from` (`fcvt.w.d`). Those that round take the rounding mode
+    /// as an optional last operand, dynamic if left out.
+    fn encode_float(&self, m: &str, ops: &[String]) -> Result<u32, AsmError> {
+        let unknown = || self.err(format!("unknown instruction `{}`", m));
+        let format = |suffix: &str| match suffix {
+            "s" => Some(0),
+            "d" => Some(1),
+            _ => None,
+        };
+        // FMV names the single-precision format by its integer width.
+        let moved = |suffix: &str| match suffix {
+            "w" => Some(0),
+            "d" => Some(1),
+            _ => None,
+        };
+        let int = |suffix: &str| match suffix {
+            "w" => Some(0),
+            "wu" => Some(1),
+            "l" => Some(2),
+            "lu" => Some(3),
+            _ => None,
+        };
+
+        let parts: Vec<&str> = m.split('.').collect();
+        let (op, fmt, int_width) = match parts[..] {
+            [op, fmt] => match format(fmt) {
+                Some(fmt) => (op, fmt, 0),
+                None => return unknown(),
+            },
+            // fcvt.w.s converts to an integer, fcvt.s.w from one, fcvt.s.d
+            // between formats.
+            ["fcvt", to, from] => match (int(to), int(from), format(to), format(from)) {
+                (Some(int), _, _, Some(fmt)) => ("fcvt.to", fmt, int),
+                (_, Some(int), Some(fmt), _) => ("fcvt.from", fmt, int),
+                (_, _, Some(fmt), Some(from)) if fmt != from => ("fcvt.fp", fmt, from),
+                _ => return unknown(),
+            },
+            // fmv.x.w moves to an integer register, fmv.w.x from one.
+            ["fmv", "x", fmt] => match moved(fmt) {
+                Some(fmt) => ("fmv.to", fmt, 0),
+                None => return unknown(),
+            },
+            ["fmv", fmt, "x"] => match moved(fmt) {
+                Some(fmt) => ("fmv.from", fmt, 0),
+                None => return unknown(),
+            },
+            _ => return unknown(),
+        };
+
+        let fused = match op {
+            "fmadd" => Some(0x43),
+            "fmsub" => Some(0x47),
+            "fnmsub" => Some(0x4B),
+            "fnmadd" => Some(0x4F),
+            _ => None,
+        };
+        if let Some(opcode) = fused {
+            let rm = self.rounding_mode(ops, 4, m)?;
+            return Ok(rtype(
+                self.freg(&ops[3])? << 2 | fmt,
+                self.freg(&ops[2])?,
+                self.freg(&ops[1])?,
+                rm,
+                self.freg(&ops[0])?,
+                opcode,
+            ));
+        }
+
+        // (funct5, funct3 or None if it's a rounding mode, rs2 or None if
+        // it's an operand, whether rd is an integer register, whether rs1 is)
+        let (funct5, funct3, rs2, int_rd, int_rs1) = match op {
+            "fadd" => (0x00, None, None, false, false),
+            "fsub" => (0x01, None, None, false, false),
+            "fmul" => (0x02, None, None, false, false),
+            "fdiv" => (0x03, None, None, false, false),
+            "fsgnj" => (0x04, Some(0x0), None, false, false),
+            "fsgnjn" => (0x04, Some(0x1), None, false, false),
+            "fsgnjx" => (0x04, Some(0x2), None, false, false),
+            "fmin" => (0x05, Some(0x0), None, false, false),
+            "fmax" => (0x05, Some(0x1), None, false, false),
+            "fsqrt" => (0x0B, None, Some(0), false, false),
+            "fcvt.fp" => (0x08, None, Some(int_width), false, false),
+            "feq" => (0x14, Some(0x2), None, true, false),
+            "flt" => (0x14, Some(0x1), None, true, false),
+            "fle" => (0x14, Some(0x0), None, true, false),
+            "fclass" => (0x1C, Some(0x1), Some(0), true, false),
+            "fcvt.to" => (0x18, None, Some(int_width), true, false),
+            "fcvt.from" => (0x1A, None, Some(int_width), false, true),
+            "fmv.to" => (0x1C, Some(0x0), Some(0), true, false),
+            "fmv.from" => (0x1E, Some(0x0), Some(0), false, true),
+            _ => return unknown(),
+        };
+
+        let operands = if rs2.is_some() { 2 } else { 3 };
+        let funct3 = match funct3 {
+            Some(funct3) => {
+                self.expect(ops, operands, m)?;
+                funct3
+            }
+            None => self.rounding_mode(ops, operands, m)?,
+        };
+        let rd = match int_rd {
+            true => self.reg(&ops[0])?,
+            false => self.freg(&ops[0])?,
+        };
+        let rs1 = match int_rs1 {
+            true => self.reg(&ops[1])?,
+            false => self.freg(&ops[1])?,
+        };
+        let rs2 = match rs2 {
+            Some(rs2) => rs2,
+            None => self.freg(&ops[2])?,
+        };
+
+        Ok(rtype(funct5 << 2 | fmt, rs2, rs1, funct3, rd, 0x53))
+    }
+
+    /// The rounding mode after `operands` register operands, if given.
+    fn rounding_mode(&self, ops: &[String], operands: usize, m: &str) -> Result<u32, AsmError> {
+        if ops.len() != operands + 1 {
+            self.expect(ops, operands, m)?;
+            return Ok(0x7);
+        }
+
+        match ops[operands].to_ascii_lowercase().as_str() {
+            "rne" => Ok(0x0),
+            "rtz" => Ok(0x1),
+            "rdn" => Ok(0x2),
+            "rup" => Ok(0x3),
+            "rmm" => Ok(0x4),
+            "dyn" => Ok(0x7),
+            other => self.err(format!("unknown rounding mode `{}`", other)),
+        }
+    }
+
+    /// The implemented V instructions: `vset{i}vl{i}`, `vle<eew>.v`,
+    /// `vse<eew>.v`, the integer `.vv`/`.vx`/`.vi` operations, `vmerge`,
+    /// `vmv`, `vredsum.vs` and the scalar moves.
+    fn encode_vector(&self, m: &str, ops: &[String]) -> Result<u32, AsmError> {
+        let (ops, masked) = match ops.split_last() {
+            Some((last, rest)) if last.eq_ignore_ascii_case("v0.t") => (rest, true),
+            _ => (ops, false),
+        };
+        let vm = (!masked as u32) << 25;
+        let opv = |funct6: u32, vs2: u32, src: u32, funct3: u32, vd: u32| {
+            (funct6 << 26) | vm | (vs2 << 20) | (src << 15) | (funct3 << 12) | (vd << 7) | 0x57
+        };
+
+        match m {
+            "vsetvli" | "vsetivli" => {
+                if ops.len() < 3 {
+                    return self.err(format!("`{}` takes a register, an AVL and a vtype", m));
+                }
+                let vtype = self.vtype(&ops[2..])?;
+                let rd = self.reg(&ops[0])?;
+                return Ok(match m {
+                    "vsetvli" => itype(vtype as i64, self.reg(&ops[1])?, 0x7, rd, 0x57),
+                    _ => {
+                        let avl = self.imm(&ops[1], 0, 31)? as u32;
+                        (0b11 << 30) | (vtype << 20) | (avl << 15) | (0x7 << 12) | (rd << 7) | 0x57
+                    }
+                });
+            }
+            "vsetvl" => {
+                self.expect(ops, 3, m)?;
+                return Ok(rtype(
+                    0x40,
+                    self.reg(&ops[2])?,
+                    self.reg(&ops[1])?,
+                    0x7,
+                    self.reg(&ops[0])?,
+                    0x57,
+                ));
+            }
+            "vmv.x.s" => {
+                self.expect(ops, 2, m)?;
+                return Ok(opv(
+                    0b010000,
+                    self.vreg(&ops[1])?,
+                    0,
+                    0x2,
+                    self.reg(&ops[0])?,
+                ));
+            }
+            "vmv.s.x" => {
+                self.expect(ops, 2, m)?;
+                return Ok(opv(
+                    0b010000,
+                    0,
+                    self.reg(&ops[1])?,
+                    0x6,
+                    self.vreg(&ops[0])?,
+                ));
+            }
+            _ => {}
+        }
+
+        // Unit-stride loads and stores: `vle32.v vd, (rs1)`.
+        let access = m
+            .strip_prefix("vle")
+            .map(|eew| (eew, 0x07))
+            .or_else(|| m.strip_prefix("vse").map(|eew| (eew, 0x27)));
+        if let Some((eew, opcode)) = access {
+            let width = match eew.strip_suffix(".v") {
+                Some("8") => 0x0,
+                Some("16") => 0x5,
+                Some("32") => 0x6,
+                Some("64") => 0x7,
+                _ => return self.err(format!("unknown instruction `{}`", m)),
+            };
+            self.expect(ops, 2, m)?;
+            let (offset, rs1) = self.mem_operand(&ops[1])?;
+            if offset != 0 {
+                return self.err(format!("`{}` takes no offset", m));
+            }
+            return Ok(vm | (rs1 << 15) | (width << 12) | (self.vreg(&ops[0])? << 7) | opcode);
+        }
+
+        let Some((name, form)) = m.split_once('.') else {
+            return self.err(format!("unknown instruction `{}`", m));
+        };
+        // vmv.v.* is vmerge, unmasked, with vs2 = v0.
+        let (name, form, ops) = match (name, form) {
+            ("vmv", "v.v" | "v.x" | "v.i") => {
+                self.expect(ops, 2, m)?;
+                if masked {
+                    return self.err(format!("`{}` can't be masked", m));
+                }
+                let ops = [ops[0].clone(), String::from("v0"), ops[1].clone()];
+                let form = [&form[..1], &form[2..]].concat();
+                return self.encode_vector_arith(m, 0b010111, &form, &ops, vm);
+            }
+            ("vmerge", "vvm" | "vxm" | "vim") => {
+                self.expect(ops, 4, m)?;
+                if masked || !ops[3].eq_ignore_ascii_case("v0") {
+                    return self.err(format!("`{}` takes v0 as its last operand", m));
+                }
+                // Its vm bit is clear: v0 selects rather than masks.
+                return self.encode_vector_arith(m, 0b010111, &form[..2], &ops[..3], 0);
+            }
+            _ => (name, form, ops),
+        };
+        self.expect(ops, 3, m)?;
+
+        let (funct6, opm) = match name {
+            "vadd" | "vredsum" => (0b000000, name == "vredsum"),
+            "vsub" => (0b000010, false),
+            "vrsub" => (0b000011, false),
+            "vminu" => (0b000100, false),
+            "vmin" => (0b000101, false),
+            "vmaxu" => (0b000110, false),
+            "vmax" => (0b000111, false),
+            "vand" => (0b001001, false),
+            "vor" => (0b001010, false),
+            "vxor" => (0b001011, false),
+            "vmseq" => (0b011000, false),
+            "vmsne" => (0b011001, false),
+            "vmsltu" => (0b011010, false),
+            "vmslt" => (0b011011, false),
+            "vmsleu" => (0b011100, false),
+            "vmsle" => (0b011101, false),
+            "vsll" => (0b100101, false),
+            "vsrl" => (0b101000, false),
+            "vsra" => (0b101001, false),
+            "vmul" => (0b100101, true),
+            _ => return self.err(format!("unknown instruction `{}`", m)),
+        };
+        let form = match (opm, form) {
+            (true, "vv" | "vs") => "mvv",
+            (true, "vx") => "mvx",
+            (false, form @ ("vv" | "vx" | "vi")) => form,
+            _ => return self.err(format!("unknown instruction `{}`", m)),
+        };
+
+        self.encode_vector_arith(m, funct6, form, ops, vm)
+    }
+
+    /// `vd, vs2, src` for an OP-V arithmetic instruction of the given form.
+    fn encode_vector_arith(
+        &self,
+        m: &str,
+        funct6: u32,
+        form: &str,
+        ops: &[String],
+        vm: u32,
+    ) -> Result<u32, AsmError> {
+        let shift = matches!(funct6, 0b100101 | 0b101000 | 0b101001) && form == "vi";
+        let (funct3, src) = match form {
+            "vv" => (0x0, self.vreg(&ops[2])?),
+            "vx" => (0x4, self.reg(&ops[2])?),
+            "vi" if shift => (0x3, self.imm(&ops[2], 0, 31)? as u32),
+            "vi" => (0x3, self.imm(&ops[2], -16, 15)? as u32 & 0x1F),
+            "mvv" => (0x2, self.vreg(&ops[2])?),
+            "mvx" => (0x6, self.reg(&ops[2])?),
+            _ => return self.err(format!("unknown instruction `{}`", m)),
+        };
+        let vd = self.vreg(&ops[0])?;
+        let vs2 = self.vreg(&ops[1])?;
+
+        Ok((funct6 << 26) | vm | (vs2 << 20) | (src << 15) | (funct3 << 12) | (vd << 7) | 0x57)
+    }
+
+    /// `e32, m1, ta, ma`: SEW, then optionally LMUL and the tail and mask
+    /// policies. LMUL defaults to 1 and the policies to undisturbed.
+    fn vtype(&self, parts: &[String]) -> Result<u32, AsmError> {
+        let mut vtype = match parts[0].to_ascii_lowercase().as_str() {
+            "e8" => 0,
+            "e16" => 1 << 3,
+            "e32" => 2 << 3,
+            "e64" => 3 << 3,
+            other => return self.err(format!("unknown element width `{}`", other)),
+        };
+        for part in &parts[1..] {
+            vtype |= match part.to_ascii_lowercase().as_str() {
+                "m1" | "tu" | "mu" => 0,
+                "m2" => 1,
+                "m4" => 2,
+                "m8" => 3,
+                "mf8" => 5,
+                "mf4" => 6,
+                "mf2" => 7,
+                "ta" => 1 << 6,
+                "ma" => 1 << 7,
+                other => return self.err(format!("unknown vtype setting `{}`", other)),
+            };
+        }
+        Ok(vtype)
+    }
+
+    /// `lr.w rd, (rs1)` and `sc.w rd, rs2, (rs1)`, plus the `.d` forms and
+    /// `.aq`/`.rl`/`.aqrl` suffixes.
+    fn encode_lrsc(&self, m: &str, ops: &[String]) -> Result<u32, AsmError> {
+        let mut parts = m.split('.');
+        let (op, width, order) = (parts.next(), parts.next(), parts.next());
+        let funct3 = match width {
+            Some("w") => 0x2,
+            Some("d") => 0x3,
+            _ => return self.err(format!("unknown instruction `{}`", m)),
+        };
+        let aq_rl = match order {
+            None => 0b00,
+            Some("rl") => 0b01,
+            Some("aq") => 0b10,
+            Some("aqrl") => 0b11,
+            Some(_) => return self.err(format!("unknown instruction `{}`", m)),
+        };
+        if parts.next().is_some() {
+            return self.err(format!("unknown instruction `{}`", m));
+        }
+
+        let (funct5, rs2, address) = if op == Some("lr") {
+            self.expect(ops, 2, m)?;
+            (0b00010, 0, &ops[1])
+        } else {
+            self.expect(ops, 3, m)?;
+            (0b00011, self.reg(&ops[1])?, &ops[2])
+        };
+        let (offset, rs1) = self.mem_operand(address)?;
+        if offset != 0 {
+            return self.err(format!("`{}` takes no offset", m));
+        }
+
+        Ok(rtype(
+            (funct5 << 2) | aq_rl,
+            rs2,
+            rs1,
+            funct3,
+            self.reg(&ops[0])?,
+            0x2F,
+        ))
+    }
+
+    /// `hlv.w rd, (rs1)`, `hlvx.hu rd, (rs1)`, `hsv.w rs2, (rs1)` and the
+    /// other widths.
+    fn encode_hlv_hsv(&self, m: &str, ops: &[String]) -> Result<u32, AsmError> {
+        let unknown = || self.err(format!("unknown instruction `{}`", m));
+        let Some((op, width)) = m.split_once('.') else {
+            return unknown();
+        };
+        let (width, unsigned) = match width.strip_suffix('u') {
+            Some(width) => (width, true),
+            None => (width, false),
+        };
+        let size = match width {
+            "b" => 0x30,
+            "h" => 0x32,
+            "w" => 0x34,
+            "d" => 0x36,
+            _ => return unknown(),
+        };
+
+        self.expect(ops, 2, m)?;
+        let (offset, rs1) = self.mem_operand(&ops[1])?;
+        if offset != 0 {
+            return self.err(format!("`{}` takes no offset", m));
+        }
+        let reg = self.reg(&ops[0])?;
+
+        let (funct7, rs2, rd) = match (op, unsigned) {
+            ("hlv", false) => (size, 0, reg),
+            ("hlv", true) => (size, 1, reg),
+            ("hlvx", true) => (size, 3, reg),
+            ("hsv", false) => (size | 1, reg, 0),
+            _ => return unknown(),
+        };
+        Ok(rtype(funct7, rs2, rs1, 0x4, rd, 0x73))
+    }
+}
+
+fn rtype(funct7: u32, rs2: u32, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn itype(imm: i64, rs1: u32, funct3: u32, rd: u32, opcode: u32) -> u32 {
+    (((imm as u32) & 0xFFF) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+fn stype(imm: i64, rs2: u32, rs1: u32, funct3: u32) -> u32 {
+    let imm = imm as u32;
+    let imm11_5 = (imm >> 5) & 0x7F;
+    let imm4_0 = imm & 0x1F;
+
+    (imm11_5 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (imm4_0 << 7) | 0x23
+}
+
+fn btype(imm: i64, rs1: u32, rs2: u32, funct3: u32) -> u32 {
+    let imm = imm as u32;
+    let b12 = (imm >> 12) & 0x1;
+    let b11 = (imm >> 11) & 0x1;
+    let b10_5 = (imm >> 5) & 0x3F;
+    let b4_1 = (imm >> 1) & 0xF;
+
+    (b12 << 31)
+        | (b10_5 << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | (b4_1 << 8)
+        | (b11 << 7)
+        | 0x63
+}
+
+fn jtype(imm: i64, rd: u32) -> u32 {
+    let imm = imm as u32;
+    let i20 = (imm >> 20) & 0x1;
+    let i19_12 = (imm >> 12) & 0xFF;
+    let i11 = (imm >> 11) & 0x1;
+    let i10_1 = (imm >> 1) & 0x3FF;
+
+    (i20 << 31) | (i10_1 << 21) | (i11 << 20) | (i19_12 << 12) | (rd << 7) | 0x6F
+}
@@ -0,0 +1,84 @@
+//! Call stacks for crash reports: a shadow stack built from the calls and
+//! returns the hart executes, or failing that, the frame pointer chain.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Calls deeper than this push the outermost ones off the shadow stack.
+const MAX_DEPTH: usize = 4096;
+
+/// How many saved frame pointers a walk follows at most.
+pub(crate) const MAX_FRAME_POINTERS: usize = 64;
+
+/// One entry of [`RiscvCpu::backtrace`](crate::RiscvCpu::backtrace).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// The PC for the innermost frame, a return address for the rest.
+    pub pc: u32,
+    /// `main+0x10`, if the hart has symbols covering `pc`.
+    pub symbol: Option<String>,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.symbol {
+            Some(symbol) => write!(f, "{:#010x} <{}>", self.pc, symbol),
+            None => write!(f, "{:#010x}", self.pc),
+        }
+    }
+}
+
+/// `ra` and `t0`, the registers the calling convention links through.
+fn is_link(reg: u8) -> bool {
+    reg == 1 || reg == 5
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CallStack {
+    tracking: bool,
+    /// Return addresses of the calls still in progress, outermost first.
+    returns: VecDeque<u32>,
+}
+
+impl CallStack {
+    pub(crate) fn set_tracking(&mut self, enabled: bool) {
+        self.tracking = enabled;
+        self.returns.clear();
+    }
+
+    pub(crate) fn is_tracking(&self) -> bool {
+        self.tracking
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.returns.clear();
+    }
+
+    /// A JAL (with no `rs1`) or JALR at `pc` jumped to `target`. Whether
+    /// that was a call, a return or both is read off the link registers, as
+    /// the ISA manual's return-address stack hints have it.
+    pub(crate) fn jump(&mut self, rd: u8, rs1: Option<u8>, pc: u32, target: u32) {
+        if rs1.is_some_and(|rs1| is_link(rs1) && rs1 != rd) {
+            self.ret(target);
+        }
+        if is_link(rd) {
+            if self.returns.len() == MAX_DEPTH {
+                self.returns.pop_front();
+            }
+            self.returns.push_back(pc.wrapping_add(4));
+        }
+    }
+
+    /// Unwind to the call `target` returns from. A return somewhere no call
+    /// is waiting to come back to, like a `longjmp`, is left alone.
+    fn ret(&mut self, target: u32) {
+        if let Some(depth) = self.returns.iter().rposition(|&addr| addr == target) {
+            self.returns.truncate(depth);
+        }
+    }
+
+    /// Innermost first.
+    pub(crate) fn return_addresses(&self) -> impl Iterator<Item = u32> + '_ {
+        self.returns.iter().rev().copied()
+    }
+}
@@ -0,0 +1,70 @@
+use riscv_emulator_rust::riscv_tests::DEFAULT_STEP_LIMIT;
+use riscv_emulator_rust::signature::{self, DEFAULT_GRANULARITY};
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "usage: riscof-dut <elf> --signature <file> \
+                     [--signature-granularity <bytes>] [--max-steps <n>]";
+
+fn main() {
+    let mut elf = None;
+    let mut signature_path = None;
+    let mut granularity = DEFAULT_GRANULARITY;
+    let mut max_steps = DEFAULT_STEP_LIMIT;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`.
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .unwrap_or_else(|| usage())
+        };
+
+        match flag.as_str() {
+            "--signature" => signature_path = Some(value()),
+            "--signature-granularity" => granularity = value().parse().unwrap_or_else(|_| usage()),
+            "--max-steps" => max_steps = value().parse().unwrap_or_else(|_| usage()),
+            _ if !flag.starts_with("--") && elf.is_none() => elf = Some(arg),
+            _ => usage(),
+        }
+    }
+
+    let (Some(elf), Some(signature_path)) = (elf, signature_path) else {
+        usage();
+    };
+
+    let bytes = fs::read(&elf).unwrap_or_else(|e| {
+        eprintln!("{}: {}", elf, e);
+        process::exit(2);
+    });
+
+    match signature::run(&bytes, granularity, max_steps) {
+        Ok((outcome, signature)) => {
+            if let Err(e) = fs::write(&signature_path, signature) {
+                eprintln!("{}: {}", signature_path, e);
+                process::exit(2);
+            }
+            if !outcome.passed() {
+                eprintln!("{}: {}", elf, outcome);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}: {}", elf, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
@@ -0,0 +1,37 @@
+use riscv_emulator_rust::riscv_tests;
+use std::env;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} <riscv-tests isa dir> [prefix]", args[0]);
+        process::exit(2);
+    }
+
+    let dir = Path::new(&args[1]);
+    let prefix = args.get(2).map(String::as_str).unwrap_or("rv32ui-p-");
+
+    let results = match riscv_tests::run_suite(dir, prefix) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("{}: {}", dir.display(), e);
+            process::exit(2);
+        }
+    };
+
+    let mut failed = 0;
+    for (name, outcome) in &results {
+        println!("{:<32} {}", name, outcome);
+        if !outcome.passed() {
+            failed += 1;
+        }
+    }
+
+    println!("\n{} passed, {} failed", results.len() - failed, failed);
+
+    if failed > 0 || results.is_empty() {
+        process::exit(1);
+    }
+}
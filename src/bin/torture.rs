@@ -0,0 +1,75 @@
+use riscv_emulator_rust::torture::{self, DEFAULT_LENGTH, DEFAULT_STEP_LIMIT};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "usage: torture [--seed <n>] [--count <n>] [--length <n>] \
+                     [--max-steps <n>] [--emit <dir>]";
+
+fn main() {
+    let mut seed = 0;
+    let mut count = 100;
+    let mut length = DEFAULT_LENGTH;
+    let mut max_steps = DEFAULT_STEP_LIMIT;
+    let mut emit = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`.
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .unwrap_or_else(|| usage())
+        };
+
+        match flag.as_str() {
+            "--seed" => seed = value().parse().unwrap_or_else(|_| usage()),
+            "--count" => count = value().parse().unwrap_or_else(|_| usage()),
+            "--length" => length = value().parse().unwrap_or_else(|_| usage()),
+            "--max-steps" => max_steps = value().parse().unwrap_or_else(|_| usage()),
+            "--emit" => emit = Some(PathBuf::from(value())),
+            _ => usage(),
+        }
+    }
+
+    let mut failed = 0;
+    for seed in seed..seed + count {
+        let test = torture::generate(seed, length);
+
+        // Other simulators can run these and diff the signature.
+        if let Some(dir) = &emit {
+            let image = dir.join(format!("torture-{}.bin", seed));
+            let signature = dir.join(format!("torture-{}.signature", seed));
+            let written = fs::create_dir_all(dir)
+                .and_then(|()| fs::write(&image, &test.image))
+                .and_then(|()| fs::write(&signature, test.signature_dump()));
+            if let Err(e) = written {
+                eprintln!("{}: {}", dir.display(), e);
+                process::exit(2);
+            }
+        }
+
+        let outcome = torture::run(&test, max_steps);
+        if !outcome.passed() {
+            println!("seed {:<8} {}", seed, outcome);
+            failed += 1;
+        }
+    }
+
+    println!("\n{} passed, {} failed", count - failed, failed);
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
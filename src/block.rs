@@ -0,0 +1,124 @@
+//! A basic-block execution engine.
+//!
+//! Straight-line runs of code are fetched and decoded once, then replayed
+//! from the cache. Interrupts and breakpoints are only looked at between
+//! blocks, so a block also ends just before any breakpoint address.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::csr::Privilege;
+use crate::decode::Instruction;
+
+/// The longest block, in instructions, before it's cut off.
+pub(crate) const MAX_BLOCK_LEN: usize = 64;
+
+/// How [`RiscvCpu::run`](crate::RiscvCpu::run) and friends execute code.
+/// Stepping with [`RiscvCpu::step`](crate::RiscvCpu::step) always
+/// interprets one instruction at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Fetch, decode and execute one instruction at a time.
+    #[default]
+    Interpreter,
+    /// Decode basic blocks once and replay them. Pending interrupts are
+    /// taken at block boundaries rather than after every instruction, and
+    /// device time advances a block at a time.
+    ///
+    /// Guest stores, FENCE.I and SFENCE.VMA keep the cache coherent. Host
+    /// writes to code through `bus` don't, so call
+    /// [`RiscvCpu::flush_blocks`](crate::RiscvCpu::flush_blocks) after
+    /// patching code that may already have run.
+    BasicBlocks,
+    /// Like `BasicBlocks`, but hot blocks are compiled to native code with
+    /// Cranelift. Tracing and step budgets that end mid-block fall back to
+    /// the block interpreter.
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+pub(crate) struct Block {
+    pub(crate) instructions: Vec<(u32, Instruction)>,
+}
+
+/// Whether `instruction` has to be the last one in its block: anything that
+/// can change the PC, the privilege level, CSR state or translation.
+pub(crate) fn ends_block(instruction: Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(
+        instruction,
+        Jal { .. }
+            | Jalr { .. }
+            | Beq { .. }
+            | Bne { .. }
+            | Blt { .. }
+            | Bge { .. }
+            | Bltu { .. }
+            | Bgeu { .. }
+            | Ecall
+            | Ebreak
+            | Mret
+            | Sret
+            | Wfi
+            | WrsNto
+            | WrsSto
+            | Custom(_)
+            | Hypervisor(_)
+            | FenceI
+            | SfenceVma { .. }
+            | Csrrw { .. }
+            | Csrrs { .. }
+            | Csrrc { .. }
+            | Csrrwi { .. }
+            | Csrrsi { .. }
+            | Csrrci { .. }
+    )
+}
+
+/// Decoded blocks keyed by their virtual start address and the privilege
+/// and V they were fetched at.
+#[derive(Default)]
+pub(crate) struct BlockCache {
+    blocks: HashMap<(u64, Privilege, bool), Rc<Block>>,
+    /// Bumped on every flush, so a running block can tell it went stale.
+    generation: u64,
+    /// The bus's count of stores to code when this cache was last synced.
+    code_writes: u64,
+}
+
+impl BlockCache {
+    pub(crate) fn get(&self, pc: u64, privilege: Privilege, virt: bool) -> Option<Rc<Block>> {
+        self.blocks.get(&(pc, privilege, virt)).cloned()
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        pc: u64,
+        privilege: Privilege,
+        virt: bool,
+        block: Block,
+    ) -> Rc<Block> {
+        let block = Rc::new(block);
+        self.blocks.insert((pc, privilege, virt), block.clone());
+        block
+    }
+
+    /// Drop everything if any hart has stored to code since the last sync.
+    /// `code_writes` comes from [`Bus::code_writes`](crate::bus::Bus).
+    pub(crate) fn sync(&mut self, code_writes: u64) {
+        if code_writes != self.code_writes {
+            self.code_writes = code_writes;
+            self.flush();
+        }
+    }
+
+    pub(crate) fn flush(&mut self) {
+        self.blocks.clear();
+        self.generation += 1;
+    }
+
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation
+    }
+}
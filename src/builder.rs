@@ -0,0 +1,404 @@
+use std::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use crate::bus::Bus;
+use crate::cache::CacheModel;
+use crate::clock::Clock;
+use crate::costs::CycleCosts;
+use crate::custom::{CustomHandler, CustomOpcode};
+use crate::devices::{Device, Ram};
+use crate::float::FloatRegs;
+use crate::isa::Extensions;
+use crate::machine::Machine;
+use crate::predictor::BranchPredictor;
+use crate::semihosting::Semihosting;
+use crate::timing::PipelineConfig;
+use crate::tlb::TlbConfig;
+use crate::trace::Tracer;
+use crate::vector::DEFAULT_VLEN;
+use crate::xlen::{Rv32, Xlen};
+use crate::{Engine, Reg, RiscvCpu};
+
+const DEFAULT_RAM_SIZE: usize = 64 * 1024;
+
+/// Where RAM's bytes live on the host.
+enum RamBacking {
+    Flat,
+    Sparse,
+    #[cfg(feature = "std")]
+    File(PathBuf),
+}
+
+/// Configures a [`RiscvCpu`] before it starts running: memory layout, reset
+/// state, preloaded images and devices.
+pub struct RiscvCpuBuilder<X: Xlen = Rv32> {
+    ram_base: u32,
+    ram_size: usize,
+    ram_backing: RamBacking,
+    reset_vector: Option<u32>,
+    stack_pointer: Option<u32>,
+    images: Vec<(u32, Vec<u8>)>,
+    roms: Vec<(u32, u32)>,
+    devices: Vec<(u32, u32, Box<dyn Device>)>,
+    guest_traps: bool,
+    call_tracking: bool,
+    coverage: bool,
+    memory_profile: bool,
+    caches: Option<CacheModel>,
+    tlb: Option<TlbConfig>,
+    pipeline: Option<PipelineConfig>,
+    predictor: Option<Box<dyn BranchPredictor>>,
+    costs: Option<CycleCosts>,
+    stats: bool,
+    engine: Engine,
+    float_regs: FloatRegs,
+    extensions: Extensions,
+    vlen: usize,
+    semihosting: Option<Semihosting>,
+    clock: Option<Clock>,
+    tracer: Option<Box<dyn Tracer>>,
+    custom: Vec<(CustomOpcode, Box<dyn CustomHandler>)>,
+    unknown: Option<Box<dyn CustomHandler>>,
+    xlen: PhantomData<X>,
+}
+
+impl RiscvCpuBuilder {
+    pub fn new() -> Self {
+        Self {
+            ram_base: 0,
+            ram_size: DEFAULT_RAM_SIZE,
+            ram_backing: RamBacking::Flat,
+            reset_vector: None,
+            stack_pointer: None,
+            images: Vec::new(),
+            roms: Vec::new(),
+            devices: Vec::new(),
+            guest_traps: false,
+            call_tracking: false,
+            coverage: false,
+            memory_profile: false,
+            caches: None,
+            tlb: None,
+            pipeline: None,
+            predictor: None,
+            costs: None,
+            stats: false,
+            engine: Engine::default(),
+            float_regs: FloatRegs::default(),
+            extensions: Extensions::all(),
+            vlen: DEFAULT_VLEN,
+            semihosting: None,
+            clock: None,
+            tracer: None,
+            custom: Vec::new(),
+            unknown: None,
+            xlen: PhantomData,
+        }
+    }
+}
+
+impl<X: Xlen> RiscvCpuBuilder<X> {
+    /// Switch the register width, e.g. `.xlen::<Rv64>()`. RV32 by default.
+    pub fn xlen<Y: Xlen>(self) -> RiscvCpuBuilder<Y> {
+        RiscvCpuBuilder {
+            ram_base: self.ram_base,
+            ram_size: self.ram_size,
+            ram_backing: self.ram_backing,
+            reset_vector: self.reset_vector,
+            stack_pointer: self.stack_pointer,
+            images: self.images,
+            roms: self.roms,
+            devices: self.devices,
+            guest_traps: self.guest_traps,
+            call_tracking: self.call_tracking,
+            coverage: self.coverage,
+            memory_profile: self.memory_profile,
+            caches: self.caches,
+            tlb: self.tlb,
+            pipeline: self.pipeline,
+            predictor: self.predictor,
+            costs: self.costs,
+            stats: self.stats,
+            engine: self.engine,
+            float_regs: self.float_regs,
+            extensions: self.extensions,
+            vlen: self.vlen,
+            semihosting: self.semihosting,
+            clock: self.clock,
+            tracer: self.tracer,
+            custom: self.custom,
+            unknown: self.unknown,
+            xlen: PhantomData,
+        }
+    }
+
+    pub fn ram_base(mut self, base: u32) -> Self {
+        self.ram_base = base;
+        self
+    }
+
+    pub fn ram_size(mut self, bytes: usize) -> Self {
+        self.ram_size = bytes;
+        self
+    }
+
+    /// Back RAM with pages allocated on first write instead of one flat
+    /// buffer, for large guest memories. Indexing `bus` directly won't work.
+    pub fn sparse_ram(mut self, enabled: bool) -> Self {
+        self.ram_backing = if enabled {
+            RamBacking::Sparse
+        } else {
+            RamBacking::Flat
+        };
+        self
+    }
+
+    /// Back RAM with a shared mapping of the file at `path` (see
+    /// [`Ram::map_file`]), so its contents outlive the emulator.
+    #[cfg(feature = "std")]
+    pub fn ram_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ram_backing = RamBacking::File(path.into());
+        self
+    }
+
+    /// Initial PC. Defaults to the start of RAM.
+    pub fn reset_vector(mut self, pc: u32) -> Self {
+        self.reset_vector = Some(pc);
+        self
+    }
+
+    /// Initial value of `sp` (x2). Left at zero if not set.
+    pub fn stack_pointer(mut self, sp: u32) -> Self {
+        self.stack_pointer = Some(sp);
+        self
+    }
+
+    /// Bytes to copy into RAM at `addr` before the first instruction runs.
+    pub fn image(mut self, addr: u32, bytes: impl Into<Vec<u8>>) -> Self {
+        self.images.push((addr, bytes.into()));
+        self
+    }
+
+    /// Like [`image`](Self::image), but the guest can't write over it.
+    pub fn rom(mut self, addr: u32, bytes: impl Into<Vec<u8>>) -> Self {
+        let bytes = bytes.into();
+        self.roms.push((addr, bytes.len() as u32));
+        self.image(addr, bytes)
+    }
+
+    pub fn device(mut self, base: u32, size: u32, device: impl Device + 'static) -> Self {
+        self.devices.push((base, size, Box::new(device)));
+        self
+    }
+
+    /// See [`RiscvCpu::set_guest_traps`].
+    pub fn guest_traps(mut self, enabled: bool) -> Self {
+        self.guest_traps = enabled;
+        self
+    }
+
+    /// See [`RiscvCpu::set_call_tracking`].
+    pub fn call_tracking(mut self, enabled: bool) -> Self {
+        self.call_tracking = enabled;
+        self
+    }
+
+    /// See [`RiscvCpu::enable_coverage`].
+    pub fn coverage(mut self, enabled: bool) -> Self {
+        self.coverage = enabled;
+        self
+    }
+
+    /// See [`RiscvCpu::enable_memory_profile`].
+    pub fn memory_profile(mut self, enabled: bool) -> Self {
+        self.memory_profile = enabled;
+        self
+    }
+
+    /// See [`RiscvCpu::set_cache_model`].
+    pub fn cache_model(mut self, caches: CacheModel) -> Self {
+        self.caches = Some(caches);
+        self
+    }
+
+    /// See [`RiscvCpu::enable_tlb`].
+    pub fn tlb(mut self, config: TlbConfig) -> Self {
+        self.tlb = Some(config);
+        self
+    }
+
+    /// See [`RiscvCpu::enable_pipeline`].
+    pub fn pipeline(mut self, config: PipelineConfig) -> Self {
+        self.pipeline = Some(config);
+        self
+    }
+
+    /// Predict the pipeline's branches with `predictor`, timing with the
+    /// default [`PipelineConfig`] unless [`pipeline`](Self::pipeline) says
+    /// otherwise.
+    pub fn branch_predictor(mut self, predictor: impl BranchPredictor + 'static) -> Self {
+        self.predictor = Some(Box::new(predictor));
+        self
+    }
+
+    /// See [`RiscvCpu::set_cycle_costs`].
+    pub fn cycle_costs(mut self, costs: CycleCosts) -> Self {
+        self.costs = Some(costs);
+        self
+    }
+
+    /// See [`RiscvCpu::set_stats_enabled`].
+    pub fn stats(mut self, enabled: bool) -> Self {
+        self.stats = enabled;
+        self
+    }
+
+    /// See [`Engine`]. The interpreter is the default.
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Keep floating-point values in the `f` registers (F and D, the
+    /// default) or in the `x` registers (Zfinx and Zdinx).
+    pub fn float_regs(mut self, float_regs: FloatRegs) -> Self {
+        self.float_regs = float_regs;
+        self
+    }
+
+    /// The optional extensions the hart implements; all of them by default.
+    /// See [`RiscvCpu::set_extensions`].
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// The width of each vector register in bits: a power of two from 64
+    /// to 65536. 128 by default.
+    pub fn vlen(mut self, bits: usize) -> Self {
+        self.vlen = bits;
+        self
+    }
+
+    pub fn semihosting(mut self, semihosting: Semihosting) -> Self {
+        self.semihosting = Some(semihosting);
+        self
+    }
+
+    /// See [`RiscvCpu::set_clock`]. Virtual time at 10 MHz by default.
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn tracer(mut self, tracer: impl Tracer + 'static) -> Self {
+        self.tracer = Some(Box::new(tracer));
+        self
+    }
+
+    /// See [`RiscvCpu::set_custom`].
+    pub fn custom(mut self, space: CustomOpcode, handler: impl CustomHandler + 'static) -> Self {
+        self.custom.push((space, Box::new(handler)));
+        self
+    }
+
+    /// See [`RiscvCpu::set_unknown_opcode_handler`].
+    pub fn unknown_opcode(mut self, handler: impl CustomHandler + 'static) -> Self {
+        self.unknown = Some(Box::new(handler));
+        self
+    }
+
+    pub fn build(self) -> Result<RiscvCpu<X>, String> {
+        if !self.vlen.is_power_of_two() || !(64..=65536).contains(&self.vlen) {
+            return Err(format!(
+                "VLEN must be a power of two from 64 to 65536, not {}",
+                self.vlen
+            ));
+        }
+
+        let ram = match &self.ram_backing {
+            RamBacking::Flat => Ram::new(self.ram_size),
+            RamBacking::Sparse => Ram::sparse(self.ram_size),
+            #[cfg(feature = "std")]
+            RamBacking::File(path) => Ram::map_file(path, self.ram_size)?,
+        };
+        let mut cpu = RiscvCpu::with_bus(Bus::with_ram(self.ram_base, ram));
+        cpu.pc = X::truncate(self.reset_vector.unwrap_or(self.ram_base) as u64);
+        cpu.set_guest_traps(self.guest_traps);
+        cpu.set_call_tracking(self.call_tracking);
+        if self.coverage {
+            cpu.enable_coverage();
+        }
+        if self.memory_profile {
+            cpu.enable_memory_profile();
+        }
+        if let Some(caches) = self.caches {
+            cpu.set_cache_model(caches);
+        }
+        if let Some(tlb) = self.tlb {
+            cpu.enable_tlb(tlb);
+        }
+        if self.pipeline.is_some() || self.predictor.is_some() {
+            cpu.enable_pipeline(self.pipeline.unwrap_or_default());
+        }
+        if let (Some(pipeline), Some(predictor)) = (cpu.pipeline_mut(), self.predictor) {
+            pipeline.set_boxed_predictor(predictor);
+        }
+        if let Some(costs) = self.costs {
+            cpu.set_cycle_costs(costs);
+        }
+        cpu.set_stats_enabled(self.stats);
+        cpu.set_engine(self.engine);
+        cpu.set_float_regs(self.float_regs);
+        cpu.set_extensions(self.extensions)?;
+        cpu.set_vlen(self.vlen);
+        if let Some(semihosting) = self.semihosting {
+            cpu.enable_semihosting(semihosting);
+        }
+        if let Some(clock) = self.clock {
+            cpu.set_clock(clock);
+        }
+        cpu.tracer = self.tracer;
+        for (space, handler) in self.custom {
+            cpu.custom[space as usize] = Some(handler);
+        }
+        cpu.unknown = self.unknown;
+
+        if let Some(sp) = self.stack_pointer {
+            cpu.set_reg(Reg::Sp, sp as u64);
+        }
+
+        for (base, size, device) in self.devices {
+            cpu.bus.map(base, size, device);
+        }
+
+        for (addr, bytes) in &self.images {
+            cpu.bus.write_bytes(*addr, bytes).ok_or_else(|| {
+                format!(
+                    "Image at {:#x} ({} bytes) doesn't fit in RAM",
+                    addr,
+                    bytes.len()
+                )
+            })?;
+        }
+
+        for (base, size) in self.roms {
+            cpu.bus.protect(base, size);
+        }
+
+        Ok(cpu)
+    }
+
+    /// Build a [`Machine`] of `harts` harts sharing this configuration's
+    /// bus. See [`Machine::new`] for what the extra harts inherit.
+    pub fn build_machine(self, harts: usize) -> Result<Machine<X>, String> {
+        Ok(Machine::new(self.build()?, harts))
+    }
+}
+
+impl Default for RiscvCpuBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::HashSet;
+use std::ops::{Index, IndexMut, Range};
+#[cfg(feature = "std")]
+use std::thread;
+
+use crate::MemSize;
+use crate::capture::OutputCapture;
+use crate::clock::{Clock, TimeMode};
+use crate::devices::{Device, Ram};
+
+/// LR reserves the whole cache line around its address, so stores anywhere
+/// in it break the reservation.
+const RESERVATION_GRANULE: u32 = 64;
+
+struct Region {
+    base: u32,
+    size: u32,
+    device: Box<dyn Device>,
+}
+
+impl Region {
+    fn offset_of(&self, addr: u32, len: usize) -> Option<u32> {
+        let offset = addr.checked_sub(self.base)?;
+        if offset as u64 + len as u64 <= self.size as u64 {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+}
+
+/// The system bus: RAM at `ram_base` plus any number of devices mapped into
+/// the rest of the address space.
+///
+/// Indexing (`bus[addr]`, `bus[start..end]`) goes straight to RAM using
+/// absolute addresses, which is handy for setting up memory from the host.
+/// It needs flat RAM; sparse RAM is only reachable through `read`/`write`.
+pub struct Bus {
+    ram: Ram,
+    ram_base: u32,
+    regions: Vec<Region>,
+    /// Ranges the guest can read but not write, whether RAM or a device.
+    read_only: Vec<(u32, u32)>,
+    /// Physical pages any hart has cached decoded blocks from, and how many
+    /// guest stores have landed on one. Harts sharing the bus flush their
+    /// block caches when the count moves.
+    code_pages: HashSet<u32>,
+    code_writes: u64,
+    /// LR/SC reservations, one per hart ID, as reservation-granule numbers.
+    reservations: Vec<(u64, u32)>,
+    /// Ticks since the bus was created, which the `time` CSR reads.
+    time: u64,
+    clock: Clock,
+    /// `time` when host time was last lined up with it.
+    #[cfg(feature = "std")]
+    host_base: u64,
+}
+
+impl Bus {
+    pub fn new(ram_base: u32, ram_size: usize) -> Self {
+        Self::with_ram(ram_base, Ram::new(ram_size))
+    }
+
+    pub fn with_ram(ram_base: u32, ram: Ram) -> Self {
+        Self {
+            ram,
+            ram_base,
+            regions: Vec::new(),
+            read_only: Vec::new(),
+            code_pages: HashSet::new(),
+            code_writes: 0,
+            reservations: Vec::new(),
+            time: 0,
+            clock: Clock::default(),
+            #[cfg(feature = "std")]
+            host_base: 0,
+        }
+    }
+
+    /// Send what every console device prints to `capture` instead.
+    pub fn capture_output(&mut self, capture: &OutputCapture) {
+        for region in &mut self.regions {
+            region.device.redirect_output(Box::new(capture.clone()));
+        }
+    }
+
+    pub fn map(&mut self, base: u32, size: u32, device: Box<dyn Device>) {
+        self.regions.push(Region { base, size, device });
+    }
+
+    /// Reject guest writes to `base..base + size`, e.g. for a boot ROM or
+    /// flash. The host can still fill it in through indexing or
+    /// `write_bytes`.
+    pub fn protect(&mut self, base: u32, size: u32) {
+        self.read_only.push((base, size));
+    }
+
+    pub fn is_read_only(&self, addr: u32, len: usize) -> bool {
+        let (start, end) = (addr as u64, addr as u64 + len as u64);
+        self.read_only.iter().any(|&(base, size)| {
+            let (base, size) = (base as u64, size as u64);
+            start < base + size && base < end
+        })
+    }
+
+    pub fn ram_base(&self) -> u32 {
+        self.ram_base
+    }
+
+    /// Size of RAM in bytes.
+    pub fn len(&self) -> usize {
+        self.ram.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ram.is_empty()
+    }
+
+    pub fn ram(&self) -> &Ram {
+        &self.ram
+    }
+
+    pub fn ram_mut(&mut self) -> &mut Ram {
+        &mut self.ram
+    }
+
+    /// `None` if no device claims the whole access.
+    pub fn read(&mut self, addr: u32, size: MemSize) -> Option<u32> {
+        if let Some(offset) = self.ram_offset(addr, size.bytes()) {
+            return Some(self.ram.read(offset as u32, size));
+        }
+
+        let region = self
+            .regions
+            .iter_mut()
+            .find(|r| r.offset_of(addr, size.bytes()).is_some())?;
+
+        let value = region.device.read(addr - region.base, size);
+
+        Some(match size {
+            MemSize::Byte => value & 0xFF,
+            MemSize::Half => value & 0xFFFF,
+            MemSize::Word => value,
+        })
+    }
+
+    /// `None` if no device claims the whole access or any of it is
+    /// read-only.
+    pub fn write(&mut self, addr: u32, size: MemSize, value: u32) -> Option<()> {
+        if self.is_read_only(addr, size.bytes()) {
+            return None;
+        }
+        if !self.reservations.is_empty() {
+            self.break_reservations(addr, size.bytes());
+        }
+
+        if let Some(offset) = self.ram_offset(addr, size.bytes()) {
+            self.ram.write(offset as u32, size, value);
+            return Some(());
+        }
+
+        let region = self
+            .regions
+            .iter_mut()
+            .find(|r| r.offset_of(addr, size.bytes()).is_some())?;
+
+        region.device.write(addr - region.base, size, value);
+        region.device.dma(&mut Dma {
+            ram: &mut self.ram,
+            ram_base: self.ram_base,
+            code_pages: &mut self.code_pages,
+            code_writes: &mut self.code_writes,
+            reservations: &mut self.reservations,
+        });
+        Some(())
+    }
+
+    /// Register `hart`'s reservation on the granule holding `paddr`,
+    /// replacing any it already had.
+    pub(crate) fn reserve(&mut self, hart: u64, paddr: u32) {
+        self.reservations.retain(|&(h, _)| h != hart);
+        self.reservations.push((hart, paddr / RESERVATION_GRANULE));
+    }
+
+    pub(crate) fn has_reservation(&self, hart: u64) -> bool {
+        self.reservations.iter().any(|&(h, _)| h == hart)
+    }
+
+    /// Drop `hart`'s reservation, returning whether it covered `paddr`.
+    /// A store conditional only goes ahead if this succeeds.
+    pub(crate) fn take_reservation(&mut self, hart: u64, paddr: u32) -> bool {
+        match self.reservations.iter().position(|&(h, _)| h == hart) {
+            Some(i) => self.reservations.swap_remove(i).1 == paddr / RESERVATION_GRANULE,
+            None => false,
+        }
+    }
+
+    /// Any guest store, from any hart, breaks every reservation it touches.
+    fn break_reservations(&mut self, addr: u32, len: usize) {
+        break_reservations(&mut self.reservations, addr, len);
+    }
+
+    /// Advance every device's clock, then let each one at memory. In host
+    /// time the clock moves to the host's time instead, however many ticks
+    /// the caller thinks have passed.
+    pub fn tick(&mut self, ticks: u64) {
+        let ticks = match self.clock.mode() {
+            TimeMode::Virtual => ticks,
+            #[cfg(feature = "std")]
+            TimeMode::Host => (self.host_base + self.clock.host_ticks()).saturating_sub(self.time),
+        };
+        self.time = self.time.wrapping_add(ticks);
+        for region in &mut self.regions {
+            region.device.tick(ticks);
+            region.device.dma(&mut Dma {
+                ram: &mut self.ram,
+                ram_base: self.ram_base,
+                code_pages: &mut self.code_pages,
+                code_writes: &mut self.code_writes,
+                reservations: &mut self.reservations,
+            });
+        }
+    }
+
+    /// Device-style access to RAM from the host, with the same bookkeeping
+    /// as a guest store.
+    pub fn dma(&mut self) -> Dma<'_> {
+        Dma {
+            ram: &mut self.ram,
+            ram_base: self.ram_base,
+            code_pages: &mut self.code_pages,
+            code_writes: &mut self.code_writes,
+            reservations: &mut self.reservations,
+        }
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Let `ticks` pass with nothing running, as when every hart is
+    /// waiting. In host time that means sleeping through them.
+    pub fn skip(&mut self, ticks: u64) {
+        #[cfg(feature = "std")]
+        if self.clock.mode() == TimeMode::Host {
+            thread::sleep(self.clock.duration(ticks));
+        }
+        self.tick(ticks);
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Take time from `clock` from now on. The tick count carries on from
+    /// where it was.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+        #[cfg(feature = "std")]
+        {
+            self.clock.restart();
+            self.host_base = self.time;
+        }
+    }
+
+    /// Interrupt lines all devices are asserting for `hart`, as `mip` bits.
+    pub fn interrupts(&self, hart: u64) -> u32 {
+        self.regions
+            .iter()
+            .fold(0, |lines, r| lines | r.device.interrupts(hart))
+    }
+
+    /// Ticks until the soonest device event, if any is scheduled.
+    pub fn next_event(&self) -> Option<u64> {
+        self.regions
+            .iter()
+            .filter_map(|r| r.device.next_event())
+            .min()
+    }
+
+    pub(crate) fn mark_code(&mut self, paddr: u32) {
+        self.code_pages.insert(paddr >> 12);
+    }
+
+    /// Note a guest store to `paddr`. Returns true if it may have changed
+    /// code some hart has cached.
+    pub(crate) fn note_write(&mut self, paddr: u32) -> bool {
+        if self.code_pages.is_empty() || !self.code_pages.contains(&(paddr >> 12)) {
+            return false;
+        }
+        self.code_pages.clear();
+        self.code_writes += 1;
+        true
+    }
+
+    pub(crate) fn code_writes(&self) -> u64 {
+        self.code_writes
+    }
+
+    /// Copy `bytes` into RAM starting at `addr`, failing if any of it falls
+    /// outside RAM.
+    pub fn write_bytes(&mut self, addr: u32, bytes: &[u8]) -> Option<()> {
+        let start = self.ram_offset(addr, bytes.len())?;
+        self.ram.write_bytes(start, bytes);
+        Some(())
+    }
+
+    /// Whether all of `addr..addr + len` is RAM.
+    pub fn in_ram(&self, addr: u32, len: usize) -> bool {
+        self.ram_offset(addr, len).is_some()
+    }
+
+    fn ram_offset(&self, addr: u32, len: usize) -> Option<usize> {
+        let offset = addr.checked_sub(self.ram_base)? as usize;
+        if offset + len <= self.ram.len() {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn ram_range(&self, range: Range<usize>) -> Range<usize> {
+        let base = self.ram_base as usize;
+        range.start - base..range.end - base
+    }
+}
+
+fn break_reservations(reservations: &mut Vec<(u64, u32)>, addr: u32, len: usize) {
+    let first = addr / RESERVATION_GRANULE;
+    let last = addr.saturating_add(len as u32 - 1) / RESERVATION_GRANULE;
+    reservations.retain(|&(_, granule)| granule < first || granule > last);
+}
+
+/// A device's window onto RAM for DMA, handed to
+/// [`Device::dma`](crate::devices::Device::dma). Addresses are physical.
+/// Writes break LR reservations and flush decoded blocks just like guest
+/// stores do.
+pub struct Dma<'a> {
+    ram: &'a mut Ram,
+    ram_base: u32,
+    code_pages: &'a mut HashSet<u32>,
+    code_writes: &'a mut u64,
+    reservations: &'a mut Vec<(u64, u32)>,
+}
+
+impl Dma<'_> {
+    /// Fill `buf` from `addr`. `None` if any of it is outside RAM.
+    pub fn read(&self, addr: u64, buf: &mut [u8]) -> Option<()> {
+        let offset = self.offset(addr, buf.len())?;
+        self.ram.read_bytes(offset, buf);
+        Some(())
+    }
+
+    /// Copy `bytes` to `addr`. `None` if any of it is outside RAM.
+    pub fn write(&mut self, addr: u64, bytes: &[u8]) -> Option<()> {
+        let offset = self.offset(addr, bytes.len())?;
+        if bytes.is_empty() {
+            return Some(());
+        }
+        self.ram.write_bytes(offset, bytes);
+
+        let addr = addr as u32;
+        if !self.reservations.is_empty() {
+            break_reservations(self.reservations, addr, bytes.len());
+        }
+        let last = addr + (bytes.len() as u32 - 1);
+        if (addr >> 12..=last >> 12).any(|page| self.code_pages.contains(&page)) {
+            self.code_pages.clear();
+            *self.code_writes += 1;
+        }
+        Some(())
+    }
+
+    pub fn read_u16(&self, addr: u64) -> Option<u16> {
+        let mut buf = [0; 2];
+        self.read(addr, &mut buf)?;
+        Some(u16::from_le_bytes(buf))
+    }
+
+    pub fn read_u32(&self, addr: u64) -> Option<u32> {
+        let mut buf = [0; 4];
+        self.read(addr, &mut buf)?;
+        Some(u32::from_le_bytes(buf))
+    }
+
+    pub fn read_u64(&self, addr: u64) -> Option<u64> {
+        let mut buf = [0; 8];
+        self.read(addr, &mut buf)?;
+        Some(u64::from_le_bytes(buf))
+    }
+
+    pub fn write_u16(&mut self, addr: u64, value: u16) -> Option<()> {
+        self.write(addr, &value.to_le_bytes())
+    }
+
+    pub fn write_u32(&mut self, addr: u64, value: u32) -> Option<()> {
+        self.write(addr, &value.to_le_bytes())
+    }
+
+    fn offset(&self, addr: u64, len: usize) -> Option<usize> {
+        let offset = addr.checked_sub(self.ram_base as u64)?;
+        if offset + len as u64 <= self.ram.len() as u64 {
+            Some(offset as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl Index<usize> for Bus {
+    type Output = u8;
+
+    fn index(&self, addr: usize) -> &u8 {
+        &self.ram.as_slice()[addr - self.ram_base as usize]
+    }
+}
+
+impl IndexMut<usize> for Bus {
+    fn index_mut(&mut self, addr: usize) -> &mut u8 {
+        let base = self.ram_base as usize;
+        &mut self.ram.as_mut_slice()[addr - base]
+    }
+}
+
+impl Index<Range<usize>> for Bus {
+    type Output = [u8];
+
+    fn index(&self, range: Range<usize>) -> &[u8] {
+        &self.ram.as_slice()[self.ram_range(range)]
+    }
+}
+
+impl IndexMut<Range<usize>> for Bus {
+    fn index_mut(&mut self, range: Range<usize>) -> &mut [u8] {
+        let range = self.ram_range(range);
+        &mut self.ram.as_mut_slice()[range]
+    }
+}
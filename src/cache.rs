@@ -0,0 +1,278 @@
+//! A model of the instruction and data caches a hart might have, for
+//! counting hits and misses. It never changes what the guest sees.
+
+use std::fmt;
+
+/// The shape of one cache. Every dimension is a power of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    size: u32,
+    ways: u32,
+    line_size: u32,
+}
+
+impl CacheConfig {
+    /// A `size`-byte, `ways`-way set-associative cache of `line_size`-byte
+    /// lines. One way is direct-mapped; `size / line_size` ways is fully
+    /// associative.
+    pub fn new(size: u32, ways: u32, line_size: u32) -> Result<Self, String> {
+        for (name, value) in [("size", size), ("ways", ways), ("line size", line_size)] {
+            if !value.is_power_of_two() {
+                return Err(format!("Cache: {} {} is not a power of two", name, value));
+            }
+        }
+        if line_size < 4 {
+            return Err(format!("Cache: {}-byte lines are too short", line_size));
+        }
+        if (size as u64) < ways as u64 * line_size as u64 {
+            return Err(format!(
+                "Cache: {} bytes can't hold {} ways of {}-byte lines",
+                size, ways, line_size
+            ));
+        }
+        Ok(Self {
+            size,
+            ways,
+            line_size,
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn ways(&self) -> u32 {
+        self.ways
+    }
+
+    pub fn line_size(&self) -> u32 {
+        self.line_size
+    }
+
+    fn sets(&self) -> u32 {
+        self.size / (self.ways * self.line_size)
+    }
+}
+
+/// What one cache has seen since it was created or last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Dirty lines evicted.
+    pub writebacks: u64,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// The fraction of accesses that hit, from 0 to 1.
+    pub fn hit_rate(&self) -> f64 {
+        match self.accesses() {
+            0 => 0.0,
+            n => self.hits as f64 / n as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Line {
+    tag: Option<u32>,
+    /// When it was last touched, for picking the least recently used.
+    used: u64,
+    dirty: bool,
+}
+
+/// One write-back, write-allocate cache with LRU replacement.
+#[derive(Debug, Clone)]
+struct Cache {
+    config: CacheConfig,
+    /// `ways` lines for each set in turn.
+    lines: Vec<Line>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl Cache {
+    fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            lines: vec![Line::default(); (config.sets() * config.ways) as usize],
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Look up the line holding `addr`, filling it on a miss. Gives whether
+    /// it hit and the address of any dirty line that was evicted.
+    fn access(&mut self, addr: u32, write: bool) -> (bool, Option<u32>) {
+        let block = addr / self.config.line_size;
+        let sets = self.config.sets();
+        let (set, tag) = (block % sets, block / sets);
+        let ways = self.config.ways as usize;
+        let start = set as usize * ways;
+        let lines = &mut self.lines[start..start + ways];
+
+        self.clock += 1;
+        if let Some(line) = lines.iter_mut().find(|line| line.tag == Some(tag)) {
+            line.used = self.clock;
+            line.dirty |= write;
+            self.stats.hits += 1;
+            return (true, None);
+        }
+
+        self.stats.misses += 1;
+        let victim = lines
+            .iter_mut()
+            .min_by_key(|line| (line.tag.is_some(), line.used))
+            .expect("a set has at least one way");
+        let writeback = match (victim.tag, victim.dirty) {
+            (Some(old), true) => {
+                self.stats.writebacks += 1;
+                Some((old * sets + set) * self.config.line_size)
+            }
+            _ => None,
+        };
+        *victim = Line {
+            tag: Some(tag),
+            used: self.clock,
+            dirty: write,
+        };
+        (false, writeback)
+    }
+}
+
+/// Level-one instruction and data caches, each optional, in front of an
+/// optional unified level two that sees their misses and writebacks.
+///
+/// Caches are looked up by virtual address, as the memory profile records
+/// them, and every line an access touches counts. Fetches count once each
+/// instruction retires, so an instruction that traps doesn't fetch.
+#[derive(Debug, Clone, Default)]
+pub struct CacheModel {
+    icache: Option<Cache>,
+    dcache: Option<Cache>,
+    l2: Option<Cache>,
+}
+
+impl CacheModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn icache(mut self, config: CacheConfig) -> Self {
+        self.icache = Some(Cache::new(config));
+        self
+    }
+
+    pub fn dcache(mut self, config: CacheConfig) -> Self {
+        self.dcache = Some(Cache::new(config));
+        self
+    }
+
+    pub fn l2(mut self, config: CacheConfig) -> Self {
+        self.l2 = Some(Cache::new(config));
+        self
+    }
+
+    /// The same caches, empty, with no stats.
+    pub fn cold(&self) -> Self {
+        let cold = |cache: &Option<Cache>| cache.as_ref().map(|c| Cache::new(c.config));
+        Self {
+            icache: cold(&self.icache),
+            dcache: cold(&self.dcache),
+            l2: cold(&self.l2),
+        }
+    }
+
+    pub fn icache_stats(&self) -> Option<CacheStats> {
+        self.icache.as_ref().map(|cache| cache.stats)
+    }
+
+    pub fn dcache_stats(&self) -> Option<CacheStats> {
+        self.dcache.as_ref().map(|cache| cache.stats)
+    }
+
+    pub fn l2_stats(&self) -> Option<CacheStats> {
+        self.l2.as_ref().map(|cache| cache.stats)
+    }
+
+    /// Zero the counts but keep what's cached.
+    pub fn reset_stats(&mut self) {
+        for cache in [&mut self.icache, &mut self.dcache, &mut self.l2]
+            .into_iter()
+            .flatten()
+        {
+            cache.stats = CacheStats::default();
+        }
+    }
+
+    pub(crate) fn fetch(&mut self, pc: u32) {
+        Self::access(&mut self.icache, &mut self.l2, pc, 4, false);
+    }
+
+    pub(crate) fn data(&mut self, addr: u32, len: u32, write: bool) {
+        Self::access(&mut self.dcache, &mut self.l2, addr, len, write);
+    }
+
+    fn access(l1: &mut Option<Cache>, l2: &mut Option<Cache>, addr: u32, len: u32, write: bool) {
+        let Some(first) = l1.as_ref().or(l2.as_ref()) else {
+            return;
+        };
+        let line_size = first.config.line_size;
+        let end = addr.saturating_add(len.max(1) - 1);
+        let mut line = addr & !(line_size - 1);
+        loop {
+            match l1 {
+                Some(l1) => {
+                    let (hit, writeback) = l1.access(line, write);
+                    if let Some(l2) = l2 {
+                        if let Some(victim) = writeback {
+                            l2.access(victim, true);
+                        }
+                        if !hit {
+                            l2.access(line, false);
+                        }
+                    }
+                }
+                None => {
+                    l2.as_mut().expect("checked above").access(line, write);
+                }
+            }
+            match line.checked_add(line_size) {
+                Some(next) if next <= end => line = next,
+                _ => break,
+            }
+        }
+    }
+}
+
+impl fmt::Display for CacheModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<6} {:>12} {:>12} {:>12} {:>9}",
+            "cache", "hits", "misses", "writebacks", "hit rate"
+        )?;
+        for (name, stats) in [
+            ("L1I", self.icache_stats()),
+            ("L1D", self.dcache_stats()),
+            ("L2", self.l2_stats()),
+        ] {
+            if let Some(stats) = stats {
+                writeln!(
+                    f,
+                    "{:<6} {:>12} {:>12} {:>12} {:>8.2}%",
+                    name,
+                    stats.hits,
+                    stats.misses,
+                    stats.writebacks,
+                    stats.hit_rate() * 100.0
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+//! Collecting what a guest prints, so tests can check its output rather
+//! than poke at memory.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// A buffer that console output can be sent to. Clones share the buffer,
+/// so keep one to read from and hand the others out as sinks, e.g. to
+/// [`Uart16550::with_output`](crate::devices::Uart16550::with_output).
+/// [`RiscvCpu::capture_output`](crate::RiscvCpu::capture_output) points
+/// every console a hart has at a new one.
+///
+/// It's `Send`, so a hart on another thread can write to it.
+#[derive(Clone, Default)]
+pub struct OutputCapture {
+    bytes: Arc<Mutex<Vec<u8>>>,
+}
+
+impl OutputCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far.
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.lock().unwrap().clone()
+    }
+
+    /// Everything written so far as text, with invalid UTF-8 replaced.
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.lock().unwrap()).into_owned()
+    }
+
+    /// Everything written so far, leaving the buffer empty.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut self.bytes.lock().unwrap())
+    }
+
+    pub fn clear(&self) {
+        self.bytes.lock().unwrap().clear();
+    }
+}
+
+impl Write for OutputCapture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.bytes.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Guest time: what `rdtime`, the CLINT's `mtime`, semihosting's clock and
+//! the time system calls all read.
+//!
+//! Time is counted in ticks on the bus. By default a tick passes per
+//! instruction, so every run of a program sees the same times however busy
+//! the host is. In `TimeMode::Host` ticks follow the host's monotonic
+//! clock instead, and a hart waiting for an interrupt really waits. Either
+//! way the clock's frequency turns ticks into seconds. Host time needs the
+//! `std` feature.
+
+use std::time::Duration;
+#[cfg(feature = "std")]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::virt::TIMEBASE_FREQUENCY;
+
+/// Where ticks come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeMode {
+    /// One tick per instruction executed.
+    #[default]
+    Virtual,
+    /// Ticks at the clock's frequency in real time.
+    #[cfg(feature = "std")]
+    Host,
+}
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    mode: TimeMode,
+    frequency: u64,
+    /// What the wall clock reads at tick 0 in virtual time, since the Unix
+    /// epoch.
+    epoch: Duration,
+    /// When host time was last lined up with the bus.
+    #[cfg(feature = "std")]
+    started: Instant,
+}
+
+impl Clock {
+    /// A clock ticking at the `virt` board's 10 MHz timebase.
+    pub fn new(mode: TimeMode) -> Self {
+        Self {
+            mode,
+            frequency: TIMEBASE_FREQUENCY as u64,
+            epoch: Duration::ZERO,
+            #[cfg(feature = "std")]
+            started: Instant::now(),
+        }
+    }
+
+    /// Ticks per second.
+    pub fn frequency(mut self, hz: u64) -> Self {
+        assert!(hz > 0, "Clock: the frequency must be at least 1 Hz");
+        self.frequency = hz;
+        self
+    }
+
+    /// Make the wall clock read `since_unix_epoch` at tick 0, to give a
+    /// virtual-time guest a plausible date rather than 1970. Host time
+    /// reads the host's wall clock.
+    pub fn epoch(mut self, since_unix_epoch: Duration) -> Self {
+        self.epoch = since_unix_epoch;
+        self
+    }
+
+    pub fn mode(&self) -> TimeMode {
+        self.mode
+    }
+
+    pub fn ticks_per_second(&self) -> u64 {
+        self.frequency
+    }
+
+    /// How long `ticks` ticks last.
+    pub fn duration(&self, ticks: u64) -> Duration {
+        let nanos = ticks as u128 * 1_000_000_000 / self.frequency as u128;
+        Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        )
+    }
+
+    /// How many ticks pass in `duration`, rounded down.
+    pub fn ticks(&self, duration: Duration) -> u64 {
+        let ticks = duration.as_nanos() * self.frequency as u128 / 1_000_000_000;
+        ticks.min(u64::MAX as u128) as u64
+    }
+
+    /// The wall-clock time at tick `ticks`, since the Unix epoch.
+    pub fn realtime(&self, ticks: u64) -> Duration {
+        match self.mode {
+            TimeMode::Virtual => self.epoch + self.duration(ticks),
+            #[cfg(feature = "std")]
+            TimeMode::Host => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Host ticks since [`restart`](Self::restart).
+    #[cfg(feature = "std")]
+    pub(crate) fn host_ticks(&self) -> u64 {
+        self.ticks(self.started.elapsed())
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn restart(&mut self) {
+        self.started = Instant::now();
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(TimeMode::Virtual)
+    }
+}
@@ -0,0 +1,597 @@
+//! Co-simulation against a reference model: run a program here and in
+//! Spike or the Sail model side by side, and stop at the first instruction
+//! where they disagree.
+//!
+//! The reference runs as a subprocess and its trace is read as it goes.
+//! Each instruction it retires is a [`Commit`]: where it was, what it was,
+//! and which integer registers it wrote. [`compare`] steps the hart once per
+//! commit and checks it retired the same instruction at the same PC and
+//! left the same values behind:
+//!
+//! ```no_run
+//! use riscv_emulator_rust::cosim::{self, Reference};
+//! use riscv_emulator_rust::RiscvCpu;
+//!
+//! let elf = std::fs::read("rv32ui-p-add").unwrap();
+//! let mut cpu = RiscvCpu::builder()
+//!     .ram_base(0x8000_0000)
+//!     .guest_traps(true)
+//!     .build()
+//!     .unwrap();
+//! cpu.load_elf(&elf).unwrap();
+//!
+//! let commits = Reference::spike("rv32ui-p-add").spawn().unwrap();
+//! match cosim::compare(&mut cpu, commits, 1_000_000) {
+//!     Ok(steps) => println!("{} instructions agree", steps),
+//!     Err(divergence) => println!("{}", divergence),
+//! }
+//! ```
+//!
+//! Only integer registers are compared, and only those an instruction
+//! writes, so a wrong value shows up where it's produced rather than
+//! wherever it's next used. Memory is checked indirectly, when a load
+//! brings back something different.
+//!
+//! The same commits make a golden trace: [`record`] a run that's known to
+//! be right, keep [`write_trace`]'s text next to the program, and later
+//! [`compare_traces`] against a fresh recording to catch regressions.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
+use std::str::FromStr;
+
+use crate::decode::Instruction;
+use crate::trace::Tracer;
+use crate::xlen::Xlen;
+use crate::{RiscvCpu, StepOutcome};
+
+/// How many commits before a divergence it reports.
+pub const CONTEXT: usize = 8;
+
+/// Non-retiring steps (traps, interrupts, WFI) allowed between two
+/// commits before the hart counts as stuck.
+const MAX_STALL: u64 = 10_000;
+
+/// One retired instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    pub pc: u64,
+    pub raw: u32,
+    /// `(register, value)` for each integer register written, `x0` aside.
+    pub writes: Vec<(u8, u64)>,
+}
+
+impl fmt::Display for Commit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#010x} ({:#010x})", self.pc, self.raw)?;
+        for (reg, value) in &self.writes {
+            write!(f, " x{} {:#x}", reg, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Commit {
+    type Err = String;
+
+    /// The format [`Display`](fmt::Display) writes: `0x00000008
+    /// (0x00208133) x2 0xa`.
+    fn from_str(s: &str) -> Result<Commit, String> {
+        let mut tokens = s.split_whitespace();
+        let (pc, raw) = pc_and_raw(&mut tokens)
+            .ok_or_else(|| format!("Commit: expected `pc (instruction)` in {:?}", s))?;
+        let mut writes = Vec::new();
+        while let Some(reg) = tokens.next() {
+            let value = tokens.next().and_then(hex);
+            match (xreg(reg), value) {
+                (Some(reg), Some(value)) => writes.push((reg, value)),
+                _ => return Err(format!("Commit: expected `xN value` in {:?}", s)),
+            }
+        }
+        Ok(Commit { pc, raw, writes })
+    }
+}
+
+/// The first instruction the two models disagree on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// How many commits matched before this one.
+    pub step: u64,
+    /// What the reference, or the known-good trace, retired. `None` if it
+    /// stopped first.
+    pub expected: Option<Commit>,
+    /// What the hart, or the trace under test, retired. `None` if it
+    /// stopped first.
+    pub actual: Option<Commit>,
+    /// Why the hart stopped, when it did.
+    pub stopped: Option<String>,
+    /// Up to [`CONTEXT`] commits before this one, oldest first.
+    pub context: Vec<Commit>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "diverged after {} instructions", self.step)?;
+        for commit in &self.context {
+            writeln!(f, "             {}", commit)?;
+        }
+        match &self.expected {
+            Some(commit) => writeln!(f, "   expected: {}", commit)?,
+            None => writeln!(f, "   expected: (stopped)")?,
+        }
+        match (&self.actual, &self.stopped) {
+            (Some(commit), _) => write!(f, "     actual: {}", commit),
+            (None, Some(reason)) => write!(f, "     actual: (stopped: {})", reason),
+            (None, None) => write!(f, "     actual: (stopped)"),
+        }
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+// ── Reference traces ──────────────────────────────────────────────────────────
+
+/// The trace format a reference writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// `spike --log-commits`, on stderr: one line per instruction, such as
+    /// `core   0: 3 0x80000000 (0x00000297) x5  0x80000000`.
+    Spike,
+    /// The Sail model's trace, on stdout: a `[n] [M]: 0x... (0x...) ...`
+    /// line per instruction, then an `x5 <- 0x...` line per write. An
+    /// instruction followed by `trapping from ...` didn't retire.
+    Sail,
+}
+
+/// Turns trace lines into commits. Unrecognised lines are skipped, since
+/// both models print banners and device output in between.
+#[derive(Debug)]
+pub struct Parser {
+    format: Format,
+    pending: Option<Commit>,
+}
+
+fn hex(token: &str) -> Option<u64> {
+    let digits = token.strip_prefix("0x")?;
+    u64::from_str_radix(digits, 16).ok()
+}
+
+fn xreg(token: &str) -> Option<u8> {
+    token
+        .strip_prefix('x')?
+        .parse()
+        .ok()
+        .filter(|&reg| reg < 32)
+}
+
+/// `0x... (0x...)`, the start of an instruction line in both formats.
+fn pc_and_raw<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Option<(u64, u32)> {
+    let pc = hex(tokens.next()?)?;
+    let raw = tokens.next()?.strip_prefix('(')?.strip_suffix(')')?;
+    Some((pc, hex(raw)? as u32))
+}
+
+impl Parser {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            pending: None,
+        }
+    }
+
+    /// Feed one line. Returns the previous instruction once this one shows
+    /// it's complete.
+    pub fn line(&mut self, line: &str) -> Option<Commit> {
+        match self.format {
+            Format::Spike => self.spike(line),
+            Format::Sail => self.sail(line),
+        }
+    }
+
+    /// The last instruction, once the trace has ended.
+    pub fn finish(&mut self) -> Option<Commit> {
+        self.pending.take()
+    }
+
+    /// Parse a whole trace.
+    pub fn parse(format: Format, trace: &str) -> Vec<Commit> {
+        let mut parser = Parser::new(format);
+        let mut commits: Vec<Commit> = trace.lines().filter_map(|l| parser.line(l)).collect();
+        commits.extend(parser.finish());
+        commits
+    }
+
+    fn spike(&mut self, line: &str) -> Option<Commit> {
+        let rest = line.trim_start().strip_prefix("core")?;
+        let (_, rest) = rest.split_once(':')?;
+        let mut tokens = rest.split_whitespace().peekable();
+        // The privilege level, in newer versions.
+        if tokens.peek()?.bytes().all(|b| b.is_ascii_digit()) {
+            tokens.next();
+        }
+        let (pc, raw) = pc_and_raw(&mut tokens)?;
+
+        let mut writes = Vec::new();
+        while let Some(token) = tokens.next() {
+            if let Some(reg) = xreg(token)
+                && let Some(value) = tokens.peek().and_then(|t| hex(t))
+            {
+                tokens.next();
+                if reg != 0 {
+                    writes.push((reg, value));
+                }
+            }
+        }
+        Some(Commit { pc, raw, writes })
+    }
+
+    fn sail(&mut self, line: &str) -> Option<Commit> {
+        let line = line.trim();
+        if line.starts_with("trapping from") {
+            self.pending = None;
+            return None;
+        }
+        if line.starts_with('[')
+            && let Some((_, rest)) = line.split_once("]: ")
+            && let Some((pc, raw)) = pc_and_raw(&mut rest.split_whitespace())
+        {
+            let writes = Vec::new();
+            return self.pending.replace(Commit { pc, raw, writes });
+        }
+        if let Some((reg, value)) = line.split_once(" <- ")
+            && let (Some(reg), Some(value)) = (xreg(reg), hex(value))
+            && let Some(commit) = &mut self.pending
+            && reg != 0
+        {
+            commit.writes.push((reg, value));
+        }
+        None
+    }
+}
+
+/// A reference simulator to run as a subprocess.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    format: Format,
+    program: String,
+    args: Vec<String>,
+    elf: PathBuf,
+}
+
+impl Reference {
+    /// `spike --isa=rv32i --log-commits <elf>`.
+    pub fn spike(elf: impl AsRef<Path>) -> Self {
+        Self {
+            format: Format::Spike,
+            program: "spike".to_string(),
+            args: vec!["--isa=rv32i".to_string(), "--log-commits".to_string()],
+            elf: elf.as_ref().to_path_buf(),
+        }
+    }
+
+    /// `riscv_sim_RV32 <elf>`, the Sail model's C emulator.
+    pub fn sail(elf: impl AsRef<Path>) -> Self {
+        Self {
+            format: Format::Sail,
+            program: "riscv_sim_RV32".to_string(),
+            args: Vec::new(),
+            elf: elf.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Run this executable instead, e.g. a full path or an RV64 build.
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = program.to_string();
+        self
+    }
+
+    /// Replace the arguments that go before the ELF.
+    pub fn args<S: AsRef<str>>(mut self, args: &[S]) -> Self {
+        self.args = args.iter().map(|a| a.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Start the reference and stream its commits.
+    pub fn spawn(&self) -> Result<Commits, String> {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args).arg(&self.elf).stdin(Stdio::null());
+        match self.format {
+            Format::Spike => command.stdout(Stdio::null()).stderr(Stdio::piped()),
+            Format::Sail => command.stdout(Stdio::piped()).stderr(Stdio::null()),
+        };
+        let mut child = command
+            .spawn()
+            .map_err(|e| format!("Reference: couldn't run {}: {}", self.program, e))?;
+
+        let output: Box<dyn Read> = match self.format {
+            Format::Spike => Box::new(child.stderr.take().unwrap()),
+            Format::Sail => Box::new(child.stdout.take().unwrap()),
+        };
+        Ok(Commits {
+            child,
+            output: BufReader::new(output),
+            parser: Parser::new(self.format),
+            line: String::new(),
+        })
+    }
+}
+
+/// The commits of a running reference, read as they're needed. Dropping it
+/// kills the reference.
+pub struct Commits {
+    child: Child,
+    output: BufReader<Box<dyn Read>>,
+    parser: Parser,
+    line: String,
+}
+
+impl Iterator for Commits {
+    type Item = Commit;
+
+    fn next(&mut self) -> Option<Commit> {
+        loop {
+            self.line.clear();
+            match self.output.read_line(&mut self.line) {
+                Ok(0) | Err(_) => return self.parser.finish(),
+                Ok(_) => {
+                    if let Some(commit) = self.parser.line(&self.line) {
+                        return Some(commit);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Commits {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// ── Comparison ────────────────────────────────────────────────────────────────
+
+/// Notes what the hart retired. A step that traps or takes an interrupt
+/// retires nothing.
+struct Retired {
+    retired: Rc<Cell<Option<(u32, u32)>>>,
+}
+
+impl Tracer for Retired {
+    fn instruction(&mut self, pc: u32, raw: u32, _instruction: &Instruction) {
+        self.retired.set(Some((pc, raw)));
+    }
+}
+
+/// Run `f` with a tracer that notes retired instructions in place of the
+/// hart's own, which is cleared afterwards.
+fn observing<X: Xlen, T>(
+    cpu: &mut RiscvCpu<X>,
+    f: impl FnOnce(&mut RiscvCpu<X>, &Cell<Option<(u32, u32)>>) -> T,
+) -> T {
+    let retired = Rc::new(Cell::new(None));
+    cpu.set_tracer(Retired {
+        retired: retired.clone(),
+    });
+    let result = f(cpu, &retired);
+    cpu.clear_tracer();
+    result
+}
+
+/// The integer register `raw` writes, if it's an instruction that writes
+/// one. A write that leaves a register unchanged doesn't show up in a
+/// before-and-after comparison, so this names it.
+fn rd(raw: u32) -> Option<u8> {
+    let rd = ((raw >> 7) & 0x1F) as u8;
+    let funct3 = (raw >> 12) & 0x7;
+    let funct7 = raw >> 25;
+    let writes = match raw & 0x7F {
+        // LOAD, OP-IMM, AUIPC, OP-IMM-32, AMO, OP, LUI, OP-32, JALR, JAL
+        0x03 | 0x13 | 0x17 | 0x1B | 0x2F | 0x33 | 0x37 | 0x3B | 0x67 | 0x6F => true,
+        // OP-FP compares, conversions to integer, FMV.X and FCLASS
+        0x53 => matches!(funct7, 0x50 | 0x51 | 0x60 | 0x61 | 0x70 | 0x71),
+        // vsetvli, vsetivli and vsetvl
+        0x57 => funct3 == 0x7,
+        // The CSR instructions
+        0x73 => funct3 != 0x0 && funct3 != 0x4,
+        _ => false,
+    };
+    (writes && rd != 0).then_some(rd)
+}
+
+/// Step until an instruction retires, and say what it wrote: every
+/// register it changed, its `rd`, and any in `also`, which the reference
+/// says it wrote. Also returns why the
+/// hart stopped, if it did; it may have retired an instruction first.
+fn retire<X: Xlen>(
+    cpu: &mut RiscvCpu<X>,
+    retired: &Cell<Option<(u32, u32)>>,
+    also: &[(u8, u64)],
+) -> (Option<Commit>, Option<String>) {
+    for _ in 0..MAX_STALL {
+        let before = cpu.regs;
+        retired.set(None);
+        let outcome = cpu.step();
+        let stopped = match outcome {
+            Ok(StepOutcome::Exited(code)) => Some(format!("exited with {}", code)),
+            Err(exception) => Some(exception.to_string()),
+            Ok(_) => None,
+        };
+        if let Some((pc, raw)) = retired.take() {
+            let written =
+                |r: usize| rd(raw) == Some(r as u8) || also.iter().any(|&(w, _)| w as usize == r);
+            let writes = (1..32)
+                .filter(|&r| cpu.regs[r] != before[r] || written(r))
+                .map(|r| (r as u8, X::widen(cpu.regs[r])))
+                .collect();
+            let commit = Commit {
+                pc: pc as u64,
+                raw,
+                writes,
+            };
+            return (Some(commit), stopped);
+        }
+        if stopped.is_some() {
+            return (None, stopped);
+        }
+    }
+    (
+        None,
+        Some(format!("nothing retired in {} steps", MAX_STALL)),
+    )
+}
+
+/// Step `cpu` in lockstep with `commits` until the reference stops, the
+/// two disagree, or `max_steps` instructions have been compared. Returns
+/// how many matched.
+///
+/// Commits before the one at the hart's PC, such as Spike's boot ROM,
+/// aren't compared; their register writes are copied onto the hart so both
+/// start out the same. The hart's tracer is replaced for the duration and
+/// cleared afterwards.
+pub fn compare<X: Xlen>(
+    cpu: &mut RiscvCpu<X>,
+    commits: impl IntoIterator<Item = Commit>,
+    max_steps: u64,
+) -> Result<u64, Box<Divergence>> {
+    observing(cpu, |cpu, retired| {
+        lockstep(cpu, commits.into_iter(), max_steps, retired)
+    })
+}
+
+fn lockstep<X: Xlen>(
+    cpu: &mut RiscvCpu<X>,
+    mut commits: impl Iterator<Item = Commit>,
+    max_steps: u64,
+    retired: &Cell<Option<(u32, u32)>>,
+) -> Result<u64, Box<Divergence>> {
+    let entry = X::widen(cpu.pc);
+    let mut context = VecDeque::with_capacity(CONTEXT);
+    let mut halted = None;
+    let mut step = 0;
+
+    let mut next = commits.next();
+    while let Some(commit) = next.take_if(|c| c.pc != entry) {
+        for &(reg, value) in &commit.writes {
+            cpu.regs[reg as usize] = X::truncate(value);
+        }
+        next = commits.next();
+    }
+
+    while step < max_steps {
+        let Some(expected) = next.take() else {
+            return Ok(step);
+        };
+
+        let (actual, stopped) = match halted.take() {
+            Some(reason) => (None, Some(reason)),
+            None => retire(cpu, retired, &expected.writes),
+        };
+        if !agree::<X>(&expected, actual.as_ref()) {
+            return Err(Box::new(Divergence {
+                step,
+                expected: Some(expected),
+                actual,
+                stopped,
+                context: context.into(),
+            }));
+        }
+        halted = stopped;
+
+        if context.len() == CONTEXT {
+            context.pop_front();
+        }
+        context.push_back(expected);
+        step += 1;
+        next = commits.next();
+    }
+    Ok(step)
+}
+
+/// Same instruction at the same PC, writing the same registers with the
+/// same values. `actual` lists every register the reference wrote, so a
+/// write that left a register unchanged still gets its value checked.
+fn agree<X: Xlen>(expected: &Commit, actual: Option<&Commit>) -> bool {
+    let Some(actual) = actual else {
+        return false;
+    };
+    expected.pc & X::MASK == actual.pc & X::MASK
+        && expected.raw == actual.raw
+        && final_writes(expected, X::MASK) == final_writes(actual, X::MASK)
+}
+
+/// `commit`'s writes masked to XLEN, sorted, keeping only the last write
+/// to each register, since that's the one that stuck.
+fn final_writes(commit: &Commit, mask: u64) -> Vec<(u8, u64)> {
+    let mut writes: Vec<(u8, u64)> = commit
+        .writes
+        .iter()
+        .rev()
+        .map(|&(reg, value)| (reg, value & mask))
+        .collect();
+    writes.sort_by_key(|&(reg, _)| reg);
+    writes.dedup_by_key(|&mut (reg, _)| reg);
+    writes
+}
+
+// ── Golden traces ─────────────────────────────────────────────────────────────
+
+/// Run `cpu` for up to `max_steps` instructions, or until it stops, and
+/// return what it retired. Like [`compare`], this replaces the hart's
+/// tracer.
+pub fn record<X: Xlen>(cpu: &mut RiscvCpu<X>, max_steps: u64) -> Vec<Commit> {
+    observing(cpu, |cpu, retired| {
+        let mut commits = Vec::new();
+        while (commits.len() as u64) < max_steps {
+            let (commit, stopped) = retire(cpu, retired, &[]);
+            commits.extend(commit);
+            if stopped.is_some() {
+                break;
+            }
+        }
+        commits
+    })
+}
+
+/// A trace as text, one commit per line as [`Commit`] displays it. This is
+/// what [`read_trace`] reads back.
+pub fn write_trace(commits: &[Commit]) -> String {
+    commits.iter().map(|c| format!("{}\n", c)).collect()
+}
+
+/// Parse a trace written by [`write_trace`]. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn read_trace(text: &str) -> Result<Vec<Commit>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| line.parse().map_err(|e| format!("line {}: {}", number, e)))
+        .collect()
+}
+
+/// The first commit where `actual` differs from the known-good `expected`,
+/// or where one trace ends before the other.
+pub fn compare_traces(expected: &[Commit], actual: &[Commit]) -> Result<(), Box<Divergence>> {
+    let same = |a: &Commit, b: &Commit| {
+        a.pc == b.pc && a.raw == b.raw && final_writes(a, u64::MAX) == final_writes(b, u64::MAX)
+    };
+    let step = match expected.iter().zip(actual).position(|(a, b)| !same(a, b)) {
+        Some(step) => step,
+        None if expected.len() == actual.len() => return Ok(()),
+        None => expected.len().min(actual.len()),
+    };
+    Err(Box::new(Divergence {
+        step: step as u64,
+        expected: expected.get(step).cloned(),
+        actual: actual.get(step).cloned(),
+        stopped: None,
+        context: expected[step.saturating_sub(CONTEXT)..step].to_vec(),
+    }))
+}
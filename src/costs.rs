@@ -0,0 +1,198 @@
+//! How many cycles each instruction takes, so that `mcycle` approximates a
+//! particular core rather than counting one cycle per instruction.
+
+use std::collections::HashMap;
+
+use crate::decode::{FloatInstruction, FpOp, HypervisorInstruction, Instruction};
+use crate::stats::mnemonic;
+
+/// The broad kinds of instruction a cost can be given to all at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstructionClass {
+    /// Integer arithmetic, LUI and AUIPC, and Zba and Zbkb.
+    Alu,
+    Branch,
+    /// JAL and JALR.
+    Jump,
+    /// Integer, floating-point and hypervisor loads.
+    Load,
+    /// Integer, floating-point and hypervisor stores.
+    Store,
+    /// LR and SC.
+    Atomic,
+    Csr,
+    /// Fences, ECALL, EBREAK, the trap returns, WFI and WRS.
+    System,
+    /// Floating-point instructions other than loads, stores, divides and
+    /// square roots.
+    Float,
+    /// Floating-point divides and square roots.
+    FloatDivide,
+    Vector,
+    Crypto,
+    Custom,
+}
+
+impl InstructionClass {
+    pub const ALL: [InstructionClass; 13] = [
+        InstructionClass::Alu,
+        InstructionClass::Branch,
+        InstructionClass::Jump,
+        InstructionClass::Load,
+        InstructionClass::Store,
+        InstructionClass::Atomic,
+        InstructionClass::Csr,
+        InstructionClass::System,
+        InstructionClass::Float,
+        InstructionClass::FloatDivide,
+        InstructionClass::Vector,
+        InstructionClass::Crypto,
+        InstructionClass::Custom,
+    ];
+
+    /// The name [`CycleCosts::parse`] knows it by.
+    pub fn name(self) -> &'static str {
+        match self {
+            InstructionClass::Alu => "alu",
+            InstructionClass::Branch => "branch",
+            InstructionClass::Jump => "jump",
+            InstructionClass::Load => "load",
+            InstructionClass::Store => "store",
+            InstructionClass::Atomic => "atomic",
+            InstructionClass::Csr => "csr",
+            InstructionClass::System => "system",
+            InstructionClass::Float => "float",
+            InstructionClass::FloatDivide => "fdiv",
+            InstructionClass::Vector => "vector",
+            InstructionClass::Crypto => "crypto",
+            InstructionClass::Custom => "custom",
+        }
+    }
+
+    /// The class `instruction` belongs to.
+    pub fn of(instruction: &Instruction) -> Self {
+        use FloatInstruction as F;
+        use Instruction::*;
+
+        match instruction {
+            Beq { .. } | Bne { .. } | Blt { .. } | Bge { .. } | Bltu { .. } | Bgeu { .. } => {
+                InstructionClass::Branch
+            }
+            Jal { .. } | Jalr { .. } => InstructionClass::Jump,
+            Lb { .. }
+            | Lh { .. }
+            | Lw { .. }
+            | Lbu { .. }
+            | Lhu { .. }
+            | Lwu { .. }
+            | Ld { .. }
+            | Float(F::Flw { .. } | F::Fld { .. })
+            | Hypervisor(HypervisorInstruction::Load { .. }) => InstructionClass::Load,
+            Sb { .. }
+            | Sh { .. }
+            | Sw { .. }
+            | Sd { .. }
+            | Float(F::Fsw { .. } | F::Fsd { .. })
+            | Hypervisor(HypervisorInstruction::Store { .. }) => InstructionClass::Store,
+            LrW { .. } | ScW { .. } | LrD { .. } | ScD { .. } => InstructionClass::Atomic,
+            Csrrw { .. }
+            | Csrrs { .. }
+            | Csrrc { .. }
+            | Csrrwi { .. }
+            | Csrrsi { .. }
+            | Csrrci { .. } => InstructionClass::Csr,
+            Fence
+            | FenceI
+            | Ecall
+            | Ebreak
+            | Mret
+            | Sret
+            | Wfi
+            | WrsNto
+            | WrsSto
+            | SfenceVma { .. }
+            | Hypervisor(_) => InstructionClass::System,
+            Float(F::Farith { op: FpOp::Div, .. } | F::Fsqrt { .. }) => {
+                InstructionClass::FloatDivide
+            }
+            Float(_) => InstructionClass::Float,
+            Vector(_) => InstructionClass::Vector,
+            Crypto(_) => InstructionClass::Crypto,
+            Custom(_) => InstructionClass::Custom,
+            _ => InstructionClass::Alu,
+        }
+    }
+}
+
+/// Cycles per instruction, by mnemonic or else by class. Anything not
+/// given a cost takes one cycle.
+///
+/// An instruction's extra cycles are added to `mcycle` as it retires, so
+/// an instruction that traps costs one cycle and `minstret` and `time`
+/// still count instructions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleCosts {
+    classes: [u64; InstructionClass::ALL.len()],
+    mnemonics: HashMap<String, u64>,
+}
+
+impl CycleCosts {
+    pub fn new() -> Self {
+        Self {
+            classes: [1; InstructionClass::ALL.len()],
+            mnemonics: HashMap::new(),
+        }
+    }
+
+    /// Charge `cycles` for each instruction of `class`.
+    pub fn class(mut self, class: InstructionClass, cycles: u64) -> Self {
+        self.classes[class as usize] = cycles;
+        self
+    }
+
+    /// Charge `cycles` for each `mnemonic`, as disassembled: `lw`,
+    /// `fdiv.d`, `vadd.vv`. This overrides the cost of its class.
+    pub fn mnemonic(mut self, mnemonic: &str, cycles: u64) -> Self {
+        self.mnemonics.insert(mnemonic.to_ascii_lowercase(), cycles);
+        self
+    }
+
+    /// Parse a table such as `load=2, fdiv=20, fsqrt.d=30`: costs separated
+    /// by commas or new lines, each naming a class or else a mnemonic.
+    pub fn parse(table: &str) -> Result<Self, String> {
+        let mut costs = Self::new();
+        for entry in table.split([',', '\n']).map(str::trim) {
+            if entry.is_empty() {
+                continue;
+            }
+            let Some((name, cycles)) = entry.split_once('=') else {
+                return Err(format!("Costs: '{}' isn't name=cycles", entry));
+            };
+            let (name, cycles) = (name.trim().to_ascii_lowercase(), cycles.trim());
+            let cycles = cycles
+                .parse()
+                .map_err(|_| format!("Costs: '{}' isn't a number of cycles", cycles))?;
+            costs = match InstructionClass::ALL.into_iter().find(|c| c.name() == name) {
+                Some(class) => costs.class(class, cycles),
+                None => costs.mnemonic(&name, cycles),
+            };
+        }
+        Ok(costs)
+    }
+
+    /// The cycles `instruction` takes.
+    pub fn cost(&self, instruction: &Instruction) -> u64 {
+        if !self.mnemonics.is_empty()
+            && let Some(&cycles) = self.mnemonics.get(&mnemonic(instruction))
+        {
+            return cycles;
+        }
+        self.classes[InstructionClass::of(instruction) as usize]
+    }
+}
+
+impl Default for CycleCosts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,83 @@
+//! Which instructions a hart has executed, for measuring how much of the
+//! guest a test run exercised.
+
+use std::collections::HashMap;
+#[cfg(feature = "dwarf")]
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "dwarf")]
+use std::rc::Rc;
+
+#[cfg(feature = "dwarf")]
+use crate::dwarf::LineTable;
+
+/// How many times the instruction at each address has retired since
+/// coverage was turned on. Instructions that trap don't count.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    hits: HashMap<u32, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&mut self, pc: u32) {
+        *self.hits.entry(pc).or_default() += 1;
+    }
+
+    pub fn hits(&self, pc: u32) -> u64 {
+        self.hits.get(&pc).copied().unwrap_or(0)
+    }
+
+    pub fn is_covered(&self, pc: u32) -> bool {
+        self.hits.contains_key(&pc)
+    }
+
+    /// How many distinct addresses have executed.
+    pub fn len(&self) -> usize {
+        self.hits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hits.is_empty()
+    }
+
+    /// Every address that has executed, in order.
+    pub fn addresses(&self) -> Vec<u32> {
+        let mut addresses: Vec<u32> = self.hits.keys().copied().collect();
+        addresses.sort_unstable();
+        addresses
+    }
+
+    /// Add `other`'s counts to these, e.g. to combine the runs of a test
+    /// suite or the harts of a machine.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&pc, &hits) in &other.hits {
+            *self.hits.entry(pc).or_default() += hits;
+        }
+    }
+
+    /// How many instructions executed from each source line.
+    #[cfg(feature = "dwarf")]
+    pub fn lines(&self, table: &LineTable) -> BTreeMap<(Rc<str>, u32), u64> {
+        let mut lines = BTreeMap::new();
+        for (&pc, &hits) in &self.hits {
+            if let Some(location) = table.location(pc) {
+                *lines.entry((location.file, location.line)).or_default() += hits;
+            }
+        }
+        lines
+    }
+
+    /// The lines `table` has code for that never executed.
+    #[cfg(feature = "dwarf")]
+    pub fn missed_lines(&self, table: &LineTable) -> BTreeSet<(Rc<str>, u32)> {
+        let covered = self.lines(table);
+        table
+            .iter()
+            .map(|(_, location)| (location.file, location.line))
+            .filter(|line| !covered.contains_key(line))
+            .collect()
+    }
+}
@@ -0,0 +1,291 @@
+//! Loads and stores: the privilege they're checked at, address
+//! translation, and the access paths behind the load, store and LR/SC
+//! instructions.
+
+use super::{MemSize, RiscvCpu, sext};
+use crate::csr::{self, HpmEvent, Privilege};
+use crate::debug::WatchKind;
+use crate::mmu::{Access, Sv32};
+use crate::trap::Exception;
+use crate::xlen::Xlen;
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// The privilege level and V that `access` is checked at. With
+    /// `mstatus.MPRV` set, M-mode loads and stores act as if at MPP, and
+    /// MPV.
+    pub(crate) fn effective_mode(&self, access: Access) -> (Privilege, bool) {
+        let mstatus = self.csrs.read_u64(csr::MSTATUS) as u32;
+        if access == Access::Fetch || mstatus & csr::MSTATUS_MPRV == 0 {
+            return (self.privilege, self.virt);
+        }
+        let mpp = Privilege::from_bits(mstatus >> 11).unwrap_or(Privilege::Machine);
+        let mpv = self.csrs.mstatush() & csr::MSTATUSH_MPV != 0;
+        (mpp, mpv && mpp != Privilege::Machine)
+    }
+
+    /// The Sv32 walker for `privilege` with V=0, or `None` if addresses are
+    /// physical: in M-mode, with `satp` in bare mode, or on RV64.
+    pub(crate) fn sv32(&self, privilege: Privilege) -> Option<Sv32> {
+        let satp = self.csrs.read_u64(csr::SATP) as u32;
+        if X::BITS != 32 || privilege == Privilege::Machine || satp & csr::SATP_SV32 == 0 {
+            return None;
+        }
+
+        let mstatus = self.csrs.read_u64(csr::MSTATUS) as u32;
+        Some(Sv32 {
+            satp,
+            privilege,
+            sum: mstatus & csr::MSTATUS_SUM != 0,
+            mxr: mstatus & csr::MSTATUS_MXR != 0,
+            g_stage: None,
+        })
+    }
+
+
+    /// Map a virtual address to a bus address.
+    pub(crate) fn translate(&mut self, vaddr: u64, access: Access) -> Result<u32, Exception> {
+        let (privilege, virt) = self.effective_mode(access);
+        self.translate_at(vaddr, access, privilege, virt)
+    }
+
+    pub(crate) fn translate_at(
+        &mut self,
+        vaddr: u64,
+        access: Access,
+        privilege: Privilege,
+        virt: bool,
+    ) -> Result<u32, Exception> {
+        if virt {
+            return self.translate_guest(vaddr, access, privilege);
+        }
+        match self.sv32(privilege) {
+            Some(sv32) => sv32.translate(&mut self.bus, vaddr as u32, access),
+            None => Self::phys(vaddr).ok_or(access.access_fault(vaddr as u32)),
+        }
+    }
+
+    // Access faults report the virtual address, as they would in `mtval`.
+
+    pub(crate) fn read_virt(
+        &mut self,
+        vaddr: u64,
+        size: MemSize,
+        access: Access,
+    ) -> Result<u32, Exception> {
+        if access == Access::Load {
+            self.check_triggers(csr::MCONTROL_LOAD, vaddr)?;
+        }
+        let addr = self.translate(vaddr, access)?;
+        let value = self
+            .bus
+            .read(addr, size)
+            .ok_or(access.access_fault(vaddr as u32))?;
+        if access == Access::Load {
+            self.trace(|t| t.memory(vaddr, size, value, false));
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn write_virt(
+        &mut self,
+        vaddr: u64,
+        size: MemSize,
+        value: u32,
+    ) -> Result<(), Exception> {
+        self.check_triggers(csr::MCONTROL_STORE, vaddr)?;
+        let addr = self.translate(vaddr, Access::Store)?;
+        self.write_phys(addr, vaddr, size, value)?;
+        self.trace(|t| t.memory(vaddr, size, value, true));
+        Ok(())
+    }
+
+    /// Store to `addr`, which `vaddr` translated to.
+    pub(crate) fn write_phys(
+        &mut self,
+        addr: u32,
+        vaddr: u64,
+        size: MemSize,
+        value: u32,
+    ) -> Result<(), Exception> {
+        if self.journal.is_some() && self.bus.in_ram(addr, size.bytes()) {
+            let old = self.bus.read(addr, size).unwrap_or_default();
+            self.journal_frame(|frame| frame.memory.push((addr, size, old)));
+        }
+        self.bus
+            .write(addr, size, value)
+            .ok_or(Exception::StoreAccessFault(vaddr as u32))?;
+        if self.bus.note_write(addr) {
+            self.blocks.sync(self.bus.code_writes());
+        }
+        Ok(())
+    }
+
+    /// A load or store of `len` bytes at `vaddr` completed: check it against
+    /// the watchpoints, profile it and run it through the cache and TLB
+    /// models.
+    pub(crate) fn data_access(&mut self, vaddr: u64, len: u32, kind: WatchKind) {
+        if self.tlb.is_some() {
+            let access = match kind {
+                WatchKind::Write => Access::Store,
+                _ => Access::Load,
+            };
+            self.tlb_lookup(vaddr, len, access);
+        }
+        let addr = vaddr as u32;
+        self.debug.check_access(self.pc_u32(), addr, len, kind);
+        if let Some(profile) = &mut self.memory_profile {
+            profile.record(addr, kind);
+        }
+        if let Some(caches) = &mut self.caches {
+            caches.data(addr, len, kind == WatchKind::Write);
+        }
+    }
+
+    /// Look up each page of a translated access of `len` bytes at `vaddr` in
+    /// the TLB model, if paging applied to it.
+    pub(crate) fn tlb_lookup(&mut self, vaddr: u64, len: u32, access: Access) {
+        let (privilege, virt) = self.effective_mode(access);
+        let asid = match self.sv32(privilege) {
+            _ if virt => return,
+            Some(sv32) => (sv32.satp >> 22) & csr::SATP_ASID_RV32,
+            None => return,
+        };
+        let Some(tlb) = &mut self.tlb else {
+            return;
+        };
+        let last = vaddr.wrapping_add(len.max(1) as u64 - 1);
+        for vpn in vaddr >> 12..=last >> 12 {
+            tlb.lookup(asid, vpn);
+        }
+    }
+
+    pub(crate) fn effective_addr(&self, rs1: u8, imm: i32) -> u64 {
+        self.read_reg(rs1).wrapping_add(sext(imm)) & X::MASK
+    }
+
+    pub(crate) fn exec_load(
+        &mut self,
+        rd: u8,
+        rs1: u8,
+        imm: i32,
+        size: MemSize,
+        signed: bool,
+    ) -> Result<(), Exception> {
+        let vaddr = self.effective_addr(rs1, imm);
+        let raw = self.read_virt(vaddr, size, Access::Load)?;
+        self.data_access(vaddr, size.bytes() as u32, WatchKind::Read);
+
+        let value = match (signed, size) {
+            (false, _) => raw as u64,
+            (true, MemSize::Byte) => raw as i8 as i64 as u64,
+            (true, MemSize::Half) => raw as i16 as i64 as u64,
+            (true, MemSize::Word) => raw as i32 as i64 as u64,
+        };
+        self.write_reg(rd, value);
+        self.csrs.count(HpmEvent::Load);
+
+        Ok(())
+    }
+
+    pub(crate) fn exec_store(
+        &mut self,
+        rs1: u8,
+        rs2: u8,
+        imm: i32,
+        size: MemSize,
+    ) -> Result<(), Exception> {
+        let vaddr = self.effective_addr(rs1, imm);
+        self.write_virt(vaddr, size, self.read_reg(rs2) as u32)?;
+        self.data_access(vaddr, size.bytes() as u32, WatchKind::Write);
+        self.csrs.count(HpmEvent::Store);
+
+        Ok(())
+    }
+
+    // The bus is at most word-wide, so LD and SD are split into two word
+    // accesses, low word first.
+
+    pub(crate) fn exec_load_double(&mut self, rd: u8, rs1: u8, imm: i32) -> Result<(), Exception> {
+        let vaddr = self.effective_addr(rs1, imm);
+        let low = self.read_virt(vaddr, MemSize::Word, Access::Load)?;
+        let high = self.read_virt(vaddr.wrapping_add(4) & X::MASK, MemSize::Word, Access::Load)?;
+        self.data_access(vaddr, 8, WatchKind::Read);
+        self.write_reg(rd, ((high as u64) << 32) | low as u64);
+        self.csrs.count(HpmEvent::Load);
+
+        Ok(())
+    }
+
+    pub(crate) fn exec_store_double(
+        &mut self,
+        rs1: u8,
+        rs2: u8,
+        imm: i32,
+    ) -> Result<(), Exception> {
+        let vaddr = self.effective_addr(rs1, imm);
+        let value = self.read_reg(rs2);
+        self.write_virt(vaddr, MemSize::Word, value as u32)?;
+        self.write_virt(
+            vaddr.wrapping_add(4) & X::MASK,
+            MemSize::Word,
+            (value >> 32) as u32,
+        )?;
+        self.data_access(vaddr, 8, WatchKind::Write);
+        self.csrs.count(HpmEvent::Store);
+
+        Ok(())
+    }
+
+    // Reservations live on the bus, keyed by `mhartid`, so a store from any
+    // hart sharing it breaks them. Misaligned LR/SC raise access faults,
+    // which the spec allows in place of misaligned-address exceptions.
+
+    pub(crate) fn exec_load_reserved(
+        &mut self,
+        rd: u8,
+        rs1: u8,
+        double: bool,
+    ) -> Result<(), Exception> {
+        let vaddr = self.read_reg(rs1);
+        if !vaddr.is_multiple_of(if double { 8 } else { 4 }) {
+            return Err(Exception::LoadAccessFault(vaddr as u32));
+        }
+
+        let paddr = self.translate(vaddr, Access::Load)?;
+        if double {
+            self.exec_load_double(rd, rs1, 0)?;
+        } else {
+            self.exec_load(rd, rs1, 0, MemSize::Word, true)?;
+        }
+        self.bus.reserve(self.hart_id(), paddr);
+
+        Ok(())
+    }
+
+    /// Writes 0 to `rd` if the store happened, 1 if the reservation was lost.
+    pub(crate) fn exec_store_conditional(
+        &mut self,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+        double: bool,
+    ) -> Result<(), Exception> {
+        let vaddr = self.read_reg(rs1);
+        if !vaddr.is_multiple_of(if double { 8 } else { 4 }) {
+            return Err(Exception::StoreAccessFault(vaddr as u32));
+        }
+
+        let paddr = self.translate(vaddr, Access::Store)?;
+        let reserved = self.bus.take_reservation(self.hart_id(), paddr);
+        if reserved {
+            if double {
+                self.exec_store_double(rs1, rs2, 0)?;
+            } else {
+                self.exec_store(rs1, rs2, 0, MemSize::Word)?;
+            }
+        }
+        self.write_reg(rd, !reserved as u64);
+
+        Ok(())
+    }
+}
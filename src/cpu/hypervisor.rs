@@ -0,0 +1,308 @@
+//! The H extension: VS- and VU-mode, the guest's trap entry and SRET,
+//! two-stage translation, and HLV/HSV/HFENCE.
+//!
+//! V=1 is tracked next to the privilege level: VS-mode is S-mode with V
+//! set, VU-mode is U-mode with V set. While V=1 the supervisor CSRs are
+//! backed by their `vs*` counterparts, and anything a guest isn't allowed
+//! to do that HS-mode could raises a virtual-instruction exception instead
+//! of an illegal-instruction one.
+
+use super::{MemSize, RiscvCpu};
+use crate::csr::{self, HpmEvent, Privilege};
+use crate::decode::{HypervisorInstruction, Instruction};
+use crate::isa::Extension;
+use crate::mmu::{Access, Sv32, Sv32x4};
+use crate::trap::Exception;
+use crate::xlen::Xlen;
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// Whether the hart is running a guest, in VS- or VU-mode.
+    pub fn virtualized(&self) -> bool {
+        self.virt
+    }
+
+    /// Enter or leave VS/VU-mode without a trap or xRET, for hosts setting
+    /// up a guest. Has no effect in M-mode or without the H extension.
+    pub fn set_virtualized(&mut self, virt: bool) {
+        self.virt =
+            virt && self.privilege != Privilege::Machine && self.extensions.contains(Extension::H);
+    }
+
+    pub(crate) fn hypervisor_permitted(&self, instruction: HypervisorInstruction) -> bool {
+        use HypervisorInstruction::*;
+
+        if self.virt {
+            return false;
+        }
+        let hstatus = self.csrs.read_u64(csr::HSTATUS) as u32;
+        match instruction {
+            Load { .. } | Store { .. } => {
+                self.privilege >= Privilege::Supervisor || hstatus & csr::HSTATUS_HU != 0
+            }
+            FenceVvma { .. } => self.privilege >= Privilege::Supervisor,
+            FenceGvma { .. } => {
+                self.privilege == Privilege::Machine || self.privileged_op(csr::MSTATUS_TVM)
+            }
+        }
+    }
+
+    /// Whether a guest's `instruction`, which it isn't allowed to run, is
+    /// one HS-mode could: then it's a virtual-instruction exception.
+    pub(crate) fn virtual_instruction(&self, instruction: Instruction) -> bool {
+        use Instruction::*;
+
+        if !self.virt {
+            return false;
+        }
+        let mstatus = self.csrs.read_u64(csr::MSTATUS) as u32;
+        let hypervisor =
+            |csr, writes| self.csr_accessible_at(csr, writes, Privilege::Supervisor, false);
+        match instruction {
+            Sret | SfenceVma { .. } | Hypervisor(_) => true,
+            Wfi => mstatus & csr::MSTATUS_TW == 0,
+            Csrrw { csr, .. } | Csrrwi { csr, .. } => hypervisor(csr, true),
+            Csrrs { rs1, csr, .. } | Csrrc { rs1, csr, .. } => hypervisor(csr, rs1 != 0),
+            Csrrsi { uimm, csr, .. } | Csrrci { uimm, csr, .. } => hypervisor(csr, uimm != 0),
+            _ => false,
+        }
+    }
+
+    /// With V=1, the supervisor CSR a guest's `csr` really names.
+    pub(crate) fn guest_csr(&self, csr: u16) -> u16 {
+        match csr {
+            csr::SSTATUS
+            | csr::SIE
+            | csr::STVEC
+            | csr::SSCRATCH
+            | csr::SEPC
+            | csr::SCAUSE
+            | csr::STVAL
+            | csr::SIP
+            | csr::SATP
+                if self.virt =>
+            {
+                csr + 0x100
+            }
+            csr => csr,
+        }
+    }
+
+    /// Translate a guest's `vaddr`: through `vsatp`'s table, then `hgatp`'s.
+    /// Either stage can be bare.
+    pub(crate) fn translate_guest(
+        &mut self,
+        vaddr: u64,
+        access: Access,
+        privilege: Privilege,
+    ) -> Result<u32, Exception> {
+        let mstatus = self.csrs.read_u64(csr::MSTATUS) as u32;
+        let vsstatus = self.csrs.read_u64(csr::VSSTATUS) as u32;
+        let vsatp = self.csrs.read_u64(csr::VSATP) as u32;
+        let hgatp = self.csrs.read_u64(csr::HGATP) as u32;
+        let rv32 = X::BITS == 32;
+
+        let g_stage = (rv32 && hgatp & csr::HGATP_SV32X4 != 0).then_some(Sv32x4 {
+            hgatp,
+            mxr: mstatus & csr::MSTATUS_MXR != 0,
+        });
+        if rv32 && vsatp & csr::SATP_SV32 != 0 {
+            let vs_stage = Sv32 {
+                satp: vsatp,
+                privilege,
+                sum: vsstatus & csr::MSTATUS_SUM != 0,
+                mxr: (mstatus | vsstatus) & csr::MSTATUS_MXR != 0,
+                g_stage,
+            };
+            return vs_stage.translate(&mut self.bus, vaddr as u32, access);
+        }
+        match g_stage {
+            Some(g_stage) => g_stage.translate(&mut self.bus, vaddr, vaddr as u32, access, access),
+            None => Self::phys(vaddr).ok_or(access.access_fault(vaddr as u32)),
+        }
+    }
+
+    pub(crate) fn execute_hypervisor(
+        &mut self,
+        instruction: HypervisorInstruction,
+    ) -> Result<(), Exception> {
+        use HypervisorInstruction::*;
+
+        match instruction {
+            FenceVvma { .. } | FenceGvma { .. } => self.blocks.flush(),
+            Load { .. } | Store { .. } => {
+                self.guest_access = true;
+                let result = self.guest_load_store(instruction);
+                self.guest_access = result.is_err();
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    /// HLV, HLVX and HSV: a load or store as the guest would do it, at
+    /// `hstatus.SPVP`. The bus is at most word-wide, so doublewords are two
+    /// word accesses, low word first.
+    fn guest_load_store(&mut self, instruction: HypervisorInstruction) -> Result<(), Exception> {
+        let hstatus = self.csrs.read_u64(csr::HSTATUS) as u32;
+        let privilege = match hstatus & csr::HSTATUS_SPVP {
+            0 => Privilege::User,
+            _ => Privilege::Supervisor,
+        };
+        let size = |bytes| match bytes {
+            1 => MemSize::Byte,
+            2 => MemSize::Half,
+            _ => MemSize::Word,
+        };
+
+        match instruction {
+            HypervisorInstruction::Load {
+                rd,
+                rs1,
+                bytes,
+                signed,
+                execute,
+            } => {
+                // HLVX needs execute permission, but faults like a load.
+                let access = if execute { Access::Fetch } else { Access::Load };
+                let vaddr = self.read_reg(rs1);
+                let mut value = 0;
+                for word in 0..bytes.div_ceil(4) as u64 {
+                    let vaddr = vaddr.wrapping_add(4 * word) & X::MASK;
+                    let addr = self
+                        .translate_at(vaddr, access, privilege, true)
+                        .map_err(as_load)?;
+                    let raw = self
+                        .bus
+                        .read(addr, size(bytes))
+                        .ok_or(Exception::LoadAccessFault(vaddr as u32))?;
+                    value |= (raw as u64) << (32 * word);
+                }
+                let value = match (signed, bytes) {
+                    (true, 1) => value as i8 as i64 as u64,
+                    (true, 2) => value as i16 as i64 as u64,
+                    (true, 4) => value as i32 as i64 as u64,
+                    _ => value,
+                };
+                self.write_reg(rd, value);
+                self.csrs.count(HpmEvent::Load);
+            }
+            HypervisorInstruction::Store { rs1, rs2, bytes } => {
+                let vaddr = self.read_reg(rs1);
+                let value = self.read_reg(rs2);
+                for word in 0..bytes.div_ceil(4) as u64 {
+                    let vaddr = vaddr.wrapping_add(4 * word) & X::MASK;
+                    let addr = self.translate_at(vaddr, Access::Store, privilege, true)?;
+                    self.write_phys(addr, vaddr, size(bytes), (value >> (32 * word)) as u32)?;
+                }
+                self.csrs.count(HpmEvent::Store);
+            }
+            _ => unreachable!("not a guest load or store"),
+        }
+        Ok(())
+    }
+
+    /// Enter the guest's trap handler, through the VS CSRs. VS-level
+    /// interrupts look like their S-level counterparts to the guest.
+    pub(crate) fn take_guest_trap(&mut self, cause: u64, tval: u64, vector: Option<u32>) {
+        self.guest_access = false;
+        let vector = vector.map(|code| match code {
+            2 | 6 | 10 => code - 1,
+            code => code,
+        });
+        let cause = match vector {
+            Some(code) => (1 << (X::BITS - 1)) | code as u64,
+            None => cause,
+        };
+
+        // SPIE <- SIE, SIE <- 0, SPP <- the interrupted mode, in vsstatus
+        let vsstatus = self.csrs.read_u64(csr::VSSTATUS);
+        let sie = vsstatus & csr::MSTATUS_SIE as u64;
+        let cleared = !((csr::MSTATUS_SIE | csr::MSTATUS_SPIE | csr::MSTATUS_SPP) as u64);
+        let spp = (self.privilege as u64) << 8;
+        self.csrs
+            .set_u64(csr::VSSTATUS, (vsstatus & cleared) | (sie << 4) | spp);
+        self.privilege = Privilege::Supervisor;
+
+        self.csrs.set_u64(csr::VSEPC, X::widen(self.pc));
+        self.csrs.set_u64(csr::VSCAUSE, cause);
+        self.csrs.set_u64(csr::VSTVAL, tval);
+        let tvec = self.csrs.read_u64(csr::VSTVEC);
+        self.jump_to_handler(tvec, vector);
+    }
+
+    /// On a trap into HS- or M-mode, record whether it came from a guest and
+    /// whether `tval` is a guest virtual address, then leave V=0. `code` is
+    /// the cause without the interrupt bit.
+    pub(crate) fn leave_guest(
+        &mut self,
+        target: Privilege,
+        code: u64,
+        exception: bool,
+        tval2: u64,
+    ) {
+        let guest_access = std::mem::take(&mut self.guest_access);
+        if !self.extensions.contains(Extension::H) {
+            return;
+        }
+
+        let address = matches!(code, 0 | 1 | 3..=7 | 12 | 13 | 15 | 20 | 21 | 23);
+        let gva = exception && address && (self.virt || guest_access);
+        match target {
+            Privilege::Machine => {
+                self.csrs.set_mstatush(csr::MSTATUSH_MPV, self.virt);
+                self.csrs.set_mstatush(csr::MSTATUSH_GVA, gva);
+                self.csrs.set_u64(csr::MTVAL2, tval2);
+                self.csrs.set_u64(csr::MTINST, 0);
+            }
+            _ => {
+                let mut hstatus = self.csrs.read_u64(csr::HSTATUS) as u32;
+                let set = |hstatus: &mut u32, bit: u32, on: bool| match on {
+                    true => *hstatus |= bit,
+                    false => *hstatus &= !bit,
+                };
+                set(&mut hstatus, csr::HSTATUS_SPV, self.virt);
+                set(&mut hstatus, csr::HSTATUS_GVA, gva);
+                if self.virt {
+                    let supervisor = self.privilege == Privilege::Supervisor;
+                    set(&mut hstatus, csr::HSTATUS_SPVP, supervisor);
+                }
+                let high = self.csrs.read_u64(csr::HSTATUS) & !0xFFFF_FFFF;
+                self.csrs.set_u64(csr::HSTATUS, high | hstatus as u64);
+                self.csrs.set_u64(csr::HTVAL, tval2);
+                self.csrs.set_u64(csr::HTINST, 0);
+            }
+        }
+        self.virt = false;
+    }
+
+    /// SRET in VS-mode: the same as in HS-mode, but on `vsstatus` and
+    /// `vsepc`, and V stays set.
+    pub(crate) fn guest_sret(&mut self, next_pc: &mut X::Reg) {
+        let vsstatus = self.csrs.read_u64(csr::VSSTATUS);
+        let spie = vsstatus & csr::MSTATUS_SPIE as u64;
+        let spp = match vsstatus & csr::MSTATUS_SPP as u64 {
+            0 => Privilege::User,
+            _ => Privilege::Supervisor,
+        };
+        let cleared = !((csr::MSTATUS_SIE | csr::MSTATUS_SPP) as u64);
+        let vsstatus = (vsstatus & cleared) | (spie >> 4) | csr::MSTATUS_SPIE as u64;
+        self.csrs.set_u64(csr::VSSTATUS, vsstatus);
+
+        let mstatus = self.csrs.read_u64(csr::MSTATUS);
+        self.csrs
+            .set_u64(csr::MSTATUS, mstatus & !(csr::MSTATUS_MPRV as u64));
+        self.privilege = spp;
+
+        *next_pc = self.csrs.read(csr::VSEPC);
+    }
+}
+
+/// A fault from HLVX's execute-permission walk, as the load it really is.
+fn as_load(exception: Exception) -> Exception {
+    match exception {
+        Exception::InstructionAccessFault(addr) => Exception::LoadAccessFault(addr),
+        Exception::InstructionPageFault(addr) => Exception::LoadPageFault(addr),
+        Exception::InstructionGuestPageFault(addr, gpa) => Exception::LoadGuestPageFault(addr, gpa),
+        exception => exception,
+    }
+}
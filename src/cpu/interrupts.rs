@@ -0,0 +1,273 @@
+//! Interrupts, WFI and guest time, and taking traps and returning from
+//! them.
+
+use super::{RiscvCpu, Wait};
+use crate::clock::Clock;
+use crate::csr::{self, HpmEvent, Privilege};
+use crate::isa::Extension;
+use crate::trap::{Exception, Interrupt};
+use crate::xlen::Xlen;
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// Mark an interrupt as pending in `mip`, as a platform device would.
+    pub fn raise_interrupt(&mut self, interrupt: Interrupt) {
+        let mip = self.csrs.read_u64(csr::MIP);
+        self.csrs.set_u64(csr::MIP, mip | interrupt.mask() as u64);
+    }
+
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        let mip = self.csrs.read_u64(csr::MIP);
+        self.csrs
+            .set_u64(csr::MIP, mip & !(interrupt.mask() as u64));
+    }
+
+    /// Where guest time comes from: one tick per instruction, or the
+    /// host's clock. See [`clock`](crate::clock).
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.bus.set_clock(clock);
+    }
+
+    pub fn clock(&self) -> &Clock {
+        self.bus.clock()
+    }
+
+    /// Whether the hart is stopped in WFI or WRS with nothing to wake it yet.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting.is_some() && !self.wakeup_pending()
+    }
+
+    /// WFI resumes once any interrupt is pending and enabled in `mie`, even
+    /// if `mstatus.MIE` keeps it from being taken. WRS also resumes when the
+    /// reservation is lost or its timeout runs out.
+    pub(crate) fn wakeup_pending(&self) -> bool {
+        if self.csrs.read_u64(csr::MIP) & self.csrs.read_u64(csr::MIE) != 0 {
+            return true;
+        }
+
+        match self.waiting {
+            Some(Wait::Reservation { deadline }) => {
+                !self.bus.has_reservation(self.hart_id())
+                    || deadline.is_some_and(|deadline| self.bus.time() >= deadline)
+            }
+            _ => false,
+        }
+    }
+
+    /// The bus time a WRS.STO in progress gives up at.
+    pub(crate) fn wait_deadline(&self) -> Option<u64> {
+        match self.waiting {
+            Some(Wait::Reservation { deadline }) => deadline,
+            _ => None,
+        }
+    }
+
+    /// Ticks until the next device event or WRS.STO timeout.
+    pub(crate) fn next_wakeup(&self) -> Option<u64> {
+        let timeout = self
+            .wait_deadline()
+            .map(|deadline| deadline.saturating_sub(self.bus.time()));
+        match (self.bus.next_event(), timeout) {
+            (Some(event), Some(timeout)) => Some(event.min(timeout)),
+            (event, timeout) => event.or(timeout),
+        }
+    }
+
+    /// Let `ticks` pass on the bus and pick up any interrupt lines that
+    /// changed as a result.
+    pub(crate) fn advance_time(&mut self, ticks: u64) {
+        self.bus.tick(ticks);
+        self.sync_device_interrupts();
+    }
+
+    /// Like [`advance_time`](Self::advance_time), but with nothing running
+    /// meanwhile, so in host time it sleeps.
+    pub(crate) fn skip_time(&mut self, ticks: u64) {
+        self.bus.skip(ticks);
+        self.sync_device_interrupts();
+    }
+
+    /// Mirror device interrupt lines into `mip`. Only lines that changed are
+    /// touched, so bits raised with [`raise_interrupt`](Self::raise_interrupt)
+    /// stay put.
+    pub(crate) fn sync_device_interrupts(&mut self) {
+        let lines = self.bus.interrupts(self.hart_id());
+        let changed = lines ^ self.device_lines;
+        if changed != 0 {
+            let mip = self.csrs.read_u64(csr::MIP) & !changed as u64;
+            self.csrs.set_u64(csr::MIP, mip | (lines & changed) as u64);
+            self.device_lines = lines;
+        }
+    }
+
+    /// The highest-priority interrupt that is pending, enabled in `mie` and
+    /// globally enabled at the privilege level it traps to: by
+    /// `mstatus.MIE` in M-mode, or for delegated ones `mstatus.SIE` in
+    /// S-mode. A lower privilege level can't mask either.
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        let mstatus = self.csrs.read_u64(csr::MSTATUS);
+        let mideleg = self.csrs.read_u64(csr::MIDELEG);
+        let hideleg = self.csrs.read_u64(csr::HIDELEG);
+        let active = self.csrs.read_u64(csr::MIP) & self.csrs.read_u64(csr::MIE);
+
+        let enabled = |status: u64, target: Privilege, bit: u32| {
+            self.privilege < target || (self.privilege == target && status & bit as u64 != 0)
+        };
+        let machine = enabled(mstatus, Privilege::Machine, csr::MSTATUS_MIE);
+        // A guest can't mask HS-mode interrupts, and only gets its own
+        // while it's running.
+        let supervisor = self.virt || enabled(mstatus, Privilege::Supervisor, csr::MSTATUS_SIE);
+        let vsstatus = self.csrs.read_u64(csr::VSSTATUS);
+        let guest = self.virt && enabled(vsstatus, Privilege::Supervisor, csr::MSTATUS_SIE);
+
+        Interrupt::PRIORITY.into_iter().find(|interrupt| {
+            let mask = interrupt.mask() as u64;
+            let enabled = match (mideleg & mask, hideleg & mask) {
+                (0, _) => machine,
+                (_, 0) => supervisor,
+                _ => guest,
+            };
+            enabled && active & mask != 0
+        })
+    }
+
+    pub(crate) fn take_interrupt(&mut self, interrupt: Interrupt) {
+        // The interrupt flag is the top bit of mcause, wherever that is.
+        let cause = (1 << (X::BITS - 1)) | interrupt.code() as u64;
+        self.take_trap(cause, 0, 0, Some(interrupt.code()));
+    }
+
+    pub(crate) fn take_exception(&mut self, exception: Exception) {
+        self.take_trap(
+            exception.cause() as u64,
+            exception.tval() as u64,
+            exception.tval2() as u64,
+            None,
+        );
+    }
+
+    /// Enter the trap handler: in S-mode if the trap came from S or U and
+    /// `medeleg`/`mideleg` delegates it, M-mode otherwise. A guest's trap
+    /// that `hedeleg`/`hideleg` delegates further goes to VS-mode. `vector`
+    /// is the interrupt code, used when the `tvec` is in vectored mode;
+    /// exceptions always go to the base. `tval2` is for `htval`/`mtval2`.
+    fn take_trap(&mut self, cause: u64, tval: u64, tval2: u64, vector: Option<u32>) {
+        self.csrs.count(match vector {
+            Some(_) => HpmEvent::Interrupt,
+            None => HpmEvent::Exception,
+        });
+
+        let code = cause & !(1 << (X::BITS - 1));
+        let (delegation, guest_delegation) = match vector {
+            Some(_) => (csr::MIDELEG, csr::HIDELEG),
+            None => (csr::MEDELEG, csr::HEDELEG),
+        };
+        let delegates = |csr| self.csrs.read_u64(csr) >> code & 1 != 0;
+        let delegated = self.privilege <= Privilege::Supervisor && delegates(delegation);
+        if delegated && self.virt && delegates(guest_delegation) {
+            return self.take_guest_trap(cause, tval, vector);
+        }
+
+        let mstatus = self.csrs.read_u64(csr::MSTATUS);
+        let tvec = if delegated {
+            self.leave_guest(Privilege::Supervisor, code, vector.is_none(), tval2);
+            // SPIE <- SIE, SIE <- 0, SPP <- the interrupted mode
+            let sie = mstatus & csr::MSTATUS_SIE as u64;
+            let cleared = !((csr::MSTATUS_SIE | csr::MSTATUS_SPIE | csr::MSTATUS_SPP) as u64);
+            let spp = (self.privilege as u64) << 8;
+            self.csrs
+                .set_u64(csr::MSTATUS, (mstatus & cleared) | (sie << 4) | spp);
+            self.privilege = Privilege::Supervisor;
+
+            self.csrs.set_u64(csr::SEPC, X::widen(self.pc));
+            self.csrs.set_u64(csr::SCAUSE, cause);
+            self.csrs.set_u64(csr::STVAL, tval);
+            self.csrs.read_u64(csr::STVEC)
+        } else {
+            self.leave_guest(Privilege::Machine, code, vector.is_none(), tval2);
+            // MPIE <- MIE, MIE <- 0, MPP <- the interrupted mode
+            let mie = mstatus & csr::MSTATUS_MIE as u64;
+            let cleared = !((csr::MSTATUS_MIE | csr::MSTATUS_MPIE | csr::MSTATUS_MPP) as u64);
+            let mpp = (self.privilege as u64) << 11;
+            self.csrs
+                .set_u64(csr::MSTATUS, (mstatus & cleared) | (mie << 4) | mpp);
+            self.privilege = Privilege::Machine;
+
+            self.csrs.set_u64(csr::MEPC, X::widen(self.pc));
+            self.csrs.set_u64(csr::MCAUSE, cause);
+            self.csrs.set_u64(csr::MTVAL, tval);
+            self.csrs.read_u64(csr::MTVEC)
+        };
+
+        self.jump_to_handler(tvec, vector);
+    }
+
+    /// Set the PC from a `tvec`: its base, or in vectored mode the entry
+    /// for interrupt `vector`.
+    pub(crate) fn jump_to_handler(&mut self, tvec: u64, vector: Option<u32>) {
+        let base = tvec & !0x3;
+        self.pc = X::truncate(match (tvec & 0x3, vector) {
+            (0x1, Some(code)) => base.wrapping_add(4 * code as u64),
+            _ => base,
+        });
+    }
+
+    pub(crate) fn mret(&mut self, next_pc: &mut X::Reg) {
+        let mstatus = self.csrs.read_u64(csr::MSTATUS);
+        let mpie = mstatus & csr::MSTATUS_MPIE as u64;
+
+        // MIE <- MPIE, MPIE <- 1, drop to MPP and leave MPP as the least
+        // privileged mode there is. Leaving M-mode also clears MPRV.
+        let mpp = Privilege::from_bits((mstatus >> 11) as u32).unwrap_or(Privilege::Machine);
+        let lowest = match self.extensions.contains(Extension::U) {
+            true => Privilege::User,
+            false => Privilege::Machine,
+        };
+        let mut cleared = !((csr::MSTATUS_MIE | csr::MSTATUS_MPP) as u64);
+        if mpp != Privilege::Machine {
+            cleared &= !(csr::MSTATUS_MPRV as u64);
+        }
+        let mstatus =
+            (mstatus & cleared) | (mpie >> 4) | csr::MSTATUS_MPIE as u64 | (lowest as u64) << 11;
+        self.csrs.set_u64(csr::MSTATUS, mstatus);
+        self.privilege = mpp;
+
+        // V <- MPV, unless returning to M-mode. MPV <- 0.
+        if self.extensions.contains(Extension::H) {
+            let mpv = self.csrs.mstatush() & csr::MSTATUSH_MPV != 0;
+            self.virt = mpv && mpp != Privilege::Machine;
+            self.csrs.set_mstatush(csr::MSTATUSH_MPV, false);
+        }
+
+        *next_pc = self.csrs.read(csr::MEPC);
+    }
+
+    pub(crate) fn sret(&mut self, next_pc: &mut X::Reg) {
+        if self.virt {
+            return self.guest_sret(next_pc);
+        }
+
+        let mstatus = self.csrs.read_u64(csr::MSTATUS);
+        let spie = mstatus & csr::MSTATUS_SPIE as u64;
+
+        // SIE <- SPIE, SPIE <- 1, drop to SPP and leave SPP <- U. S and U
+        // are both below M, so MPRV is cleared too.
+        let spp = match mstatus & csr::MSTATUS_SPP as u64 {
+            0 => Privilege::User,
+            _ => Privilege::Supervisor,
+        };
+        let cleared = !((csr::MSTATUS_SIE | csr::MSTATUS_SPP | csr::MSTATUS_MPRV) as u64);
+        let mstatus = (mstatus & cleared) | (spie >> 4) | csr::MSTATUS_SPIE as u64;
+        self.csrs.set_u64(csr::MSTATUS, mstatus);
+        self.privilege = spp;
+
+        // V <- SPV, SPV <- 0.
+        if self.extensions.contains(Extension::H) {
+            let hstatus = self.csrs.read_u64(csr::HSTATUS);
+            self.virt = hstatus & csr::HSTATUS_SPV as u64 != 0;
+            self.csrs
+                .set_u64(csr::HSTATUS, hstatus & !(csr::HSTATUS_SPV as u64));
+        }
+
+        *next_pc = self.csrs.read(csr::SEPC);
+    }
+}
@@ -0,0 +1,2124 @@
+//! The hart: its state, and fetch, decode and dispatch. Loads, stores and
+//! translation are in `access`, interrupts and traps in `interrupts`, and
+//! the H extension in `hypervisor`.
+
+mod access;
+mod hypervisor;
+mod interrupts;
+
+use std::rc::Rc;
+#[cfg(feature = "std")]
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::backtrace::{CallStack, Frame};
+use crate::block::{Block, BlockCache, Engine, MAX_BLOCK_LEN};
+use crate::builder::RiscvCpuBuilder;
+use crate::bus::Bus;
+use crate::cache::CacheModel;
+use crate::capture::OutputCapture;
+use crate::costs::CycleCosts;
+use crate::coverage::Coverage;
+use crate::csr::{CsrFile, HpmEvent, Privilege};
+use crate::custom::{CustomHandler, CustomOpcode, Hart, HartView};
+use crate::debug::{Debugger, RegisterWrite, WatchHit, WatchKind, Watchpoint};
+use crate::decode::{DecodeError, FloatInstruction, Instruction};
+use crate::devices::Device;
+#[cfg(feature = "dwarf")]
+use crate::dwarf;
+use crate::float::FloatRegs;
+use crate::hooks::{StepHook, StepInfo};
+use crate::icache::DecodeCache;
+use crate::isa::{Extension, Extensions};
+#[cfg(feature = "jit")]
+use crate::jit;
+use crate::journal::Journal;
+use crate::loader::{ElfFile, Image, IntelHex, Symbol, SymbolTable};
+use crate::mmu::Access;
+#[cfg(feature = "std")]
+use crate::perf::{PerfCounter, PerfStats};
+use crate::profile::MemoryProfile;
+use crate::reg::Reg;
+use crate::semihosting::Semihosting;
+use crate::snapshot::{MemoryDiff, MemorySnapshot, Snapshot};
+use crate::stats::Stats;
+use crate::syscall::Syscalls;
+use crate::timing::{Pipeline, PipelineConfig};
+use crate::tlb::{Tlb, TlbConfig};
+use crate::trace::Tracer;
+use crate::trap::Exception;
+use crate::user::Process;
+use crate::vector::VectorRegs;
+use crate::xlen::{Rv32, Xlen};
+use crate::{backtrace, block, csr, journal, semihosting, syscall, user, vector};
+
+/// A single hart. `X` picks the register width; RV32 is the default.
+///
+/// The bus has a 32-bit physical address space on either width, so
+/// addresses handed to the host (breakpoints, watchpoints, exception
+/// values, trace events) are `u32`. An RV64 access outside that space
+/// faults with the address truncated to 32 bits.
+pub struct RiscvCpu<X: Xlen = Rv32> {
+    /// `x0` to `x31`. Writing here bypasses the x0 invariant and register
+    /// watches; [`reg`](Self::reg) and [`set_reg`](Self::set_reg) don't.
+    pub regs: [X::Reg; 32],
+    /// The F/D register file, raw 64-bit values. Unused with Zfinx.
+    pub fregs: [u64; 32],
+    pub pc: X::Reg,
+    pub bus: Bus,
+    pub csrs: CsrFile<X>,
+    pub(crate) privilege: Privilege,
+    /// The H extension's V: running a guest in VS- or VU-mode.
+    pub(crate) virt: bool,
+    /// An HLV or HSV faulted, so the trap reports a guest virtual address.
+    guest_access: bool,
+    pub(crate) float_regs: FloatRegs,
+    pub(crate) extensions: Extensions,
+    pub(crate) vector: VectorRegs,
+    pub(crate) debug: Debugger,
+    calls: CallStack,
+    pub(crate) icache: DecodeCache,
+    pub(crate) engine: Engine,
+    pub(crate) blocks: BlockCache,
+    #[cfg(feature = "jit")]
+    pub(crate) jit: Option<jit::Jit>,
+    pub(crate) guest_traps: bool,
+    pub(crate) semihosting: Option<Semihosting>,
+    pub(crate) syscalls: Option<Syscalls>,
+    exit_code: Option<i32>,
+    pub(crate) tracer: Option<Box<dyn Tracer>>,
+    pre_step: Vec<StepHook>,
+    post_step: Vec<StepHook>,
+    coverage: Option<Coverage>,
+    memory_profile: Option<MemoryProfile>,
+    caches: Option<CacheModel>,
+    tlb: Option<Tlb>,
+    pipeline: Option<Pipeline>,
+    costs: Option<CycleCosts>,
+    stats: Stats,
+    pub(crate) symbols: SymbolTable,
+    #[cfg(feature = "dwarf")]
+    lines: dwarf::LineTable,
+    /// Handlers for custom-0..3, in that order.
+    pub(crate) custom: [Option<Box<dyn CustomHandler>>; 4],
+    /// The handler for opcodes the decoder doesn't know.
+    pub(crate) unknown: Option<Box<dyn CustomHandler>>,
+    #[cfg(feature = "std")]
+    perf: PerfCounter,
+    /// Stopped in WFI or WRS, and what will wake it.
+    waiting: Option<Wait>,
+    /// Whether a waiting hart may skip time ahead to the next device event.
+    /// Off when other harts share the bus and still have work to do.
+    pub(crate) fast_forward: bool,
+    /// The `mip` bits devices were asserting when last looked at.
+    device_lines: u32,
+    journal: Option<Journal<X>>,
+}
+
+/// What a stalled hart is waiting for. Any pending, enabled interrupt wakes
+/// either kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Wait {
+    Interrupt,
+    /// WRS: the LR reservation going away, or for WRS.STO, the bus time it
+    /// gives up at.
+    Reservation {
+        deadline: Option<u64>,
+    },
+}
+
+/// How long WRS.STO waits at most, in ticks.
+const WRS_STO_TICKS: u64 = 64;
+
+/// Steps between reads of the clock when running to a deadline.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// How a call to [`RiscvCpu::step`] ended when no exception was raised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    /// Stopped before executing the instruction at this address. Stepping
+    /// again runs it.
+    Breakpoint(u32),
+    /// The instruction completed but touched a watched address.
+    Watchpoint(WatchHit),
+    /// The instruction completed but wrote a watched register.
+    RegisterWrite(RegisterWrite),
+    /// The guest asked to exit, through semihosting or the `exit` system
+    /// call.
+    Exited(i32),
+}
+
+/// Why [`RiscvCpu::run`] and friends returned control to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitReason {
+    Breakpoint(u32),
+    Watchpoint(WatchHit),
+    RegisterWrite(RegisterWrite),
+    /// `run_until` reached its target PC. The instruction there hasn't run.
+    ReachedPc(u32),
+    /// `run_steps` used up its step budget.
+    StepLimit,
+    /// `run_with_limits` ran out of wall-clock time.
+    TimeLimit,
+    /// The hart is waiting in WFI and no device has an event scheduled that
+    /// could wake it. Raise an interrupt and run again to continue.
+    Idle,
+    Exception(Exception),
+    Exited(i32),
+}
+
+#[derive(Copy, Clone)]
+pub enum MemSize {
+    Byte,
+    Half,
+    Word,
+}
+
+impl MemSize {
+    pub fn bytes(self) -> usize {
+        match self {
+            MemSize::Byte => 1,
+            MemSize::Half => 2,
+            MemSize::Word => 4,
+        }
+    }
+}
+
+impl RiscvCpu {
+    pub fn new(ram_size: usize) -> Self {
+        Self::with_ram(0, ram_size)
+    }
+
+    pub fn builder() -> RiscvCpuBuilder {
+        RiscvCpuBuilder::new()
+    }
+
+    /// A fresh machine with no devices, in the state captured by `snapshot`.
+    pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+        let mut cpu = Self::with_ram(snapshot.ram_base, snapshot.ram.len());
+        cpu.set_vlen(snapshot.vregs.len() * 8 / 32);
+        cpu.restore(snapshot)
+            .expect("RAM was sized to match the snapshot");
+        cpu
+    }
+}
+
+impl<X: Xlen> RiscvCpu<X> {
+    pub(crate) fn with_ram(ram_base: u32, ram_size: usize) -> Self {
+        Self::with_bus(Bus::new(ram_base, ram_size))
+    }
+
+    pub(crate) fn with_bus(bus: Bus) -> Self {
+        let mut cpu = Self {
+            regs: [X::Reg::default(); 32],
+            fregs: [0; 32],
+            pc: X::Reg::default(),
+            bus,
+            csrs: CsrFile::new(),
+            privilege: Privilege::Machine,
+            virt: false,
+            guest_access: false,
+            float_regs: FloatRegs::default(),
+            extensions: Extensions::all(),
+            vector: VectorRegs::new(0),
+            debug: Debugger::default(),
+            calls: CallStack::default(),
+            icache: DecodeCache::new(),
+            engine: Engine::default(),
+            blocks: BlockCache::default(),
+            #[cfg(feature = "jit")]
+            jit: None,
+            guest_traps: false,
+            semihosting: None,
+            syscalls: None,
+            exit_code: None,
+            tracer: None,
+            pre_step: Vec::new(),
+            post_step: Vec::new(),
+            coverage: None,
+            memory_profile: None,
+            caches: None,
+            tlb: None,
+            pipeline: None,
+            costs: None,
+            stats: Stats::default(),
+            symbols: SymbolTable::default(),
+            #[cfg(feature = "dwarf")]
+            lines: dwarf::LineTable::default(),
+            custom: [None, None, None, None],
+            unknown: None,
+            #[cfg(feature = "std")]
+            perf: PerfCounter::default(),
+            waiting: None,
+            fast_forward: true,
+            device_lines: 0,
+            journal: None,
+        };
+        cpu.set_vlen(vector::DEFAULT_VLEN);
+        cpu
+    }
+
+    /// Map a memory-mapped device at `base`. Accesses in `base..base + size`
+    /// are routed to the device instead of RAM.
+    pub fn map_device(&mut self, base: u32, size: u32, device: impl Device + 'static) {
+        self.bus.map(base, size, Box::new(device));
+    }
+
+    /// Copy every PT_LOAD segment of an ELF32 image to its virtual address,
+    /// zero-fill the rest of each segment (.bss) and jump to the entry point.
+    /// Its symbol table, and with the `dwarf` feature its line table,
+    /// replace any this hart already had. Debug info that can't be parsed is
+    /// dropped rather than failing the load.
+    pub fn load_elf(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let elf = ElfFile::parse(bytes)?;
+
+        for segment in elf.loadable_segments() {
+            let data = elf.segment_data(segment)?;
+
+            if segment.filesz > segment.memsz {
+                return Err(format!(
+                    "ELF: segment at {:#x} is larger on disk than in memory",
+                    segment.vaddr
+                ));
+            }
+
+            let mut image = data.to_vec();
+            image.resize(segment.memsz as usize, 0);
+
+            self.bus.write_bytes(segment.vaddr, &image).ok_or_else(|| {
+                format!("ELF: segment at {:#x} doesn't fit in memory", segment.vaddr)
+            })?;
+        }
+
+        self.pc = X::truncate(elf.entry as u64);
+        self.calls.clear();
+        self.symbols = elf.symbols();
+        #[cfg(feature = "dwarf")]
+        {
+            self.lines = dwarf::LineTable::parse(&elf).unwrap_or_default();
+        }
+        self.blocks.flush();
+
+        Ok(())
+    }
+
+    /// Load a Linux user-mode ELF and set it up to start as `process`,
+    /// with its arguments, environment and auxiliary vector on the stack
+    /// at the top of RAM. See [`user`]. From then on ECALLs are system
+    /// calls serviced by the host, see [`syscall`].
+    pub fn load_process(&mut self, bytes: &[u8], process: &Process) -> Result<(), String> {
+        user::start(self, bytes, process)
+    }
+
+    /// The heap, mappings and files of the program started with
+    /// [`load_process`](Self::load_process), if there is one.
+    pub fn syscalls(&self) -> Option<&Syscalls> {
+        self.syscalls.as_ref()
+    }
+
+    pub fn syscalls_mut(&mut self) -> Option<&mut Syscalls> {
+        self.syscalls.as_mut()
+    }
+
+    /// Write the data records of an Intel HEX file to memory and, if it has
+    /// a start address record, jump there.
+    pub fn load_ihex(&mut self, text: &str) -> Result<(), String> {
+        self.load_image(&IntelHex::parse(text)?.into())
+    }
+
+    /// Copy a raw binary into RAM at `addr`. Like the builder's images,
+    /// this can fill in read-only regions.
+    pub fn load_binary(&mut self, addr: u32, bytes: &[u8]) -> Result<(), String> {
+        self.load_image(&Image::new().segment(addr, bytes))
+    }
+
+    /// Copy every segment of `image` into RAM, protect the read-only ones
+    /// and jump to its entry point if it has one. Nothing is written unless
+    /// every segment fits in RAM and no two overlap.
+    pub fn load_image(&mut self, image: &Image) -> Result<(), String> {
+        if let Some((first, second)) = image.overlap() {
+            return Err(format!(
+                "Image: segments at {:#x} and {:#x} overlap",
+                first.addr, second.addr
+            ));
+        }
+        for segment in &image.segments {
+            if !self.bus.in_ram(segment.addr, segment.bytes.len()) {
+                return Err(format!(
+                    "Image: segment at {:#x} ({} bytes) doesn't fit in RAM",
+                    segment.addr,
+                    segment.bytes.len()
+                ));
+            }
+        }
+
+        for segment in &image.segments {
+            self.bus.dma().write(segment.addr as u64, &segment.bytes);
+            if segment.read_only {
+                self.bus.protect(segment.addr, segment.bytes.len() as u32);
+            }
+        }
+
+        if let Some(entry) = image.entry {
+            self.pc = X::truncate(entry as u64);
+        }
+        self.blocks.flush();
+
+        Ok(())
+    }
+
+    pub fn save_snapshot(&self) -> Snapshot {
+        Snapshot::new(self)
+    }
+
+    /// Copy RAM alone, to [`memory_diff`](Self::memory_diff) against later.
+    pub fn memory_snapshot(&self) -> MemorySnapshot {
+        MemorySnapshot::new(&self.bus)
+    }
+
+    /// What the guest, or anything else, has written to RAM since
+    /// `snapshot` was taken.
+    ///
+    /// # Panics
+    ///
+    /// If `snapshot` is of differently laid out RAM.
+    pub fn memory_diff(&self, snapshot: &MemorySnapshot) -> MemoryDiff {
+        snapshot.diff_live(&self.bus)
+    }
+
+    /// Roll registers, CSRs and RAM back to `snapshot`. Devices, breakpoints
+    /// and the tracer are left alone, so this can be used to fork execution
+    /// from a common point. RAM must be laid out the same way.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), String> {
+        if snapshot.xlen != X::BITS {
+            return Err(format!(
+                "Snapshot is from an RV{} machine, not RV{}",
+                snapshot.xlen,
+                X::BITS
+            ));
+        }
+        if snapshot.ram_base != self.bus.ram_base() || snapshot.ram.len() != self.bus.len() {
+            return Err(format!(
+                "Snapshot RAM ({} bytes at {:#x}) doesn't match this machine ({} bytes at {:#x})",
+                snapshot.ram.len(),
+                snapshot.ram_base,
+                self.bus.len(),
+                self.bus.ram_base()
+            ));
+        }
+        if snapshot.vregs.len() != self.vector.bytes().len() {
+            return Err(format!(
+                "Snapshot VLEN ({}) doesn't match this machine ({})",
+                snapshot.vregs.len() * 8 / 32,
+                self.vlen()
+            ));
+        }
+
+        self.regs = snapshot.regs.map(X::truncate);
+        self.fregs = snapshot.fregs;
+        self.vector.bytes_mut().copy_from_slice(&snapshot.vregs);
+        self.pc = X::truncate(snapshot.pc);
+        self.privilege = snapshot.privilege;
+        self.virt = snapshot.virt;
+        snapshot.restore_csrs(&mut self.csrs);
+        // misa describes this hart's configuration, not the snapshot's.
+        self.update_misa();
+        self.bus.ram_mut().write_bytes(0, &snapshot.ram);
+        if let Some(journal) = &mut self.journal {
+            journal.clear();
+            self.csrs.take_journal();
+        }
+        self.debug.resume_from = None;
+        self.calls.clear();
+        self.exit_code = None;
+        self.waiting = None;
+        self.blocks.flush();
+
+        Ok(())
+    }
+
+    /// Execute one instruction, or take a pending interrupt, then advance
+    /// device time by one tick. A hart waiting in WFI just lets the tick
+    /// pass.
+    pub fn step(&mut self) -> Result<StepOutcome, Exception> {
+        self.sync_device_interrupts();
+        let idle = self.is_waiting();
+        let outcome = self.step_one();
+        if !idle {
+            self.csrs.retire(1);
+        }
+        self.advance_time(1);
+        if let Ok(StepOutcome::Executed) = outcome {
+            self.preempt(1);
+        }
+        outcome
+    }
+
+    /// [`step`](Self::step) without touching time, for the run loop to
+    /// account for itself.
+    pub(crate) fn step_one(&mut self) -> Result<StepOutcome, Exception> {
+        if self.waiting.is_some() && !self.wakeup_pending() {
+            return Ok(StepOutcome::Executed);
+        }
+        if self.journal.is_some() {
+            self.journal_begin();
+        }
+        self.waiting = None;
+
+        let pc = self.pc_u32();
+
+        if let Some(interrupt) = self.pending_interrupt() {
+            self.trace(|t| t.interrupt(pc, interrupt));
+            self.take_interrupt(interrupt);
+            return Ok(StepOutcome::Executed);
+        }
+
+        let vpc = X::widen(self.pc);
+
+        if Self::phys(vpc).is_some() && self.debug.should_break(pc) {
+            self.journal_discard();
+            return Ok(StepOutcome::Breakpoint(pc));
+        }
+
+        let fetched = self
+            .check_triggers(csr::MCONTROL_EXECUTE, vpc)
+            .and_then(|()| self.read_virt(vpc, MemSize::Word, Access::Fetch));
+        let instruction = match fetched {
+            Ok(instruction) => instruction,
+            // Page faults and triggers are the guest's business, but a
+            // fetch access fault would just refault from an unmapped mtvec.
+            Err(
+                exception @ (Exception::InstructionPageFault(_)
+                | Exception::InstructionGuestPageFault(..)
+                | Exception::Breakpoint(_)),
+            ) if self.guest_traps => {
+                self.trace(|t| t.exception(pc, &exception));
+                self.take_exception(exception);
+                return Ok(StepOutcome::Executed);
+            }
+            Err(exception) => {
+                self.trace(|t| t.exception(pc, &exception));
+                self.journal_discard();
+                return Err(exception);
+            }
+        };
+
+        let mut next_pc = X::truncate(X::widen(self.pc).wrapping_add(4));
+
+        if let Err(exception) = self.execute(instruction, &mut next_pc) {
+            self.trace(|t| t.exception(pc, &exception));
+            if !self.guest_traps {
+                self.journal_discard();
+                return Err(exception);
+            }
+            self.take_exception(exception);
+            return Ok(StepOutcome::Executed);
+        }
+
+        self.pc = next_pc;
+
+        if let Some(code) = self.exit_code.take() {
+            return Ok(StepOutcome::Exited(code));
+        }
+
+        Ok(self.take_watch_hit().unwrap_or(StepOutcome::Executed))
+    }
+
+    /// The watchpoint or register watch the last instruction tripped, if
+    /// any. A watchpoint wins if it tripped both.
+    pub(crate) fn take_watch_hit(&mut self) -> Option<StepOutcome> {
+        let write = self.debug.reg_hit.take();
+        match self.debug.hit.take() {
+            Some(hit) => Some(StepOutcome::Watchpoint(hit)),
+            None => write.map(StepOutcome::RegisterWrite),
+        }
+    }
+
+    /// By default, exceptions (including ECALL and EBREAK) stop the CPU and
+    /// are returned to the host. With guest traps enabled they're taken
+    /// through `mtvec` like interrupts, and only instruction fetch faults are
+    /// still returned.
+    pub fn set_guest_traps(&mut self, enabled: bool) {
+        self.guest_traps = enabled;
+    }
+
+    /// Service semihosting calls (EBREAK wrapped in the magic slli/srai
+    /// pair) on the host instead of treating them as breakpoints. Only the
+    /// RV32 calling convention is implemented, so RV64 harts ignore this.
+    pub fn enable_semihosting(&mut self, semihosting: Semihosting) {
+        self.semihosting = Some(semihosting);
+    }
+
+    /// Redirect what the guest prints through semihosting, system calls or
+    /// the UARTs already on the bus into the returned capture.
+    pub fn capture_output(&mut self) -> OutputCapture {
+        let capture = OutputCapture::new();
+        if let Some(semihosting) = &mut self.semihosting {
+            semihosting.set_output(Box::new(capture.clone()));
+        }
+        if let Some(syscalls) = &mut self.syscalls {
+            syscalls.set_output(Box::new(capture.clone()));
+        }
+        self.bus.capture_output(&capture);
+        capture
+    }
+
+    /// Step until a breakpoint, watchpoint or exception stops execution.
+    pub fn run(&mut self) -> ExitReason {
+        self.run_with(None, None, None)
+    }
+
+    /// Like [`run`](Self::run), but gives up after `steps` steps.
+    pub fn run_steps(&mut self, steps: u64) -> ExitReason {
+        self.run_with(Some(steps), None, None)
+    }
+
+    /// Like [`run`](Self::run), but gives up after `max_instructions`
+    /// steps with [`StepLimit`](ExitReason::StepLimit), or once
+    /// `max_wall_time` has passed with [`TimeLimit`](ExitReason::TimeLimit),
+    /// whichever comes first. The clock is only read every 1,024 steps or
+    /// so, so a run can go over by that much.
+    #[cfg(feature = "std")]
+    pub fn run_with_limits(
+        &mut self,
+        max_instructions: Option<u64>,
+        max_wall_time: Option<Duration>,
+    ) -> ExitReason {
+        let deadline = max_wall_time.map(|time| Instant::now() + time);
+        self.run_with(max_instructions, None, deadline)
+    }
+
+    /// Like [`run`](Self::run), but also stops when the PC reaches `pc`.
+    /// At least one step is always taken, so calling this again from the
+    /// target runs until the next time it's reached.
+    pub fn run_until(&mut self, pc: u32) -> ExitReason {
+        self.run_with(None, Some(pc), None)
+    }
+
+    fn run_with(
+        &mut self,
+        limit: Option<u64>,
+        target: Option<u32>,
+        deadline: Option<Instant>,
+    ) -> ExitReason {
+        let mut steps = 0;
+        let mut next_clock = 0;
+        self.sync_device_interrupts();
+
+        loop {
+            if limit.is_some_and(|limit| steps >= limit) {
+                return ExitReason::StepLimit;
+            }
+            if let Some(deadline) = deadline
+                && steps >= next_clock
+            {
+                if Instant::now() >= deadline {
+                    return ExitReason::TimeLimit;
+                }
+                next_clock = steps + CLOCK_INTERVAL;
+            }
+
+            if self.waiting.is_some() {
+                if self.wakeup_pending() {
+                    self.waiting = None;
+                } else {
+                    match self.next_wakeup() {
+                        Some(ticks) if self.fast_forward => {
+                            self.skip_time(ticks);
+                            continue;
+                        }
+                        _ => return ExitReason::Idle,
+                    }
+                }
+            }
+
+            // Blocks don't journal each instruction on its own.
+            let engine = match self.journal {
+                Some(_) => Engine::Interpreter,
+                None => self.engine,
+            };
+            let (executed, outcome) = match engine {
+                Engine::Interpreter => (1, self.step_one()),
+                Engine::BasicBlocks => self.step_block(self.block_budget(limit, steps), target),
+                #[cfg(feature = "jit")]
+                Engine::Jit => self.step_jit(self.block_budget(limit, steps), target),
+            };
+            self.csrs.retire(executed);
+            self.advance_time(executed);
+
+            match outcome {
+                Ok(StepOutcome::Executed) => {}
+                Ok(StepOutcome::Breakpoint(pc)) => return ExitReason::Breakpoint(pc),
+                Ok(StepOutcome::Watchpoint(hit)) => return ExitReason::Watchpoint(hit),
+                Ok(StepOutcome::RegisterWrite(write)) => return ExitReason::RegisterWrite(write),
+                Ok(StepOutcome::Exited(code)) => return ExitReason::Exited(code),
+                Err(exception) => return ExitReason::Exception(exception),
+            }
+            steps += executed;
+            #[cfg(feature = "std")]
+            self.perf.retire(executed);
+            if self.syscalls.is_some() {
+                self.preempt(executed);
+            }
+
+            if let Some(target) = target
+                && X::widen(self.pc) == target as u64
+            {
+                return ExitReason::ReachedPc(target);
+            }
+        }
+    }
+
+    /// Start (or resume) measuring host time and executed instructions.
+    #[cfg(feature = "std")]
+    pub fn perf_start(&mut self) {
+        self.perf.start();
+    }
+
+    /// Pause measuring. The counts are kept until [`perf_reset`](Self::perf_reset).
+    #[cfg(feature = "std")]
+    pub fn perf_stop(&mut self) {
+        self.perf.stop();
+    }
+
+    #[cfg(feature = "std")]
+    pub fn perf_reset(&mut self) {
+        self.perf.reset();
+    }
+
+    #[cfg(feature = "std")]
+    pub fn perf_running(&self) -> bool {
+        self.perf.is_running()
+    }
+
+    /// Counts so far, including the current measurement if one is running.
+    #[cfg(feature = "std")]
+    pub fn perf_stats(&self) -> PerfStats {
+        self.perf.stats()
+    }
+
+    /// How far the next block may run. Blocks only look at interrupts
+    /// between them, so one mustn't run past the next device event either.
+    fn block_budget(&self, limit: Option<u64>, steps: u64) -> Option<u64> {
+        let remaining = limit.map(|limit| limit - steps);
+        match (remaining, self.bus.next_event()) {
+            (Some(remaining), Some(event)) => Some(remaining.min(event)),
+            (remaining, event) => remaining.or(event),
+        }
+    }
+
+    /// Stop before the instruction at `addr` is executed.
+    pub fn add_breakpoint(&mut self, addr: u32) {
+        // Blocks end before breakpoints, so existing ones may run past it.
+        self.blocks.flush();
+        self.debug.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u32) -> bool {
+        self.debug.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_ {
+        self.debug.breakpoints.iter().copied()
+    }
+
+    /// Stop after any load or store of `kind` that touches `addr..addr + len`.
+    pub fn add_watchpoint(&mut self, addr: u32, len: u32, kind: WatchKind) {
+        let watchpoint = Watchpoint { addr, len, kind };
+        if !self.debug.watchpoints.contains(&watchpoint) {
+            self.debug.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, addr: u32, len: u32, kind: WatchKind) -> bool {
+        let before = self.debug.watchpoints.len();
+        self.debug
+            .watchpoints
+            .retain(|w| *w != Watchpoint { addr, len, kind });
+        self.debug.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.debug.watchpoints
+    }
+
+    /// Stop after any instruction that writes integer register `reg`, even
+    /// with the value it already held. Writes to x0 are discarded, so
+    /// watching it never stops.
+    pub fn add_register_watch(&mut self, reg: u8) {
+        assert!(reg < 32, "x{} isn't a register", reg);
+        self.debug.watched_regs |= 1 << reg;
+    }
+
+    pub fn remove_register_watch(&mut self, reg: u8) -> bool {
+        let watched = reg < 32 && self.debug.watches_reg(reg);
+        if watched {
+            self.debug.watched_regs &= !(1 << reg);
+        }
+        watched
+    }
+
+    pub fn register_watches(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..32).filter(|&reg| self.debug.watches_reg(reg))
+    }
+
+    /// Record what each of the last `capacity` steps changes, so that
+    /// [`step_back`](Self::step_back) can undo them. Registers, CSRs, RAM,
+    /// the PC and privilege level are rewound; devices, bus time, page
+    /// table A and D bits, semihosting writes and LR reservations are not.
+    /// Execution is interpreted one instruction at a time while this is on.
+    pub fn enable_reverse(&mut self, capacity: usize) {
+        self.journal = Some(Journal::new(capacity));
+        self.csrs.set_journaling(true);
+    }
+
+    /// Stop recording and forget the steps recorded so far.
+    pub fn disable_reverse(&mut self) {
+        self.journal = None;
+        self.csrs.set_journaling(false);
+    }
+
+    /// How many steps [`step_back`](Self::step_back) can undo.
+    pub fn reverse_depth(&self) -> usize {
+        self.journal.as_ref().map_or(0, Journal::len)
+    }
+
+    /// Undo up to `steps` of the most recent steps, newest first, and
+    /// return how many were undone. Steps that returned an exception to
+    /// the host changed nothing and aren't counted.
+    pub fn step_back(&mut self, steps: usize) -> usize {
+        self.journal_close();
+        let mut undone = 0;
+        while undone < steps {
+            let Some(frame) = self.journal.as_mut().and_then(Journal::pop) else {
+                break;
+            };
+            self.csrs.undo(&frame.csrs);
+            for &(addr, size, old) in frame.memory.iter().rev() {
+                self.bus.write(addr, size, old);
+            }
+            for &(reg, old) in frame.regs.iter().rev() {
+                self.regs[reg as usize] = old;
+            }
+            if let Some(fregs) = frame.fregs {
+                self.fregs = fregs;
+            }
+            if let Some(vregs) = &frame.vregs {
+                self.vector.bytes_mut().copy_from_slice(vregs);
+            }
+            self.pc = frame.pc;
+            self.privilege = frame.privilege;
+            self.virt = frame.virt;
+            self.waiting = frame.waiting;
+            undone += 1;
+        }
+        if undone > 0 {
+            self.debug.resume_from = None;
+            self.debug.hit = None;
+            self.debug.reg_hit = None;
+            self.exit_code = None;
+            self.blocks.flush();
+        }
+        undone
+    }
+
+    /// Start recording a step, handing the CSR writes so far to the last.
+    fn journal_begin(&mut self) {
+        self.journal_close();
+        let frame = journal::Frame::new(self.pc, self.privilege, self.virt, self.waiting);
+        if let Some(journal) = &mut self.journal {
+            journal.push(frame);
+        }
+    }
+
+    /// Hand the CSR writes since the step being recorded began to it, now
+    /// that it's over.
+    fn journal_close(&mut self) {
+        if self.journal.is_none() {
+            return;
+        }
+        let csrs = self.csrs.take_journal();
+        self.journal_frame(|frame| frame.csrs.extend(csrs));
+        if let Some(journal) = &mut self.journal {
+            journal.trim();
+        }
+    }
+
+    /// The step being recorded returned an exception to the host instead,
+    /// or stopped at a breakpoint, so it was never a step.
+    fn journal_discard(&mut self) {
+        if let Some(journal) = &mut self.journal {
+            journal.discard();
+        }
+    }
+
+    fn journal_frame(&mut self, record: impl FnOnce(&mut journal::Frame<X>)) {
+        if let Some(frame) = self.journal.as_mut().and_then(Journal::current) {
+            record(frame);
+        }
+    }
+
+    /// Save the register files that `instruction` may write but
+    /// [`write_reg`](Self::write_reg) doesn't see.
+    fn journal_registers(&mut self, instruction: Instruction) {
+        let fregs = self.fregs;
+        let vregs = match instruction {
+            Instruction::Vector(_) => Some(self.vector.bytes().to_vec()),
+            Instruction::Float(_) => None,
+            _ => return,
+        };
+        self.journal_frame(|frame| {
+            frame.fregs.get_or_insert(fregs);
+            if frame.vregs.is_none() {
+                frame.vregs = vregs;
+            }
+        });
+    }
+
+    /// Keep a shadow call stack for [`backtrace`](Self::backtrace) from
+    /// every JAL and JALR that links through or returns via `ra` or `t0`.
+    /// Off by default; the JIT stays out of the way while it's on.
+    pub fn set_call_tracking(&mut self, enabled: bool) {
+        self.calls.set_tracking(enabled);
+    }
+
+    pub fn call_tracking(&self) -> bool {
+        self.calls.is_tracking()
+    }
+
+    /// The PC, then the return address of each call in progress, innermost
+    /// first. With call tracking on these come from the shadow stack.
+    /// Otherwise they're read off the frame pointer chain in `s0`, which
+    /// needs code built with frame pointers and only works while addresses
+    /// are physical.
+    pub fn backtrace(&self) -> Vec<Frame> {
+        let returns = match self.calls.is_tracking() {
+            true => self.calls.return_addresses().collect(),
+            false => self.frame_pointer_chain(),
+        };
+        std::iter::once(self.pc_u32())
+            .chain(returns)
+            .map(|pc| Frame {
+                pc,
+                symbol: self.symbols.describe(pc),
+            })
+            .collect()
+    }
+
+    /// The return addresses saved by a standard prologue, which stores `ra`
+    /// just below the frame pointer and the caller's frame pointer below
+    /// that. Stops at the first frame that doesn't look like one: outside
+    /// RAM, misaligned, a null `ra`, or a caller frame below this one.
+    fn frame_pointer_chain(&self) -> Vec<u32> {
+        let (privilege, virt) = self.effective_mode(Access::Load);
+        if virt || self.sv32(privilege).is_some() {
+            return Vec::new();
+        }
+
+        let bytes = X::BITS as u64 / 8;
+        let read = |addr: u64| {
+            let addr = Self::phys(addr)?;
+            if !self.bus.in_ram(addr, bytes as usize) {
+                return None;
+            }
+            let mut buf = [0; 8];
+            let offset = (addr - self.bus.ram_base()) as usize;
+            self.bus
+                .ram()
+                .read_bytes(offset, &mut buf[..bytes as usize]);
+            Some(u64::from_le_bytes(buf))
+        };
+
+        let mut returns = Vec::new();
+        let mut fp = self.reg(Reg::S0);
+        while returns.len() < backtrace::MAX_FRAME_POINTERS && fp.is_multiple_of(bytes) {
+            let (Some(ra), Some(caller)) = (
+                read(fp.wrapping_sub(bytes)),
+                read(fp.wrapping_sub(2 * bytes)),
+            ) else {
+                break;
+            };
+            if ra == 0 {
+                break;
+            }
+            returns.push(ra as u32);
+            if caller <= fp {
+                break;
+            }
+            fp = caller;
+        }
+        returns
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn engine(&self) -> Engine {
+        self.engine
+    }
+
+    /// Forget every decoded basic block, e.g. after the host patches code
+    /// through `bus`.
+    pub fn flush_blocks(&mut self) {
+        self.blocks.flush();
+    }
+
+    /// Run the basic block at the PC, stopping early after `budget`
+    /// instructions or on reaching `target`. Returns how many instructions
+    /// ran along with the outcome of the last one. Falls back to a single
+    /// [`step`](Self::step) where the interpreter has something to check:
+    /// a pending interrupt, a breakpoint or trigger, or code that can't be
+    /// prefetched.
+    pub(crate) fn step_block(
+        &mut self,
+        budget: Option<u64>,
+        target: Option<u32>,
+    ) -> (u64, Result<StepOutcome, Exception>) {
+        let vpc = X::widen(self.pc);
+        let breakpoint = Self::phys(vpc).is_some_and(|pc| self.debug.breakpoints.contains(&pc));
+
+        if breakpoint || self.triggers_armed() || self.pending_interrupt().is_some() {
+            return (1, self.step_one());
+        }
+        let Some(block) = self.block_at(vpc) else {
+            return (1, self.step_one());
+        };
+
+        let generation = self.blocks.generation();
+        let mut executed = 0;
+
+        for &(raw, instruction) in &block.instructions {
+            if budget.is_some_and(|budget| executed >= budget) {
+                break;
+            }
+            executed += 1;
+
+            let pc = self.pc_u32();
+            let fallthrough = X::truncate(X::widen(self.pc).wrapping_add(4));
+            let mut next_pc = fallthrough;
+            let step = StepInfo {
+                pc,
+                raw,
+                instruction,
+            };
+            self.call_hooks(|cpu| &mut cpu.pre_step, &step, &mut next_pc);
+
+            let result = if self.permitted(instruction) {
+                self.execute_instruction(instruction, &mut next_pc)
+            } else {
+                Err(self.refused(raw, instruction))
+            };
+
+            if let Err(exception) = result {
+                self.trace(|t| t.exception(pc, &exception));
+                if !self.guest_traps {
+                    return (executed, Err(exception));
+                }
+                self.take_exception(exception);
+                return (executed, Ok(StepOutcome::Executed));
+            }
+
+            self.observe(pc, raw, &instruction, next_pc);
+            self.call_hooks(|cpu| &mut cpu.post_step, &step, &mut next_pc);
+            self.pc = next_pc;
+
+            if let Some(code) = self.exit_code.take() {
+                return (executed, Ok(StepOutcome::Exited(code)));
+            }
+            if let Some(hit) = self.take_watch_hit() {
+                return (executed, Ok(hit));
+            }
+
+            // Only the last instruction branches, unless a hook jumped.
+            let jumped = next_pc != fallthrough;
+            let reached = target.is_some_and(|target| X::widen(self.pc) == target as u64);
+            if jumped || reached || self.blocks.generation() != generation {
+                break;
+            }
+        }
+
+        (executed, Ok(StepOutcome::Executed))
+    }
+
+    /// The cached block at `pc`, decoding it first if needed. `None` if not
+    /// even the first instruction can be fetched and decoded.
+    pub(crate) fn block_at(&mut self, pc: u64) -> Option<Rc<Block>> {
+        self.blocks.sync(self.bus.code_writes());
+        if let Some(block) = self.blocks.get(pc, self.privilege, self.virt) {
+            return Some(block);
+        }
+
+        let paddr = self.translate(pc, Access::Fetch).ok()?;
+        let mut instructions = Vec::new();
+
+        // A block never crosses a page, so one translation covers it.
+        for offset in (0..0x1000 - (paddr & 0xFFF)).step_by(4) {
+            let breakpoint = self
+                .debug
+                .breakpoints
+                .contains(&(pc as u32).wrapping_add(offset));
+            if offset != 0 && breakpoint {
+                break;
+            }
+
+            let Some(raw) = self.bus.read(paddr + offset, MemSize::Word) else {
+                break;
+            };
+            let Ok(decoded) = self.decode_enabled(raw) else {
+                break;
+            };
+
+            instructions.push((raw, decoded));
+            if block::ends_block(decoded) || instructions.len() == MAX_BLOCK_LEN {
+                break;
+            }
+        }
+
+        if instructions.is_empty() {
+            return None;
+        }
+
+        let block = Block { instructions };
+        self.bus.mark_code(paddr);
+        Some(self.blocks.insert(pc, self.privilege, self.virt, block))
+    }
+
+    pub fn execute(&mut self, instruction: u32, next_pc: &mut X::Reg) -> Result<(), Exception> {
+        let pc = self.pc_u32();
+
+        match self.decode_cached(instruction) {
+            Ok(decoded) => {
+                let step = StepInfo {
+                    pc,
+                    raw: instruction,
+                    instruction: decoded,
+                };
+                self.call_hooks(|cpu| &mut cpu.pre_step, &step, next_pc);
+                if !self.permitted(decoded) {
+                    return Err(self.refused(instruction, decoded));
+                }
+                if self.journal.is_some() {
+                    self.journal_registers(decoded);
+                }
+                self.execute_instruction(decoded, next_pc)?;
+                self.observe(pc, instruction, &decoded, *next_pc);
+                self.call_hooks(|cpu| &mut cpu.post_step, &step, next_pc);
+                Ok(())
+            }
+            Err(DecodeError::IllegalInstruction(bits)) => Err(Exception::IllegalInstruction(bits)),
+            Err(DecodeError::UnknownOpcode(bits)) if self.unknown.is_some() => {
+                self.run_handler(|cpu| &mut cpu.unknown, bits, next_pc)
+            }
+            Err(DecodeError::UnknownOpcode(bits)) => {
+                self.trace(|t| t.unknown_opcode(pc, bits));
+                Ok(())
+            }
+        }
+    }
+
+    fn decode_cached(&mut self, instruction: u32) -> Result<Instruction, DecodeError> {
+        let pc = X::widen(self.pc);
+        if let Some(decoded) = self.icache.get(pc, instruction) {
+            return Ok(decoded);
+        }
+
+        let decoded = self.decode_enabled(instruction)?;
+        self.icache.insert(pc, instruction, decoded);
+        Ok(decoded)
+    }
+
+    /// Whether the current privilege level may execute `instruction`.
+    fn permitted(&self, instruction: Instruction) -> bool {
+        use Instruction::*;
+
+        match instruction {
+            Mret => self.privilege == Privilege::Machine,
+            Sret => self.privilege == Privilege::Machine || self.privileged_op(csr::MSTATUS_TSR),
+            Wfi => self.privilege == Privilege::Machine || self.privileged_op(csr::MSTATUS_TW),
+            SfenceVma { .. } => {
+                self.privilege == Privilege::Machine || self.privileged_op(csr::MSTATUS_TVM)
+            }
+            Csrrw { csr, .. } | Csrrwi { csr, .. } => self.csr_accessible(csr, true),
+            Csrrs { rs1, csr, .. } | Csrrc { rs1, csr, .. } => self.csr_accessible(csr, rs1 != 0),
+            Csrrsi { uimm, csr, .. } | Csrrci { uimm, csr, .. } => {
+                self.csr_accessible(csr, uimm != 0)
+            }
+            Float(instruction) => self.float_enabled() && self.float_permitted(instruction),
+            Vector(instruction) => self.vector_enabled() && self.vector_permitted(instruction),
+            Hypervisor(instruction) => self.hypervisor_permitted(instruction),
+            _ => true,
+        }
+    }
+
+    /// The exception for an instruction [`permitted`](Self::permitted)
+    /// turned down.
+    fn refused(&self, raw: u32, instruction: Instruction) -> Exception {
+        match self.virtual_instruction(instruction) {
+            true => Exception::VirtualInstruction(raw),
+            false => Exception::IllegalInstruction(raw),
+        }
+    }
+
+    /// Whether S-mode may use an operation that the `mstatus` bit `trap`
+    /// (TW, TVM or TSR) can take away. U-mode never may. In VS-mode it's
+    /// the `hstatus` bit in the same place (VTW, VTVM or VTSR) that
+    /// decides, though TW still applies.
+    fn privileged_op(&self, trap: u32) -> bool {
+        let mstatus = self.csrs.read_u64(csr::MSTATUS) as u32;
+        if self.privilege != Privilege::Supervisor {
+            return false;
+        }
+        if !self.virt {
+            return mstatus & trap == 0;
+        }
+        let hstatus = self.csrs.read_u64(csr::HSTATUS) as u32;
+        hstatus & trap == 0 && (trap != csr::MSTATUS_TW || mstatus & trap == 0)
+    }
+
+    /// FS being Off turns F/D off. Zfinx has no FS, so it's never off.
+    fn float_enabled(&self) -> bool {
+        self.float_regs == FloatRegs::Integer || self.ext_enabled(csr::MSTATUS_FS)
+    }
+
+    fn vector_enabled(&self) -> bool {
+        self.ext_enabled(csr::MSTATUS_VS)
+    }
+
+    /// Whether FS or VS is on: in `mstatus`, and with V=1 in `vsstatus` too.
+    fn ext_enabled(&self, field: u32) -> bool {
+        let on = |status| self.csrs.ext_state(status, field) != csr::EXT_OFF;
+        on(csr::MSTATUS) && (!self.virt || on(csr::VSSTATUS))
+    }
+
+    /// Mark FS or VS Dirty wherever [`ext_enabled`](Self::ext_enabled)
+    /// looks.
+    fn set_dirty(&mut self, field: u32) {
+        self.csrs.set_dirty(csr::MSTATUS, field);
+        if self.virt {
+            self.csrs.set_dirty(csr::VSSTATUS, field);
+        }
+    }
+
+    fn csr_accessible(&self, csr: u16, writes: bool) -> bool {
+        self.csr_accessible_at(csr, writes, self.privilege, self.virt)
+    }
+
+    /// CSR addresses encode their own access rules: bits 9:8 are the lowest
+    /// privilege level allowed, and 0b11 in bits 11:10 means read-only.
+    /// Below M-mode the user-level counters also need their `mcounteren` bit,
+    /// and in U-mode their `scounteren` bit if there's an S-mode. With V=1
+    /// they need their `hcounteren` bit too, and the hypervisor-level CSRs
+    /// (0b10 in bits 9:8) are out of reach.
+    fn csr_accessible_at(&self, csr: u16, writes: bool, privilege: Privilege, virt: bool) -> bool {
+        let required = (csr >> 8) & 0b11;
+        let read_only = (csr >> 10) & 0b11 == 0b11;
+        if !csr::implemented(csr) {
+            return false;
+        }
+
+        let upper_half = matches!(
+            csr,
+            csr::MCYCLEH..=csr::MHPMCOUNTER31H | csr::CYCLEH..=csr::HPMCOUNTER31H
+        );
+        if upper_half && X::BITS == 64 {
+            return false;
+        }
+        if matches!(csr, csr::CYCLE..=csr::HPMCOUNTER31 | csr::CYCLEH..=csr::HPMCOUNTER31H)
+            && privilege < Privilege::Machine
+        {
+            let enabled = |counteren: u16| self.csrs.read_u64(counteren) >> (csr & 0x1F) & 1 != 0;
+            if !enabled(csr::MCOUNTEREN) || (virt && !enabled(csr::HCOUNTEREN)) {
+                return false;
+            }
+            let supervisor = self.extensions.contains(Extension::S);
+            if privilege == Privilege::User && supervisor && !enabled(csr::SCOUNTEREN) {
+                return false;
+            }
+        }
+
+        if matches!(csr, csr::MSTATUSH | csr::MENVCFGH | csr::HTIMEDELTAH) && X::BITS == 64 {
+            return false;
+        }
+        if matches!(csr, csr::PMPCFG0..=csr::PMPCFG15) && csr & 1 != 0 && X::BITS == 64 {
+            return false;
+        }
+        if csr == csr::SATP && privilege == Privilege::Supervisor {
+            let status = if virt { csr::HSTATUS } else { csr::MSTATUS };
+            return self.csrs.read_u64(status) & csr::MSTATUS_TVM as u64 == 0;
+        }
+        if required == 2 && virt {
+            return false;
+        }
+
+        let extension = match csr {
+            csr::FFLAGS | csr::FRM | csr::FCSR if !self.float_enabled() => return false,
+            csr::VSTART | csr::VL | csr::VTYPE | csr::VLENB if !self.vector_enabled() => {
+                return false;
+            }
+            csr::FFLAGS | csr::FRM | csr::FCSR => Some(Extension::F),
+            csr::MEDELEG | csr::MIDELEG => Some(Extension::S),
+            csr::VSTART | csr::VL | csr::VTYPE | csr::VLENB => Some(Extension::V),
+            csr::MTINST | csr::MTVAL2 => Some(Extension::H),
+            _ if required == 1 => Some(Extension::S),
+            _ if required == 2 => Some(Extension::H),
+            _ => None,
+        };
+        if extension.is_some_and(|extension| !self.extensions.contains(extension)) {
+            return false;
+        }
+
+        // HS-mode is S-mode with V=0.
+        let required = match required {
+            2 => Privilege::Supervisor as u16,
+            required => required,
+        };
+        privilege as u16 >= required && !(writes && read_only)
+    }
+
+    /// Send diagnostics to `tracer`. Nothing is traced by default.
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    /// Call `hook` before each instruction executes, once it's been
+    /// fetched and decoded. Hooks run in the order they were added.
+    pub fn on_pre_step(&mut self, hook: impl FnMut(&mut dyn Hart, &StepInfo) + 'static) {
+        self.pre_step.push(Box::new(hook));
+    }
+
+    /// Call `hook` after each instruction retires, before the PC moves on.
+    /// Instructions that trap don't retire.
+    pub fn on_post_step(&mut self, hook: impl FnMut(&mut dyn Hart, &StepInfo) + 'static) {
+        self.post_step.push(Box::new(hook));
+    }
+
+    pub fn clear_step_hooks(&mut self) {
+        self.pre_step.clear();
+        self.post_step.clear();
+    }
+
+    /// Start recording which instructions execute, from scratch.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    /// Stop recording and hand back what was collected.
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Start recording where loads and stores go, from scratch.
+    pub fn enable_memory_profile(&mut self) {
+        self.memory_profile = Some(MemoryProfile::new());
+    }
+
+    /// Stop recording and hand back what was collected.
+    pub fn take_memory_profile(&mut self) -> Option<MemoryProfile> {
+        self.memory_profile.take()
+    }
+
+    pub fn memory_profile(&self) -> Option<&MemoryProfile> {
+        self.memory_profile.as_ref()
+    }
+
+    /// Run every fetch, load and store through `caches` to count hits and
+    /// misses. The guest sees no difference.
+    pub fn set_cache_model(&mut self, caches: CacheModel) {
+        self.caches = Some(caches);
+    }
+
+    /// Stop modelling caches and hand back the model with its stats.
+    pub fn take_cache_model(&mut self) -> Option<CacheModel> {
+        self.caches.take()
+    }
+
+    pub fn cache_model(&self) -> Option<&CacheModel> {
+        self.caches.as_ref()
+    }
+
+    /// E.g. to [`reset_stats`](CacheModel::reset_stats) once a benchmark has
+    /// warmed up.
+    pub fn cache_model_mut(&mut self) -> Option<&mut CacheModel> {
+        self.caches.as_mut()
+    }
+
+    /// Model a TLB of `config`'s shape, starting empty. See [`Tlb`] for
+    /// what it counts.
+    pub fn enable_tlb(&mut self, config: TlbConfig) {
+        self.tlb = Some(Tlb::new(config));
+    }
+
+    /// Stop modelling the TLB and hand it back with its stats.
+    pub fn take_tlb(&mut self) -> Option<Tlb> {
+        self.tlb.take()
+    }
+
+    pub fn tlb(&self) -> Option<&Tlb> {
+        self.tlb.as_ref()
+    }
+
+    pub fn tlb_mut(&mut self) -> Option<&mut Tlb> {
+        self.tlb.as_mut()
+    }
+
+    /// Time execution on a model five-stage pipeline with `config`'s
+    /// hazard costs, starting empty. See [`Pipeline`] for what it charges.
+    pub fn enable_pipeline(&mut self, config: PipelineConfig) {
+        self.pipeline = Some(Pipeline::new(config));
+    }
+
+    /// Stop timing and hand back the pipeline with its stats.
+    pub fn take_pipeline(&mut self) -> Option<Pipeline> {
+        self.pipeline.take()
+    }
+
+    pub fn pipeline(&self) -> Option<&Pipeline> {
+        self.pipeline.as_ref()
+    }
+
+    pub fn pipeline_mut(&mut self) -> Option<&mut Pipeline> {
+        self.pipeline.as_mut()
+    }
+
+    /// Count `costs`' cycles for each instruction on `mcycle`, rather than
+    /// one each.
+    pub fn set_cycle_costs(&mut self, costs: CycleCosts) {
+        self.costs = Some(costs);
+    }
+
+    /// Go back to one cycle per instruction.
+    pub fn take_cycle_costs(&mut self) -> Option<CycleCosts> {
+        self.costs.take()
+    }
+
+    pub fn cycle_costs(&self) -> Option<&CycleCosts> {
+        self.costs.as_ref()
+    }
+
+    /// Count what each retired instruction was and which way each branch
+    /// went. Off by default; turning it off keeps the counts until
+    /// [`reset_stats`](Self::reset_stats).
+    pub fn set_stats_enabled(&mut self, enabled: bool) {
+        self.stats.set_enabled(enabled);
+    }
+
+    pub fn stats_enabled(&self) -> bool {
+        self.stats.is_enabled()
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// The symbols from the last ELF image loaded, if it wasn't stripped.
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    /// For images that don't come with their own, such as raw binaries
+    /// built alongside an ELF.
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// The function or object covering `pc` and the offset into it.
+    pub fn symbol_at(&self, pc: u32) -> Option<(&Symbol, u32)> {
+        self.symbols.symbol_at(pc)
+    }
+
+    /// The DWARF line table from the last ELF image loaded.
+    #[cfg(feature = "dwarf")]
+    pub fn line_table(&self) -> &dwarf::LineTable {
+        &self.lines
+    }
+
+    #[cfg(feature = "dwarf")]
+    pub fn set_line_table(&mut self, lines: dwarf::LineTable) {
+        self.lines = lines;
+    }
+
+    /// The source line the instruction at `pc` came from.
+    #[cfg(feature = "dwarf")]
+    pub fn source_at(&self, pc: u32) -> Option<dwarf::SourceLocation> {
+        self.lines.location(pc)
+    }
+
+    /// Run instructions in `space` through `handler`. Without one they raise
+    /// illegal-instruction exceptions.
+    pub fn set_custom(&mut self, space: CustomOpcode, handler: impl CustomHandler + 'static) {
+        self.custom[space as usize] = Some(Box::new(handler));
+    }
+
+    pub fn clear_custom(&mut self, space: CustomOpcode) {
+        self.custom[space as usize] = None;
+    }
+
+    /// Hand instructions whose opcode belongs to an extension the emulator
+    /// doesn't implement to `handler`, to emulate or, by returning
+    /// `Ok(false)`, to raise an illegal-instruction exception. Without one
+    /// they're skipped and reported to the tracer.
+    pub fn set_unknown_opcode_handler(&mut self, handler: impl CustomHandler + 'static) {
+        self.unknown = Some(Box::new(handler));
+    }
+
+    pub fn clear_unknown_opcode_handler(&mut self) {
+        self.unknown = None;
+    }
+
+    fn execute_custom(&mut self, raw: u32, next_pc: &mut X::Reg) -> Result<(), Exception> {
+        let space = CustomOpcode::of(raw).ok_or(Exception::IllegalInstruction(raw))? as usize;
+        self.run_handler(|cpu| &mut cpu.custom[space], raw, next_pc)
+    }
+
+    /// Run the handler `handler` picks out, if there is one. It's taken out
+    /// while it runs, so it can be handed the hart.
+    fn run_handler(
+        &mut self,
+        handler: impl Fn(&mut Self) -> &mut Option<Box<dyn CustomHandler>>,
+        raw: u32,
+        next_pc: &mut X::Reg,
+    ) -> Result<(), Exception> {
+        let illegal = Exception::IllegalInstruction(raw);
+        let mut taken = handler(self).take().ok_or(illegal)?;
+
+        let mut hart = HartView { cpu: self, next_pc };
+        let result = taken.execute(&mut hart, raw);
+        *handler(self) = Some(taken);
+
+        match result? {
+            true => Ok(()),
+            false => Err(illegal),
+        }
+    }
+
+    /// The hooks `hooks` picks out are taken out while they run, so they
+    /// can be handed the hart.
+    fn call_hooks(
+        &mut self,
+        hooks: fn(&mut Self) -> &mut Vec<StepHook>,
+        step: &StepInfo,
+        next_pc: &mut X::Reg,
+    ) {
+        if hooks(self).is_empty() {
+            return;
+        }
+        let mut taken = std::mem::take(hooks(self));
+        let mut hart = HartView { cpu: self, next_pc };
+        for hook in &mut taken {
+            hook(&mut hart, step);
+        }
+        *hooks(self) = taken;
+    }
+
+    fn trace(&mut self, event: impl FnOnce(&mut dyn Tracer)) {
+        if let Some(tracer) = self.tracer.as_deref_mut() {
+            event(tracer);
+        }
+    }
+
+    /// Report a retired instruction to the tracer and collectors.
+    fn observe(&mut self, pc: u32, raw: u32, instruction: &Instruction, next_pc: X::Reg) {
+        self.trace(|t| t.instruction(pc, raw, instruction));
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(pc);
+        }
+        if let Some(caches) = &mut self.caches {
+            caches.fetch(pc);
+        }
+        if self.tlb.is_some() {
+            self.tlb_lookup(X::widen(self.pc), 4, Access::Fetch);
+        }
+        if let Some(pipeline) = &mut self.pipeline {
+            let fallthrough = X::truncate(X::widen(self.pc).wrapping_add(4));
+            pipeline.retire(pc, instruction, next_pc != fallthrough);
+        }
+        if let Some(costs) = &self.costs {
+            // The step already counted one.
+            self.csrs
+                .add_cycles(costs.cost(instruction).wrapping_sub(1));
+        }
+        if self.stats.is_enabled() {
+            self.stats.record(raw, instruction);
+        }
+    }
+
+    /// Whether anything wants to hear about each instruction, which
+    /// compiled code can't tell it.
+    #[cfg(feature = "jit")]
+    pub(crate) fn observed(&self) -> bool {
+        self.tracer.is_some()
+            || self.coverage.is_some()
+            || self.memory_profile.is_some()
+            || self.caches.is_some()
+            || self.tlb.is_some()
+            || self.pipeline.is_some()
+            || self.costs.is_some()
+            || !self.pre_step.is_empty()
+            || !self.post_step.is_empty()
+            || self.stats.is_enabled()
+            || self.calls.is_tracking()
+    }
+
+    /// Arithmetic is done on zero-extended `u64`s and narrowed by
+    /// `write_reg`. Signed comparisons and right shifts go through `sreg`.
+    pub fn execute_instruction(
+        &mut self,
+        instruction: Instruction,
+        next_pc: &mut X::Reg,
+    ) -> Result<(), Exception> {
+        use Instruction::*;
+
+        let pc = X::widen(self.pc);
+        let shamt_mask = (X::BITS - 1) as u64;
+
+        match instruction {
+            Lui { rd, imm } => self.write_reg(rd, sext(imm as i32)),
+            Auipc { rd, imm } => self.write_reg(rd, pc.wrapping_add(sext(imm as i32))),
+
+            Jal { rd, imm } => {
+                self.write_reg(rd, pc.wrapping_add(4));
+                *next_pc = X::truncate(pc.wrapping_add(sext(imm)));
+                if self.calls.is_tracking() {
+                    let target = X::widen(*next_pc) as u32;
+                    self.calls.jump(rd, None, pc as u32, target);
+                }
+            }
+            Jalr { rd, rs1, imm } => {
+                let target = self.read_reg(rs1).wrapping_add(sext(imm)) & !1;
+                self.write_reg(rd, pc.wrapping_add(4));
+                *next_pc = X::truncate(target);
+                if self.calls.is_tracking() {
+                    let target = X::widen(*next_pc) as u32;
+                    self.calls.jump(rd, Some(rs1), pc as u32, target);
+                }
+            }
+
+            Beq { rs1, rs2, imm } => {
+                self.branch(self.read_reg(rs1) == self.read_reg(rs2), imm, next_pc)
+            }
+            Bne { rs1, rs2, imm } => {
+                self.branch(self.read_reg(rs1) != self.read_reg(rs2), imm, next_pc)
+            }
+            Blt { rs1, rs2, imm } => self.branch(self.sreg(rs1) < self.sreg(rs2), imm, next_pc),
+            Bge { rs1, rs2, imm } => self.branch(self.sreg(rs1) >= self.sreg(rs2), imm, next_pc),
+            Bltu { rs1, rs2, imm } => {
+                self.branch(self.read_reg(rs1) < self.read_reg(rs2), imm, next_pc)
+            }
+            Bgeu { rs1, rs2, imm } => {
+                self.branch(self.read_reg(rs1) >= self.read_reg(rs2), imm, next_pc)
+            }
+
+            Lb { rd, rs1, imm } => self.exec_load(rd, rs1, imm, MemSize::Byte, true)?,
+            Lh { rd, rs1, imm } => self.exec_load(rd, rs1, imm, MemSize::Half, true)?,
+            Lw { rd, rs1, imm } => self.exec_load(rd, rs1, imm, MemSize::Word, true)?,
+            Lbu { rd, rs1, imm } => self.exec_load(rd, rs1, imm, MemSize::Byte, false)?,
+            Lhu { rd, rs1, imm } => self.exec_load(rd, rs1, imm, MemSize::Half, false)?,
+            Lwu { rd, rs1, imm } => self.exec_load(rd, rs1, imm, MemSize::Word, false)?,
+            Ld { rd, rs1, imm } => self.exec_load_double(rd, rs1, imm)?,
+
+            Sb { rs1, rs2, imm } => self.exec_store(rs1, rs2, imm, MemSize::Byte)?,
+            Sh { rs1, rs2, imm } => self.exec_store(rs1, rs2, imm, MemSize::Half)?,
+            Sw { rs1, rs2, imm } => self.exec_store(rs1, rs2, imm, MemSize::Word)?,
+            Sd { rs1, rs2, imm } => self.exec_store_double(rs1, rs2, imm)?,
+
+            LrW { rd, rs1 } => self.exec_load_reserved(rd, rs1, false)?,
+            ScW { rd, rs1, rs2 } => self.exec_store_conditional(rd, rs1, rs2, false)?,
+            LrD { rd, rs1 } => self.exec_load_reserved(rd, rs1, true)?,
+            ScD { rd, rs1, rs2 } => self.exec_store_conditional(rd, rs1, rs2, true)?,
+
+            Addi { rd, rs1, imm } => self.write_reg(rd, self.read_reg(rs1).wrapping_add(sext(imm))),
+            Slti { rd, rs1, imm } => self.write_reg(rd, (self.sreg(rs1) < imm as i64) as u64),
+            Sltiu { rd, rs1, imm } => {
+                self.write_reg(rd, (self.read_reg(rs1) < sext(imm) & X::MASK) as u64)
+            }
+            Xori { rd, rs1, imm } => self.write_reg(rd, self.read_reg(rs1) ^ sext(imm)),
+            Ori { rd, rs1, imm } => self.write_reg(rd, self.read_reg(rs1) | sext(imm)),
+            Andi { rd, rs1, imm } => self.write_reg(rd, self.read_reg(rs1) & sext(imm)),
+            Slli { rd, rs1, shamt } => self.write_reg(rd, self.read_reg(rs1) << shamt),
+            Srli { rd, rs1, shamt } => self.write_reg(rd, self.read_reg(rs1) >> shamt),
+            Srai { rd, rs1, shamt } => self.write_reg(rd, (self.sreg(rs1) >> shamt) as u64),
+
+            Add { rd, rs1, rs2 } => {
+                self.write_reg(rd, self.read_reg(rs1).wrapping_add(self.read_reg(rs2)))
+            }
+            Sub { rd, rs1, rs2 } => {
+                self.write_reg(rd, self.read_reg(rs1).wrapping_sub(self.read_reg(rs2)))
+            }
+            Sll { rd, rs1, rs2 } => {
+                self.write_reg(rd, self.read_reg(rs1) << (self.read_reg(rs2) & shamt_mask))
+            }
+            Slt { rd, rs1, rs2 } => self.write_reg(rd, (self.sreg(rs1) < self.sreg(rs2)) as u64),
+            Sltu { rd, rs1, rs2 } => {
+                self.write_reg(rd, (self.read_reg(rs1) < self.read_reg(rs2)) as u64)
+            }
+            Xor { rd, rs1, rs2 } => self.write_reg(rd, self.read_reg(rs1) ^ self.read_reg(rs2)),
+            Srl { rd, rs1, rs2 } => {
+                self.write_reg(rd, self.read_reg(rs1) >> (self.read_reg(rs2) & shamt_mask))
+            }
+            Sra { rd, rs1, rs2 } => self.write_reg(
+                rd,
+                (self.sreg(rs1) >> (self.read_reg(rs2) & shamt_mask)) as u64,
+            ),
+            Or { rd, rs1, rs2 } => self.write_reg(rd, self.read_reg(rs1) | self.read_reg(rs2)),
+            And { rd, rs1, rs2 } => self.write_reg(rd, self.read_reg(rs1) & self.read_reg(rs2)),
+
+            Addiw { rd, rs1, imm } => {
+                self.write_word(rd, (self.read_reg(rs1) as u32).wrapping_add(imm as u32))
+            }
+            Slliw { rd, rs1, shamt } => self.write_word(rd, (self.read_reg(rs1) as u32) << shamt),
+            Srliw { rd, rs1, shamt } => self.write_word(rd, (self.read_reg(rs1) as u32) >> shamt),
+            Sraiw { rd, rs1, shamt } => {
+                self.write_word(rd, ((self.read_reg(rs1) as i32) >> shamt) as u32)
+            }
+            Addw { rd, rs1, rs2 } => self.write_word(
+                rd,
+                (self.read_reg(rs1) as u32).wrapping_add(self.read_reg(rs2) as u32),
+            ),
+            Subw { rd, rs1, rs2 } => self.write_word(
+                rd,
+                (self.read_reg(rs1) as u32).wrapping_sub(self.read_reg(rs2) as u32),
+            ),
+            Sllw { rd, rs1, rs2 } => self.write_word(
+                rd,
+                (self.read_reg(rs1) as u32) << (self.read_reg(rs2) & 0x1F),
+            ),
+            Srlw { rd, rs1, rs2 } => self.write_word(
+                rd,
+                (self.read_reg(rs1) as u32) >> (self.read_reg(rs2) & 0x1F),
+            ),
+            Sraw { rd, rs1, rs2 } => self.write_word(
+                rd,
+                ((self.read_reg(rs1) as i32) >> (self.read_reg(rs2) & 0x1F)) as u32,
+            ),
+
+            Float(instruction) => {
+                // Only stores leave the floating-point state as it was.
+                if !matches!(
+                    instruction,
+                    FloatInstruction::Fsw { .. } | FloatInstruction::Fsd { .. }
+                ) {
+                    self.set_dirty(csr::MSTATUS_FS);
+                }
+                self.execute_float(instruction)?
+            }
+            Vector(instruction) => {
+                self.set_dirty(csr::MSTATUS_VS);
+                self.execute_vector(instruction)?
+            }
+            Crypto(instruction) => self.execute_crypto(instruction),
+            Hypervisor(instruction) => self.execute_hypervisor(instruction)?,
+            Custom(raw) => self.execute_custom(raw, next_pc)?,
+
+            Sh1add { rd, rs1, rs2 } => self.shift_add(rd, self.read_reg(rs1), 1, rs2),
+            Sh2add { rd, rs1, rs2 } => self.shift_add(rd, self.read_reg(rs1), 2, rs2),
+            Sh3add { rd, rs1, rs2 } => self.shift_add(rd, self.read_reg(rs1), 3, rs2),
+            AddUw { rd, rs1, rs2 } => self.shift_add(rd, self.read_reg(rs1) as u32 as u64, 0, rs2),
+            Sh1addUw { rd, rs1, rs2 } => {
+                self.shift_add(rd, self.read_reg(rs1) as u32 as u64, 1, rs2)
+            }
+            Sh2addUw { rd, rs1, rs2 } => {
+                self.shift_add(rd, self.read_reg(rs1) as u32 as u64, 2, rs2)
+            }
+            Sh3addUw { rd, rs1, rs2 } => {
+                self.shift_add(rd, self.read_reg(rs1) as u32 as u64, 3, rs2)
+            }
+            SlliUw { rd, rs1, shamt } => {
+                self.write_reg(rd, (self.read_reg(rs1) as u32 as u64) << shamt)
+            }
+
+            Pack { rd, rs1, rs2 } => {
+                let half = X::BITS / 2;
+                let low = self.read_reg(rs1) & (X::MASK >> half);
+                self.write_reg(rd, low | self.read_reg(rs2) << half);
+            }
+            Packh { rd, rs1, rs2 } => self.write_reg(
+                rd,
+                (self.read_reg(rs1) & 0xFF) | (self.read_reg(rs2) & 0xFF) << 8,
+            ),
+            Packw { rd, rs1, rs2 } => {
+                let packed = (self.read_reg(rs1) & 0xFFFF) | (self.read_reg(rs2) & 0xFFFF) << 16;
+                self.write_word(rd, packed as u32);
+            }
+            Brev8 { rd, rs1 } => {
+                let bytes = self.read_reg(rs1).to_le_bytes().map(u8::reverse_bits);
+                self.write_reg(rd, u64::from_le_bytes(bytes));
+            }
+            Zip { rd, rs1 } => self.write_word(rd, zip(self.read_reg(rs1) as u32)),
+            Unzip { rd, rs1 } => self.write_word(rd, unzip(self.read_reg(rs1) as u32)),
+
+            // Memory is always coherent and the TLB is only a model, so only
+            // the decoded-code caches have anything to flush.
+            Fence => {}
+            FenceI => {
+                self.icache.clear();
+                self.blocks.flush();
+            }
+            SfenceVma { rs1, rs2 } => {
+                self.blocks.flush();
+                let vpn = (rs1 != 0).then(|| self.read_reg(rs1) >> 12);
+                let asid_mask = if X::BITS == 32 {
+                    csr::SATP_ASID_RV32
+                } else {
+                    csr::SATP_ASID_RV64
+                };
+                let asid = (rs2 != 0).then(|| self.read_reg(rs2) as u32 & asid_mask);
+                if let Some(tlb) = &mut self.tlb {
+                    tlb.fence(vpn, asid);
+                }
+            }
+
+            Ecall if self.syscalls.is_some() => self.syscall(next_pc),
+            Ecall => {
+                return Err(match self.privilege {
+                    Privilege::User => Exception::UserEnvironmentCall,
+                    Privilege::Supervisor if self.virt => {
+                        Exception::VirtualSupervisorEnvironmentCall
+                    }
+                    Privilege::Supervisor => Exception::SupervisorEnvironmentCall,
+                    Privilege::Machine => Exception::EnvironmentCall,
+                });
+            }
+            Ebreak if self.is_semihosting_call() => self.semihost(),
+            Ebreak => return Err(Exception::Breakpoint(self.pc_u32())),
+            Mret => self.mret(next_pc),
+            Sret => self.sret(next_pc),
+            Wfi => self.waiting = Some(Wait::Interrupt),
+            // Without a reservation there's nothing to wait on.
+            WrsNto | WrsSto if self.bus.has_reservation(self.hart_id()) => {
+                let deadline = (instruction == WrsSto).then(|| self.bus.time() + WRS_STO_TICKS);
+                self.waiting = Some(Wait::Reservation { deadline });
+            }
+            WrsNto | WrsSto => {}
+
+            // The immediate forms reuse the rs1 field as a 5-bit zero-extended value.
+            Csrrw { rd, rs1, csr } => self.csr_op(rd, csr, Some(self.read_reg(rs1)), |_, v| v),
+            Csrrs { rd, rs1, csr } => self.csr_op(rd, csr, self.csr_operand(rs1), |old, v| old | v),
+            Csrrc { rd, rs1, csr } => {
+                self.csr_op(rd, csr, self.csr_operand(rs1), |old, v| old & !v)
+            }
+            Csrrwi { rd, uimm, csr } => self.csr_op(rd, csr, Some(uimm as u64), |_, v| v),
+            Csrrsi { rd, uimm, csr } => {
+                self.csr_op(rd, csr, Self::csr_uimm(uimm), |old, v| old | v)
+            }
+            Csrrci { rd, uimm, csr } => {
+                self.csr_op(rd, csr, Self::csr_uimm(uimm), |old, v| old & !v)
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load(&mut self, addr: u32, size: MemSize, signed: bool) -> Result<u32, Exception> {
+        let raw = self
+            .bus
+            .read(addr, size)
+            .ok_or(Exception::LoadAccessFault(addr))?;
+
+        if !signed {
+            return Ok(raw);
+        }
+
+        match size {
+            MemSize::Byte => Ok((raw as i8 as i32) as u32),
+            MemSize::Half => Ok((raw as i16 as i32) as u32),
+            MemSize::Word => Ok(raw),
+        }
+    }
+
+    pub fn store(&mut self, addr: u32, size: MemSize, value: u32) -> Result<(), Exception> {
+        self.bus
+            .write(addr, size, value)
+            .ok_or(Exception::StoreAccessFault(addr))
+    }
+
+    // The per-format handlers below predate the decoder. They're kept so
+    // callers can drive a single instruction without going through step().
+
+    pub fn handle_rtype(&mut self, instruction: u32) -> Result<(), Exception> {
+        self.handle(instruction)
+    }
+
+    pub fn handle_itype(&mut self, instruction: u32) -> Result<(), Exception> {
+        self.handle(instruction)
+    }
+
+    pub fn handle_load(&mut self, instruction: u32) -> Result<(), Exception> {
+        self.handle(instruction)
+    }
+
+    pub fn handle_store(&mut self, instruction: u32) -> Result<(), Exception> {
+        self.handle(instruction)
+    }
+
+    pub fn handle_btype(
+        &mut self,
+        instruction: u32,
+        next_pc: &mut X::Reg,
+    ) -> Result<(), Exception> {
+        self.execute(instruction, next_pc)
+    }
+
+    pub fn handle_jal(&mut self, instruction: u32, next_pc: &mut X::Reg) -> Result<(), Exception> {
+        self.execute(instruction, next_pc)
+    }
+
+    pub fn handle_jalr(&mut self, instruction: u32, next_pc: &mut X::Reg) -> Result<(), Exception> {
+        self.execute(instruction, next_pc)
+    }
+
+    pub fn handle_lui(&mut self, instruction: u32) -> Result<(), Exception> {
+        self.handle(instruction)
+    }
+
+    pub fn handle_auipc(&mut self, instruction: u32) -> Result<(), Exception> {
+        self.handle(instruction)
+    }
+
+    pub fn handle_system(
+        &mut self,
+        instruction: u32,
+        next_pc: &mut X::Reg,
+    ) -> Result<(), Exception> {
+        self.execute(instruction, next_pc)
+    }
+
+    fn handle(&mut self, instruction: u32) -> Result<(), Exception> {
+        let mut next_pc = X::truncate(X::widen(self.pc).wrapping_add(4));
+        self.execute(instruction, &mut next_pc)
+    }
+
+    fn is_semihosting_call(&mut self) -> bool {
+        if self.semihosting.is_none() || X::BITS != 32 {
+            return false;
+        }
+
+        let pc = self.pc_u32();
+        let before = self.bus.read(pc.wrapping_sub(4), MemSize::Word);
+        let after = self.bus.read(pc.wrapping_add(4), MemSize::Word);
+
+        before == Some(semihosting::ENTRY_NOP) && after == Some(semihosting::EXIT_NOP)
+    }
+
+    fn semihost(&mut self) {
+        let (op, arg) = (self.reg(Reg::A0) as u32, self.reg(Reg::A1) as u32);
+        let Some(host) = self.semihosting.as_mut() else {
+            return;
+        };
+
+        match host.call(op, arg, &mut self.bus) {
+            semihosting::Outcome::Return(value) => self.write_reg(Reg::A0.index(), value as u64),
+            semihosting::Outcome::Exit(code) => self.exit_code = Some(code),
+        }
+    }
+
+    fn syscall(&mut self, next_pc: &mut X::Reg) {
+        let nr = self.reg(Reg::A7);
+        let args = [Reg::A0, Reg::A1, Reg::A2, Reg::A3, Reg::A4, Reg::A5].map(|r| self.reg(r));
+        let Some(host) = self.syscalls.as_mut() else {
+            return;
+        };
+
+        match host.call(nr, args, &mut self.bus) {
+            syscall::Outcome::Return(value) => self.write_reg(Reg::A0.index(), value as u64),
+            syscall::Outcome::Switch(value) => {
+                self.write_reg(Reg::A0.index(), value as u64);
+                self.pc = *next_pc;
+                self.switch_thread();
+                *next_pc = self.pc;
+            }
+            syscall::Outcome::Clone(thread) => {
+                let mut context = self.thread_context();
+                context.pc = X::widen(*next_pc);
+                let tid = thread.tid();
+                if let Some(host) = self.syscalls.as_mut() {
+                    host.spawn(thread, context);
+                }
+                self.write_reg(Reg::A0.index(), tid as u64);
+            }
+            syscall::Outcome::Exit(code) => self.exit_code = Some(code),
+        }
+    }
+
+    /// The running thread's registers, to resume at the PC.
+    fn thread_context(&self) -> syscall::Context {
+        syscall::Context {
+            regs: self.regs.map(X::widen),
+            fregs: self.fregs,
+            fcsr: self.csrs.read_u64(csr::FCSR),
+            pc: X::widen(self.pc),
+        }
+    }
+
+    /// Put the running thread away and load the next one. If every thread
+    /// is blocked for good, the hart waits like it does in WFI.
+    fn switch_thread(&mut self) {
+        let saved = self.thread_context();
+        let Some(host) = self.syscalls.as_mut() else {
+            return;
+        };
+        let Some(next) = host.switch(saved, &mut self.bus) else {
+            self.waiting = Some(Wait::Interrupt);
+            return;
+        };
+
+        self.regs = next.regs.map(X::truncate);
+        self.fregs = next.fregs;
+        self.csrs.set_u64(csr::FCSR, next.fcsr);
+        self.pc = X::truncate(next.pc);
+        // An LR in one thread mustn't let an SC in another succeed.
+        self.bus.take_reservation(self.hart_id(), 0);
+    }
+
+    /// Count `executed` instructions against the running thread's turn and
+    /// switch threads once it's over.
+    fn preempt(&mut self, executed: u64) {
+        if let Some(host) = &mut self.syscalls
+            && host.preempt(executed)
+        {
+            self.switch_thread();
+        }
+    }
+
+    /// Zba: `(base << shift) + rs2`.
+    fn shift_add(&mut self, rd: u8, base: u64, shift: u32, rs2: u8) {
+        self.write_reg(rd, (base << shift).wrapping_add(self.read_reg(rs2)));
+    }
+
+    fn branch(&mut self, taken: bool, imm: i32, next_pc: &mut X::Reg) {
+        self.csrs.count(HpmEvent::Branch);
+        if self.stats.is_enabled() {
+            self.stats.record_branch(self.pc_u32(), taken);
+        }
+        if taken {
+            self.csrs.count(HpmEvent::BranchTaken);
+            *next_pc = X::truncate(X::widen(self.pc).wrapping_add(sext(imm)));
+        }
+    }
+
+    /// The current privilege level. Harts start in M-mode.
+    pub fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    pub fn set_privilege(&mut self, privilege: Privilege) {
+        self.privilege = privilege;
+    }
+
+    fn hart_id(&self) -> u64 {
+        self.csrs.read_u64(csr::MHARTID)
+    }
+
+    /// CSRRS/CSRRC with rs1 = x0 must not write the CSR at all.
+    fn csr_operand(&self, rs1: u8) -> Option<u64> {
+        (rs1 != 0).then(|| self.read_reg(rs1))
+    }
+
+    fn csr_uimm(uimm: u8) -> Option<u64> {
+        (uimm != 0).then_some(uimm as u64)
+    }
+
+    fn csr_op(&mut self, rd: u8, csr: u16, operand: Option<u64>, op: fn(u64, u64) -> u64) {
+        let csr = self.guest_csr(csr);
+        let time = match self.virt {
+            true => self.bus.time().wrapping_add(self.csrs.time_delta()),
+            false => self.bus.time(),
+        };
+        let old = match csr {
+            csr::TIME => time & X::MASK,
+            csr::TIMEH => time >> 32,
+            _ => self.csrs.read_u64(csr),
+        };
+
+        if let Some(value) = operand {
+            self.csrs.write_u64(csr, op(old, value));
+            match csr {
+                csr::FFLAGS | csr::FRM | csr::FCSR => self.set_dirty(csr::MSTATUS_FS),
+                csr::VSTART => self.set_dirty(csr::MSTATUS_VS),
+                _ => {}
+            }
+        }
+
+        self.write_reg(rd, old);
+    }
+
+    pub fn dump_registers(&self) {
+        println!("\n{}", self.state());
+    }
+
+    /// A bus address, if `addr` fits in the 32-bit physical address space.
+    pub(crate) fn phys(addr: u64) -> Option<u32> {
+        u32::try_from(addr).ok()
+    }
+
+    /// The PC as reported to the host: see the note on [`RiscvCpu`].
+    pub(crate) fn pc_u32(&self) -> u32 {
+        X::widen(self.pc) as u32
+    }
+
+    pub(crate) fn read_reg(&self, reg: u8) -> u64 {
+        X::widen(self.regs[reg as usize])
+    }
+
+    pub(crate) fn sreg(&self, reg: u8) -> i64 {
+        X::signed(self.regs[reg as usize])
+    }
+
+    pub(crate) fn write_reg(&mut self, reg: u8, value: u64) {
+        if reg == 0 {
+            return;
+        }
+        let value = X::truncate(value);
+        if self.journal.is_some() {
+            let old = self.regs[reg as usize];
+            self.journal_frame(|frame| frame.regs.push((reg, old)));
+        }
+        if self.debug.watched_regs != 0 {
+            self.debug.check_reg_write(RegisterWrite {
+                pc: self.pc_u32(),
+                reg,
+                old: X::widen(self.regs[reg as usize]),
+                new: X::widen(value),
+            });
+        }
+        self.regs[reg as usize] = value;
+        self.trace(|t| t.register_write(reg, X::widen(value)));
+    }
+
+    /// Sign-extend a 32-bit result into `reg`, as the RV64 `*W` forms do.
+    pub(crate) fn write_word(&mut self, reg: u8, value: u32) {
+        self.write_reg(reg, value as i32 as i64 as u64);
+    }
+}
+
+/// Interleave the low and high halves: bit `i` goes to `2i`, bit `16 + i`
+/// to `2i + 1`.
+fn zip(x: u32) -> u32 {
+    (0..16).fold(0, |out, i| {
+        out | ((x >> i) & 1) << (2 * i) | ((x >> (16 + i)) & 1) << (2 * i + 1)
+    })
+}
+
+fn unzip(x: u32) -> u32 {
+    (0..16).fold(0, |out, i| {
+        out | ((x >> (2 * i)) & 1) << i | ((x >> (2 * i + 1)) & 1) << (16 + i)
+    })
+}
+
+/// Sign-extend an immediate to 64 bits; `write_reg` narrows it again on RV32.
+fn sext(imm: i32) -> u64 {
+    imm as i64 as u64
+}
@@ -0,0 +1,863 @@
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::xlen::{Rv32, Xlen};
+
+pub const FFLAGS: u16 = 0x001;
+pub const FRM: u16 = 0x002;
+pub const FCSR: u16 = 0x003;
+pub const VSTART: u16 = 0x008;
+
+// The supervisor views of `mstatus`, `mie` and `mip` have no storage of
+// their own.
+pub const SSTATUS: u16 = 0x100;
+pub const SIE: u16 = 0x104;
+pub const STVEC: u16 = 0x105;
+pub const SCOUNTEREN: u16 = 0x106;
+pub const SENVCFG: u16 = 0x10A;
+pub const SSCRATCH: u16 = 0x140;
+pub const SEPC: u16 = 0x141;
+pub const SCAUSE: u16 = 0x142;
+pub const STVAL: u16 = 0x143;
+pub const SIP: u16 = 0x144;
+pub const SATP: u16 = 0x180;
+
+// H. While V=1 the VS CSRs stand in for the supervisor ones of the same
+// name. `vsie` and `vsip` are views of `mie` and `mip`, as are `hie`,
+// `hip` and `hvip`.
+pub const VSSTATUS: u16 = 0x200;
+pub const VSIE: u16 = 0x204;
+pub const VSTVEC: u16 = 0x205;
+pub const VSSCRATCH: u16 = 0x240;
+pub const VSEPC: u16 = 0x241;
+pub const VSCAUSE: u16 = 0x242;
+pub const VSTVAL: u16 = 0x243;
+pub const VSIP: u16 = 0x244;
+pub const VSATP: u16 = 0x280;
+pub const HSTATUS: u16 = 0x600;
+pub const HEDELEG: u16 = 0x602;
+pub const HIDELEG: u16 = 0x603;
+pub const HIE: u16 = 0x604;
+pub const HTIMEDELTA: u16 = 0x605;
+pub const HCOUNTEREN: u16 = 0x606;
+/// There are no guest external interrupts, so `hgeie` and `hgeip` are
+/// always zero.
+pub const HGEIE: u16 = 0x607;
+/// RV32 only.
+pub const HTIMEDELTAH: u16 = 0x615;
+pub const HTVAL: u16 = 0x643;
+pub const HIP: u16 = 0x644;
+pub const HVIP: u16 = 0x645;
+pub const HTINST: u16 = 0x64A;
+pub const HGATP: u16 = 0x680;
+pub const HGEIP: u16 = 0xE12;
+
+pub const MSTATUS: u16 = 0x300;
+pub const MISA: u16 = 0x301;
+pub const MEDELEG: u16 = 0x302;
+pub const MIDELEG: u16 = 0x303;
+/// RV32 only. MBE and SBE are always zero, so nothing in it is writable.
+pub const MSTATUSH: u16 = 0x310;
+pub const MIE: u16 = 0x304;
+pub const MTVEC: u16 = 0x305;
+pub const MCOUNTEREN: u16 = 0x306;
+pub const MENVCFG: u16 = 0x30A;
+/// RV32 only, and nothing in it is writable.
+pub const MENVCFGH: u16 = 0x31A;
+/// Counters can't be stopped, so this is read-only zero.
+pub const MCOUNTINHIBIT: u16 = 0x320;
+pub const MHPMEVENT3: u16 = 0x323;
+pub const MHPMEVENT31: u16 = 0x33F;
+pub const MSCRATCH: u16 = 0x340;
+pub const MEPC: u16 = 0x341;
+pub const MCAUSE: u16 = 0x342;
+pub const MTVAL: u16 = 0x343;
+pub const MIP: u16 = 0x344;
+/// Transformed instructions aren't provided, so `mtinst` and `htinst` are
+/// only ever written with zero.
+pub const MTINST: u16 = 0x34A;
+pub const MTVAL2: u16 = 0x34B;
+
+// There are no PMP entries, which the spec allows: every `pmpcfg` and
+// `pmpaddr` register is read-only zero. On RV64 only the even `pmpcfg`s
+// exist.
+pub const PMPCFG0: u16 = 0x3A0;
+pub const PMPCFG15: u16 = 0x3AF;
+pub const PMPADDR0: u16 = 0x3B0;
+pub const PMPADDR63: u16 = 0x3EF;
+
+// Read-only machine information, all zero.
+pub const MVENDORID: u16 = 0xF11;
+pub const MARCHID: u16 = 0xF12;
+pub const MIMPID: u16 = 0xF13;
+pub const MHARTID: u16 = 0xF14;
+pub const MCONFIGPTR: u16 = 0xF15;
+
+// Sdtrig. `tdata1` and `tdata2` are those of the trigger `tselect` picks.
+// Every trigger is an mcontrol one, so `tdata3` is always zero.
+pub const TSELECT: u16 = 0x7A0;
+pub const TDATA1: u16 = 0x7A1;
+pub const TDATA2: u16 = 0x7A2;
+pub const TDATA3: u16 = 0x7A3;
+pub const TINFO: u16 = 0x7A4;
+
+// Zicntr. The user-level CSRs are read-only shadows of the machine ones,
+// and the `h` halves only exist on RV32.
+pub const MCYCLE: u16 = 0xB00;
+pub const MINSTRET: u16 = 0xB02;
+pub const MCYCLEH: u16 = 0xB80;
+pub const MINSTRETH: u16 = 0xB82;
+pub const CYCLE: u16 = 0xC00;
+pub const TIME: u16 = 0xC01;
+pub const INSTRET: u16 = 0xC02;
+pub const CYCLEH: u16 = 0xC80;
+pub const TIMEH: u16 = 0xC81;
+pub const INSTRETH: u16 = 0xC82;
+
+// V. `vl` and `vtype` are only written by the vset instructions, and
+// `vlenb` is VLEN in bytes.
+pub const VL: u16 = 0xC20;
+pub const VTYPE: u16 = 0xC21;
+pub const VLENB: u16 = 0xC22;
+
+// Counter n of the 29 programmable ones is `MHPMCOUNTER3 + (n - 3)`, and so
+// on for the other ranges.
+pub const MHPMCOUNTER3: u16 = 0xB03;
+pub const MHPMCOUNTER31: u16 = 0xB1F;
+pub const MHPMCOUNTER3H: u16 = 0xB83;
+pub const MHPMCOUNTER31H: u16 = 0xB9F;
+pub const HPMCOUNTER3: u16 = 0xC03;
+pub const HPMCOUNTER31: u16 = 0xC1F;
+pub const HPMCOUNTER3H: u16 = 0xC83;
+pub const HPMCOUNTER31H: u16 = 0xC9F;
+
+pub const MSTATUS_SIE: u32 = 1 << 1;
+pub const MSTATUS_MIE: u32 = 1 << 3;
+pub const MSTATUS_SPIE: u32 = 1 << 5;
+pub const MSTATUS_MPIE: u32 = 1 << 7;
+pub const MSTATUS_SPP: u32 = 1 << 8;
+pub const MSTATUS_VS: u32 = 0b11 << 9;
+pub const MSTATUS_MPP: u32 = 0b11 << 11;
+pub const MSTATUS_FS: u32 = 0b11 << 13;
+pub const MSTATUS_MPRV: u32 = 1 << 17;
+pub const MSTATUS_SUM: u32 = 1 << 18;
+pub const MSTATUS_MXR: u32 = 1 << 19;
+pub const MSTATUS_TVM: u32 = 1 << 20;
+pub const MSTATUS_TW: u32 = 1 << 21;
+pub const MSTATUS_TSR: u32 = 1 << 22;
+
+/// `mstatus.GVA` and `MPV`, as bits of `mstatush` on RV32. On RV64 they're
+/// bits 38 and 39 of `mstatus`.
+pub const MSTATUSH_GVA: u32 = 1 << 6;
+pub const MSTATUSH_MPV: u32 = 1 << 7;
+
+pub const HSTATUS_GVA: u32 = 1 << 6;
+pub const HSTATUS_SPV: u32 = 1 << 7;
+pub const HSTATUS_SPVP: u32 = 1 << 8;
+pub const HSTATUS_HU: u32 = 1 << 9;
+pub const HSTATUS_VTVM: u32 = 1 << 20;
+pub const HSTATUS_VTW: u32 = 1 << 21;
+pub const HSTATUS_VTSR: u32 = 1 << 22;
+
+const HSTATUS_WRITE_MASK: u32 = HSTATUS_GVA
+    | HSTATUS_SPV
+    | HSTATUS_SPVP
+    | HSTATUS_HU
+    | HSTATUS_VTVM
+    | HSTATUS_VTW
+    | HSTATUS_VTSR;
+
+/// The FS and VS states, shifted down. Both start out Initial, so code that
+/// never touches `mstatus` can still use floating point and vectors; any
+/// instruction that may change the state makes it Dirty.
+pub const EXT_OFF: u32 = 0;
+pub const EXT_INITIAL: u32 = 1;
+pub const EXT_CLEAN: u32 = 2;
+pub const EXT_DIRTY: u32 = 3;
+
+/// `satp.MODE` on RV32: Sv32 translation instead of bare addressing.
+pub const SATP_SV32: u32 = 1 << 31;
+
+/// `satp.ASID` once shifted down: 9 bits from bit 22 on RV32, 16 from bit
+/// 44 on RV64. SFENCE.VMA takes it unshifted in `rs2`.
+pub const SATP_ASID_RV32: u32 = 0x1FF;
+pub const SATP_ASID_RV64: u32 = 0xFFFF;
+
+/// `menvcfg`/`senvcfg.FIOM`. None of the extensions the other fields
+/// control are implemented, so they're read-only zero; in particular STCE
+/// is, as there's no Sstc.
+pub const ENVCFG_FIOM: u32 = 1 << 0;
+
+/// `hgatp.MODE` on RV32: Sv32x4 G-stage translation. The root table is
+/// 16 KiB, so the low two bits of the PPN read as zero, and VMIDs aren't
+/// implemented.
+pub const HGATP_SV32X4: u32 = 1 << 31;
+const HGATP_WRITE_MASK: u32 = HGATP_SV32X4 | 0x3F_FFFC;
+
+pub const MIP_SSIP: u32 = 1 << 1;
+pub const MIP_VSSIP: u32 = 1 << 2;
+pub const MIP_MSIP: u32 = 1 << 3;
+pub const MIP_STIP: u32 = 1 << 5;
+pub const MIP_VSTIP: u32 = 1 << 6;
+pub const MIP_MTIP: u32 = 1 << 7;
+pub const MIP_SEIP: u32 = 1 << 9;
+pub const MIP_VSEIP: u32 = 1 << 10;
+pub const MIP_MEIP: u32 = 1 << 11;
+pub const MIP_SGEIP: u32 = 1 << 12;
+
+/// The interrupts that can be delegated to S-mode.
+const SUPERVISOR_INTERRUPTS: u32 = MIP_SSIP | MIP_STIP | MIP_SEIP;
+
+/// The interrupts that can be delegated on to VS-mode through `hideleg`.
+/// `mideleg` always delegates them, and SGEI, to HS-mode.
+pub(crate) const VS_INTERRUPTS: u32 = MIP_VSSIP | MIP_VSTIP | MIP_VSEIP;
+
+/// Accrued floating-point exception flags, in `fflags` and the low bits
+/// of `fcsr`.
+pub const FFLAG_NX: u32 = 1 << 0;
+pub const FFLAG_UF: u32 = 1 << 1;
+pub const FFLAG_OF: u32 = 1 << 2;
+pub const FFLAG_DZ: u32 = 1 << 3;
+pub const FFLAG_NV: u32 = 1 << 4;
+
+/// `mcounteren` bits letting lower privilege levels read `cycle`, `time`
+/// and `instret`. `hpmcounter`n is bit n.
+pub const COUNTEREN_CY: u32 = 1 << 0;
+pub const COUNTEREN_TM: u32 = 1 << 1;
+pub const COUNTEREN_IR: u32 = 1 << 2;
+
+/// How many triggers `tselect` can pick from.
+pub const TRIGGERS: usize = 4;
+
+// The mcontrol fields of `tdata1` below bit 32. `type` is in the top four
+// bits, reading 2 for mcontrol or 15 for a disabled trigger, and `maskmax`
+// in the six below `dmode`.
+pub const MCONTROL_LOAD: u32 = 1 << 0;
+pub const MCONTROL_STORE: u32 = 1 << 1;
+pub const MCONTROL_EXECUTE: u32 = 1 << 2;
+pub const MCONTROL_U: u32 = 1 << 3;
+pub const MCONTROL_S: u32 = 1 << 4;
+pub const MCONTROL_M: u32 = 1 << 6;
+/// How `tdata2` is compared: equal, NAPOT, `>=` or `<`.
+pub const MCONTROL_MATCH: u32 = 0xF << 7;
+pub const MCONTROL_HIT: u32 = 1 << 20;
+pub const TRIGGER_MCONTROL: u64 = 2;
+pub const TRIGGER_DISABLED: u64 = 15;
+
+/// Breakpoint exceptions are the only action, with no chaining or sizes, and
+/// matches 4 and up (the masked ones) aren't implemented.
+const MCONTROL_WRITE_MASK: u32 = MCONTROL_LOAD
+    | MCONTROL_STORE
+    | MCONTROL_EXECUTE
+    | MCONTROL_U
+    | MCONTROL_S
+    | MCONTROL_M
+    | 0x3 << 7
+    | MCONTROL_HIT;
+
+const MIE_WRITE_MASK: u32 = MIP_MSIP | MIP_MTIP | MIP_MEIP | SUPERVISOR_INTERRUPTS | VS_INTERRUPTS;
+const MCOUNTEREN_WRITE_MASK: u32 = u32::MAX;
+
+// MSIP/MTIP/MEIP are driven by the platform, so the guest can't set them
+// through a CSR write. M-mode software raises the supervisor ones, and the
+// hypervisor the VS ones through `hvip`.
+const MIP_WRITE_MASK: u32 = SUPERVISOR_INTERRUPTS | VS_INTERRUPTS;
+
+/// The `mstatus` fields S-mode sees in `sstatus`, besides SD and UXL.
+const SSTATUS_FIELDS: u32 =
+    MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP | MSTATUS_VS | MSTATUS_FS | MSTATUS_SUM | MSTATUS_MXR;
+
+/// Every exception cause but ECALL from M-mode (11), which always stays
+/// there. Bits for causes that don't exist are read-only zero.
+const MEDELEG_WRITE_MASK: u32 = 0xB3FF;
+
+/// ECALL from VS-mode and the guest-page faults, which only exist with H.
+/// The virtual-instruction exception can be delegated too.
+const MEDELEG_HYPERVISOR: u32 = 1 << 10 | 0xF << 20;
+
+/// What HS-mode can delegate on to VS-mode: not the ECALLs from HS- or
+/// VS-mode, nor anything only H raises.
+const HEDELEG_WRITE_MASK: u32 = 0xB1FF;
+
+pub const MISA_D: u32 = 1 << 3;
+pub const MISA_F: u32 = 1 << 5;
+pub const MISA_H: u32 = 1 << 7;
+pub const MISA_I: u32 = 1 << 8;
+pub const MISA_S: u32 = 1 << 18;
+pub const MISA_U: u32 = 1 << 20;
+pub const MISA_V: u32 = 1 << 21;
+
+/// The `misa` bits of every extension there is here; MXL in the top two
+/// bits is filled in per XLEN.
+pub(crate) const MISA_EXTENSIONS: u32 =
+    MISA_D | MISA_F | MISA_H | MISA_I | MISA_S | MISA_U | MISA_V;
+
+/// Whether there's a CSR at `addr` on some hart. Whether a particular hart
+/// has it also depends on its XLEN and extensions; accessing one that
+/// doesn't exist is an illegal instruction, which is how firmware probes
+/// for optional CSRs.
+pub fn implemented(addr: u16) -> bool {
+    matches!(
+        addr,
+        FFLAGS..=FCSR
+            | VSTART
+            | SSTATUS
+            | SIE..=SCOUNTEREN
+            | SENVCFG
+            | SSCRATCH..=SIP
+            | SATP
+            | VSSTATUS
+            | VSIE
+            | VSTVEC
+            | VSSCRATCH..=VSIP
+            | VSATP
+            | HSTATUS
+            | HEDELEG..=HGEIE
+            | MSTATUS..=MCOUNTEREN
+            | MENVCFG
+            | MSTATUSH
+            | MENVCFGH
+            | MCOUNTINHIBIT
+            | MHPMEVENT3..=MHPMEVENT31
+            | MSCRATCH..=MIP
+            | MTINST
+            | MTVAL2
+            | PMPCFG0..=PMPADDR63
+            | HTIMEDELTAH
+            | HTVAL
+            | HIP
+            | HVIP
+            | HTINST
+            | HGATP
+            | TSELECT..=TINFO
+            | MCYCLE
+            | MINSTRET..=MHPMCOUNTER31
+            | MCYCLEH
+            | MINSTRETH..=MHPMCOUNTER31H
+            | CYCLE..=HPMCOUNTER31
+            | VL..=VLENB
+            | CYCLEH..=HPMCOUNTER31H
+            | HGEIP
+            | MVENDORID..=MCONFIGPTR
+    )
+}
+
+/// A privilege level, numbered as in `mstatus.MPP`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+
+impl Privilege {
+    /// Decode a two-bit MPP-style field. 0b10 is reserved.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits & 0b11 {
+            0 => Some(Privilege::User),
+            1 => Some(Privilege::Supervisor),
+            3 => Some(Privilege::Machine),
+            _ => None,
+        }
+    }
+}
+
+/// What an `mhpmevent` register can select. Writing any other value turns
+/// the counter off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HpmEvent {
+    Load = 1,
+    Store = 2,
+    /// Conditional branches, taken or not.
+    Branch = 3,
+    BranchTaken = 4,
+    /// Exceptions taken by the guest's trap handler.
+    Exception = 5,
+    Interrupt = 6,
+}
+
+impl HpmEvent {
+    pub fn from_code(code: u64) -> Option<Self> {
+        match code {
+            1 => Some(HpmEvent::Load),
+            2 => Some(HpmEvent::Store),
+            3 => Some(HpmEvent::Branch),
+            4 => Some(HpmEvent::BranchTaken),
+            5 => Some(HpmEvent::Exception),
+            6 => Some(HpmEvent::Interrupt),
+            _ => None,
+        }
+    }
+}
+
+/// Where a CSR's bits are kept, for undoing writes to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CsrSlot {
+    Reg(usize),
+    Counter(usize),
+    /// Trigger, then `tdata1` or `tdata2`.
+    Trigger(usize, usize),
+}
+
+/// CSRs are stored as `u64` and narrowed to the hart's XLEN on access.
+pub struct CsrFile<X: Xlen = Rv32> {
+    regs: Vec<u64>,
+    /// The 64-bit counters, indexed like `cycle` + n. `time` (index 1) is
+    /// kept by the bus instead.
+    counters: [u64; 32],
+    /// Which events some `mhpmevent` selects, as `1 << code`, so that
+    /// counting nothing stays cheap.
+    events: u32,
+    /// `tdata1` and `tdata2` of each trigger.
+    triggers: [[u64; 2]; TRIGGERS],
+    /// The value each write replaced, oldest first, while journaling.
+    undo: Option<Vec<(CsrSlot, u64)>>,
+    xlen: PhantomData<X>,
+}
+
+impl<X: Xlen> CsrFile<X> {
+    pub fn new() -> Self {
+        let mxl = if X::BITS == 64 { 2 } else { 1 };
+
+        let mut regs = vec![0; 4096];
+        regs[MISA as usize] = (mxl << (X::BITS - 2)) | MISA_EXTENSIONS as u64;
+        regs[MSTATUS as usize] = (MSTATUS_MPP | EXT_INITIAL << 13 | EXT_INITIAL << 9) as u64;
+        regs[VSSTATUS as usize] = (EXT_INITIAL << 13 | EXT_INITIAL << 9) as u64;
+        // vill, until the first vset.
+        regs[VTYPE as usize] = 1 << (X::BITS - 1);
+
+        Self {
+            regs,
+            counters: [0; 32],
+            events: 0,
+            triggers: [[Self::mcontrol(0), 0]; TRIGGERS],
+            undo: None,
+            xlen: PhantomData,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> X::Reg {
+        X::truncate(self.read_u64(addr))
+    }
+
+    /// Write as the guest would through a CSR instruction, honoring the
+    /// read-only bits of each register.
+    pub fn write(&mut self, addr: u16, value: X::Reg) {
+        self.write_u64(addr, X::widen(value));
+    }
+
+    /// Write without any masking, for use by the emulator itself.
+    pub fn set(&mut self, addr: u16, value: X::Reg) {
+        self.set_u64(addr, X::widen(value));
+    }
+
+    // The emulator works on widened values internally.
+
+    pub(crate) fn read_u64(&self, addr: u16) -> u64 {
+        let fcsr = self.regs[FCSR as usize];
+        match (addr, Self::counter(addr)) {
+            (FFLAGS, _) => fcsr & 0x1F,
+            (FRM, _) => fcsr >> 5 & 0x7,
+            (MSTATUS, _) => self.read_mstatus(),
+            (SSTATUS, _) => self.read_mstatus() & self.sstatus_view(),
+            (VSSTATUS, _) => self.read_vsstatus(),
+            (HSTATUS, _) if X::BITS == 64 => self.regs[HSTATUS as usize] | 2 << 32,
+            (MIDELEG, _) => self.regs[MIDELEG as usize] | self.hypervisor_interrupts(),
+            (SIE, _) => self.regs[MIE as usize] & self.regs[MIDELEG as usize],
+            (SIP, _) => self.regs[MIP as usize] & self.regs[MIDELEG as usize],
+            (HIE, _) => self.regs[MIE as usize] & VS_INTERRUPTS as u64,
+            (HIP | HVIP, _) => self.regs[MIP as usize] & VS_INTERRUPTS as u64,
+            (VSIE, _) => (self.regs[MIE as usize] & self.regs[HIDELEG as usize]) >> 1,
+            (VSIP, _) => (self.regs[MIP as usize] & self.regs[HIDELEG as usize]) >> 1,
+            (TDATA1, _) => self.triggers[self.selected()][0],
+            (TDATA2, _) => self.triggers[self.selected()][1],
+            (TINFO, _) => 1 << TRIGGER_MCONTROL | 1 << TRIGGER_DISABLED,
+            (_, Some((n, true))) => self.counters[n] >> 32,
+            (_, Some((n, false))) => self.counters[n] & X::MASK,
+            (_, None) => self.regs[(addr & 0xFFF) as usize],
+        }
+    }
+
+    pub(crate) fn write_u64(&mut self, addr: u16, value: u64) {
+        // Writes through a view only reach the bits it shows. Of `sip`,
+        // `hip` and `vsip`, only the software interrupt is writable. The VS
+        // views are shifted down a bit from where `mie`/`mip` have them.
+        let mideleg = self.regs[MIDELEG as usize];
+        let hideleg = self.regs[HIDELEG as usize];
+        let view = match addr {
+            SSTATUS => Some((MSTATUS, SSTATUS_FIELDS as u64, 0)),
+            SIE => Some((MIE, mideleg, 0)),
+            SIP => Some((MIP, mideleg & MIP_SSIP as u64, 0)),
+            HIE => Some((MIE, VS_INTERRUPTS as u64, 0)),
+            HVIP => Some((MIP, VS_INTERRUPTS as u64, 0)),
+            HIP => Some((MIP, MIP_VSSIP as u64, 0)),
+            VSIE => Some((MIE, hideleg, 1)),
+            VSIP => Some((MIP, hideleg & MIP_VSSIP as u64, 1)),
+            _ => None,
+        };
+        if let Some((target, mask, shift)) = view {
+            let merged = (self.read_u64(target) & !mask) | ((value << shift) & mask);
+            return self.write_u64(target, merged);
+        }
+
+        let mask = match addr {
+            MSTATUS => self.mstatus_fields(),
+            MIE => (MIE_WRITE_MASK & self.interrupts()) as u64,
+            MIP => (MIP_WRITE_MASK & self.interrupts()) as u64,
+            MEDELEG if self.hypervisor_interrupts() != 0 => {
+                (MEDELEG_WRITE_MASK | MEDELEG_HYPERVISOR) as u64
+            }
+            MEDELEG => MEDELEG_WRITE_MASK as u64,
+            MIDELEG => SUPERVISOR_INTERRUPTS as u64,
+            MSTATUSH if self.hypervisor_interrupts() != 0 => (MSTATUSH_GVA | MSTATUSH_MPV) as u64,
+            VSSTATUS => SSTATUS_FIELDS as u64 & self.mstatus_fields(),
+            HSTATUS => HSTATUS_WRITE_MASK as u64,
+            HEDELEG => HEDELEG_WRITE_MASK as u64,
+            HIDELEG => VS_INTERRUPTS as u64,
+            HGATP if X::BITS == 32 => HGATP_WRITE_MASK as u64,
+            FFLAGS => 0x1F,
+            FRM => 0x7,
+            FCSR => 0xFF,
+            MCOUNTEREN | SCOUNTEREN | HCOUNTEREN => MCOUNTEREN_WRITE_MASK as u64,
+            MENVCFG | SENVCFG => ENVCFG_FIOM as u64,
+            MISA | MSTATUSH | MHARTID | HGEIE | TDATA3 | TINFO => 0,
+            MENVCFGH | MCOUNTINHIBIT | PMPCFG0..=PMPADDR63 => 0,
+            _ => X::MASK,
+        };
+
+        let a = (addr & 0xFFF) as usize;
+        let old = self.regs[a];
+        let value = (old & !mask) | (value & mask);
+        let mpp = MSTATUS_MPP as u64;
+
+        match addr {
+            // MPP is WARL: the reserved encoding, or a mode `misa` doesn't
+            // have, leaves the old mode in place.
+            MSTATUS if !self.has_mode((value >> 11) as u32) => {
+                self.log(CsrSlot::Reg(a));
+                self.regs[a] = (value & !mpp) | (old & mpp);
+            }
+            // Only Sv32 is implemented, so RV64 can't leave bare mode.
+            SATP | VSATP | HGATP if X::BITS == 64 && value >> 60 != 0 => {}
+            // Reading back something else is how a debugger finds out how
+            // many triggers there are.
+            TSELECT if value as usize >= TRIGGERS => {}
+            // Anything but an mcontrol trigger turns it off.
+            TDATA1 if value >> (X::BITS - 4) != TRIGGER_MCONTROL => {
+                self.set_u64(addr, TRIGGER_DISABLED << (X::BITS - 4));
+            }
+            TDATA1 => self.set_u64(addr, Self::mcontrol(value & MCONTROL_WRITE_MASK as u64)),
+            MHPMEVENT3..=MHPMEVENT31 if HpmEvent::from_code(value).is_none() => {
+                self.set_u64(addr, 0);
+            }
+            _ => self.set_u64(addr, value),
+        }
+    }
+
+    /// The `mip`/`mie` bits that exist: the supervisor ones need S-mode,
+    /// the VS ones H.
+    fn interrupts(&self) -> u32 {
+        let mut interrupts = u32::MAX;
+        if self.regs[MISA as usize] as u32 & MISA_S == 0 {
+            interrupts &= !SUPERVISOR_INTERRUPTS;
+        }
+        if self.hypervisor_interrupts() == 0 {
+            interrupts &= !(VS_INTERRUPTS | MIP_SGEIP);
+        }
+        interrupts
+    }
+
+    /// The `mideleg` bits that are read-only one: with H, the VS and guest
+    /// external interrupts always go to HS-mode at least.
+    fn hypervisor_interrupts(&self) -> u64 {
+        match self.regs[MISA as usize] as u32 & MISA_H {
+            0 => 0,
+            _ => (VS_INTERRUPTS | MIP_SGEIP) as u64,
+        }
+    }
+
+    /// The `mstatus` fields that exist with the extensions in `misa`. The
+    /// rest read as zero.
+    fn mstatus_fields(&self) -> u64 {
+        let misa = self.regs[MISA as usize] as u32;
+        let mut fields = MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP;
+        if misa & MISA_S != 0 {
+            fields |= MSTATUS_SIE
+                | MSTATUS_SPIE
+                | MSTATUS_SPP
+                | MSTATUS_SUM
+                | MSTATUS_MXR
+                | MSTATUS_TVM
+                | MSTATUS_TSR;
+        }
+        if misa & MISA_U != 0 {
+            fields |= MSTATUS_MPRV | MSTATUS_TW;
+        }
+        if misa & MISA_F != 0 {
+            fields |= MSTATUS_FS;
+        }
+        if misa & MISA_V != 0 {
+            fields |= MSTATUS_VS;
+        }
+        let mut fields = fields as u64;
+        if X::BITS == 64 && misa & MISA_H != 0 {
+            fields |= ((MSTATUSH_GVA | MSTATUSH_MPV) as u64) << 32;
+        }
+        fields
+    }
+
+    /// What `sstatus` shows of `mstatus`: SD, UXL on RV64, and the fields
+    /// S-mode owns.
+    fn sstatus_view(&self) -> u64 {
+        let uxl = if X::BITS == 64 { 0b11 << 32 } else { 0 };
+        SSTATUS_FIELDS as u64 | 1 << (X::BITS - 1) | uxl
+    }
+
+    /// `mstatus` with the read-only summary bits filled in: SD when FS or VS
+    /// is Dirty, and on RV64 UXL and SXL, which are fixed at 64 bits.
+    fn read_mstatus(&self) -> u64 {
+        let value = self.regs[MSTATUS as usize] & self.mstatus_fields();
+        let mut summary = Self::dirty_summary(value);
+        if X::BITS == 64 {
+            let misa = self.regs[MISA as usize] as u32;
+            if misa & MISA_U != 0 {
+                summary |= 2 << 32;
+            }
+            if misa & MISA_S != 0 {
+                summary |= 2 << 34;
+            }
+        }
+        value | summary
+    }
+
+    /// `vsstatus`, laid out like `sstatus`, with SD and UXL filled in.
+    fn read_vsstatus(&self) -> u64 {
+        let value = self.regs[VSSTATUS as usize] & SSTATUS_FIELDS as u64 & self.mstatus_fields();
+        let uxl = if X::BITS == 64 { 2 << 32 } else { 0 };
+        value | Self::dirty_summary(value) | uxl
+    }
+
+    /// SD, if FS or VS in `status` is Dirty.
+    fn dirty_summary(status: u64) -> u64 {
+        let dirty = |field: u32| status & field as u64 == field as u64;
+        match dirty(MSTATUS_FS) || dirty(MSTATUS_VS) {
+            true => 1 << (X::BITS - 1),
+            false => 0,
+        }
+    }
+
+    /// The upper half of `mstatus`, where GVA and MPV are: `mstatush` on
+    /// RV32.
+    pub(crate) fn mstatush(&self) -> u32 {
+        match X::BITS {
+            64 => (self.read_mstatus() >> 32) as u32,
+            _ => self.regs[MSTATUSH as usize] as u32,
+        }
+    }
+
+    /// Set or clear one of the `MSTATUSH_*` bits.
+    pub(crate) fn set_mstatush(&mut self, field: u32, on: bool) {
+        let (addr, field) = match X::BITS {
+            64 => (MSTATUS, (field as u64) << 32),
+            _ => (MSTATUSH, field as u64),
+        };
+        self.log(CsrSlot::Reg(addr as usize));
+        let reg = &mut self.regs[addr as usize];
+        *reg = if on { *reg | field } else { *reg & !field };
+    }
+
+    /// `htimedelta`, with `htimedeltah` on top on RV32.
+    pub(crate) fn time_delta(&self) -> u64 {
+        let low = self.regs[HTIMEDELTA as usize];
+        match X::BITS {
+            64 => low,
+            _ => self.regs[HTIMEDELTAH as usize] << 32 | low,
+        }
+    }
+
+    /// Mark the floating-point (`MSTATUS_FS`) or vector (`MSTATUS_VS`) state
+    /// in `status`, `mstatus` or `vsstatus`, Dirty.
+    pub(crate) fn set_dirty(&mut self, status: u16, field: u32) {
+        self.log(CsrSlot::Reg(status as usize));
+        self.regs[status as usize] |= field as u64;
+    }
+
+    /// The FS or VS state in `status`, as one of the `EXT_*` values.
+    pub(crate) fn ext_state(&self, status: u16, field: u32) -> u32 {
+        (self.read_u64(status) as u32 & field) >> field.trailing_zeros()
+    }
+
+    /// Whether `bits` name a privilege level `misa` says is implemented.
+    fn has_mode(&self, bits: u32) -> bool {
+        let misa = self.regs[MISA as usize] as u32;
+        match Privilege::from_bits(bits) {
+            Some(Privilege::Machine) => true,
+            Some(Privilege::Supervisor) => misa & MISA_S != 0,
+            Some(Privilege::User) => misa & MISA_U != 0,
+            None => false,
+        }
+    }
+
+    pub(crate) fn set_u64(&mut self, addr: u16, value: u64) {
+        self.log(self.slot(addr));
+        let fcsr = &mut self.regs[FCSR as usize];
+        match (addr, Self::counter(addr)) {
+            (FFLAGS, _) => *fcsr = (*fcsr & !0x1F) | (value & 0x1F),
+            (FRM, _) => *fcsr = (*fcsr & !0xE0) | (value & 0x7) << 5,
+            (_, Some((n, true))) => {
+                self.counters[n] = (self.counters[n] & 0xFFFF_FFFF) | (value << 32);
+            }
+            (_, Some((n, false))) => {
+                self.counters[n] = (self.counters[n] & !X::MASK) | (value & X::MASK);
+            }
+            (TDATA1, _) => self.triggers[self.selected()][0] = value & X::MASK,
+            (TDATA2, _) => self.triggers[self.selected()][1] = value & X::MASK,
+            (_, None) => self.regs[(addr & 0xFFF) as usize] = value & X::MASK,
+        }
+
+        if (MHPMEVENT3..=MHPMEVENT31).contains(&addr) {
+            self.update_events();
+        }
+    }
+
+    fn update_events(&mut self) {
+        self.events = self
+            .selected_events()
+            .fold(0, |events, code| events | 1 << code);
+    }
+
+    /// Where [`set_u64`](Self::set_u64) puts `addr`'s value.
+    fn slot(&self, addr: u16) -> CsrSlot {
+        match (addr, Self::counter(addr)) {
+            (FFLAGS | FRM, _) => CsrSlot::Reg(FCSR as usize),
+            (_, Some((n, _))) => CsrSlot::Counter(n),
+            (TDATA1, _) => CsrSlot::Trigger(self.selected(), 0),
+            (TDATA2, _) => CsrSlot::Trigger(self.selected(), 1),
+            (_, None) => CsrSlot::Reg((addr & 0xFFF) as usize),
+        }
+    }
+
+    fn slot_mut(&mut self, slot: CsrSlot) -> &mut u64 {
+        match slot {
+            CsrSlot::Reg(a) => &mut self.regs[a],
+            CsrSlot::Counter(n) => &mut self.counters[n],
+            CsrSlot::Trigger(n, i) => &mut self.triggers[n][i],
+        }
+    }
+
+    /// Note what `slot` holds before it's written, if journaling.
+    fn log(&mut self, slot: CsrSlot) {
+        if self.undo.is_none() {
+            return;
+        }
+        let old = *self.slot_mut(slot);
+        if let Some(undo) = &mut self.undo {
+            undo.push((slot, old));
+        }
+    }
+
+    /// Start or stop noting the value every write replaces.
+    pub(crate) fn set_journaling(&mut self, on: bool) {
+        self.undo = on.then(Vec::new);
+    }
+
+    /// The writes noted since last asked, oldest first.
+    pub(crate) fn take_journal(&mut self) -> Vec<(CsrSlot, u64)> {
+        self.undo.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    /// Put back what `writes` replaced, newest first.
+    pub(crate) fn undo(&mut self, writes: &[(CsrSlot, u64)]) {
+        for &(slot, old) in writes.iter().rev() {
+            *self.slot_mut(slot) = old;
+        }
+        self.update_events();
+    }
+
+    /// An mcontrol `tdata1` with `fields`. NAPOT ranges can cover up to
+    /// 2^31 bytes.
+    fn mcontrol(fields: u64) -> u64 {
+        TRIGGER_MCONTROL << (X::BITS - 4) | 31 << (X::BITS - 11) | fields
+    }
+
+    /// The trigger `tselect` picks. Only [`set`](Self::set) can put an
+    /// out-of-range value there.
+    fn selected(&self) -> usize {
+        (self.regs[TSELECT as usize] as usize).min(TRIGGERS - 1)
+    }
+
+    /// `tdata1` and `tdata2` of every trigger.
+    pub(crate) fn triggers(&self) -> &[[u64; 2]; TRIGGERS] {
+        &self.triggers
+    }
+
+    /// Set the hit bit of trigger `n`.
+    pub(crate) fn trigger_hit(&mut self, n: usize) {
+        self.log(CsrSlot::Trigger(n, 0));
+        self.triggers[n][0] |= MCONTROL_HIT as u64;
+    }
+
+    /// Accrue floating-point exception flags.
+    pub(crate) fn raise_fflags(&mut self, flags: u32) {
+        self.log(CsrSlot::Reg(FCSR as usize));
+        self.regs[FCSR as usize] |= flags as u64;
+    }
+
+    /// Count `instructions` retired, one cycle each.
+    pub(crate) fn retire(&mut self, instructions: u64) {
+        self.log(CsrSlot::Counter(0));
+        self.log(CsrSlot::Counter(2));
+        self.counters[0] = self.counters[0].wrapping_add(instructions);
+        self.counters[2] = self.counters[2].wrapping_add(instructions);
+    }
+
+    /// Count `cycles` more on `mcycle`, for an instruction that takes longer
+    /// than one.
+    pub(crate) fn add_cycles(&mut self, cycles: u64) {
+        self.log(CsrSlot::Counter(0));
+        self.counters[0] = self.counters[0].wrapping_add(cycles);
+    }
+
+    /// Whether any `mhpmevent` is selecting `event`.
+    pub(crate) fn counts(&self, event: HpmEvent) -> bool {
+        self.events & 1 << event as u32 != 0
+    }
+
+    /// Bump every programmable counter that's selecting `event`.
+    pub(crate) fn count(&mut self, event: HpmEvent) {
+        if !self.counts(event) {
+            return;
+        }
+        for n in 3..32 {
+            if self.regs[MHPMEVENT3 as usize + n - 3] == event as u64 {
+                self.log(CsrSlot::Counter(n));
+                self.counters[n] = self.counters[n].wrapping_add(1);
+            }
+        }
+    }
+
+    fn selected_events(&self) -> impl Iterator<Item = u64> + '_ {
+        self.regs[MHPMEVENT3 as usize..=MHPMEVENT31 as usize]
+            .iter()
+            .copied()
+            .filter(|&code| HpmEvent::from_code(code).is_some())
+    }
+
+    /// Which counter `addr` names, and whether it's the RV32 upper half.
+    /// Index 1 is `time`, which isn't kept here.
+    fn counter(addr: u16) -> Option<(usize, bool)> {
+        let high = match addr & !0x1F {
+            MCYCLE | CYCLE => false,
+            MCYCLEH | CYCLEH if X::BITS == 32 => true,
+            _ => return None,
+        };
+        let n = (addr & 0x1F) as usize;
+        (n != 1).then_some((n, high))
+    }
+}
+
+impl<X: Xlen> Default for CsrFile<X> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
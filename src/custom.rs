@@ -0,0 +1,129 @@
+//! Hooks for modelling instructions in the custom-0 to custom-3 major
+//! opcodes, e.g. a prototype accelerator, without changing the decoder.
+
+use crate::mmu::Access;
+use crate::trap::Exception;
+use crate::xlen::Xlen;
+use crate::{MemSize, RiscvCpu};
+
+/// The four major opcodes the base ISA leaves to custom extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomOpcode {
+    Custom0,
+    Custom1,
+    Custom2,
+    Custom3,
+}
+
+impl CustomOpcode {
+    pub const ALL: [CustomOpcode; 4] = [
+        CustomOpcode::Custom0,
+        CustomOpcode::Custom1,
+        CustomOpcode::Custom2,
+        CustomOpcode::Custom3,
+    ];
+
+    /// The low seven bits of instructions in this space.
+    pub fn opcode(self) -> u32 {
+        match self {
+            CustomOpcode::Custom0 => 0x0B,
+            CustomOpcode::Custom1 => 0x2B,
+            CustomOpcode::Custom2 => 0x5B,
+            CustomOpcode::Custom3 => 0x7B,
+        }
+    }
+
+    /// The custom space `instruction` belongs to, if any.
+    pub fn of(instruction: u32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|space| space.opcode() == instruction & 0x7F)
+    }
+}
+
+/// What a handler sees of the hart executing its instruction. Registers are
+/// widened to 64 bits whatever the XLEN.
+pub trait Hart {
+    fn xlen(&self) -> u32;
+
+    fn reg(&self, n: u8) -> u64;
+
+    /// Writes to x0 are ignored, and RV32 keeps the low 32 bits.
+    fn set_reg(&mut self, n: u8, value: u64);
+
+    /// The address of the instruction being executed.
+    fn pc(&self) -> u64;
+
+    /// Continue at `target` rather than the next instruction.
+    fn jump(&mut self, target: u64);
+
+    /// Read memory at a virtual address, as a guest load would.
+    fn load(&mut self, vaddr: u64, size: MemSize) -> Result<u32, Exception>;
+
+    fn store(&mut self, vaddr: u64, size: MemSize, value: u32) -> Result<(), Exception>;
+
+    fn csr(&self, csr: u16) -> u64;
+
+    fn set_csr(&mut self, csr: u16, value: u64);
+}
+
+/// Executes the instructions of one custom opcode space. Closures taking
+/// `(&mut dyn Hart, u32)` implement it.
+pub trait CustomHandler {
+    /// Run `instruction`. `Ok(false)` means it isn't an encoding the
+    /// handler knows, and raises an illegal-instruction exception.
+    fn execute(&mut self, hart: &mut dyn Hart, instruction: u32) -> Result<bool, Exception>;
+}
+
+impl<F> CustomHandler for F
+where
+    F: FnMut(&mut dyn Hart, u32) -> Result<bool, Exception>,
+{
+    fn execute(&mut self, hart: &mut dyn Hart, instruction: u32) -> Result<bool, Exception> {
+        self(hart, instruction)
+    }
+}
+
+/// A hart partway through an instruction, for handlers to work on.
+pub(crate) struct HartView<'a, X: Xlen> {
+    pub(crate) cpu: &'a mut RiscvCpu<X>,
+    pub(crate) next_pc: &'a mut X::Reg,
+}
+
+impl<X: Xlen> Hart for HartView<'_, X> {
+    fn xlen(&self) -> u32 {
+        X::BITS
+    }
+
+    fn reg(&self, n: u8) -> u64 {
+        self.cpu.read_reg(n)
+    }
+
+    fn set_reg(&mut self, n: u8, value: u64) {
+        self.cpu.write_reg(n, value);
+    }
+
+    fn pc(&self) -> u64 {
+        X::widen(self.cpu.pc)
+    }
+
+    fn jump(&mut self, target: u64) {
+        *self.next_pc = X::truncate(target);
+    }
+
+    fn load(&mut self, vaddr: u64, size: MemSize) -> Result<u32, Exception> {
+        self.cpu.read_virt(vaddr, size, Access::Load)
+    }
+
+    fn store(&mut self, vaddr: u64, size: MemSize, value: u32) -> Result<(), Exception> {
+        self.cpu.write_virt(vaddr, size, value)
+    }
+
+    fn csr(&self, csr: u16) -> u64 {
+        self.cpu.csrs.read_u64(csr)
+    }
+
+    fn set_csr(&mut self, csr: u16, value: u64) {
+        self.cpu.csrs.write_u64(csr, value);
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeSet;
+
+/// Which kind of data access a watchpoint fires on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: WatchKind) -> bool {
+        self == WatchKind::ReadWrite || self == access
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u32,
+    pub len: u32,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn overlaps(&self, addr: u32, len: u32) -> bool {
+        let (start, end) = (self.addr as u64, self.addr as u64 + self.len as u64);
+        let (a_start, a_end) = (addr as u64, addr as u64 + len as u64);
+        a_start < end && start < a_end
+    }
+}
+
+/// A data access that tripped a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// PC of the instruction that made the access.
+    pub pc: u32,
+    pub addr: u32,
+    /// `Read` or `Write`, never `ReadWrite`.
+    pub kind: WatchKind,
+}
+
+/// An instruction writing a watched register. Registers are widened to 64
+/// bits whatever the XLEN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterWrite {
+    /// PC of the instruction that wrote it.
+    pub pc: u32,
+    pub reg: u8,
+    pub old: u64,
+    pub new: u64,
+}
+
+/// Breakpoint and watchpoint state owned by the CPU.
+#[derive(Debug, Default)]
+pub(crate) struct Debugger {
+    pub(crate) breakpoints: BTreeSet<u32>,
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    /// Watched integer registers, as `1 << n`.
+    pub(crate) watched_regs: u32,
+    /// Set after stopping on a breakpoint so the next step executes the
+    /// instruction instead of stopping on it again.
+    pub(crate) resume_from: Option<u32>,
+    pub(crate) hit: Option<WatchHit>,
+    pub(crate) reg_hit: Option<RegisterWrite>,
+}
+
+impl Debugger {
+    /// Whether execution should stop before running the instruction at `pc`.
+    pub(crate) fn should_break(&mut self, pc: u32) -> bool {
+        let resuming = self.resume_from.take() == Some(pc);
+
+        if !resuming && self.breakpoints.contains(&pc) {
+            self.resume_from = Some(pc);
+            return true;
+        }
+
+        false
+    }
+
+    pub(crate) fn check_access(&mut self, pc: u32, addr: u32, len: u32, kind: WatchKind) {
+        if self.hit.is_some() {
+            return;
+        }
+
+        let tripped = self
+            .watchpoints
+            .iter()
+            .any(|w| w.kind.matches(kind) && w.overlaps(addr, len));
+
+        if tripped {
+            self.hit = Some(WatchHit { pc, addr, kind });
+        }
+    }
+
+    pub(crate) fn watches_reg(&self, reg: u8) -> bool {
+        self.watched_regs >> reg & 1 != 0
+    }
+
+    pub(crate) fn check_reg_write(&mut self, write: RegisterWrite) {
+        if self.reg_hit.is_none() && self.watches_reg(write.reg) {
+            self.reg_hit = Some(write);
+        }
+    }
+}
@@ -0,0 +1,1569 @@
+use std::fmt;
+
+use crate::custom::CustomOpcode;
+
+/// A decoded RV32I/RV64I/Zicsr/Zalrsc/Zawrs/Zba/Zbkb/Zkne/Zknd/Zknh/F/D/V
+/// instruction. Register fields are register numbers (0-31) and immediates
+/// are already sign-extended.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Lui { rd: u8, imm: u32 },
+    Auipc { rd: u8, imm: u32 },
+
+    Jal { rd: u8, imm: i32 },
+    Jalr { rd: u8, rs1: u8, imm: i32 },
+
+    Beq { rs1: u8, rs2: u8, imm: i32 },
+    Bne { rs1: u8, rs2: u8, imm: i32 },
+    Blt { rs1: u8, rs2: u8, imm: i32 },
+    Bge { rs1: u8, rs2: u8, imm: i32 },
+    Bltu { rs1: u8, rs2: u8, imm: i32 },
+    Bgeu { rs1: u8, rs2: u8, imm: i32 },
+
+    Lb { rd: u8, rs1: u8, imm: i32 },
+    Lh { rd: u8, rs1: u8, imm: i32 },
+    Lw { rd: u8, rs1: u8, imm: i32 },
+    Lbu { rd: u8, rs1: u8, imm: i32 },
+    Lhu { rd: u8, rs1: u8, imm: i32 },
+    Lwu { rd: u8, rs1: u8, imm: i32 },
+    Ld { rd: u8, rs1: u8, imm: i32 },
+
+    Sb { rs1: u8, rs2: u8, imm: i32 },
+    Sh { rs1: u8, rs2: u8, imm: i32 },
+    Sw { rs1: u8, rs2: u8, imm: i32 },
+    Sd { rs1: u8, rs2: u8, imm: i32 },
+
+    Addi { rd: u8, rs1: u8, imm: i32 },
+    Slti { rd: u8, rs1: u8, imm: i32 },
+    Sltiu { rd: u8, rs1: u8, imm: i32 },
+    Xori { rd: u8, rs1: u8, imm: i32 },
+    Ori { rd: u8, rs1: u8, imm: i32 },
+    Andi { rd: u8, rs1: u8, imm: i32 },
+    Slli { rd: u8, rs1: u8, shamt: u8 },
+    Srli { rd: u8, rs1: u8, shamt: u8 },
+    Srai { rd: u8, rs1: u8, shamt: u8 },
+
+    Add { rd: u8, rs1: u8, rs2: u8 },
+    Sub { rd: u8, rs1: u8, rs2: u8 },
+    Sll { rd: u8, rs1: u8, rs2: u8 },
+    Slt { rd: u8, rs1: u8, rs2: u8 },
+    Sltu { rd: u8, rs1: u8, rs2: u8 },
+    Xor { rd: u8, rs1: u8, rs2: u8 },
+    Srl { rd: u8, rs1: u8, rs2: u8 },
+    Sra { rd: u8, rs1: u8, rs2: u8 },
+    Or { rd: u8, rs1: u8, rs2: u8 },
+    And { rd: u8, rs1: u8, rs2: u8 },
+
+    // RV64-only operations on the low 32 bits, sign-extending the result.
+    Addiw { rd: u8, rs1: u8, imm: i32 },
+    Slliw { rd: u8, rs1: u8, shamt: u8 },
+    Srliw { rd: u8, rs1: u8, shamt: u8 },
+    Sraiw { rd: u8, rs1: u8, shamt: u8 },
+    Addw { rd: u8, rs1: u8, rs2: u8 },
+    Subw { rd: u8, rs1: u8, rs2: u8 },
+    Sllw { rd: u8, rs1: u8, rs2: u8 },
+    Srlw { rd: u8, rs1: u8, rs2: u8 },
+    Sraw { rd: u8, rs1: u8, rs2: u8 },
+
+    // Zba: rs1 shifted left by 1-3 plus rs2, for indexing arrays. The
+    // `.uw` forms are RV64-only and zero-extend the low word of rs1 first.
+    Sh1add { rd: u8, rs1: u8, rs2: u8 },
+    Sh2add { rd: u8, rs1: u8, rs2: u8 },
+    Sh3add { rd: u8, rs1: u8, rs2: u8 },
+    AddUw { rd: u8, rs1: u8, rs2: u8 },
+    Sh1addUw { rd: u8, rs1: u8, rs2: u8 },
+    Sh2addUw { rd: u8, rs1: u8, rs2: u8 },
+    Sh3addUw { rd: u8, rs1: u8, rs2: u8 },
+    SlliUw { rd: u8, rs1: u8, shamt: u8 },
+
+    // Zbkb: packing halves or bytes of two registers, and permutations of
+    // one. `packw` is RV64-only, `zip` and `unzip` RV32-only.
+    Pack { rd: u8, rs1: u8, rs2: u8 },
+    Packh { rd: u8, rs1: u8, rs2: u8 },
+    Packw { rd: u8, rs1: u8, rs2: u8 },
+    Brev8 { rd: u8, rs1: u8 },
+    Zip { rd: u8, rs1: u8 },
+    Unzip { rd: u8, rs1: u8 },
+
+    // Zalrsc. The aq/rl ordering bits don't matter to a sequential
+    // emulator, so they aren't kept.
+    LrW { rd: u8, rs1: u8 },
+    ScW { rd: u8, rs1: u8, rs2: u8 },
+    LrD { rd: u8, rs1: u8 },
+    ScD { rd: u8, rs1: u8, rs2: u8 },
+
+    // F and D, or Zfinx/Zdinx.
+    Float(FloatInstruction),
+    // The subset of V that's implemented.
+    Vector(VectorInstruction),
+
+    // Scalar cryptography.
+    Crypto(CryptoInstruction),
+
+    // H: loads and stores as the guest, and the guest TLB fences.
+    Hypervisor(HypervisorInstruction),
+
+    // Anything in the custom-0..3 opcode spaces, raw, for a registered
+    // handler to make sense of.
+    Custom(u32),
+
+    Fence,
+    FenceI,
+
+    Ecall,
+    Ebreak,
+    Mret,
+    Sret,
+    Wfi,
+    // Zawrs: stall while the LR reservation holds, STO only briefly.
+    WrsNto,
+    WrsSto,
+    SfenceVma { rs1: u8, rs2: u8 },
+
+    Csrrw { rd: u8, rs1: u8, csr: u16 },
+    Csrrs { rd: u8, rs1: u8, csr: u16 },
+    Csrrc { rd: u8, rs1: u8, csr: u16 },
+    Csrrwi { rd: u8, uimm: u8, csr: u16 },
+    Csrrsi { rd: u8, uimm: u8, csr: u16 },
+    Csrrci { rd: u8, uimm: u8, csr: u16 },
+}
+
+/// An F/D instruction. Floating-point operands name `f` registers, or `x`
+/// registers under Zfinx/Zdinx. `rm` is the raw rounding-mode field, with 7
+/// meaning whatever `frm` says.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FloatInstruction {
+    Flw {
+        rd: u8,
+        rs1: u8,
+        imm: i32,
+    },
+    Fsw {
+        rs1: u8,
+        rs2: u8,
+        imm: i32,
+    },
+    Fld {
+        rd: u8,
+        rs1: u8,
+        imm: i32,
+    },
+    Fsd {
+        rs1: u8,
+        rs2: u8,
+        imm: i32,
+    },
+    Farith {
+        op: FpOp,
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+        rm: u8,
+    },
+    Fma {
+        op: FmaOp,
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+        rs3: u8,
+        rm: u8,
+    },
+    Fsqrt {
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+        rm: u8,
+    },
+    Fcmp {
+        op: FpCompare,
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+    },
+    Fclass {
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+    },
+    FcvtToInt {
+        fmt: FpFormat,
+        int: IntWidth,
+        rd: u8,
+        rs1: u8,
+        rm: u8,
+    },
+    FcvtFromInt {
+        fmt: FpFormat,
+        int: IntWidth,
+        rd: u8,
+        rs1: u8,
+        rm: u8,
+    },
+    /// FCVT.S.D or FCVT.D.S; `fmt` is the result's format.
+    FcvtFp {
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+        rm: u8,
+    },
+    FmvToInt {
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+    },
+    FmvFromInt {
+        fmt: FpFormat,
+        rd: u8,
+        rs1: u8,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FpFormat {
+    Single,
+    Double,
+}
+
+impl FpFormat {
+    fn suffix(self) -> &'static str {
+        match self {
+            FpFormat::Single => "s",
+            FpFormat::Double => "d",
+        }
+    }
+}
+
+/// Two-operand F/D operations. Only the first four round.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FpOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Min,
+    Max,
+    Sgnj,
+    Sgnjn,
+    Sgnjx,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FmaOp {
+    /// rs1 * rs2 + rs3
+    Madd,
+    /// rs1 * rs2 - rs3
+    Msub,
+    /// -(rs1 * rs2) + rs3
+    Nmsub,
+    /// -(rs1 * rs2) - rs3
+    Nmadd,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FpCompare {
+    Eq,
+    Lt,
+    Le,
+}
+
+/// The integer side of an FCVT. The 64-bit ones are RV64-only.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntWidth {
+    W,
+    Wu,
+    L,
+    Lu,
+}
+
+impl IntWidth {
+    fn name(self) -> &'static str {
+        match self {
+            IntWidth::W => "w",
+            IntWidth::Wu => "wu",
+            IntWidth::L => "l",
+            IntWidth::Lu => "lu",
+        }
+    }
+}
+
+/// A scalar cryptography instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CryptoInstruction {
+    /// RV32 only: pass byte `bs` of `rs2` through an AES round and XOR the
+    /// result, rotated back into that byte's place, into `rs1`.
+    Aes32 {
+        op: AesOp,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+        bs: u8,
+    },
+    /// A SHA-2 sigma or sum function of `rs1`.
+    Sha { op: ShaOp, rd: u8, rs1: u8 },
+    /// RV32 only: half of a SHA-512 function, with the 64-bit input split
+    /// across `rs1` and `rs2`.
+    Sha512Half {
+        op: Sha512HalfOp,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+    },
+}
+
+/// The SHA-2 functions. The SHA-512 ones are RV64 only.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShaOp {
+    Sha256Sig0,
+    Sha256Sig1,
+    Sha256Sum0,
+    Sha256Sum1,
+    Sha512Sig0,
+    Sha512Sig1,
+    Sha512Sum0,
+    Sha512Sum1,
+}
+
+/// RV32's SHA-512 halves. The `r` sums give the half of the result matching
+/// the input half in `rs1`; the `l` and `h` sigmas want the low or the high
+/// half in `rs1` and give that half of the result.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Sha512HalfOp {
+    Sum0r,
+    Sum1r,
+    Sig0l,
+    Sig0h,
+    Sig1l,
+    Sig1h,
+}
+
+/// An H-extension instruction. The loads and stores go through two-stage
+/// translation as VS- or VU-mode would, whichever `hstatus.SPVP` says.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HypervisorInstruction {
+    /// HLV, or with `execute` HLVX, which needs execute permission rather
+    /// than read. `bytes` is 1, 2, 4 or, on RV64, 8.
+    Load {
+        rd: u8,
+        rs1: u8,
+        bytes: u8,
+        signed: bool,
+        execute: bool,
+    },
+    /// HSV.
+    Store {
+        rs1: u8,
+        rs2: u8,
+        bytes: u8,
+    },
+    FenceVvma {
+        rs1: u8,
+        rs2: u8,
+    },
+    FenceGvma {
+        rs1: u8,
+        rs2: u8,
+    },
+}
+
+/// Which half of an AES round an `aes32*` instruction does: encrypt or
+/// decrypt, and whether MixColumns is applied (`m`) or not (the final round).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AesOp {
+    Esi,
+    Esmi,
+    Dsi,
+    Dsmi,
+}
+
+/// A V instruction. `masked` operations only touch elements whose bit is set
+/// in `v0`. `vtype` is the raw immediate of a `vsetvli`/`vsetivli`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VectorInstruction {
+    Vsetvli {
+        rd: u8,
+        rs1: u8,
+        vtype: u16,
+    },
+    Vsetivli {
+        rd: u8,
+        avl: u8,
+        vtype: u16,
+    },
+    Vsetvl {
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+    },
+    /// Unit-stride load of `eew`-bit elements.
+    Vload {
+        eew: u8,
+        vd: u8,
+        rs1: u8,
+        masked: bool,
+    },
+    Vstore {
+        eew: u8,
+        vs3: u8,
+        rs1: u8,
+        masked: bool,
+    },
+    /// `vd[i] = vs2[i] op src[i]`, or for the compares, mask bit i of `vd`.
+    Varith {
+        op: VectorOp,
+        vd: u8,
+        vs2: u8,
+        src: VectorOperand,
+        masked: bool,
+    },
+    /// `vd[0] = vs1[0] + the sum of vs2`.
+    Vredsum {
+        vd: u8,
+        vs2: u8,
+        vs1: u8,
+        masked: bool,
+    },
+    /// Element 0 of `vs2` to an integer register, sign-extended.
+    VmvXS {
+        rd: u8,
+        vs2: u8,
+    },
+    VmvSX {
+        vd: u8,
+        rs1: u8,
+    },
+}
+
+/// Integer vector operations. The `M` ones are compares writing a mask.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VectorOp {
+    Add,
+    Sub,
+    /// `src - vs2`.
+    Rsub,
+    Minu,
+    Min,
+    Maxu,
+    Max,
+    And,
+    Or,
+    Xor,
+    Sll,
+    Srl,
+    Sra,
+    Mul,
+    /// `vmerge` when masked, `vmv.v` when not: picks `src` where `v0` is set
+    /// and `vs2` elsewhere.
+    Merge,
+    Mseq,
+    Msne,
+    Msltu,
+    Mslt,
+    Msleu,
+    Msle,
+}
+
+impl VectorOp {
+    fn name(self) -> &'static str {
+        match self {
+            VectorOp::Add => "vadd",
+            VectorOp::Sub => "vsub",
+            VectorOp::Rsub => "vrsub",
+            VectorOp::Minu => "vminu",
+            VectorOp::Min => "vmin",
+            VectorOp::Maxu => "vmaxu",
+            VectorOp::Max => "vmax",
+            VectorOp::And => "vand",
+            VectorOp::Or => "vor",
+            VectorOp::Xor => "vxor",
+            VectorOp::Sll => "vsll",
+            VectorOp::Srl => "vsrl",
+            VectorOp::Sra => "vsra",
+            VectorOp::Mul => "vmul",
+            VectorOp::Merge => "vmerge",
+            VectorOp::Mseq => "vmseq",
+            VectorOp::Msne => "vmsne",
+            VectorOp::Msltu => "vmsltu",
+            VectorOp::Mslt => "vmslt",
+            VectorOp::Msleu => "vmsleu",
+            VectorOp::Msle => "vmsle",
+        }
+    }
+
+    pub fn is_compare(self) -> bool {
+        matches!(
+            self,
+            VectorOp::Mseq
+                | VectorOp::Msne
+                | VectorOp::Msltu
+                | VectorOp::Mslt
+                | VectorOp::Msleu
+                | VectorOp::Msle
+        )
+    }
+}
+
+/// The second source of a vector operation: `.vv`, `.vx` or `.vi`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VectorOperand {
+    Vector(u8),
+    Scalar(u8),
+    /// Sign-extended, except for shifts where it's an unsigned amount.
+    Imm(i8),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The opcode belongs to an extension this emulator doesn't implement.
+    UnknownOpcode(u32),
+    /// A known opcode with a funct3/funct7/immediate combination the spec
+    /// doesn't define.
+    IllegalInstruction(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(inst) => {
+                write!(f, "Unknown opcode {:#04x} in {:#010x}", inst & 0x7F, inst)
+            }
+            DecodeError::IllegalInstruction(inst) => {
+                write!(f, "Illegal instruction {:#010x}", inst)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+fn rd(instruction: u32) -> u8 {
+    ((instruction >> 7) & 0x1F) as u8
+}
+
+fn rs1(instruction: u32) -> u8 {
+    ((instruction >> 15) & 0x1F) as u8
+}
+
+fn rs2(instruction: u32) -> u8 {
+    ((instruction >> 20) & 0x1F) as u8
+}
+
+fn funct3(instruction: u32) -> u32 {
+    (instruction >> 12) & 0x7
+}
+
+fn funct7(instruction: u32) -> u32 {
+    (instruction >> 25) & 0x7F
+}
+
+fn i_imm(instruction: u32) -> i32 {
+    (instruction as i32) >> 20
+}
+
+fn s_imm(instruction: u32) -> i32 {
+    let i4_0 = (instruction >> 7) & 0x1F;
+    let i11_5 = (instruction >> 25) & 0x7F;
+
+    let imm_u: u32 = (i11_5 << 5) | i4_0;
+    ((imm_u << 20) as i32) >> 20
+}
+
+fn b_imm(instruction: u32) -> i32 {
+    let i12 = (instruction >> 31) & 0x1;
+    let i11 = (instruction >> 7) & 0x1;
+    let i10_5 = (instruction >> 25) & 0x3F;
+    let i4_1 = (instruction >> 8) & 0xF;
+
+    let imm_u32 = (i12 << 12) | (i11 << 11) | (i10_5 << 5) | (i4_1 << 1);
+    ((imm_u32 << 19) as i32) >> 19
+}
+
+fn j_imm(instruction: u32) -> i32 {
+    let i19_12 = (instruction >> 12) & 0xFF;
+    let i11 = (instruction >> 20) & 0x1;
+    let i10_1 = (instruction >> 21) & 0x3FF;
+    let i20 = (instruction >> 31) & 0x1;
+
+    let imm_u32 = (i20 << 20) | (i19_12 << 12) | (i11 << 11) | (i10_1 << 1);
+    ((imm_u32 << 11) as i32) >> 11
+}
+
+/// Decode a 32-bit instruction word without executing it.
+pub fn decode(instruction: u32) -> Result<Instruction, DecodeError> {
+    decode_with(instruction, false)
+}
+
+/// Like [`decode`], but for an RV64 hart: accepts the RV64I additions and
+/// 6-bit shift amounts.
+pub fn decode_rv64(instruction: u32) -> Result<Instruction, DecodeError> {
+    decode_with(instruction, true)
+}
+
+fn decode_with(instruction: u32, rv64: bool) -> Result<Instruction, DecodeError> {
+    use Instruction::*;
+
+    let illegal = DecodeError::IllegalInstruction(instruction);
+    let rd = rd(instruction);
+    let rs1 = rs1(instruction);
+    let rs2 = rs2(instruction);
+    let funct3 = funct3(instruction);
+    let funct7 = funct7(instruction);
+
+    let decoded = match instruction & 0x7F {
+        0x37 => Lui {
+            rd,
+            imm: instruction & 0xFFFF_F000,
+        },
+        0x17 => Auipc {
+            rd,
+            imm: instruction & 0xFFFF_F000,
+        },
+        0x6F => Jal {
+            rd,
+            imm: j_imm(instruction),
+        },
+        0x67 => match funct3 {
+            0x0 => Jalr {
+                rd,
+                rs1,
+                imm: i_imm(instruction),
+            },
+            _ => return Err(illegal),
+        },
+        0x63 => {
+            let imm = b_imm(instruction);
+            match funct3 {
+                0x0 => Beq { rs1, rs2, imm },
+                0x1 => Bne { rs1, rs2, imm },
+                0x4 => Blt { rs1, rs2, imm },
+                0x5 => Bge { rs1, rs2, imm },
+                0x6 => Bltu { rs1, rs2, imm },
+                0x7 => Bgeu { rs1, rs2, imm },
+                _ => return Err(illegal),
+            }
+        }
+        0x03 => {
+            let imm = i_imm(instruction);
+            match funct3 {
+                0x0 => Lb { rd, rs1, imm },
+                0x1 => Lh { rd, rs1, imm },
+                0x2 => Lw { rd, rs1, imm },
+                0x4 => Lbu { rd, rs1, imm },
+                0x5 => Lhu { rd, rs1, imm },
+                0x3 if rv64 => Ld { rd, rs1, imm },
+                0x6 if rv64 => Lwu { rd, rs1, imm },
+                _ => return Err(illegal),
+            }
+        }
+        0x23 => {
+            let imm = s_imm(instruction);
+            match funct3 {
+                0x0 => Sb { rs1, rs2, imm },
+                0x1 => Sh { rs1, rs2, imm },
+                0x2 => Sw { rs1, rs2, imm },
+                0x3 if rv64 => Sd { rs1, rs2, imm },
+                _ => return Err(illegal),
+            }
+        }
+        0x13 => {
+            let imm = i_imm(instruction);
+            // RV64 borrows the low bit of funct7 for a 6-bit shift amount.
+            let (shamt, funct7) = match rv64 {
+                true => (((instruction >> 20) & 0x3F) as u8, funct7 & !1),
+                false => (rs2, funct7),
+            };
+            match (funct3, funct7) {
+                (0x0, _) => Addi { rd, rs1, imm },
+                (0x2, _) => Slti { rd, rs1, imm },
+                (0x3, _) => Sltiu { rd, rs1, imm },
+                (0x4, _) => Xori { rd, rs1, imm },
+                (0x6, _) => Ori { rd, rs1, imm },
+                (0x7, _) => Andi { rd, rs1, imm },
+                (0x1, 0x00) => Slli { rd, rs1, shamt },
+                (0x5, 0x00) => Srli { rd, rs1, shamt },
+                (0x5, 0x20) => Srai { rd, rs1, shamt },
+                (0x5, _) if instruction >> 20 == 0x687 => Brev8 { rd, rs1 },
+                (0x1, _) if !rv64 && instruction >> 20 == 0x08F => Zip { rd, rs1 },
+                (0x5, _) if !rv64 && instruction >> 20 == 0x08F => Unzip { rd, rs1 },
+                (0x1, 0x08) if instruction >> 25 == 0x08 => {
+                    let op = match rs2 {
+                        0x0 => ShaOp::Sha256Sum0,
+                        0x1 => ShaOp::Sha256Sum1,
+                        0x2 => ShaOp::Sha256Sig0,
+                        0x3 => ShaOp::Sha256Sig1,
+                        0x4 if rv64 => ShaOp::Sha512Sum0,
+                        0x5 if rv64 => ShaOp::Sha512Sum1,
+                        0x6 if rv64 => ShaOp::Sha512Sig0,
+                        0x7 if rv64 => ShaOp::Sha512Sig1,
+                        _ => return Err(illegal),
+                    };
+                    Crypto(CryptoInstruction::Sha { op, rd, rs1 })
+                }
+                _ => return Err(illegal),
+            }
+        }
+        0x33 => match (funct3, funct7) {
+            (0x0, 0x00) => Add { rd, rs1, rs2 },
+            (0x0, 0x20) => Sub { rd, rs1, rs2 },
+            (0x1, 0x00) => Sll { rd, rs1, rs2 },
+            (0x2, 0x00) => Slt { rd, rs1, rs2 },
+            (0x3, 0x00) => Sltu { rd, rs1, rs2 },
+            (0x4, 0x00) => Xor { rd, rs1, rs2 },
+            (0x5, 0x00) => Srl { rd, rs1, rs2 },
+            (0x5, 0x20) => Sra { rd, rs1, rs2 },
+            (0x6, 0x00) => Or { rd, rs1, rs2 },
+            (0x7, 0x00) => And { rd, rs1, rs2 },
+            (0x2, 0x10) => Sh1add { rd, rs1, rs2 },
+            (0x4, 0x10) => Sh2add { rd, rs1, rs2 },
+            (0x6, 0x10) => Sh3add { rd, rs1, rs2 },
+            (0x4, 0x04) => Pack { rd, rs1, rs2 },
+            (0x7, 0x04) => Packh { rd, rs1, rs2 },
+            (0x0, _) if !rv64 && matches!(funct7 & 0x1F, 0x11 | 0x13 | 0x15 | 0x17) => {
+                let op = match funct7 & 0x1F {
+                    0x11 => AesOp::Esi,
+                    0x13 => AesOp::Esmi,
+                    0x15 => AesOp::Dsi,
+                    _ => AesOp::Dsmi,
+                };
+                Crypto(CryptoInstruction::Aes32 {
+                    op,
+                    rd,
+                    rs1,
+                    rs2,
+                    bs: (funct7 >> 5) as u8,
+                })
+            }
+            (0x0, 0x28..=0x2B | 0x2E | 0x2F) if !rv64 => {
+                let op = match funct7 {
+                    0x28 => Sha512HalfOp::Sum0r,
+                    0x29 => Sha512HalfOp::Sum1r,
+                    0x2A => Sha512HalfOp::Sig0l,
+                    0x2B => Sha512HalfOp::Sig1l,
+                    0x2E => Sha512HalfOp::Sig0h,
+                    _ => Sha512HalfOp::Sig1h,
+                };
+                Crypto(CryptoInstruction::Sha512Half { op, rd, rs1, rs2 })
+            }
+            _ => return Err(illegal),
+        },
+        0x1B if rv64 => {
+            let shamt = rs2;
+            match (funct3, funct7) {
+                (0x0, _) => Addiw {
+                    rd,
+                    rs1,
+                    imm: i_imm(instruction),
+                },
+                (0x1, 0x00) => Slliw { rd, rs1, shamt },
+                (0x5, 0x00) => Srliw { rd, rs1, shamt },
+                (0x5, 0x20) => Sraiw { rd, rs1, shamt },
+                (0x1, 0x04 | 0x05) => SlliUw {
+                    rd,
+                    rs1,
+                    shamt: ((instruction >> 20) & 0x3F) as u8,
+                },
+                _ => return Err(illegal),
+            }
+        }
+        0x3B if rv64 => match (funct3, funct7) {
+            (0x0, 0x00) => Addw { rd, rs1, rs2 },
+            (0x0, 0x20) => Subw { rd, rs1, rs2 },
+            (0x1, 0x00) => Sllw { rd, rs1, rs2 },
+            (0x5, 0x00) => Srlw { rd, rs1, rs2 },
+            (0x5, 0x20) => Sraw { rd, rs1, rs2 },
+            (0x0, 0x04) => AddUw { rd, rs1, rs2 },
+            (0x2, 0x10) => Sh1addUw { rd, rs1, rs2 },
+            (0x4, 0x10) => Sh2addUw { rd, rs1, rs2 },
+            (0x6, 0x10) => Sh3addUw { rd, rs1, rs2 },
+            (0x4, 0x04) => Packw { rd, rs1, rs2 },
+            _ => return Err(illegal),
+        },
+        0x2F => match (funct3, instruction >> 27) {
+            (0x2, 0b00010) if rs2 == 0 => LrW { rd, rs1 },
+            (0x2, 0b00011) => ScW { rd, rs1, rs2 },
+            (0x3, 0b00010) if rv64 && rs2 == 0 => LrD { rd, rs1 },
+            (0x3, 0b00011) if rv64 => ScD { rd, rs1, rs2 },
+            // AMOs aren't implemented.
+            _ => return Err(illegal),
+        },
+        0x07 | 0x27 if matches!(funct3, 0x0 | 0x5 | 0x6 | 0x7) => {
+            Vector(decode_vector(instruction)?)
+        }
+        0x57 => Vector(decode_vector(instruction)?),
+        0x07 | 0x27 | 0x43 | 0x47 | 0x4B | 0x4F | 0x53 => Float(decode_fp(instruction, rv64)?),
+        0x0F => match funct3 {
+            0x0 => Fence,
+            0x1 => FenceI,
+            _ => return Err(illegal),
+        },
+        0x73 => {
+            let csr = (instruction >> 20) as u16;
+            let uimm = rs1;
+            match funct3 {
+                0x0 if funct7 == 0x09 && rd == 0 => SfenceVma { rs1, rs2 },
+                0x0 if funct7 == 0x11 && rd == 0 => {
+                    Hypervisor(HypervisorInstruction::FenceVvma { rs1, rs2 })
+                }
+                0x0 if funct7 == 0x31 && rd == 0 => {
+                    Hypervisor(HypervisorInstruction::FenceGvma { rs1, rs2 })
+                }
+                0x0 => match (instruction >> 20, rs1, rd) {
+                    (0x000, 0, 0) => Ecall,
+                    (0x001, 0, 0) => Ebreak,
+                    (0x302, 0, 0) => Mret,
+                    (0x102, 0, 0) => Sret,
+                    (0x105, 0, 0) => Wfi,
+                    (0x00D, 0, 0) => WrsNto,
+                    (0x01D, 0, 0) => WrsSto,
+                    _ => return Err(illegal),
+                },
+                0x1 => Csrrw { rd, rs1, csr },
+                0x2 => Csrrs { rd, rs1, csr },
+                0x3 => Csrrc { rd, rs1, csr },
+                0x5 => Csrrwi { rd, uimm, csr },
+                0x6 => Csrrsi { rd, uimm, csr },
+                0x7 => Csrrci { rd, uimm, csr },
+                0x4 => Hypervisor(decode_hypervisor(instruction, rv64)?),
+                _ => return Err(illegal),
+            }
+        }
+        0x0B | 0x2B | 0x5B | 0x7B => Custom(instruction),
+        _ => return Err(DecodeError::UnknownOpcode(instruction)),
+    };
+
+    Ok(decoded)
+}
+
+/// HLV, HLVX and HSV: SYSTEM with funct3 4. funct7 picks the width and
+/// whether it's a store; for loads, `rs2` picks HLV (0), the unsigned HLV
+/// (1) or HLVX (3).
+fn decode_hypervisor(instruction: u32, rv64: bool) -> Result<HypervisorInstruction, DecodeError> {
+    let illegal = DecodeError::IllegalInstruction(instruction);
+    let (rd, rs1, rs2) = (rd(instruction), rs1(instruction), rs2(instruction));
+    let funct7 = funct7(instruction);
+
+    let bytes = match funct7 >> 1 {
+        0x18 => 1,
+        0x19 => 2,
+        0x1A => 4,
+        0x1B if rv64 => 8,
+        _ => return Err(illegal),
+    };
+
+    if funct7 & 1 == 1 {
+        if rd != 0 {
+            return Err(illegal);
+        }
+        return Ok(HypervisorInstruction::Store { rs1, rs2, bytes });
+    }
+
+    let (signed, execute) = match rs2 {
+        0 => (true, false),
+        // HLV.WU only exists on RV64.
+        1 if bytes < 4 || (bytes == 4 && rv64) => (false, false),
+        3 if bytes == 2 || bytes == 4 => (false, true),
+        _ => return Err(illegal),
+    };
+    Ok(HypervisorInstruction::Load {
+        rd,
+        rs1,
+        bytes,
+        signed,
+        execute,
+    })
+}
+
+/// The F and D opcodes: loads, stores, fused multiply-adds and OP-FP.
+fn decode_fp(instruction: u32, rv64: bool) -> Result<FloatInstruction, DecodeError> {
+    use FloatInstruction::*;
+
+    let illegal = DecodeError::IllegalInstruction(instruction);
+    let rd = rd(instruction);
+    let rs1 = rs1(instruction);
+    let rs2 = rs2(instruction);
+    let funct3 = funct3(instruction);
+    // 5 and 6 are reserved rounding modes.
+    let rm = match funct3 {
+        0x5 | 0x6 => None,
+        rm => Some(rm as u8),
+    };
+    // Loads and stores keep immediate bits where the others have the format.
+    let fmt = match (instruction >> 25) & 0x3 {
+        0 => Ok(FpFormat::Single),
+        1 => Ok(FpFormat::Double),
+        _ => Err(illegal),
+    };
+
+    let decoded = match instruction & 0x7F {
+        0x07 => match funct3 {
+            0x2 => Flw {
+                rd,
+                rs1,
+                imm: i_imm(instruction),
+            },
+            0x3 => Fld {
+                rd,
+                rs1,
+                imm: i_imm(instruction),
+            },
+            _ => return Err(illegal),
+        },
+        0x27 => match funct3 {
+            0x2 => Fsw {
+                rs1,
+                rs2,
+                imm: s_imm(instruction),
+            },
+            0x3 => Fsd {
+                rs1,
+                rs2,
+                imm: s_imm(instruction),
+            },
+            _ => return Err(illegal),
+        },
+        0x53 => {
+            let fmt = fmt?;
+            // For FCVTs to and from integers, rs2 picks the integer type.
+            let int = match rs2 {
+                0 => Ok(IntWidth::W),
+                1 => Ok(IntWidth::Wu),
+                2 if rv64 => Ok(IntWidth::L),
+                3 if rv64 => Ok(IntWidth::Lu),
+                _ => Err(illegal),
+            };
+            let arith = |op| -> Result<FloatInstruction, DecodeError> {
+                let rm = rm.ok_or(illegal)?;
+                Ok(Farith {
+                    op,
+                    fmt,
+                    rd,
+                    rs1,
+                    rs2,
+                    rm,
+                })
+            };
+
+            match (instruction >> 27, funct3) {
+                (0b00000, _) => arith(FpOp::Add)?,
+                (0b00001, _) => arith(FpOp::Sub)?,
+                (0b00010, _) => arith(FpOp::Mul)?,
+                (0b00011, _) => arith(FpOp::Div)?,
+                (0b00100, 0x0) => arith(FpOp::Sgnj)?,
+                (0b00100, 0x1) => arith(FpOp::Sgnjn)?,
+                (0b00100, 0x2) => arith(FpOp::Sgnjx)?,
+                (0b00101, 0x0) => arith(FpOp::Min)?,
+                (0b00101, 0x1) => arith(FpOp::Max)?,
+                (0b01011, _) if rs2 == 0 => Fsqrt {
+                    fmt,
+                    rd,
+                    rs1,
+                    rm: rm.ok_or(illegal)?,
+                },
+                // The source format is in rs2, and must be the other one.
+                (0b01000, _) if rs2 == (fmt == FpFormat::Single) as u8 => FcvtFp {
+                    fmt,
+                    rd,
+                    rs1,
+                    rm: rm.ok_or(illegal)?,
+                },
+                (0b10100, 0x2) => Fcmp {
+                    op: FpCompare::Eq,
+                    fmt,
+                    rd,
+                    rs1,
+                    rs2,
+                },
+                (0b10100, 0x1) => Fcmp {
+                    op: FpCompare::Lt,
+                    fmt,
+                    rd,
+                    rs1,
+                    rs2,
+                },
+                (0b10100, 0x0) => Fcmp {
+                    op: FpCompare::Le,
+                    fmt,
+                    rd,
+                    rs1,
+                    rs2,
+                },
+                (0b11000, _) => FcvtToInt {
+                    fmt,
+                    int: int?,
+                    rd,
+                    rs1,
+                    rm: rm.ok_or(illegal)?,
+                },
+                (0b11010, _) => FcvtFromInt {
+                    fmt,
+                    int: int?,
+                    rd,
+                    rs1,
+                    rm: rm.ok_or(illegal)?,
+                },
+                (0b11100, 0x0) if rs2 == 0 && (fmt == FpFormat::Single || rv64) => {
+                    FmvToInt { fmt, rd, rs1 }
+                }
+                (0b11100, 0x1) if rs2 == 0 => Fclass { fmt, rd, rs1 },
+                (0b11110, 0x0) if rs2 == 0 && (fmt == FpFormat::Single || rv64) => {
+                    FmvFromInt { fmt, rd, rs1 }
+                }
+                _ => return Err(illegal),
+            }
+        }
+        opcode => Fma {
+            op: match opcode {
+                0x43 => FmaOp::Madd,
+                0x47 => FmaOp::Msub,
+                0x4B => FmaOp::Nmsub,
+                _ => FmaOp::Nmadd,
+            },
+            rd,
+            rs1,
+            rs2,
+            fmt: fmt?,
+            rs3: (instruction >> 27) as u8,
+            rm: rm.ok_or(illegal)?,
+        },
+    };
+
+    Ok(decoded)
+}
+
+/// OP-V and the vector forms of LOAD-FP/STORE-FP.
+fn decode_vector(instruction: u32) -> Result<VectorInstruction, DecodeError> {
+    use VectorInstruction::*;
+
+    let illegal = DecodeError::IllegalInstruction(instruction);
+    let vd = rd(instruction);
+    let rs1 = rs1(instruction);
+    let vs2 = rs2(instruction);
+    let funct3 = funct3(instruction);
+    let funct6 = instruction >> 26;
+    let masked = (instruction >> 25) & 1 == 0;
+
+    let decoded = match (instruction & 0x7F, funct3) {
+        (0x57, 0x7) => match instruction >> 30 {
+            0b00 | 0b01 => Vsetvli {
+                rd: vd,
+                rs1,
+                vtype: ((instruction >> 20) & 0x7FF) as u16,
+            },
+            0b11 => Vsetivli {
+                rd: vd,
+                avl: rs1,
+                vtype: ((instruction >> 20) & 0x3FF) as u16,
+            },
+            _ if funct7(instruction) == 0b1000000 => Vsetvl {
+                rd: vd,
+                rs1,
+                rs2: vs2,
+            },
+            _ => return Err(illegal),
+        },
+        // Only unit-stride, single-segment accesses: nf, mew, mop and
+        // lumop/sumop all zero.
+        (0x07 | 0x27, _) => {
+            if instruction >> 28 != 0 || (instruction >> 26) & 0x3 != 0 || vs2 != 0 {
+                return Err(illegal);
+            }
+            let eew = match funct3 {
+                0x0 => 8,
+                0x5 => 16,
+                0x6 => 32,
+                _ => 64,
+            };
+            match instruction & 0x7F {
+                0x07 => Vload {
+                    eew,
+                    vd,
+                    rs1,
+                    masked,
+                },
+                _ => Vstore {
+                    eew,
+                    vs3: vd,
+                    rs1,
+                    masked,
+                },
+            }
+        }
+        // OPMVV and OPMVX.
+        (_, 0x2 | 0x6) => match (funct6, funct3) {
+            (0b000000, 0x2) => Vredsum {
+                vd,
+                vs2,
+                vs1: rs1,
+                masked,
+            },
+            (0b010000, 0x2) if rs1 == 0 && !masked => VmvXS { rd: vd, vs2 },
+            (0b010000, 0x6) if vs2 == 0 && !masked => VmvSX { vd, rs1 },
+            (0b100101, _) => Varith {
+                op: VectorOp::Mul,
+                vd,
+                vs2,
+                src: match funct3 {
+                    0x2 => VectorOperand::Vector(rs1),
+                    _ => VectorOperand::Scalar(rs1),
+                },
+                masked,
+            },
+            _ => return Err(illegal),
+        },
+        // OPIVV, OPIVI and OPIVX. The floating-point forms aren't
+        // implemented.
+        (_, 0x0 | 0x3 | 0x4) => {
+            let src = match funct3 {
+                0x0 => VectorOperand::Vector(rs1),
+                0x4 => VectorOperand::Scalar(rs1),
+                _ => VectorOperand::Imm(((rs1 << 3) as i8) >> 3),
+            };
+            let (op, vv, vi) = match funct6 {
+                0b000000 => (VectorOp::Add, true, true),
+                0b000010 => (VectorOp::Sub, true, false),
+                0b000011 => (VectorOp::Rsub, false, true),
+                0b000100 => (VectorOp::Minu, true, false),
+                0b000101 => (VectorOp::Min, true, false),
+                0b000110 => (VectorOp::Maxu, true, false),
+                0b000111 => (VectorOp::Max, true, false),
+                0b001001 => (VectorOp::And, true, true),
+                0b001010 => (VectorOp::Or, true, true),
+                0b001011 => (VectorOp::Xor, true, true),
+                // vmv.v has vs2 = 0 and vm = 1.
+                0b010111 if !masked && vs2 != 0 => return Err(illegal),
+                0b010111 => (VectorOp::Merge, true, true),
+                0b011000 => (VectorOp::Mseq, true, true),
+                0b011001 => (VectorOp::Msne, true, true),
+                0b011010 => (VectorOp::Msltu, true, false),
+                0b011011 => (VectorOp::Mslt, true, false),
+                0b011100 => (VectorOp::Msleu, true, true),
+                0b011101 => (VectorOp::Msle, true, true),
+                0b100101 => (VectorOp::Sll, true, true),
+                0b101000 => (VectorOp::Srl, true, true),
+                0b101001 => (VectorOp::Sra, true, true),
+                _ => return Err(illegal),
+            };
+            // Shifts take their immediate unsigned.
+            let src = match (op, src) {
+                (VectorOp::Sll | VectorOp::Srl | VectorOp::Sra, VectorOperand::Imm(_)) => {
+                    VectorOperand::Imm(rs1 as i8)
+                }
+                (_, src) => src,
+            };
+            match src {
+                VectorOperand::Vector(_) if !vv => return Err(illegal),
+                VectorOperand::Imm(_) if !vi => return Err(illegal),
+                _ => {}
+            }
+            Varith {
+                op,
+                vd,
+                vs2,
+                src,
+                masked,
+            }
+        }
+        _ => return Err(illegal),
+    };
+
+    Ok(decoded)
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Lui { rd, imm } => write!(f, "lui x{}, {:#x}", rd, imm >> 12),
+            Auipc { rd, imm } => write!(f, "auipc x{}, {:#x}", rd, imm >> 12),
+            Jal { rd, imm } => write!(f, "jal x{}, {}", rd, imm),
+            Jalr { rd, rs1, imm } => write!(f, "jalr x{}, {}(x{})", rd, imm, rs1),
+
+            Beq { rs1, rs2, imm } => write!(f, "beq x{}, x{}, {}", rs1, rs2, imm),
+            Bne { rs1, rs2, imm } => write!(f, "bne x{}, x{}, {}", rs1, rs2, imm),
+            Blt { rs1, rs2, imm } => write!(f, "blt x{}, x{}, {}", rs1, rs2, imm),
+            Bge { rs1, rs2, imm } => write!(f, "bge x{}, x{}, {}", rs1, rs2, imm),
+            Bltu { rs1, rs2, imm } => write!(f, "bltu x{}, x{}, {}", rs1, rs2, imm),
+            Bgeu { rs1, rs2, imm } => write!(f, "bgeu x{}, x{}, {}", rs1, rs2, imm),
+
+            Lb { rd, rs1, imm } => write!(f, "lb x{}, {}(x{})", rd, imm, rs1),
+            Lh { rd, rs1, imm } => write!(f, "lh x{}, {}(x{})", rd, imm, rs1),
+            Lw { rd, rs1, imm } => write!(f, "lw x{}, {}(x{})", rd, imm, rs1),
+            Lbu { rd, rs1, imm } => write!(f, "lbu x{}, {}(x{})", rd, imm, rs1),
+            Lhu { rd, rs1, imm } => write!(f, "lhu x{}, {}(x{})", rd, imm, rs1),
+            Lwu { rd, rs1, imm } => write!(f, "lwu x{}, {}(x{})", rd, imm, rs1),
+            Ld { rd, rs1, imm } => write!(f, "ld x{}, {}(x{})", rd, imm, rs1),
+
+            Sb { rs1, rs2, imm } => write!(f, "sb x{}, {}(x{})", rs2, imm, rs1),
+            Sh { rs1, rs2, imm } => write!(f, "sh x{}, {}(x{})", rs2, imm, rs1),
+            Sw { rs1, rs2, imm } => write!(f, "sw x{}, {}(x{})", rs2, imm, rs1),
+            Sd { rs1, rs2, imm } => write!(f, "sd x{}, {}(x{})", rs2, imm, rs1),
+
+            Addi { rd, rs1, imm } => write!(f, "addi x{}, x{}, {}", rd, rs1, imm),
+            Slti { rd, rs1, imm } => write!(f, "slti x{}, x{}, {}", rd, rs1, imm),
+            Sltiu { rd, rs1, imm } => write!(f, "sltiu x{}, x{}, {}", rd, rs1, imm),
+            Xori { rd, rs1, imm } => write!(f, "xori x{}, x{}, {}", rd, rs1, imm),
+            Ori { rd, rs1, imm } => write!(f, "ori x{}, x{}, {}", rd, rs1, imm),
+            Andi { rd, rs1, imm } => write!(f, "andi x{}, x{}, {}", rd, rs1, imm),
+            Slli { rd, rs1, shamt } => write!(f, "slli x{}, x{}, {}", rd, rs1, shamt),
+            Srli { rd, rs1, shamt } => write!(f, "srli x{}, x{}, {}", rd, rs1, shamt),
+            Srai { rd, rs1, shamt } => write!(f, "srai x{}, x{}, {}", rd, rs1, shamt),
+
+            Add { rd, rs1, rs2 } => write!(f, "add x{}, x{}, x{}", rd, rs1, rs2),
+            Sub { rd, rs1, rs2 } => write!(f, "sub x{}, x{}, x{}", rd, rs1, rs2),
+            Sll { rd, rs1, rs2 } => write!(f, "sll x{}, x{}, x{}", rd, rs1, rs2),
+            Slt { rd, rs1, rs2 } => write!(f, "slt x{}, x{}, x{}", rd, rs1, rs2),
+            Sltu { rd, rs1, rs2 } => write!(f, "sltu x{}, x{}, x{}", rd, rs1, rs2),
+            Xor { rd, rs1, rs2 } => write!(f, "xor x{}, x{}, x{}", rd, rs1, rs2),
+            Srl { rd, rs1, rs2 } => write!(f, "srl x{}, x{}, x{}", rd, rs1, rs2),
+            Sra { rd, rs1, rs2 } => write!(f, "sra x{}, x{}, x{}", rd, rs1, rs2),
+            Or { rd, rs1, rs2 } => write!(f, "or x{}, x{}, x{}", rd, rs1, rs2),
+            And { rd, rs1, rs2 } => write!(f, "and x{}, x{}, x{}", rd, rs1, rs2),
+
+            Addiw { rd, rs1, imm } => write!(f, "addiw x{}, x{}, {}", rd, rs1, imm),
+            Slliw { rd, rs1, shamt } => write!(f, "slliw x{}, x{}, {}", rd, rs1, shamt),
+            Srliw { rd, rs1, shamt } => write!(f, "srliw x{}, x{}, {}", rd, rs1, shamt),
+            Sraiw { rd, rs1, shamt } => write!(f, "sraiw x{}, x{}, {}", rd, rs1, shamt),
+            Addw { rd, rs1, rs2 } => write!(f, "addw x{}, x{}, x{}", rd, rs1, rs2),
+            Subw { rd, rs1, rs2 } => write!(f, "subw x{}, x{}, x{}", rd, rs1, rs2),
+            Sllw { rd, rs1, rs2 } => write!(f, "sllw x{}, x{}, x{}", rd, rs1, rs2),
+            Srlw { rd, rs1, rs2 } => write!(f, "srlw x{}, x{}, x{}", rd, rs1, rs2),
+            Sraw { rd, rs1, rs2 } => write!(f, "sraw x{}, x{}, x{}", rd, rs1, rs2),
+
+            Sh1add { rd, rs1, rs2 } => write!(f, "sh1add x{}, x{}, x{}", rd, rs1, rs2),
+            Sh2add { rd, rs1, rs2 } => write!(f, "sh2add x{}, x{}, x{}", rd, rs1, rs2),
+            Sh3add { rd, rs1, rs2 } => write!(f, "sh3add x{}, x{}, x{}", rd, rs1, rs2),
+            AddUw { rd, rs1, rs2 } => write!(f, "add.uw x{}, x{}, x{}", rd, rs1, rs2),
+            Sh1addUw { rd, rs1, rs2 } => write!(f, "sh1add.uw x{}, x{}, x{}", rd, rs1, rs2),
+            Sh2addUw { rd, rs1, rs2 } => write!(f, "sh2add.uw x{}, x{}, x{}", rd, rs1, rs2),
+            Sh3addUw { rd, rs1, rs2 } => write!(f, "sh3add.uw x{}, x{}, x{}", rd, rs1, rs2),
+            SlliUw { rd, rs1, shamt } => write!(f, "slli.uw x{}, x{}, {}", rd, rs1, shamt),
+
+            Pack { rd, rs1, rs2 } => write!(f, "pack x{}, x{}, x{}", rd, rs1, rs2),
+            Packh { rd, rs1, rs2 } => write!(f, "packh x{}, x{}, x{}", rd, rs1, rs2),
+            Packw { rd, rs1, rs2 } => write!(f, "packw x{}, x{}, x{}", rd, rs1, rs2),
+            Brev8 { rd, rs1 } => write!(f, "brev8 x{}, x{}", rd, rs1),
+            Zip { rd, rs1 } => write!(f, "zip x{}, x{}", rd, rs1),
+            Unzip { rd, rs1 } => write!(f, "unzip x{}, x{}", rd, rs1),
+
+            LrW { rd, rs1 } => write!(f, "lr.w x{}, (x{})", rd, rs1),
+            ScW { rd, rs1, rs2 } => write!(f, "sc.w x{}, x{}, (x{})", rd, rs2, rs1),
+            LrD { rd, rs1 } => write!(f, "lr.d x{}, (x{})", rd, rs1),
+            ScD { rd, rs1, rs2 } => write!(f, "sc.d x{}, x{}, (x{})", rd, rs2, rs1),
+
+            Float(instruction) => instruction.fmt(f),
+            Vector(instruction) => instruction.fmt(f),
+            Crypto(instruction) => instruction.fmt(f),
+            Hypervisor(instruction) => instruction.fmt(f),
+            Custom(raw) => {
+                let space = CustomOpcode::of(raw).map_or(0, |space| space as u8);
+                write!(f, "custom-{} {:#010x}", space, raw)
+            }
+
+            Fence => write!(f, "fence"),
+            FenceI => write!(f, "fence.i"),
+
+            Ecall => write!(f, "ecall"),
+            Ebreak => write!(f, "ebreak"),
+            Mret => write!(f, "mret"),
+            Sret => write!(f, "sret"),
+            Wfi => write!(f, "wfi"),
+            WrsNto => write!(f, "wrs.nto"),
+            WrsSto => write!(f, "wrs.sto"),
+            SfenceVma { rs1, rs2 } => write!(f, "sfence.vma x{}, x{}", rs1, rs2),
+
+            Csrrw { rd, rs1, csr } => write!(f, "csrrw x{}, {:#x}, x{}", rd, csr, rs1),
+            Csrrs { rd, rs1, csr } => write!(f, "csrrs x{}, {:#x}, x{}", rd, csr, rs1),
+            Csrrc { rd, rs1, csr } => write!(f, "csrrc x{}, {:#x}, x{}", rd, csr, rs1),
+            Csrrwi { rd, uimm, csr } => write!(f, "csrrwi x{}, {:#x}, {}", rd, csr, uimm),
+            Csrrsi { rd, uimm, csr } => write!(f, "csrrsi x{}, {:#x}, {}", rd, csr, uimm),
+            Csrrci { rd, uimm, csr } => write!(f, "csrrci x{}, {:#x}, {}", rd, csr, uimm),
+        }
+    }
+}
+
+impl fmt::Display for HypervisorInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use HypervisorInstruction::*;
+
+        let width = |bytes| match bytes {
+            1 => "b",
+            2 => "h",
+            4 => "w",
+            _ => "d",
+        };
+        match *self {
+            Load {
+                rd,
+                rs1,
+                bytes,
+                signed,
+                execute,
+            } => {
+                let name = if execute { "hlvx" } else { "hlv" };
+                let unsigned = if signed { "" } else { "u" };
+                write!(
+                    f,
+                    "{}.{}{} x{}, (x{})",
+                    name,
+                    width(bytes),
+                    unsigned,
+                    rd,
+                    rs1
+                )
+            }
+            Store { rs1, rs2, bytes } => write!(f, "hsv.{} x{}, (x{})", width(bytes), rs2, rs1),
+            FenceVvma { rs1, rs2 } => write!(f, "hfence.vvma x{}, x{}", rs1, rs2),
+            FenceGvma { rs1, rs2 } => write!(f, "hfence.gvma x{}, x{}", rs1, rs2),
+        }
+    }
+}
+
+impl fmt::Display for FloatInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FloatInstruction::*;
+
+        match *self {
+            Flw { rd, rs1, imm } => write!(f, "flw f{}, {}(x{})", rd, imm, rs1),
+            Fsw { rs1, rs2, imm } => write!(f, "fsw f{}, {}(x{})", rs2, imm, rs1),
+            Fld { rd, rs1, imm } => write!(f, "fld f{}, {}(x{})", rd, imm, rs1),
+            Fsd { rs1, rs2, imm } => write!(f, "fsd f{}, {}(x{})", rs2, imm, rs1),
+            Farith {
+                op,
+                fmt,
+                rd,
+                rs1,
+                rs2,
+                ..
+            } => {
+                let name = match op {
+                    FpOp::Add => "fadd",
+                    FpOp::Sub => "fsub",
+                    FpOp::Mul => "fmul",
+                    FpOp::Div => "fdiv",
+                    FpOp::Min => "fmin",
+                    FpOp::Max => "fmax",
+                    FpOp::Sgnj => "fsgnj",
+                    FpOp::Sgnjn => "fsgnjn",
+                    FpOp::Sgnjx => "fsgnjx",
+                };
+                write!(f, "{}.{} f{}, f{}, f{}", name, fmt.suffix(), rd, rs1, rs2)
+            }
+            Fma {
+                op,
+                fmt,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                ..
+            } => {
+                let name = match op {
+                    FmaOp::Madd => "fmadd",
+                    FmaOp::Msub => "fmsub",
+                    FmaOp::Nmsub => "fnmsub",
+                    FmaOp::Nmadd => "fnmadd",
+                };
+                let s = fmt.suffix();
+                write!(f, "{}.{} f{}, f{}, f{}, f{}", name, s, rd, rs1, rs2, rs3)
+            }
+            Fsqrt { fmt, rd, rs1, .. } => write!(f, "fsqrt.{} f{}, f{}", fmt.suffix(), rd, rs1),
+            Fcmp {
+                op,
+                fmt,
+                rd,
+                rs1,
+                rs2,
+            } => {
+                let name = match op {
+                    FpCompare::Eq => "feq",
+                    FpCompare::Lt => "flt",
+                    FpCompare::Le => "fle",
+                };
+                write!(f, "{}.{} x{}, f{}, f{}", name, fmt.suffix(), rd, rs1, rs2)
+            }
+            Fclass { fmt, rd, rs1 } => write!(f, "fclass.{} x{}, f{}", fmt.suffix(), rd, rs1),
+            FcvtToInt {
+                fmt, int, rd, rs1, ..
+            } => write!(f, "fcvt.{}.{} x{}, f{}", int.name(), fmt.suffix(), rd, rs1),
+            FcvtFromInt {
+                fmt, int, rd, rs1, ..
+            } => write!(f, "fcvt.{}.{} f{}, x{}", fmt.suffix(), int.name(), rd, rs1),
+            FcvtFp { fmt, rd, rs1, .. } => {
+                let from = match fmt {
+                    FpFormat::Single => "d",
+                    FpFormat::Double => "s",
+                };
+                write!(f, "fcvt.{}.{} f{}, f{}", fmt.suffix(), from, rd, rs1)
+            }
+            FmvToInt { fmt, rd, rs1 } => {
+                let name = match fmt {
+                    FpFormat::Single => "w",
+                    FpFormat::Double => "d",
+                };
+                write!(f, "fmv.x.{} x{}, f{}", name, rd, rs1)
+            }
+            FmvFromInt { fmt, rd, rs1 } => {
+                let name = match fmt {
+                    FpFormat::Single => "w",
+                    FpFormat::Double => "d",
+                };
+                write!(f, "fmv.{}.x f{}, x{}", name, rd, rs1)
+            }
+        }
+    }
+}
+
+impl fmt::Display for CryptoInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CryptoInstruction::Aes32 {
+                op,
+                rd,
+                rs1,
+                rs2,
+                bs,
+            } => {
+                let name = match op {
+                    AesOp::Esi => "aes32esi",
+                    AesOp::Esmi => "aes32esmi",
+                    AesOp::Dsi => "aes32dsi",
+                    AesOp::Dsmi => "aes32dsmi",
+                };
+                write!(f, "{} x{}, x{}, x{}, {}", name, rd, rs1, rs2, bs)
+            }
+            CryptoInstruction::Sha { op, rd, rs1 } => {
+                let name = match op {
+                    ShaOp::Sha256Sig0 => "sha256sig0",
+                    ShaOp::Sha256Sig1 => "sha256sig1",
+                    ShaOp::Sha256Sum0 => "sha256sum0",
+                    ShaOp::Sha256Sum1 => "sha256sum1",
+                    ShaOp::Sha512Sig0 => "sha512sig0",
+                    ShaOp::Sha512Sig1 => "sha512sig1",
+                    ShaOp::Sha512Sum0 => "sha512sum0",
+                    ShaOp::Sha512Sum1 => "sha512sum1",
+                };
+                write!(f, "{} x{}, x{}", name, rd, rs1)
+            }
+            CryptoInstruction::Sha512Half { op, rd, rs1, rs2 } => {
+                let name = match op {
+                    Sha512HalfOp::Sum0r => "sha512sum0r",
+                    Sha512HalfOp::Sum1r => "sha512sum1r",
+                    Sha512HalfOp::Sig0l => "sha512sig0l",
+                    Sha512HalfOp::Sig0h => "sha512sig0h",
+                    Sha512HalfOp::Sig1l => "sha512sig1l",
+                    Sha512HalfOp::Sig1h => "sha512sig1h",
+                };
+                write!(f, "{} x{}, x{}, x{}", name, rd, rs1, rs2)
+            }
+        }
+    }
+}
+
+impl fmt::Display for VectorInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use VectorInstruction::*;
+
+        let mask = |masked: bool| if masked { ", v0.t" } else { "" };
+
+        match *self {
+            Vsetvli { rd, rs1, vtype } => {
+                write!(f, "vsetvli x{}, x{}, {}", rd, rs1, VtypeName(vtype))
+            }
+            Vsetivli { rd, avl, vtype } => {
+                write!(f, "vsetivli x{}, {}, {}", rd, avl, VtypeName(vtype))
+            }
+            Vsetvl { rd, rs1, rs2 } => write!(f, "vsetvl x{}, x{}, x{}", rd, rs1, rs2),
+            Vload {
+                eew,
+                vd,
+                rs1,
+                masked,
+            } => write!(f, "vle{}.v v{}, (x{}){}", eew, vd, rs1, mask(masked)),
+            Vstore {
+                eew,
+                vs3,
+                rs1,
+                masked,
+            } => write!(f, "vse{}.v v{}, (x{}){}", eew, vs3, rs1, mask(masked)),
+            Varith {
+                op: VectorOp::Merge,
+                vd,
+                src,
+                masked: false,
+                ..
+            } => match src {
+                VectorOperand::Vector(vs1) => write!(f, "vmv.v.v v{}, v{}", vd, vs1),
+                VectorOperand::Scalar(rs1) => write!(f, "vmv.v.x v{}, x{}", vd, rs1),
+                VectorOperand::Imm(imm) => write!(f, "vmv.v.i v{}, {}", vd, imm),
+            },
+            Varith {
+                op,
+                vd,
+                vs2,
+                src,
+                masked,
+            } => {
+                // vmerge's v0 is an operand rather than a mask.
+                let (suffix, tail) = match op {
+                    VectorOp::Merge => ("m", ", v0"),
+                    _ => ("", mask(masked)),
+                };
+                let name = op.name();
+                match src {
+                    VectorOperand::Vector(vs1) => {
+                        write!(
+                            f,
+                            "{}.vv{} v{}, v{}, v{}{}",
+                            name, suffix, vd, vs2, vs1, tail
+                        )
+                    }
+                    VectorOperand::Scalar(rs1) => {
+                        write!(
+                            f,
+                            "{}.vx{} v{}, v{}, x{}{}",
+                            name, suffix, vd, vs2, rs1, tail
+                        )
+                    }
+                    VectorOperand::Imm(imm) => {
+                        write!(
+                            f,
+                            "{}.vi{} v{}, v{}, {}{}",
+                            name, suffix, vd, vs2, imm, tail
+                        )
+                    }
+                }
+            }
+            Vredsum {
+                vd,
+                vs2,
+                vs1,
+                masked,
+            } => write!(f, "vredsum.vs v{}, v{}, v{}{}", vd, vs2, vs1, mask(masked)),
+            VmvXS { rd, vs2 } => write!(f, "vmv.x.s x{}, v{}", rd, vs2),
+            VmvSX { vd, rs1 } => write!(f, "vmv.s.x v{}, x{}", vd, rs1),
+        }
+    }
+}
+
+/// `e32, m1, ta, ma`, as written in `vsetvli`.
+struct VtypeName(u16);
+
+impl fmt::Display for VtypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vtype = self.0;
+        let lmul = ["m1", "m2", "m4", "m8", "m?", "mf8", "mf4", "mf2"][(vtype & 0x7) as usize];
+        let tail = if vtype & 0x40 != 0 { "ta" } else { "tu" };
+        let mask = if vtype & 0x80 != 0 { "ma" } else { "mu" };
+        write!(
+            f,
+            "e{}, {}, {}, {}",
+            8 << ((vtype >> 3) & 0x7),
+            lmul,
+            tail,
+            mask
+        )
+    }
+}
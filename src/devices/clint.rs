@@ -0,0 +1,120 @@
+use super::Device;
+use crate::MemSize;
+use crate::csr::{MIP_MSIP, MIP_MTIP};
+
+const MSIP: u32 = 0x0000;
+const MTIMECMP: u32 = 0x4000;
+const MTIME: u32 = 0xBFF8;
+
+/// A SiFive-style core-local interruptor: a free-running `mtime`, one
+/// `mtimecmp` per hart driving its timer interrupt, and one `msip` per hart
+/// for software interrupts (IPIs).
+///
+/// `mtime` advances one tick per instruction executed, so timer deadlines
+/// are deterministic, and idle harts can skip straight to the next one.
+pub struct Clint {
+    mtime: u64,
+    mtimecmp: Vec<u64>,
+    msip: Vec<bool>,
+}
+
+impl Clint {
+    /// Where QEMU's `virt` machine puts its CLINT.
+    pub const BASE: u32 = 0x0200_0000;
+    pub const SIZE: u32 = 0x1_0000;
+
+    pub fn new(harts: usize) -> Self {
+        Self {
+            mtime: 0,
+            // Nothing fires until software programs a deadline.
+            mtimecmp: vec![u64::MAX; harts],
+            msip: vec![false; harts],
+        }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn mtimecmp(&self, hart: usize) -> u64 {
+        self.mtimecmp[hart]
+    }
+
+    /// The 64-bit register and which part of it `offset` lands in.
+    fn register(&mut self, offset: u32) -> Option<(&mut u64, u32)> {
+        let harts = self.mtimecmp.len() as u32;
+        match offset {
+            MTIME..=0xBFFF => Some((&mut self.mtime, offset - MTIME)),
+            MTIMECMP..MTIME if (offset - MTIMECMP) / 8 < harts => {
+                let hart = (offset - MTIMECMP) / 8;
+                Some((&mut self.mtimecmp[hart as usize], (offset - MTIMECMP) % 8))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Device for Clint {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        if offset < MTIMECMP {
+            let hart = (offset - MSIP) as usize / 4;
+            return self.msip.get(hart).copied().unwrap_or(false) as u32;
+        }
+
+        match self.register(offset) {
+            Some((register, shift)) => (*register >> (8 * shift)) as u32 & mask(size),
+            None => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, size: MemSize, value: u32) {
+        if offset < MTIMECMP {
+            let hart = (offset - MSIP) as usize / 4;
+            if let Some(msip) = self.msip.get_mut(hart) {
+                *msip = value & 1 != 0;
+            }
+            return;
+        }
+
+        if let Some((register, shift)) = self.register(offset) {
+            let mask = (mask(size) as u64) << (8 * shift);
+            *register = (*register & !mask) | (((value as u64) << (8 * shift)) & mask);
+        }
+    }
+
+    fn tick(&mut self, ticks: u64) {
+        self.mtime = self.mtime.wrapping_add(ticks);
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        let hart = hart as usize;
+        let mut lines = 0;
+        if self.msip.get(hart) == Some(&true) {
+            lines |= MIP_MSIP;
+        }
+        if self
+            .mtimecmp
+            .get(hart)
+            .is_some_and(|&cmp| self.mtime >= cmp)
+        {
+            lines |= MIP_MTIP;
+        }
+        lines
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.mtimecmp
+            .iter()
+            .filter(|&&cmp| cmp > self.mtime && cmp != u64::MAX)
+            .map(|&cmp| cmp - self.mtime)
+            .min()
+    }
+}
+
+fn mask(size: MemSize) -> u32 {
+    match size {
+        MemSize::Byte => 0xFF,
+        MemSize::Half => 0xFFFF,
+        MemSize::Word => 0xFFFF_FFFF,
+    }
+}
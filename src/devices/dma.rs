@@ -0,0 +1,240 @@
+use super::Device;
+use crate::MemSize;
+use crate::bus::Dma;
+use crate::csr::MIP_MEIP;
+
+const DESC_LO: u32 = 0x00;
+const DESC_HI: u32 = 0x04;
+const CONTROL: u32 = 0x08;
+const STATUS: u32 = 0x0C;
+const CURRENT_LO: u32 = 0x10;
+const CURRENT_HI: u32 = 0x14;
+const COPIED: u32 = 0x18;
+
+pub const CONTROL_START: u32 = 1 << 0;
+pub const CONTROL_IE: u32 = 1 << 1;
+pub const CONTROL_ABORT: u32 = 1 << 2;
+
+pub const STATUS_BUSY: u32 = 1 << 0;
+pub const STATUS_DONE: u32 = 1 << 1;
+pub const STATUS_ERROR: u32 = 1 << 2;
+
+/// Source, destination, length, flags (reserved) and next descriptor.
+pub const DESC_SIZE: u64 = 32;
+
+/// The descriptor being worked on.
+struct Transfer {
+    src: u64,
+    dst: u64,
+    remaining: u64,
+    next: u64,
+}
+
+/// A descriptor-based DMA engine. Software writes the address of a chain
+/// of 32-byte descriptors to `desc` and sets `control.start`; the engine
+/// then copies each one in the background, a fixed number of bytes per
+/// tick, and sets `status.done` once it reaches a descriptor whose `next`
+/// is zero. A descriptor is laid out as:
+///
+/// | offset | field              |
+/// |--------|--------------------|
+/// | 0      | source (u64)       |
+/// | 8      | destination (u64)  |
+/// | 16     | length (u32)       |
+/// | 20     | flags, zero (u32)  |
+/// | 24     | next (u64)         |
+///
+/// Descriptors and both ends of every copy must be in RAM; anything else
+/// stops the chain with `status.error` and leaves `current` pointing at the
+/// descriptor that failed. Done and error are write-one-to-clear, and raise
+/// MEIP on hart 0 while `control.ie` is set, unless
+/// [`with_interrupt`](Self::with_interrupt) picks another line.
+pub struct DmaController {
+    desc: u64,
+    current: u64,
+    transfer: Option<Transfer>,
+    busy: bool,
+    status: u32,
+    /// Only `control.ie` sticks; start and abort are commands.
+    control: u32,
+    copied: u32,
+    rate: u64,
+    /// Bytes the engine may move before the next tick.
+    budget: u64,
+    hart: u64,
+    line: u32,
+}
+
+impl DmaController {
+    pub const BASE: u32 = 0x1000_9000;
+    pub const SIZE: u32 = 0x1000;
+
+    pub fn new() -> Self {
+        Self {
+            desc: 0,
+            current: 0,
+            transfer: None,
+            busy: false,
+            status: 0,
+            control: 0,
+            copied: 0,
+            rate: 64,
+            budget: 0,
+            hart: 0,
+            line: MIP_MEIP,
+        }
+    }
+
+    /// Move `bytes` per tick rather than 64. Fetching a descriptor costs
+    /// as much as copying its 32 bytes.
+    pub fn with_rate(mut self, bytes: u64) -> Self {
+        assert!(bytes > 0, "a DMA engine has to move something");
+        self.rate = bytes;
+        self
+    }
+
+    /// Raise the `mip` bits in `line` on the hart with this `mhartid`
+    /// instead.
+    pub fn with_interrupt(mut self, hart: u64, line: u32) -> Self {
+        self.hart = hart;
+        self.line = line;
+        self
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    fn stop(&mut self, status: u32) {
+        self.busy = false;
+        self.transfer = None;
+        self.budget = 0;
+        self.status |= status;
+    }
+
+    fn fetch(&self, memory: &Dma<'_>) -> Option<Transfer> {
+        let at = self.current;
+        let len = memory.read_u32(at.checked_add(16)?)?;
+        Some(Transfer {
+            src: memory.read_u64(at)?,
+            dst: memory.read_u64(at + 8)?,
+            remaining: len as u64,
+            next: memory.read_u64(at + 24)?,
+        })
+    }
+}
+
+impl Default for DmaController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for DmaController {
+    fn read(&mut self, offset: u32, _size: MemSize) -> u32 {
+        match offset {
+            DESC_LO => self.desc as u32,
+            DESC_HI => (self.desc >> 32) as u32,
+            CONTROL => self.control,
+            STATUS => self.status | if self.busy { STATUS_BUSY } else { 0 },
+            CURRENT_LO => self.current as u32,
+            CURRENT_HI => (self.current >> 32) as u32,
+            COPIED => self.copied,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, _size: MemSize, value: u32) {
+        match offset {
+            DESC_LO => self.desc = self.desc & !0xFFFF_FFFF | value as u64,
+            DESC_HI => self.desc = self.desc & 0xFFFF_FFFF | (value as u64) << 32,
+            CONTROL => {
+                self.control = value & CONTROL_IE;
+                if value & CONTROL_ABORT != 0 {
+                    self.stop(0);
+                } else if value & CONTROL_START != 0 && !self.busy {
+                    self.busy = true;
+                    self.current = self.desc;
+                    self.copied = 0;
+                }
+            }
+            STATUS => self.status &= !(value & (STATUS_DONE | STATUS_ERROR)),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, ticks: u64) {
+        if self.busy {
+            self.budget = self.budget.saturating_add(ticks.saturating_mul(self.rate));
+        }
+    }
+
+    fn dma(&mut self, memory: &mut Dma<'_>) {
+        let mut buf = [0; 4096];
+        while self.busy {
+            let Some(transfer) = &mut self.transfer else {
+                if self.budget < DESC_SIZE {
+                    return;
+                }
+                self.budget -= DESC_SIZE;
+                match self.fetch(memory) {
+                    Some(transfer) => self.transfer = Some(transfer),
+                    None => self.stop(STATUS_ERROR),
+                }
+                continue;
+            };
+
+            if transfer.remaining == 0 {
+                self.current = transfer.next;
+                self.transfer = None;
+                if self.current == 0 {
+                    self.stop(STATUS_DONE);
+                }
+                continue;
+            }
+
+            let len = transfer.remaining.min(self.budget).min(buf.len() as u64) as usize;
+            if len == 0 {
+                return;
+            }
+            let chunk = &mut buf[..len];
+            if memory.read(transfer.src, chunk).is_none()
+                || memory.write(transfer.dst, chunk).is_none()
+            {
+                self.stop(STATUS_ERROR);
+                return;
+            }
+            transfer.src += len as u64;
+            transfer.dst += len as u64;
+            transfer.remaining -= len as u64;
+            self.budget -= len as u64;
+            self.copied = self.copied.wrapping_add(len as u32);
+        }
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        if hart == self.hart
+            && self.control & CONTROL_IE != 0
+            && self.status & (STATUS_DONE | STATUS_ERROR) != 0
+        {
+            self.line
+        } else {
+            0
+        }
+    }
+
+    /// Ticks until the descriptor in hand is finished, which is as far
+    /// ahead as the engine can see.
+    fn next_event(&self) -> Option<u64> {
+        if !self.busy {
+            return None;
+        }
+        let needed = self.transfer.as_ref().map_or(DESC_SIZE, |t| t.remaining);
+        Some(
+            needed
+                .saturating_sub(self.budget)
+                .div_ceil(self.rate)
+                .max(1),
+        )
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::Device;
+use crate::MemSize;
+use crate::csr::MIP_MEIP;
+
+const INPUT_VAL: u32 = 0x00;
+const INPUT_EN: u32 = 0x04;
+const OUTPUT_EN: u32 = 0x08;
+const OUTPUT_VAL: u32 = 0x0C;
+const PUE: u32 = 0x10;
+const DS: u32 = 0x14;
+/// Enable and pending pairs for rise, fall, high and low interrupts follow
+/// from here, eight bytes apart.
+const RISE_IE: u32 = 0x18;
+const LOW_IP: u32 = 0x34;
+const IOF_EN: u32 = 0x38;
+const IOF_SEL: u32 = 0x3C;
+const OUT_XOR: u32 = 0x40;
+
+/// The host's side of the pins: what it drives onto the inputs and what
+/// the guest is driving out. Clones share the same pins, from any thread.
+#[derive(Clone, Default)]
+pub struct GpioPins {
+    inputs: Arc<AtomicU32>,
+    outputs: Arc<AtomicU32>,
+}
+
+impl GpioPins {
+    /// Drive `pin` high or low, as a button or sensor would.
+    pub fn set(&self, pin: u32, high: bool) {
+        if high {
+            self.inputs.fetch_or(1 << pin, Ordering::Relaxed);
+        } else {
+            self.inputs.fetch_and(!(1 << pin), Ordering::Relaxed);
+        }
+    }
+
+    /// The level the guest is driving on `pin`. Pins it isn't driving read
+    /// low.
+    pub fn output(&self, pin: u32) -> bool {
+        self.outputs.load(Ordering::Relaxed) & 1 << pin != 0
+    }
+
+    pub fn outputs(&self) -> u32 {
+        self.outputs.load(Ordering::Relaxed)
+    }
+}
+
+/// A SiFive-style GPIO controller with 32 pins, as on the FE310. Pins the
+/// guest enables as outputs drive their `output_val` bit (inverted by
+/// `out_xor`); the rest read whatever the host drives through
+/// [`GpioPins`]. Output changes are reported to the callbacks registered
+/// with [`on_output`](Self::on_output).
+///
+/// Edge and level interrupts raise MEIP on hart 0 unless
+/// [`with_interrupt`](Self::with_interrupt) picks another line.
+pub struct Gpio {
+    pins: GpioPins,
+    callbacks: Vec<Box<dyn FnMut(u32, bool)>>,
+    input_en: u32,
+    output_en: u32,
+    output_val: u32,
+    out_xor: u32,
+    pue: u32,
+    ds: u32,
+    iof_en: u32,
+    iof_sel: u32,
+    /// Interrupt enables and pending bits: rise, fall, high, low.
+    ie: [u32; 4],
+    ip: [u32; 4],
+    /// `input_val` as of the last sample, to spot edges.
+    last_input: u32,
+    /// Levels last reported to the callbacks.
+    driven: u32,
+    hart: u64,
+    line: u32,
+}
+
+impl Gpio {
+    /// Where the FE310 puts its GPIO block.
+    pub const BASE: u32 = 0x1001_2000;
+    pub const SIZE: u32 = 0x1000;
+
+    pub fn new() -> Self {
+        Self {
+            pins: GpioPins::default(),
+            callbacks: Vec::new(),
+            input_en: 0,
+            output_en: 0,
+            output_val: 0,
+            out_xor: 0,
+            pue: 0,
+            ds: 0,
+            iof_en: 0,
+            iof_sel: 0,
+            ie: [0; 4],
+            ip: [0; 4],
+            last_input: 0,
+            driven: 0,
+            hart: 0,
+            line: MIP_MEIP,
+        }
+    }
+
+    /// Call `callback` with the pin number and new level whenever a pin the
+    /// guest drives changes.
+    pub fn on_output(mut self, callback: impl FnMut(u32, bool) + 'static) -> Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Raise the `mip` bits in `line` on the hart with this `mhartid`
+    /// instead.
+    pub fn with_interrupt(mut self, hart: u64, line: u32) -> Self {
+        self.hart = hart;
+        self.line = line;
+        self
+    }
+
+    pub fn pins(&self) -> GpioPins {
+        self.pins.clone()
+    }
+
+    /// The level on every pin: driven by the guest where it's an output,
+    /// by the host everywhere else.
+    fn levels(&self) -> u32 {
+        let inputs = self.pins.inputs.load(Ordering::Relaxed);
+        self.driven | inputs & !self.output_en
+    }
+
+    fn input_val(&self) -> u32 {
+        self.levels() & self.input_en
+    }
+
+    /// Latch edges and levels into the pending bits.
+    fn sample(&mut self) {
+        let input = self.input_val();
+        self.ip[0] |= input & !self.last_input;
+        self.ip[1] |= !input & self.last_input;
+        self.ip[2] |= input;
+        self.ip[3] |= !input & self.input_en;
+        self.last_input = input;
+    }
+
+    /// Tell the host about any output that changed.
+    fn update_outputs(&mut self) {
+        let driven = self.output_en & (self.output_val ^ self.out_xor);
+        let changed = driven ^ self.driven;
+        self.driven = driven;
+        self.pins.outputs.store(driven, Ordering::Relaxed);
+
+        for pin in (0..32).filter(|pin| changed & 1 << pin != 0) {
+            for callback in &mut self.callbacks {
+                callback(pin, driven & 1 << pin != 0);
+            }
+        }
+    }
+}
+
+impl Default for Gpio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Gpio {
+    fn read(&mut self, offset: u32, _size: MemSize) -> u32 {
+        match offset {
+            INPUT_VAL => self.input_val(),
+            INPUT_EN => self.input_en,
+            OUTPUT_EN => self.output_en,
+            OUTPUT_VAL => self.output_val,
+            PUE => self.pue,
+            DS => self.ds,
+            RISE_IE..=LOW_IP => {
+                let i = ((offset - RISE_IE) / 8) as usize;
+                if offset.is_multiple_of(8) {
+                    self.ie[i]
+                } else {
+                    self.ip[i]
+                }
+            }
+            IOF_EN => self.iof_en,
+            IOF_SEL => self.iof_sel,
+            OUT_XOR => self.out_xor,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, _size: MemSize, value: u32) {
+        match offset {
+            INPUT_EN => self.input_en = value,
+            OUTPUT_EN => self.output_en = value,
+            OUTPUT_VAL => self.output_val = value,
+            PUE => self.pue = value,
+            DS => self.ds = value,
+            RISE_IE..=LOW_IP => {
+                let i = ((offset - RISE_IE) / 8) as usize;
+                if offset.is_multiple_of(8) {
+                    self.ie[i] = value;
+                } else {
+                    // Pending bits are write-one-to-clear.
+                    self.ip[i] &= !value;
+                }
+            }
+            IOF_EN => self.iof_en = value,
+            IOF_SEL => self.iof_sel = value,
+            OUT_XOR => self.out_xor = value,
+            _ => {}
+        }
+        self.update_outputs();
+        self.sample();
+    }
+
+    fn tick(&mut self, _ticks: u64) {
+        self.sample();
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        let pending = (0..4).fold(0, |pending, i| pending | self.ie[i] & self.ip[i]);
+        if hart == self.hart && pending != 0 {
+            self.line
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,84 @@
+pub mod clint;
+pub mod dma;
+pub mod gpio;
+pub mod plic;
+pub mod ram;
+pub mod spi;
+pub mod uart;
+pub mod virtio;
+
+pub use clint::Clint;
+pub use dma::DmaController;
+pub use gpio::{Gpio, GpioPins};
+pub use plic::Plic;
+pub use ram::Ram;
+pub use spi::{Spi, SpiFlash};
+pub use uart::Uart16550;
+pub use virtio::VirtioMmio;
+
+use std::io::Write;
+
+use crate::MemSize;
+use crate::bus::Dma;
+
+/// Anything that can sit on the bus, from RAM to peripherals. Offsets are relative to the base address the
+/// device was mapped at.
+pub trait Device {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32;
+    fn write(&mut self, offset: u32, size: MemSize, value: u32);
+
+    /// Let `ticks` units of guest time pass. The CPU ticks the bus once per
+    /// instruction executed.
+    fn tick(&mut self, _ticks: u64) {}
+
+    /// The `mip` bits (MSIP, MTIP, MEIP) this device is asserting for the
+    /// hart with this `mhartid`.
+    fn interrupts(&self, _hart: u64) -> u32 {
+        0
+    }
+
+    /// Ticks until this device will change its interrupt lines by itself,
+    /// or `None` if it never will. Lets a hart waiting in WFI skip ahead.
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+
+    /// Read or write RAM directly. The bus calls this after every write to
+    /// the device and every tick, so work a register write kicks off can
+    /// finish straight away.
+    fn dma(&mut self, _memory: &mut Dma<'_>) {}
+
+    /// Send whatever the device prints as a console to `output` instead.
+    /// Devices that aren't consoles ignore it.
+    fn redirect_output(&mut self, _output: Box<dyn Write>) {}
+}
+
+impl<D: Device + ?Sized> Device for Box<D> {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        (**self).read(offset, size)
+    }
+
+    fn write(&mut self, offset: u32, size: MemSize, value: u32) {
+        (**self).write(offset, size, value)
+    }
+
+    fn tick(&mut self, ticks: u64) {
+        (**self).tick(ticks)
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        (**self).interrupts(hart)
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        (**self).next_event()
+    }
+
+    fn dma(&mut self, memory: &mut Dma<'_>) {
+        (**self).dma(memory)
+    }
+
+    fn redirect_output(&mut self, output: Box<dyn Write>) {
+        (**self).redirect_output(output)
+    }
+}
@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+use super::Device;
+use crate::MemSize;
+use crate::bus::Dma;
+use crate::csr::{MIP_MEIP, MIP_SEIP};
+
+const PRIORITY: u32 = 0x0000;
+const PENDING: u32 = 0x1000;
+const ENABLE: u32 = 0x2000;
+const ENABLE_STRIDE: u32 = 0x80;
+const CONTEXT: u32 = 0x20_0000;
+const CONTEXT_STRIDE: u32 = 0x1000;
+const CLAIM: u32 = 4;
+
+/// Sources 1 to 95; source 0 means "no interrupt".
+pub const SOURCES: usize = 96;
+const WORDS: usize = SOURCES.div_ceil(32);
+
+/// Priorities and thresholds are 0 to 7.
+const PRIORITY_MASK: u32 = 7;
+
+/// The input side of a [`Plic`]: one level per source. Clones share the
+/// same lines.
+#[derive(Clone)]
+pub struct PlicLines {
+    levels: Rc<RefCell<[bool; SOURCES]>>,
+}
+
+impl PlicLines {
+    /// Drive `source` high or low, for a device the host models itself.
+    pub fn set(&self, source: u32, level: bool) {
+        assert!(
+            (1..SOURCES as u32).contains(&source),
+            "PLIC sources run from 1 to {}",
+            SOURCES - 1
+        );
+        self.levels.borrow_mut()[source as usize] = level;
+    }
+
+    fn level(&self, source: usize) -> bool {
+        self.levels.borrow()[source]
+    }
+
+    /// Wire `device` to `source`: whatever the device would raise on hart
+    /// 0 becomes the source's level, and it raises nothing in `mip`
+    /// directly.
+    pub fn route<D: Device>(&self, source: u32, device: D) -> Routed<D> {
+        self.set(source, false);
+        Routed {
+            device,
+            lines: self.clone(),
+            source,
+        }
+    }
+}
+
+/// A device whose interrupt goes through a [`Plic`]. See
+/// [`PlicLines::route`].
+pub struct Routed<D> {
+    device: D,
+    lines: PlicLines,
+    source: u32,
+}
+
+impl<D: Device> Routed<D> {
+    pub fn inner(&self) -> &D {
+        &self.device
+    }
+
+    pub fn inner_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    /// Anything the bus does to the device can change its line, so this
+    /// runs after each of them.
+    fn update(&self) {
+        let level = self.device.interrupts(0) != 0;
+        self.lines.set(self.source, level);
+    }
+}
+
+impl<D: Device> Device for Routed<D> {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        let value = self.device.read(offset, size);
+        self.update();
+        value
+    }
+
+    fn write(&mut self, offset: u32, size: MemSize, value: u32) {
+        self.device.write(offset, size, value);
+        self.update();
+    }
+
+    fn tick(&mut self, ticks: u64) {
+        self.device.tick(ticks);
+        self.update();
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        self.device.next_event()
+    }
+
+    fn dma(&mut self, memory: &mut Dma<'_>) {
+        self.device.dma(memory);
+        self.update();
+    }
+
+    fn redirect_output(&mut self, output: Box<dyn Write>) {
+        self.device.redirect_output(output);
+    }
+
+    /// Devices like the UART can have news from the host without the bus
+    /// touching them, which shows up here. The [`Plic`] only sees it if it's
+    /// mapped after the device.
+    fn interrupts(&self, _hart: u64) -> u32 {
+        self.update();
+        0
+    }
+}
+
+/// A platform-level interrupt controller laid out like SiFive's and
+/// QEMU's `virt` one: a priority per source, then per context a set of
+/// enables, a threshold and a claim/complete register. Hart `h` has two
+/// contexts, `2h` for M-mode (MEIP) and `2h + 1` for S-mode (SEIP).
+///
+/// Sources are level-triggered: one is pending while its line is high and
+/// it hasn't been claimed, and a claim hands out the highest-priority
+/// pending source above the context's threshold, lowest number first on a
+/// tie. Devices reach their line through [`lines`](Self::lines).
+pub struct Plic {
+    lines: PlicLines,
+    priority: [u32; SOURCES],
+    enable: Vec<[u32; WORDS]>,
+    threshold: Vec<u32>,
+    /// Sources claimed but not completed yet.
+    claimed: [bool; SOURCES],
+}
+
+impl Plic {
+    /// Where QEMU's `virt` machine puts its PLIC.
+    pub const BASE: u32 = 0x0C00_0000;
+    pub const SIZE: u32 = 0x0400_0000;
+
+    pub fn new(harts: usize) -> Self {
+        Self {
+            lines: PlicLines {
+                levels: Rc::new(RefCell::new([false; SOURCES])),
+            },
+            priority: [0; SOURCES],
+            enable: vec![[0; WORDS]; 2 * harts],
+            threshold: vec![0; 2 * harts],
+            claimed: [false; SOURCES],
+        }
+    }
+
+    pub fn lines(&self) -> PlicLines {
+        self.lines.clone()
+    }
+
+    fn pending(&self, source: usize) -> bool {
+        self.lines.level(source) && !self.claimed[source]
+    }
+
+    fn enabled(&self, context: usize, source: usize) -> bool {
+        self.enable[context][source / 32] >> (source % 32) & 1 != 0
+    }
+
+    /// The source `context` would get if it claimed now.
+    fn best(&self, context: usize) -> Option<usize> {
+        let mut best = None;
+        let mut max = self.threshold[context];
+        for source in 1..SOURCES {
+            if self.priority[source] > max && self.pending(source) && self.enabled(context, source)
+            {
+                best = Some(source);
+                max = self.priority[source];
+            }
+        }
+        best
+    }
+
+    /// The context and register within it that `offset` lands in.
+    fn context(&self, offset: u32) -> Option<(usize, u32)> {
+        let context = ((offset - CONTEXT) / CONTEXT_STRIDE) as usize;
+        (context < self.threshold.len()).then_some((context, offset % CONTEXT_STRIDE))
+    }
+}
+
+impl Device for Plic {
+    fn read(&mut self, offset: u32, _size: MemSize) -> u32 {
+        match offset {
+            PRIORITY..PENDING => {
+                let source = (offset / 4) as usize;
+                self.priority.get(source).copied().unwrap_or(0)
+            }
+            PENDING..ENABLE => {
+                let word = ((offset - PENDING) / 4) as usize;
+                (0..32)
+                    .map(|bit| word * 32 + bit)
+                    .filter(|&source| source < SOURCES && self.pending(source))
+                    .fold(0, |bits, source| bits | 1 << (source % 32))
+            }
+            ENABLE..CONTEXT => {
+                let context = ((offset - ENABLE) / ENABLE_STRIDE) as usize;
+                let word = ((offset - ENABLE) % ENABLE_STRIDE / 4) as usize;
+                self.enable
+                    .get(context)
+                    .and_then(|words| words.get(word))
+                    .copied()
+                    .unwrap_or(0)
+            }
+            _ => match self.context(offset) {
+                Some((context, 0)) => self.threshold[context],
+                Some((context, CLAIM)) => match self.best(context) {
+                    Some(source) => {
+                        self.claimed[source] = true;
+                        source as u32
+                    }
+                    None => 0,
+                },
+                _ => 0,
+            },
+        }
+    }
+
+    fn write(&mut self, offset: u32, _size: MemSize, value: u32) {
+        match offset {
+            PRIORITY..PENDING => {
+                if let Some(priority) = self.priority.get_mut((offset / 4) as usize) {
+                    *priority = value & PRIORITY_MASK;
+                }
+            }
+            // Pending bits are read-only.
+            PENDING..ENABLE => {}
+            ENABLE..CONTEXT => {
+                let context = ((offset - ENABLE) / ENABLE_STRIDE) as usize;
+                let word = ((offset - ENABLE) % ENABLE_STRIDE / 4) as usize;
+                if let Some(bits) = self
+                    .enable
+                    .get_mut(context)
+                    .and_then(|words| words.get_mut(word))
+                {
+                    // Source 0 doesn't exist.
+                    *bits = if word == 0 { value & !1 } else { value };
+                }
+            }
+            _ => match self.context(offset) {
+                Some((context, 0)) => self.threshold[context] = value & PRIORITY_MASK,
+                // Completing a source the context can't see does nothing.
+                Some((context, CLAIM)) => {
+                    let source = value as usize;
+                    if source < SOURCES && self.enabled(context, source) {
+                        self.claimed[source] = false;
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        let context = 2 * hart as usize;
+        if context + 1 >= self.threshold.len() {
+            return 0;
+        }
+        let mut lines = 0;
+        if self.best(context).is_some() {
+            lines |= MIP_MEIP;
+        }
+        if self.best(context + 1).is_some() {
+            lines |= MIP_SEIP;
+        }
+        lines
+    }
+}
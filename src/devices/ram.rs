@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::fs::OpenOptions;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+use memmap2::MmapMut;
+
+use super::Device;
+use crate::MemSize;
+
+const PAGE_SIZE: usize = 4096;
+
+enum Storage {
+    Flat(Vec<u8>),
+    /// A shared mapping of a host file. The OS pages it in on demand and
+    /// guest writes land in the file.
+    #[cfg(feature = "std")]
+    Mapped(MmapMut),
+    /// 4 KiB pages allocated on first write. Untouched pages read as zero.
+    Sparse {
+        pages: HashMap<usize, Box<[u8; PAGE_SIZE]>>,
+        len: usize,
+    },
+}
+
+/// Plain little-endian byte-addressable memory.
+pub struct Ram {
+    storage: Storage,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Self {
+        Self {
+            storage: Storage::Flat(vec![0; size]),
+        }
+    }
+
+    /// RAM that only takes up host memory for the pages the guest has
+    /// written, so it can be as large as the address space.
+    pub fn sparse(size: usize) -> Self {
+        Self {
+            storage: Storage::Sparse {
+                pages: HashMap::new(),
+                len: size,
+            },
+        }
+    }
+
+    /// RAM backed by the file at `path`, which is created or grown to `size`
+    /// bytes as needed. Existing contents become the initial memory image
+    /// and guest writes go straight back to the file.
+    #[cfg(feature = "std")]
+    pub fn map_file(path: impl AsRef<Path>, size: usize) -> Result<Self, String> {
+        let path = path.as_ref();
+        let err = |e: std::io::Error| format!("Can't map {}: {}", path.display(), e);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(err)?;
+
+        if file.metadata().map_err(err)?.len() < size as u64 {
+            file.set_len(size as u64).map_err(err)?;
+        }
+
+        // SAFETY: the mapping is only reachable through this `Ram`. Another
+        // process changing the file underneath us shows up as guest memory
+        // changing, which is no worse than a DMA-capable device.
+        let map = unsafe { memmap2::MmapOptions::new().len(size).map_mut(&file) }.map_err(err)?;
+
+        Ok(Self {
+            storage: Storage::Mapped(map),
+        })
+    }
+
+    /// Write a file-backed RAM's dirty pages out to disk. Does nothing for
+    /// other kinds of RAM.
+    pub fn flush(&self) -> Result<(), String> {
+        #[cfg(feature = "std")]
+        if let Storage::Mapped(map) = &self.storage {
+            return map.flush().map_err(|e| format!("Can't flush RAM: {}", e));
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Flat(data) => data.len(),
+            #[cfg(feature = "std")]
+            Storage::Mapped(map) => map.len(),
+            Storage::Sparse { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.storage, Storage::Sparse { .. })
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        #[cfg(feature = "std")]
+        return matches!(self.storage, Storage::Mapped(_));
+        #[cfg(not(feature = "std"))]
+        false
+    }
+
+    /// Bytes of host memory set aside for the guest's RAM. A mapped file
+    /// counts in full even though the OS pages it in lazily.
+    pub fn resident_bytes(&self) -> usize {
+        match &self.storage {
+            Storage::Sparse { pages, .. } => pages.len() * PAGE_SIZE,
+            _ => self.len(),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If the RAM is sparse, since it isn't contiguous. Use
+    /// [`read_bytes`](Self::read_bytes) instead.
+    pub fn as_slice(&self) -> &[u8] {
+        self.contiguous()
+            .expect("sparse RAM can't be borrowed as a slice")
+    }
+
+    /// # Panics
+    ///
+    /// If the RAM is sparse. Use [`write_bytes`](Self::write_bytes) instead.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        self.contiguous_mut()
+            .expect("sparse RAM can't be borrowed as a slice")
+    }
+
+    fn contiguous(&self) -> Option<&[u8]> {
+        match &self.storage {
+            Storage::Flat(data) => Some(data),
+            #[cfg(feature = "std")]
+            Storage::Mapped(map) => Some(map),
+            Storage::Sparse { .. } => None,
+        }
+    }
+
+    fn contiguous_mut(&mut self) -> Option<&mut [u8]> {
+        match &mut self.storage {
+            Storage::Flat(data) => Some(data),
+            #[cfg(feature = "std")]
+            Storage::Mapped(map) => Some(map),
+            Storage::Sparse { .. } => None,
+        }
+    }
+
+    /// Fill `buf` from `offset`. The caller checks that the range is in
+    /// bounds.
+    pub fn read_bytes(&self, offset: usize, buf: &mut [u8]) {
+        match &self.storage {
+            Storage::Sparse { pages, .. } => {
+                for (i, byte) in buf.iter_mut().enumerate() {
+                    let a = offset + i;
+                    *byte = pages
+                        .get(&(a / PAGE_SIZE))
+                        .map_or(0, |page| page[a % PAGE_SIZE]);
+                }
+            }
+            _ => buf.copy_from_slice(&self.as_slice()[offset..offset + buf.len()]),
+        }
+    }
+
+    /// Copy `bytes` in at `offset`. Zeros written to pages that were never
+    /// touched don't allocate them.
+    pub fn write_bytes(&mut self, offset: usize, bytes: &[u8]) {
+        let Storage::Sparse { pages, .. } = &mut self.storage else {
+            self.as_mut_slice()[offset..offset + bytes.len()].copy_from_slice(bytes);
+            return;
+        };
+
+        let mut a = offset;
+        let mut rest = bytes;
+
+        while !rest.is_empty() {
+            let start = a % PAGE_SIZE;
+            let n = rest.len().min(PAGE_SIZE - start);
+            let (chunk, tail) = rest.split_at(n);
+
+            let page = pages.get_mut(&(a / PAGE_SIZE));
+            match page {
+                Some(page) => page[start..start + n].copy_from_slice(chunk),
+                None if chunk.iter().all(|&b| b == 0) => {}
+                None => {
+                    let mut page = Box::new([0; PAGE_SIZE]);
+                    page[start..start + n].copy_from_slice(chunk);
+                    pages.insert(a / PAGE_SIZE, page);
+                }
+            }
+
+            a += n;
+            rest = tail;
+        }
+    }
+
+    /// The whole of RAM as one buffer. This materializes sparse RAM in full.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut out = vec![0; self.len()];
+        self.read_bytes(0, &mut out);
+        out
+    }
+}
+
+impl Device for Ram {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        let a = offset as usize;
+
+        let Some(data) = self.contiguous() else {
+            let mut bytes = [0; 4];
+            self.read_bytes(a, &mut bytes[..size.bytes()]);
+            return u32::from_le_bytes(bytes);
+        };
+
+        match size {
+            MemSize::Byte => data[a] as u32,
+            MemSize::Half => u16::from_le_bytes([data[a], data[a + 1]]) as u32,
+            MemSize::Word => u32::from_le_bytes([data[a], data[a + 1], data[a + 2], data[a + 3]]),
+        }
+    }
+
+    fn write(&mut self, offset: u32, size: MemSize, value: u32) {
+        let bytes = value.to_le_bytes();
+        self.write_bytes(offset as usize, &bytes[..size.bytes()]);
+    }
+}
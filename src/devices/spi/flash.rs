@@ -0,0 +1,198 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::SpiDevice;
+use crate::MemSize;
+use crate::devices::Device;
+
+const PAGE_PROGRAM: u8 = 0x02;
+const READ: u8 = 0x03;
+const WRITE_DISABLE: u8 = 0x04;
+const READ_STATUS: u8 = 0x05;
+const WRITE_ENABLE: u8 = 0x06;
+const FAST_READ: u8 = 0x0B;
+const SECTOR_ERASE: u8 = 0x20;
+const CHIP_ERASE: u8 = 0xC7;
+const CHIP_ERASE_ALT: u8 = 0x60;
+const READ_ID: u8 = 0x9F;
+const BLOCK_ERASE: u8 = 0xD8;
+
+/// Status register: write enable latch. Busy (bit 0) is never set, because
+/// programs and erases finish at once.
+const STATUS_WEL: u8 = 1 << 1;
+
+const PAGE_SIZE: usize = 256;
+const SECTOR_SIZE: usize = 4 << 10;
+const BLOCK_SIZE: usize = 64 << 10;
+
+/// A NOR flash chip with 24-bit addresses, answering the JEDEC commands
+/// every SPI flash shares: read ID, read and fast read, write enable and
+/// disable, read status, page program and sector, block and chip erase.
+/// Programming only clears bits, and erasing sets them back to ones.
+///
+/// Clones share the same contents, so one can go on a [`Spi`](super::Spi)
+/// while another is read by the host or mapped for execute-in-place with
+/// [`xip`](Self::xip).
+#[derive(Clone)]
+pub struct SpiFlash {
+    memory: Rc<RefCell<Vec<u8>>>,
+    command: Option<u8>,
+    /// Bytes shifted in since the command byte.
+    count: usize,
+    address: usize,
+    status: u8,
+}
+
+impl SpiFlash {
+    /// An erased chip of `size` bytes, a power of two no bigger than 16 MiB.
+    pub fn new(size: usize) -> Self {
+        assert!(
+            size.is_power_of_two() && size <= 1 << 24,
+            "flash size must be a power of two up to 16 MiB"
+        );
+        Self {
+            memory: Rc::new(RefCell::new(vec![0xFF; size])),
+            command: None,
+            count: 0,
+            address: 0,
+            status: 0,
+        }
+    }
+
+    /// A chip of `size` bytes with `image` programmed at its start.
+    pub fn with_image(size: usize, image: &[u8]) -> Result<Self, String> {
+        if image.len() > size {
+            return Err(format!(
+                "image of {} bytes doesn't fit in {} bytes of flash",
+                image.len(),
+                size
+            ));
+        }
+        let flash = Self::new(size);
+        flash.memory.borrow_mut()[..image.len()].copy_from_slice(image);
+        Ok(flash)
+    }
+
+    pub fn size(&self) -> usize {
+        self.memory.borrow().len()
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        self.memory.borrow().clone()
+    }
+
+    /// The chip as read-only memory, for mapping at [`FlashXip::BASE`].
+    pub fn xip(&self) -> FlashXip {
+        FlashXip {
+            memory: self.memory.clone(),
+        }
+    }
+
+    /// Winbond's manufacturer ID, a serial-flash memory type and the
+    /// capacity as a power of two.
+    fn id(&self) -> [u8; 3] {
+        [0xEF, 0x40, self.size().trailing_zeros() as u8]
+    }
+
+    fn erase(&mut self, len: usize) {
+        let len = len.min(self.size());
+        let start = self.address & !(len - 1) & (self.size() - 1);
+        self.memory.borrow_mut()[start..start + len].fill(0xFF);
+    }
+
+    /// Runs once chip select goes high, as a real chip does for commands
+    /// that change its contents or status.
+    fn finish(&mut self) {
+        let writable = self.status & STATUS_WEL != 0;
+        match self.command {
+            Some(WRITE_ENABLE) => self.status |= STATUS_WEL,
+            Some(WRITE_DISABLE) => self.status &= !STATUS_WEL,
+            Some(SECTOR_ERASE) if writable && self.count >= 3 => self.erase(SECTOR_SIZE),
+            Some(BLOCK_ERASE) if writable && self.count >= 3 => self.erase(BLOCK_SIZE),
+            Some(CHIP_ERASE | CHIP_ERASE_ALT) if writable => {
+                self.memory.borrow_mut().fill(0xFF);
+            }
+            _ => {}
+        }
+        if matches!(
+            self.command,
+            Some(PAGE_PROGRAM | SECTOR_ERASE | BLOCK_ERASE | CHIP_ERASE | CHIP_ERASE_ALT)
+        ) {
+            self.status &= !STATUS_WEL;
+        }
+    }
+}
+
+impl SpiDevice for SpiFlash {
+    fn select(&mut self) {
+        self.command = None;
+        self.count = 0;
+        self.address = 0;
+    }
+
+    fn deselect(&mut self) {
+        self.finish();
+        self.command = None;
+    }
+
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let Some(command) = self.command else {
+            self.command = Some(byte);
+            return 0xFF;
+        };
+        let n = self.count;
+        self.count += 1;
+
+        // The three address bytes, most significant first.
+        if matches!(
+            command,
+            READ | FAST_READ | PAGE_PROGRAM | SECTOR_ERASE | BLOCK_ERASE
+        ) && n < 3
+        {
+            self.address = self.address << 8 | byte as usize;
+            return 0xFF;
+        }
+
+        let mask = self.size() - 1;
+        match command {
+            READ_ID => self.id().get(n).copied().unwrap_or(0),
+            READ_STATUS => self.status,
+            READ => self.memory.borrow()[(self.address + n - 3) & mask],
+            // One dummy byte after the address.
+            FAST_READ if n == 3 => 0xFF,
+            FAST_READ => self.memory.borrow()[(self.address + n - 4) & mask],
+            PAGE_PROGRAM if self.status & STATUS_WEL != 0 => {
+                // Wraps around within the page rather than running on.
+                let page = self.address & !(PAGE_SIZE - 1);
+                let at = (page | ((self.address + n - 3) % PAGE_SIZE)) & mask;
+                self.memory.borrow_mut()[at] &= byte;
+                0xFF
+            }
+            _ => 0xFF,
+        }
+    }
+}
+
+/// A [`SpiFlash`] mapped straight onto the bus, the way the FE310's QSPI0
+/// controller lets code execute in place from its flash. Writes are
+/// ignored. Run `fence.i` before executing code reprogrammed over SPI.
+pub struct FlashXip {
+    memory: Rc<RefCell<Vec<u8>>>,
+}
+
+impl FlashXip {
+    /// Where the FE310 maps its boot flash.
+    pub const BASE: u32 = 0x2000_0000;
+}
+
+impl Device for FlashXip {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        let memory = self.memory.borrow();
+        (0..size.bytes()).fold(0, |value, i| {
+            let byte = memory.get(offset as usize + i).copied().unwrap_or(0xFF);
+            value | (byte as u32) << (8 * i)
+        })
+    }
+
+    fn write(&mut self, _offset: u32, _size: MemSize, _value: u32) {}
+}
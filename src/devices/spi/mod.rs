@@ -0,0 +1,253 @@
+pub mod flash;
+
+pub use flash::{FlashXip, SpiFlash};
+
+use std::collections::VecDeque;
+
+use super::Device;
+use crate::MemSize;
+use crate::csr::MIP_MEIP;
+
+const SCKDIV: u32 = 0x00;
+const SCKMODE: u32 = 0x04;
+const CSID: u32 = 0x10;
+const CSDEF: u32 = 0x14;
+const CSMODE: u32 = 0x18;
+const DELAY0: u32 = 0x28;
+const DELAY1: u32 = 0x2C;
+const FMT: u32 = 0x40;
+const TXDATA: u32 = 0x48;
+const RXDATA: u32 = 0x4C;
+const TXMARK: u32 = 0x50;
+const RXMARK: u32 = 0x54;
+const FCTRL: u32 = 0x60;
+const FFMT: u32 = 0x64;
+const IE: u32 = 0x70;
+const IP: u32 = 0x74;
+
+const CSMODE_AUTO: u32 = 0;
+const CSMODE_HOLD: u32 = 2;
+const CSMODE_OFF: u32 = 3;
+
+/// `fmt.dir`: transmit only, so nothing goes into the receive FIFO.
+const FMT_DIR_TX: u32 = 1 << 3;
+
+const IP_TXWM: u32 = 1 << 0;
+const IP_RXWM: u32 = 1 << 1;
+
+/// `rxdata`'s flag for an empty FIFO.
+const RX_EMPTY: u32 = 1 << 31;
+const FIFO_DEPTH: usize = 8;
+const CHIP_SELECTS: usize = 4;
+
+/// Something on the other end of a SPI bus.
+pub trait SpiDevice {
+    /// Chip select was asserted: a new command starts.
+    fn select(&mut self) {}
+
+    fn deselect(&mut self) {}
+
+    /// Shift one byte out to the device and the device's byte back in.
+    fn transfer(&mut self, byte: u8) -> u8;
+}
+
+/// A SiFive-style SPI master, as on the FE310, with up to four devices on
+/// its chip selects. Transfers finish as soon as `txdata` is written, so
+/// the transmit FIFO is always empty and received bytes are waiting in
+/// `rxdata` straight away.
+///
+/// With `csmode` AUTO, chip select is asserted around each byte; with HOLD
+/// it stays asserted from the first byte until `csmode`, `csid` or `csdef`
+/// changes, which is how multi-byte commands are sent. Its watermark
+/// interrupts raise MEIP on hart 0 unless
+/// [`with_interrupt`](Self::with_interrupt) picks another line.
+pub struct Spi {
+    devices: Vec<Option<Box<dyn SpiDevice>>>,
+    rx: VecDeque<u8>,
+    sckdiv: u32,
+    sckmode: u32,
+    csid: u32,
+    csdef: u32,
+    csmode: u32,
+    delay: [u32; 2],
+    fmt: u32,
+    txmark: u32,
+    rxmark: u32,
+    fctrl: u32,
+    ffmt: u32,
+    ie: u32,
+    /// Which device has chip select asserted, in HOLD mode.
+    selected: Option<usize>,
+    hart: u64,
+    line: u32,
+}
+
+impl Spi {
+    /// Where the FE310 puts QSPI0, the controller its boot flash is on.
+    pub const BASE: u32 = 0x1001_4000;
+    pub const SIZE: u32 = 0x1000;
+
+    pub fn new() -> Self {
+        Self {
+            devices: (0..CHIP_SELECTS).map(|_| None).collect(),
+            rx: VecDeque::new(),
+            sckdiv: 3,
+            sckmode: 0,
+            csid: 0,
+            csdef: 0xF,
+            csmode: CSMODE_AUTO,
+            delay: [0x0001_0001, 0x0000_0001],
+            fmt: 0x0008_0000,
+            txmark: 0,
+            rxmark: 0,
+            fctrl: 1,
+            ffmt: 0x0003_0007,
+            ie: 0,
+            selected: None,
+            hart: 0,
+            line: MIP_MEIP,
+        }
+    }
+
+    /// Put `device` on chip select `cs`, replacing whatever was there.
+    pub fn attach(mut self, cs: usize, device: impl SpiDevice + 'static) -> Self {
+        assert!(
+            cs < CHIP_SELECTS,
+            "there are only {} chip selects",
+            CHIP_SELECTS
+        );
+        self.devices[cs] = Some(Box::new(device));
+        self
+    }
+
+    /// Raise the `mip` bits in `line` on the hart with this `mhartid`
+    /// instead.
+    pub fn with_interrupt(mut self, hart: u64, line: u32) -> Self {
+        self.hart = hart;
+        self.line = line;
+        self
+    }
+
+    fn device(&mut self, cs: usize) -> Option<&mut Box<dyn SpiDevice>> {
+        self.devices.get_mut(cs)?.as_mut()
+    }
+
+    fn release(&mut self) {
+        if let Some(cs) = self.selected.take()
+            && let Some(device) = self.device(cs)
+        {
+            device.deselect();
+        }
+    }
+
+    /// Shift one byte out on `csid`, minding `csmode`.
+    fn send(&mut self, byte: u8) {
+        let cs = self.csid as usize;
+        let received = match self.csmode {
+            CSMODE_OFF => None,
+            CSMODE_HOLD => {
+                if self.selected != Some(cs) {
+                    self.release();
+                    self.selected = Some(cs);
+                    if let Some(device) = self.device(cs) {
+                        device.select();
+                    }
+                }
+                self.device(cs).map(|device| device.transfer(byte))
+            }
+            _ => self.device(cs).map(|device| {
+                device.select();
+                let received = device.transfer(byte);
+                device.deselect();
+                received
+            }),
+        };
+
+        // Nothing driving MISO reads as all ones.
+        if self.fmt & FMT_DIR_TX == 0 && self.rx.len() < FIFO_DEPTH {
+            self.rx.push_back(received.unwrap_or(0xFF));
+        }
+    }
+
+    fn ip(&self) -> u32 {
+        // The transmit FIFO is always empty.
+        let mut ip = 0;
+        if self.txmark > 0 {
+            ip |= IP_TXWM;
+        }
+        if self.rx.len() > self.rxmark as usize {
+            ip |= IP_RXWM;
+        }
+        ip
+    }
+}
+
+impl Default for Spi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Spi {
+    fn read(&mut self, offset: u32, _size: MemSize) -> u32 {
+        match offset {
+            SCKDIV => self.sckdiv,
+            SCKMODE => self.sckmode,
+            CSID => self.csid,
+            CSDEF => self.csdef,
+            CSMODE => self.csmode,
+            DELAY0 => self.delay[0],
+            DELAY1 => self.delay[1],
+            FMT => self.fmt,
+            // Never full.
+            TXDATA => 0,
+            RXDATA => self.rx.pop_front().map_or(RX_EMPTY, |byte| byte as u32),
+            TXMARK => self.txmark,
+            RXMARK => self.rxmark,
+            FCTRL => self.fctrl,
+            FFMT => self.ffmt,
+            IE => self.ie,
+            IP => self.ip(),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, _size: MemSize, value: u32) {
+        match offset {
+            SCKDIV => self.sckdiv = value & 0xFFF,
+            SCKMODE => self.sckmode = value & 3,
+            CSID => {
+                self.release();
+                self.csid = value;
+            }
+            CSDEF => {
+                self.release();
+                self.csdef = value;
+            }
+            CSMODE => {
+                if value & 3 != CSMODE_HOLD {
+                    self.release();
+                }
+                self.csmode = value & 3;
+            }
+            DELAY0 => self.delay[0] = value,
+            DELAY1 => self.delay[1] = value,
+            FMT => self.fmt = value,
+            TXDATA => self.send(value as u8),
+            TXMARK => self.txmark = value & 7,
+            RXMARK => self.rxmark = value & 7,
+            FCTRL => self.fctrl = value & 1,
+            FFMT => self.ffmt = value,
+            IE => self.ie = value & (IP_TXWM | IP_RXWM),
+            _ => {}
+        }
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        if hart == self.hart && self.ie & self.ip() != 0 {
+            self.line
+        } else {
+            0
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::io::Read;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+#[cfg(feature = "std")]
+use std::thread;
+
+use super::Device;
+use crate::MemSize;
+use crate::csr::MIP_MEIP;
+
+const RBR: u32 = 0; // receive buffer (read, DLAB = 0)
+const THR: u32 = 0; // transmit holding (write, DLAB = 0)
+const IER: u32 = 1;
+const IIR: u32 = 2; // interrupt identification (read)
+const FCR: u32 = 2; // FIFO control (write)
+const LCR: u32 = 3;
+const MCR: u32 = 4;
+const LSR: u32 = 5;
+const MSR: u32 = 6;
+const SCR: u32 = 7;
+
+const IER_RDA: u8 = 1 << 0;
+const IER_THRE: u8 = 1 << 1;
+
+const IIR_NONE: u8 = 0x01;
+const IIR_THRE: u8 = 0x02;
+const IIR_RDA: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+const FCR_ENABLE: u8 = 1 << 0;
+const FCR_CLEAR_RX: u8 = 1 << 1;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+pub const LSR_DR: u8 = 1 << 0;
+pub const LSR_THRE: u8 = 1 << 5;
+pub const LSR_TEMT: u8 = 1 << 6;
+
+/// A 16550-compatible UART. Bytes written to THR go to the output sink
+/// (stdout by default) and bytes sent through [`Uart16550::input`] show up
+/// in RBR.
+///
+/// The interrupts IER enables raise MEIP on hart 0 unless
+/// [`with_interrupt`](Self::with_interrupt) picks another line.
+pub struct Uart16550 {
+    output: Box<dyn Write>,
+    input_tx: Sender<u8>,
+    input_rx: Receiver<u8>,
+    /// Filled from `input_rx` lazily, including when the bus asks about
+    /// interrupts, so a byte from the host can wake a hart in WFI.
+    rx_fifo: RefCell<VecDeque<u8>>,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+    hart: u64,
+    line: u32,
+}
+
+impl Uart16550 {
+    /// Where QEMU's `virt` machine puts its UART.
+    pub const BASE: u32 = 0x1000_0000;
+    pub const SIZE: u32 = 0x100;
+
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        let (input_tx, input_rx) = mpsc::channel();
+
+        Self {
+            output,
+            input_tx,
+            input_rx,
+            rx_fifo: RefCell::new(VecDeque::new()),
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            hart: 0,
+            line: MIP_MEIP,
+        }
+    }
+
+    /// Raise the `mip` bits in `line` on the hart with this `mhartid`
+    /// instead.
+    pub fn with_interrupt(mut self, hart: u64, line: u32) -> Self {
+        self.hart = hart;
+        self.line = line;
+        self
+    }
+
+    /// A handle the host can use to feed bytes to the guest.
+    pub fn input(&self) -> Sender<u8> {
+        self.input_tx.clone()
+    }
+
+    /// Forward host stdin to the receiver on a background thread.
+    #[cfg(feature = "std")]
+    pub fn attach_stdin(&self) {
+        let tx = self.input();
+        thread::spawn(move || {
+            let mut buf = [0u8; 256];
+            let mut stdin = io::stdin();
+
+            while let Ok(n) = stdin.read(&mut buf) {
+                if n == 0 || buf[..n].iter().any(|&b| tx.send(b).is_err()) {
+                    break;
+                }
+            }
+        });
+    }
+
+    pub fn interrupt_pending(&mut self) -> bool {
+        self.iir() != IIR_NONE
+    }
+
+    fn poll_input(&self) {
+        let mut fifo = self.rx_fifo.borrow_mut();
+        while let Ok(byte) = self.input_rx.try_recv() {
+            fifo.push_back(byte);
+        }
+    }
+
+    fn lsr(&self) -> u8 {
+        self.poll_input();
+
+        // Transmission is instantaneous, so the transmitter is always empty.
+        let mut lsr = LSR_THRE | LSR_TEMT;
+        if !self.rx_fifo.borrow().is_empty() {
+            lsr |= LSR_DR;
+        }
+        lsr
+    }
+
+    fn iir(&mut self) -> u8 {
+        let fifo = if self.fcr & FCR_ENABLE != 0 {
+            IIR_FIFO_ENABLED
+        } else {
+            0
+        };
+
+        let lsr = self.lsr();
+        let id = if self.ier & IER_RDA != 0 && lsr & LSR_DR != 0 {
+            IIR_RDA
+        } else if self.ier & IER_THRE != 0 {
+            IIR_THRE
+        } else {
+            IIR_NONE
+        };
+
+        fifo | id
+    }
+
+    fn dlab(&self) -> bool {
+        self.lcr & LCR_DLAB != 0
+    }
+}
+
+impl Default for Uart16550 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Uart16550 {
+    fn read(&mut self, offset: u32, _size: MemSize) -> u32 {
+        let value = match offset {
+            RBR if self.dlab() => self.dll,
+            RBR => {
+                self.poll_input();
+                self.rx_fifo.get_mut().pop_front().unwrap_or(0)
+            }
+            IER if self.dlab() => self.dlm,
+            IER => self.ier,
+            IIR => self.iir(),
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => self.lsr(),
+            MSR => 0,
+            SCR => self.scr,
+            _ => 0,
+        };
+
+        value as u32
+    }
+
+    fn write(&mut self, offset: u32, _size: MemSize, value: u32) {
+        let value = value as u8;
+
+        match offset {
+            THR if self.dlab() => self.dll = value,
+            THR => {
+                let _ = self.output.write_all(&[value]);
+                let _ = self.output.flush();
+            }
+            IER if self.dlab() => self.dlm = value,
+            IER => self.ier = value & 0x0F,
+            FCR => {
+                if value & FCR_CLEAR_RX != 0 {
+                    self.rx_fifo.get_mut().clear();
+                }
+                self.fcr = value;
+            }
+            LCR => self.lcr = value,
+            MCR => self.mcr = value,
+            SCR => self.scr = value,
+            _ => {}
+        }
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        self.poll_input();
+        let rda = self.ier & IER_RDA != 0 && !self.rx_fifo.borrow().is_empty();
+        let thre = self.ier & IER_THRE != 0;
+        if hart == self.hart && (rda || thre) {
+            self.line
+        } else {
+            0
+        }
+    }
+
+    fn redirect_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::{Chain, VirtioDevice, Virtqueue, read_config};
+use crate::MemSize;
+use crate::bus::Dma;
+
+pub const SECTOR_SIZE: usize = 512;
+
+pub const VIRTIO_BLK_F_RO: u64 = 1 << 5;
+pub const VIRTIO_BLK_F_FLUSH: u64 = 1 << 9;
+
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+const T_GET_ID: u32 = 8;
+
+const S_OK: u8 = 0;
+const S_IOERR: u8 = 1;
+const S_UNSUPP: u8 = 2;
+
+const HEADER_SIZE: usize = 16;
+const ID_SIZE: usize = 20;
+
+/// How a [`VirtioBlk`] treats its image file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiskMode {
+    /// Guest writes go straight to the file.
+    ReadWrite,
+    /// The guest is told the disk is read-only, and writes fail.
+    ReadOnly,
+    /// Guest writes are kept in memory on top of the file, which is never
+    /// changed. They're lost when the device is dropped.
+    CopyOnWrite,
+}
+
+/// A virtio block device backed by a host image file. The capacity is the
+/// file's size in whole 512-byte sectors.
+pub struct VirtioBlk {
+    file: File,
+    mode: DiskMode,
+    sectors: u64,
+    /// Sectors written since a copy-on-write image was opened.
+    overlay: HashMap<u64, Box<[u8; SECTOR_SIZE]>>,
+    serial: String,
+}
+
+impl VirtioBlk {
+    pub fn open(path: impl AsRef<Path>, mode: DiskMode) -> Result<Self, String> {
+        let path = path.as_ref();
+        let err = |e: std::io::Error| format!("Can't open {}: {}", path.display(), e);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(mode == DiskMode::ReadWrite)
+            .open(path)
+            .map_err(err)?;
+        let sectors = file.metadata().map_err(err)?.len() / SECTOR_SIZE as u64;
+        let serial = path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+
+        Ok(Self {
+            file,
+            mode,
+            sectors,
+            overlay: HashMap::new(),
+            serial,
+        })
+    }
+
+    /// What `VIRTIO_BLK_T_GET_ID` returns, up to 20 bytes. Defaults to the
+    /// image's file name.
+    pub fn with_serial(mut self, serial: impl Into<String>) -> Self {
+        self.serial = serial.into();
+        self
+    }
+
+    /// Capacity in sectors.
+    pub fn sectors(&self) -> u64 {
+        self.sectors
+    }
+
+    pub fn mode(&self) -> DiskMode {
+        self.mode
+    }
+
+    /// Handle one request, returning its status and how many bytes of data
+    /// went to the guest.
+    fn request(&mut self, header: &[u8], chain: &Chain, memory: &mut Dma<'_>) -> (u8, usize) {
+        if header.len() < HEADER_SIZE {
+            return (S_IOERR, 0);
+        }
+        let kind = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let sector = u64::from_le_bytes(header[8..16].try_into().unwrap());
+        // The last writable byte is the status.
+        let room = chain.writable_len().saturating_sub(1);
+
+        match kind {
+            T_IN => {
+                let mut data = vec![0; room];
+                if self.read_sectors(sector, &mut data).is_err() {
+                    return (S_IOERR, 0);
+                }
+                (S_OK, chain.write(memory, 0, &data))
+            }
+            T_OUT if self.mode == DiskMode::ReadOnly => (S_IOERR, 0),
+            T_OUT => {
+                let mut data = vec![0; chain.readable_len() - HEADER_SIZE];
+                chain.read(memory, HEADER_SIZE, &mut data);
+                match self.write_sectors(sector, &data) {
+                    Ok(()) => (S_OK, 0),
+                    Err(()) => (S_IOERR, 0),
+                }
+            }
+            T_FLUSH => match self.mode {
+                DiskMode::ReadWrite if self.file.sync_data().is_err() => (S_IOERR, 0),
+                _ => (S_OK, 0),
+            },
+            T_GET_ID => {
+                let mut id = [0; ID_SIZE];
+                let serial = self.serial.as_bytes();
+                let n = serial.len().min(ID_SIZE);
+                id[..n].copy_from_slice(&serial[..n]);
+                (S_OK, chain.write(memory, 0, &id[..room.min(ID_SIZE)]))
+            }
+            _ => (S_UNSUPP, 0),
+        }
+    }
+
+    /// Whether `len` bytes from `sector` on are all on the disk. Transfers
+    /// have to be whole sectors.
+    fn in_bounds(&self, sector: u64, len: usize) -> bool {
+        len.is_multiple_of(SECTOR_SIZE)
+            && sector
+                .checked_add((len / SECTOR_SIZE) as u64)
+                .is_some_and(|end| end <= self.sectors)
+    }
+
+    fn read_sectors(&mut self, sector: u64, buf: &mut [u8]) -> Result<(), ()> {
+        if !self.in_bounds(sector, buf.len()) {
+            return Err(());
+        }
+        self.file
+            .seek(SeekFrom::Start(sector * SECTOR_SIZE as u64))
+            .and_then(|_| self.file.read_exact(buf))
+            .map_err(|_| ())?;
+
+        for (i, chunk) in buf.chunks_mut(SECTOR_SIZE).enumerate() {
+            if let Some(data) = self.overlay.get(&(sector + i as u64)) {
+                chunk.copy_from_slice(&data[..]);
+            }
+        }
+        Ok(())
+    }
+
+    fn write_sectors(&mut self, sector: u64, data: &[u8]) -> Result<(), ()> {
+        if !self.in_bounds(sector, data.len()) {
+            return Err(());
+        }
+        if self.mode == DiskMode::CopyOnWrite {
+            for (i, chunk) in data.chunks(SECTOR_SIZE).enumerate() {
+                let copy = Box::new(chunk.try_into().unwrap());
+                self.overlay.insert(sector + i as u64, copy);
+            }
+            return Ok(());
+        }
+        self.file
+            .seek(SeekFrom::Start(sector * SECTOR_SIZE as u64))
+            .and_then(|_| self.file.write_all(data))
+            .map_err(|_| ())
+    }
+}
+
+impl VirtioDevice for VirtioBlk {
+    fn device_id(&self) -> u32 {
+        2
+    }
+
+    fn features(&self) -> u64 {
+        match self.mode {
+            DiskMode::ReadOnly => VIRTIO_BLK_F_RO | VIRTIO_BLK_F_FLUSH,
+            _ => VIRTIO_BLK_F_FLUSH,
+        }
+    }
+
+    /// Just the capacity, in sectors.
+    fn read_config(&mut self, offset: u32, size: MemSize) -> u32 {
+        read_config(&self.sectors.to_le_bytes(), offset, size)
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], memory: &mut Dma<'_>) {
+        let queue = &mut queues[queue];
+        while let Some(chain) = queue.pop(memory) {
+            let mut header = [0; HEADER_SIZE];
+            let n = chain.read(memory, 0, &mut header);
+            let (status, len) = self.request(&header[..n], &chain, memory);
+
+            // Without room for a status byte there's no way to answer.
+            let used = match chain.writable_len() {
+                0 => 0,
+                writable => {
+                    chain.write(memory, writable - 1, &[status]);
+                    len + 1
+                }
+            };
+            queue.push(memory, chain.head, used as u32);
+        }
+    }
+}
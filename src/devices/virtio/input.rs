@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use super::{VirtioDevice, Virtqueue, read_config};
+use crate::MemSize;
+use crate::bus::Dma;
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+
+pub const REL_X: u16 = 0x00;
+pub const REL_Y: u16 = 0x01;
+pub const REL_WHEEL: u16 = 0x08;
+
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_RIGHT: u16 = 0x111;
+pub const BTN_MIDDLE: u16 = 0x112;
+
+/// The highest key code a keyboard reports, KEY_MICMUTE.
+const KEY_MAX: u16 = 0xF8;
+
+const CFG_UNSET: u8 = 0x00;
+const CFG_ID_NAME: u8 = 0x01;
+const CFG_ID_DEVIDS: u8 = 0x03;
+const CFG_EV_BITS: u8 = 0x11;
+
+const EVENTQ: usize = 0;
+const STATUSQ: usize = 1;
+
+/// Ticks between checks for host events.
+const POLL_INTERVAL: u64 = 1024;
+
+/// One Linux input event, with codes from `linux/input-event-codes.h`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputEvent {
+    pub kind: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    pub fn new(kind: u16, code: u16, value: i32) -> Self {
+        Self { kind, code, value }
+    }
+
+    /// The end of a group of events that happened together.
+    pub fn sync() -> Self {
+        Self::new(EV_SYN, 0, 0)
+    }
+
+    fn to_bytes(self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[0..2].copy_from_slice(&self.kind.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.code.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.value.to_le_bytes());
+        bytes
+    }
+}
+
+/// What a [`VirtioInput`] says it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputKind {
+    Keyboard,
+    /// A relative pointer with left, right and middle buttons and a wheel.
+    Mouse,
+}
+
+/// The host's end of a [`VirtioInput`], for a display window or a test to
+/// send events through. Each call is followed by a sync event.
+#[derive(Clone)]
+pub struct InputHandle {
+    events: Sender<InputEvent>,
+}
+
+impl InputHandle {
+    /// Send events as they are, with no sync added.
+    pub fn send(&self, events: &[InputEvent]) {
+        for &event in events {
+            let _ = self.events.send(event);
+        }
+    }
+
+    /// Press or release a key or mouse button.
+    pub fn key(&self, code: u16, pressed: bool) {
+        self.send(&[
+            InputEvent::new(EV_KEY, code, pressed as i32),
+            InputEvent::sync(),
+        ]);
+    }
+
+    pub fn move_by(&self, dx: i32, dy: i32) {
+        self.send(&[
+            InputEvent::new(EV_REL, REL_X, dx),
+            InputEvent::new(EV_REL, REL_Y, dy),
+            InputEvent::sync(),
+        ]);
+    }
+
+    pub fn scroll(&self, delta: i32) {
+        self.send(&[
+            InputEvent::new(EV_REL, REL_WHEEL, delta),
+            InputEvent::sync(),
+        ]);
+    }
+}
+
+/// A virtio keyboard or mouse fed from the host through an
+/// [`InputHandle`]. Events wait on the host side until the guest has
+/// buffers for them.
+pub struct VirtioInput {
+    kind: InputKind,
+    tx: Sender<InputEvent>,
+    rx: Receiver<InputEvent>,
+    queued: VecDeque<InputEvent>,
+    select: u8,
+    subsel: u8,
+    elapsed: u64,
+}
+
+impl VirtioInput {
+    pub fn new(kind: InputKind) -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self {
+            kind,
+            tx,
+            rx,
+            queued: VecDeque::new(),
+            select: CFG_UNSET,
+            subsel: 0,
+            elapsed: 0,
+        }
+    }
+
+    pub fn keyboard() -> Self {
+        Self::new(InputKind::Keyboard)
+    }
+
+    pub fn mouse() -> Self {
+        Self::new(InputKind::Mouse)
+    }
+
+    pub fn handle(&self) -> InputHandle {
+        InputHandle {
+            events: self.tx.clone(),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.kind {
+            InputKind::Keyboard => "virtio-keyboard",
+            InputKind::Mouse => "virtio-mouse",
+        }
+    }
+
+    /// The codes this device can send for events of type `kind`.
+    fn codes(&self, kind: u16) -> Vec<u16> {
+        match (self.kind, kind) {
+            (InputKind::Keyboard, EV_KEY) => (1..=KEY_MAX).collect(),
+            (InputKind::Mouse, EV_KEY) => vec![BTN_LEFT, BTN_RIGHT, BTN_MIDDLE],
+            (InputKind::Mouse, EV_REL) => vec![REL_X, REL_Y, REL_WHEEL],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The data for the selected configuration item.
+    fn config_data(&self) -> Vec<u8> {
+        match (self.select, self.subsel) {
+            (CFG_ID_NAME, 0) => self.name().as_bytes().to_vec(),
+            // BUS_VIRTUAL, with the vendor and product QEMU uses.
+            (CFG_ID_DEVIDS, 0) => [0x06u16, 0x0627, 1, 1]
+                .iter()
+                .flat_map(|v| v.to_le_bytes())
+                .collect(),
+            (CFG_EV_BITS, kind) => {
+                let codes = self.codes(kind as u16);
+                let len = codes.iter().max().map_or(0, |&max| max as usize / 8 + 1);
+                let mut bitmap = vec![0; len];
+                for code in codes {
+                    bitmap[code as usize / 8] |= 1 << (code % 8);
+                }
+                bitmap
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn deliver(&mut self, queue: &mut Virtqueue, memory: &mut Dma<'_>) {
+        self.queued.extend(self.rx.try_iter());
+        while let Some(&event) = self.queued.front() {
+            let Some(chain) = queue.pop(memory) else {
+                return;
+            };
+            let n = chain.write(memory, 0, &event.to_bytes());
+            queue.push(memory, chain.head, n as u32);
+            self.queued.pop_front();
+        }
+    }
+}
+
+impl VirtioDevice for VirtioInput {
+    fn device_id(&self) -> u32 {
+        18
+    }
+
+    fn queues(&self) -> usize {
+        2
+    }
+
+    fn read_config(&mut self, offset: u32, size: MemSize) -> u32 {
+        let mut data = self.config_data();
+        data.truncate(128);
+        let mut config = vec![self.select, self.subsel, data.len() as u8, 0, 0, 0, 0, 0];
+        config.extend_from_slice(&data);
+        read_config(&config, offset, size)
+    }
+
+    fn write_config(&mut self, offset: u32, size: MemSize, value: u32) {
+        let bytes = value.to_le_bytes();
+        for (i, &byte) in bytes.iter().enumerate().take(size.bytes()) {
+            match offset as usize + i {
+                0 => self.select = byte,
+                1 => self.subsel = byte,
+                _ => {}
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.queued.clear();
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], memory: &mut Dma<'_>) {
+        match queue {
+            EVENTQ => self.deliver(&mut queues[EVENTQ], memory),
+            // LED changes and the like, which there's nothing to show on.
+            STATUSQ => {
+                let status = &mut queues[STATUSQ];
+                while let Some(chain) = status.pop(memory) {
+                    status.push(memory, chain.head, 0);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, ticks: u64) {
+        self.elapsed += ticks;
+    }
+
+    fn poll(&mut self, queues: &mut [Virtqueue], memory: &mut Dma<'_>) {
+        if self.elapsed >= POLL_INTERVAL {
+            self.elapsed = 0;
+            self.deliver(&mut queues[EVENTQ], memory);
+        }
+    }
+
+    /// The host can send an event at any time.
+    fn next_event(&self) -> Option<u64> {
+        Some(POLL_INTERVAL.saturating_sub(self.elapsed).max(1))
+    }
+}
@@ -0,0 +1,369 @@
+//! The virtio-mmio transport (version 2, the non-legacy layout) and split
+//! virtqueues. [`VirtioMmio`] handles the registers, feature negotiation,
+//! queue setup and interrupts; what the device actually does is up to the
+//! [`VirtioDevice`] backend it wraps.
+
+#[cfg(feature = "std")]
+pub mod blk;
+pub mod input;
+pub mod net;
+pub mod queue;
+
+#[cfg(feature = "std")]
+pub use blk::{DiskMode, VirtioBlk};
+pub use input::{InputHandle, InputKind, VirtioInput};
+#[cfg(feature = "std")]
+pub use net::UserNet;
+pub use net::{NetBackend, VirtioNet};
+pub use queue::{Chain, Virtqueue};
+
+use super::Device;
+use crate::MemSize;
+use crate::bus::Dma;
+use crate::csr::MIP_MEIP;
+
+const MAGIC_VALUE: u32 = 0x000;
+const VERSION: u32 = 0x004;
+const DEVICE_ID: u32 = 0x008;
+const VENDOR_ID: u32 = 0x00C;
+const DEVICE_FEATURES: u32 = 0x010;
+const DEVICE_FEATURES_SEL: u32 = 0x014;
+const DRIVER_FEATURES: u32 = 0x020;
+const DRIVER_FEATURES_SEL: u32 = 0x024;
+const QUEUE_SEL: u32 = 0x030;
+const QUEUE_NUM_MAX: u32 = 0x034;
+const QUEUE_NUM: u32 = 0x038;
+const QUEUE_READY: u32 = 0x044;
+const QUEUE_NOTIFY: u32 = 0x050;
+const INTERRUPT_STATUS: u32 = 0x060;
+const INTERRUPT_ACK: u32 = 0x064;
+const STATUS: u32 = 0x070;
+const QUEUE_DESC_LOW: u32 = 0x080;
+const QUEUE_DESC_HIGH: u32 = 0x084;
+const QUEUE_DRIVER_LOW: u32 = 0x090;
+const QUEUE_DRIVER_HIGH: u32 = 0x094;
+const QUEUE_DEVICE_LOW: u32 = 0x0A0;
+const QUEUE_DEVICE_HIGH: u32 = 0x0A4;
+const CONFIG_GENERATION: u32 = 0x0FC;
+const CONFIG: u32 = 0x100;
+
+/// "virt" in little-endian ASCII.
+const MAGIC: u32 = 0x7472_6976;
+/// "QEMU", which is what guests are used to seeing.
+const VENDOR: u32 = 0x554D_4551;
+
+pub const STATUS_ACKNOWLEDGE: u32 = 1;
+pub const STATUS_DRIVER: u32 = 2;
+pub const STATUS_DRIVER_OK: u32 = 4;
+pub const STATUS_FEATURES_OK: u32 = 8;
+pub const STATUS_NEEDS_RESET: u32 = 64;
+pub const STATUS_FAILED: u32 = 128;
+
+pub const INTERRUPT_USED_BUFFER: u32 = 1;
+pub const INTERRUPT_CONFIG_CHANGE: u32 = 2;
+
+/// The only feature the transport itself insists on. Legacy drivers that
+/// don't accept it aren't supported.
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+/// What sits behind a virtio-mmio transport: a block device, a network
+/// card, and so on.
+pub trait VirtioDevice {
+    /// The virtio device type, e.g. 2 for a block device.
+    fn device_id(&self) -> u32;
+
+    /// Device-specific feature bits. The transport adds
+    /// [`VIRTIO_F_VERSION_1`].
+    fn features(&self) -> u64 {
+        0
+    }
+
+    fn queues(&self) -> usize {
+        1
+    }
+
+    /// The most descriptors the driver may give each queue.
+    fn queue_size(&self) -> u16 {
+        256
+    }
+
+    /// Read the device-specific configuration space.
+    fn read_config(&mut self, _offset: u32, _size: MemSize) -> u32 {
+        0
+    }
+
+    fn write_config(&mut self, _offset: u32, _size: MemSize, _value: u32) {}
+
+    /// The driver has set DRIVER_OK, having accepted `features`.
+    fn activate(&mut self, _features: u64) {}
+
+    /// The driver has reset the device.
+    fn reset(&mut self) {}
+
+    /// The driver has made buffers available on `queues[queue]`.
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], memory: &mut Dma<'_>);
+
+    /// Let `ticks` units of guest time pass.
+    fn tick(&mut self, _ticks: u64) {}
+
+    /// Called after every tick while the driver is running, for work that
+    /// doesn't start with a notification, like an incoming packet.
+    fn poll(&mut self, _queues: &mut [Virtqueue], _memory: &mut Dma<'_>) {}
+
+    /// Ticks until `poll` may have something to do by itself, as for
+    /// [`Device::next_event`].
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Read `size` bytes of a little-endian configuration space laid out in
+/// `config`. Anything past its end reads as zero.
+pub fn read_config(config: &[u8], offset: u32, size: MemSize) -> u32 {
+    (0..size.bytes()).fold(0, |value, i| {
+        let byte = config.get(offset as usize + i).copied().unwrap_or(0);
+        value | (byte as u32) << (8 * i)
+    })
+}
+
+/// A virtio-mmio register block in front of a [`VirtioDevice`]. It raises
+/// MEIP on hart 0 while any interrupt status bit is set, unless
+/// [`with_interrupt`](Self::with_interrupt) picks another line.
+pub struct VirtioMmio<D> {
+    backend: D,
+    queues: Vec<Virtqueue>,
+    /// The used index of each queue as of the last interrupt check.
+    seen_used: Vec<u16>,
+    /// Queues the driver has notified that haven't been serviced yet.
+    notified: Vec<usize>,
+    queue_sel: u32,
+    device_features_sel: u32,
+    driver_features_sel: u32,
+    driver_features: u64,
+    status: u32,
+    interrupt_status: u32,
+    hart: u64,
+    line: u32,
+}
+
+impl<D: VirtioDevice> VirtioMmio<D> {
+    /// Where QEMU's `virt` machine puts the first of its virtio-mmio slots;
+    /// the rest follow every `SIZE` bytes.
+    pub const BASE: u32 = 0x1000_1000;
+    pub const SIZE: u32 = 0x1000;
+
+    pub fn new(backend: D) -> Self {
+        let queues = backend.queues();
+        Self {
+            backend,
+            queues: vec![Virtqueue::default(); queues],
+            seen_used: vec![0; queues],
+            notified: Vec::new(),
+            queue_sel: 0,
+            device_features_sel: 0,
+            driver_features_sel: 0,
+            driver_features: 0,
+            status: 0,
+            interrupt_status: 0,
+            hart: 0,
+            line: MIP_MEIP,
+        }
+    }
+
+    /// Raise the `mip` bits in `line` on the hart with this `mhartid`
+    /// instead.
+    pub fn with_interrupt(mut self, hart: u64, line: u32) -> Self {
+        self.hart = hart;
+        self.line = line;
+        self
+    }
+
+    pub fn backend(&self) -> &D {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut D {
+        &mut self.backend
+    }
+
+    pub fn status(&self) -> u32 {
+        self.status
+    }
+
+    pub fn interrupt_status(&self) -> u32 {
+        self.interrupt_status
+    }
+
+    fn device_features(&self) -> u64 {
+        self.backend.features() | VIRTIO_F_VERSION_1
+    }
+
+    fn queue(&mut self) -> Option<&mut Virtqueue> {
+        self.queues.get_mut(self.queue_sel as usize)
+    }
+
+    fn reset(&mut self) {
+        self.queues.fill(Virtqueue::default());
+        self.seen_used.fill(0);
+        self.notified.clear();
+        self.device_features_sel = 0;
+        self.driver_features_sel = 0;
+        self.driver_features = 0;
+        self.status = 0;
+        self.interrupt_status = 0;
+        self.backend.reset();
+    }
+
+    fn set_status(&mut self, value: u32) {
+        if value == 0 {
+            self.reset();
+            return;
+        }
+
+        let mut value = value;
+        let unsupported = self.driver_features & !self.device_features();
+        if value & STATUS_FEATURES_OK != 0
+            && self.status & STATUS_FEATURES_OK == 0
+            && (unsupported != 0 || self.driver_features & VIRTIO_F_VERSION_1 == 0)
+        {
+            value &= !STATUS_FEATURES_OK;
+        }
+        if value & STATUS_DRIVER_OK != 0 && self.status & STATUS_DRIVER_OK == 0 {
+            self.backend.activate(self.driver_features);
+        }
+        self.status = value | self.status & STATUS_NEEDS_RESET;
+    }
+
+    /// Set the low or high half of a 64-bit register.
+    fn set_half(register: &mut u64, high: bool, value: u32) {
+        *register = if high {
+            *register & 0xFFFF_FFFF | (value as u64) << 32
+        } else {
+            *register & !0xFFFF_FFFF | value as u64
+        };
+    }
+}
+
+impl<D: VirtioDevice> Device for VirtioMmio<D> {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        if offset >= CONFIG {
+            return self.backend.read_config(offset - CONFIG, size);
+        }
+
+        match offset {
+            MAGIC_VALUE => MAGIC,
+            VERSION => 2,
+            DEVICE_ID => self.backend.device_id(),
+            VENDOR_ID => VENDOR,
+            DEVICE_FEATURES => match self.device_features_sel {
+                0 => self.device_features() as u32,
+                1 => (self.device_features() >> 32) as u32,
+                _ => 0,
+            },
+            QUEUE_NUM_MAX => match self.queue() {
+                Some(_) => self.backend.queue_size() as u32,
+                None => 0,
+            },
+            QUEUE_NUM => self.queue().map_or(0, |q| q.size as u32),
+            QUEUE_READY => self.queue().map_or(0, |q| q.ready as u32),
+            INTERRUPT_STATUS => self.interrupt_status,
+            STATUS => self.status,
+            CONFIG_GENERATION => 0,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: u32, size: MemSize, value: u32) {
+        if offset >= CONFIG {
+            self.backend.write_config(offset - CONFIG, size, value);
+            return;
+        }
+
+        let max = self.backend.queue_size() as u32;
+        match offset {
+            DEVICE_FEATURES_SEL => self.device_features_sel = value,
+            DRIVER_FEATURES => match self.driver_features_sel {
+                0 => Self::set_half(&mut self.driver_features, false, value),
+                1 => Self::set_half(&mut self.driver_features, true, value),
+                _ => {}
+            },
+            DRIVER_FEATURES_SEL => self.driver_features_sel = value,
+            QUEUE_SEL => self.queue_sel = value,
+            QUEUE_NUM => {
+                if let Some(queue) = self.queue().filter(|_| value <= max) {
+                    queue.size = value as u16;
+                }
+            }
+            QUEUE_READY => {
+                if let Some(queue) = self.queue() {
+                    queue.ready = value & 1 != 0 && queue.size != 0;
+                }
+            }
+            QUEUE_NOTIFY if (value as usize) < self.queues.len() => {
+                self.notified.push(value as usize);
+            }
+            INTERRUPT_ACK => self.interrupt_status &= !value,
+            STATUS => self.set_status(value),
+            QUEUE_DESC_LOW | QUEUE_DESC_HIGH => {
+                if let Some(queue) = self.queue() {
+                    Self::set_half(&mut queue.desc, offset == QUEUE_DESC_HIGH, value);
+                }
+            }
+            QUEUE_DRIVER_LOW | QUEUE_DRIVER_HIGH => {
+                if let Some(queue) = self.queue() {
+                    Self::set_half(&mut queue.driver, offset == QUEUE_DRIVER_HIGH, value);
+                }
+            }
+            QUEUE_DEVICE_LOW | QUEUE_DEVICE_HIGH => {
+                if let Some(queue) = self.queue() {
+                    Self::set_half(&mut queue.device, offset == QUEUE_DEVICE_HIGH, value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, ticks: u64) {
+        self.backend.tick(ticks);
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        if self.status & STATUS_DRIVER_OK == 0 {
+            return None;
+        }
+        self.backend.next_event()
+    }
+
+    fn interrupts(&self, hart: u64) -> u32 {
+        if hart == self.hart && self.interrupt_status != 0 {
+            self.line
+        } else {
+            0
+        }
+    }
+
+    fn dma(&mut self, memory: &mut Dma<'_>) {
+        if self.status & STATUS_DRIVER_OK == 0 || self.status & STATUS_NEEDS_RESET != 0 {
+            self.notified.clear();
+            return;
+        }
+
+        for queue in self.notified.drain(..) {
+            self.backend.notify(queue, &mut self.queues, memory);
+        }
+        self.backend.poll(&mut self.queues, memory);
+
+        for (queue, seen) in self.queues.iter().zip(&mut self.seen_used) {
+            if queue.used() != *seen {
+                *seen = queue.used();
+                if !queue.suppressed(memory) {
+                    self.interrupt_status |= INTERRUPT_USED_BUFFER;
+                }
+            }
+        }
+        if self.queues.iter().any(|q| q.broken) {
+            self.status |= STATUS_NEEDS_RESET;
+            self.interrupt_status |= INTERRUPT_CONFIG_CHANGE;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+#[cfg(feature = "std")]
+mod packet;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub mod tap;
+#[cfg(feature = "std")]
+pub mod user;
+
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use tap::Tap;
+#[cfg(feature = "std")]
+pub use user::UserNet;
+
+use super::{VirtioDevice, Virtqueue, read_config};
+use crate::MemSize;
+use crate::bus::Dma;
+
+pub const VIRTIO_NET_F_MAC: u64 = 1 << 5;
+
+const RX: usize = 0;
+const TX: usize = 1;
+
+/// `virtio_net_hdr` as VERSION_1 lays it out. The device never offloads
+/// anything, so it's all zeros apart from `num_buffers`.
+const HEADER_SIZE: usize = 12;
+
+/// Ticks between checks for incoming frames.
+const POLL_INTERVAL: u64 = 1024;
+
+/// Where a [`VirtioNet`] sends the guest's Ethernet frames and gets the
+/// ones it receives.
+pub trait NetBackend {
+    fn send(&mut self, frame: &[u8]);
+
+    /// The next frame for the guest, if one has arrived.
+    fn recv(&mut self) -> Option<Vec<u8>>;
+
+    /// Whether a frame may still arrive without the guest sending
+    /// anything first. A hart waiting in WFI keeps polling while it can.
+    fn active(&self) -> bool {
+        true
+    }
+}
+
+/// A virtio network card: queue 0 receives, queue 1 transmits.
+pub struct VirtioNet<B> {
+    backend: B,
+    mac: [u8; 6],
+    /// A frame that arrived when the guest had no receive buffers posted.
+    pending: Option<Vec<u8>>,
+    /// Ticks since the last check.
+    elapsed: u64,
+}
+
+impl<B: NetBackend> VirtioNet<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            mac: [0x52, 0x54, 0x00, 0x12, 0x34, 0x56],
+            pending: None,
+            elapsed: 0,
+        }
+    }
+
+    pub fn with_mac(mut self, mac: [u8; 6]) -> Self {
+        self.mac = mac;
+        self
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    fn transmit(&mut self, queue: &mut Virtqueue, memory: &mut Dma<'_>) {
+        while let Some(chain) = queue.pop(memory) {
+            let frame = chain.read_all(memory);
+            if frame.len() > HEADER_SIZE {
+                self.backend.send(&frame[HEADER_SIZE..]);
+            }
+            queue.push(memory, chain.head, 0);
+        }
+    }
+
+    /// Hand the guest as many frames as it has buffers for.
+    fn receive(&mut self, queue: &mut Virtqueue, memory: &mut Dma<'_>) {
+        while let Some(frame) = self.pending.take().or_else(|| self.backend.recv()) {
+            let Some(chain) = queue.pop(memory) else {
+                self.pending = Some(frame);
+                return;
+            };
+
+            let mut header = [0; HEADER_SIZE];
+            header[10] = 1;
+            let n = chain.write(memory, 0, &header);
+            let len = n + chain.write(memory, HEADER_SIZE, &frame);
+            queue.push(memory, chain.head, len as u32);
+        }
+    }
+}
+
+impl<B: NetBackend> VirtioDevice for VirtioNet<B> {
+    fn device_id(&self) -> u32 {
+        1
+    }
+
+    fn features(&self) -> u64 {
+        VIRTIO_NET_F_MAC
+    }
+
+    fn queues(&self) -> usize {
+        2
+    }
+
+    fn read_config(&mut self, offset: u32, size: MemSize) -> u32 {
+        read_config(&self.mac, offset, size)
+    }
+
+    fn reset(&mut self) {
+        self.pending = None;
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], memory: &mut Dma<'_>) {
+        if queue == TX {
+            self.transmit(&mut queues[TX], memory);
+        }
+        // New receive buffers, or a reply to what was just sent.
+        self.receive(&mut queues[RX], memory);
+    }
+
+    fn tick(&mut self, ticks: u64) {
+        self.elapsed += ticks;
+    }
+
+    fn poll(&mut self, queues: &mut [Virtqueue], memory: &mut Dma<'_>) {
+        if self.elapsed >= POLL_INTERVAL {
+            self.elapsed = 0;
+            self.receive(&mut queues[RX], memory);
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        let waiting = self.pending.is_some() || self.backend.active();
+        waiting.then_some(POLL_INTERVAL.saturating_sub(self.elapsed).max(1))
+    }
+}
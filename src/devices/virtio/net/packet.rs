@@ -0,0 +1,200 @@
+//! Just enough Ethernet, ARP, IPv4, UDP and TCP to parse what the guest
+//! sends and build what it gets back.
+
+use std::net::Ipv4Addr;
+
+pub const ETH_HEADER: usize = 14;
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+pub const PROTO_ICMP: u8 = 1;
+pub const PROTO_TCP: u8 = 6;
+pub const PROTO_UDP: u8 = 17;
+
+pub const TCP_FIN: u8 = 0x01;
+pub const TCP_SYN: u8 = 0x02;
+pub const TCP_RST: u8 = 0x04;
+pub const TCP_PSH: u8 = 0x08;
+pub const TCP_ACK: u8 = 0x10;
+
+pub type Mac = [u8; 6];
+
+pub const BROADCAST: Mac = [0xFF; 6];
+
+pub fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([bytes[at], bytes[at + 1]])
+}
+
+pub fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+pub fn ip_at(bytes: &[u8], at: usize) -> Ipv4Addr {
+    Ipv4Addr::new(bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3])
+}
+
+/// The Internet checksum of `data`, continuing from a partial `sum`.
+pub fn checksum(data: &[u8], sum: u32) -> u16 {
+    let mut sum = data.chunks(2).fold(sum, |sum, pair| {
+        sum + u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32
+    });
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// The partial sum of the pseudo-header TCP and UDP checksums cover.
+fn pseudo_header(src: Ipv4Addr, dst: Ipv4Addr, proto: u8, len: usize) -> u32 {
+    let words = [src.octets(), dst.octets()]
+        .iter()
+        .flat_map(|ip| {
+            [
+                u16::from_be_bytes([ip[0], ip[1]]),
+                u16::from_be_bytes([ip[2], ip[3]]),
+            ]
+        })
+        .fold(0u32, |sum, word| sum + word as u32);
+    words + proto as u32 + len as u32
+}
+
+pub fn ethernet(dst: Mac, src: Mac, ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(ETH_HEADER + payload.len());
+    frame.extend_from_slice(&dst);
+    frame.extend_from_slice(&src);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// An IPv4 packet with no options.
+pub fn ipv4(src: Ipv4Addr, dst: Ipv4Addr, proto: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0; 20];
+    packet[0] = 0x45;
+    packet[2..4].copy_from_slice(&((20 + payload.len()) as u16).to_be_bytes());
+    packet[6] = 0x40; // don't fragment
+    packet[8] = 64;
+    packet[9] = proto;
+    packet[12..16].copy_from_slice(&src.octets());
+    packet[16..20].copy_from_slice(&dst.octets());
+    let sum = checksum(&packet, 0);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+    packet.extend_from_slice(payload);
+    packet
+}
+
+pub fn udp(src: (Ipv4Addr, u16), dst: (Ipv4Addr, u16), payload: &[u8]) -> Vec<u8> {
+    let len = 8 + payload.len();
+    let mut datagram = Vec::with_capacity(len);
+    datagram.extend_from_slice(&src.1.to_be_bytes());
+    datagram.extend_from_slice(&dst.1.to_be_bytes());
+    datagram.extend_from_slice(&(len as u16).to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+
+    let sum = match checksum(&datagram, pseudo_header(src.0, dst.0, PROTO_UDP, len)) {
+        0 => 0xFFFF,
+        sum => sum,
+    };
+    datagram[6..8].copy_from_slice(&sum.to_be_bytes());
+    ipv4(src.0, dst.0, PROTO_UDP, &datagram)
+}
+
+/// A TCP segment. SYNs carry an MSS option.
+pub fn tcp(
+    src: (Ipv4Addr, u16),
+    dst: (Ipv4Addr, u16),
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    payload: &[u8],
+) -> Vec<u8> {
+    let options: &[u8] = if flags & TCP_SYN != 0 {
+        &[2, 4, 0x05, 0xB4] // MSS 1460
+    } else {
+        &[]
+    };
+    let header = 20 + options.len();
+    let mut segment = Vec::with_capacity(header + payload.len());
+    segment.extend_from_slice(&src.1.to_be_bytes());
+    segment.extend_from_slice(&dst.1.to_be_bytes());
+    segment.extend_from_slice(&seq.to_be_bytes());
+    segment.extend_from_slice(&ack.to_be_bytes());
+    segment.push((header as u8 / 4) << 4);
+    segment.push(flags);
+    segment.extend_from_slice(&window.to_be_bytes());
+    segment.extend_from_slice(&[0, 0, 0, 0]);
+    segment.extend_from_slice(options);
+    segment.extend_from_slice(payload);
+
+    let sum = checksum(
+        &segment,
+        pseudo_header(src.0, dst.0, PROTO_TCP, segment.len()),
+    );
+    segment[16..18].copy_from_slice(&sum.to_be_bytes());
+    ipv4(src.0, dst.0, PROTO_TCP, &segment)
+}
+
+/// The parts of an IPv4 packet the user-mode stack looks at.
+pub struct Ipv4Packet<'a> {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub proto: u8,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Ipv4Packet<'a> {
+    /// `None` for anything malformed or fragmented.
+    pub fn parse(packet: &'a [u8]) -> Option<Self> {
+        if packet.len() < 20 || packet[0] >> 4 != 4 {
+            return None;
+        }
+        let header = (packet[0] & 0xF) as usize * 4;
+        let total = (u16_at(packet, 2) as usize).min(packet.len());
+        let fragmented = u16_at(packet, 6) & 0x3FFF != 0;
+        if header < 20 || total < header || fragmented {
+            return None;
+        }
+
+        Some(Self {
+            src: ip_at(packet, 12),
+            dst: ip_at(packet, 16),
+            proto: packet[9],
+            payload: &packet[header..total],
+        })
+    }
+}
+
+/// The parts of a TCP segment the user-mode stack looks at.
+pub struct TcpSegment<'a> {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub seq: u32,
+    pub ack: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> TcpSegment<'a> {
+    pub fn parse(segment: &'a [u8]) -> Option<Self> {
+        if segment.len() < 20 {
+            return None;
+        }
+        let header = (segment[12] >> 4) as usize * 4;
+        if header < 20 || header > segment.len() {
+            return None;
+        }
+
+        Some(Self {
+            src_port: u16_at(segment, 0),
+            dst_port: u16_at(segment, 2),
+            seq: u32_at(segment, 4),
+            ack: u32_at(segment, 8),
+            flags: segment[13],
+            window: u16_at(segment, 14),
+            payload: &segment[header..],
+        })
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_int, c_ulong};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use super::NetBackend;
+
+const TUNSETIFF: c_ulong = 0x4004_54CA;
+const IFF_TAP: i16 = 0x0002;
+const IFF_NO_PI: i16 = 0x1000;
+
+unsafe extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+/// `struct ifreq` as far as TUNSETIFF looks at it.
+#[repr(C)]
+struct IfReq {
+    name: [u8; 16],
+    flags: i16,
+    _pad: [u8; 22],
+}
+
+/// A host TAP interface, which puts the guest on whatever network the host
+/// bridges it to. The interface has to exist already (`ip tuntap add`) or
+/// the process needs CAP_NET_ADMIN.
+pub struct Tap {
+    file: File,
+    frames: Receiver<Vec<u8>>,
+}
+
+impl Tap {
+    pub fn open(name: &str) -> Result<Self, String> {
+        let err = |e: std::io::Error| format!("Can't open TAP device {}: {}", name, e);
+        if name.len() >= 16 {
+            return Err(format!("TAP device name {} is too long", name));
+        }
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")
+            .map_err(err)?;
+
+        let mut request = IfReq {
+            name: [0; 16],
+            flags: IFF_TAP | IFF_NO_PI,
+            _pad: [0; 22],
+        };
+        request.name[..name.len()].copy_from_slice(name.as_bytes());
+        // SAFETY: TUNSETIFF reads and writes an `ifreq`, which `IfReq` is
+        // laid out like, and `file` is an open tun device.
+        if unsafe { ioctl(file.as_raw_fd(), TUNSETIFF, &mut request) } < 0 {
+            return Err(err(std::io::Error::last_os_error()));
+        }
+
+        // Reads block, so they happen on their own thread.
+        let mut reader = file.try_clone().map_err(err)?;
+        let (tx, frames) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = vec![0; 65536];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 || tx.send(buf[..n].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self { file, frames })
+    }
+}
+
+impl NetBackend for Tap {
+    fn send(&mut self, frame: &[u8]) {
+        let _ = self.file.write_all(frame);
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        self.frames.try_recv().ok()
+    }
+}
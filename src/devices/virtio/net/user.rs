@@ -0,0 +1,467 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+use super::NetBackend;
+use super::packet::{self, Ipv4Packet, Mac, TcpSegment};
+
+/// The host as the guest sees it, and the router for everything else.
+pub const GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+/// The address DHCP hands out.
+pub const GUEST: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 15);
+pub const NETMASK: Ipv4Addr = Ipv4Addr::new(255, 255, 255, 0);
+
+const GATEWAY_MAC: Mac = [0x52, 0x55, 0x0A, 0x00, 0x02, 0x02];
+
+const MSS: usize = 1460;
+const WINDOW: u16 = u16::MAX;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DHCP_LEASE: u32 = 86400;
+
+/// A guest's TCP connection: (guest port, remote address, remote port),
+/// with the remote address as the guest sees it.
+type FlowKey = (u16, Ipv4Addr, u16);
+
+/// The guest's end of the link: where to send frames and what they say.
+struct Link {
+    guest_mac: Mac,
+    guest_ip: Ipv4Addr,
+    frames: VecDeque<Vec<u8>>,
+}
+
+impl Link {
+    fn send_ip(&mut self, packet: Vec<u8>) {
+        let frame = packet::ethernet(self.guest_mac, GATEWAY_MAC, packet::ETHERTYPE_IPV4, &packet);
+        self.frames.push_back(frame);
+    }
+
+    fn send_tcp(&mut self, key: FlowKey, seq: u32, ack: u32, flags: u8, payload: &[u8]) {
+        let (guest_port, remote, remote_port) = key;
+        let dst = (self.guest_ip, guest_port);
+        let segment = packet::tcp((remote, remote_port), dst, seq, ack, flags, WINDOW, payload);
+        self.send_ip(segment);
+    }
+}
+
+enum Connection {
+    /// The host `connect` is running on another thread.
+    Connecting(Receiver<io::Result<TcpStream>>),
+    Open(TcpStream),
+}
+
+struct TcpFlow {
+    connection: Connection,
+    /// The next sequence number we'll send, and the guest's latest ack.
+    seq: u32,
+    acked: u32,
+    /// The next sequence number expected from the guest.
+    ack: u32,
+    window: u32,
+    /// Data from the guest the host socket hasn't taken yet.
+    to_host: Vec<u8>,
+    guest_closed: bool,
+    host_closed: bool,
+    dead: bool,
+}
+
+/// SLIRP-style user-mode networking: the guest gets a private
+/// 10.0.2.0/24 network, and its TCP and UDP traffic is carried over
+/// ordinary host sockets, so no privileges are needed.
+///
+/// 10.0.2.2 is the gateway, answers ARP, ping and DHCP, and stands for the
+/// host's own loopback address. Inbound connections aren't supported.
+pub struct UserNet {
+    link: Link,
+    udp: HashMap<u16, UdpSocket>,
+    tcp: HashMap<FlowKey, TcpFlow>,
+    next_isn: u32,
+}
+
+impl UserNet {
+    pub fn new() -> Self {
+        Self {
+            link: Link {
+                guest_mac: packet::BROADCAST,
+                guest_ip: GUEST,
+                frames: VecDeque::new(),
+            },
+            udp: HashMap::new(),
+            tcp: HashMap::new(),
+            next_isn: 0x1000_0000,
+        }
+    }
+
+    fn arp(&mut self, request: &[u8]) {
+        let is_request = request.len() >= 28 && packet::u16_at(request, 6) == 1;
+        if !is_request || packet::ip_at(request, 24) != GATEWAY {
+            return;
+        }
+
+        let mut reply = request[..28].to_vec();
+        reply[7] = 2;
+        reply[8..14].copy_from_slice(&GATEWAY_MAC);
+        reply[14..18].copy_from_slice(&GATEWAY.octets());
+        reply[18..28].copy_from_slice(&request[8..18]);
+        let frame = packet::ethernet(
+            self.link.guest_mac,
+            GATEWAY_MAC,
+            packet::ETHERTYPE_ARP,
+            &reply,
+        );
+        self.link.frames.push_back(frame);
+    }
+
+    fn ip(&mut self, packet: Ipv4Packet<'_>) {
+        if !packet.src.is_unspecified() {
+            self.link.guest_ip = packet.src;
+        }
+
+        match packet.proto {
+            packet::PROTO_ICMP => self.icmp(packet),
+            packet::PROTO_UDP => self.udp(packet),
+            packet::PROTO_TCP => self.tcp(packet),
+            _ => {}
+        }
+    }
+
+    /// Only the gateway answers pings; there's no way to send ICMP from
+    /// the host without privileges.
+    fn icmp(&mut self, packet: Ipv4Packet<'_>) {
+        let echo = packet.payload;
+        if packet.dst != GATEWAY || echo.len() < 8 || echo[0] != 8 {
+            return;
+        }
+
+        let mut reply = echo.to_vec();
+        reply[0] = 0;
+        reply[2..4].fill(0);
+        let sum = packet::checksum(&reply, 0);
+        reply[2..4].copy_from_slice(&sum.to_be_bytes());
+        self.link.send_ip(packet::ipv4(
+            GATEWAY,
+            packet.src,
+            packet::PROTO_ICMP,
+            &reply,
+        ));
+    }
+
+    fn udp(&mut self, packet: Ipv4Packet<'_>) {
+        let datagram = packet.payload;
+        if datagram.len() < 8 {
+            return;
+        }
+        let (src_port, dst_port) = (packet::u16_at(datagram, 0), packet::u16_at(datagram, 2));
+        let len = (packet::u16_at(datagram, 4) as usize).clamp(8, datagram.len());
+        let payload = &datagram[8..len];
+
+        if dst_port == 67 {
+            self.dhcp(payload);
+            return;
+        }
+
+        let socket = match self.udp.get(&src_port) {
+            Some(socket) => socket,
+            None => {
+                let Ok(socket) = UdpSocket::bind("0.0.0.0:0") else {
+                    return;
+                };
+                if socket.set_nonblocking(true).is_err() {
+                    return;
+                }
+                self.udp.entry(src_port).or_insert(socket)
+            }
+        };
+        let _ = socket.send_to(payload, (host_addr(packet.dst), dst_port));
+    }
+
+    /// Answer DISCOVER with an offer of [`GUEST`] and REQUEST with an ack.
+    fn dhcp(&mut self, request: &[u8]) {
+        const COOKIE: [u8; 4] = [99, 130, 83, 99];
+        if request.len() < 240 || request[0] != 1 || request[236..240] != COOKIE {
+            return;
+        }
+
+        let mut options = &request[240..];
+        let mut kind = None;
+        while let [code, len, rest @ ..] = options {
+            let len = (*len as usize).min(rest.len());
+            if *code == 53 && len == 1 {
+                kind = Some(rest[0]);
+            }
+            options = &rest[len..];
+        }
+        let reply_kind = match kind {
+            Some(1) => 2,
+            Some(3) => 5,
+            _ => return,
+        };
+
+        let mut reply = vec![0; 240];
+        reply[0] = 2;
+        reply[1..3].copy_from_slice(&[1, 6]);
+        reply[4..8].copy_from_slice(&request[4..8]);
+        reply[10..12].copy_from_slice(&request[10..12]);
+        reply[16..20].copy_from_slice(&GUEST.octets());
+        reply[20..24].copy_from_slice(&GATEWAY.octets());
+        reply[28..44].copy_from_slice(&request[28..44]);
+        reply[236..240].copy_from_slice(&COOKIE);
+        reply.extend_from_slice(&[53, 1, reply_kind, 54, 4]);
+        reply.extend_from_slice(&GATEWAY.octets());
+        reply.extend_from_slice(&[51, 4]);
+        reply.extend_from_slice(&DHCP_LEASE.to_be_bytes());
+        reply.extend_from_slice(&[1, 4]);
+        reply.extend_from_slice(&NETMASK.octets());
+        reply.extend_from_slice(&[3, 4]);
+        reply.extend_from_slice(&GATEWAY.octets());
+        reply.push(255);
+
+        let datagram = packet::udp((GATEWAY, 67), (Ipv4Addr::BROADCAST, 68), &reply);
+        let frame = packet::ethernet(
+            packet::BROADCAST,
+            GATEWAY_MAC,
+            packet::ETHERTYPE_IPV4,
+            &datagram,
+        );
+        self.link.frames.push_back(frame);
+        self.link.guest_ip = GUEST;
+    }
+
+    fn tcp(&mut self, packet: Ipv4Packet<'_>) {
+        let Some(segment) = TcpSegment::parse(packet.payload) else {
+            return;
+        };
+        let key = (segment.src_port, packet.dst, segment.dst_port);
+        let flags = segment.flags;
+
+        if flags & packet::TCP_RST != 0 {
+            self.tcp.remove(&key);
+            return;
+        }
+
+        let Some(flow) = self.tcp.get_mut(&key) else {
+            if flags & (packet::TCP_SYN | packet::TCP_ACK) == packet::TCP_SYN {
+                self.connect(key, &segment);
+            } else {
+                // Nothing to talk to.
+                let len = segment.payload.len() as u32;
+                let ack = segment.seq.wrapping_add(len);
+                self.link.send_tcp(
+                    key,
+                    segment.ack,
+                    ack,
+                    packet::TCP_RST | packet::TCP_ACK,
+                    &[],
+                );
+            }
+            return;
+        };
+
+        if flags & packet::TCP_ACK != 0 {
+            flow.acked = segment.ack;
+            flow.window = segment.window as u32;
+        }
+        if flags & packet::TCP_SYN != 0 {
+            return;
+        }
+
+        let mut reply = false;
+        if !segment.payload.is_empty() {
+            if segment.seq == flow.ack && !flow.guest_closed {
+                flow.to_host.extend_from_slice(segment.payload);
+                flow.ack = flow.ack.wrapping_add(segment.payload.len() as u32);
+            }
+            reply = true;
+        }
+        let fin_seq = segment.seq.wrapping_add(segment.payload.len() as u32);
+        if flags & packet::TCP_FIN != 0 && fin_seq == flow.ack && !flow.guest_closed {
+            flow.ack = flow.ack.wrapping_add(1);
+            flow.guest_closed = true;
+            reply = true;
+        }
+        if reply {
+            self.link
+                .send_tcp(key, flow.seq, flow.ack, packet::TCP_ACK, &[]);
+            // Pass the data on now rather than at the next poll.
+            flow.poll(key, &mut self.link);
+        }
+        if flow.finished() {
+            self.tcp.remove(&key);
+        }
+    }
+
+    /// Start connecting to the host side of a new flow. The SYN-ACK goes
+    /// out once that succeeds.
+    fn connect(&mut self, key: FlowKey, syn: &TcpSegment<'_>) {
+        let addr = SocketAddr::from((host_addr(key.1), key.2));
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = tx.send(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT));
+        });
+
+        let isn = self.next_isn;
+        self.next_isn = self.next_isn.wrapping_add(64000);
+        self.tcp.insert(
+            key,
+            TcpFlow {
+                connection: Connection::Connecting(rx),
+                seq: isn,
+                acked: isn,
+                ack: syn.seq.wrapping_add(1),
+                window: syn.window as u32,
+                to_host: Vec::new(),
+                guest_closed: false,
+                host_closed: false,
+                dead: false,
+            },
+        );
+    }
+
+    /// Pick up anything that has arrived on the host sockets.
+    fn poll(&mut self) {
+        let mut buf = [0; 65536];
+        for (&port, socket) in &self.udp {
+            while let Ok((n, SocketAddr::V4(from))) = socket.recv_from(&mut buf) {
+                let src = (guest_view(*from.ip()), from.port());
+                let datagram = packet::udp(src, (self.link.guest_ip, port), &buf[..n]);
+                self.link.send_ip(datagram);
+            }
+        }
+
+        for (&key, flow) in &mut self.tcp {
+            flow.poll(key, &mut self.link);
+        }
+        self.tcp.retain(|_, flow| !flow.finished());
+    }
+}
+
+impl TcpFlow {
+    fn poll(&mut self, key: FlowKey, link: &mut Link) {
+        let stream = match &mut self.connection {
+            Connection::Connecting(rx) => {
+                match rx.try_recv() {
+                    Ok(Ok(stream)) if stream.set_nonblocking(true).is_ok() => {
+                        let flags = packet::TCP_SYN | packet::TCP_ACK;
+                        link.send_tcp(key, self.seq, self.ack, flags, &[]);
+                        self.seq = self.seq.wrapping_add(1);
+                        self.connection = Connection::Open(stream);
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    _ => {
+                        let flags = packet::TCP_RST | packet::TCP_ACK;
+                        link.send_tcp(key, 0, self.ack, flags, &[]);
+                        self.dead = true;
+                    }
+                }
+                return;
+            }
+            Connection::Open(stream) => stream,
+        };
+
+        while !self.to_host.is_empty() {
+            match stream.write(&self.to_host) {
+                Ok(n) if n > 0 => drop(self.to_host.drain(..n)),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                _ => {
+                    self.reset(key, link);
+                    return;
+                }
+            }
+        }
+        if self.guest_closed && self.to_host.is_empty() {
+            let _ = stream.shutdown(Shutdown::Write);
+        }
+
+        let mut buf = [0; MSS];
+        while !self.host_closed {
+            let in_flight = self.seq.wrapping_sub(self.acked);
+            let room = (self.window.saturating_sub(in_flight) as usize).min(MSS);
+            if room == 0 {
+                break;
+            }
+            match stream.read(&mut buf[..room]) {
+                Ok(0) => {
+                    let flags = packet::TCP_FIN | packet::TCP_ACK;
+                    link.send_tcp(key, self.seq, self.ack, flags, &[]);
+                    self.seq = self.seq.wrapping_add(1);
+                    self.host_closed = true;
+                }
+                Ok(n) => {
+                    let flags = packet::TCP_PSH | packet::TCP_ACK;
+                    link.send_tcp(key, self.seq, self.ack, flags, &buf[..n]);
+                    self.seq = self.seq.wrapping_add(n as u32);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    self.reset(key, link);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Whether the flow has been reset, or both sides have closed and the
+    /// guest has acked everything.
+    fn finished(&self) -> bool {
+        self.dead || self.guest_closed && self.host_closed && self.acked == self.seq
+    }
+
+    fn reset(&mut self, key: FlowKey, link: &mut Link) {
+        let flags = packet::TCP_RST | packet::TCP_ACK;
+        link.send_tcp(key, self.seq, self.ack, flags, &[]);
+        self.dead = true;
+    }
+}
+
+impl Default for UserNet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetBackend for UserNet {
+    fn send(&mut self, frame: &[u8]) {
+        if frame.len() < packet::ETH_HEADER {
+            return;
+        }
+        self.link.guest_mac.copy_from_slice(&frame[6..12]);
+
+        let payload = &frame[packet::ETH_HEADER..];
+        match packet::u16_at(frame, 12) {
+            packet::ETHERTYPE_ARP => self.arp(payload),
+            packet::ETHERTYPE_IPV4 => {
+                if let Some(packet) = Ipv4Packet::parse(payload) {
+                    self.ip(packet);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        if self.link.frames.is_empty() {
+            self.poll();
+        }
+        self.link.frames.pop_front()
+    }
+
+    fn active(&self) -> bool {
+        !self.link.frames.is_empty() || !self.udp.is_empty() || !self.tcp.is_empty()
+    }
+}
+
+/// Where the guest's `addr` is on the host: the gateway is the host
+/// itself.
+fn host_addr(addr: Ipv4Addr) -> Ipv4Addr {
+    if addr == GATEWAY {
+        Ipv4Addr::LOCALHOST
+    } else {
+        addr
+    }
+}
+
+fn guest_view(addr: Ipv4Addr) -> Ipv4Addr {
+    if addr.is_loopback() { GATEWAY } else { addr }
+}
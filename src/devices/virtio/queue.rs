@@ -0,0 +1,208 @@
+use crate::bus::Dma;
+
+const DESC_SIZE: u64 = 16;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+/// The driver doesn't want an interrupt when buffers are used.
+const AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+/// One split virtqueue: the descriptor table, the driver's available ring
+/// and the device's used ring, all in guest RAM where the driver put them.
+#[derive(Clone, Debug, Default)]
+pub struct Virtqueue {
+    pub(super) size: u16,
+    pub(super) ready: bool,
+    pub(super) desc: u64,
+    pub(super) driver: u64,
+    pub(super) device: u64,
+    last_avail: u16,
+    used: u16,
+    /// Set when the driver hands over something malformed. The transport
+    /// reports it as DEVICE_NEEDS_RESET.
+    pub(super) broken: bool,
+}
+
+/// A descriptor chain taken off the available ring: the buffers the device
+/// may read, then the ones it may write, as (address, length) pairs.
+#[derive(Clone, Debug)]
+pub struct Chain {
+    pub head: u16,
+    readable: Vec<(u64, u32)>,
+    writable: Vec<(u64, u32)>,
+}
+
+impl Virtqueue {
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready && !self.broken
+    }
+
+    /// Take the next chain the driver has made available, if any.
+    pub fn pop(&mut self, memory: &Dma<'_>) -> Option<Chain> {
+        if !self.is_ready() || self.size == 0 {
+            return None;
+        }
+        let avail = memory.read_u16(self.driver + 2);
+        if avail? == self.last_avail {
+            return None;
+        }
+
+        let slot = self.driver + 4 + 2 * (self.last_avail % self.size) as u64;
+        let chain = memory
+            .read_u16(slot)
+            .and_then(|head| self.chain(memory, head));
+        if chain.is_none() {
+            self.broken = true;
+        }
+        self.last_avail = self.last_avail.wrapping_add(1);
+        chain
+    }
+
+    /// Walk the descriptors from `head`. Chains that loop, run off the
+    /// table or put a readable buffer after a writable one are malformed.
+    fn chain(&self, memory: &Dma<'_>, head: u16) -> Option<Chain> {
+        let mut chain = Chain {
+            head,
+            readable: Vec::new(),
+            writable: Vec::new(),
+        };
+        let mut index = head;
+
+        for _ in 0..self.size {
+            if index >= self.size {
+                return None;
+            }
+            let desc = self.desc + DESC_SIZE * index as u64;
+            let addr = memory.read_u64(desc)?;
+            let len = memory.read_u32(desc + 8)?;
+            let flags = memory.read_u16(desc + 12)?;
+
+            if flags & DESC_F_WRITE != 0 {
+                chain.writable.push((addr, len));
+            } else if chain.writable.is_empty() {
+                chain.readable.push((addr, len));
+            } else {
+                return None;
+            }
+
+            if flags & DESC_F_NEXT == 0 {
+                return Some(chain);
+            }
+            index = memory.read_u16(desc + 14)?;
+        }
+        None
+    }
+
+    /// Return the chain starting at `head` to the driver, having written
+    /// `len` bytes into it.
+    pub fn push(&mut self, memory: &mut Dma<'_>, head: u16, len: u32) {
+        if !self.is_ready() {
+            return;
+        }
+        let elem = self.device + 4 + 8 * (self.used % self.size) as u64;
+        let written = memory
+            .write_u32(elem, head as u32)
+            .and_then(|_| memory.write_u32(elem + 4, len));
+        self.used = self.used.wrapping_add(1);
+        if written
+            .and_then(|_| memory.write_u16(self.device + 2, self.used))
+            .is_none()
+        {
+            self.broken = true;
+        }
+    }
+
+    /// How many chains have been used so far, wrapping at 2^16.
+    pub(super) fn used(&self) -> u16 {
+        self.used
+    }
+
+    /// Whether the driver has asked not to be interrupted for this queue.
+    pub(super) fn suppressed(&self, memory: &Dma<'_>) -> bool {
+        memory
+            .read_u16(self.driver)
+            .is_some_and(|flags| flags & AVAIL_F_NO_INTERRUPT != 0)
+    }
+}
+
+impl Chain {
+    /// Total length of the device-readable buffers.
+    pub fn readable_len(&self) -> usize {
+        self.readable.iter().map(|&(_, len)| len as usize).sum()
+    }
+
+    /// Total length of the device-writable buffers.
+    pub fn writable_len(&self) -> usize {
+        self.writable.iter().map(|&(_, len)| len as usize).sum()
+    }
+
+    /// Fill `buf` from the readable buffers, starting `offset` bytes in.
+    /// Returns how many bytes there were.
+    pub fn read(&self, memory: &Dma<'_>, offset: usize, buf: &mut [u8]) -> usize {
+        let mut done = 0;
+        for (addr, len, skip) in segments(&self.readable, offset) {
+            if done == buf.len() {
+                break;
+            }
+            let n = (len - skip).min(buf.len() - done);
+            if memory
+                .read(addr + skip as u64, &mut buf[done..done + n])
+                .is_none()
+            {
+                break;
+            }
+            done += n;
+        }
+        done
+    }
+
+    /// Gather all of the readable buffers.
+    pub fn read_all(&self, memory: &Dma<'_>) -> Vec<u8> {
+        let mut buf = vec![0; self.readable_len()];
+        let n = self.read(memory, 0, &mut buf);
+        buf.truncate(n);
+        buf
+    }
+
+    /// Scatter `bytes` into the writable buffers, starting `offset` bytes
+    /// in. Returns how many fit.
+    pub fn write(&self, memory: &mut Dma<'_>, offset: usize, bytes: &[u8]) -> usize {
+        let mut done = 0;
+        for (addr, len, skip) in segments(&self.writable, offset) {
+            if done == bytes.len() {
+                break;
+            }
+            let n = (len - skip).min(bytes.len() - done);
+            if memory
+                .write(addr + skip as u64, &bytes[done..done + n])
+                .is_none()
+            {
+                break;
+            }
+            done += n;
+        }
+        done
+    }
+}
+
+/// The buffers at or after `offset`, with how far into each to start.
+fn segments(
+    buffers: &[(u64, u32)],
+    mut offset: usize,
+) -> impl Iterator<Item = (u64, usize, usize)> + '_ {
+    buffers.iter().filter_map(move |&(addr, len)| {
+        let len = len as usize;
+        if offset >= len {
+            offset -= len;
+            return None;
+        }
+        let skip = offset;
+        offset = 0;
+        Some((addr, len, skip))
+    })
+}
@@ -0,0 +1,173 @@
+//! Source locations from an image's DWARF line tables, for annotating
+//! traces with `file:line`. Only built with the `dwarf` feature.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use gimli::{
+    AttributeValue, DebugLine, DebugLineOffset, DebugLineStr, DebugStr, EndianSlice,
+    LineProgramHeader, LittleEndian,
+};
+
+use crate::loader::ElfFile;
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// Where an instruction came from in the guest's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: Rc<str>,
+    pub line: u32,
+    /// Zero when the compiler didn't record one.
+    pub column: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Row {
+    addr: u32,
+    /// Past the end of a sequence: no code here until the next one starts.
+    end: bool,
+    file: Rc<str>,
+    /// Zero for code the compiler couldn't attribute to a line.
+    line: u32,
+    column: u32,
+}
+
+/// The rows of every line number program in `.debug_line`, sorted by
+/// address.
+#[derive(Debug, Clone, Default)]
+pub struct LineTable {
+    rows: Vec<Row>,
+}
+
+impl LineTable {
+    /// Empty if the image has no `.debug_line`.
+    pub fn parse(elf: &ElfFile) -> Result<Self, String> {
+        let Some(section) = elf.section(".debug_line") else {
+            return Ok(Self::default());
+        };
+        let debug_line = DebugLine::new(section, LittleEndian);
+        let strings = Strings {
+            line_str: DebugLineStr::new(
+                elf.section(".debug_line_str").unwrap_or(&[]),
+                LittleEndian,
+            ),
+            str: DebugStr::new(elf.section(".debug_str").unwrap_or(&[]), LittleEndian),
+        };
+
+        let mut rows = Vec::new();
+        let mut offset = 0;
+        while offset < section.len() {
+            let start = offset;
+            let error = |e: gimli::Error| format!("DWARF: line program at {:#x}: {}", start, e);
+            let program = debug_line
+                .program(DebugLineOffset(start), 4, None, None)
+                .map_err(error)?;
+            let header = program.header();
+            offset += header.format().initial_length_size() as usize + header.unit_length();
+
+            let mut files: HashMap<u64, Rc<str>> = HashMap::new();
+            let mut program_rows = program.rows();
+            while let Some((header, row)) = program_rows.next_row().map_err(error)? {
+                let file = files
+                    .entry(row.file_index())
+                    .or_insert_with(|| strings.file_name(header, row.file_index()).into())
+                    .clone();
+                rows.push(Row {
+                    addr: row.address() as u32,
+                    end: row.end_sequence(),
+                    file,
+                    line: row.line().map_or(0, |line| line.get() as u32),
+                    column: match row.column() {
+                        gimli::ColumnType::LeftEdge => 0,
+                        gimli::ColumnType::Column(column) => column.get() as u32,
+                    },
+                });
+            }
+        }
+
+        // Where one sequence ends and the next begins at the same address,
+        // the start of the next one wins.
+        rows.sort_by_key(|row| (row.addr, !row.end));
+        Ok(Self { rows })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The address each row starts at and the line it's for, skipping the
+    /// ends of sequences and code with no line.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, SourceLocation)> + '_ {
+        self.rows
+            .iter()
+            .filter(|row| !row.end && row.line != 0)
+            .map(|row| {
+                let location = SourceLocation {
+                    file: row.file.clone(),
+                    line: row.line,
+                    column: row.column,
+                };
+                (row.addr, location)
+            })
+    }
+
+    /// The source line the instruction at `addr` was compiled from.
+    pub fn location(&self, addr: u32) -> Option<SourceLocation> {
+        let index = self.rows.partition_point(|row| row.addr <= addr);
+        let row = self.rows[..index].last()?;
+        if row.end || row.line == 0 {
+            return None;
+        }
+        Some(SourceLocation {
+            file: row.file.clone(),
+            line: row.line,
+            column: row.column,
+        })
+    }
+}
+
+/// The sections file and directory names can point into.
+struct Strings<'a> {
+    line_str: DebugLineStr<Reader<'a>>,
+    str: DebugStr<Reader<'a>>,
+}
+
+impl Strings<'_> {
+    /// The file's name with its include directory in front. Before DWARF 5
+    /// directory 0 is the compilation directory, which only `.debug_info`
+    /// knows, so those names are left relative.
+    fn file_name(&self, header: &LineProgramHeader<Reader<'_>>, index: u64) -> String {
+        let Some(file) = header.file(index) else {
+            return String::from("??");
+        };
+        let name = self.string(file.path_name());
+        if name.starts_with('/') || (header.version() < 5 && file.directory_index() == 0) {
+            return name;
+        }
+        match file.directory(header).map(|dir| self.string(dir)) {
+            Some(dir) if !dir.is_empty() => format!("{}/{}", dir.trim_end_matches('/'), name),
+            _ => name,
+        }
+    }
+
+    fn string(&self, value: AttributeValue<Reader<'_>>) -> String {
+        let bytes = match value {
+            AttributeValue::String(bytes) => Some(bytes),
+            AttributeValue::DebugLineStrRef(offset) => self.line_str.get_str(offset).ok(),
+            AttributeValue::DebugStrRef(offset) => self.str.get_str(offset).ok(),
+            _ => None,
+        };
+        bytes.map_or_else(
+            || String::from("??"),
+            |bytes| bytes.to_string_lossy().into_owned(),
+        )
+    }
+}
@@ -0,0 +1,342 @@
+//! Encoding instructions into machine words, for building test programs
+//! without going through text.
+//!
+//! The format functions ([`rtype`], [`itype`], ...) pack raw fields; the
+//! rest encode one instruction each and take operands in assembly order:
+//!
+//! ```
+//! use riscv_emulator_rust::encode::*;
+//!
+//! let program = [
+//!     addi(5, 0, 10),  // addi t0, zero, 10
+//!     addi(5, 5, -1),  // loop: addi t0, t0, -1
+//!     bne(5, 0, -4),   // bne  t0, zero, loop
+//!     ebreak(),
+//! ];
+//! assert_eq!(program[0], 0x00A0_0293);
+//! ```
+//!
+//! Registers are `0`-`31` and offsets are in bytes from the instruction.
+//! Everything panics on an operand that doesn't fit its field rather than
+//! silently truncating it.
+
+// ── Formats ───────────────────────────────────────────────────────────────────
+
+fn check_reg(reg: u8) -> u32 {
+    assert!(reg < 32, "x{} isn't a register", reg);
+    reg as u32
+}
+
+fn check_field(name: &str, value: u8, bits: u32) -> u32 {
+    assert!(
+        (value as u32) < 1 << bits,
+        "{} {:#x} doesn't fit in {} bits",
+        name,
+        value,
+        bits
+    );
+    value as u32
+}
+
+fn check_imm(name: &str, imm: i32, min: i32, max: i32) -> u32 {
+    assert!(
+        (min..=max).contains(&imm),
+        "{} {} is out of range [{}, {}]",
+        name,
+        imm,
+        min,
+        max
+    );
+    imm as u32
+}
+
+/// A signed 12-bit immediate, for the instructions that can't take a raw
+/// field.
+fn signed12(name: &str, imm: i32) -> i32 {
+    check_imm(name, imm, -2048, 2047);
+    imm
+}
+
+fn check_offset(name: &str, offset: i32, bits: u32) -> u32 {
+    let limit = 1 << (bits - 1);
+    check_imm(name, offset, -limit, limit - 2);
+    assert!(offset % 2 == 0, "{} {} isn't 2-byte aligned", name, offset);
+    offset as u32
+}
+
+pub fn rtype(funct7: u8, rs2: u8, rs1: u8, funct3: u8, rd: u8, opcode: u8) -> u32 {
+    (check_field("funct7", funct7, 7) << 25)
+        | (check_reg(rs2) << 20)
+        | (check_reg(rs1) << 15)
+        | (check_field("funct3", funct3, 3) << 12)
+        | (check_reg(rd) << 7)
+        | check_field("opcode", opcode, 7)
+}
+
+/// `imm` may be a signed 12-bit immediate or a raw 12-bit field, such as a
+/// CSR number.
+pub fn itype(imm: i32, rs1: u8, funct3: u8, rd: u8, opcode: u8) -> u32 {
+    ((check_imm("immediate", imm, -2048, 4095) & 0xFFF) << 20)
+        | (check_reg(rs1) << 15)
+        | (check_field("funct3", funct3, 3) << 12)
+        | (check_reg(rd) << 7)
+        | check_field("opcode", opcode, 7)
+}
+
+/// A store.
+pub fn stype(imm: i32, rs2: u8, rs1: u8, funct3: u8) -> u32 {
+    let imm = check_imm("offset", imm, -2048, 2047);
+    let imm11_5 = (imm >> 5) & 0x7F;
+    let imm4_0 = imm & 0x1F;
+
+    (imm11_5 << 25)
+        | (check_reg(rs2) << 20)
+        | (check_reg(rs1) << 15)
+        | (check_field("funct3", funct3, 3) << 12)
+        | (imm4_0 << 7)
+        | 0x23
+}
+
+/// A conditional branch.
+pub fn btype(imm: i32, rs1: u8, rs2: u8, funct3: u8) -> u32 {
+    let imm = check_offset("branch offset", imm, 13);
+    let b12 = (imm >> 12) & 0x1;
+    let b11 = (imm >> 11) & 0x1;
+    let b10_5 = (imm >> 5) & 0x3F;
+    let b4_1 = (imm >> 1) & 0xF;
+
+    (b12 << 31)
+        | (b10_5 << 25)
+        | (check_reg(rs2) << 20)
+        | (check_reg(rs1) << 15)
+        | (check_field("funct3", funct3, 3) << 12)
+        | (b4_1 << 8)
+        | (b11 << 7)
+        | 0x63
+}
+
+/// `imm` is the 20 bits that land in `[31:12]`.
+pub fn utype(imm: u32, rd: u8, opcode: u8) -> u32 {
+    assert!(
+        imm <= 0xFFFFF,
+        "upper immediate {:#x} doesn't fit in 20 bits",
+        imm
+    );
+    (imm << 12) | (check_reg(rd) << 7) | check_field("opcode", opcode, 7)
+}
+
+/// JAL, the only J-type instruction.
+pub fn jtype(imm: i32, rd: u8) -> u32 {
+    let imm = check_offset("jump offset", imm, 21);
+    let i20 = (imm >> 20) & 0x1;
+    let i19_12 = (imm >> 12) & 0xFF;
+    let i11 = (imm >> 11) & 0x1;
+    let i10_1 = (imm >> 1) & 0x3FF;
+
+    (i20 << 31) | (i10_1 << 21) | (i11 << 20) | (i19_12 << 12) | (check_reg(rd) << 7) | 0x6F
+}
+
+// ── RV32I ─────────────────────────────────────────────────────────────────────
+
+const LOAD: u8 = 0x03;
+const OP_IMM: u8 = 0x13;
+const OP: u8 = 0x33;
+const SYSTEM: u8 = 0x73;
+
+pub fn lui(rd: u8, imm: u32) -> u32 {
+    utype(imm, rd, 0x37)
+}
+
+pub fn auipc(rd: u8, imm: u32) -> u32 {
+    utype(imm, rd, 0x17)
+}
+
+pub fn jal(rd: u8, offset: i32) -> u32 {
+    jtype(offset, rd)
+}
+
+pub fn jalr(rd: u8, rs1: u8, offset: i32) -> u32 {
+    itype(signed12("offset", offset), rs1, 0x0, rd, 0x67)
+}
+
+macro_rules! branches {
+    ($($name:ident = $funct3:expr),* $(,)?) => {$(
+        pub fn $name(rs1: u8, rs2: u8, offset: i32) -> u32 {
+            btype(offset, rs1, rs2, $funct3)
+        }
+    )*};
+}
+
+branches! {
+    beq = 0x0,
+    bne = 0x1,
+    blt = 0x4,
+    bge = 0x5,
+    bltu = 0x6,
+    bgeu = 0x7,
+}
+
+macro_rules! loads {
+    ($($name:ident = $funct3:expr),* $(,)?) => {$(
+        pub fn $name(rd: u8, rs1: u8, offset: i32) -> u32 {
+            itype(signed12("offset", offset), rs1, $funct3, rd, LOAD)
+        }
+    )*};
+}
+
+loads! {
+    lb = 0x0,
+    lh = 0x1,
+    lw = 0x2,
+    lbu = 0x4,
+    lhu = 0x5,
+}
+
+macro_rules! stores {
+    ($($name:ident = $funct3:expr),* $(,)?) => {$(
+        pub fn $name(rs2: u8, rs1: u8, offset: i32) -> u32 {
+            stype(offset, rs2, rs1, $funct3)
+        }
+    )*};
+}
+
+stores! {
+    sb = 0x0,
+    sh = 0x1,
+    sw = 0x2,
+}
+
+macro_rules! immediates {
+    ($($name:ident = $funct3:expr),* $(,)?) => {$(
+        pub fn $name(rd: u8, rs1: u8, imm: i32) -> u32 {
+            itype(signed12("immediate", imm), rs1, $funct3, rd, OP_IMM)
+        }
+    )*};
+}
+
+immediates! {
+    addi = 0x0,
+    slti = 0x2,
+    sltiu = 0x3,
+    xori = 0x4,
+    ori = 0x6,
+    andi = 0x7,
+}
+
+macro_rules! shifts {
+    ($($name:ident = ($funct7:expr, $funct3:expr)),* $(,)?) => {$(
+        pub fn $name(rd: u8, rs1: u8, shamt: u8) -> u32 {
+            assert!(shamt < 32, "shift amount {} is out of range [0, 31]", shamt);
+            rtype($funct7, shamt, rs1, $funct3, rd, OP_IMM)
+        }
+    )*};
+}
+
+shifts! {
+    slli = (0x00, 0x1),
+    srli = (0x00, 0x5),
+    srai = (0x20, 0x5),
+}
+
+macro_rules! registers {
+    ($($name:ident = ($funct7:expr, $funct3:expr)),* $(,)?) => {$(
+        pub fn $name(rd: u8, rs1: u8, rs2: u8) -> u32 {
+            rtype($funct7, rs2, rs1, $funct3, rd, OP)
+        }
+    )*};
+}
+
+registers! {
+    add = (0x00, 0x0),
+    sub = (0x20, 0x0),
+    sll = (0x00, 0x1),
+    slt = (0x00, 0x2),
+    sltu = (0x00, 0x3),
+    xor = (0x00, 0x4),
+    srl = (0x00, 0x5),
+    sra = (0x20, 0x5),
+    or = (0x00, 0x6),
+    and = (0x00, 0x7),
+}
+
+// ── Pseudo-instructions ───────────────────────────────────────────────────────
+
+/// `li rd, value`: one ADDI if `value` fits in 12 bits, else LUI and an
+/// ADDI. LUI sign-extends on RV64, so a value just below 2^31, whose upper
+/// part would round up to 0x80000, flips its low bits with XORI instead.
+pub fn li(rd: u8, value: i32) -> Vec<u32> {
+    if (-2048..=2047).contains(&value) {
+        return vec![addi(rd, 0, value)];
+    }
+    let lo = (value << 20) >> 20;
+    match value.checked_sub(lo) {
+        Some(upper) => match lo {
+            0 => vec![lui(rd, upper as u32 >> 12)],
+            _ => vec![lui(rd, upper as u32 >> 12), addi(rd, rd, lo)],
+        },
+        None => vec![lui(rd, 0x80000), xori(rd, rd, value ^ i32::MIN)],
+    }
+}
+
+/// `offset` from an AUIPC split into its upper 20 bits and the signed low
+/// 12 bits the instruction after it adds.
+pub fn split_offset(offset: i32) -> (u32, i32) {
+    let lo = (offset << 20) >> 20;
+    (offset.wrapping_sub(lo) as u32 >> 12, lo)
+}
+
+/// `fence iorw, iorw`.
+pub fn fence() -> u32 {
+    0x0FF0_000F
+}
+
+pub fn ecall() -> u32 {
+    0x0000_0073
+}
+
+pub fn ebreak() -> u32 {
+    0x0010_0073
+}
+
+// ── Zicsr and privileged ──────────────────────────────────────────────────────
+
+fn csr_op(funct3: u8, rd: u8, csr: u16, rs1: u8) -> u32 {
+    assert!(csr <= 0xFFF, "CSR {:#x} doesn't fit in 12 bits", csr);
+    itype(csr as i32, rs1, funct3, rd, SYSTEM)
+}
+
+pub fn csrrw(rd: u8, csr: u16, rs1: u8) -> u32 {
+    csr_op(0x1, rd, csr, rs1)
+}
+
+pub fn csrrs(rd: u8, csr: u16, rs1: u8) -> u32 {
+    csr_op(0x2, rd, csr, rs1)
+}
+
+pub fn csrrc(rd: u8, csr: u16, rs1: u8) -> u32 {
+    csr_op(0x3, rd, csr, rs1)
+}
+
+pub fn csrrwi(rd: u8, csr: u16, uimm: u8) -> u32 {
+    csr_op(0x5, rd, csr, check_field("CSR immediate", uimm, 5) as u8)
+}
+
+pub fn csrrsi(rd: u8, csr: u16, uimm: u8) -> u32 {
+    csr_op(0x6, rd, csr, check_field("CSR immediate", uimm, 5) as u8)
+}
+
+pub fn csrrci(rd: u8, csr: u16, uimm: u8) -> u32 {
+    csr_op(0x7, rd, csr, check_field("CSR immediate", uimm, 5) as u8)
+}
+
+pub fn mret() -> u32 {
+    0x3020_0073
+}
+
+pub fn sret() -> u32 {
+    0x1020_0073
+}
+
+pub fn wfi() -> u32 {
+    0x1050_0073
+}
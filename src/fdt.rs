@@ -0,0 +1,151 @@
+//! A writer for flattened device trees (DTBs), the blob firmware and
+//! kernels read to find out what hardware they're running on.
+
+use std::collections::HashMap;
+
+const MAGIC: u32 = 0xD00D_FEED;
+const VERSION: u32 = 17;
+const LAST_COMPATIBLE_VERSION: u32 = 16;
+const HEADER_SIZE: usize = 40;
+/// An empty memory reservation map: just the terminating entry.
+const RESERVE_MAP_SIZE: usize = 16;
+
+const BEGIN_NODE: u32 = 1;
+const END_NODE: u32 = 2;
+const PROP: u32 = 3;
+const END: u32 = 9;
+
+/// Builds a device tree node by node. Nodes nest between
+/// [`begin_node`](Self::begin_node) and [`end_node`](Self::end_node),
+/// starting with the root, whose name is empty; properties go to the node
+/// most recently begun.
+#[derive(Default)]
+pub struct Fdt {
+    structure: Vec<u8>,
+    strings: Vec<u8>,
+    string_offsets: HashMap<String, u32>,
+    depth: usize,
+    next_phandle: u32,
+}
+
+impl Fdt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin_node(&mut self, name: &str) {
+        self.push_u32(BEGIN_NODE);
+        self.structure.extend_from_slice(name.as_bytes());
+        self.structure.push(0);
+        self.pad();
+        self.depth += 1;
+    }
+
+    pub fn end_node(&mut self) {
+        assert!(self.depth > 0, "no node to end");
+        self.push_u32(END_NODE);
+        self.depth -= 1;
+    }
+
+    pub fn property(&mut self, name: &str, value: &[u8]) {
+        assert!(self.depth > 0, "properties belong to a node");
+        let name = self.string(name);
+        self.push_u32(PROP);
+        self.push_u32(value.len() as u32);
+        self.push_u32(name);
+        self.structure.extend_from_slice(value);
+        self.pad();
+    }
+
+    /// A property with no value, which is true by being there.
+    pub fn property_flag(&mut self, name: &str) {
+        self.property(name, &[]);
+    }
+
+    pub fn property_u32(&mut self, name: &str, value: u32) {
+        self.property_cells(name, &[value]);
+    }
+
+    /// A 64-bit value as two cells, high first.
+    pub fn property_u64(&mut self, name: &str, value: u64) {
+        self.property_cells(name, &[(value >> 32) as u32, value as u32]);
+    }
+
+    pub fn property_cells(&mut self, name: &str, cells: &[u32]) {
+        let value: Vec<u8> = cells.iter().flat_map(|cell| cell.to_be_bytes()).collect();
+        self.property(name, &value);
+    }
+
+    pub fn property_str(&mut self, name: &str, value: &str) {
+        self.property_strs(name, &[value]);
+    }
+
+    /// A string list, such as `compatible` with several entries.
+    pub fn property_strs(&mut self, name: &str, values: &[&str]) {
+        let mut value = Vec::new();
+        for s in values {
+            value.extend_from_slice(s.as_bytes());
+            value.push(0);
+        }
+        self.property(name, &value);
+    }
+
+    /// Give the current node a fresh `phandle` for other nodes to refer to
+    /// it by, and return it.
+    pub fn phandle(&mut self) -> u32 {
+        self.next_phandle += 1;
+        let phandle = self.next_phandle;
+        self.property_u32("phandle", phandle);
+        phandle
+    }
+
+    /// The finished blob. Every node must have been ended.
+    pub fn finish(mut self) -> Vec<u8> {
+        assert_eq!(self.depth, 0, "unended nodes");
+        self.push_u32(END);
+
+        let structure_offset = HEADER_SIZE + RESERVE_MAP_SIZE;
+        let strings_offset = structure_offset + self.structure.len();
+        let total = strings_offset + self.strings.len();
+
+        let header = [
+            MAGIC,
+            total as u32,
+            structure_offset as u32,
+            strings_offset as u32,
+            HEADER_SIZE as u32,
+            VERSION,
+            LAST_COMPATIBLE_VERSION,
+            0, // boot CPU
+            self.strings.len() as u32,
+            self.structure.len() as u32,
+        ];
+        let mut blob: Vec<u8> = header.iter().flat_map(|word| word.to_be_bytes()).collect();
+        blob.extend_from_slice(&[0; RESERVE_MAP_SIZE]);
+        blob.extend_from_slice(&self.structure);
+        blob.extend_from_slice(&self.strings);
+        blob
+    }
+
+    /// The offset of `name` in the strings block, adding it if it's new.
+    fn string(&mut self, name: &str) -> u32 {
+        if let Some(&offset) = self.string_offsets.get(name) {
+            return offset;
+        }
+        let offset = self.strings.len() as u32;
+        self.strings.extend_from_slice(name.as_bytes());
+        self.strings.push(0);
+        self.string_offsets.insert(name.to_string(), offset);
+        offset
+    }
+
+    fn push_u32(&mut self, value: u32) {
+        self.structure.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn pad(&mut self) {
+        while !self.structure.len().is_multiple_of(4) {
+            self.structure.push(0);
+        }
+    }
+}
@@ -0,0 +1,282 @@
+//! A C API for embedding the emulator in C and C++ simulators, built with
+//! `--features ffi`. `include/riscv_emu.h` declares it.
+//!
+//! Every function takes the handle [`riscv_emu_new`] returned. Functions
+//! that can fail return 0 on success and -1 on failure, and
+//! [`riscv_emu_last_error`] says why.
+
+use std::ffi::{CString, c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use crate::devices::Device;
+use crate::{ExitReason, MemSize, Reg, RiscvCpu, StepOutcome};
+
+/// An RV32 hart and its bus.
+pub struct RiscvEmu {
+    cpu: RiscvCpu,
+    error: CString,
+}
+
+impl RiscvEmu {
+    fn fail(&mut self, error: impl Into<Vec<u8>>) -> c_int {
+        self.error = CString::new(error).unwrap_or_default();
+        -1
+    }
+}
+
+/// Why [`riscv_emu_step`] or [`riscv_emu_run`] returned.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiscvEmuStatus {
+    Executed = 0,
+    Breakpoint = 1,
+    Watchpoint = 2,
+    RegisterWrite = 3,
+    ReachedPc = 4,
+    StepLimit = 5,
+    Idle = 6,
+    /// The guest exited through semihosting.
+    Exited = 7,
+    /// An exception was returned to the host; see
+    /// [`riscv_emu_last_error`].
+    Exception = -1,
+}
+
+/// Reads `size` bytes (1, 2 or 4) at `offset` into the region.
+pub type RiscvEmuMmioRead = extern "C" fn(ctx: *mut c_void, offset: u32, size: u32) -> u32;
+
+/// Writes the low `size` bytes (1, 2 or 4) of `value` at `offset`.
+pub type RiscvEmuMmioWrite = extern "C" fn(ctx: *mut c_void, offset: u32, size: u32, value: u32);
+
+/// A bus region served by C callbacks.
+struct Callbacks {
+    read: RiscvEmuMmioRead,
+    write: RiscvEmuMmioWrite,
+    ctx: *mut c_void,
+}
+
+impl Device for Callbacks {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        (self.read)(self.ctx, offset, size.bytes() as u32)
+    }
+
+    fn write(&mut self, offset: u32, size: MemSize, value: u32) {
+        (self.write)(self.ctx, offset, size.bytes() as u32, value)
+    }
+}
+
+/// A hart with `ram_size` bytes of RAM at `ram_base`, starting there. Null
+/// if the RAM can't be allocated.
+#[unsafe(no_mangle)]
+pub extern "C" fn riscv_emu_new(ram_base: u32, ram_size: usize) -> *mut RiscvEmu {
+    match RiscvCpu::builder()
+        .ram_base(ram_base)
+        .ram_size(ram_size)
+        .build()
+    {
+        Ok(cpu) => Box::into_raw(Box::new(RiscvEmu {
+            cpu,
+            error: CString::default(),
+        })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// # Safety
+///
+/// `emu` must have come from [`riscv_emu_new`] and not been freed, or be
+/// null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_free(emu: *mut RiscvEmu) {
+    if !emu.is_null() {
+        drop(unsafe { Box::from_raw(emu) });
+    }
+}
+
+/// Why the last call that returned -1 or [`RiscvEmuStatus::Exception`]
+/// failed. Valid until the next call that fails.
+///
+/// # Safety
+///
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_last_error(emu: *const RiscvEmu) -> *const c_char {
+    unsafe { &*emu }.error.as_ptr()
+}
+
+/// Load an ELF, an Intel HEX file or else a raw binary at the start of
+/// RAM, and jump to its entry point.
+///
+/// # Safety
+///
+/// `emu` must be a live handle and `data` must point to `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_load(emu: *mut RiscvEmu, data: *const u8, len: usize) -> c_int {
+    let emu = unsafe { &mut *emu };
+    let program = unsafe { slice::from_raw_parts(data, len) };
+    let loaded = if program.starts_with(b"\x7fELF") {
+        emu.cpu.load_elf(program)
+    } else if program.starts_with(b":") {
+        emu.cpu.load_ihex(&String::from_utf8_lossy(program))
+    } else {
+        let base = emu.cpu.bus.ram_base();
+        emu.cpu
+            .load_binary(base, program)
+            .map(|()| emu.cpu.pc = base)
+    };
+    match loaded {
+        Ok(()) => 0,
+        Err(e) => emu.fail(e),
+    }
+}
+
+/// Execute one instruction.
+///
+/// # Safety
+///
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_step(emu: *mut RiscvEmu) -> RiscvEmuStatus {
+    let emu = unsafe { &mut *emu };
+    match emu.cpu.step() {
+        Ok(StepOutcome::Executed) => RiscvEmuStatus::Executed,
+        Ok(StepOutcome::Breakpoint(_)) => RiscvEmuStatus::Breakpoint,
+        Ok(StepOutcome::Watchpoint(_)) => RiscvEmuStatus::Watchpoint,
+        Ok(StepOutcome::RegisterWrite(_)) => RiscvEmuStatus::RegisterWrite,
+        Ok(StepOutcome::Exited(_)) => RiscvEmuStatus::Exited,
+        Err(exception) => {
+            emu.fail(exception.to_string());
+            RiscvEmuStatus::Exception
+        }
+    }
+}
+
+/// Run up to `max_steps` instructions.
+///
+/// # Safety
+///
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_run(emu: *mut RiscvEmu, max_steps: u64) -> RiscvEmuStatus {
+    let emu = unsafe { &mut *emu };
+    match emu.cpu.run_steps(max_steps) {
+        ExitReason::Breakpoint(_) => RiscvEmuStatus::Breakpoint,
+        ExitReason::Watchpoint(_) => RiscvEmuStatus::Watchpoint,
+        ExitReason::RegisterWrite(_) => RiscvEmuStatus::RegisterWrite,
+        ExitReason::ReachedPc(_) => RiscvEmuStatus::ReachedPc,
+        ExitReason::StepLimit => RiscvEmuStatus::StepLimit,
+        ExitReason::TimeLimit => unreachable!("riscv_emu_run has no time limit"),
+        ExitReason::Idle => RiscvEmuStatus::Idle,
+        ExitReason::Exited(_) => RiscvEmuStatus::Exited,
+        ExitReason::Exception(exception) => {
+            emu.fail(exception.to_string());
+            RiscvEmuStatus::Exception
+        }
+    }
+}
+
+/// Register `reg` (0 to 31), or 0 if there's no such register.
+///
+/// # Safety
+///
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_get_reg(emu: *const RiscvEmu, reg: u32) -> u32 {
+    let emu = unsafe { &*emu };
+    emu.cpu.regs.get(reg as usize).copied().unwrap_or(0)
+}
+
+/// Set register `reg` (1 to 31). Writes to x0 and beyond x31 are ignored.
+///
+/// # Safety
+///
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_set_reg(emu: *mut RiscvEmu, reg: u32, value: u32) {
+    let emu = unsafe { &mut *emu };
+    if let Some(reg) = u8::try_from(reg).ok().and_then(Reg::from_index) {
+        emu.cpu.set_reg(reg, value as u64);
+    }
+}
+
+/// # Safety
+///
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_get_pc(emu: *const RiscvEmu) -> u32 {
+    unsafe { &*emu }.cpu.pc
+}
+
+/// # Safety
+///
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_set_pc(emu: *mut RiscvEmu, pc: u32) {
+    unsafe { &mut *emu }.cpu.pc = pc;
+}
+
+/// Copy `len` bytes of RAM from `addr` to `buf`.
+///
+/// # Safety
+///
+/// `emu` must be a live handle and `buf` must have room for `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_read_mem(
+    emu: *mut RiscvEmu,
+    addr: u32,
+    buf: *mut u8,
+    len: usize,
+) -> c_int {
+    let emu = unsafe { &mut *emu };
+    let buf = unsafe { slice::from_raw_parts_mut(buf, len) };
+    match emu.cpu.bus.dma().read(addr as u64, buf) {
+        Some(()) => 0,
+        None => emu.fail(format!("{} bytes at {:#x} aren't all RAM", len, addr)),
+    }
+}
+
+/// Copy `len` bytes from `data` to RAM at `addr`.
+///
+/// # Safety
+///
+/// `emu` must be a live handle and `data` must point to `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_write_mem(
+    emu: *mut RiscvEmu,
+    addr: u32,
+    data: *const u8,
+    len: usize,
+) -> c_int {
+    let emu = unsafe { &mut *emu };
+    let data = unsafe { slice::from_raw_parts(data, len) };
+    match emu.cpu.bus.write_bytes(addr, data) {
+        Some(()) => {
+            emu.cpu.flush_blocks();
+            0
+        }
+        None => emu.fail(format!("{} bytes at {:#x} aren't all RAM", len, addr)),
+    }
+}
+
+/// Serve loads and stores to `base..base + size` with `read` and `write`,
+/// which are passed `ctx` and the offset from `base`.
+///
+/// # Safety
+///
+/// `emu` must be a live handle, and the callbacks must be safe to call
+/// with `ctx` for as long as it lives.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn riscv_emu_add_mmio(
+    emu: *mut RiscvEmu,
+    base: u32,
+    size: u32,
+    read: RiscvEmuMmioRead,
+    write: RiscvEmuMmioWrite,
+    ctx: *mut c_void,
+) {
+    let emu = unsafe { &mut *emu };
+    emu.cpu
+        .bus
+        .map(base, size, Box::new(Callbacks { read, write, ctx }));
+}
@@ -0,0 +1,378 @@
+//! Arithmetic on the host's `f64`. The host only rounds to nearest-even, so
+//! each operation also recovers its exact error (with two-sum, FMA residuals
+//! and, for FMA itself, Boldo and Muller's ErrFma), on operands scaled so
+//! that nothing underflows. [`round`] then rounds the exact result in
+//! whichever mode `rm` selects and raises NX, UF and OF exactly, detecting
+//! tininess after rounding as RISC-V does.
+//!
+//! This relies on the host's `+`, `*`, `/`, `sqrt` and `mul_add` being
+//! correctly rounded, as IEEE 754 requires. The `softfloat` feature swaps in
+//! an integer implementation that doesn't.
+
+use std::cmp::Ordering;
+
+use super::{Float, RDN, RMM, RTZ, RUP, Remainder, rounds_away, signed_zero};
+use crate::csr;
+use crate::decode::IntWidth;
+
+/// Round `x` to an integer and saturate it into `int`'s range, along with
+/// the flags that raises.
+pub(super) fn to_int<F: Float>(x: F, int: IntWidth, rm: u8) -> (u64, u32) {
+    let x = x.to_f64();
+    let (min, max): (i128, i128) = match int {
+        IntWidth::W => (i32::MIN as i128, i32::MAX as i128),
+        IntWidth::Wu => (0, u32::MAX as i128),
+        IntWidth::L => (i64::MIN as i128, i64::MAX as i128),
+        IntWidth::Lu => (0, u64::MAX as i128),
+    };
+
+    let rounded = match rm {
+        RTZ => x.trunc(),
+        RDN => x.floor(),
+        RUP => x.ceil(),
+        RMM => x.round(),
+        _ => x.round_ties_even(),
+    };
+
+    let (value, flags) = if x.is_nan() {
+        (max, csr::FFLAG_NV)
+    } else if rounded < min as f64 {
+        (min, csr::FFLAG_NV)
+    } else if rounded >= (max + 1) as f64 {
+        (max, csr::FFLAG_NV)
+    } else {
+        let inexact = if rounded != x { csr::FFLAG_NX } else { 0 };
+        (rounded as i128, inexact)
+    };
+
+    // 32-bit results are sign-extended into the register, even unsigned ones.
+    let value = match int {
+        IntWidth::W | IntWidth::Wu => value as i32 as i64 as u64,
+        IntWidth::L | IntWidth::Lu => value as u64,
+    };
+    (value, flags)
+}
+
+/// An exact result, `(hi + lo + ε) · 2^scale`, where `lo` is at most half an
+/// ulp of `hi` and ε is too small to matter except for its sign, which is
+/// `sticky`'s. Zero only when `hi` is.
+#[derive(Debug, Clone, Copy)]
+struct Exact {
+    hi: f64,
+    lo: f64,
+    sticky: f64,
+    scale: i32,
+}
+
+impl Exact {
+    fn new(hi: f64, lo: f64, sticky: f64, scale: i32) -> Self {
+        Self {
+            hi,
+            lo,
+            sticky,
+            scale,
+        }
+    }
+}
+
+/// 2^e, for e from -1074 to 1023.
+fn pow2(e: i32) -> f64 {
+    match e {
+        -1022.. => f64::from_bits(((e + 1023) as u64) << 52),
+        _ => f64::from_bits(1 << (e + 1074)),
+    }
+}
+
+/// `x` · 2^k, exact when the result is representable.
+fn ldexp(mut x: f64, mut k: i32) -> f64 {
+    while k > 1000 {
+        x *= pow2(1000);
+        k -= 1000;
+    }
+    while k < -1000 {
+        x *= pow2(-1000);
+        k += 1000;
+    }
+    x * pow2(k)
+}
+
+/// The exponent of `x`'s leading bit, for finite, nonzero `x`.
+fn exponent(x: f64) -> i32 {
+    let bits = x.to_bits() & !(1 << 63);
+    match (bits >> 52) as i32 {
+        0 => -1011 - bits.leading_zeros() as i32,
+        e => e - 1023,
+    }
+}
+
+/// Split finite, nonzero `x` into a significand with a magnitude in [1, 2)
+/// and an exponent.
+fn split(x: f64) -> (f64, i32) {
+    let e = exponent(x);
+    (ldexp(x, -e), e)
+}
+
+/// `a + b` and its rounding error.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// `a + b` and its rounding error, when `|a| >= |b|`.
+fn fast_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+fn exact_add(a: f64, b: f64) -> Exact {
+    // The error term would be too small to scale, but only its sign matters.
+    let (big, small) = if a.abs() >= b.abs() { (a, b) } else { (b, a) };
+    if exponent(big) - exponent(small) > 110 {
+        return Exact::new(big, 0.0, small, 0);
+    }
+
+    let (hi, lo) = two_sum(a, b);
+    if hi.is_finite() && lo.is_finite() {
+        return Exact::new(hi, lo, 0.0, 0);
+    }
+    // Halving can't lose bits from a sum big enough to overflow.
+    let (hi, lo) = two_sum(a / 2.0, b / 2.0);
+    Exact::new(hi, lo, 0.0, 1)
+}
+
+fn exact_mul(a: f64, b: f64) -> Exact {
+    let ((a, ea), (b, eb)) = (split(a), split(b));
+    let hi = a * b;
+    Exact::new(hi, a.mul_add(b, -hi), 0.0, ea + eb)
+}
+
+fn exact_div(a: f64, b: f64) -> Exact {
+    let ((a, ea), (b, eb)) = (split(a), split(b));
+    let hi = a / b;
+    // The true quotient is hi + remainder / b.
+    let remainder = (-hi).mul_add(b, a);
+    Exact::new(hi, 0.0, remainder * b, ea - eb)
+}
+
+fn exact_sqrt(a: f64) -> Exact {
+    let (mut a, mut e) = split(a);
+    if e % 2 != 0 {
+        a *= 2.0;
+        e -= 1;
+    }
+    let hi = a.sqrt();
+    Exact::new(hi, 0.0, (-hi).mul_add(hi, a), e / 2)
+}
+
+/// `a * b + c` for finite, nonzero `a` and `b` and finite `c`.
+fn exact_fma(a: f64, b: f64, c: f64) -> Exact {
+    let ((a, ea), (b, eb)) = (split(a), split(b));
+    let scale = ea + eb;
+    let (u1, u2) = (a * b, a.mul_add(b, -(a * b)));
+    if c == 0.0 {
+        return Exact::new(u1, u2, 0.0, scale);
+    }
+
+    // Far enough apart, the smaller of the product and the addend only
+    // decides which way a tie breaks.
+    let ec = exponent(c);
+    if ec - scale > 110 {
+        return Exact::new(ldexp(c, -ec), 0.0, u1, ec);
+    }
+    if ec - scale < -110 {
+        return Exact::new(u1, u2, c, scale);
+    }
+
+    // ErrFma: r1 + r2 + r3 is exactly a * b + c.
+    let c = ldexp(c, -scale);
+    let r1 = a.mul_add(b, c);
+    let (alpha1, alpha2) = two_sum(c, u2);
+    let (beta1, beta2) = two_sum(u1, alpha1);
+    let gamma = (beta1 - r1) + beta2;
+    let (r2, r3) = fast_two_sum(gamma, alpha2);
+    Exact::new(r1, r2, r3, scale)
+}
+
+/// Round a positive value, `(h + l + s·ε) · 2^scale`, to a multiple of
+/// 2^`unit`, which is in the same scaled units. Returns the multiple and
+/// whether it was inexact.
+fn round_to_grid(h: f64, l: f64, s: f64, unit: i32, rm: u8, negative: bool) -> (f64, bool) {
+    use Remainder::*;
+    use std::cmp::Ordering::*;
+
+    // A tie between the last bits, then the sticky sign.
+    let tie_break = |x: f64| match x.partial_cmp(&0.0) {
+        Some(Greater) => AboveHalf,
+        Some(Less) => BelowHalf,
+        _ => Half,
+    };
+    let against_half = |x: f64, tie: f64| match x.partial_cmp(&0.5) {
+        Some(Greater) => AboveHalf,
+        Some(Less) => BelowHalf,
+        _ => tie_break(tie),
+    };
+    let beyond = |s: f64| match s.partial_cmp(&0.0) {
+        Some(Greater) => BelowHalf,
+        _ => Exact,
+    };
+
+    let (down, remainder) = if unit > exponent(h) + 3 {
+        // Less than a quarter of the way to the first grid point.
+        (0.0, BelowHalf)
+    } else {
+        let step = pow2(unit);
+        let x = h / step;
+        let (n, frac, l) = (x.floor(), x - x.floor(), l / step);
+
+        if frac > 0.0 {
+            let tie = if l != 0.0 { l } else { s };
+            (n, against_half(frac, tie))
+        } else if l > 0.0 {
+            (n, against_half(l, s))
+        } else if l == -1.0 {
+            match s.partial_cmp(&0.0) {
+                Some(Less) => (n - 2.0, AboveHalf),
+                _ => (n - 1.0, beyond(s)),
+            }
+        } else if l < 0.0 {
+            // 1 + l may not be representable, so mirror the comparison.
+            let remainder = match against_half(-l, -s) {
+                AboveHalf => BelowHalf,
+                BelowHalf => AboveHalf,
+                tie => tie,
+            };
+            (n - 1.0, remainder)
+        } else if s < 0.0 {
+            (n - 1.0, AboveHalf)
+        } else {
+            (n, beyond(s))
+        }
+    };
+
+    let away = rounds_away(remainder, down % 2.0 == 1.0, rm, negative);
+    (down + away as u8 as f64, remainder != Exact)
+}
+
+/// Round a nonzero exact result to `F` in mode `rm`, along with the NX, UF
+/// and OF flags that raises.
+fn round<F: Float>(exact: Exact, rm: u8) -> (F, u32) {
+    let negative = exact.hi < 0.0;
+    let sign = if negative { -1.0 } else { 1.0 };
+    let (h, l, s) = (exact.hi * sign, exact.lo * sign, exact.sticky * sign);
+
+    // The exponent of the exact value, which is just below h when h is a
+    // power of two and the rest is negative.
+    let mut e = exponent(h) + exact.scale;
+    if h.to_bits() << 12 == 0 && (l < 0.0 || (l == 0.0 && s < 0.0)) {
+        e -= 1;
+    }
+
+    let unit = e.max(F::EMIN) - F::PRECISION + 1;
+    let (m, inexact) = round_to_grid(h, l, s, unit - exact.scale, rm, negative);
+
+    if m != 0.0 && exponent(m) + unit > F::EMAX {
+        let infinite = match rm {
+            RTZ => false,
+            RDN => negative,
+            RUP => !negative,
+            _ => true,
+        };
+        let magnitude = match infinite {
+            true => F::from_f64(f64::INFINITY),
+            false => F::from_raw(F::MAX),
+        };
+        let result = if negative { -magnitude } else { magnitude };
+        return (result, csr::FFLAG_OF | csr::FFLAG_NX);
+    }
+
+    // Tiny if the result would still be below the smallest normal with an
+    // unbounded exponent range.
+    let tiny = e < F::EMIN && {
+        let unit = e - F::PRECISION + 1;
+        let (m, _) = round_to_grid(h, l, s, unit - exact.scale, rm, negative);
+        exponent(m) + unit < F::EMIN
+    };
+
+    let mut flags = 0;
+    if inexact {
+        flags |= csr::FFLAG_NX;
+    }
+    if tiny && inexact {
+        flags |= csr::FFLAG_UF;
+    }
+    (F::from_f64(ldexp(m, unit) * sign), flags)
+}
+
+/// `a + b` in mode `rm`, along with the NX, UF and OF flags.
+pub(super) fn add<F: Float>(a: F, b: F, rm: u8) -> (F, u32) {
+    let opposite = a.is_negative() != b.is_negative();
+    if !a.is_finite_nonzero() || !b.is_finite_nonzero() {
+        return (signed_zero(a + b, opposite, rm), 0);
+    }
+    match exact_add(a.to_f64(), b.to_f64()) {
+        exact if exact.hi == 0.0 => (signed_zero(F::ZERO, true, rm), 0),
+        exact => round(exact, rm),
+    }
+}
+
+pub(super) fn mul<F: Float>(a: F, b: F, rm: u8) -> (F, u32) {
+    if !a.is_finite_nonzero() || !b.is_finite_nonzero() {
+        return (a * b, 0);
+    }
+    round(exact_mul(a.to_f64(), b.to_f64()), rm)
+}
+
+pub(super) fn div<F: Float>(a: F, b: F, rm: u8) -> (F, u32) {
+    if !a.is_finite_nonzero() || !b.is_finite_nonzero() {
+        return (a / b, 0);
+    }
+    round(exact_div(a.to_f64(), b.to_f64()), rm)
+}
+
+pub(super) fn sqrt<F: Float>(a: F, rm: u8) -> (F, u32) {
+    if !a.is_finite_nonzero() || a.is_negative() {
+        return (a.sqrt(), 0);
+    }
+    round(exact_sqrt(a.to_f64()), rm)
+}
+
+pub(super) fn fma<F: Float>(a: F, b: F, c: F, rm: u8) -> (F, u32) {
+    let opposite = (a.is_negative() != b.is_negative()) != c.is_negative();
+    if !a.is_finite_nonzero() || !b.is_finite_nonzero() || c.is_nan() || c.is_infinite() {
+        return (signed_zero(a.fma(b, c), opposite, rm), 0);
+    }
+    match exact_fma(a.to_f64(), b.to_f64(), c.to_f64()) {
+        exact if exact.hi == 0.0 => (signed_zero(F::ZERO, true, rm), 0),
+        exact => round(exact, rm),
+    }
+}
+
+/// An integer converted to `F` in mode `rm`, along with the NX flag.
+pub(super) fn from_int<F: Float>(value: i128, rm: u8) -> (F, u32) {
+    if value == 0 {
+        return (F::ZERO, 0);
+    }
+    let hi = value as f64;
+    let lo = (value - hi as i128) as f64;
+    round(Exact::new(hi, lo, 0.0, 0), rm)
+}
+
+/// A double narrowed to a single in mode `rm`, along with the NX, UF and OF
+/// flags.
+pub(super) fn narrow(a: f64, rm: u8) -> (f32, u32) {
+    if !a.is_finite_nonzero() {
+        return (a as f32, 0);
+    }
+    round(Exact::new(a, 0.0, 0.0, 0), rm)
+}
+
+/// A single widened to a double, which is always exact.
+pub(super) fn widen(a: f32) -> f64 {
+    a as f64
+}
+
+/// How `a` compares to `b`, or `None` if either is a NaN.
+pub(super) fn compare<F: Float>(a: F, b: F) -> Option<Ordering> {
+    a.partial_cmp(&b)
+}
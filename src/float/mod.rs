@@ -0,0 +1,627 @@
+//! The F and D extensions, or Zfinx and Zdinx when the hart keeps its
+//! floating-point values in the integer registers.
+//!
+//! Every operation honors its rounding mode and raises exact flags. The
+//! arithmetic itself is done by one of two backends: `host`, which rounds
+//! the host's `f64` results exactly, or, with the `softfloat` feature,
+//! `soft`, which uses only integer arithmetic so results are the same bit
+//! for bit on any host. Either way NaN results are always the canonical NaN.
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::csr::{self, HpmEvent};
+use crate::debug::WatchKind;
+use crate::decode::{FloatInstruction, FmaOp, FpCompare, FpFormat, FpOp, IntWidth};
+use crate::mmu::Access;
+use crate::trap::Exception;
+use crate::xlen::Xlen;
+use crate::{MemSize, RiscvCpu};
+
+#[cfg(not(feature = "softfloat"))]
+mod host;
+#[cfg(feature = "softfloat")]
+mod soft;
+
+#[cfg(not(feature = "softfloat"))]
+use host::{add, compare, div, fma, from_int, mul, narrow, sqrt, to_int, widen};
+#[cfg(feature = "softfloat")]
+use soft::{add, compare, div, fma, from_int, mul, narrow, sqrt, to_int, widen};
+
+/// Where floating-point instructions find their operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FloatRegs {
+    /// F and D: 32 separate 64-bit `f` registers, singles NaN-boxed.
+    #[default]
+    Separate,
+    /// Zfinx and Zdinx: the `x` registers, as on many small cores. On RV32 a
+    /// double takes an even/odd pair. There are no FP loads, stores or
+    /// moves, and `misa` doesn't report F or D.
+    Integer,
+}
+
+/// The rounding modes `rm` and `frm` can hold.
+const RNE: u8 = 0;
+const RTZ: u8 = 1;
+const RDN: u8 = 2;
+const RUP: u8 = 3;
+const RMM: u8 = 4;
+const DYNAMIC: u8 = 7;
+
+/// What the emulator needs from `f32` and `f64`, with raw bits as `u64`. The
+/// soft-float backend only uses the raw bits, leaving the host arithmetic
+/// unused.
+#[cfg_attr(feature = "softfloat", allow(dead_code))]
+trait Float:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const SIGN: u64;
+    const QUIET: u64;
+    const CANONICAL_NAN: u64;
+    /// The largest finite value.
+    const MAX: u64;
+    const FORMAT: FpFormat;
+    /// Significand bits, counting the implicit one.
+    const PRECISION: i32;
+    /// The exponents of the smallest normal and the largest finite values.
+    const EMIN: i32;
+    const EMAX: i32;
+
+    fn from_raw(bits: u64) -> Self;
+    fn raw(self) -> u64;
+    fn sqrt(self) -> Self;
+    fn fma(self, b: Self, c: Self) -> Self;
+    fn is_nan(self) -> bool;
+    fn is_infinite(self) -> bool;
+    fn is_subnormal(self) -> bool;
+    fn to_f64(self) -> f64;
+    /// `value`, which must be representable.
+    fn from_f64(value: f64) -> Self;
+
+    fn is_signaling(self) -> bool {
+        self.is_nan() && self.raw() & Self::QUIET == 0
+    }
+
+    fn canonical(self) -> Self {
+        if self.is_nan() {
+            Self::from_raw(Self::CANONICAL_NAN)
+        } else {
+            self
+        }
+    }
+
+    fn is_negative(self) -> bool {
+        self.raw() & Self::SIGN != 0
+    }
+
+    /// Neither zero, infinite nor NaN: the values arithmetic can be inexact
+    /// on.
+    fn is_finite_nonzero(self) -> bool {
+        !self.is_nan() && !self.is_infinite() && self != Self::ZERO
+    }
+}
+
+impl Float for f32 {
+    const ZERO: Self = 0.0;
+    const SIGN: u64 = 1 << 31;
+    const QUIET: u64 = 1 << 22;
+    const CANONICAL_NAN: u64 = 0x7FC0_0000;
+    const MAX: u64 = 0x7F7F_FFFF;
+    const FORMAT: FpFormat = FpFormat::Single;
+    const PRECISION: i32 = 24;
+    const EMIN: i32 = -126;
+    const EMAX: i32 = 127;
+
+    fn from_raw(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+    fn raw(self) -> u64 {
+        self.to_bits() as u64
+    }
+    fn sqrt(self) -> Self {
+        f32::sqrt(self)
+    }
+    fn fma(self, b: Self, c: Self) -> Self {
+        self.mul_add(b, c)
+    }
+    fn is_nan(self) -> bool {
+        f32::is_nan(self)
+    }
+    fn is_infinite(self) -> bool {
+        f32::is_infinite(self)
+    }
+    fn is_subnormal(self) -> bool {
+        f32::is_subnormal(self)
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+}
+
+impl Float for f64 {
+    const ZERO: Self = 0.0;
+    const SIGN: u64 = 1 << 63;
+    const QUIET: u64 = 1 << 51;
+    const CANONICAL_NAN: u64 = 0x7FF8_0000_0000_0000;
+    const MAX: u64 = 0x7FEF_FFFF_FFFF_FFFF;
+    const FORMAT: FpFormat = FpFormat::Double;
+    const PRECISION: i32 = 53;
+    const EMIN: i32 = -1022;
+    const EMAX: i32 = 1023;
+
+    fn from_raw(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+    fn raw(self) -> u64 {
+        self.to_bits()
+    }
+    fn sqrt(self) -> Self {
+        f64::sqrt(self)
+    }
+    fn fma(self, b: Self, c: Self) -> Self {
+        self.mul_add(b, c)
+    }
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+    fn is_infinite(self) -> bool {
+        f64::is_infinite(self)
+    }
+    fn is_subnormal(self) -> bool {
+        f64::is_subnormal(self)
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+}
+
+/// A single in an `f` register must be NaN-boxed: anything else reads as
+/// the canonical NaN.
+fn unbox(bits: u64) -> u64 {
+    match bits >> 32 {
+        0xFFFF_FFFF => bits & 0xFFFF_FFFF,
+        _ => f32::CANONICAL_NAN,
+    }
+}
+
+fn nan_box(bits: u32) -> u64 {
+    0xFFFF_FFFF_0000_0000 | bits as u64
+}
+
+/// Invalid if an operand is a signaling NaN, or the result is a NaN that
+/// didn't come from one of them (0/0, inf - inf, sqrt(-1), ...).
+fn invalid<F: Float>(operands: &[F], result: F) -> bool {
+    operands.iter().any(|x| x.is_signaling())
+        || (result.is_nan() && !operands.iter().any(|x| x.is_nan()))
+}
+
+/// Where an exact value lies between two neighbouring points of a grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Remainder {
+    Exact,
+    BelowHalf,
+    Half,
+    AboveHalf,
+}
+
+/// Whether a value `remainder` past `down`, a multiple of the grid, rounds
+/// away from zero in mode `rm`.
+fn rounds_away(remainder: Remainder, odd: bool, rm: u8, negative: bool) -> bool {
+    use Remainder::*;
+
+    match (remainder, rm) {
+        (Exact, _) | (_, RTZ) => false,
+        (_, RDN) => negative,
+        (_, RUP) => !negative,
+        (_, RMM) => remainder != BelowHalf,
+        _ => remainder == AboveHalf || (remainder == Half && odd),
+    }
+}
+
+/// A sum that is exactly zero is +0, or -0 rounding down, when its addends
+/// have opposite signs.
+fn signed_zero<F: Float>(sum: F, opposite: bool, rm: u8) -> F {
+    match (sum == F::ZERO && opposite, rm) {
+        (true, RDN) => -F::ZERO,
+        (true, _) => F::ZERO,
+        _ => sum,
+    }
+}
+
+/// The ten-bit FCLASS mask.
+fn classify<F: Float>(x: F) -> u64 {
+    let bit = match (x.is_negative(), x) {
+        _ if x.is_signaling() => 8,
+        _ if x.is_nan() => 9,
+        (true, _) if x.is_infinite() => 0,
+        (true, _) if x.is_subnormal() => 2,
+        (true, _) if x == F::ZERO => 3,
+        (true, _) => 1,
+        (false, _) if x.is_infinite() => 7,
+        (false, _) if x.is_subnormal() => 5,
+        (false, _) if x == F::ZERO => 4,
+        (false, _) => 6,
+    };
+    1 << bit
+}
+
+/// FMIN/FMAX: a NaN loses to a number, and -0 is less than +0.
+fn min_max<F: Float>(a: F, b: F, max: bool) -> F {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => F::from_raw(F::CANONICAL_NAN),
+        (true, false) => b,
+        (false, true) => a,
+        _ => match compare(a, b) {
+            Some(Ordering::Equal) if a.is_negative() != max => a,
+            Some(Ordering::Equal) => b,
+            order if (order == Some(Ordering::Less)) != max => a,
+            _ => b,
+        },
+    }
+}
+
+impl<X: Xlen> RiscvCpu<X> {
+    pub fn float_regs(&self) -> FloatRegs {
+        self.float_regs
+    }
+
+    /// Choose between F/D and Zfinx/Zdinx, and report it in `misa`.
+    pub fn set_float_regs(&mut self, float_regs: FloatRegs) {
+        self.float_regs = float_regs;
+        self.update_misa();
+    }
+
+    /// Whether `instruction`, a floating-point one, exists in this
+    /// configuration and has a usable rounding mode.
+    pub(crate) fn float_permitted(&self, instruction: FloatInstruction) -> bool {
+        use FloatInstruction::*;
+
+        let rm = match instruction {
+            Farith { rm, .. }
+            | Fma { rm, .. }
+            | Fsqrt { rm, .. }
+            | FcvtToInt { rm, .. }
+            | FcvtFromInt { rm, .. }
+            | FcvtFp { rm, .. } => rm,
+            _ => RNE,
+        };
+        // 5 and 6 are reserved, as static modes and in frm alike.
+        if matches!(rm, 5 | 6) || rm == DYNAMIC && self.csrs.read_u64(csr::FRM) > RMM as u64 {
+            return false;
+        }
+
+        if self.float_regs == FloatRegs::Separate {
+            return true;
+        }
+        if X::BITS == 64 {
+            return !matches!(
+                instruction,
+                Flw { .. }
+                    | Fsw { .. }
+                    | Fld { .. }
+                    | Fsd { .. }
+                    | FmvToInt { .. }
+                    | FmvFromInt { .. }
+            );
+        }
+
+        // Zdinx on RV32: every double operand is an even register pair.
+        let double = FpFormat::Double;
+        let pairs: &[u8] = match instruction {
+            Farith {
+                fmt, rd, rs1, rs2, ..
+            } if fmt == double => &[rd, rs1, rs2],
+            Fma {
+                fmt,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                ..
+            } if fmt == double => &[rd, rs1, rs2, rs3],
+            Fsqrt { fmt, rd, rs1, .. } if fmt == double => &[rd, rs1],
+            Fcmp { fmt, rs1, rs2, .. } if fmt == double => &[rs1, rs2],
+            Fclass { fmt, rs1, .. } | FcvtToInt { fmt, rs1, .. } if fmt == double => &[rs1],
+            FcvtFromInt { fmt, rd, .. } if fmt == double => &[rd],
+            FcvtFp { fmt, rd, .. } if fmt == double => &[rd],
+            FcvtFp { rs1, .. } => &[rs1],
+            Flw { .. } | Fsw { .. } | Fld { .. } | Fsd { .. } => return false,
+            FmvToInt { .. } | FmvFromInt { .. } => return false,
+            _ => &[],
+        };
+        pairs.iter().all(|reg| reg % 2 == 0)
+    }
+
+    pub(crate) fn execute_float(&mut self, instruction: FloatInstruction) -> Result<(), Exception> {
+        use FloatInstruction::*;
+
+        match instruction {
+            Flw { rd, rs1, imm } => {
+                let value = self.load_float(rs1, imm, false)?;
+                self.fregs[rd as usize] = nan_box(value as u32);
+            }
+            Fld { rd, rs1, imm } => self.fregs[rd as usize] = self.load_float(rs1, imm, true)?,
+            Fsw { rs1, rs2, imm } => self.store_float(rs1, imm, self.fregs[rs2 as usize], false)?,
+            Fsd { rs1, rs2, imm } => self.store_float(rs1, imm, self.fregs[rs2 as usize], true)?,
+            FmvToInt {
+                fmt: FpFormat::Single,
+                rd,
+                rs1,
+            } => self.write_word(rd, self.fregs[rs1 as usize] as u32),
+            FmvToInt { rd, rs1, .. } => self.write_reg(rd, self.fregs[rs1 as usize]),
+            FmvFromInt {
+                fmt: FpFormat::Single,
+                rd,
+                rs1,
+            } => self.fregs[rd as usize] = nan_box(self.read_reg(rs1) as u32),
+            FmvFromInt { rd, rs1, .. } => self.fregs[rd as usize] = self.read_reg(rs1),
+
+            FcvtFp {
+                fmt: FpFormat::Single,
+                rd,
+                rs1,
+                rm,
+            } => {
+                let a: f64 = self.read_float(rs1);
+                self.flag_if(a.is_signaling(), csr::FFLAG_NV);
+                let result = self.rounded(narrow(a, self.rounding_mode(rm)));
+                self.write_float(rd, result.canonical());
+            }
+            FcvtFp { rd, rs1, .. } => {
+                let a: f32 = self.read_float(rs1);
+                self.flag_if(a.is_signaling(), csr::FFLAG_NV);
+                self.write_float(rd, widen(a).canonical());
+            }
+
+            Farith { fmt, .. }
+            | Fma { fmt, .. }
+            | Fsqrt { fmt, .. }
+            | Fcmp { fmt, .. }
+            | Fclass { fmt, .. }
+            | FcvtToInt { fmt, .. }
+            | FcvtFromInt { fmt, .. } => match fmt {
+                FpFormat::Single => self.execute_format::<f32>(instruction),
+                FpFormat::Double => self.execute_format::<f64>(instruction),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// The instructions that work the same way on either format.
+    fn execute_format<F: Float>(&mut self, instruction: FloatInstruction) {
+        use FloatInstruction::*;
+
+        match instruction {
+            Farith {
+                op,
+                rd,
+                rs1,
+                rs2,
+                rm,
+                ..
+            } => {
+                let (a, b): (F, F) = (self.read_float(rs1), self.read_float(rs2));
+                let sign = |x: F| x.raw() & F::SIGN;
+                let magnitude = a.raw() & !F::SIGN;
+                let rm = self.rounding_mode(rm);
+
+                let result = match op {
+                    FpOp::Add => self.rounded(add(a, b, rm)),
+                    FpOp::Sub => self.rounded(add(a, -b, rm)),
+                    FpOp::Mul => self.rounded(mul(a, b, rm)),
+                    FpOp::Div => {
+                        self.flag_if(a.is_finite_nonzero() && b == F::ZERO, csr::FFLAG_DZ);
+                        self.rounded(div(a, b, rm))
+                    }
+                    FpOp::Min | FpOp::Max => {
+                        self.flag_if(a.is_signaling() || b.is_signaling(), csr::FFLAG_NV);
+                        self.write_float(rd, min_max(a, b, op == FpOp::Max));
+                        return;
+                    }
+                    // Sign injection copies bits, NaNs and all.
+                    FpOp::Sgnj | FpOp::Sgnjn | FpOp::Sgnjx => {
+                        let sign = match op {
+                            FpOp::Sgnj => sign(b),
+                            FpOp::Sgnjn => sign(b) ^ F::SIGN,
+                            _ => sign(a) ^ sign(b),
+                        };
+                        self.write_float(rd, F::from_raw(magnitude | sign));
+                        return;
+                    }
+                };
+
+                self.flag_if(invalid(&[a, b], result), csr::FFLAG_NV);
+                self.write_float(rd, result.canonical());
+            }
+            Fma {
+                op,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+                ..
+            } => {
+                let a: F = self.read_float(rs1);
+                let b: F = self.read_float(rs2);
+                let c: F = self.read_float(rs3);
+                let rm = self.rounding_mode(rm);
+
+                let result = self.rounded(match op {
+                    FmaOp::Madd => fma(a, b, c, rm),
+                    FmaOp::Msub => fma(a, b, -c, rm),
+                    FmaOp::Nmsub => fma(-a, b, c, rm),
+                    FmaOp::Nmadd => fma(-a, b, -c, rm),
+                });
+                // inf * 0 is invalid even when the addend is a quiet NaN.
+                let zero_times_inf =
+                    (a.is_infinite() && b == F::ZERO) || (a == F::ZERO && b.is_infinite());
+
+                self.flag_if(zero_times_inf || invalid(&[a, b, c], result), csr::FFLAG_NV);
+                self.write_float(rd, result.canonical());
+            }
+            Fsqrt { rd, rs1, rm, .. } => {
+                let a: F = self.read_float(rs1);
+                let result = self.rounded(sqrt(a, self.rounding_mode(rm)));
+
+                self.flag_if(invalid(&[a], result), csr::FFLAG_NV);
+                self.write_float(rd, result.canonical());
+            }
+            Fcmp {
+                op, rd, rs1, rs2, ..
+            } => {
+                let (a, b): (F, F) = (self.read_float(rs1), self.read_float(rs2));
+
+                // FEQ is quiet: only signaling NaNs are invalid.
+                let invalid = match op {
+                    FpCompare::Eq => a.is_signaling() || b.is_signaling(),
+                    _ => a.is_nan() || b.is_nan(),
+                };
+                self.flag_if(invalid, csr::FFLAG_NV);
+
+                let order = compare(a, b);
+                let result = match op {
+                    FpCompare::Eq => order == Some(Ordering::Equal),
+                    FpCompare::Lt => order == Some(Ordering::Less),
+                    FpCompare::Le => matches!(order, Some(Ordering::Less | Ordering::Equal)),
+                };
+                self.write_reg(rd, result as u64);
+            }
+            Fclass { rd, rs1, .. } => {
+                let a: F = self.read_float(rs1);
+                self.write_reg(rd, classify(a));
+            }
+            FcvtToInt {
+                int, rd, rs1, rm, ..
+            } => {
+                let a: F = self.read_float(rs1);
+                let (value, flags) = to_int(a, int, self.rounding_mode(rm));
+
+                self.csrs.raise_fflags(flags);
+                self.write_reg(rd, value);
+            }
+            FcvtFromInt {
+                int, rd, rs1, rm, ..
+            } => {
+                let value = match int {
+                    IntWidth::W => self.read_reg(rs1) as i32 as i128,
+                    IntWidth::Wu => self.read_reg(rs1) as u32 as i128,
+                    IntWidth::L => self.read_reg(rs1) as i64 as i128,
+                    IntWidth::Lu => self.read_reg(rs1) as i128,
+                };
+                let result: F = self.rounded(from_int(value, self.rounding_mode(rm)));
+                self.write_float(rd, result);
+            }
+            _ => unreachable!("not a {:?} instruction: {:?}", F::FORMAT, instruction),
+        }
+    }
+
+    /// Raise a rounded result's flags and return it.
+    fn rounded<F: Float>(&mut self, (result, flags): (F, u32)) -> F {
+        self.csrs.raise_fflags(flags);
+        result
+    }
+
+    /// The rounding mode an instruction's `rm` field selects.
+    fn rounding_mode(&self, rm: u8) -> u8 {
+        match rm {
+            DYNAMIC => self.csrs.read_u64(csr::FRM) as u8,
+            rm => rm,
+        }
+    }
+
+    fn flag_if(&mut self, condition: bool, flag: u32) {
+        if condition {
+            self.csrs.raise_fflags(flag);
+        }
+    }
+
+    fn read_float<F: Float>(&self, reg: u8) -> F {
+        let r = reg as usize;
+        let bits = match (self.float_regs, F::FORMAT) {
+            (FloatRegs::Separate, FpFormat::Single) => unbox(self.fregs[r]),
+            (FloatRegs::Separate, FpFormat::Double) => self.fregs[r],
+            (FloatRegs::Integer, FpFormat::Single) => self.read_reg(reg) & 0xFFFF_FFFF,
+            (FloatRegs::Integer, FpFormat::Double) if X::BITS == 64 => self.read_reg(reg),
+            // The x0 "pair" reads as zero rather than pulling in x1.
+            (FloatRegs::Integer, FpFormat::Double) if reg == 0 => 0,
+            (FloatRegs::Integer, FpFormat::Double) => {
+                self.read_reg(reg) | self.read_reg(reg + 1) << 32
+            }
+        };
+        F::from_raw(bits)
+    }
+
+    fn write_float<F: Float>(&mut self, reg: u8, value: F) {
+        let bits = value.raw();
+        match (self.float_regs, F::FORMAT) {
+            (FloatRegs::Separate, FpFormat::Single) => {
+                self.fregs[reg as usize] = nan_box(bits as u32)
+            }
+            (FloatRegs::Separate, FpFormat::Double) => self.fregs[reg as usize] = bits,
+            (FloatRegs::Integer, FpFormat::Single) => self.write_word(reg, bits as u32),
+            (FloatRegs::Integer, FpFormat::Double) if X::BITS == 64 => self.write_reg(reg, bits),
+            (FloatRegs::Integer, FpFormat::Double) => {
+                if reg != 0 {
+                    self.write_reg(reg, bits);
+                    self.write_reg(reg + 1, bits >> 32);
+                }
+            }
+        }
+    }
+
+    // The bus is at most word-wide, so FLD and FSD are two word accesses,
+    // low word first, like LD and SD.
+
+    fn load_float(&mut self, rs1: u8, imm: i32, double: bool) -> Result<u64, Exception> {
+        let vaddr = self.effective_addr(rs1, imm);
+        let mut value = self.read_virt(vaddr, MemSize::Word, Access::Load)? as u64;
+        if double {
+            let high =
+                self.read_virt(vaddr.wrapping_add(4) & X::MASK, MemSize::Word, Access::Load)?;
+            value |= (high as u64) << 32;
+        }
+        let len = if double { 8 } else { 4 };
+        self.data_access(vaddr, len, WatchKind::Read);
+        self.csrs.count(HpmEvent::Load);
+
+        Ok(value)
+    }
+
+    fn store_float(
+        &mut self,
+        rs1: u8,
+        imm: i32,
+        value: u64,
+        double: bool,
+    ) -> Result<(), Exception> {
+        let vaddr = self.effective_addr(rs1, imm);
+        self.write_virt(vaddr, MemSize::Word, value as u32)?;
+        if double {
+            self.write_virt(
+                vaddr.wrapping_add(4) & X::MASK,
+                MemSize::Word,
+                (value >> 32) as u32,
+            )?;
+        }
+        let len = if double { 8 } else { 4 };
+        self.data_access(vaddr, len, WatchKind::Write);
+        self.csrs.count(HpmEvent::Store);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+//! Soft-float: the F and D operations in integer arithmetic alone, so every
+//! result and flag is the same bit for bit whatever the host's FPU does with
+//! subnormals, NaNs or extended precision.
+//!
+//! Operands are unpacked into a sign, an exponent and an integer
+//! significand. Each operation is carried out exactly, or keeps a sticky bit
+//! for whatever it had to drop, and [`round`] packs the result in whichever
+//! mode `rm` selects, detecting tininess after rounding as RISC-V does.
+
+use std::cmp::Ordering;
+
+use super::{Float, RDN, RNE, RTZ, RUP, Remainder, rounds_away, signed_zero};
+use crate::csr;
+use crate::decode::IntWidth;
+
+/// A finite, nonzero value: `(-1)^negative · significand · 2^exponent`.
+#[derive(Debug, Clone, Copy)]
+struct Unpacked {
+    negative: bool,
+    exponent: i32,
+    significand: u128,
+}
+
+impl Unpacked {
+    /// The exponent of the leading bit.
+    fn top(self) -> i32 {
+        self.exponent + 127 - self.significand.leading_zeros() as i32
+    }
+}
+
+fn unpack<F: Float>(x: F) -> Unpacked {
+    let fraction_bits = F::PRECISION - 1;
+    let bits = x.raw() & !F::SIGN;
+    let fraction = bits & ((1 << fraction_bits) - 1);
+    let (exponent, significand) = match (bits >> fraction_bits) as i32 {
+        0 => (F::EMIN, fraction),
+        biased => (biased - F::EMAX, fraction | 1 << fraction_bits),
+    };
+    Unpacked {
+        negative: x.is_negative(),
+        exponent: exponent - fraction_bits,
+        significand: significand as u128,
+    }
+}
+
+fn is_zero<F: Float>(x: F) -> bool {
+    x.raw() & !F::SIGN == 0
+}
+
+fn sign<F: Float>(negative: bool) -> u64 {
+    if negative { F::SIGN } else { 0 }
+}
+
+fn zero<F: Float>(negative: bool) -> F {
+    F::from_raw(sign::<F>(negative))
+}
+
+fn infinity<F: Float>(negative: bool) -> F {
+    F::from_raw((F::MAX + 1) | sign::<F>(negative))
+}
+
+fn nan<F: Float>() -> F {
+    F::from_raw(F::CANONICAL_NAN)
+}
+
+/// Round `significand + ε` to a multiple of 2^`shift`, where ε is less than
+/// one if `sticky` and zero otherwise. Returns the multiple and whether it
+/// was inexact. Callers only set `sticky` when `shift` is at least 2.
+fn round_to(significand: u128, sticky: bool, shift: i32, rm: u8, negative: bool) -> (u128, bool) {
+    use Remainder::*;
+
+    let (down, remainder) = match shift {
+        ..=0 => (
+            significand << -shift,
+            if sticky { BelowHalf } else { Exact },
+        ),
+        129.. => (0, BelowHalf),
+        _ => {
+            let (down, rest) = match shift {
+                128 => (0, significand),
+                _ => (significand >> shift, significand & ((1 << shift) - 1)),
+            };
+            let remainder = match (rest.cmp(&(1 << (shift - 1))), sticky) {
+                (Ordering::Less, false) if rest == 0 => Exact,
+                (Ordering::Less, _) => BelowHalf,
+                (Ordering::Equal, false) => Half,
+                _ => AboveHalf,
+            };
+            (down, remainder)
+        }
+    };
+
+    let away = rounds_away(remainder, down & 1 == 1, rm, negative);
+    (down + away as u128, remainder != Exact)
+}
+
+/// Round `(-1)^negative · (significand + ε) · 2^exponent` to `F` in mode
+/// `rm`, where ε is less than one if `sticky` and zero otherwise, along with
+/// the NX, UF and OF flags that raises. A sticky significand must have at
+/// least `F::PRECISION + 2` bits.
+fn round<F: Float>(value: Unpacked, sticky: bool, rm: u8) -> (F, u32) {
+    let Unpacked {
+        negative,
+        exponent,
+        significand,
+    } = value;
+    let top = value.top();
+    let leading = |m: u128| 127 - m.leading_zeros() as i32;
+
+    let unit = top.max(F::EMIN) - F::PRECISION + 1;
+    let (m, inexact) = round_to(significand, sticky, unit - exponent, rm, negative);
+
+    if m != 0 && leading(m) + unit > F::EMAX {
+        let magnitude = match rm {
+            RTZ => F::MAX,
+            RDN if !negative => F::MAX,
+            RUP if negative => F::MAX,
+            _ => F::MAX + 1,
+        };
+        let result = F::from_raw(magnitude | sign::<F>(negative));
+        return (result, csr::FFLAG_OF | csr::FFLAG_NX);
+    }
+
+    // Tiny if the result would still be below the smallest normal with an
+    // unbounded exponent range.
+    let tiny = top < F::EMIN && {
+        let unit = top - F::PRECISION + 1;
+        let (m, _) = round_to(significand, sticky, unit - exponent, rm, negative);
+        leading(m) + unit < F::EMIN
+    };
+
+    let mut flags = 0;
+    if inexact {
+        flags |= csr::FFLAG_NX;
+    }
+    if tiny && inexact {
+        flags |= csr::FFLAG_UF;
+    }
+
+    // Rounding up may carry into the next binade.
+    let (m, unit) = match m >> F::PRECISION {
+        0 => (m as u64, unit),
+        _ => ((m >> 1) as u64, unit + 1),
+    };
+    let fraction_bits = F::PRECISION - 1;
+    let bits = match m >> fraction_bits {
+        0 => m,
+        _ => {
+            let biased = (unit + fraction_bits + F::EMAX) as u64;
+            biased << fraction_bits | (m & ((1 << fraction_bits) - 1))
+        }
+    };
+    (F::from_raw(bits | sign::<F>(negative)), flags)
+}
+
+/// `x + y`, exactly or with a sticky bit. The significands can be up to 106
+/// bits wide, as products are.
+fn sum(x: Unpacked, y: Unpacked) -> (Unpacked, bool) {
+    let (x, y) = if x.top() >= y.top() { (x, y) } else { (y, x) };
+
+    // Line x's leading bit up with bit 125, leaving room for a carry and at
+    // least 19 bits below a 106-bit y before any of it is dropped.
+    let exponent = x.top() - 125;
+    let big = x.significand << (x.exponent - exponent);
+    let (small, sticky) = match y.exponent - exponent {
+        shift @ 0.. => (y.significand << shift, false),
+        shift @ -127..0 => {
+            let dropped = y.significand & ((1 << -shift) - 1);
+            (y.significand >> -shift, dropped != 0)
+        }
+        _ => (0, true),
+    };
+
+    let result = |negative, significand| Unpacked {
+        negative,
+        exponent,
+        significand,
+    };
+    if x.negative == y.negative {
+        return (result(x.negative, big + small), sticky);
+    }
+    // With a sticky bit, y is a little more than `small`: big - small - ε is
+    // (big - small - 1) + (1 - ε).
+    match (big.cmp(&small), sticky) {
+        (Ordering::Greater, true) => (result(x.negative, big - small - 1), true),
+        (Ordering::Greater, false) => (result(x.negative, big - small), false),
+        _ => (result(y.negative, small - big), sticky),
+    }
+}
+
+/// A sum's result: the sign rules for an exact zero, or the rounded value.
+fn rounded_sum<F: Float>((value, sticky): (Unpacked, bool), rm: u8) -> (F, u32) {
+    match value.significand == 0 && !sticky {
+        true => (signed_zero(F::ZERO, true, rm), 0),
+        false => round(value, sticky, rm),
+    }
+}
+
+fn product(a: Unpacked, b: Unpacked) -> Unpacked {
+    Unpacked {
+        negative: a.negative != b.negative,
+        exponent: a.exponent + b.exponent,
+        significand: a.significand * b.significand,
+    }
+}
+
+/// `a + b` in mode `rm`, along with the NX, UF and OF flags.
+pub(super) fn add<F: Float>(a: F, b: F, rm: u8) -> (F, u32) {
+    let opposite = a.is_negative() != b.is_negative();
+    let result = match (a, b) {
+        _ if a.is_nan() || b.is_nan() => nan(),
+        _ if a.is_infinite() && b.is_infinite() && opposite => nan(),
+        _ if a.is_infinite() => a,
+        _ if b.is_infinite() => b,
+        _ if is_zero(a) && is_zero(b) => signed_zero(a, opposite, rm),
+        _ if is_zero(a) => b,
+        _ if is_zero(b) => a,
+        _ => return rounded_sum(sum(unpack(a), unpack(b)), rm),
+    };
+    (result, 0)
+}
+
+pub(super) fn mul<F: Float>(a: F, b: F, rm: u8) -> (F, u32) {
+    let negative = a.is_negative() != b.is_negative();
+    let result = match (a, b) {
+        _ if a.is_nan() || b.is_nan() => nan(),
+        _ if (a.is_infinite() && is_zero(b)) || (is_zero(a) && b.is_infinite()) => nan(),
+        _ if a.is_infinite() || b.is_infinite() => infinity(negative),
+        _ if is_zero(a) || is_zero(b) => zero(negative),
+        _ => return round(product(unpack(a), unpack(b)), false, rm),
+    };
+    (result, 0)
+}
+
+pub(super) fn div<F: Float>(a: F, b: F, rm: u8) -> (F, u32) {
+    let negative = a.is_negative() != b.is_negative();
+    let result = match (a, b) {
+        _ if a.is_nan() || b.is_nan() => nan(),
+        _ if (a.is_infinite() && b.is_infinite()) || (is_zero(a) && is_zero(b)) => nan(),
+        _ if a.is_infinite() || is_zero(b) => infinity(negative),
+        _ if is_zero(a) || b.is_infinite() => zero(negative),
+        _ => {
+            let (a, b) = (unpack(a), unpack(b));
+            // Enough quotient bits to round with, whatever the operands'.
+            let bits = |x: Unpacked| 128 - x.significand.leading_zeros() as i32;
+            let shift = bits(b) - bits(a) + F::PRECISION + 2;
+            let dividend = a.significand << shift;
+            let quotient = Unpacked {
+                negative,
+                exponent: a.exponent - b.exponent - shift,
+                significand: dividend / b.significand,
+            };
+            return round(quotient, dividend % b.significand != 0, rm);
+        }
+    };
+    (result, 0)
+}
+
+pub(super) fn sqrt<F: Float>(a: F, rm: u8) -> (F, u32) {
+    let result = match a {
+        _ if a.is_nan() => nan(),
+        _ if is_zero(a) => a,
+        _ if a.is_negative() => nan(),
+        _ if a.is_infinite() => a,
+        _ => {
+            let a = unpack(a);
+            // Twice the bits the root needs, and an even exponent to halve.
+            let bits = 128 - a.significand.leading_zeros() as i32;
+            let mut shift = (2 * F::PRECISION + 4 - bits).max(0);
+            shift += (a.exponent - shift) & 1;
+            let square = a.significand << shift;
+            let root = square.isqrt();
+            let value = Unpacked {
+                negative: false,
+                exponent: (a.exponent - shift) / 2,
+                significand: root,
+            };
+            return round(value, root * root != square, rm);
+        }
+    };
+    (result, 0)
+}
+
+pub(super) fn fma<F: Float>(a: F, b: F, c: F, rm: u8) -> (F, u32) {
+    let negative = a.is_negative() != b.is_negative();
+    let opposite = negative != c.is_negative();
+    let result = match (a, b, c) {
+        _ if a.is_nan() || b.is_nan() || c.is_nan() => nan(),
+        _ if (a.is_infinite() && is_zero(b)) || (is_zero(a) && b.is_infinite()) => nan(),
+        _ if a.is_infinite() || b.is_infinite() => match c.is_infinite() && opposite {
+            true => nan(),
+            false => infinity(negative),
+        },
+        _ if c.is_infinite() => c,
+        _ if is_zero(a) || is_zero(b) => match is_zero(c) {
+            true => signed_zero(zero(negative), opposite, rm),
+            false => c,
+        },
+        _ => {
+            let product = product(unpack(a), unpack(b));
+            return match is_zero(c) {
+                true => round(product, false, rm),
+                false => rounded_sum(sum(product, unpack(c)), rm),
+            };
+        }
+    };
+    (result, 0)
+}
+
+/// An integer converted to `F` in mode `rm`, along with the NX flag.
+pub(super) fn from_int<F: Float>(value: i128, rm: u8) -> (F, u32) {
+    if value == 0 {
+        return (F::ZERO, 0);
+    }
+    let value = Unpacked {
+        negative: value < 0,
+        exponent: 0,
+        significand: value.unsigned_abs(),
+    };
+    round(value, false, rm)
+}
+
+/// A double narrowed to a single in mode `rm`, along with the NX, UF and OF
+/// flags.
+pub(super) fn narrow(a: f64, rm: u8) -> (f32, u32) {
+    let result = match a {
+        _ if a.is_nan() => nan(),
+        _ if a.is_infinite() => infinity(Float::is_negative(a)),
+        _ if is_zero(a) => zero(Float::is_negative(a)),
+        _ => return round(unpack(a), false, rm),
+    };
+    (result, 0)
+}
+
+/// A single widened to a double, which is always exact.
+pub(super) fn widen(a: f32) -> f64 {
+    match a {
+        _ if a.is_nan() => nan(),
+        _ if a.is_infinite() => infinity(Float::is_negative(a)),
+        _ if is_zero(a) => zero(Float::is_negative(a)),
+        _ => round(unpack(a), false, RNE).0,
+    }
+}
+
+/// How `a` compares to `b`, or `None` if either is a NaN.
+pub(super) fn compare<F: Float>(a: F, b: F) -> Option<Ordering> {
+    if a.is_nan() || b.is_nan() {
+        return None;
+    }
+    if is_zero(a) && is_zero(b) {
+        return Some(Ordering::Equal);
+    }
+    // Sign and magnitude order like integers once negative ones are flipped.
+    let key = |x: F| match x.is_negative() {
+        true => -((x.raw() & !F::SIGN) as i64),
+        false => x.raw() as i64,
+    };
+    Some(key(a).cmp(&key(b)))
+}
+
+/// Round `x` to an integer and saturate it into `int`'s range, along with
+/// the flags that raises.
+pub(super) fn to_int<F: Float>(x: F, int: IntWidth, rm: u8) -> (u64, u32) {
+    let (min, max): (i128, i128) = match int {
+        IntWidth::W => (i32::MIN as i128, i32::MAX as i128),
+        IntWidth::Wu => (0, u32::MAX as i128),
+        IntWidth::L => (i64::MIN as i128, i64::MAX as i128),
+        IntWidth::Lu => (0, u64::MAX as i128),
+    };
+
+    let (value, flags) = match x {
+        _ if x.is_nan() => (max, csr::FFLAG_NV),
+        _ if x.is_infinite() && x.is_negative() => (min, csr::FFLAG_NV),
+        _ if x.is_infinite() => (max, csr::FFLAG_NV),
+        _ if is_zero(x) => (0, 0),
+        _ => {
+            let x = unpack(x);
+            // Anything past 2^117 saturates; below that the magnitude fits.
+            let (magnitude, inexact) = match x.exponent {
+                65.. => (u128::MAX, false),
+                exponent @ 0.. => (x.significand << exponent, false),
+                exponent => round_to(x.significand, false, -exponent, rm, x.negative),
+            };
+            let value = match (x.negative, magnitude) {
+                (_, u128::MAX) => None,
+                (true, magnitude) => Some(-(magnitude as i128)),
+                (false, magnitude) => Some(magnitude as i128),
+            };
+            match value {
+                Some(value) if value < min => (min, csr::FFLAG_NV),
+                Some(value) if value > max => (max, csr::FFLAG_NV),
+                Some(value) => (value, if inexact { csr::FFLAG_NX } else { 0 }),
+                None if x.negative => (min, csr::FFLAG_NV),
+                None => (max, csr::FFLAG_NV),
+            }
+        }
+    };
+
+    // 32-bit results are sign-extended into the register, even unsigned ones.
+    let value = match int {
+        IntWidth::W | IntWidth::Wu => value as i32 as i64 as u64,
+        IntWidth::L | IntWidth::Lu => value as u64,
+    };
+    (value, flags)
+}
@@ -0,0 +1,297 @@
+//! Fuzzing the decoder and the hart against invariants that hold whatever
+//! a program does: nothing panics, x0 reads zero, and the PC stays where it
+//! can be.
+//!
+//! [`check_bytes`] takes arbitrary input, for a `cargo fuzz` target:
+//!
+//! ```ignore
+//! fuzz_target!(|data: &[u8]| {
+//!     riscv_emulator_rust::fuzz::check_bytes(data).unwrap();
+//! });
+//! ```
+//!
+//! [`check_random`] generates a valid RV32I program from a seed, so a
+//! property test only needs to pick seeds. Its programs keep loads and
+//! stores inside RAM and branches inside the program, so it also checks the
+//! PC never leaves the program.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::decode::{decode, decode_rv64};
+use crate::encode;
+use crate::xlen::{Rv32, Rv64, Xlen};
+use crate::{Reg, RiscvCpu, StepOutcome};
+
+/// How far a fuzzed program may go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub ram_size: usize,
+    pub max_steps: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            ram_size: 64 * 1024,
+            max_steps: 10_000,
+        }
+    }
+}
+
+/// A broken invariant, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Decoding or running panicked. `step` is the instruction it was on,
+    /// or 0 for the decoder.
+    Panic {
+        step: u64,
+        message: String,
+    },
+    X0Written {
+        step: u64,
+        pc: u64,
+        value: u64,
+    },
+    PcOutOfBounds {
+        step: u64,
+        pc: u64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Panic { step, message } => {
+                write!(f, "panicked at step {}: {}", step, message)
+            }
+            Violation::X0Written { step, pc, value } => {
+                write!(f, "x0 read {:#x} after step {} (pc {:#x})", value, step, pc)
+            }
+            Violation::PcOutOfBounds { step, pc } => {
+                write!(f, "pc {:#x} out of bounds after step {}", pc, step)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Violation {}
+
+fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panic".to_string(),
+        },
+    }
+}
+
+/// Decode and print `word` as both widths would.
+pub fn check_decode(word: u32) -> Result<(), Violation> {
+    panic::catch_unwind(|| {
+        for decoded in [decode(word), decode_rv64(word)] {
+            let _ = match decoded {
+                Ok(instruction) => instruction.to_string(),
+                Err(error) => error.to_string(),
+            };
+        }
+    })
+    .map_err(|payload| Violation::Panic {
+        step: 0,
+        message: panic_message(payload),
+    })
+}
+
+/// Run `words` from address 0 on a hart of width `X`, checking after each
+/// step that x0 is zero and, if `bounds` is given, that the PC is inside
+/// it. Stops quietly when the hart does: an exception returned to the
+/// host is the program's business, not a violation.
+///
+/// Alignment isn't checked: the hart doesn't raise instruction address
+/// misaligned exceptions, and JALR to a 2-byte boundary just lands there.
+pub fn check_program<X: Xlen>(
+    words: &[u32],
+    limits: &Limits,
+    bounds: Option<(u64, u64)>,
+) -> Result<(), Violation> {
+    let image: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+    let mut cpu = match RiscvCpu::builder()
+        .xlen::<X>()
+        .ram_size(limits.ram_size)
+        .image(0, image)
+        .build()
+    {
+        Ok(cpu) => cpu,
+        Err(_) => return Ok(()),
+    };
+    cpu.set_reg(Reg::Sp, limits.ram_size as u64 / 2);
+
+    let mut step = 0;
+    while step < limits.max_steps {
+        let stepped = panic::catch_unwind(AssertUnwindSafe(|| cpu.step()));
+        step += 1;
+        let outcome = stepped.map_err(|payload| Violation::Panic {
+            step,
+            message: panic_message(payload),
+        })?;
+
+        let pc = X::widen(cpu.pc);
+        let value = X::widen(cpu.regs[0]);
+        if value != 0 {
+            return Err(Violation::X0Written { step, pc, value });
+        }
+        match outcome {
+            Ok(StepOutcome::Executed) => {}
+            _ => return Ok(()),
+        }
+        if bounds.is_some_and(|(start, end)| !(start..end).contains(&pc)) {
+            return Err(Violation::PcOutOfBounds { step, pc });
+        }
+    }
+    Ok(())
+}
+
+/// Arbitrary input, for a fuzzer: decode every word, then run them all on
+/// an RV32 and an RV64 hart.
+pub fn check_bytes(data: &[u8]) -> Result<(), Violation> {
+    let words: Vec<u32> = data
+        .chunks(4)
+        .map(|chunk| {
+            let mut word = [0; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            u32::from_le_bytes(word)
+        })
+        .collect();
+
+    for &word in &words {
+        check_decode(word)?;
+    }
+    let limits = Limits::default();
+    check_program::<Rv32>(&words, &limits, None)?;
+    check_program::<Rv64>(&words, &limits, None)
+}
+
+/// Generate a `len`-instruction program from `seed` with [`Generator`] and
+/// run it on an RV32 and an RV64 hart, checking the PC stays inside it.
+pub fn check_random(seed: u64, len: usize, limits: &Limits) -> Result<(), Violation> {
+    let words = Generator::new(seed).program(len);
+    let bounds = Some((0, 4 * words.len() as u64));
+    check_program::<Rv32>(&words, limits, bounds)?;
+    check_program::<Rv64>(&words, limits, bounds)
+}
+
+/// Random but valid RV32I programs. Branches and jumps land inside the
+/// program, and loads and stores go through `sp`, which nothing writes, so
+/// they stay within 2 KiB of wherever it points.
+pub struct Generator {
+    state: u64,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero.
+        Self {
+            state: seed ^ 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// xorshift64*.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Any register but `sp`.
+    fn reg(&mut self) -> u8 {
+        match self.below(31) as u8 {
+            2 => 31,
+            reg => reg,
+        }
+    }
+
+    fn imm12(&mut self) -> i32 {
+        self.below(4096) as i32 - 2048
+    }
+
+    /// `len` instructions and an EBREAK to end on.
+    pub fn program(&mut self, len: usize) -> Vec<u32> {
+        let mut words: Vec<u32> = (0..len).map(|i| self.instruction(i, len)).collect();
+        words.push(encode::ebreak());
+        words
+    }
+
+    /// The instruction at `index` of a program of `len`, whose targets are
+    /// instructions `0..=len`.
+    fn instruction(&mut self, index: usize, len: usize) -> u32 {
+        let (rd, rs1, rs2) = (self.reg(), self.reg(), self.reg());
+        let target = 4 * (self.below(len as u64 + 1) as i32 - index as i32);
+        let offset = self.imm12();
+
+        match self.below(8) {
+            0 => {
+                let ops = [
+                    encode::add,
+                    encode::sub,
+                    encode::sll,
+                    encode::slt,
+                    encode::sltu,
+                    encode::xor,
+                    encode::srl,
+                    encode::sra,
+                    encode::or,
+                    encode::and,
+                ];
+                ops[self.below(ops.len() as u64) as usize](rd, rs1, rs2)
+            }
+            1 => {
+                let ops = [
+                    encode::addi,
+                    encode::slti,
+                    encode::sltiu,
+                    encode::xori,
+                    encode::ori,
+                    encode::andi,
+                ];
+                ops[self.below(ops.len() as u64) as usize](rd, rs1, offset)
+            }
+            2 => {
+                let ops = [encode::slli, encode::srli, encode::srai];
+                ops[self.below(ops.len() as u64) as usize](rd, rs1, self.below(32) as u8)
+            }
+            3 => match self.below(2) {
+                0 => encode::lui(rd, self.below(1 << 20) as u32),
+                _ => encode::auipc(rd, self.below(1 << 20) as u32),
+            },
+            4 => {
+                let ops = [encode::lb, encode::lh, encode::lw, encode::lbu, encode::lhu];
+                ops[self.below(ops.len() as u64) as usize](rd, 2, offset)
+            }
+            5 => {
+                let ops = [encode::sb, encode::sh, encode::sw];
+                ops[self.below(ops.len() as u64) as usize](rs2, 2, offset)
+            }
+            6 => {
+                let ops = [
+                    encode::beq,
+                    encode::bne,
+                    encode::blt,
+                    encode::bge,
+                    encode::bltu,
+                    encode::bgeu,
+                ];
+                match target {
+                    -4096..=4094 => ops[self.below(ops.len() as u64) as usize](rs1, rs2, target),
+                    _ => encode::jal(rd, target),
+                }
+            }
+            _ => encode::jal(rd, target),
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! Callbacks around each instruction, for tools that trace, inject faults
+//! or gather statistics without a step loop of their own.
+
+use crate::custom::Hart;
+use crate::decode::Instruction;
+
+/// The instruction a hook is called about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    pub pc: u32,
+    pub raw: u32,
+    pub instruction: Instruction,
+}
+
+/// Called with the hart and the instruction it's executing. The hart's
+/// [`jump`](Hart::jump) sets where execution carries on, though before
+/// the instruction runs a branch it takes still wins.
+pub type StepHook = Box<dyn FnMut(&mut dyn Hart, &StepInfo)>;
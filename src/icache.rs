@@ -0,0 +1,46 @@
+//! A direct-mapped cache of decoded instructions, indexed by PC.
+
+use crate::decode::Instruction;
+
+const ENTRIES: usize = 4096;
+
+#[derive(Clone, Copy)]
+struct Entry {
+    raw: u32,
+    instruction: Instruction,
+}
+
+/// Saves re-decoding hot instructions. An entry only hits if the word just
+/// fetched matches the one it was decoded from, so self-modifying code and
+/// host writes through `bus[..]` never see a stale decode. FENCE.I still
+/// flushes it, as the spec requires of an instruction cache.
+pub(crate) struct DecodeCache {
+    entries: Vec<Option<Entry>>,
+}
+
+impl DecodeCache {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: vec![None; ENTRIES],
+        }
+    }
+
+    pub(crate) fn get(&self, pc: u64, raw: u32) -> Option<Instruction> {
+        match self.entries[Self::index(pc)] {
+            Some(entry) if entry.raw == raw => Some(entry.instruction),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, pc: u64, raw: u32, instruction: Instruction) {
+        self.entries[Self::index(pc)] = Some(Entry { raw, instruction });
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    fn index(pc: u64) -> usize {
+        (pc >> 2) as usize & (ENTRIES - 1)
+    }
+}
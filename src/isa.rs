@@ -0,0 +1,326 @@
+//! Which optional extensions a hart implements. Instructions from a
+//! disabled extension decode as illegal, and `misa` only reports the
+//! enabled single-letter ones.
+
+use crate::RiscvCpu;
+use crate::csr;
+use crate::decode::{
+    AesOp, CryptoInstruction, DecodeError, FloatInstruction, FpFormat, Instruction, decode,
+    decode_rv64,
+};
+use crate::float::FloatRegs;
+use crate::xlen::Xlen;
+
+/// An extension that can be left out. The base ISA, Zicsr, Zifencei,
+/// counters and machine mode are always there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Extension {
+    F,
+    D,
+    V,
+    /// Supervisor mode, SFENCE.VMA and the supervisor CSRs.
+    S,
+    /// User mode.
+    U,
+    /// The hypervisor extension: VS- and VU-mode, two-stage translation
+    /// and the hypervisor CSRs.
+    H,
+    Zalrsc,
+    Zawrs,
+    Zba,
+    Zbkb,
+    Zkne,
+    Zknd,
+    Zknh,
+}
+
+impl Extension {
+    pub const ALL: [Extension; 13] = [
+        Extension::F,
+        Extension::D,
+        Extension::V,
+        Extension::S,
+        Extension::U,
+        Extension::H,
+        Extension::Zalrsc,
+        Extension::Zawrs,
+        Extension::Zba,
+        Extension::Zbkb,
+        Extension::Zkne,
+        Extension::Zknd,
+        Extension::Zknh,
+    ];
+
+    /// The name used in ISA strings, lowercase.
+    pub fn name(self) -> &'static str {
+        match self {
+            Extension::F => "f",
+            Extension::D => "d",
+            Extension::V => "v",
+            Extension::S => "s",
+            Extension::U => "u",
+            Extension::H => "h",
+            Extension::Zalrsc => "zalrsc",
+            Extension::Zawrs => "zawrs",
+            Extension::Zba => "zba",
+            Extension::Zbkb => "zbkb",
+            Extension::Zkne => "zkne",
+            Extension::Zknd => "zknd",
+            Extension::Zknh => "zknh",
+        }
+    }
+
+    /// The extension's `misa` bit, for single-letter ones.
+    pub fn misa_bit(self) -> Option<u32> {
+        match self {
+            Extension::F => Some(csr::MISA_F),
+            Extension::D => Some(csr::MISA_D),
+            Extension::V => Some(csr::MISA_V),
+            Extension::S => Some(csr::MISA_S),
+            Extension::U => Some(csr::MISA_U),
+            Extension::H => Some(csr::MISA_H),
+            _ => None,
+        }
+    }
+}
+
+/// A set of [`Extension`]s. Everything is enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Extensions(u32);
+
+impl Extensions {
+    pub fn all() -> Self {
+        Self((1 << Extension::ALL.len()) - 1)
+    }
+
+    /// Just RV32I/RV64I and machine mode.
+    pub fn none() -> Self {
+        Self(0)
+    }
+
+    pub fn with(self, extension: Extension) -> Self {
+        Self(self.0 | 1 << extension as u32)
+    }
+
+    pub fn without(self, extension: Extension) -> Self {
+        Self(self.0 & !(1 << extension as u32))
+    }
+
+    pub fn contains(self, extension: Extension) -> bool {
+        self.0 >> extension as u32 & 1 != 0
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Extension> {
+        Extension::ALL
+            .into_iter()
+            .filter(move |&extension| self.contains(extension))
+    }
+
+    /// Parse an ISA string such as `rv32ifd_zba_zbkb`. The `rv32`/`rv64`
+    /// prefix is optional and isn't checked against the hart; `g` stands
+    /// for `ifd`. Extensions the emulator doesn't implement, like M or C,
+    /// are an error.
+    pub fn parse(isa: &str) -> Result<Self, String> {
+        let isa = isa.to_ascii_lowercase();
+        let rest = isa
+            .strip_prefix("rv32")
+            .or_else(|| isa.strip_prefix("rv64"))
+            .unwrap_or(&isa);
+
+        let mut parts = rest.split('_');
+        let letters = parts.next().unwrap_or_default();
+        let Some(letters) = letters
+            .strip_prefix('i')
+            .or_else(|| letters.strip_prefix('g'))
+        else {
+            return Err(format!("'{}' doesn't start with I or G", isa));
+        };
+
+        let mut extensions = Self::none();
+        if rest.starts_with('g') {
+            extensions = extensions.with(Extension::F).with(Extension::D);
+        }
+        for letter in letters.chars() {
+            let letter = letter.to_string();
+            let extension = Extension::ALL
+                .into_iter()
+                .find(|e| e.misa_bit().is_some() && e.name() == letter)
+                .ok_or_else(|| format!("extension '{}' isn't supported", letter))?;
+            extensions = extensions.with(extension);
+        }
+
+        for name in parts {
+            if matches!(name, "zicsr" | "zifencei" | "zicntr" | "zihpm") {
+                continue;
+            }
+            let extension = Extension::ALL
+                .into_iter()
+                .find(|e| e.misa_bit().is_none() && e.name() == name)
+                .ok_or_else(|| format!("extension '{}' isn't supported", name))?;
+            extensions = extensions.with(extension);
+        }
+
+        extensions.check()?;
+        Ok(extensions)
+    }
+
+    /// The ISA string for a hart of `xlen` bits with these extensions, in
+    /// canonical order, as device trees want it: `rv32ifdvh_zicntr_...`.
+    /// S and U have no letter there.
+    pub fn isa_string(self, xlen: u32) -> String {
+        let mut isa = format!("rv{}i", xlen);
+        for extension in self.iter() {
+            if extension.misa_bit().is_some() && !matches!(extension, Extension::S | Extension::U) {
+                isa.push_str(extension.name());
+            }
+        }
+
+        let mut names = vec!["zicntr", "zicsr", "zifencei", "zihpm"];
+        names.extend(
+            self.iter()
+                .filter(|extension| extension.misa_bit().is_none())
+                .map(Extension::name),
+        );
+        // By the letter after the z, in the order single letters go, then
+        // alphabetically.
+        names.sort_by_key(|name| ("iabk".find(&name[1..2]), *name));
+        for name in names {
+            isa.push('_');
+            isa.push_str(name);
+        }
+        isa
+    }
+
+    /// Reject combinations the spec doesn't allow.
+    pub(crate) fn check(self) -> Result<(), String> {
+        if self.contains(Extension::D) && !self.contains(Extension::F) {
+            return Err("D requires F".to_string());
+        }
+        if self.contains(Extension::S) && !self.contains(Extension::U) {
+            return Err("S requires U".to_string());
+        }
+        if self.contains(Extension::H) && !self.contains(Extension::S) {
+            return Err("H requires S".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for Extensions {
+    fn default() -> Self {
+        Self::all()
+    }
+}
+
+impl FromIterator<Extension> for Extensions {
+    fn from_iter<I: IntoIterator<Item = Extension>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::none(), Self::with)
+    }
+}
+
+/// The extension `instruction` belongs to, if it's an optional one.
+pub(crate) fn required(instruction: Instruction) -> Option<Extension> {
+    use Instruction::*;
+
+    let extension = match instruction {
+        Float(instruction) => float_extension(instruction),
+        Vector(_) => Extension::V,
+        Crypto(CryptoInstruction::Aes32 { op, .. }) => match op {
+            AesOp::Esi | AesOp::Esmi => Extension::Zkne,
+            AesOp::Dsi | AesOp::Dsmi => Extension::Zknd,
+        },
+        Crypto(_) => Extension::Zknh,
+        Hypervisor(_) => Extension::H,
+        Sh1add { .. }
+        | Sh2add { .. }
+        | Sh3add { .. }
+        | AddUw { .. }
+        | Sh1addUw { .. }
+        | Sh2addUw { .. }
+        | Sh3addUw { .. }
+        | SlliUw { .. } => Extension::Zba,
+        Pack { .. } | Packh { .. } | Packw { .. } | Brev8 { .. } | Zip { .. } | Unzip { .. } => {
+            Extension::Zbkb
+        }
+        LrW { .. } | ScW { .. } | LrD { .. } | ScD { .. } => Extension::Zalrsc,
+        WrsNto | WrsSto => Extension::Zawrs,
+        SfenceVma { .. } | Sret => Extension::S,
+        _ => return None,
+    };
+    Some(extension)
+}
+
+fn float_extension(instruction: FloatInstruction) -> Extension {
+    use FloatInstruction::*;
+
+    let fmt = match instruction {
+        Flw { .. } | Fsw { .. } => FpFormat::Single,
+        Fld { .. } | Fsd { .. } | FcvtFp { .. } => FpFormat::Double,
+        Farith { fmt, .. }
+        | Fma { fmt, .. }
+        | Fsqrt { fmt, .. }
+        | Fcmp { fmt, .. }
+        | Fclass { fmt, .. }
+        | FcvtToInt { fmt, .. }
+        | FcvtFromInt { fmt, .. }
+        | FmvToInt { fmt, .. }
+        | FmvFromInt { fmt, .. } => fmt,
+    };
+    match fmt {
+        FpFormat::Single => Extension::F,
+        FpFormat::Double => Extension::D,
+    }
+}
+
+impl<X: Xlen> RiscvCpu<X> {
+    pub fn extensions(&self) -> Extensions {
+        self.extensions
+    }
+
+    /// Enable only `extensions`, and report them in `misa`. Code already
+    /// decoded is thrown away.
+    pub fn set_extensions(&mut self, extensions: Extensions) -> Result<(), String> {
+        extensions.check()?;
+        self.extensions = extensions;
+        self.update_misa();
+        self.icache.clear();
+        self.flush_blocks();
+        Ok(())
+    }
+
+    /// Whether the hart implements `extension`.
+    pub fn has_extension(&self, extension: Extension) -> bool {
+        self.extensions.contains(extension)
+    }
+
+    /// Set the extension bits of `misa` from the enabled extensions. F and
+    /// D aren't reported under Zfinx/Zdinx.
+    pub(crate) fn update_misa(&mut self) {
+        let mut bits = 0;
+        for extension in self.extensions.iter() {
+            let float = matches!(extension, Extension::F | Extension::D);
+            if float && self.float_regs == FloatRegs::Integer {
+                continue;
+            }
+            bits |= extension.misa_bit().unwrap_or(0);
+        }
+
+        let misa = self.csrs.read_u64(csr::MISA) & !(csr::MISA_EXTENSIONS as u64);
+        self.csrs
+            .set_u64(csr::MISA, misa | csr::MISA_I as u64 | bits as u64);
+    }
+
+    /// Decode `raw` for this hart: illegal if its extension is disabled.
+    pub(crate) fn decode_enabled(&self, raw: u32) -> Result<Instruction, DecodeError> {
+        let decoded = match X::BITS {
+            64 => decode_rv64(raw)?,
+            _ => decode(raw)?,
+        };
+        match required(decoded) {
+            Some(extension) if !self.extensions.contains(extension) => {
+                Err(DecodeError::IllegalInstruction(raw))
+            }
+            _ => Ok(decoded),
+        }
+    }
+}
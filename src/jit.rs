@@ -0,0 +1,714 @@
+//! A Cranelift backend for [`Engine::Jit`](crate::Engine::Jit).
+//!
+//! Hot basic blocks are compiled to native functions that work directly on
+//! the register file. Loads and stores call back into the interpreter, so
+//! translation, faults, watchpoints and device accesses behave exactly as
+//! they do there. Anything privileged ends a block anyway, and is left to
+//! [`RiscvCpu::step`].
+
+use std::collections::HashMap;
+use std::ptr;
+use std::rc::Rc;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{AbiParam, InstBuilder, MemFlags, Type, Value, types};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Module, default_libcall_names};
+
+use crate::block::Block;
+use crate::csr::{HpmEvent, Privilege};
+use crate::decode::Instruction;
+use crate::trap::Exception;
+use crate::xlen::Xlen;
+use crate::{RiscvCpu, StepOutcome};
+
+/// Runs through the block interpreter before a block is compiled.
+const HOT_THRESHOLD: u32 = 16;
+
+// What `exec_helper` tells the compiled code to do next.
+const FAULTED: u32 = 0;
+const CONTINUE: u32 = 1;
+const STOP: u32 = 2;
+
+/// `(regs, pc, cpu, budget) -> instructions retired`. The PC is written back
+/// on every exit. A block that branches back to its own start keeps looping
+/// while another pass fits in `budget`.
+type BlockFn = unsafe extern "C" fn(*mut u8, *mut u8, *mut u8, u64) -> u64;
+
+enum Entry {
+    Cold(u32),
+    Compiled {
+        code: BlockFn,
+        /// Compiled code points into the block's instructions.
+        _block: Rc<Block>,
+    },
+    /// Starts with an instruction the JIT leaves to the interpreter.
+    Unsupported,
+}
+
+pub(crate) struct Jit {
+    module: JITModule,
+    ctx: cranelift_codegen::Context,
+    builder_ctx: FunctionBuilderContext,
+    entries: HashMap<(u64, Privilege, bool), Entry>,
+    /// The block cache generation `entries` was built against. Code for
+    /// flushed blocks stays in the module until the CPU is dropped.
+    generation: u64,
+    /// Set by `exec_helper` when compiled code had to bail out mid-block.
+    stopped: bool,
+}
+
+impl Jit {
+    pub(crate) fn new() -> Result<Self, String> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").map_err(|e| e.to_string())?;
+        let isa = cranelift_native::builder()
+            .map_err(|e| format!("JIT: unsupported host: {}", e))?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| format!("JIT: {}", e))?;
+
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+        Ok(Self {
+            ctx: module.make_context(),
+            module,
+            builder_ctx: FunctionBuilderContext::new(),
+            entries: HashMap::new(),
+            generation: 0,
+            stopped: false,
+        })
+    }
+
+    /// Compiled code for the block at `pc`, once it's been run often enough.
+    fn lookup<X: Xlen>(
+        &mut self,
+        pc: u64,
+        privilege: Privilege,
+        virt: bool,
+        block: &Rc<Block>,
+        generation: u64,
+    ) -> Option<BlockFn> {
+        if generation != self.generation {
+            self.entries.clear();
+            self.generation = generation;
+        }
+
+        let entry = self
+            .entries
+            .entry((pc, privilege, virt))
+            .or_insert(Entry::Cold(0));
+        match entry {
+            Entry::Compiled { code, .. } => return Some(*code),
+            Entry::Unsupported => return None,
+            Entry::Cold(runs) if *runs < HOT_THRESHOLD => {
+                *runs += 1;
+                return None;
+            }
+            Entry::Cold(_) => {}
+        }
+
+        let compiled = compiled_len(block);
+        let new_entry = if compiled == 0 {
+            Entry::Unsupported
+        } else {
+            match self.compile::<X>(pc, &block.instructions[..compiled]) {
+                Ok(code) => Entry::Compiled {
+                    code,
+                    _block: block.clone(),
+                },
+                Err(_) => Entry::Unsupported,
+            }
+        };
+
+        let code = match &new_entry {
+            Entry::Compiled { code, .. } => Some(*code),
+            _ => None,
+        };
+        self.entries.insert((pc, privilege, virt), new_entry);
+        code
+    }
+
+    fn compile<X: Xlen>(
+        &mut self,
+        start: u64,
+        instructions: &[(u32, Instruction)],
+    ) -> Result<BlockFn, String> {
+        let ptr_ty = self.module.target_config().pointer_type();
+        let ty = if X::BITS == 64 {
+            types::I64
+        } else {
+            types::I32
+        };
+
+        let mut sig = self.module.make_signature();
+        sig.params.extend([AbiParam::new(ptr_ty); 3]);
+        sig.params.push(AbiParam::new(types::I64));
+        sig.returns.push(AbiParam::new(types::I64));
+
+        let mut helper_sig = self.module.make_signature();
+        helper_sig.params.extend([
+            AbiParam::new(ptr_ty),
+            AbiParam::new(ptr_ty),
+            AbiParam::new(types::I64),
+        ]);
+        helper_sig.returns.push(AbiParam::new(types::I32));
+
+        let id = self
+            .module
+            .declare_anonymous_function(&sig)
+            .map_err(|e| e.to_string())?;
+        self.ctx.func.signature = sig;
+
+        let mut b = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_ctx);
+        let helper_sig = b.import_signature(helper_sig);
+        let entry = b.create_block();
+        b.append_block_params_for_function_params(entry);
+        b.switch_to_block(entry);
+        b.seal_block(entry);
+
+        let params = b.block_params(entry).to_vec();
+        let g = Gen {
+            ty,
+            regs: params[0],
+            pc_out: params[1],
+            cpu: params[2],
+        };
+        let budget = params[3];
+
+        // `done` counts instructions retired by earlier passes.
+        let top = b.create_block();
+        b.append_block_param(top, types::I64);
+        let zero = b.ins().iconst(types::I64, 0);
+        b.ins().jump(top, &[zero]);
+        b.switch_to_block(top);
+        let done = b.block_params(top)[0];
+        let len = instructions.len() as i64;
+
+        let helper = exec_helper::<X> as *const u8 as i64;
+        let mut ended = false;
+
+        for (i, &(_, instruction)) in instructions.iter().enumerate() {
+            let pc = start.wrapping_add(4 * i as u64) & X::MASK;
+            let next = pc.wrapping_add(4) & X::MASK;
+
+            if let Some(jump) = g.control_flow(&mut b, instruction, pc, X::MASK) {
+                // Only the last instruction can jump, so this is a full pass.
+                let retired = b.ins().iadd_imm(done, len);
+                let start_pc = g.konst(&mut b, start);
+                let again = b.ins().icmp(IntCC::Equal, jump, start_pc);
+                let next_pass = b.ins().iadd_imm(retired, len);
+                let fits = b
+                    .ins()
+                    .icmp(IntCC::UnsignedLessThanOrEqual, next_pass, budget);
+                let again = b.ins().band(again, fits);
+
+                let leave = b.create_block();
+                b.ins().brif(again, top, &[retired], leave, &[]);
+                b.switch_to_block(leave);
+                b.seal_block(leave);
+                g.exit(&mut b, jump, retired);
+                ended = true;
+                break;
+            }
+            if g.alu(&mut b, instruction, pc) {
+                continue;
+            }
+
+            // Everything else runs in the interpreter.
+            let callee = b.ins().iconst(ptr_ty, helper);
+            let instruction_ptr = b.ins().iconst(
+                ptr_ty,
+                &instructions[i].1 as *const Instruction as usize as i64,
+            );
+            let pc_arg = b.ins().iconst(types::I64, pc as i64);
+            let call = b
+                .ins()
+                .call_indirect(helper_sig, callee, &[g.cpu, instruction_ptr, pc_arg]);
+            let status = b.inst_results(call)[0];
+
+            let (cont, not_ok, faulted, stopped) = (
+                b.create_block(),
+                b.create_block(),
+                b.create_block(),
+                b.create_block(),
+            );
+            let ok = b.ins().icmp_imm(IntCC::Equal, status, CONTINUE as i64);
+            b.ins().brif(ok, cont, &[], not_ok, &[]);
+
+            b.switch_to_block(not_ok);
+            b.seal_block(not_ok);
+            let fault = b.ins().icmp_imm(IntCC::Equal, status, FAULTED as i64);
+            b.ins().brif(fault, faulted, &[], stopped, &[]);
+
+            b.switch_to_block(faulted);
+            b.seal_block(faulted);
+            let here = g.konst(&mut b, pc);
+            let retired = b.ins().iadd_imm(done, i as i64);
+            g.exit(&mut b, here, retired);
+
+            b.switch_to_block(stopped);
+            b.seal_block(stopped);
+            let after = g.konst(&mut b, next);
+            let retired = b.ins().iadd_imm(done, i as i64 + 1);
+            g.exit(&mut b, after, retired);
+
+            b.switch_to_block(cont);
+            b.seal_block(cont);
+        }
+
+        if !ended {
+            let end = start.wrapping_add(4 * instructions.len() as u64) & X::MASK;
+            let end = g.konst(&mut b, end);
+            let retired = b.ins().iadd_imm(done, len);
+            g.exit(&mut b, end, retired);
+        }
+
+        b.seal_block(top);
+        b.finalize();
+
+        self.module
+            .define_function(id, &mut self.ctx)
+            .map_err(|e| e.to_string())?;
+        self.module.clear_context(&mut self.ctx);
+        self.module
+            .finalize_definitions()
+            .map_err(|e| e.to_string())?;
+
+        let code = self.module.get_finalized_function(id);
+        // SAFETY: the function was just built with `BlockFn`'s signature.
+        Ok(unsafe { std::mem::transmute::<*const u8, BlockFn>(code) })
+    }
+}
+
+/// How many of `block`'s instructions can be compiled. Privileged ones are
+/// always last, so they just get cut off.
+fn compiled_len(block: &Block) -> usize {
+    use Instruction::*;
+
+    block
+        .instructions
+        .iter()
+        .take_while(|(_, instruction)| {
+            !matches!(
+                instruction,
+                Ecall
+                    | Ebreak
+                    | Mret
+                    | Sret
+                    | Wfi
+                    | WrsNto
+                    | WrsSto
+                    | Custom(_)
+                    | Hypervisor(_)
+                    | FenceI
+                    | SfenceVma { .. }
+                    | Csrrw { .. }
+                    | Csrrs { .. }
+                    | Csrrc { .. }
+                    | Csrrwi { .. }
+                    | Csrrsi { .. }
+                    | Csrrci { .. }
+            )
+        })
+        .count()
+}
+
+/// Run one instruction the compiled code doesn't handle itself.
+unsafe extern "C" fn exec_helper<X: Xlen>(
+    cpu: *mut u8,
+    instruction: *const Instruction,
+    pc: u64,
+) -> u32 {
+    // SAFETY: compiled code only ever passes the CPU it was called with and
+    // an instruction in a block it keeps alive.
+    let (cpu, instruction) = unsafe { (&mut *(cpu as *mut RiscvCpu<X>), *instruction) };
+
+    cpu.pc = X::truncate(pc);
+    let generation = cpu.blocks.generation();
+    let mut next_pc = X::truncate(pc.wrapping_add(4));
+
+    let status = match cpu.execute_instruction(instruction, &mut next_pc) {
+        Err(_) => FAULTED,
+        Ok(()) if cpu.debug.hit.is_some() || cpu.blocks.generation() != generation => STOP,
+        // A device may have raised one, and a looping block wouldn't notice.
+        Ok(()) if cpu.pending_interrupt().is_some() => STOP,
+        Ok(()) => CONTINUE,
+    };
+    if status != CONTINUE
+        && let Some(jit) = cpu.jit.as_mut()
+    {
+        jit.stopped = true;
+    }
+    status
+}
+
+/// Per-function codegen state.
+struct Gen {
+    ty: Type,
+    regs: Value,
+    pc_out: Value,
+    cpu: Value,
+}
+
+impl Gen {
+    fn reg_offset(&self, reg: u8) -> i32 {
+        reg as i32 * self.ty.bytes() as i32
+    }
+
+    fn read(&self, b: &mut FunctionBuilder, reg: u8) -> Value {
+        if reg == 0 {
+            return b.ins().iconst(self.ty, 0);
+        }
+        b.ins().load(
+            self.ty,
+            MemFlags::trusted(),
+            self.regs,
+            self.reg_offset(reg),
+        )
+    }
+
+    fn write(&self, b: &mut FunctionBuilder, reg: u8, value: Value) {
+        if reg != 0 {
+            b.ins()
+                .store(MemFlags::trusted(), value, self.regs, self.reg_offset(reg));
+        }
+    }
+
+    /// A register-width constant; `value` is truncated to fit.
+    fn konst(&self, b: &mut FunctionBuilder, value: u64) -> Value {
+        let value = if self.ty == types::I32 {
+            value as u32 as i64
+        } else {
+            value as i64
+        };
+        b.ins().iconst(self.ty, value)
+    }
+
+    fn imm(&self, b: &mut FunctionBuilder, imm: i32) -> Value {
+        self.konst(b, imm as i64 as u64)
+    }
+
+    fn exit(&self, b: &mut FunctionBuilder, pc: Value, retired: Value) {
+        b.ins().store(MemFlags::trusted(), pc, self.pc_out, 0);
+        b.ins().return_(&[retired]);
+    }
+
+    /// A jump or branch: returns the next PC.
+    fn control_flow(
+        &self,
+        b: &mut FunctionBuilder,
+        instruction: Instruction,
+        pc: u64,
+        mask: u64,
+    ) -> Option<Value> {
+        use Instruction::*;
+
+        let link = pc.wrapping_add(4) & mask;
+        let (cc, rs1, rs2, imm) = match instruction {
+            Jal { rd, imm } => {
+                let link = self.konst(b, link);
+                self.write(b, rd, link);
+                return Some(self.konst(b, pc.wrapping_add(imm as i64 as u64)));
+            }
+            Jalr { rd, rs1, imm } => {
+                let base = self.read(b, rs1);
+                let offset = self.imm(b, imm);
+                let target = b.ins().iadd(base, offset);
+                let mask = self.konst(b, !1);
+                let target = b.ins().band(target, mask);
+                let link = self.konst(b, link);
+                self.write(b, rd, link);
+                return Some(target);
+            }
+            Beq { rs1, rs2, imm } => (IntCC::Equal, rs1, rs2, imm),
+            Bne { rs1, rs2, imm } => (IntCC::NotEqual, rs1, rs2, imm),
+            Blt { rs1, rs2, imm } => (IntCC::SignedLessThan, rs1, rs2, imm),
+            Bge { rs1, rs2, imm } => (IntCC::SignedGreaterThanOrEqual, rs1, rs2, imm),
+            Bltu { rs1, rs2, imm } => (IntCC::UnsignedLessThan, rs1, rs2, imm),
+            Bgeu { rs1, rs2, imm } => (IntCC::UnsignedGreaterThanOrEqual, rs1, rs2, imm),
+            _ => return None,
+        };
+
+        let (a, c) = (self.read(b, rs1), self.read(b, rs2));
+        let taken = b.ins().icmp(cc, a, c);
+        let target = self.konst(b, pc.wrapping_add(imm as i64 as u64));
+        let fallthrough = self.konst(b, link);
+        Some(b.ins().select(taken, target, fallthrough))
+    }
+
+    /// Integer computation with no side effects besides `rd`. Returns false
+    /// if `instruction` isn't one.
+    fn alu(&self, b: &mut FunctionBuilder, instruction: Instruction, pc: u64) -> bool {
+        use Instruction::*;
+
+        let value = match instruction {
+            Lui { rd, imm } => (rd, self.imm(b, imm as i32)),
+            Auipc { rd, imm } => (rd, self.konst(b, pc.wrapping_add(imm as i32 as i64 as u64))),
+
+            Addi { rd, rs1, imm } => self.binary_imm(b, rd, rs1, imm, |b, x, y| b.ins().iadd(x, y)),
+            Xori { rd, rs1, imm } => self.binary_imm(b, rd, rs1, imm, |b, x, y| b.ins().bxor(x, y)),
+            Ori { rd, rs1, imm } => self.binary_imm(b, rd, rs1, imm, |b, x, y| b.ins().bor(x, y)),
+            Andi { rd, rs1, imm } => self.binary_imm(b, rd, rs1, imm, |b, x, y| b.ins().band(x, y)),
+            Slti { rd, rs1, imm } => self.compare_imm(b, rd, rs1, imm, IntCC::SignedLessThan),
+            Sltiu { rd, rs1, imm } => self.compare_imm(b, rd, rs1, imm, IntCC::UnsignedLessThan),
+            Slli { rd, rs1, shamt } => {
+                self.binary_imm(b, rd, rs1, shamt as i32, |b, x, y| b.ins().ishl(x, y))
+            }
+            Srli { rd, rs1, shamt } => {
+                self.binary_imm(b, rd, rs1, shamt as i32, |b, x, y| b.ins().ushr(x, y))
+            }
+            Srai { rd, rs1, shamt } => {
+                self.binary_imm(b, rd, rs1, shamt as i32, |b, x, y| b.ins().sshr(x, y))
+            }
+
+            // Cranelift takes shift amounts modulo the type width, which is
+            // exactly RISC-V's rule.
+            Add { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().iadd(x, y)),
+            Sub { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().isub(x, y)),
+            Sll { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().ishl(x, y)),
+            Srl { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().ushr(x, y)),
+            Sra { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().sshr(x, y)),
+            Xor { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().bxor(x, y)),
+            Or { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().bor(x, y)),
+            And { rd, rs1, rs2 } => self.binary(b, rd, rs1, rs2, |b, x, y| b.ins().band(x, y)),
+            Slt { rd, rs1, rs2 } => self.compare(b, rd, rs1, rs2, IntCC::SignedLessThan),
+            Sltu { rd, rs1, rs2 } => self.compare(b, rd, rs1, rs2, IntCC::UnsignedLessThan),
+
+            Addiw { rd, rs1, imm } => self.word_imm(b, rd, rs1, imm, |b, x, y| b.ins().iadd(x, y)),
+            Slliw { rd, rs1, shamt } => {
+                self.word_imm(b, rd, rs1, shamt as i32, |b, x, y| b.ins().ishl(x, y))
+            }
+            Srliw { rd, rs1, shamt } => {
+                self.word_imm(b, rd, rs1, shamt as i32, |b, x, y| b.ins().ushr(x, y))
+            }
+            Sraiw { rd, rs1, shamt } => {
+                self.word_imm(b, rd, rs1, shamt as i32, |b, x, y| b.ins().sshr(x, y))
+            }
+            Addw { rd, rs1, rs2 } => self.word(b, rd, rs1, rs2, |b, x, y| b.ins().iadd(x, y)),
+            Subw { rd, rs1, rs2 } => self.word(b, rd, rs1, rs2, |b, x, y| b.ins().isub(x, y)),
+            Sllw { rd, rs1, rs2 } => self.word(b, rd, rs1, rs2, |b, x, y| b.ins().ishl(x, y)),
+            Srlw { rd, rs1, rs2 } => self.word(b, rd, rs1, rs2, |b, x, y| b.ins().ushr(x, y)),
+            Sraw { rd, rs1, rs2 } => self.word(b, rd, rs1, rs2, |b, x, y| b.ins().sshr(x, y)),
+
+            Sh1add { rd, rs1, rs2 } => self.shift_add(b, rd, rs1, rs2, 1, false),
+            Sh2add { rd, rs1, rs2 } => self.shift_add(b, rd, rs1, rs2, 2, false),
+            Sh3add { rd, rs1, rs2 } => self.shift_add(b, rd, rs1, rs2, 3, false),
+            AddUw { rd, rs1, rs2 } => self.shift_add(b, rd, rs1, rs2, 0, true),
+            Sh1addUw { rd, rs1, rs2 } => self.shift_add(b, rd, rs1, rs2, 1, true),
+            Sh2addUw { rd, rs1, rs2 } => self.shift_add(b, rd, rs1, rs2, 2, true),
+            Sh3addUw { rd, rs1, rs2 } => self.shift_add(b, rd, rs1, rs2, 3, true),
+            SlliUw { rd, rs1, shamt } => {
+                let x = self.read(b, rs1);
+                let low = b.ins().band_imm(x, 0xFFFF_FFFF);
+                (rd, b.ins().ishl_imm(low, shamt as i64))
+            }
+            Pack { rd, rs1, rs2 } => {
+                let half = self.ty.bits() as i64 / 2;
+                let (x, y) = (self.read(b, rs1), self.read(b, rs2));
+                let low = b.ins().band_imm(x, (1i64 << half) - 1);
+                let high = b.ins().ishl_imm(y, half);
+                (rd, b.ins().bor(low, high))
+            }
+            Packh { rd, rs1, rs2 } => {
+                let (x, y) = (self.read(b, rs1), self.read(b, rs2));
+                let low = b.ins().band_imm(x, 0xFF);
+                let high = b.ins().band_imm(y, 0xFF);
+                let high = b.ins().ishl_imm(high, 8);
+                (rd, b.ins().bor(low, high))
+            }
+
+            Fence => return true,
+            _ => return false,
+        };
+
+        self.write(b, value.0, value.1);
+        true
+    }
+
+    fn binary(
+        &self,
+        b: &mut FunctionBuilder,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+        op: fn(&mut FunctionBuilder, Value, Value) -> Value,
+    ) -> (u8, Value) {
+        let (x, y) = (self.read(b, rs1), self.read(b, rs2));
+        (rd, op(b, x, y))
+    }
+
+    /// Zba's `(rs1 << shift) + rs2`, zero-extending rs1's low word first
+    /// for the `.uw` forms.
+    fn shift_add(
+        &self,
+        b: &mut FunctionBuilder,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+        shift: i64,
+        unsigned_word: bool,
+    ) -> (u8, Value) {
+        let (mut x, y) = (self.read(b, rs1), self.read(b, rs2));
+        if unsigned_word {
+            x = b.ins().band_imm(x, 0xFFFF_FFFF);
+        }
+        let shifted = b.ins().ishl_imm(x, shift);
+        (rd, b.ins().iadd(shifted, y))
+    }
+
+    fn binary_imm(
+        &self,
+        b: &mut FunctionBuilder,
+        rd: u8,
+        rs1: u8,
+        imm: i32,
+        op: fn(&mut FunctionBuilder, Value, Value) -> Value,
+    ) -> (u8, Value) {
+        let (x, y) = (self.read(b, rs1), self.imm(b, imm));
+        (rd, op(b, x, y))
+    }
+
+    fn compare(&self, b: &mut FunctionBuilder, rd: u8, rs1: u8, rs2: u8, cc: IntCC) -> (u8, Value) {
+        let (x, y) = (self.read(b, rs1), self.read(b, rs2));
+        let flag = b.ins().icmp(cc, x, y);
+        (rd, b.ins().uextend(self.ty, flag))
+    }
+
+    fn compare_imm(
+        &self,
+        b: &mut FunctionBuilder,
+        rd: u8,
+        rs1: u8,
+        imm: i32,
+        cc: IntCC,
+    ) -> (u8, Value) {
+        let (x, y) = (self.read(b, rs1), self.imm(b, imm));
+        let flag = b.ins().icmp(cc, x, y);
+        (rd, b.ins().uextend(self.ty, flag))
+    }
+
+    /// An RV64 *W operation: work on the low 32 bits and sign-extend.
+    fn word(
+        &self,
+        b: &mut FunctionBuilder,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+        op: fn(&mut FunctionBuilder, Value, Value) -> Value,
+    ) -> (u8, Value) {
+        let (x, y) = (self.read(b, rs1), self.read(b, rs2));
+        let (x, y) = (
+            b.ins().ireduce(types::I32, x),
+            b.ins().ireduce(types::I32, y),
+        );
+        let result = op(b, x, y);
+        (rd, b.ins().sextend(types::I64, result))
+    }
+
+    fn word_imm(
+        &self,
+        b: &mut FunctionBuilder,
+        rd: u8,
+        rs1: u8,
+        imm: i32,
+        op: fn(&mut FunctionBuilder, Value, Value) -> Value,
+    ) -> (u8, Value) {
+        let x = self.read(b, rs1);
+        let x = b.ins().ireduce(types::I32, x);
+        let y = b.ins().iconst(types::I32, imm as u32 as i64);
+        let result = op(b, x, y);
+        (rd, b.ins().sextend(types::I64, result))
+    }
+}
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// [`step_block`](Self::step_block), but running the block as native
+    /// code once it's hot. Anything the compiled code can't stop precisely
+    /// for (a tracer, a step budget or target inside the block) goes
+    /// through the block interpreter instead.
+    pub(crate) fn step_jit(
+        &mut self,
+        budget: Option<u64>,
+        target: Option<u32>,
+    ) -> (u64, Result<StepOutcome, Exception>) {
+        let vpc = X::widen(self.pc);
+        let breakpoint = Self::phys(vpc).is_some_and(|pc| self.debug.breakpoints.contains(&pc));
+
+        // Compiled branches don't report themselves to the event counters.
+        let counting_branches =
+            self.csrs.counts(HpmEvent::Branch) || self.csrs.counts(HpmEvent::BranchTaken);
+
+        if breakpoint
+            || counting_branches
+            || self.debug.watched_regs != 0
+            || self.triggers_armed()
+            || self.observed()
+            || self.pending_interrupt().is_some()
+        {
+            return self.step_block(budget, target);
+        }
+        let Some(block) = self.block_at(vpc) else {
+            return self.step_block(budget, target);
+        };
+
+        let len = block.instructions.len() as u64;
+        let end = vpc + 4 * len;
+        let stops_inside = target.is_some_and(|t| (t as u64) > vpc && (t as u64) < end);
+        if budget.is_some_and(|budget| budget < len) || stops_inside {
+            return self.step_block(budget, target);
+        }
+
+        if self.jit.is_none() {
+            match Jit::new() {
+                Ok(jit) => self.jit = Some(jit),
+                Err(_) => {
+                    self.engine = crate::Engine::BasicBlocks;
+                    return self.step_block(budget, target);
+                }
+            }
+        }
+
+        let generation = self.blocks.generation();
+        let (privilege, virt) = (self.privilege, self.virt);
+        let jit = self.jit.as_mut().expect("initialized above");
+        let Some(code) = jit.lookup::<X>(vpc, privilege, virt, &block, generation) else {
+            return self.step_block(budget, target);
+        };
+        jit.stopped = false;
+
+        // Looping back to `target` has to stop there.
+        let budget = if target.is_some_and(|t| t as u64 == vpc) {
+            len
+        } else {
+            budget.unwrap_or(u64::MAX)
+        };
+
+        let cpu: *mut Self = self;
+        // SAFETY: the compiled code only touches the register file, the PC
+        // and, through `exec_helper`, the CPU itself, all derived from `cpu`.
+        let retired = unsafe {
+            code(
+                ptr::addr_of_mut!((*cpu).regs).cast(),
+                ptr::addr_of_mut!((*cpu).pc).cast(),
+                cpu.cast(),
+                budget,
+            )
+        };
+
+        if let Some(hit) = self.take_watch_hit() {
+            return (retired, Ok(hit));
+        }
+
+        // The rest of the block (a fault, a privileged instruction or
+        // self-modified code) goes through the interpreter one step at a
+        // time, so the run loop always makes progress.
+        let stopped = self.jit.as_ref().is_some_and(|jit| jit.stopped);
+        if (stopped || retired % len != 0) && retired < budget {
+            return (retired + 1, self.step_one());
+        }
+
+        (retired, Ok(StepOutcome::Executed))
+    }
+}
@@ -0,0 +1,109 @@
+//! A bounded record of what each step changed, so that the debugger can
+//! undo steps and run backwards.
+
+use std::collections::VecDeque;
+
+use crate::MemSize;
+use crate::cpu::Wait;
+use crate::csr::{CsrSlot, Privilege};
+use crate::xlen::Xlen;
+
+/// The state one step replaced. Each list is oldest first, so undoing
+/// goes through it backwards.
+pub(crate) struct Frame<X: Xlen> {
+    pub(crate) pc: X::Reg,
+    pub(crate) privilege: Privilege,
+    pub(crate) virt: bool,
+    pub(crate) waiting: Option<Wait>,
+    pub(crate) regs: Vec<(u8, X::Reg)>,
+    /// All the floating-point registers, if a float instruction ran.
+    pub(crate) fregs: Option<[u64; 32]>,
+    /// All the vector registers, if a vector instruction ran.
+    pub(crate) vregs: Option<Vec<u8>>,
+    pub(crate) csrs: Vec<(CsrSlot, u64)>,
+    /// RAM stores by physical address.
+    pub(crate) memory: Vec<(u32, MemSize, u32)>,
+}
+
+impl<X: Xlen> Frame<X> {
+    pub(crate) fn new(pc: X::Reg, privilege: Privilege, virt: bool, waiting: Option<Wait>) -> Self {
+        Self {
+            pc,
+            privilege,
+            virt,
+            waiting,
+            regs: Vec::new(),
+            fregs: None,
+            vregs: None,
+            csrs: Vec::new(),
+            memory: Vec::new(),
+        }
+    }
+
+    /// Fold in the changes of the step after this one, as if this step had
+    /// made them.
+    fn absorb(&mut self, next: Frame<X>) {
+        self.regs.extend(next.regs);
+        self.fregs = self.fregs.or(next.fregs);
+        self.vregs = self.vregs.take().or(next.vregs);
+        self.csrs.extend(next.csrs);
+        self.memory.extend(next.memory);
+    }
+}
+
+/// The last `capacity` steps, oldest first.
+pub(crate) struct Journal<X: Xlen> {
+    capacity: usize,
+    frames: VecDeque<Frame<X>>,
+}
+
+impl<X: Xlen> Journal<X> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.frames.len().min(self.capacity)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Start recording a step. Until it's known to be one, the oldest is
+    /// kept in case this one is discarded.
+    pub(crate) fn push(&mut self, frame: Frame<X>) {
+        if self.capacity > 0 {
+            self.frames.push_back(frame);
+        }
+    }
+
+    /// The step being recorded.
+    pub(crate) fn current(&mut self) -> Option<&mut Frame<X>> {
+        self.frames.back_mut()
+    }
+
+    /// Forget the oldest steps beyond the capacity.
+    pub(crate) fn trim(&mut self) {
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+        }
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Frame<X>> {
+        self.frames.pop_back()
+    }
+
+    /// Drop the step being recorded, which turned out not to be one, but
+    /// keep anything it changed with the step before.
+    pub(crate) fn discard(&mut self) {
+        if let Some(frame) = self.frames.pop_back()
+            && let Some(previous) = self.frames.back_mut()
+        {
+            previous.absorb(frame);
+        }
+    }
+}
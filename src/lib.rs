@@ -1,405 +1,80 @@
-pub struct RiscvCpu {
-    pub regs: [u32; 32],
-    pub pc: u32,
-    pub bus: Vec<u8>,
-}
-
-#[derive(Copy, Clone)]
-pub enum MemSize {
-    Byte,
-    Half,
-    Word,
-}
-
-impl RiscvCpu {
-    pub fn new(ram_size: usize) -> Self {
-        Self {
-            regs: [0; 32],
-            pc: 0,
-            bus: vec![0; ram_size],
-        }
-    }
-
-    pub fn step(&mut self) -> Result<(), String> {
-        let instruction = self.load(self.pc, MemSize::Word, false)?;
-
-        let mut next_pc = self.pc + 4;
-
-        self.execute(instruction, &mut next_pc)?;
-
-        self.pc = next_pc;
-
-        Ok(())
-    }
-
-    pub fn execute(&mut self, instruction: u32, mut next_pc: &mut u32) -> Result<(), String> {
-        let opcode = instruction & 0x7f;
-
-        match opcode {
-            0x33 => self.handle_rtype(instruction)?,
-            0x13 => self.handle_itype(instruction)?,
-            0x03 => self.handle_load(instruction)?,
-            0x23 => self.handle_store(instruction)?,
-            0x63 => self.handle_btype(instruction, &mut next_pc)?,
-            0x6F => self.handle_jal(instruction, &mut next_pc)?,
-            0x67 => self.handle_jalr(instruction, &mut next_pc)?,
-            0x37 => self.handle_lui(instruction)?,
-            0x17 => self.handle_auipc(instruction)?,
-            0x73 => {
-                if (instruction >> 20) == 0x1 {
-                    return Err(String::from("EBREAK: program halted normally"));
-                }
-            }
-            _ => println!("don't have this yet"),
-        }
-
-        Ok(())
-    }
-
-    pub fn load(&self, addr: u32, size: MemSize, signed: bool) -> Result<u32, String> {
-        let raw = self.read_raw(addr, size);
-
-        let byte_count = match size {
-            MemSize::Byte => 1,
-            MemSize::Half => 2,
-            MemSize::Word => 4,
-        };
-
-        if (addr as usize) + byte_count > self.bus.len() {
-            return Err(format!("Load Acces Fault: {:#x} is out of bounds", addr));
-        }
-
-        if !signed {
-            return Ok(raw);
-        }
-
-        match size {
-            MemSize::Byte => Ok((raw as i8 as i32) as u32),
-            MemSize::Half => Ok((raw as i16 as i32) as u32),
-            MemSize::Word => Ok(raw),
-        }
-    }
-
-    pub fn store(&mut self, addr: u32, size: MemSize, value: u32) -> Result<(), String> {
-        let a = addr as usize;
-
-        let byte_count = match size {
-            MemSize::Byte => 1,
-            MemSize::Half => 2,
-            MemSize::Word => 4,
-        };
-
-        if a + byte_count > self.bus.len() {
-            return Err(format!("Store Access Fault: {:#x} is out of bounds", addr));
-        }
-
-        match size {
-            MemSize::Byte => self.bus[a] = (value & 0xFF) as u8,
-            MemSize::Half => {
-                self.bus[a] = (value & 0xFF) as u8;
-                self.bus[a + 1] = ((value >> 8) & 0xFF) as u8;
-            }
-            MemSize::Word => {
-                self.bus[a] = (value & 0xFF) as u8;
-                self.bus[a + 1] = ((value >> 8) & 0xFF) as u8;
-                self.bus[a + 2] = ((value >> 16) & 0xFF) as u8;
-                self.bus[a + 3] = ((value >> 24) & 0xFF) as u8;
-            }
-        }
-
-        Ok(())
-    }
-
-    pub fn handle_rtype(&mut self, instruction: u32) -> Result<(), String> {
-        let rd = (instruction >> 7) & 0x1F;
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs1 = (instruction >> 15) & 0x1F;
-        let rs2 = (instruction >> 20) & 0x1F;
-        let funct7 = (instruction >> 25) & 0x7f;
-
-        let rs1_value = self.regs[rs1 as usize];
-        let rs2_value = self.regs[rs2 as usize];
-
-        let rd_value = match funct3 {
-            0x0 => match funct7 {
-                0x00 => rs1_value.wrapping_add(rs2_value),
-                0x20 => rs1_value.wrapping_sub(rs2_value),
-                _ => return Err(format!("Invalid funct7 {:#x} for funct3 0x0", funct7)),
-            },
-            0x4 => rs1_value ^ rs2_value,
-            0x6 => rs1_value | rs2_value,
-            0x7 => rs1_value & rs2_value,
-            0x1 => rs1_value << (rs2_value & 0x1F),
-            0x5 => match funct7 {
-                0x00 => rs1_value >> (rs2_value & 0x1F),
-                0x20 => ((rs1_value as i32) >> (rs2_value & 0x1F)) as u32,
-                _ => {
-                    return Err(format!(
-                        "Invalid funct7 {:#x} for funct3 {:#x}",
-                        funct3, funct7
-                    ));
-                }
-            },
-            0x2 => {
-                if (rs1_value as i32) < (rs2_value as i32) {
-                    1
-                } else {
-                    0
-                }
-            }
-            0x3 => {
-                if rs1_value < rs2_value {
-                    1
-                } else {
-                    0
-                }
-            }
-            _ => return Err(format!("Invalid funct3 {:#x}", funct3)),
-        };
-
-        if rd != 0 {
-            self.write_reg(rd, rd_value);
-        }
-
-        Ok(())
-    }
-
-    pub fn handle_itype(&mut self, instruction: u32) -> Result<(), String> {
-        let rd = (instruction >> 7) & 0x1F;
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs = (instruction >> 15) & 0x1F;
-        let imm = (instruction as i32) >> 20;
-        let rs_value = self.regs[rs as usize];
-
-        let rd_value = match funct3 {
-            0x0 => (rs_value as i32 + imm) as u32,
-            0x4 => rs_value ^ (imm as u32),
-            0x6 => rs_value | (imm as u32),
-            0x7 => rs_value & (imm as u32),
-            0x1 => rs_value << (imm & 0x1F),
-            0x5 => {
-                let funct7 = (instruction >> 25) & 0x7F;
-                let shamt = imm & 0x1F;
-
-                match funct7 {
-                    0x00 => {
-                        println!(
-                            "rd: {:#010x} = rs: {:#010x} >> imm[0:4]: {} ",
-                            rs_value >> shamt,
-                            rs_value,
-                            shamt
-                        );
-                        rs_value >> shamt
-                    }
-                    0x20 => {
-                        println!(
-                            "rd: {:#010x} = rs: {:#010x} >> imm[0:4]: {} ",
-                            (rs_value as i32 >> shamt) as u32,
-                            rs_value,
-                            shamt
-                        );
-                        (rs_value as i32 >> shamt) as u32
-                    }
-                    _ => {
-                        return Err(format!("Invalid funct7 {:#x} for funct3 0x5", funct7));
-                    }
-                }
-            }
-            0x2 => {
-                if (rs_value as i32) < imm {
-                    1_u32
-                } else {
-                    0_u32
-                }
-            }
-            0x3 => {
-                if rs_value < (imm as u32) {
-                    1_u32
-                } else {
-                    0_u32
-                }
-            }
-            _ => {
-                return Err(format!("Unknown i-type funct3: {:#x}", funct3));
-            }
-        };
-
-        if rd != 0 {
-            self.write_reg(rd, rd_value);
-        }
-
-        Ok(())
-    }
-
-    pub fn handle_load(&mut self, instruction: u32) -> Result<(), String> {
-        let rd = (instruction >> 7) & 0x1F;
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs = (instruction >> 15) & 0x1F;
-        let imm = (instruction as i32) >> 20;
-        let rs_value = self.regs[rs as usize] as i32;
-        let addr = rs_value.wrapping_add(imm) as u32;
-
-        let rd_value = match funct3 {
-            0x0 => self.load(addr, MemSize::Byte, true)?,
-            0x1 => self.load(addr, MemSize::Half, true)?,
-            0x2 => self.load(addr, MemSize::Word, true)?,
-            0x4 => self.load(addr, MemSize::Byte, false)?,
-            0x5 => self.load(addr, MemSize::Half, false)?,
-            _ => return Err(format!("Unknown load funct3: {:#x}", funct3)),
-        };
-
-        self.write_reg(rd, rd_value);
-
-        Ok(())
-    }
-
-    pub fn handle_store(&mut self, instruction: u32) -> Result<(), String> {
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs1 = (instruction >> 15) & 0x1F;
-        let rs2 = (instruction >> 20) & 0x1F;
-
-        let i4_0 = (instruction >> 7) & 0x1F;
-        let i11_5 = (instruction >> 25) & 0x7F;
-
-        let imm_u: u32 = (i11_5 << 5) | i4_0;
-        let imm = ((imm_u << 20) as i32) >> 20;
-
-        let rs1_value = self.regs[rs1 as usize];
-        let rs2_value = self.regs[rs2 as usize];
-
-        let addr = (rs1_value as i32).wrapping_add(imm) as u32;
-
-        match funct3 {
-            0x0 => self.store(addr, MemSize::Byte, rs2_value)?,
-            0x1 => self.store(addr, MemSize::Half, rs2_value)?,
-            0x2 => self.store(addr, MemSize::Word, rs2_value)?,
-            _ => return Err(format!("Unknown S-type funct3: {:#x}", funct3)),
-        }
-
-        Ok(())
-    }
-
-    pub fn handle_btype(&mut self, instruction: u32, next_pc: &mut u32) -> Result<(), String> {
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs1 = (instruction >> 15) & 0x1F;
-        let rs2 = (instruction >> 20) & 0x1F;
-
-        let i12 = (instruction >> 31) & 0x1;
-        let i11 = (instruction >> 7) & 0x1;
-        let i10_5 = (instruction >> 25) & 0x3F;
-        let i4_1 = (instruction >> 8) & 0xF;
-
-        let imm_u32 = (i12 << 12) | (i11 << 11) | (i10_5 << 5) | (i4_1 << 1);
-        let imm = ((imm_u32 << 19) as i32) >> 19;
-
-        let rs1_value = self.regs[rs1 as usize];
-        let rs2_value = self.regs[rs2 as usize];
-
-        let should_branch = match funct3 {
-            0x0 => rs1_value == rs2_value,
-            0x1 => rs1_value != rs2_value,
-            0x4 => (rs1_value as i32) < (rs2_value as i32),
-            0x5 => (rs1_value as i32) >= (rs2_value as i32),
-            0x6 => rs1_value < rs2_value,
-            0x7 => rs1_value >= rs2_value,
-            _ => return Err(format!("Unknown B-type funct3: {:#x}", funct3)),
-        };
-
-        if should_branch {
-            *next_pc = (self.pc as i32).wrapping_add(imm) as u32;
-        }
-
-        Ok(())
-    }
-
-    pub fn handle_jal(&mut self, instruction: u32, next_pc: &mut u32) -> Result<(), String> {
-        let rd = (instruction >> 7) & 0x1F;
-
-        let i19_12 = (instruction >> 12) & 0xFF;
-        let i11 = (instruction >> 20) & 0x1;
-        let i10_1 = (instruction >> 21) & 0x3FF;
-        let i20 = (instruction >> 31) & 0x1;
-
-        let imm_u32 = (i20 << 20) | (i19_12 << 12) | (i11 << 11) | (i10_1 << 1);
-        let imm = ((imm_u32 << 11) as i32) >> 11;
-
-        let rd_value = self.pc + 4;
-        self.write_reg(rd, rd_value);
-        *next_pc = (self.pc as i32).wrapping_add(imm) as u32;
-
-        Ok(())
-    }
-
-    pub fn handle_jalr(&mut self, instruction: u32, next_pc: &mut u32) -> Result<(), String> {
-        let rd = (instruction >> 7) & 0x1F;
-        let funct3 = (instruction >> 12) & 0x7;
-        let rs = (instruction >> 15) & 0x1F;
-        let imm = (instruction as i32) >> 20;
-        let rs_value = self.regs[rs as usize];
-
-        let rd_value = self.pc + 4;
-
-        match funct3 {
-            0x0 => *next_pc = (rs_value as i32).wrapping_add(imm) as u32,
-            _ => return Err(format!("Unknown JALR funct3 {:#x}", funct3)),
-        }
-
-        self.write_reg(rd, rd_value);
-
-        Ok(())
-    }
-
-    pub fn handle_lui(&mut self, instruction: u32) -> Result<(), String> {
-        let rd = (instruction >> 7) & 0x1F;
-        let imm = (instruction >> 12) & 0xFFFFF;
-
-        let rd_value = imm << 12;
-        self.write_reg(rd, rd_value);
-
-        Ok(())
-    }
-
-    pub fn handle_auipc(&mut self, instruction: u32) -> Result<(), String> {
-        let rd = (instruction >> 7) & 0x1F;
-        let imm = (instruction >> 12) & 0xFFFFF;
-
-        let offset = (imm << 12) as i32;
-
-        let rd_value = (self.pc as i32).wrapping_add(offset) as u32;
-        self.write_reg(rd, rd_value);
-
-        Ok(())
-    }
-
-    pub fn dump_registers(&self) {
-        println!("\n--- Register Dump ---");
-        for i in 0..32 {
-            print!("x{:02}: {:#010x}  ", i, self.regs[i]);
-            if (i + 1) % 4 == 0 {
-                println!();
-            } // Print 4 per line
-        }
-        println!("PC : {:#010x}", self.pc);
-        println!("---------------------\n");
-    }
-
-    fn write_reg(&mut self, reg: u32, value: u32) {
-        if reg != 0 {
-            self.regs[reg as usize] = value;
-        }
-    }
-
-    fn read_raw(&self, addr: u32, size: MemSize) -> u32 {
-        let a = addr as usize;
-        match size {
-            MemSize::Byte => self.bus[a] as u32,
-            MemSize::Half => (self.bus[a] as u32) | ((self.bus[a + 1] as u32) << 8),
-            MemSize::Word => {
-                (self.bus[a] as u32)
-                    | ((self.bus[a + 1] as u32) << 8)
-                    | ((self.bus[a + 2] as u32) << 16)
-                    | ((self.bus[a + 3] as u32) << 24)
-            }
-        }
-    }
-}
+//! An RV32/RV64 RISC-V emulator.
+//!
+//! [`RiscvCpu`] is a hart: its registers, CSRs and bus, built with
+//! [`RiscvCpu::builder`]. [`Machine`] runs several on one bus. The hart
+//! itself lives in `cpu`, split there by concern. The public modules
+//! are what it's built from and what's around it: [`decode`] and [`asm`],
+//! [`csr`], [`bus`] and [`devices`], [`mmu`], [`loader`], [`debug`], and
+//! the analysis models ([`cache`], [`tlb`], [`timing`] and friends).
+
+pub mod asm;
+pub mod backtrace;
+pub mod block;
+pub mod builder;
+pub mod bus;
+pub mod cache;
+pub mod capture;
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod cosim;
+pub mod costs;
+pub mod coverage;
+mod cpu;
+pub mod crypto;
+pub mod csr;
+pub mod custom;
+pub mod debug;
+pub mod decode;
+pub mod devices;
+#[cfg(feature = "dwarf")]
+pub mod dwarf;
+pub mod encode;
+pub mod fdt;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod float;
+pub mod fuzz;
+pub mod hooks;
+mod icache;
+pub mod isa;
+#[cfg(feature = "jit")]
+mod jit;
+mod journal;
+pub mod loader;
+pub mod machine;
+pub mod mmu;
+#[cfg(feature = "std")]
+pub mod perf;
+pub mod predictor;
+pub mod profile;
+pub mod program;
+pub mod reg;
+#[cfg(feature = "std")]
+pub mod remote;
+pub mod riscv_tests;
+pub mod semihosting;
+pub mod signature;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
+pub mod syscall;
+pub mod timing;
+pub mod tlb;
+pub mod torture;
+pub mod trace;
+pub mod trap;
+mod trigger;
+pub mod user;
+pub mod vcd;
+pub mod vector;
+pub mod virt;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xlen;
+
+pub use block::Engine;
+pub use builder::RiscvCpuBuilder;
+pub use cpu::{ExitReason, MemSize, RiscvCpu, StepOutcome};
+pub use machine::Machine;
+pub use reg::Reg;
+pub use trap::{Exception, Interrupt};
@@ -0,0 +1,454 @@
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const EM_RISCV: u16 = 243;
+
+const EHDR_SIZE: usize = 52;
+const PHDR_SIZE: usize = 32;
+const SHDR_SIZE: usize = 40;
+const SYM_SIZE: usize = 16;
+
+const SHT_SYMTAB: u32 = 2;
+const SHN_UNDEF: u16 = 0;
+
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_PHDR: u32 = 6;
+
+/// A loadable segment described by an ELF program header.
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub kind: u32,
+    pub offset: u32,
+    pub vaddr: u32,
+    pub paddr: u32,
+    pub filesz: u32,
+    pub memsz: u32,
+    pub flags: u32,
+}
+
+/// The parts of an ELF32 little-endian RISC-V image needed to run it.
+pub struct ElfFile<'a> {
+    data: &'a [u8],
+    pub entry: u32,
+    pub segments: Vec<Segment>,
+    /// Where the program headers are in the file, and the size of each.
+    pub phoff: u32,
+    pub phentsize: u16,
+}
+
+impl<'a> ElfFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, String> {
+        if data.len() < EHDR_SIZE || data[0..4] != ELF_MAGIC {
+            return Err(String::from("ELF: bad magic number"));
+        }
+        if data[4] != ELFCLASS32 {
+            return Err(String::from("ELF: only 32-bit images are supported"));
+        }
+        if data[5] != ELFDATA2LSB {
+            return Err(String::from("ELF: only little-endian images are supported"));
+        }
+
+        let machine = read_u16(data, 18)?;
+        if machine != EM_RISCV {
+            return Err(format!("ELF: machine {} is not RISC-V", machine));
+        }
+
+        let entry = read_u32(data, 24)?;
+        let phoff = read_u32(data, 28)? as usize;
+        let phentsize = read_u16(data, 42)? as usize;
+        let phnum = read_u16(data, 44)? as usize;
+
+        if phnum > 0 && phentsize < PHDR_SIZE {
+            return Err(format!(
+                "ELF: program header size {} is too small",
+                phentsize
+            ));
+        }
+
+        let mut segments = Vec::with_capacity(phnum);
+        for i in 0..phnum {
+            let ph = phoff + i * phentsize;
+            segments.push(Segment {
+                kind: read_u32(data, ph)?,
+                offset: read_u32(data, ph + 4)?,
+                vaddr: read_u32(data, ph + 8)?,
+                paddr: read_u32(data, ph + 12)?,
+                filesz: read_u32(data, ph + 16)?,
+                memsz: read_u32(data, ph + 20)?,
+                flags: read_u32(data, ph + 24)?,
+            });
+        }
+
+        Ok(Self {
+            data,
+            entry,
+            segments,
+            phoff: phoff as u32,
+            phentsize: phentsize as u16,
+        })
+    }
+
+    pub fn loadable_segments(&self) -> impl Iterator<Item = &Segment> {
+        self.segments.iter().filter(|s| s.kind == PT_LOAD)
+    }
+
+    /// Where the program headers end up in memory, from a `PT_PHDR` entry
+    /// or else the loadable segment they're part of. `None` if they aren't
+    /// loaded.
+    pub fn program_headers_addr(&self) -> Option<u32> {
+        if let Some(phdr) = self.segments.iter().find(|s| s.kind == PT_PHDR) {
+            return Some(phdr.vaddr);
+        }
+        self.loadable_segments()
+            .find(|s| (s.offset..s.offset.saturating_add(s.filesz)).contains(&self.phoff))
+            .map(|s| s.vaddr + (self.phoff - s.offset))
+    }
+
+    /// The bytes stored in the file for a segment (its first `filesz` bytes).
+    pub fn segment_data(&self, segment: &Segment) -> Result<&'a [u8], String> {
+        let start = segment.offset as usize;
+        let end = start + segment.filesz as usize;
+
+        self.data
+            .get(start..end)
+            .ok_or_else(|| format!("ELF: segment at {:#x} runs past end of file", segment.vaddr))
+    }
+
+    /// The contents of the section called `name`, e.g. `.debug_line`.
+    pub fn section(&self, name: &str) -> Option<&'a [u8]> {
+        let data = self.data;
+        let shoff = read_u32(data, 32).ok()? as usize;
+        let shentsize = read_u16(data, 46).ok()? as usize;
+        let shnum = read_u16(data, 48).ok()? as usize;
+        let shstrndx = read_u16(data, 50).ok()? as usize;
+
+        if shentsize < SHDR_SIZE {
+            return None;
+        }
+
+        let names = read_u32(data, shoff + shstrndx * shentsize + 16).ok()? as usize;
+        (0..shnum).find_map(|i| {
+            let sh = shoff + i * shentsize;
+            let name_off = read_u32(data, sh).ok()? as usize;
+            if read_cstr(data, names + name_off)? != name.as_bytes() {
+                return None;
+            }
+            let offset = read_u32(data, sh + 16).ok()? as usize;
+            let size = read_u32(data, sh + 20).ok()? as usize;
+            data.get(offset..offset.checked_add(size)?)
+        })
+    }
+
+    /// Look up a symbol's value in the static symbol table (`.symtab`).
+    /// Returns `None` for stripped or malformed images.
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symtab_entries()?
+            .into_iter()
+            .find(|entry| entry.name == name.as_bytes())
+            .map(|entry| entry.value)
+    }
+
+    /// The functions and data objects in `.symtab`, for turning addresses
+    /// back into names. Empty for stripped or malformed images.
+    pub fn symbols(&self) -> SymbolTable {
+        let entries = self.symtab_entries().unwrap_or_default();
+        SymbolTable::new(
+            entries
+                .into_iter()
+                .filter(|entry| {
+                    matches!(entry.info & 0xF, STT_NOTYPE | STT_OBJECT | STT_FUNC)
+                        && entry.shndx != SHN_UNDEF
+                        && !entry.name.is_empty()
+                        // Mapping symbols ($x, $d) and assembler-local labels.
+                        && !entry.name.starts_with(b"$")
+                        && !entry.name.starts_with(b".L")
+                })
+                .map(|entry| Symbol {
+                    name: String::from_utf8_lossy(entry.name).into_owned(),
+                    addr: entry.value,
+                    size: entry.size,
+                    function: entry.info & 0xF == STT_FUNC,
+                })
+                .collect(),
+        )
+    }
+
+    fn symtab_entries(&self) -> Option<Vec<SymtabEntry<'a>>> {
+        let data = self.data;
+        let shoff = read_u32(data, 32).ok()? as usize;
+        let shentsize = read_u16(data, 46).ok()? as usize;
+        let shnum = read_u16(data, 48).ok()? as usize;
+
+        if shentsize < SHDR_SIZE {
+            return None;
+        }
+
+        let mut entries = Vec::new();
+        for i in 0..shnum {
+            let sh = shoff + i * shentsize;
+            if read_u32(data, sh + 4).ok()? != SHT_SYMTAB {
+                continue;
+            }
+
+            let offset = read_u32(data, sh + 16).ok()? as usize;
+            let size = read_u32(data, sh + 20).ok()? as usize;
+            let link = read_u32(data, sh + 24).ok()? as usize;
+            let strtab = read_u32(data, shoff + link * shentsize + 16).ok()? as usize;
+
+            for sym in (offset..offset + size).step_by(SYM_SIZE) {
+                let name_off = read_u32(data, sym).ok()? as usize;
+                entries.push(SymtabEntry {
+                    name: read_cstr(data, strtab + name_off)?,
+                    value: read_u32(data, sym + 4).ok()?,
+                    size: read_u32(data, sym + 8).ok()?,
+                    info: *data.get(sym + 12)?,
+                    shndx: read_u16(data, sym + 14).ok()?,
+                });
+            }
+        }
+
+        Some(entries)
+    }
+}
+
+struct SymtabEntry<'a> {
+    name: &'a [u8],
+    value: u32,
+    size: u32,
+    info: u8,
+    shndx: u16,
+}
+
+/// A named function or data object from an image's symbol table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub addr: u32,
+    /// Zero for labels in assembly, which don't record one.
+    pub size: u32,
+    pub function: bool,
+}
+
+/// Symbols sorted by address, for looking up which one covers a PC.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new(mut symbols: Vec<Symbol>) -> Self {
+        // Where several symbols share an address, functions come last so
+        // lookups find them first.
+        symbols.sort_by_key(|s| (s.addr, s.function));
+        Self { symbols }
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// The symbol covering `addr` and how far into it `addr` is. A symbol
+    /// without a size covers everything up to the next one.
+    pub fn symbol_at(&self, addr: u32) -> Option<(&Symbol, u32)> {
+        let index = self.symbols.partition_point(|s| s.addr <= addr);
+        let symbol = self.symbols[..index].last()?;
+        let offset = addr - symbol.addr;
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+        Some((symbol, offset))
+    }
+
+    /// `name` or `name+0x10`, as in a backtrace.
+    pub fn describe(&self, addr: u32) -> Option<String> {
+        self.symbol_at(addr).map(|(symbol, offset)| match offset {
+            0 => symbol.name.clone(),
+            offset => format!("{}+{:#x}", symbol.name, offset),
+        })
+    }
+}
+
+fn read_cstr(data: &[u8], offset: usize) -> Option<&[u8]> {
+    let bytes = data.get(offset..)?;
+    let len = bytes.iter().position(|&b| b == 0)?;
+    Some(&bytes[..len])
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, String> {
+    data.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| format!("ELF: truncated at offset {:#x}", offset))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| format!("ELF: truncated at offset {:#x}", offset))
+}
+
+/// The contents of an Intel HEX file: the bytes it stores and, if it has a
+/// start address record, where execution begins.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntelHex {
+    /// Runs of consecutive bytes, each with the address of its first byte.
+    pub chunks: Vec<(u32, Vec<u8>)>,
+    pub entry: Option<u32>,
+}
+
+impl IntelHex {
+    /// Parse data (00), end of file (01), extended segment address (02),
+    /// start segment address (03), extended linear address (04) and start
+    /// linear address (05) records. Anything after the end of file record
+    /// is ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut hex = Self::default();
+        let mut base = 0u32;
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| format!("Intel HEX: line {}: {}", index + 1, message);
+
+            let digits = line
+                .strip_prefix(':')
+                .ok_or_else(|| error("record doesn't start with ':'"))?;
+            if digits.len() % 2 != 0 {
+                return Err(error("odd number of hex digits"));
+            }
+            let bytes = (0..digits.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&digits[i..i + 2], 16))
+                .collect::<Result<Vec<u8>, _>>()
+                .map_err(|_| error("not a hex digit"))?;
+            if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+                return Err(error("record length doesn't match its byte count"));
+            }
+            if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+                return Err(error("bad checksum"));
+            }
+
+            let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+            let data = &bytes[4..bytes.len() - 1];
+            let word = |len: usize| {
+                (data.len() == len)
+                    .then(|| data.iter().fold(0u32, |value, &b| value << 8 | b as u32))
+                    .ok_or_else(|| error(&format!("expected {} bytes of data", len)))
+            };
+
+            match bytes[3] {
+                0x00 => hex.push(base.wrapping_add(offset), data),
+                0x01 => break,
+                0x02 => base = word(2)? << 4,
+                0x03 => {
+                    let address = word(4)?;
+                    hex.entry = Some((address >> 16 << 4) + (address & 0xFFFF));
+                }
+                0x04 => base = word(2)? << 16,
+                0x05 => hex.entry = Some(word(4)?),
+                kind => return Err(error(&format!("unknown record type {:02x}", kind))),
+            }
+        }
+
+        Ok(hex)
+    }
+
+    fn push(&mut self, addr: u32, data: &[u8]) {
+        if let Some((start, bytes)) = self.chunks.last_mut()
+            && start.wrapping_add(bytes.len() as u32) == addr
+        {
+            bytes.extend_from_slice(data);
+            return;
+        }
+        self.chunks.push((addr, data.to_vec()));
+    }
+}
+
+/// A program as runs of bytes to copy into memory, for raw binaries and
+/// anything else without headers saying where it goes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub segments: Vec<ImageSegment>,
+    pub entry: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageSegment {
+    pub addr: u32,
+    pub bytes: Vec<u8>,
+    /// Protect the segment from guest writes once it's loaded.
+    pub read_only: bool,
+}
+
+impl Image {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn segment(mut self, addr: u32, bytes: impl Into<Vec<u8>>) -> Self {
+        self.segments.push(ImageSegment {
+            addr,
+            bytes: bytes.into(),
+            read_only: false,
+        });
+        self
+    }
+
+    /// Like [`segment`](Self::segment), but the guest can't write over it.
+    pub fn rom(mut self, addr: u32, bytes: impl Into<Vec<u8>>) -> Self {
+        self = self.segment(addr, bytes);
+        if let Some(segment) = self.segments.last_mut() {
+            segment.read_only = true;
+        }
+        self
+    }
+
+    /// Where to jump once the image is loaded. Without one the PC is left
+    /// alone.
+    pub fn entry(mut self, pc: u32) -> Self {
+        self.entry = Some(pc);
+        self
+    }
+
+    /// The first pair of non-empty segments that share an address.
+    pub fn overlap(&self) -> Option<(&ImageSegment, &ImageSegment)> {
+        let mut segments: Vec<_> = self
+            .segments
+            .iter()
+            .filter(|s| !s.bytes.is_empty())
+            .collect();
+        segments.sort_by_key(|s| s.addr);
+        segments
+            .windows(2)
+            .find(|pair| pair[0].addr as u64 + pair[0].bytes.len() as u64 > pair[1].addr as u64)
+            .map(|pair| (pair[0], pair[1]))
+    }
+}
+
+impl From<IntelHex> for Image {
+    fn from(hex: IntelHex) -> Self {
+        let mut image = Self::new();
+        for (addr, bytes) in hex.chunks {
+            image = image.segment(addr, bytes);
+        }
+        image.entry = hex.entry;
+        image
+    }
+}
@@ -0,0 +1,242 @@
+//! Several harts sharing one bus.
+
+use std::mem;
+
+use crate::bus::Bus;
+use crate::capture::OutputCapture;
+use crate::coverage::Coverage;
+use crate::csr;
+use crate::profile::MemoryProfile;
+use crate::stats::Stats;
+use crate::trap::Exception;
+use crate::xlen::{Rv32, Xlen};
+use crate::{ExitReason, Reg, RiscvCpu, StepOutcome};
+
+/// Instructions a hart runs before the scheduler moves on, by default.
+const DEFAULT_QUANTUM: u64 = 64;
+
+/// N harts with their own registers, PC, CSRs and caches, on one shared
+/// bus. Hart `i` reads `i` from `mhartid`.
+///
+/// Harts take turns in round-robin order, each running up to a quantum of
+/// instructions before the next one goes. A quantum of 1 interleaves them
+/// instruction by instruction. Harts waiting in WFI or WRS are passed over,
+/// and device time only skips ahead once all of them are waiting. Time
+/// advances one tick for every instruction any hart executes.
+pub struct Machine<X: Xlen = Rv32> {
+    pub bus: Bus,
+    harts: Vec<RiscvCpu<X>>,
+    quantum: u64,
+    /// The hart whose turn is next.
+    next: usize,
+}
+
+impl<X: Xlen> Machine<X> {
+    /// Turn `hart0` into a machine of `harts` harts. Its bus becomes the
+    /// shared one; the others start at its PC with its stack pointer, engine,
+    /// floating-point registers, extensions, VLEN, guest trap, call tracking,
+    /// coverage, memory profile, stats and cycle cost settings, empty
+    /// caches, TLB and pipeline of the same shape, symbols and line table,
+    /// but no tracer, step hooks, semihosting, custom or unknown opcode
+    /// handlers, or branch predictor beyond the default.
+    pub fn new(mut hart0: RiscvCpu<X>, harts: usize) -> Self {
+        assert!(harts > 0, "a machine needs at least one hart");
+
+        let bus = mem::replace(&mut hart0.bus, Bus::new(0, 0));
+        let mut all = vec![hart0];
+
+        for id in 1..harts {
+            let first = &all[0];
+            let mut hart = RiscvCpu::with_bus(Bus::new(0, 0));
+            hart.pc = first.pc;
+            hart.set_reg(Reg::Sp, first.reg(Reg::Sp));
+            hart.set_engine(first.engine());
+            hart.set_float_regs(first.float_regs());
+            hart.set_extensions(first.extensions())
+                .expect("hart 0 already has these extensions");
+            hart.set_vlen(first.vlen());
+            hart.set_guest_traps(first.guest_traps);
+            hart.set_call_tracking(first.call_tracking());
+            if first.coverage().is_some() {
+                hart.enable_coverage();
+            }
+            if first.memory_profile().is_some() {
+                hart.enable_memory_profile();
+            }
+            if let Some(caches) = first.cache_model() {
+                hart.set_cache_model(caches.cold());
+            }
+            if let Some(tlb) = first.tlb() {
+                hart.enable_tlb(tlb.config());
+            }
+            if let Some(pipeline) = first.pipeline() {
+                hart.enable_pipeline(pipeline.config());
+            }
+            if let Some(costs) = first.cycle_costs() {
+                hart.set_cycle_costs(costs.clone());
+            }
+            hart.set_stats_enabled(first.stats_enabled());
+            hart.set_symbols(first.symbols().clone());
+            #[cfg(feature = "dwarf")]
+            hart.set_line_table(first.line_table().clone());
+            hart.csrs.set(csr::MHARTID, X::truncate(id as u64));
+            all.push(hart);
+        }
+        for hart in &mut all {
+            hart.fast_forward = harts == 1;
+        }
+
+        Self {
+            bus,
+            harts: all,
+            quantum: DEFAULT_QUANTUM,
+            next: 0,
+        }
+    }
+
+    pub fn hart_count(&self) -> usize {
+        self.harts.len()
+    }
+
+    /// Hart `id`'s registers and state. Its own `bus` field is an empty
+    /// placeholder while the machine isn't running it; use [`Machine::bus`].
+    pub fn hart(&self, id: usize) -> &RiscvCpu<X> {
+        &self.harts[id]
+    }
+
+    /// Mutable access to hart `id`, e.g. to raise an interrupt on it. The
+    /// same caveat about `bus` as [`hart`](Self::hart) applies.
+    pub fn hart_mut(&mut self, id: usize) -> &mut RiscvCpu<X> {
+        &mut self.harts[id]
+    }
+
+    /// The coverage of every hart that's recording it, combined.
+    pub fn coverage(&self) -> Option<Coverage> {
+        self.harts
+            .iter()
+            .filter_map(|hart| hart.coverage())
+            .fold(None, |total, coverage| {
+                let mut total = total.unwrap_or_default();
+                total.merge(coverage);
+                Some(total)
+            })
+    }
+
+    /// The memory profile of every hart that's recording one, combined.
+    /// Compare the harts' own profiles to see which of them share a line.
+    pub fn memory_profile(&self) -> Option<MemoryProfile> {
+        self.harts
+            .iter()
+            .filter_map(|hart| hart.memory_profile())
+            .fold(None, |total, profile| {
+                let mut total = total.unwrap_or_default();
+                total.merge(profile);
+                Some(total)
+            })
+    }
+
+    /// Every hart's stats added together.
+    pub fn stats(&self) -> Stats {
+        let mut total = Stats::default();
+        for hart in &self.harts {
+            total.merge(hart.stats());
+        }
+        total
+    }
+
+    /// Collect what the guest prints from now on, through the UARTs on the
+    /// shared bus or any hart's semihosting, in one buffer.
+    pub fn capture_output(&mut self) -> OutputCapture {
+        let capture = OutputCapture::new();
+        for hart in &mut self.harts {
+            if let Some(semihosting) = &mut hart.semihosting {
+                semihosting.set_output(Box::new(capture.clone()));
+            }
+        }
+        self.bus.capture_output(&capture);
+        capture
+    }
+
+    pub fn quantum(&self) -> u64 {
+        self.quantum
+    }
+
+    pub fn set_quantum(&mut self, instructions: u64) {
+        assert!(
+            instructions > 0,
+            "the quantum must be at least one instruction"
+        );
+        self.quantum = instructions;
+    }
+
+    /// Execute one instruction on hart `id`. Doesn't affect whose turn it is.
+    pub fn step_hart(&mut self, id: usize) -> Result<StepOutcome, Exception> {
+        self.with_hart(id, |hart| hart.step())
+    }
+
+    /// Run until some hart stops for a breakpoint, watchpoint, exception or
+    /// exit, or every hart is idle in WFI with nothing scheduled to wake
+    /// them. Returns which hart and why.
+    pub fn run(&mut self) -> (usize, ExitReason) {
+        self.run_with(None)
+    }
+
+    /// Like [`run`](Self::run), but gives up after `steps` steps across all
+    /// harts, returning the hart that would have run next.
+    pub fn run_steps(&mut self, steps: u64) -> (usize, ExitReason) {
+        self.run_with(Some(steps))
+    }
+
+    fn run_with(&mut self, limit: Option<u64>) -> (usize, ExitReason) {
+        let mut steps = 0;
+        // Harts in a row that had nothing to do.
+        let mut idle = 0;
+
+        loop {
+            let id = self.next;
+            let slice = match limit {
+                Some(limit) if steps >= limit => return (id, ExitReason::StepLimit),
+                Some(limit) => self.quantum.min(limit - steps),
+                None => self.quantum,
+            };
+
+            match self.with_hart(id, |hart| hart.run_steps(slice)) {
+                ExitReason::StepLimit => {
+                    steps += slice;
+                    idle = 0;
+                }
+                ExitReason::Idle => idle += 1,
+                reason => return (id, reason),
+            }
+
+            if idle == self.harts.len() {
+                match self.next_wakeup() {
+                    Some(ticks) => self.bus.skip(ticks),
+                    None => return (id, ExitReason::Idle),
+                }
+                idle = 0;
+            }
+            self.next = (id + 1) % self.harts.len();
+        }
+    }
+
+    /// Ticks until the next device event or WRS.STO timeout on any hart.
+    fn next_wakeup(&self) -> Option<u64> {
+        let now = self.bus.time();
+        self.harts
+            .iter()
+            .filter_map(|hart| hart.wait_deadline())
+            .map(|deadline| deadline.saturating_sub(now))
+            .chain(self.bus.next_event())
+            .min()
+    }
+
+    /// Lend the shared bus to hart `id` for the duration of `f`.
+    fn with_hart<T>(&mut self, id: usize, f: impl FnOnce(&mut RiscvCpu<X>) -> T) -> T {
+        let hart = &mut self.harts[id];
+        mem::swap(&mut self.bus, &mut hart.bus);
+        let result = f(hart);
+        mem::swap(&mut self.bus, &mut hart.bus);
+        result
+    }
+}
@@ -1,39 +1,387 @@
-use riscv_emulator_rust::RiscvCpu;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use riscv_emulator_rust::asm;
+use riscv_emulator_rust::clock::{Clock, TimeMode};
+use riscv_emulator_rust::cosim::{self, Reference};
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::loader::ElfFile;
+use riscv_emulator_rust::semihosting::Semihosting;
+use riscv_emulator_rust::trace::{JsonTracer, PrintTracer};
+use riscv_emulator_rust::vcd::Vcd;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu};
 use std::fs;
+use std::io;
+use std::path::PathBuf;
 use std::process;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "riscv-emu", version, about = "An RV32 RISC-V emulator")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Load a program and run it until it exits, faults or stops.
+    Run(RunArgs),
+    /// Print the instructions in a program.
+    Disasm(DisasmArgs),
+    /// Run an ELF here and in Spike or Sail, and report the first
+    /// instruction they disagree on.
+    Cosim(CosimArgs),
+}
+
+#[derive(Args)]
+struct RunArgs {
+    /// An ELF, Intel HEX or raw binary image.
+    image: PathBuf,
+    /// RAM size, in bytes or with a K, M or G suffix.
+    #[arg(long, value_parser = parse_size)]
+    memory: Option<usize>,
+    /// Where RAM starts, and where a raw binary is loaded.
+    #[arg(long, default_value = "0", value_parser = parse_addr)]
+    base: u32,
+    /// Start here instead of at the image's entry point.
+    #[arg(long, value_parser = parse_addr)]
+    entry: Option<u32>,
+    /// Print each instruction as it executes.
+    #[arg(long)]
+    trace: bool,
+    /// Write a JSON record per instruction and trap to this file, one per
+    /// line.
+    #[arg(long, conflicts_with = "trace")]
+    trace_json: Option<PathBuf>,
+    /// Write the PC and registers to this VCD file after each step.
+    #[arg(long)]
+    vcd: Option<PathBuf>,
+    /// Add a CSR, by name or number, to the VCD file.
+    #[arg(long, requires = "vcd", value_parser = parse_csr)]
+    vcd_csr: Vec<(String, u16)>,
+    /// Add the word of RAM at this address to the VCD file.
+    #[arg(long, requires = "vcd", value_parser = parse_addr)]
+    vcd_mem: Vec<u32>,
+    /// Time the VCD file by mcycle instead of by steps.
+    #[arg(long, requires = "vcd")]
+    vcd_cycles: bool,
+    /// Give up after this many instructions.
+    #[arg(long)]
+    max_steps: Option<u64>,
+    /// Give up after this many seconds.
+    #[arg(long, conflicts_with = "vcd", value_parser = parse_seconds)]
+    timeout: Option<Duration>,
+    #[arg(long, value_enum, default_value_t = EngineArg::Interpreter)]
+    engine: EngineArg,
+    /// Where guest time comes from: one tick per instruction, which is
+    /// repeatable, or the host's clock.
+    #[arg(long, value_enum, default_value_t = TimeArg::Virtual)]
+    time: TimeArg,
+    /// Print the registers when the program stops.
+    #[arg(long)]
+    regs: bool,
+}
+
+#[derive(Args)]
+struct DisasmArgs {
+    /// An ELF or raw binary image.
+    image: PathBuf,
+    /// The address a raw binary starts at.
+    #[arg(long, default_value = "0", value_parser = parse_addr)]
+    base: u32,
+}
+
+#[derive(Args)]
+struct CosimArgs {
+    elf: PathBuf,
+    #[arg(long, value_enum, default_value_t = ReferenceArg::Spike)]
+    reference: ReferenceArg,
+    /// The reference's executable, if it isn't `spike` or `riscv_sim_RV32`
+    /// on the PATH.
+    #[arg(long)]
+    program: Option<String>,
+    /// RAM size, in bytes or with a K, M or G suffix.
+    #[arg(long, value_parser = parse_size)]
+    memory: Option<usize>,
+    /// Where RAM starts. Spike's starts at 0x80000000.
+    #[arg(long, default_value = "0x80000000", value_parser = parse_addr)]
+    base: u32,
+    /// Stop comparing after this many instructions.
+    #[arg(long, default_value_t = 1_000_000)]
+    max_steps: u64,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReferenceArg {
+    Spike,
+    Sail,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EngineArg {
+    Interpreter,
+    Blocks,
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum TimeArg {
+    Virtual,
+    Host,
+}
+
+impl From<TimeArg> for TimeMode {
+    fn from(time: TimeArg) -> Self {
+        match time {
+            TimeArg::Virtual => TimeMode::Virtual,
+            TimeArg::Host => TimeMode::Host,
+        }
+    }
+}
+
+impl From<EngineArg> for Engine {
+    fn from(engine: EngineArg) -> Self {
+        match engine {
+            EngineArg::Interpreter => Engine::Interpreter,
+            EngineArg::Blocks => Engine::BasicBlocks,
+            #[cfg(feature = "jit")]
+            EngineArg::Jit => Engine::Jit,
+        }
+    }
+}
 
 fn main() {
-    // Setup CPU
-    let mut cpu = RiscvCpu::new(1024 * 64);
-
-    //Setup program
-    // let program: Vec<u32> = vec![
-    //     0x00a00093, // li x1, 10
-    //     0x01400113, // li x2, 20
-    //     0x002081b3, // add x3, x1, x2
-    //     0x00100073, // EBREAK
-    // ];
-
-    // for (i, instr) in program.iter().enumerate() {
-    //     let bytes = instr.to_le_bytes();
-    //     let start = i * 4;
-    //     cpu.bus[start..start + 4].copy_from_slice(&bytes);
-    // }
-
-    let program = fs::read("programs/bin/test.bin").expect("Failed");
-
-    cpu.bus[0..program.len()].copy_from_slice(&program);
-
-    loop {
-        match cpu.step() {
-            Ok(_) => {
-                println!("Executed PC: {:#x}", cpu.pc);
-                cpu.dump_registers();
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Run(args) => run(args),
+        Command::Disasm(args) => disasm(args),
+        Command::Cosim(args) => cosim(args),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: RunArgs) -> Result<(), String> {
+    let program = read(&args.image)?;
+
+    let mut builder = RiscvCpu::builder()
+        .ram_base(args.base)
+        .engine(args.engine.into())
+        .clock(Clock::new(args.time.into()))
+        .semihosting(Semihosting::new())
+        .call_tracking(true);
+    if let Some(bytes) = args.memory {
+        builder = builder.ram_size(bytes);
+    }
+    let mut cpu = builder.build()?;
+
+    if program.starts_with(b"\x7fELF") {
+        cpu.load_elf(&program)?;
+    } else if program.starts_with(b":") {
+        cpu.load_ihex(&String::from_utf8_lossy(&program))?;
+    } else {
+        cpu.load_binary(args.base, &program)?;
+    }
+    if let Some(entry) = args.entry {
+        cpu.pc = entry;
+    }
+    if args.trace {
+        cpu.set_tracer(PrintTracer::new().symbols(cpu.symbols().clone()));
+    }
+    if let Some(path) = &args.trace_json {
+        let file = fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        cpu.set_tracer(JsonTracer::with_output(Box::new(io::BufWriter::new(file))));
+    }
+
+    let exit = match &args.vcd {
+        Some(path) => {
+            let file = fs::File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let mut vcd = Vcd::new(Box::new(io::BufWriter::new(file)));
+            for (name, addr) in &args.vcd_csr {
+                vcd = vcd.csr(name, *addr);
+            }
+            for &addr in &args.vcd_mem {
+                vcd = vcd.memory(&format!("{:#x}", addr), addr as u64, MemSize::Word);
+            }
+            if args.vcd_cycles {
+                vcd = vcd.cycles();
             }
-            Err(e) => {
-                println!("\n[CPU HALTED]: {}", e);
-                process::exit(1);
+            vcd.run(&mut cpu, args.max_steps)
+                .map_err(|e| format!("{}: {}", path.display(), e))?
+        }
+        None => cpu.run_with_limits(args.max_steps, args.timeout),
+    };
+    // Flushes a JSON trace, which process::exit below wouldn't.
+    cpu.clear_tracer();
+    if args.regs {
+        cpu.dump_registers();
+    }
+
+    match exit {
+        ExitReason::Exited(code) => process::exit(code),
+        ExitReason::Exception(e) => {
+            println!("\n[CPU HALTED]: {}\n", e);
+            if !args.regs {
+                print!("{}", cpu.state());
             }
+            for frame in cpu.backtrace() {
+                println!("  {}", frame);
+            }
+            process::exit(1);
+        }
+        other => {
+            println!("\n[CPU STOPPED]: {:?}", other);
+            process::exit(1);
         }
     }
 }
+
+fn disasm(args: DisasmArgs) -> Result<(), String> {
+    let program = read(&args.image)?;
+
+    if program.starts_with(b"\x7fELF") {
+        let elf = ElfFile::parse(&program)?;
+        for segment in elf.loadable_segments() {
+            print_code(segment.vaddr, elf.segment_data(segment)?);
+        }
+    } else {
+        print_code(args.base, &program);
+    }
+    Ok(())
+}
+
+fn cosim(args: CosimArgs) -> Result<(), String> {
+    let program = read(&args.elf)?;
+
+    let mut builder = RiscvCpu::builder().ram_base(args.base).guest_traps(true);
+    if let Some(bytes) = args.memory {
+        builder = builder.ram_size(bytes);
+    }
+    let mut cpu = builder.build()?;
+    cpu.load_elf(&program)?;
+
+    let mut reference = match args.reference {
+        ReferenceArg::Spike => Reference::spike(&args.elf),
+        ReferenceArg::Sail => Reference::sail(&args.elf),
+    };
+    if let Some(program) = &args.program {
+        reference = reference.program(program);
+    }
+
+    match cosim::compare(&mut cpu, reference.spawn()?, args.max_steps) {
+        Ok(steps) => {
+            println!("{} instructions agree", steps);
+            Ok(())
+        }
+        Err(divergence) => {
+            println!("{}", divergence);
+            process::exit(1);
+        }
+    }
+}
+
+fn print_code(addr: u32, bytes: &[u8]) {
+    for (i, word) in bytes.chunks_exact(4).enumerate() {
+        let raw = u32::from_le_bytes(word.try_into().unwrap());
+        let pc = addr.wrapping_add(4 * i as u32);
+        match decode(raw) {
+            Ok(instruction) => println!("{:08x}:  {:08x}  {}", pc, raw, instruction),
+            Err(_) => println!("{:08x}:  {:08x}  .word {:#x}", pc, raw, raw),
+        }
+    }
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// A number of bytes such as `65536`, `64K`, `64M` or `1G`.
+fn parse_size(text: &str) -> Result<usize, String> {
+    let (digits, scale) = match text.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&text[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&text[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&text[..i], 1 << 30),
+        _ => (text, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .ok_or_else(|| format!("'{}' isn't a size", text))
+}
+
+/// An address in decimal or, with `0x`, hex. Underscores are ignored.
+fn parse_addr(text: &str) -> Result<u32, String> {
+    let digits = text.replace('_', "");
+    match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|_| format!("'{}' isn't an address", text))
+}
+
+/// A number of seconds, which needn't be whole.
+fn parse_seconds(text: &str) -> Result<Duration, String> {
+    text.parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("'{}' isn't a number of seconds", text))
+}
+
+/// A CSR's name, such as `mstatus`, or its number.
+fn parse_csr(text: &str) -> Result<(String, u16), String> {
+    let addr = asm::parse_csr(text).ok_or_else(|| format!("'{}' isn't a CSR", text))?;
+    Ok((text.to_ascii_lowercase(), addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("65536"), Ok(65536));
+        assert_eq!(parse_size("64K"), Ok(64 << 10));
+        assert_eq!(parse_size("64m"), Ok(64 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+
+        assert_eq!(parse_size("64T"), Err("'64T' isn't a size".to_string()));
+        assert!(parse_size("17179869184G").is_err(), "overflows");
+        assert!(parse_size("99999999999999999999").is_err(), "overflows");
+        assert!(parse_size("K").is_err());
+        assert!(parse_size("").is_err());
+    }
+
+    #[test]
+    fn test_parse_addr() {
+        assert_eq!(parse_addr("4096"), Ok(4096));
+        assert_eq!(parse_addr("0x8000_0000"), Ok(0x8000_0000));
+        assert_eq!(parse_addr("0XFFFFFFFF"), Ok(u32::MAX));
+
+        assert_eq!(parse_addr("0x"), Err("'0x' isn't an address".to_string()));
+        assert!(parse_addr("0x1_0000_0000").is_err(), "overflows");
+        assert!(parse_addr("4294967296").is_err(), "overflows");
+        assert!(parse_addr("0x80g").is_err());
+        assert!(parse_addr("").is_err());
+    }
+
+    #[test]
+    fn test_parse_seconds() {
+        assert_eq!(parse_seconds("2"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_seconds("0.25"), Ok(Duration::from_millis(250)));
+
+        assert_eq!(
+            parse_seconds("5s"),
+            Err("'5s' isn't a number of seconds".to_string())
+        );
+        assert!(parse_seconds("-1").is_err());
+        assert!(parse_seconds("1e30").is_err(), "overflows");
+        assert!(parse_seconds("NaN").is_err());
+        assert!(parse_seconds("").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Sv32 address translation, and the H extension's Sv32x4 G-stage.
+
+use crate::MemSize;
+use crate::bus::Bus;
+use crate::csr::Privilege;
+use crate::trap::Exception;
+
+const PAGE_SHIFT: u32 = 12;
+const LEVELS: u32 = 2;
+
+pub const PTE_V: u32 = 1 << 0;
+pub const PTE_R: u32 = 1 << 1;
+pub const PTE_W: u32 = 1 << 2;
+pub const PTE_X: u32 = 1 << 3;
+pub const PTE_U: u32 = 1 << 4;
+pub const PTE_G: u32 = 1 << 5;
+pub const PTE_A: u32 = 1 << 6;
+pub const PTE_D: u32 = 1 << 7;
+
+/// What a translated address is about to be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Fetch,
+    Load,
+    Store,
+}
+
+impl Access {
+    pub fn page_fault(self, vaddr: u32) -> Exception {
+        match self {
+            Access::Fetch => Exception::InstructionPageFault(vaddr),
+            Access::Load => Exception::LoadPageFault(vaddr),
+            Access::Store => Exception::StorePageFault(vaddr),
+        }
+    }
+
+    pub fn guest_page_fault(self, vaddr: u32, gpa: u64) -> Exception {
+        let gpa = (gpa >> 2) as u32;
+        match self {
+            Access::Fetch => Exception::InstructionGuestPageFault(vaddr, gpa),
+            Access::Load => Exception::LoadGuestPageFault(vaddr, gpa),
+            Access::Store => Exception::StoreGuestPageFault(vaddr, gpa),
+        }
+    }
+
+    pub fn access_fault(self, addr: u32) -> Exception {
+        match self {
+            Access::Fetch => Exception::InstructionAccessFault(addr),
+            Access::Load => Exception::LoadAccessFault(addr),
+            Access::Store => Exception::StoreAccessFault(addr),
+        }
+    }
+}
+
+/// Everything besides the address that decides whether a translation is
+/// allowed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sv32 {
+    pub(crate) satp: u32,
+    pub(crate) privilege: Privilege,
+    /// `mstatus.SUM`: S-mode may load and store to user pages.
+    pub(crate) sum: bool,
+    /// `mstatus.MXR`: loads from execute-only pages are allowed.
+    pub(crate) mxr: bool,
+    /// For a VS-stage walk, the G-stage its page tables and result go
+    /// through. The table is in `vsatp` then.
+    pub(crate) g_stage: Option<Sv32x4>,
+}
+
+impl Sv32 {
+    /// Walk the page table for `vaddr`, setting the accessed and dirty bits
+    /// of the leaf as needed. Page tables live in physical memory, so a
+    /// table outside the bus is an access fault rather than a page fault.
+    pub(crate) fn translate(
+        self,
+        bus: &mut Bus,
+        vaddr: u32,
+        access: Access,
+    ) -> Result<u32, Exception> {
+        let walk = Walk {
+            root: ((self.satp & 0x3F_FFFF) as u64) << PAGE_SHIFT,
+            root_bits: 10,
+            page_fault: access.page_fault(vaddr),
+            access_fault: access.access_fault(vaddr),
+        };
+        // Under a G-stage, the VS-stage tables are guest physical too.
+        let locate = |bus: &mut Bus, addr: u64| match self.g_stage {
+            Some(g_stage) => g_stage.translate(bus, addr, vaddr, access, Access::Load),
+            None => u32::try_from(addr).map_err(|_| access.access_fault(vaddr)),
+        };
+        let addr = walk.run(
+            bus,
+            vaddr as u64,
+            access,
+            |pte| self.permits(pte, access),
+            locate,
+        )?;
+
+        match self.g_stage {
+            Some(g_stage) => g_stage.translate(bus, addr, vaddr, access, access),
+            None => u32::try_from(addr).map_err(|_| access.access_fault(vaddr)),
+        }
+    }
+
+    fn permits(self, pte: u32, access: Access) -> bool {
+        let user_page = pte & PTE_U != 0;
+        let privileged = match self.privilege {
+            Privilege::User => user_page,
+            Privilege::Supervisor => !user_page || (self.sum && access != Access::Fetch),
+            Privilege::Machine => true,
+        };
+
+        permits(pte, access, self.mxr) && privileged
+    }
+}
+
+/// The H extension's G-stage: guest physical to bus addresses. Like Sv32,
+/// but with a 16 KiB root table covering 34 bits, and every page has to be
+/// a user page.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sv32x4 {
+    pub(crate) hgatp: u32,
+    /// `mstatus.MXR`. The guest's own MXR doesn't apply here.
+    pub(crate) mxr: bool,
+}
+
+impl Sv32x4 {
+    /// Translate `gpa`, which `vaddr` mapped to. Faults are of the kind
+    /// `access` raises, but `permission` is what's checked: VS-stage page
+    /// table reads only need read permission whatever they're for.
+    pub(crate) fn translate(
+        self,
+        bus: &mut Bus,
+        gpa: u64,
+        vaddr: u32,
+        access: Access,
+        permission: Access,
+    ) -> Result<u32, Exception> {
+        let page_fault = access.guest_page_fault(vaddr, gpa);
+        if gpa >> 34 != 0 {
+            return Err(page_fault);
+        }
+
+        let walk = Walk {
+            root: ((self.hgatp & 0x3F_FFFC) as u64) << PAGE_SHIFT,
+            root_bits: 12,
+            page_fault,
+            access_fault: access.access_fault(vaddr),
+        };
+        let permits = |pte| pte & PTE_U != 0 && permits(pte, permission, self.mxr);
+        let locate =
+            |_: &mut Bus, addr: u64| u32::try_from(addr).map_err(|_| access.access_fault(vaddr));
+        let paddr = walk.run(bus, gpa, permission, permits, locate)?;
+
+        u32::try_from(paddr).map_err(|_| access.access_fault(vaddr))
+    }
+}
+
+/// The part of a two-level walk that doesn't depend on which stage it is.
+struct Walk {
+    root: u64,
+    /// How many address bits index the root table.
+    root_bits: u32,
+    page_fault: Exception,
+    access_fault: Exception,
+}
+
+impl Walk {
+    /// Find the leaf for `addr` and return what it maps to. `locate` turns
+    /// the address of a PTE into a bus address.
+    fn run(
+        &self,
+        bus: &mut Bus,
+        addr: u64,
+        access: Access,
+        permits: impl Fn(u32) -> bool,
+        mut locate: impl FnMut(&mut Bus, u64) -> Result<u32, Exception>,
+    ) -> Result<u64, Exception> {
+        let mut table = self.root;
+
+        for level in (0..LEVELS).rev() {
+            let bits = if level == LEVELS - 1 {
+                self.root_bits
+            } else {
+                10
+            };
+            let vpn = (addr >> (PAGE_SHIFT + 10 * level)) & ((1 << bits) - 1);
+            let pte_addr = locate(bus, table + vpn * 4)?;
+            let pte = bus.read(pte_addr, MemSize::Word).ok_or(self.access_fault)?;
+
+            if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) {
+                return Err(self.page_fault);
+            }
+
+            let ppn = (pte >> 10) as u64;
+
+            if pte & (PTE_R | PTE_X) == 0 {
+                table = ppn << PAGE_SHIFT;
+                continue;
+            }
+
+            if !permits(pte) {
+                return Err(self.page_fault);
+            }
+
+            // A superpage must be aligned to its size.
+            let offset_bits = PAGE_SHIFT + 10 * level;
+            let low_ppn_mask = (1u64 << (10 * level)) - 1;
+            if ppn & low_ppn_mask != 0 {
+                return Err(self.page_fault);
+            }
+
+            let dirty = if access == Access::Store { PTE_D } else { 0 };
+            if pte & (PTE_A | dirty) != PTE_A | dirty {
+                bus.write(pte_addr, MemSize::Word, pte | PTE_A | dirty)
+                    .ok_or(self.access_fault)?;
+            }
+
+            let offset = addr & ((1 << offset_bits) - 1);
+            return Ok((ppn << PAGE_SHIFT) | offset);
+        }
+
+        Err(self.page_fault)
+    }
+}
+
+/// The R/W/X half of a permission check.
+fn permits(pte: u32, access: Access, mxr: bool) -> bool {
+    match access {
+        Access::Fetch => pte & PTE_X != 0,
+        Access::Load => pte & PTE_R != 0 || (mxr && pte & PTE_X != 0),
+        Access::Store => pte & PTE_W != 0,
+    }
+}
@@ -0,0 +1,79 @@
+//! Host-side performance counters, for measuring the emulator itself.
+
+use std::time::{Duration, Instant};
+
+/// What [`RiscvCpu::perf_stats`](crate::RiscvCpu::perf_stats) reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PerfStats {
+    /// Instructions executed by `run`, `run_steps` and `run_until` while
+    /// measuring.
+    pub instructions: u64,
+    /// Host time spent measuring, whether or not the guest was running.
+    pub elapsed: Duration,
+}
+
+impl PerfStats {
+    /// Millions of guest instructions per host second.
+    pub fn mips(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds == 0.0 {
+            return 0.0;
+        }
+        self.instructions as f64 / seconds / 1e6
+    }
+
+    /// Average host time per guest instruction.
+    pub fn time_per_instruction(&self) -> Duration {
+        match self.instructions {
+            0 => Duration::ZERO,
+            n => self.elapsed.div_f64(n as f64),
+        }
+    }
+}
+
+/// A stopwatch plus an instruction count that only advance while started.
+#[derive(Debug, Default)]
+pub(crate) struct PerfCounter {
+    instructions: u64,
+    elapsed: Duration,
+    started: Option<Instant>,
+}
+
+impl PerfCounter {
+    pub(crate) fn start(&mut self) {
+        self.started.get_or_insert_with(Instant::now);
+    }
+
+    pub(crate) fn stop(&mut self) {
+        if let Some(started) = self.started.take() {
+            self.elapsed += started.elapsed();
+        }
+    }
+
+    /// Zero the counts. A running counter keeps running from now.
+    pub(crate) fn reset(&mut self) {
+        self.instructions = 0;
+        self.elapsed = Duration::ZERO;
+        if self.started.is_some() {
+            self.started = Some(Instant::now());
+        }
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.started.is_some()
+    }
+
+    pub(crate) fn retire(&mut self, instructions: u64) {
+        if self.started.is_some() {
+            self.instructions += instructions;
+        }
+    }
+
+    pub(crate) fn stats(&self) -> PerfStats {
+        let running = self.started.map_or(Duration::ZERO, |s| s.elapsed());
+        PerfStats {
+            instructions: self.instructions,
+            elapsed: self.elapsed + running,
+        }
+    }
+}
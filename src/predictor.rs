@@ -0,0 +1,135 @@
+//! Branch predictors for the pipeline timing model. A correct prediction
+//! costs nothing, as if a branch target buffer supplied the target; a wrong
+//! one flushes the pipeline.
+
+/// Guesses which way each conditional branch goes, then learns the answer.
+pub trait BranchPredictor {
+    /// Whether the branch at `pc` will be taken.
+    fn predict(&mut self, pc: u32) -> bool;
+
+    /// The branch at `pc` went the way `taken` says.
+    fn update(&mut self, _pc: u32, _taken: bool) {}
+}
+
+/// Predicts every branch taken.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysTaken;
+
+impl BranchPredictor for AlwaysTaken {
+    fn predict(&mut self, _pc: u32) -> bool {
+        true
+    }
+}
+
+/// Predicts every branch falls through, which is what a pipeline that just
+/// keeps fetching does. The default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NeverTaken;
+
+impl BranchPredictor for NeverTaken {
+    fn predict(&mut self, _pc: u32) -> bool {
+        false
+    }
+}
+
+/// A table of two-bit saturating counters. Taken from 2 up.
+#[derive(Debug, Clone)]
+struct Counters(Vec<u8>);
+
+impl Counters {
+    fn new(entries: usize) -> Self {
+        // Weakly not taken.
+        Self(vec![1; entries])
+    }
+
+    fn predict(&self, index: usize) -> bool {
+        self.0[index] >= 2
+    }
+
+    fn update(&mut self, index: usize, taken: bool) {
+        let counter = &mut self.0[index];
+        *counter = match taken {
+            true => (*counter + 1).min(3),
+            false => counter.saturating_sub(1),
+        };
+    }
+}
+
+/// A two-bit counter per branch, found by the low bits of its address.
+#[derive(Debug, Clone)]
+pub struct Bimodal {
+    counters: Counters,
+}
+
+impl Bimodal {
+    /// A table of `entries` counters, a power of two.
+    pub fn new(entries: usize) -> Result<Self, String> {
+        if !entries.is_power_of_two() {
+            return Err(format!(
+                "Bimodal: {} entries is not a power of two",
+                entries
+            ));
+        }
+        Ok(Self {
+            counters: Counters::new(entries),
+        })
+    }
+
+    fn index(&self, pc: u32) -> usize {
+        (pc >> 2) as usize & (self.counters.0.len() - 1)
+    }
+}
+
+impl BranchPredictor for Bimodal {
+    fn predict(&mut self, pc: u32) -> bool {
+        self.counters.predict(self.index(pc))
+    }
+
+    fn update(&mut self, pc: u32, taken: bool) {
+        let index = self.index(pc);
+        self.counters.update(index, taken);
+    }
+}
+
+/// Two-bit counters found by the branch's address XORed with the
+/// directions of the last `history_bits` branches, so one branch can be
+/// predicted differently depending on how the code got there.
+#[derive(Debug, Clone)]
+pub struct Gshare {
+    counters: Counters,
+    history: usize,
+    mask: usize,
+}
+
+impl Gshare {
+    /// A table of `2^history_bits` counters.
+    pub fn new(history_bits: u32) -> Result<Self, String> {
+        if !(1..=24).contains(&history_bits) {
+            return Err(format!(
+                "Gshare: {} bits of history is outside 1 to 24",
+                history_bits
+            ));
+        }
+        Ok(Self {
+            counters: Counters::new(1 << history_bits),
+            history: 0,
+            mask: (1 << history_bits) - 1,
+        })
+    }
+
+    fn index(&self, pc: u32) -> usize {
+        ((pc >> 2) as usize ^ self.history) & self.mask
+    }
+}
+
+impl BranchPredictor for Gshare {
+    fn predict(&mut self, pc: u32) -> bool {
+        self.counters.predict(self.index(pc))
+    }
+
+    fn update(&mut self, pc: u32, taken: bool) {
+        let index = self.index(pc);
+        self.counters.update(index, taken);
+        self.history = ((self.history << 1) | taken as usize) & self.mask;
+    }
+}
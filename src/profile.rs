@@ -0,0 +1,138 @@
+//! Where the guest's loads and stores go, for finding hot buffers and
+//! data that several harts fight over.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::AddAssign;
+
+use crate::debug::WatchKind;
+
+const PAGE_SIZE: u32 = 0x1000;
+
+/// How many addresses the report lists.
+const REPORT_HOTTEST: usize = 10;
+
+/// Loads and stores to one address or range of them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessCounts {
+    pub loads: u64,
+    pub stores: u64,
+}
+
+impl AccessCounts {
+    pub fn total(&self) -> u64 {
+        self.loads + self.stores
+    }
+}
+
+impl AddAssign for AccessCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.loads += other.loads;
+        self.stores += other.stores;
+    }
+}
+
+/// Every completed load and store, by the virtual address it started at,
+/// the same addresses watchpoints see. A vector access counts once, at its
+/// first element.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryProfile {
+    accesses: HashMap<u32, AccessCounts>,
+}
+
+impl MemoryProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn record(&mut self, addr: u32, kind: WatchKind) {
+        let counts = self.accesses.entry(addr).or_default();
+        match kind {
+            WatchKind::Write => counts.stores += 1,
+            _ => counts.loads += 1,
+        }
+    }
+
+    pub fn counts(&self, addr: u32) -> AccessCounts {
+        self.accesses.get(&addr).copied().unwrap_or_default()
+    }
+
+    /// All accesses added up.
+    pub fn total(&self) -> AccessCounts {
+        let mut total = AccessCounts::default();
+        for &counts in self.accesses.values() {
+            total += counts;
+        }
+        total
+    }
+
+    /// How many distinct addresses were accessed.
+    pub fn len(&self) -> usize {
+        self.accesses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accesses.is_empty()
+    }
+
+    /// The `n` most accessed addresses, busiest first.
+    pub fn hottest(&self, n: usize) -> Vec<(u32, AccessCounts)> {
+        let mut accesses: Vec<(u32, AccessCounts)> = self
+            .accesses
+            .iter()
+            .map(|(&addr, &counts)| (addr, counts))
+            .collect();
+        accesses.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then(a.0.cmp(&b.0)));
+        accesses.truncate(n);
+        accesses
+    }
+
+    /// Accesses grouped into aligned blocks of `size` bytes, keyed by the
+    /// address each block starts at. A size of 64 shows cache lines, which
+    /// is where false sharing turns up. `size` must be a power of two.
+    pub fn regions(&self, size: u32) -> BTreeMap<u32, AccessCounts> {
+        assert!(size.is_power_of_two(), "region size must be a power of two");
+        let mut regions: BTreeMap<u32, AccessCounts> = BTreeMap::new();
+        for (&addr, &counts) in &self.accesses {
+            *regions.entry(addr & !(size - 1)).or_default() += counts;
+        }
+        regions
+    }
+
+    /// Accesses per 4 KiB page.
+    pub fn pages(&self) -> BTreeMap<u32, AccessCounts> {
+        self.regions(PAGE_SIZE)
+    }
+
+    /// Add `other`'s counts to these, e.g. to combine the harts of a
+    /// machine.
+    pub fn merge(&mut self, other: &MemoryProfile) {
+        for (&addr, &counts) in &other.accesses {
+            *self.accesses.entry(addr).or_default() += counts;
+        }
+    }
+}
+
+/// The hottest addresses, then every page that was touched.
+impl fmt::Display for MemoryProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<12} {:>12} {:>12}", "address", "loads", "stores")?;
+        for (addr, counts) in self.hottest(REPORT_HOTTEST) {
+            writeln!(
+                f,
+                "{:#010x}   {:>12} {:>12}",
+                addr, counts.loads, counts.stores
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:<12} {:>12} {:>12}", "page", "loads", "stores")?;
+        for (page, counts) in self.pages() {
+            writeln!(
+                f,
+                "{:#010x}   {:>12} {:>12}",
+                page, counts.loads, counts.stores
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+//! Building guest programs in Rust, with labels for branch and jump
+//! targets.
+//!
+//! ```
+//! use riscv_emulator_rust::program::Program;
+//!
+//! let image = Program::at(0x0)
+//!     .addi(1, 0, 10)
+//!     .label("loop")
+//!     .addi(1, 1, -1)
+//!     .bne(1, 0, "loop")
+//!     .ebreak()
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(image.len(), 16);
+//! ```
+//!
+//! Instructions take operands as the functions in [`encode`](crate::encode)
+//! do, and panic the same way on one that doesn't fit. Label targets are
+//! resolved by [`Program::build`], which fails on a label that's missing,
+//! defined twice or out of reach.
+
+use std::collections::HashMap;
+
+use crate::encode;
+
+/// An instruction waiting for its label's address.
+struct Fixup {
+    /// Index of the (first) word to patch.
+    index: usize,
+    label: String,
+    target: Target,
+}
+
+enum Target {
+    Branch {
+        funct3: u8,
+        rs1: u8,
+        rs2: u8,
+    },
+    Jal {
+        rd: u8,
+    },
+    /// AUIPC into `rd`, then an I-type instruction with `opcode` that adds
+    /// the low 12 bits: ADDI for `la`, JALR for `call`.
+    PcRelative {
+        rd: u8,
+        opcode: u8,
+    },
+}
+
+/// A guest program being built an instruction at a time.
+pub struct Program {
+    base: u32,
+    words: Vec<u32>,
+    labels: HashMap<String, u32>,
+    duplicate: Option<String>,
+    fixups: Vec<Fixup>,
+}
+
+macro_rules! instructions {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {$(
+        pub fn $name(self, $($arg: $ty),*) -> Self {
+            self.word(encode::$name($($arg),*))
+        }
+    )*};
+}
+
+macro_rules! branches {
+    ($($name:ident = $funct3:expr),* $(,)?) => {$(
+        pub fn $name(self, rs1: u8, rs2: u8, label: &str) -> Self {
+            self.fixup(1, label, Target::Branch { funct3: $funct3, rs1, rs2 })
+        }
+    )*};
+}
+
+impl Program {
+    /// A program to be loaded at `base`.
+    pub fn at(base: u32) -> Self {
+        Self {
+            base,
+            words: Vec::new(),
+            labels: HashMap::new(),
+            duplicate: None,
+            fixups: Vec::new(),
+        }
+    }
+
+    /// The address the next instruction will be at.
+    pub fn here(&self) -> u32 {
+        self.base.wrapping_add(4 * self.words.len() as u32)
+    }
+
+    /// Name the address of the next instruction.
+    pub fn label(mut self, name: &str) -> Self {
+        let here = self.here();
+        if self.labels.insert(name.to_string(), here).is_some() && self.duplicate.is_none() {
+            self.duplicate = Some(name.to_string());
+        }
+        self
+    }
+
+    /// Append a raw word: an instruction encoded some other way, or data.
+    pub fn word(mut self, word: u32) -> Self {
+        self.words.push(word);
+        self
+    }
+
+    fn fixup(mut self, words: usize, label: &str, target: Target) -> Self {
+        self.fixups.push(Fixup {
+            index: self.words.len(),
+            label: label.to_string(),
+            target,
+        });
+        self.words.resize(self.words.len() + words, 0);
+        self
+    }
+
+    instructions! {
+        lui(rd: u8, imm: u32);
+        auipc(rd: u8, imm: u32);
+        jalr(rd: u8, rs1: u8, offset: i32);
+        lb(rd: u8, rs1: u8, offset: i32);
+        lh(rd: u8, rs1: u8, offset: i32);
+        lw(rd: u8, rs1: u8, offset: i32);
+        lbu(rd: u8, rs1: u8, offset: i32);
+        lhu(rd: u8, rs1: u8, offset: i32);
+        sb(rs2: u8, rs1: u8, offset: i32);
+        sh(rs2: u8, rs1: u8, offset: i32);
+        sw(rs2: u8, rs1: u8, offset: i32);
+        addi(rd: u8, rs1: u8, imm: i32);
+        slti(rd: u8, rs1: u8, imm: i32);
+        sltiu(rd: u8, rs1: u8, imm: i32);
+        xori(rd: u8, rs1: u8, imm: i32);
+        ori(rd: u8, rs1: u8, imm: i32);
+        andi(rd: u8, rs1: u8, imm: i32);
+        slli(rd: u8, rs1: u8, shamt: u8);
+        srli(rd: u8, rs1: u8, shamt: u8);
+        srai(rd: u8, rs1: u8, shamt: u8);
+        add(rd: u8, rs1: u8, rs2: u8);
+        sub(rd: u8, rs1: u8, rs2: u8);
+        sll(rd: u8, rs1: u8, rs2: u8);
+        slt(rd: u8, rs1: u8, rs2: u8);
+        sltu(rd: u8, rs1: u8, rs2: u8);
+        xor(rd: u8, rs1: u8, rs2: u8);
+        srl(rd: u8, rs1: u8, rs2: u8);
+        sra(rd: u8, rs1: u8, rs2: u8);
+        or(rd: u8, rs1: u8, rs2: u8);
+        and(rd: u8, rs1: u8, rs2: u8);
+        fence();
+        ecall();
+        ebreak();
+        csrrw(rd: u8, csr: u16, rs1: u8);
+        csrrs(rd: u8, csr: u16, rs1: u8);
+        csrrc(rd: u8, csr: u16, rs1: u8);
+        csrrwi(rd: u8, csr: u16, uimm: u8);
+        csrrsi(rd: u8, csr: u16, uimm: u8);
+        csrrci(rd: u8, csr: u16, uimm: u8);
+        mret();
+        sret();
+        wfi();
+    }
+
+    branches! {
+        beq = 0x0,
+        bne = 0x1,
+        blt = 0x4,
+        bge = 0x5,
+        bltu = 0x6,
+        bgeu = 0x7,
+    }
+
+    pub fn jal(self, rd: u8, label: &str) -> Self {
+        self.fixup(1, label, Target::Jal { rd })
+    }
+
+    // ── Pseudo-instructions ──────────────────────────────────────────────────
+
+    pub fn nop(self) -> Self {
+        self.addi(0, 0, 0)
+    }
+
+    pub fn mv(self, rd: u8, rs: u8) -> Self {
+        self.addi(rd, rs, 0)
+    }
+
+    /// Load a 32-bit value, sign-extended on RV64, in one or two
+    /// instructions.
+    pub fn li(mut self, rd: u8, value: i32) -> Self {
+        self.words.extend(encode::li(rd, value));
+        self
+    }
+
+    pub fn j(self, label: &str) -> Self {
+        self.jal(0, label)
+    }
+
+    pub fn ret(self) -> Self {
+        self.jalr(0, 1, 0)
+    }
+
+    /// Call `label` from anywhere, through `ra`.
+    pub fn call(self, label: &str) -> Self {
+        self.fixup(
+            2,
+            label,
+            Target::PcRelative {
+                rd: 1,
+                opcode: 0x67,
+            },
+        )
+    }
+
+    /// Load the address of `label` from anywhere.
+    pub fn la(self, rd: u8, label: &str) -> Self {
+        self.fixup(2, label, Target::PcRelative { rd, opcode: 0x13 })
+    }
+
+    /// Resolve labels and lay the program out as little-endian bytes.
+    pub fn build(mut self) -> Result<Vec<u8>, String> {
+        if let Some(label) = self.duplicate {
+            return Err(format!("Program: duplicate label `{}`", label));
+        }
+
+        for fixup in &self.fixups {
+            let Some(&target) = self.labels.get(&fixup.label) else {
+                return Err(format!("Program: undefined label `{}`", fixup.label));
+            };
+            let pc = self.base.wrapping_add(4 * fixup.index as u32);
+            let offset = target.wrapping_sub(pc) as i32;
+            let out_of_range = |kind: &str| {
+                format!(
+                    "Program: `{}` is out of range of the {} at {:#x}",
+                    fixup.label, kind, pc
+                )
+            };
+
+            match fixup.target {
+                Target::Branch { funct3, rs1, rs2 } => {
+                    if !(-4096..4096).contains(&offset) {
+                        return Err(out_of_range("branch"));
+                    }
+                    self.words[fixup.index] = encode::btype(offset, rs1, rs2, funct3);
+                }
+                Target::Jal { rd } => {
+                    if !(-(1 << 20)..1 << 20).contains(&offset) {
+                        return Err(out_of_range("jump"));
+                    }
+                    self.words[fixup.index] = encode::jal(rd, offset);
+                }
+                Target::PcRelative { rd, opcode } => {
+                    let (hi, lo) = encode::split_offset(offset);
+                    self.words[fixup.index] = encode::auipc(rd, hi);
+                    self.words[fixup.index + 1] = encode::itype(lo, rd, 0x0, rd, opcode);
+                }
+            }
+        }
+
+        Ok(self.words.iter().flat_map(|w| w.to_le_bytes()).collect())
+    }
+}
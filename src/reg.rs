@@ -0,0 +1,150 @@
+//! Integer registers by ABI name.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::RiscvCpu;
+use crate::state::ABI_NAMES;
+use crate::xlen::Xlen;
+
+/// One of `x0` to `x31`, named as the calling convention names it.
+/// [`RiscvCpu::reg`](crate::RiscvCpu::reg) and
+/// [`RiscvCpu::set_reg`](crate::RiscvCpu::set_reg) take these.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Reg {
+    Zero,
+    Ra,
+    Sp,
+    Gp,
+    Tp,
+    T0,
+    T1,
+    T2,
+    /// Also the frame pointer, `fp`.
+    S0,
+    S1,
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    A7,
+    S2,
+    S3,
+    S4,
+    S5,
+    S6,
+    S7,
+    S8,
+    S9,
+    S10,
+    S11,
+    T3,
+    T4,
+    T5,
+    T6,
+}
+
+impl Reg {
+    /// `x0` to `x31`, in order.
+    pub const ALL: [Reg; 32] = [
+        Reg::Zero,
+        Reg::Ra,
+        Reg::Sp,
+        Reg::Gp,
+        Reg::Tp,
+        Reg::T0,
+        Reg::T1,
+        Reg::T2,
+        Reg::S0,
+        Reg::S1,
+        Reg::A0,
+        Reg::A1,
+        Reg::A2,
+        Reg::A3,
+        Reg::A4,
+        Reg::A5,
+        Reg::A6,
+        Reg::A7,
+        Reg::S2,
+        Reg::S3,
+        Reg::S4,
+        Reg::S5,
+        Reg::S6,
+        Reg::S7,
+        Reg::S8,
+        Reg::S9,
+        Reg::S10,
+        Reg::S11,
+        Reg::T3,
+        Reg::T4,
+        Reg::T5,
+        Reg::T6,
+    ];
+
+    /// `x<index>`, if there is one.
+    pub fn from_index(index: u8) -> Option<Reg> {
+        Reg::ALL.get(index as usize).copied()
+    }
+
+    /// The `n` of `xn`.
+    pub fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// The ABI name, as `objdump` prints it.
+    pub fn name(self) -> &'static str {
+        ABI_NAMES[self as usize]
+    }
+}
+
+impl From<Reg> for u8 {
+    fn from(reg: Reg) -> u8 {
+        reg.index()
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Reg {
+    type Err = String;
+
+    /// An ABI name, `fp`, or `x0` to `x31`.
+    fn from_str(s: &str) -> Result<Reg, String> {
+        let index = match s {
+            "fp" => Some(8),
+            _ => match s.strip_prefix('x') {
+                Some(n) if !n.starts_with('+') => n.parse().ok(),
+                _ => ABI_NAMES
+                    .iter()
+                    .position(|&name| name == s)
+                    .map(|i| i as u8),
+            },
+        };
+        index
+            .and_then(Reg::from_index)
+            .ok_or_else(|| format!("Reg: no register named {:?}", s))
+    }
+}
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// The value of `reg`, zero-extended to 64 bits.
+    pub fn reg(&self, reg: Reg) -> u64 {
+        self.read_reg(reg.index())
+    }
+
+    /// Set `reg` from the host. Writes to `zero` are ignored, RV32 keeps
+    /// the low 32 bits, and register watches don't fire.
+    pub fn set_reg(&mut self, reg: Reg, value: u64) {
+        if reg != Reg::Zero {
+            self.regs[reg as usize] = X::truncate(value);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Running a hart on a thread of its own, driven from others.
+//!
+//! A hart isn't `Send`: tracers, step hooks, custom instruction handlers
+//! and devices are plain trait objects, and the block cache shares blocks
+//! through `Rc`. So rather than lock each of those, the hart stays on one
+//! thread and everything else talks to it over a channel. Commands are
+//! handled between slices of [`SLICE`] instructions, so a closure passed to
+//! [`HartHandle::with`] always sees the hart between two instructions.
+
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::trap::Interrupt;
+use crate::xlen::{Rv32, Xlen};
+use crate::{ExitReason, RiscvCpu};
+
+/// How many instructions a running hart executes between looking for
+/// commands.
+pub const SLICE: u64 = 10_000;
+
+type Call<X> = Box<dyn FnOnce(&mut RiscvCpu<X>) + Send>;
+
+enum Command<X: Xlen> {
+    Resume,
+    Pause,
+    Call(Call<X>),
+    Shutdown,
+}
+
+/// Sends commands to a hart running on its own thread. Cheap to clone, and
+/// can be handed to any thread.
+pub struct HartHandle<X: Xlen = Rv32> {
+    commands: Sender<Command<X>>,
+}
+
+impl<X: Xlen> Clone for HartHandle<X> {
+    fn clone(&self) -> Self {
+        Self {
+            commands: self.commands.clone(),
+        }
+    }
+}
+
+impl<X: Xlen> HartHandle<X> {
+    /// Start running, until an exception, breakpoint, watchpoint or exit
+    /// stops the hart. A hart waiting in WFI with nothing to wake it waits
+    /// for the next command instead.
+    pub fn resume(&self) -> Result<(), String> {
+        self.send(Command::Resume)
+    }
+
+    /// Stop running at the end of the current slice.
+    pub fn pause(&self) -> Result<(), String> {
+        self.send(Command::Pause)
+    }
+
+    /// Run `f` on the hart's thread between two instructions, and return
+    /// what it returns.
+    pub fn with<R, F>(&self, f: F) -> Result<R, String>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut RiscvCpu<X>) -> R + Send + 'static,
+    {
+        let (reply, result) = mpsc::sync_channel(1);
+        self.send(Command::Call(Box::new(move |cpu| {
+            let _ = reply.send(f(cpu));
+        })))?;
+        result.recv().map_err(|_| gone())
+    }
+
+    /// Set `interrupt` pending in `mip`, as a device would.
+    pub fn raise_interrupt(&self, interrupt: Interrupt) -> Result<(), String> {
+        self.send(Command::Call(Box::new(move |cpu| {
+            cpu.raise_interrupt(interrupt)
+        })))
+    }
+
+    fn send(&self, command: Command<X>) -> Result<(), String> {
+        self.commands.send(command).map_err(|_| gone())
+    }
+}
+
+fn gone() -> String {
+    "HartThread: the hart's thread has exited".to_string()
+}
+
+/// A hart on a thread of its own. It starts paused; dropping this shuts the
+/// thread down.
+pub struct HartThread<X: Xlen = Rv32> {
+    handle: HartHandle<X>,
+    stops: Receiver<ExitReason>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<X: Xlen> HartThread<X> {
+    /// Build a hart with `build` on a new thread. `build` runs on that
+    /// thread, so the hart and everything in it needn't be `Send`.
+    pub fn spawn<F>(build: F) -> Result<Self, String>
+    where
+        F: FnOnce() -> Result<RiscvCpu<X>, String> + Send + 'static,
+    {
+        let (commands, received) = mpsc::channel();
+        let (stopped, stops) = mpsc::channel();
+        let (built, result) = mpsc::sync_channel(1);
+
+        let thread = thread::spawn(move || match build() {
+            Ok(cpu) => {
+                let _ = built.send(Ok(()));
+                serve(cpu, received, stopped);
+            }
+            Err(e) => {
+                let _ = built.send(Err(e));
+            }
+        });
+        result.recv().map_err(|_| gone())??;
+
+        Ok(Self {
+            handle: HartHandle { commands },
+            stops,
+            thread: Some(thread),
+        })
+    }
+
+    /// A handle other threads can command the hart through.
+    pub fn handle(&self) -> HartHandle<X> {
+        self.handle.clone()
+    }
+
+    /// See [`HartHandle::resume`].
+    pub fn resume(&self) -> Result<(), String> {
+        self.handle.resume()
+    }
+
+    /// See [`HartHandle::pause`].
+    pub fn pause(&self) -> Result<(), String> {
+        self.handle.pause()
+    }
+
+    /// See [`HartHandle::with`].
+    pub fn with<R, F>(&self, f: F) -> Result<R, String>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut RiscvCpu<X>) -> R + Send + 'static,
+    {
+        self.handle.with(f)
+    }
+
+    /// See [`HartHandle::raise_interrupt`].
+    pub fn raise_interrupt(&self, interrupt: Interrupt) -> Result<(), String> {
+        self.handle.raise_interrupt(interrupt)
+    }
+
+    /// Wait until running stops, and say why.
+    pub fn wait(&self) -> Result<ExitReason, String> {
+        self.stops.recv().map_err(|_| gone())
+    }
+
+    /// Why running stopped, if it has since last asked.
+    pub fn stopped(&self) -> Option<ExitReason> {
+        self.stops.try_recv().ok()
+    }
+}
+
+impl<X: Xlen> Drop for HartThread<X> {
+    fn drop(&mut self) {
+        let _ = self.handle.send(Command::Shutdown);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The hart's thread: handle commands, and while running, run a slice at a
+/// time in between.
+fn serve<X: Xlen>(
+    mut cpu: RiscvCpu<X>,
+    commands: Receiver<Command<X>>,
+    stopped: Sender<ExitReason>,
+) {
+    let mut running = false;
+    // Idle in WFI: nothing will change until a command comes.
+    let mut idle = false;
+
+    loop {
+        let command = if running && !idle {
+            match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return,
+            }
+        };
+
+        match command {
+            Some(command) => {
+                idle = false;
+                match command {
+                    Command::Resume => running = true,
+                    Command::Pause => running = false,
+                    Command::Call(f) => f(&mut cpu),
+                    Command::Shutdown => return,
+                }
+            }
+            None => match cpu.run_steps(SLICE) {
+                ExitReason::StepLimit => {}
+                ExitReason::Idle => idle = true,
+                reason => {
+                    running = false;
+                    let _ = stopped.send(reason);
+                }
+            },
+        }
+    }
+}
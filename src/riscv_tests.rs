@@ -0,0 +1,131 @@
+//! Runner for the upstream riscv-tests ISA suite (`rv32ui-p-*` and friends).
+//!
+//! The tests are linked at 0x8000_0000 and report their result by storing to
+//! the `tohost` symbol: 1 means pass, `(n << 1) | 1` means test case `n`
+//! failed. They finish with an ECALL into their own trap handler, so the CPU
+//! runs with guest traps enabled.
+
+use std::fmt;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::debug::WatchKind;
+use crate::loader::ElfFile;
+use crate::trap::Exception;
+use crate::{MemSize, RiscvCpu, StepOutcome};
+
+pub const RAM_BASE: u32 = 0x8000_0000;
+pub const RAM_SIZE: usize = 1024 * 1024;
+pub const DEFAULT_STEP_LIMIT: u64 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestOutcome {
+    Pass,
+    /// The number of the failing test case.
+    Fail(u32),
+    /// Never wrote to `tohost` within the step limit.
+    Timeout,
+    /// An exception escaped to the host instead of being handled by the test.
+    Halted(Exception),
+    /// The image couldn't be loaded.
+    Error(String),
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        *self == TestOutcome::Pass
+    }
+}
+
+impl fmt::Display for TestOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestOutcome::Pass => write!(f, "PASS"),
+            TestOutcome::Fail(case) => write!(f, "FAIL (test {})", case),
+            TestOutcome::Timeout => write!(f, "TIMEOUT"),
+            TestOutcome::Halted(e) => write!(f, "HALTED: {}", e),
+            TestOutcome::Error(e) => write!(f, "ERROR: {}", e),
+        }
+    }
+}
+
+/// Load a single test ELF and run it until it writes `tohost`.
+pub fn run_elf(bytes: &[u8], step_limit: u64) -> TestOutcome {
+    match load(bytes, RAM_SIZE) {
+        Ok((mut cpu, tohost)) => run_to_tohost(&mut cpu, tohost, step_limit),
+        Err(e) => TestOutcome::Error(e),
+    }
+}
+
+/// Build a machine the way the test environments expect, with RAM at
+/// [`RAM_BASE`] and guest traps enabled, and load `bytes` into it. Also
+/// returns the address of `tohost`.
+pub fn load(bytes: &[u8], ram_size: usize) -> Result<(RiscvCpu, u32), String> {
+    let tohost = ElfFile::parse(bytes)?
+        .symbol("tohost")
+        .ok_or_else(|| String::from("no `tohost` symbol"))?;
+
+    let mut cpu = RiscvCpu::builder()
+        .ram_base(RAM_BASE)
+        .ram_size(ram_size)
+        .guest_traps(true)
+        .build()?;
+    cpu.load_elf(bytes)?;
+
+    Ok((cpu, tohost))
+}
+
+/// Run until the guest writes a result to `tohost`.
+pub fn run_to_tohost(cpu: &mut RiscvCpu, tohost: u32, step_limit: u64) -> TestOutcome {
+    cpu.add_watchpoint(tohost, 4, WatchKind::Write);
+
+    for _ in 0..step_limit {
+        match cpu.step() {
+            Ok(StepOutcome::Watchpoint(hit)) => match cpu.bus.read(tohost, MemSize::Word) {
+                Some(0) => {}
+                Some(1) => return TestOutcome::Pass,
+                Some(value) if value & 1 == 1 => return TestOutcome::Fail(value >> 1),
+                Some(value) => {
+                    return TestOutcome::Error(format!(
+                        "unexpected tohost value {:#x} written at {:#x}",
+                        value, hit.pc
+                    ));
+                }
+                None => return TestOutcome::Error(String::from("`tohost` is not in RAM")),
+            },
+            Ok(_) => {}
+            Err(e) => return TestOutcome::Halted(e),
+        }
+    }
+
+    TestOutcome::Timeout
+}
+
+/// Run every test in `dir` whose file name starts with `prefix`, e.g.
+/// `"rv32ui-p-"`. Disassembly dumps and other files with an extension are
+/// skipped. Results are sorted by test name.
+#[cfg(feature = "std")]
+pub fn run_suite(dir: &Path, prefix: &str) -> io::Result<Vec<(String, TestOutcome)>> {
+    let mut results = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if !name.starts_with(prefix) || path.extension().is_some() || !path.is_file() {
+            continue;
+        }
+
+        let bytes = fs::read(&path)?;
+        results.push((name.to_string(), run_elf(&bytes, DEFAULT_STEP_LIMIT)));
+    }
+
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(results)
+}
@@ -0,0 +1,341 @@
+//! RISC-V semihosting: lets bare-metal programs (newlib, picolibc) use the
+//! host's console and filesystem.
+//!
+//! A call is an EBREAK wrapped in a magic `slli x0, x0, 0x1f` /
+//! `srai x0, x0, 7` pair, with the operation number in `a0` and a pointer to
+//! its parameter block in `a1`. The result comes back in `a0`. Operation
+//! numbers and semantics follow the ARM semihosting spec. Opening, removing
+//! and renaming host files needs the `std` feature.
+
+use std::collections::HashMap;
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::MemSize;
+use crate::bus::Bus;
+
+/// `slli x0, x0, 0x1f`, the instruction before the EBREAK.
+pub const ENTRY_NOP: u32 = 0x01f0_1013;
+/// `srai x0, x0, 7`, the instruction after the EBREAK.
+pub const EXIT_NOP: u32 = 0x4070_5013;
+
+pub const SYS_OPEN: u32 = 0x01;
+pub const SYS_CLOSE: u32 = 0x02;
+pub const SYS_WRITEC: u32 = 0x03;
+pub const SYS_WRITE0: u32 = 0x04;
+pub const SYS_WRITE: u32 = 0x05;
+pub const SYS_READ: u32 = 0x06;
+pub const SYS_READC: u32 = 0x07;
+pub const SYS_ISERROR: u32 = 0x08;
+pub const SYS_ISTTY: u32 = 0x09;
+pub const SYS_SEEK: u32 = 0x0A;
+pub const SYS_FLEN: u32 = 0x0C;
+pub const SYS_REMOVE: u32 = 0x0E;
+pub const SYS_RENAME: u32 = 0x0F;
+pub const SYS_CLOCK: u32 = 0x10;
+pub const SYS_TIME: u32 = 0x11;
+pub const SYS_ERRNO: u32 = 0x13;
+pub const SYS_GET_CMDLINE: u32 = 0x15;
+pub const SYS_HEAPINFO: u32 = 0x16;
+pub const SYS_EXIT: u32 = 0x18;
+pub const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// `ADP_Stopped_ApplicationExit`, the only SYS_EXIT reason that means success.
+const APPLICATION_EXIT: u32 = 0x20026;
+
+const STDIN: u32 = 0;
+const STDOUT: u32 = 1;
+const STDERR: u32 = 2;
+
+/// What the CPU should do after a semihosting call.
+pub(crate) enum Outcome {
+    /// Write this to `a0` and carry on.
+    Return(u32),
+    Exit(i32),
+}
+
+pub struct Semihosting {
+    output: Box<dyn Write>,
+    files: HashMap<u32, File>,
+    #[cfg(feature = "std")]
+    next_handle: u32,
+    cmdline: String,
+    errno: u32,
+}
+
+impl Semihosting {
+    /// Console output goes to the host's stdout.
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            files: HashMap::new(),
+            #[cfg(feature = "std")]
+            next_handle: 3,
+            cmdline: String::new(),
+            errno: 0,
+        }
+    }
+
+    /// Send console output to `output` from now on.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// What `SYS_GET_CMDLINE` returns, e.g. `"prog arg1 arg2"`.
+    pub fn cmdline(mut self, cmdline: impl Into<String>) -> Self {
+        self.cmdline = cmdline.into();
+        self
+    }
+
+    pub(crate) fn call(&mut self, op: u32, arg: u32, bus: &mut Bus) -> Outcome {
+        let result = match op {
+            SYS_EXIT => return Outcome::Exit(self.exit_code(arg, bus, false)),
+            SYS_EXIT_EXTENDED => return Outcome::Exit(self.exit_code(arg, bus, true)),
+            _ => self.dispatch(op, arg, bus),
+        };
+
+        match result {
+            Ok(value) => Outcome::Return(value),
+            Err(e) => {
+                self.errno = e.raw_os_error().unwrap_or(5) as u32; // EIO
+                Outcome::Return(u32::MAX)
+            }
+        }
+    }
+
+    fn dispatch(&mut self, op: u32, arg: u32, bus: &mut Bus) -> io::Result<u32> {
+        match op {
+            SYS_OPEN => {
+                let [name, mode, len] = params(bus, arg)?;
+                let name = read_string(bus, name, len)?;
+                self.open(&name, mode)
+            }
+            SYS_CLOSE => {
+                let [handle] = params(bus, arg)?;
+                if handle > STDERR && self.files.remove(&handle).is_none() {
+                    return Err(bad_handle());
+                }
+                Ok(0)
+            }
+            SYS_WRITEC => {
+                let c = read_byte(bus, arg)?;
+                self.output.write_all(&[c])?;
+                self.output.flush()?;
+                Ok(0)
+            }
+            SYS_WRITE0 => {
+                let mut bytes = Vec::new();
+                let mut addr = arg;
+                loop {
+                    match read_byte(bus, addr)? {
+                        0 => break,
+                        c => bytes.push(c),
+                    }
+                    addr = addr.wrapping_add(1);
+                }
+                self.output.write_all(&bytes)?;
+                self.output.flush()?;
+                Ok(0)
+            }
+            SYS_WRITE => {
+                let [handle, buf, len] = params(bus, arg)?;
+                let bytes = read_bytes(bus, buf, len)?;
+                match handle {
+                    STDOUT | STDERR => {
+                        self.output.write_all(&bytes)?;
+                        self.output.flush()?;
+                    }
+                    _ => self.file(handle)?.write_all(&bytes)?,
+                }
+                // Returns the number of bytes *not* written.
+                Ok(0)
+            }
+            SYS_READ => {
+                let [handle, buf, len] = params(bus, arg)?;
+                let mut bytes = vec![0; len as usize];
+                let n = match handle {
+                    STDIN => io::stdin().read(&mut bytes)?,
+                    _ => self.file(handle)?.read(&mut bytes)?,
+                };
+                write_bytes(bus, buf, &bytes[..n])?;
+                Ok(len - n as u32)
+            }
+            SYS_READC => {
+                let mut c = [0];
+                io::stdin().read_exact(&mut c)?;
+                Ok(c[0] as u32)
+            }
+            SYS_ISERROR => {
+                let [status] = params(bus, arg)?;
+                Ok(((status as i32) < 0) as u32)
+            }
+            SYS_ISTTY => {
+                let [handle] = params(bus, arg)?;
+                Ok((handle <= STDERR) as u32)
+            }
+            SYS_SEEK => {
+                let [handle, pos] = params(bus, arg)?;
+                self.file(handle)?.seek(SeekFrom::Start(pos as u64))?;
+                Ok(0)
+            }
+            SYS_FLEN => {
+                let [handle] = params(bus, arg)?;
+                Ok(self.file(handle)?.metadata()?.len() as u32)
+            }
+            #[cfg(feature = "std")]
+            SYS_REMOVE => {
+                let [name, len] = params(bus, arg)?;
+                fs::remove_file(read_string(bus, name, len)?)?;
+                Ok(0)
+            }
+            #[cfg(feature = "std")]
+            SYS_RENAME => {
+                let [from, from_len, to, to_len] = params(bus, arg)?;
+                let from = read_string(bus, from, from_len)?;
+                let to = read_string(bus, to, to_len)?;
+                fs::rename(from, to)?;
+                Ok(0)
+            }
+            // Both go by guest time, so they're repeatable in virtual time.
+            SYS_CLOCK => Ok((bus.clock().duration(bus.time()).as_millis() / 10) as u32),
+            SYS_TIME => Ok(bus.clock().realtime(bus.time()).as_secs() as u32),
+            SYS_ERRNO => Ok(self.errno),
+            SYS_GET_CMDLINE => {
+                let [buf, len] = params(bus, arg)?;
+                let cmdline = self.cmdline.as_bytes();
+                if cmdline.len() >= len as usize {
+                    return Err(io::Error::from_raw_os_error(7)); // E2BIG
+                }
+                write_bytes(bus, buf, cmdline)?;
+                write_bytes(bus, buf.wrapping_add(cmdline.len() as u32), &[0])?;
+                write_word(bus, arg.wrapping_add(4), cmdline.len() as u32)?;
+                Ok(0)
+            }
+            SYS_HEAPINFO => {
+                // Zeros tell the C library to fall back on its linker symbols.
+                let [block] = params(bus, arg)?;
+                for i in 0..4 {
+                    write_word(bus, block.wrapping_add(4 * i), 0)?;
+                }
+                Ok(0)
+            }
+            _ => Err(io::Error::from_raw_os_error(38)), // ENOSYS
+        }
+    }
+
+    fn open(&mut self, name: &str, mode: u32) -> io::Result<u32> {
+        // ":tt" is the console; the mode picks which stream.
+        if name == ":tt" {
+            return Ok(match mode {
+                0..=3 => STDIN,
+                4..=7 => STDOUT,
+                _ => STDERR,
+            });
+        }
+        self.open_file(name, mode)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn open_file(&mut self, _name: &str, _mode: u32) -> io::Result<u32> {
+        Err(io::Error::from_raw_os_error(38)) // ENOSYS
+    }
+
+    #[cfg(feature = "std")]
+    fn open_file(&mut self, name: &str, mode: u32) -> io::Result<u32> {
+        // Modes are fopen()'s "r", "rb", "r+", "r+b", "w", ... "a+b" in order.
+        let mut options = OpenOptions::new();
+        match mode {
+            0 | 1 => options.read(true),
+            2 | 3 => options.read(true).write(true),
+            4 | 5 => options.write(true).create(true).truncate(true),
+            6 | 7 => options.read(true).write(true).create(true).truncate(true),
+            8 | 9 => options.append(true).create(true),
+            10 | 11 => options.read(true).append(true).create(true),
+            _ => return Err(io::Error::from_raw_os_error(22)), // EINVAL
+        };
+
+        let file = options.open(name)?;
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.files.insert(handle, file);
+
+        Ok(handle)
+    }
+
+    fn file(&mut self, handle: u32) -> io::Result<&mut File> {
+        self.files.get_mut(&handle).ok_or_else(bad_handle)
+    }
+
+    /// On RV32, SYS_EXIT passes the reason directly in `a1`, while
+    /// SYS_EXIT_EXTENDED points at a `[reason, status]` block.
+    fn exit_code(&self, arg: u32, bus: &mut Bus, extended: bool) -> i32 {
+        if !extended {
+            return if arg == APPLICATION_EXIT { 0 } else { 1 };
+        }
+
+        match params(bus, arg) {
+            Ok([APPLICATION_EXIT, status]) => status as i32,
+            _ => 1,
+        }
+    }
+}
+
+impl Default for Semihosting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn bad_handle() -> io::Error {
+    io::Error::from_raw_os_error(9) // EBADF
+}
+
+fn fault() -> io::Error {
+    io::Error::from_raw_os_error(14) // EFAULT
+}
+
+/// Read an `N`-word parameter block.
+fn params<const N: usize>(bus: &mut Bus, addr: u32) -> io::Result<[u32; N]> {
+    let mut words = [0; N];
+    for (i, word) in words.iter_mut().enumerate() {
+        *word = bus
+            .read(addr.wrapping_add(4 * i as u32), MemSize::Word)
+            .ok_or_else(fault)?;
+    }
+    Ok(words)
+}
+
+fn read_byte(bus: &mut Bus, addr: u32) -> io::Result<u8> {
+    bus.read(addr, MemSize::Byte)
+        .map(|b| b as u8)
+        .ok_or_else(fault)
+}
+
+fn read_bytes(bus: &mut Bus, addr: u32, len: u32) -> io::Result<Vec<u8>> {
+    (0..len)
+        .map(|i| read_byte(bus, addr.wrapping_add(i)))
+        .collect()
+}
+
+fn read_string(bus: &mut Bus, addr: u32, len: u32) -> io::Result<String> {
+    let bytes = read_bytes(bus, addr, len)?;
+    String::from_utf8(bytes).map_err(|_| io::Error::from_raw_os_error(22))
+}
+
+fn write_bytes(bus: &mut Bus, addr: u32, bytes: &[u8]) -> io::Result<()> {
+    for (i, &b) in bytes.iter().enumerate() {
+        bus.write(addr.wrapping_add(i as u32), MemSize::Byte, b as u32)
+            .ok_or_else(fault)?;
+    }
+    Ok(())
+}
+
+fn write_word(bus: &mut Bus, addr: u32, value: u32) -> io::Result<()> {
+    bus.write(addr, MemSize::Word, value).ok_or_else(fault)
+}
@@ -0,0 +1,79 @@
+//! Signature dumps for the RISC-V architecture tests (riscv-arch-test), so
+//! the emulator can act as a RISCOF DUT.
+//!
+//! A test writes its results between the `begin_signature` and
+//! `end_signature` symbols and then halts by writing `tohost`. The signature
+//! file holds that region as one hex value per line, `granularity` bytes per
+//! line, lowest address first.
+
+use std::fmt::Write;
+
+use crate::MemSize;
+use crate::bus::Bus;
+use crate::loader::ElfFile;
+use crate::riscv_tests::{self, TestOutcome};
+
+pub const RAM_SIZE: usize = 8 * 1024 * 1024;
+pub const DEFAULT_GRANULARITY: u32 = 4;
+
+/// Run an architecture test to completion and dump its signature region.
+/// The signature is produced even if the test didn't halt cleanly, so the
+/// outcome is returned alongside it.
+pub fn run(
+    bytes: &[u8],
+    granularity: u32,
+    step_limit: u64,
+) -> Result<(TestOutcome, String), String> {
+    let (begin, end) = region(&ElfFile::parse(bytes)?)?;
+    let (mut cpu, tohost) = riscv_tests::load(bytes, RAM_SIZE)?;
+
+    let outcome = riscv_tests::run_to_tohost(&mut cpu, tohost, step_limit);
+    let signature = dump(&mut cpu.bus, begin, end, granularity)?;
+
+    Ok((outcome, signature))
+}
+
+/// The `begin_signature..end_signature` range of a test image.
+pub fn region(elf: &ElfFile) -> Result<(u32, u32), String> {
+    let begin = elf
+        .symbol("begin_signature")
+        .ok_or_else(|| String::from("no `begin_signature` symbol"))?;
+    let end = elf
+        .symbol("end_signature")
+        .ok_or_else(|| String::from("no `end_signature` symbol"))?;
+
+    if end < begin {
+        return Err(format!(
+            "signature ends ({:#x}) before it begins ({:#x})",
+            end, begin
+        ));
+    }
+
+    Ok((begin, end))
+}
+
+/// Format `begin..end` as a signature file. `granularity` must be a
+/// non-zero multiple of 4.
+pub fn dump(bus: &mut Bus, begin: u32, end: u32, granularity: u32) -> Result<String, String> {
+    if granularity == 0 || !granularity.is_multiple_of(4) {
+        return Err(format!("unsupported signature granularity {}", granularity));
+    }
+
+    let mut out = String::new();
+    let mut addr = begin;
+
+    while addr < end {
+        // Words within a line are printed most significant first.
+        for word in (0..granularity / 4).rev() {
+            let word_addr = addr.wrapping_add(word * 4);
+            let value = bus
+                .read(word_addr, MemSize::Word)
+                .ok_or_else(|| format!("signature address {:#x} is not mapped", word_addr))?;
+            let _ = write!(out, "{:08x}", value);
+        }
+        out.push('\n');
+        addr = addr.wrapping_add(granularity);
+    }
+
+    Ok(out)
+}
@@ -0,0 +1,354 @@
+use std::fmt;
+#[cfg(feature = "std")]
+use std::fs;
+use std::ops::Range;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::RiscvCpu;
+use crate::bus::Bus;
+use crate::csr::{CsrFile, Privilege};
+use crate::xlen::Xlen;
+
+const MAGIC: &[u8; 8] = b"RVSNAP\0\x06";
+const CSR_COUNT: usize = 4096;
+
+/// Architectural state of a [`RiscvCpu`](crate::RiscvCpu): registers, PC,
+/// CSRs and RAM. Mapped devices keep their own state and aren't captured.
+/// Registers are zero-extended to 64 bits whatever the machine's XLEN.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// 32 or 64; a snapshot can only be restored on a machine of that width.
+    pub xlen: u32,
+    pub regs: [u64; 32],
+    pub fregs: [u64; 32],
+    /// The vector registers, VLEN/8 bytes each.
+    pub vregs: Vec<u8>,
+    pub pc: u64,
+    pub privilege: Privilege,
+    /// The H extension's V bit: whether the hart was running a guest.
+    pub virt: bool,
+    pub ram_base: u32,
+    pub ram: Vec<u8>,
+    csrs: Vec<u64>,
+}
+
+impl Snapshot {
+    pub(crate) fn new<X: Xlen>(cpu: &RiscvCpu<X>) -> Self {
+        Self {
+            xlen: X::BITS,
+            regs: cpu.regs.map(X::widen),
+            fregs: cpu.fregs,
+            vregs: cpu.vector.bytes().to_vec(),
+            pc: X::widen(cpu.pc),
+            privilege: cpu.privilege(),
+            virt: cpu.virtualized(),
+            ram_base: cpu.bus.ram_base(),
+            ram: cpu.bus.ram().to_vec(),
+            csrs: (0..CSR_COUNT as u16)
+                .map(|addr| cpu.csrs.read_u64(addr))
+                .collect(),
+        }
+    }
+
+    pub fn csr(&self, addr: u16) -> u64 {
+        self.csrs[(addr & 0xFFF) as usize]
+    }
+
+    pub(crate) fn restore_csrs<X: Xlen>(&self, csrs: &mut CsrFile<X>) {
+        for (addr, &value) in self.csrs.iter().enumerate() {
+            csrs.set_u64(addr as u16, value);
+        }
+    }
+
+    /// Serialize to a self-describing little-endian byte format. Only
+    /// non-zero CSRs are stored.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.ram.len() + 512);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&self.xlen.to_le_bytes());
+        out.push(self.privilege as u8);
+        out.push(self.virt as u8);
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        for reg in self.regs.iter().chain(&self.fregs) {
+            out.extend_from_slice(&reg.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.vregs.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.vregs);
+
+        let csrs: Vec<(usize, u64)> = self
+            .csrs
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, value)| value != 0)
+            .collect();
+        out.extend_from_slice(&(csrs.len() as u32).to_le_bytes());
+        for (addr, value) in csrs {
+            out.extend_from_slice(&(addr as u16).to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+
+        out.extend_from_slice(&self.ram_base.to_le_bytes());
+        out.extend_from_slice(&(self.ram.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.ram);
+
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(String::from("Snapshot: bad magic or unsupported version"));
+        }
+
+        let xlen = reader.u32()?;
+        if xlen != 32 && xlen != 64 {
+            return Err(format!("Snapshot: unsupported XLEN {}", xlen));
+        }
+
+        let privilege = reader.take(1)?[0];
+        let privilege = Privilege::from_bits(privilege as u32)
+            .filter(|p| *p as u8 == privilege)
+            .ok_or_else(|| format!("Snapshot: bad privilege level {}", privilege))?;
+        let virt = reader.take(1)?[0] != 0;
+
+        let pc = reader.u64()?;
+        let mut regs = [0; 32];
+        let mut fregs = [0; 32];
+        for reg in regs.iter_mut().chain(fregs.iter_mut()) {
+            *reg = reader.u64()?;
+        }
+        let vregs_len = reader.u32()? as usize;
+        let vregs = reader.take(vregs_len)?.to_vec();
+
+        let mut csrs = vec![0; CSR_COUNT];
+        for _ in 0..reader.u32()? {
+            let addr = reader.u16()? as usize;
+            let value = reader.u64()?;
+            *csrs
+                .get_mut(addr)
+                .ok_or_else(|| format!("Snapshot: CSR address {:#x} out of range", addr))? = value;
+        }
+
+        let ram_base = reader.u32()?;
+        let ram_len = reader.u32()? as usize;
+        let ram = reader.take(ram_len)?.to_vec();
+
+        Ok(Self {
+            xlen,
+            regs,
+            fregs,
+            vregs,
+            pc,
+            privilege,
+            virt,
+            ram_base,
+            ram,
+            csrs,
+        })
+    }
+
+    #[cfg(feature = "std")]
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+/// A copy of RAM alone, much cheaper than a [`Snapshot`] to take, for
+/// seeing what a stretch of execution wrote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemorySnapshot {
+    pub base: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// Bytes read at a time when diffing against live RAM, so sparse RAM isn't
+/// materialized in full.
+const DIFF_CHUNK: usize = 64 * 1024;
+
+impl MemorySnapshot {
+    pub(crate) fn new(bus: &Bus) -> Self {
+        Self {
+            base: bus.ram_base(),
+            bytes: bus.ram().to_vec(),
+        }
+    }
+
+    /// What changed between this snapshot and `later`.
+    ///
+    /// # Panics
+    ///
+    /// If they aren't of the same RAM.
+    pub fn diff(&self, later: &MemorySnapshot) -> MemoryDiff {
+        self.check(later.base, later.bytes.len());
+        let mut diff = MemoryDiff::default();
+        diff.compare(self.base as u64, &self.bytes, &later.bytes);
+        diff
+    }
+
+    /// What's changed in `bus`'s RAM since this snapshot was taken.
+    pub(crate) fn diff_live(&self, bus: &Bus) -> MemoryDiff {
+        self.check(bus.ram_base(), bus.len());
+        let mut diff = MemoryDiff::default();
+        let mut live = vec![0; DIFF_CHUNK];
+        for (i, before) in self.bytes.chunks(DIFF_CHUNK).enumerate() {
+            let offset = i * DIFF_CHUNK;
+            let live = &mut live[..before.len()];
+            bus.ram().read_bytes(offset, live);
+            diff.compare(self.base as u64 + offset as u64, before, live);
+        }
+        diff
+    }
+
+    fn check(&self, base: u32, len: usize) {
+        assert!(
+            base == self.base && len == self.bytes.len(),
+            "MemorySnapshot: can't diff {} bytes at {:#x} against {} bytes at {:#x}",
+            self.bytes.len(),
+            self.base,
+            len,
+            base
+        );
+    }
+}
+
+/// The runs of bytes that differ between two copies of memory, lowest
+/// address first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryDiff {
+    pub changes: Vec<MemoryChange>,
+}
+
+/// `before.len()` bytes at `addr`, each of which changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub addr: u64,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+impl MemoryDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// How many bytes changed.
+    pub fn len(&self) -> usize {
+        self.changes.iter().map(|change| change.before.len()).sum()
+    }
+
+    /// The changes outside `range`, such as writes that missed the buffer
+    /// a test expected them in. Runs straddling its ends are cut there.
+    pub fn outside(&self, range: Range<u64>) -> MemoryDiff {
+        let mut outside = MemoryDiff::default();
+        for change in &self.changes {
+            let end = change.addr + change.before.len() as u64;
+            for (start, end) in [
+                (change.addr, end.min(range.start)),
+                (change.addr.max(range.end), end),
+            ] {
+                if start < end {
+                    let (from, to) = ((start - change.addr) as usize, (end - change.addr) as usize);
+                    outside.changes.push(MemoryChange {
+                        addr: start,
+                        before: change.before[from..to].to_vec(),
+                        after: change.after[from..to].to_vec(),
+                    });
+                }
+            }
+        }
+        outside
+    }
+
+    /// Add the bytes that differ between `before` and `after`, both at
+    /// `addr`, extending the last run if they carry straight on from it.
+    fn compare(&mut self, addr: u64, before: &[u8], after: &[u8]) {
+        if before == after {
+            return;
+        }
+        for (i, (&old, &new)) in before.iter().zip(after).enumerate() {
+            if old == new {
+                continue;
+            }
+            let addr = addr + i as u64;
+            match self.changes.last_mut() {
+                Some(last) if last.addr + last.before.len() as u64 == addr => {
+                    last.before.push(old);
+                    last.after.push(new);
+                }
+                _ => self.changes.push(MemoryChange {
+                    addr,
+                    before: vec![old],
+                    after: vec![new],
+                }),
+            }
+        }
+    }
+}
+
+/// One line per run: `0x00000100: 00 00 -> 0a 00`.
+impl fmt::Display for MemoryDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        for (i, change) in self.changes.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(
+                f,
+                "{:#010x}: {} -> {}",
+                change.addr,
+                hex(&change.before),
+                hex(&change.after)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| format!("Snapshot: truncated at offset {:#x}", self.pos))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes(b.try_into().unwrap()))
+    }
+}
@@ -0,0 +1,123 @@
+//! A printable picture of a hart, for debuggers and crash reports.
+
+use std::fmt;
+
+use crate::RiscvCpu;
+use crate::csr::Privilege;
+use crate::decode::decode;
+use crate::xlen::Xlen;
+
+/// The ABI names of `x0` to `x31`.
+pub const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// How many instructions either side of the PC [`CpuState`] shows.
+const CONTEXT: u32 = 3;
+
+/// The registers, PC and privilege level of a hart, and the code around
+/// the PC. Its `Display` names registers by ABI name and disassembles the
+/// code, marking the instruction at the PC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuState {
+    pub xlen: u32,
+    pub regs: [u64; 32],
+    pub pc: u64,
+    pub privilege: Privilege,
+    /// Running a guest, in VS- or VU-mode.
+    pub virt: bool,
+    /// The function the PC is in, and how far into it, if symbols say.
+    pub symbol: Option<String>,
+    /// Each word around the PC, in order, or `None` where there's no RAM.
+    /// These are read from physical memory, so under paging they're only
+    /// the code at the PC if it's identity mapped.
+    pub code: Vec<(u32, Option<u32>)>,
+}
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// What the hart looks like right now, to print.
+    pub fn state(&self) -> CpuState {
+        let pc = self.pc_u32();
+        let first = pc.saturating_sub(4 * CONTEXT);
+        let code = (0..=2 * CONTEXT)
+            .map(|i| first.wrapping_add(4 * i))
+            .take_while(|&addr| addr >= first)
+            .map(|addr| (addr, self.peek_word(addr)))
+            .collect();
+
+        CpuState {
+            xlen: X::BITS,
+            regs: self.regs.map(X::widen),
+            pc: X::widen(self.pc),
+            privilege: self.privilege,
+            virt: self.virt,
+            symbol: self.symbols.describe(pc),
+            code,
+        }
+    }
+
+    fn peek_word(&self, addr: u32) -> Option<u32> {
+        if !self.bus.in_ram(addr, 4) {
+            return None;
+        }
+        let mut word = [0; 4];
+        let offset = (addr - self.bus.ram_base()) as usize;
+        self.bus.ram().read_bytes(offset, &mut word);
+        Some(u32::from_le_bytes(word))
+    }
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = 2 + self.xlen as usize / 4;
+
+        for (i, value) in self.regs.iter().enumerate() {
+            write!(f, "{:>4} {:#0width$x}", ABI_NAMES[i], value, width = width)?;
+            match (i + 1) % 4 {
+                0 => writeln!(f)?,
+                _ => write!(f, "  ")?,
+            }
+        }
+
+        let mode = match (self.privilege, self.virt) {
+            (Privilege::Machine, _) => "M",
+            (Privilege::Supervisor, false) => "S",
+            (Privilege::User, false) => "U",
+            (Privilege::Supervisor, true) => "VS",
+            (Privilege::User, true) => "VU",
+        };
+        write!(
+            f,
+            "{:>4} {:#0width$x} ({}-mode)",
+            "pc",
+            self.pc,
+            mode,
+            width = width
+        )?;
+        if let Some(symbol) = &self.symbol {
+            write!(f, " <{}>", symbol)?;
+        }
+        writeln!(f)?;
+
+        if !self.code.is_empty() {
+            writeln!(f)?;
+        }
+        for &(addr, raw) in &self.code {
+            let marker = if addr as u64 == self.pc { "=>" } else { "  " };
+            match raw {
+                Some(raw) => match decode(raw) {
+                    Ok(instruction) => {
+                        writeln!(f, "{} {:08x}:  {:08x}  {}", marker, addr, raw, instruction)?
+                    }
+                    Err(_) => {
+                        writeln!(f, "{} {:08x}:  {:08x}  .word {:#x}", marker, addr, raw, raw)?
+                    }
+                },
+                None => writeln!(f, "{} {:08x}:  ????????", marker, addr)?,
+            }
+        }
+        Ok(())
+    }
+}
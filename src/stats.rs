@@ -0,0 +1,179 @@
+//! Counts of what the guest executed, for teaching and for deciding which
+//! paths through the emulator are worth making fast.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use crate::decode::Instruction;
+
+/// What [`RiscvCpu::stats`](crate::RiscvCpu::stats) reports: the
+/// instructions executed and which way each conditional branch went. Nothing
+/// is counted until collection is turned on, and instructions that trap
+/// don't count.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    enabled: bool,
+    /// How often each instruction word retired, and what it decoded to.
+    executed: HashMap<u32, (Instruction, u64)>,
+    /// Conditional branches by address.
+    branches: BTreeMap<u32, BranchCounts>,
+}
+
+impl Stats {
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.executed.clear();
+        self.branches.clear();
+    }
+
+    pub(crate) fn record(&mut self, raw: u32, instruction: &Instruction) {
+        self.executed.entry(raw).or_insert((*instruction, 0)).1 += 1;
+    }
+
+    pub(crate) fn record_branch(&mut self, pc: u32, taken: bool) {
+        self.branches.entry(pc).or_default().add(taken as u64, 1);
+    }
+
+    /// Instructions retired while collecting.
+    pub fn instructions(&self) -> u64 {
+        self.executed.values().map(|&(_, count)| count).sum()
+    }
+
+    /// How often each mnemonic executed.
+    pub fn instruction_mix(&self) -> InstructionMix {
+        let mut counts: HashMap<String, u64> = HashMap::new();
+        for (instruction, count) in self.executed.values() {
+            *counts.entry(mnemonic(instruction)).or_default() += count;
+        }
+        let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        InstructionMix { counts }
+    }
+
+    /// Every conditional branch executed, added up.
+    pub fn branches(&self) -> BranchCounts {
+        let mut total = BranchCounts::default();
+        for counts in self.branches.values() {
+            total.add(counts.taken, counts.executed);
+        }
+        total
+    }
+
+    /// The branch at `pc`, if it has executed.
+    pub fn branch_site(&self, pc: u32) -> Option<BranchCounts> {
+        self.branches.get(&pc).copied()
+    }
+
+    /// Each branch that has executed, by address.
+    pub fn branch_sites(&self) -> impl Iterator<Item = (u32, BranchCounts)> + '_ {
+        self.branches.iter().map(|(&pc, &counts)| (pc, counts))
+    }
+
+    /// Add `other`'s counts to these, e.g. to combine the harts of a
+    /// machine.
+    pub fn merge(&mut self, other: &Stats) {
+        for (&raw, &(instruction, count)) in &other.executed {
+            self.executed.entry(raw).or_insert((instruction, 0)).1 += count;
+        }
+        for (&pc, counts) in &other.branches {
+            self.branches
+                .entry(pc)
+                .or_default()
+                .add(counts.taken, counts.executed);
+        }
+    }
+}
+
+/// How often a conditional branch, or all of them, executed and went which
+/// way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCounts {
+    pub executed: u64,
+    pub taken: u64,
+}
+
+impl BranchCounts {
+    fn add(&mut self, taken: u64, executed: u64) {
+        self.taken += taken;
+        self.executed += executed;
+    }
+
+    pub fn not_taken(&self) -> u64 {
+        self.executed - self.taken
+    }
+
+    /// The fraction of executions that were taken, from 0 to 1.
+    pub fn taken_rate(&self) -> f64 {
+        match self.executed {
+            0 => 0.0,
+            n => self.taken as f64 / n as f64,
+        }
+    }
+}
+
+/// The first word of the disassembly: `addi`, `fadd.s`, `vadd.vv`.
+pub(crate) fn mnemonic(instruction: &Instruction) -> String {
+    let text = instruction.to_string();
+    match text.split_once(' ') {
+        Some((mnemonic, _)) => mnemonic.to_string(),
+        None => text,
+    }
+}
+
+/// Execution counts by mnemonic, most executed first. Prints as a
+/// histogram.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InstructionMix {
+    counts: Vec<(String, u64)>,
+}
+
+/// How many `#`s the most executed mnemonic gets.
+const BAR_WIDTH: u64 = 40;
+
+impl InstructionMix {
+    pub fn count(&self, mnemonic: &str) -> u64 {
+        self.counts
+            .iter()
+            .find(|(name, _)| name == mnemonic)
+            .map_or(0, |&(_, count)| count)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().map(|&(_, count)| count).sum()
+    }
+
+    /// How many different mnemonics executed.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64)> + '_ {
+        self.counts
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count))
+    }
+}
+
+impl fmt::Display for InstructionMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self.total();
+        let most = self.counts.first().map_or(0, |&(_, count)| count);
+        for (name, count) in &self.counts {
+            let percent = *count as f64 * 100.0 / total as f64;
+            let bar = "#".repeat((count * BAR_WIDTH).div_ceil(most) as usize);
+            writeln!(f, "{:<12} {:>12} {:>6.2}%  {}", name, count, percent, bar)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1336 @@
+//! Linux system calls for programs started with
+//! [`RiscvCpu::load_process`](crate::RiscvCpu::load_process), serviced on
+//! the host the way `qemu-riscv32` does.
+//!
+//! A call is an ECALL with the number in `a7` and up to six arguments in
+//! `a0`-`a5`. The result comes back in `a0`, or a negated errno for a
+//! failure. Numbers are the generic ones RISC-V uses. Anything not
+//! implemented returns `-ENOSYS`, which libc copes with.
+//!
+//! Memory is the heap, which `brk` grows up from the end of the image, and
+//! anonymous mappings, which `mmap` hands out top-down from below the stack.
+//! Neither can grow into the other. There are no page protections, so
+//! `mprotect` succeeds without changing what the guest can touch.
+//!
+//! Files are reached through a table of descriptors, starting with the
+//! console on 0, 1 and 2. The guest sees the host filesystem only under a
+//! sandbox root: `/` is the root, `..` stops there and symlinks can't lead
+//! out of it. Without a root, opening anything fails with `EACCES`. Files
+//! need the `std` feature; without it there is only the console.
+//!
+//! The time calls read the bus's [`Clock`](crate::clock::Clock), the same
+//! guest time as `rdtime`, and sleeping lets that time pass. In virtual
+//! time, what a program sees of the clock is the same on every run.
+//!
+//! `clone` starts threads, which share everything but their registers. They
+//! take turns on the one hart, a quantum of instructions at a time, the way
+//! a single-core kernel would run them, so a multi-threaded program is as
+//! repeatable as any other. A thread blocked in `futex` or asleep gives up
+//! the rest of its turn, and once every thread is blocked, time skips ahead
+//! to the first timeout. If there isn't one, nothing can ever wake them and
+//! the run stops with [`ExitReason::Idle`](crate::ExitReason::Idle).
+
+use std::collections::BTreeMap;
+#[cfg(feature = "std")]
+use std::fs::{File, Metadata, OpenOptions};
+use std::io::{self, Read, Write};
+#[cfg(feature = "std")]
+use std::io::{Seek, SeekFrom};
+use std::ops::Range;
+#[cfg(feature = "std")]
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+#[cfg(feature = "std")]
+use std::time::UNIX_EPOCH;
+
+use crate::Reg;
+use crate::bus::Bus;
+use crate::user::PAGE_SIZE;
+
+pub const SYS_OPENAT: u64 = 56;
+pub const SYS_CLOSE: u64 = 57;
+/// `_llseek` on RV32, which takes the offset in two halves and writes the
+/// result to memory.
+pub const SYS_LSEEK: u64 = 62;
+pub const SYS_READ: u64 = 63;
+pub const SYS_WRITE: u64 = 64;
+pub const SYS_READV: u64 = 65;
+pub const SYS_WRITEV: u64 = 66;
+pub const SYS_FSTAT: u64 = 80;
+pub const SYS_EXIT: u64 = 93;
+pub const SYS_EXIT_GROUP: u64 = 94;
+pub const SYS_SET_TID_ADDRESS: u64 = 96;
+/// `futex` on RV64. RV32 only has `futex_time64`.
+pub const SYS_FUTEX: u64 = 98;
+pub const SYS_NANOSLEEP: u64 = 101;
+pub const SYS_CLOCK_GETTIME: u64 = 113;
+pub const SYS_CLOCK_GETRES: u64 = 114;
+pub const SYS_CLOCK_NANOSLEEP: u64 = 115;
+pub const SYS_SCHED_YIELD: u64 = 124;
+pub const SYS_GETTIMEOFDAY: u64 = 169;
+pub const SYS_GETPID: u64 = 172;
+pub const SYS_GETTID: u64 = 178;
+pub const SYS_BRK: u64 = 214;
+pub const SYS_MUNMAP: u64 = 215;
+/// Takes the flags, stack, parent TID pointer, TLS and child TID pointer,
+/// in the order the RISC-V libcs pass them.
+pub const SYS_CLONE: u64 = 220;
+/// `mmap2` on RV32, which counts the offset in pages, but anonymous
+/// mappings ignore it.
+pub const SYS_MMAP: u64 = 222;
+pub const SYS_MPROTECT: u64 = 226;
+/// What RV32 libcs use for `stat` and `fstat`, having no `fstat` of their
+/// own.
+pub const SYS_STATX: u64 = 291;
+/// The time calls RV32 uses, with 64-bit seconds whatever the XLEN.
+pub const SYS_CLOCK_GETTIME64: u64 = 403;
+pub const SYS_CLOCK_GETRES_TIME64: u64 = 406;
+pub const SYS_CLOCK_NANOSLEEP_TIME64: u64 = 407;
+pub const SYS_FUTEX_TIME64: u64 = 422;
+
+pub const CLOCK_REALTIME: u64 = 0;
+pub const CLOCK_MONOTONIC: u64 = 1;
+pub const CLOCK_PROCESS_CPUTIME_ID: u64 = 2;
+pub const CLOCK_THREAD_CPUTIME_ID: u64 = 3;
+pub const CLOCK_MONOTONIC_RAW: u64 = 4;
+pub const CLOCK_REALTIME_COARSE: u64 = 5;
+pub const CLOCK_MONOTONIC_COARSE: u64 = 6;
+pub const CLOCK_BOOTTIME: u64 = 7;
+pub const TIMER_ABSTIME: u64 = 1;
+
+pub const CLONE_VM: u64 = 0x100;
+pub const CLONE_FS: u64 = 0x200;
+pub const CLONE_FILES: u64 = 0x400;
+pub const CLONE_SIGHAND: u64 = 0x800;
+pub const CLONE_THREAD: u64 = 0x10000;
+pub const CLONE_SYSVSEM: u64 = 0x40000;
+pub const CLONE_SETTLS: u64 = 0x80000;
+pub const CLONE_PARENT_SETTID: u64 = 0x10_0000;
+pub const CLONE_CHILD_CLEARTID: u64 = 0x20_0000;
+pub const CLONE_DETACHED: u64 = 0x40_0000;
+pub const CLONE_CHILD_SETTID: u64 = 0x100_0000;
+
+pub const FUTEX_WAIT: u64 = 0;
+pub const FUTEX_WAKE: u64 = 1;
+pub const FUTEX_REQUEUE: u64 = 3;
+pub const FUTEX_CMP_REQUEUE: u64 = 4;
+pub const FUTEX_WAIT_BITSET: u64 = 9;
+pub const FUTEX_WAKE_BITSET: u64 = 10;
+/// Ignored: every futex is private to the one process.
+pub const FUTEX_PRIVATE_FLAG: u64 = 128;
+/// Makes `FUTEX_WAIT_BITSET`'s deadline a `CLOCK_REALTIME` one.
+pub const FUTEX_CLOCK_REALTIME: u64 = 256;
+pub const FUTEX_BITSET_MATCH_ANY: u32 = u32::MAX;
+
+pub const AT_FDCWD: i32 = -100;
+pub const AT_EMPTY_PATH: u64 = 0x1000;
+
+pub const O_ACCMODE: u64 = 0o3;
+pub const O_RDONLY: u64 = 0o0;
+pub const O_WRONLY: u64 = 0o1;
+pub const O_RDWR: u64 = 0o2;
+pub const O_CREAT: u64 = 0o100;
+pub const O_EXCL: u64 = 0o200;
+pub const O_TRUNC: u64 = 0o1000;
+pub const O_APPEND: u64 = 0o2000;
+pub const O_DIRECTORY: u64 = 0o200000;
+
+pub const SEEK_SET: u64 = 0;
+pub const SEEK_CUR: u64 = 1;
+pub const SEEK_END: u64 = 2;
+
+pub const MAP_SHARED: u64 = 0x01;
+pub const MAP_PRIVATE: u64 = 0x02;
+pub const MAP_FIXED: u64 = 0x10;
+pub const MAP_ANONYMOUS: u64 = 0x20;
+pub const MAP_FIXED_NOREPLACE: u64 = 0x10_0000;
+
+pub const ENOENT: i64 = 2;
+pub const ESRCH: i64 = 3;
+pub const EIO: i64 = 5;
+pub const EBADF: i64 = 9;
+pub const EAGAIN: i64 = 11;
+pub const ENOMEM: i64 = 12;
+pub const EACCES: i64 = 13;
+pub const EFAULT: i64 = 14;
+pub const EEXIST: i64 = 17;
+pub const ENODEV: i64 = 19;
+pub const ENOTDIR: i64 = 20;
+pub const EISDIR: i64 = 21;
+pub const EINVAL: i64 = 22;
+pub const EMFILE: i64 = 24;
+pub const ESPIPE: i64 = 29;
+pub const ENAMETOOLONG: i64 = 36;
+pub const ENOSYS: i64 = 38;
+pub const ETIMEDOUT: i64 = 110;
+
+/// The most descriptors open at once.
+#[cfg(feature = "std")]
+const MAX_FDS: usize = 1024;
+/// The most threads alive at once.
+const MAX_THREADS: usize = 1024;
+/// The TID of the first thread, which is also the process ID.
+const PID: u32 = 1;
+/// Instructions a thread runs before the next one gets a turn, by default.
+const DEFAULT_QUANTUM: u64 = 10_000;
+/// The longest path `openat` accepts, NUL included.
+#[cfg(feature = "std")]
+const PATH_MAX: u64 = 4096;
+/// The most one `read` asks the host for.
+const MAX_READ: u64 = 1 << 20;
+
+const S_IFCHR: u32 = 0o020000;
+#[cfg(feature = "std")]
+const S_IFDIR: u32 = 0o040000;
+#[cfg(feature = "std")]
+const S_IFREG: u32 = 0o100000;
+#[cfg(feature = "std")]
+const STATX_BASIC_STATS: u32 = 0x7ff;
+
+/// What the CPU should do after a system call.
+pub(crate) enum Outcome {
+    /// Write this to `a0` and carry on.
+    Return(i64),
+    /// Write this to `a0`, then [`switch`](Syscalls::switch) threads: the
+    /// caller is blocked, has exited or is yielding.
+    Switch(i64),
+    /// Hand a copy of the caller's registers to [`Syscalls::spawn`] for a
+    /// new thread, and return its TID.
+    Clone(NewThread),
+    Exit(i32),
+}
+
+/// A thread `clone` has set up everything for but the registers.
+pub(crate) struct NewThread {
+    tid: u32,
+    stack: u64,
+    tls: Option<u64>,
+}
+
+impl NewThread {
+    pub(crate) fn tid(&self) -> u32 {
+        self.tid
+    }
+}
+
+/// A thread's registers while it isn't running. Vector state isn't
+/// switched, so only one thread should use the V extension.
+#[derive(Clone)]
+pub(crate) struct Context {
+    pub regs: [u64; 32],
+    pub fregs: [u64; 32],
+    pub fcsr: u64,
+    pub pc: u64,
+}
+
+/// A system call's result, or the errno it failed with.
+type SysResult = Result<u64, i64>;
+
+/// The host side of a user-mode program: its heap, memory mappings and
+/// open files.
+pub struct Syscalls {
+    rv64: bool,
+    heap_start: u32,
+    brk: u32,
+    /// Where `mmap` starts looking for room, going down.
+    mmap_top: u32,
+    /// Start and end of each anonymous mapping.
+    mappings: BTreeMap<u32, u32>,
+    /// Indexed by descriptor. Closing one leaves a hole for the next open.
+    fds: Vec<Option<Fd>>,
+    #[cfg(feature = "std")]
+    sandbox: Option<PathBuf>,
+    stdin: Box<dyn Read>,
+    stdout: Box<dyn Write>,
+    /// `None` once it's been sent to wherever stdout goes.
+    stderr: Option<Box<dyn Write>>,
+    /// In the order they take turns.
+    threads: Vec<Thread>,
+    /// Index of the thread on the hart.
+    current: usize,
+    next_tid: u32,
+    quantum: u64,
+    /// What's left of the current thread's turn.
+    slice: u64,
+    /// Counts futex waits, so waiters wake in the order they came.
+    waits: u64,
+}
+
+struct Thread {
+    tid: u32,
+    /// Saved while the thread is off the hart.
+    context: Option<Context>,
+    state: State,
+    /// Cleared and woken on exit, from `set_tid_address` or
+    /// `CLONE_CHILD_CLEARTID`.
+    clear_tid: u64,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Ready,
+    Blocked(Block),
+    Exited,
+}
+
+/// What a thread is waiting for: a futex wake, the deadline, or either.
+#[derive(Clone, Copy, PartialEq)]
+struct Block {
+    futex: Option<Waiter>,
+    /// The bus time it gives up at.
+    deadline: Option<u64>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Waiter {
+    addr: u32,
+    bitset: u32,
+    /// When it started waiting, from [`Syscalls::waits`].
+    order: u64,
+}
+
+enum Fd {
+    Stdin,
+    Stdout,
+    Stderr,
+    #[cfg(feature = "std")]
+    File(OpenFile),
+}
+
+#[cfg(feature = "std")]
+struct OpenFile {
+    file: File,
+    /// Where the guest thinks it is, for resolving paths relative to it.
+    path: PathBuf,
+    read: bool,
+    write: bool,
+}
+
+impl Syscalls {
+    /// The heap starts empty at `heap_start`, and mappings go below
+    /// `mmap_top`. Both should be page-aligned. The console is the host's
+    /// stdin, stdout and stderr, and there are no files until
+    /// [`sandbox`](Self::sandbox).
+    pub(crate) fn new(heap_start: u32, mmap_top: u32, rv64: bool) -> Self {
+        Self {
+            rv64,
+            heap_start,
+            brk: heap_start,
+            mmap_top,
+            mappings: BTreeMap::new(),
+            fds: vec![Some(Fd::Stdin), Some(Fd::Stdout), Some(Fd::Stderr)],
+            #[cfg(feature = "std")]
+            sandbox: None,
+            stdin: Box::new(io::stdin()),
+            stdout: Box::new(io::stdout()),
+            stderr: Some(Box::new(io::stderr())),
+            threads: vec![Thread {
+                tid: PID,
+                context: None,
+                state: State::Ready,
+                clear_tid: 0,
+            }],
+            current: 0,
+            next_tid: PID + 1,
+            quantum: DEFAULT_QUANTUM,
+            slice: DEFAULT_QUANTUM,
+            waits: 0,
+        }
+    }
+
+    /// Let the guest open files under `root`, which it sees as `/`.
+    #[cfg(feature = "std")]
+    pub(crate) fn sandbox(mut self, root: Option<PathBuf>) -> Self {
+        self.sandbox = root;
+        self
+    }
+
+    /// Where reads from descriptor 0 come from.
+    pub fn set_stdin(&mut self, input: Box<dyn Read>) {
+        self.stdin = input;
+    }
+
+    /// Send what's written to descriptors 1 and 2 to `output` from now on,
+    /// interleaved as it was written.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.stdout = output;
+        self.stderr = None;
+    }
+
+    /// The program break: where the heap currently ends.
+    pub fn brk(&self) -> u32 {
+        self.brk
+    }
+
+    pub fn heap(&self) -> Range<u32> {
+        self.heap_start..self.brk
+    }
+
+    /// The anonymous mappings in address order. Ones made by separate
+    /// calls are listed separately, even if they touch.
+    pub fn mappings(&self) -> impl Iterator<Item = Range<u32>> + '_ {
+        self.mappings.iter().map(|(&start, &end)| start..end)
+    }
+
+    /// The TIDs of the threads that haven't exited, in the order they take
+    /// turns. The first thread's is the process ID.
+    pub fn threads(&self) -> impl Iterator<Item = u32> + '_ {
+        self.threads
+            .iter()
+            .filter(|thread| thread.state != State::Exited)
+            .map(|thread| thread.tid)
+    }
+
+    /// The TID of the thread on the hart.
+    pub fn current_thread(&self) -> u32 {
+        self.threads[self.current].tid
+    }
+
+    pub fn quantum(&self) -> u64 {
+        self.quantum
+    }
+
+    /// How many instructions a thread runs before the next one gets a
+    /// turn. A quantum of 1 interleaves them instruction by instruction.
+    pub fn set_quantum(&mut self, instructions: u64) {
+        assert!(
+            instructions > 0,
+            "the quantum must be at least one instruction"
+        );
+        self.quantum = instructions;
+        self.slice = self.slice.min(instructions);
+    }
+
+    pub(crate) fn call(&mut self, nr: u64, args: [u64; 6], bus: &mut Bus) -> Outcome {
+        let result = match nr {
+            SYS_EXIT => return self.exit_thread(args[0] as u8 as i32, bus),
+            SYS_EXIT_GROUP => return Outcome::Exit(args[0] as u8 as i32),
+            SYS_CLONE => {
+                return match self.clone(args[0], args[1], args[2], args[3], args[4], bus) {
+                    Ok(thread) => Outcome::Clone(thread),
+                    Err(errno) => Outcome::Return(-errno),
+                };
+            }
+            SYS_SCHED_YIELD => return Outcome::Switch(0),
+            SYS_GETPID => Ok(PID as u64),
+            SYS_GETTID => Ok(self.current_thread() as u64),
+            SYS_SET_TID_ADDRESS => {
+                self.threads[self.current].clear_tid = args[0];
+                Ok(self.current_thread() as u64)
+            }
+            SYS_FUTEX => self.futex(args, self.word(), bus),
+            SYS_FUTEX_TIME64 => self.futex(args, 8, bus),
+            #[cfg(feature = "std")]
+            SYS_OPENAT => self.openat(args[0] as i32, args[1], args[2], bus),
+            #[cfg(not(feature = "std"))]
+            SYS_OPENAT => Err(EACCES),
+            SYS_CLOSE => self.close(args[0] as i32),
+            SYS_READ => self.read(args[0] as i32, args[1], args[2], bus),
+            SYS_WRITE => self.write(args[0] as i32, args[1], args[2], bus),
+            SYS_READV => self.vectored(args[0] as i32, args[1], args[2], bus, Self::read),
+            SYS_WRITEV => self.vectored(args[0] as i32, args[1], args[2], bus, Self::write),
+            #[cfg(feature = "std")]
+            SYS_LSEEK if self.rv64 => self.lseek(args[0] as i32, args[1] as i64, args[2]),
+            #[cfg(feature = "std")]
+            SYS_LSEEK => self.llseek(args[0] as i32, args[1], args[2], args[3], args[4], bus),
+            SYS_FSTAT => self.fstat(args[0] as i32, args[1], bus),
+            #[cfg(feature = "std")]
+            SYS_STATX => self.statx(args[0] as i32, args[1], args[2], args[4], bus),
+            SYS_CLOCK_GETTIME => clock_gettime(args[0], args[1], self.word(), bus),
+            SYS_CLOCK_GETTIME64 => clock_gettime(args[0], args[1], 8, bus),
+            SYS_CLOCK_GETRES => clock_getres(args[0], args[1], self.word(), bus),
+            SYS_CLOCK_GETRES_TIME64 => clock_getres(args[0], args[1], 8, bus),
+            SYS_GETTIMEOFDAY => gettimeofday(args[0], self.word(), bus),
+            SYS_NANOSLEEP => self.sleep(CLOCK_MONOTONIC, 0, args[0], self.word(), bus),
+            SYS_CLOCK_NANOSLEEP => self.sleep(args[0], args[1], args[2], self.word(), bus),
+            SYS_CLOCK_NANOSLEEP_TIME64 => self.sleep(args[0], args[1], args[2], 8, bus),
+            SYS_BRK => Ok(self.set_brk(args[0], bus) as u64),
+            SYS_MMAP => self.mmap(args[0], args[1], args[3], bus),
+            SYS_MUNMAP => self.munmap(args[0], args[1]),
+            SYS_MPROTECT => range(args[0], args[1]).map(|_| 0),
+            _ => Err(ENOSYS),
+        };
+
+        let value = match result {
+            Ok(value) => value as i64,
+            Err(errno) => -errno,
+        };
+        match self.threads[self.current].state {
+            State::Ready => Outcome::Return(value),
+            _ => Outcome::Switch(value),
+        }
+    }
+
+    /// Whether the current thread's turn is over after `executed` more
+    /// instructions, and another should be switched to.
+    pub(crate) fn preempt(&mut self, executed: u64) -> bool {
+        self.slice = self.slice.saturating_sub(executed);
+        self.slice == 0 && self.threads.len() > 1
+    }
+
+    /// Put the current thread's registers away as `saved`, unless it has
+    /// exited, and take the next thread's out. Threads whose deadline has
+    /// passed wake first, and if none is ready, time skips to the first
+    /// deadline. `None` means every thread is blocked for good and the
+    /// current one stays on the hart.
+    pub(crate) fn switch(&mut self, saved: Context, bus: &mut Bus) -> Option<Context> {
+        self.threads[self.current].context = Some(saved);
+        self.wake_expired(bus.time());
+
+        if self.next_ready().is_none() {
+            let deadline = self
+                .threads
+                .iter()
+                .filter_map(|thread| match thread.state {
+                    State::Blocked(block) => block.deadline,
+                    _ => None,
+                })
+                .min()?;
+            bus.skip(deadline.saturating_sub(bus.time()));
+            self.wake_expired(bus.time());
+        }
+
+        let next = self.next_ready()?;
+        let tid = self.threads[next].tid;
+        self.threads.retain(|thread| thread.state != State::Exited);
+        self.current = self.threads.iter().position(|t| t.tid == tid)?;
+        self.slice = self.quantum;
+        self.threads[self.current].context.take()
+    }
+
+    /// Fill in a thread `clone` set up: it starts where the caller returns
+    /// to, with a copy of its registers, but `a0` is 0 and it may have its
+    /// own stack and thread pointer.
+    pub(crate) fn spawn(&mut self, thread: NewThread, mut context: Context) {
+        context.regs[Reg::A0.index() as usize] = 0;
+        if thread.stack != 0 {
+            context.regs[Reg::Sp.index() as usize] = thread.stack;
+        }
+        if let Some(tls) = thread.tls {
+            context.regs[Reg::Tp.index() as usize] = tls;
+        }
+        if let Some(new) = self.threads.iter_mut().find(|t| t.tid == thread.tid) {
+            new.context = Some(context);
+        }
+    }
+
+    /// Only threads sharing everything are supported, not new processes.
+    fn clone(
+        &mut self,
+        flags: u64,
+        stack: u64,
+        parent_tid: u64,
+        tls: u64,
+        child_tid: u64,
+        bus: &mut Bus,
+    ) -> Result<NewThread, i64> {
+        if flags & (CLONE_VM | CLONE_THREAD | CLONE_SIGHAND)
+            != CLONE_VM | CLONE_THREAD | CLONE_SIGHAND
+        {
+            return Err(ENOSYS);
+        }
+        if self.threads.len() >= MAX_THREADS {
+            return Err(EAGAIN);
+        }
+
+        let tid = self.next_tid;
+        if flags & CLONE_PARENT_SETTID != 0 {
+            write_guest(bus, parent_tid, &tid.to_le_bytes())?;
+        }
+        if flags & CLONE_CHILD_SETTID != 0 {
+            write_guest(bus, child_tid, &tid.to_le_bytes())?;
+        }
+        self.next_tid += 1;
+        self.threads.push(Thread {
+            tid,
+            context: None,
+            state: State::Ready,
+            clear_tid: if flags & CLONE_CHILD_CLEARTID != 0 {
+                child_tid
+            } else {
+                0
+            },
+        });
+        Ok(NewThread {
+            tid,
+            stack,
+            tls: (flags & CLONE_SETTLS != 0).then_some(tls),
+        })
+    }
+
+    /// `exit`, which ends only the calling thread, unless it's the last.
+    /// Its `clear_tid` word is zeroed and woken, which is how `pthread_join`
+    /// finds out.
+    fn exit_thread(&mut self, status: i32, bus: &mut Bus) -> Outcome {
+        if self.threads().count() == 1 {
+            return Outcome::Exit(status);
+        }
+        let clear_tid = self.threads[self.current].clear_tid;
+        if clear_tid != 0 && write_guest(bus, clear_tid, &[0; 4]).is_ok() {
+            self.wake(clear_tid as u32, FUTEX_BITSET_MATCH_ANY, 1);
+        }
+        self.threads[self.current].state = State::Exited;
+        Outcome::Switch(0)
+    }
+
+    /// `futex` and `futex_time64`, with their timeouts' fields `width`
+    /// bytes wide.
+    fn futex(&mut self, args: [u64; 6], width: usize, bus: &mut Bus) -> SysResult {
+        let [addr, op, val, timeout, addr2, val3] = args;
+        if !addr.is_multiple_of(4) {
+            return Err(EINVAL);
+        }
+        let addr = u32::try_from(addr).map_err(|_| EFAULT)?;
+        let bitset = match op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME) {
+            FUTEX_WAIT_BITSET | FUTEX_WAKE_BITSET if val3 == 0 => return Err(EINVAL),
+            FUTEX_WAIT_BITSET | FUTEX_WAKE_BITSET => val3 as u32,
+            _ => FUTEX_BITSET_MATCH_ANY,
+        };
+
+        match op & !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME) {
+            cmd @ (FUTEX_WAIT | FUTEX_WAIT_BITSET) => {
+                let deadline = match timeout {
+                    0 => None,
+                    _ => {
+                        let timeout = read_timespec(bus, timeout, width)?;
+                        // The bitset variant's is a deadline on one clock
+                        // or the other.
+                        let timeout = match cmd {
+                            FUTEX_WAIT => timeout,
+                            _ if op & FUTEX_CLOCK_REALTIME != 0 => {
+                                timeout.saturating_sub(clock_time(CLOCK_REALTIME, bus)?)
+                            }
+                            _ => timeout.saturating_sub(clock_time(CLOCK_MONOTONIC, bus)?),
+                        };
+                        Some(bus.time() + ticks_for(bus, timeout))
+                    }
+                };
+                if read_word(bus, addr as u64, 4)? as u32 != val as u32 {
+                    return Err(EAGAIN);
+                }
+
+                self.waits += 1;
+                let futex = Waiter {
+                    addr,
+                    bitset,
+                    order: self.waits,
+                };
+                self.threads[self.current].state = State::Blocked(Block {
+                    futex: Some(futex),
+                    deadline,
+                });
+                Ok(0)
+            }
+            FUTEX_WAKE | FUTEX_WAKE_BITSET => Ok(self.wake(addr, bitset, val as u32)),
+            cmd @ (FUTEX_REQUEUE | FUTEX_CMP_REQUEUE) => {
+                if cmd == FUTEX_CMP_REQUEUE && read_word(bus, addr as u64, 4)? as u32 != val3 as u32
+                {
+                    return Err(EAGAIN);
+                }
+                let addr2 = u32::try_from(addr2).map_err(|_| EFAULT)?;
+                let woken = self.wake(addr, bitset, val as u32);
+                // The timeout argument is the most to move to `addr2`.
+                let moved = self.waiters(addr, bitset);
+                let moved = &moved[..moved.len().min(timeout as u32 as usize)];
+                for &i in moved {
+                    if let State::Blocked(Block {
+                        futex: Some(futex), ..
+                    }) = &mut self.threads[i].state
+                    {
+                        futex.addr = addr2;
+                    }
+                }
+                Ok(woken + moved.len() as u64)
+            }
+            _ => Err(ENOSYS),
+        }
+    }
+
+    /// Wake up to `count` threads waiting on `addr` with a bit of `bitset`,
+    /// oldest first, returning how many.
+    fn wake(&mut self, addr: u32, bitset: u32, count: u32) -> u64 {
+        let waiters = self.waiters(addr, bitset);
+        let woken = waiters.len().min(count as usize);
+        for &i in &waiters[..woken] {
+            self.resume(i, 0);
+        }
+        woken as u64
+    }
+
+    /// The threads waiting on `addr` with a bit of `bitset`, oldest first.
+    fn waiters(&self, addr: u32, bitset: u32) -> Vec<usize> {
+        let mut waiters: Vec<(u64, usize)> = self
+            .threads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, thread)| match thread.state {
+                State::Blocked(Block {
+                    futex: Some(futex), ..
+                }) if futex.addr == addr && futex.bitset & bitset != 0 => Some((futex.order, i)),
+                _ => None,
+            })
+            .collect();
+        waiters.sort_unstable();
+        waiters.into_iter().map(|(_, i)| i).collect()
+    }
+
+    /// Make thread `i` ready, with `result` as what its system call
+    /// returned.
+    fn resume(&mut self, i: usize, result: i64) {
+        let thread = &mut self.threads[i];
+        thread.state = State::Ready;
+        if let Some(context) = &mut thread.context {
+            context.regs[Reg::A0.index() as usize] = result as u64;
+        }
+    }
+
+    /// Wake the threads whose deadline is `now` or earlier: a futex wait
+    /// times out, a sleep finishes.
+    fn wake_expired(&mut self, now: u64) {
+        for i in 0..self.threads.len() {
+            if let State::Blocked(block) = self.threads[i].state
+                && block.deadline.is_some_and(|deadline| deadline <= now)
+            {
+                let result = match block.futex {
+                    Some(_) => -ETIMEDOUT,
+                    None => 0,
+                };
+                self.resume(i, result);
+            }
+        }
+    }
+
+    /// The first ready thread after the current one, coming back round to
+    /// it last.
+    fn next_ready(&self) -> Option<usize> {
+        let count = self.threads.len();
+        (1..=count)
+            .map(|offset| (self.current + offset) % count)
+            .find(|&i| self.threads[i].state == State::Ready)
+    }
+
+    /// `clock_nanosleep`, and `nanosleep` on the monotonic clock: block the
+    /// thread until the requested time has passed on the bus, rounded up to
+    /// a whole tick. Nothing interrupts it, so the remaining time is never
+    /// written.
+    fn sleep(
+        &mut self,
+        clock: u64,
+        flags: u64,
+        request: u64,
+        width: usize,
+        bus: &mut Bus,
+    ) -> SysResult {
+        let now = clock_time(clock, bus)?;
+        let request = read_timespec(bus, request, width)?;
+        let duration = if flags & TIMER_ABSTIME != 0 {
+            request.saturating_sub(now)
+        } else {
+            request
+        };
+
+        self.threads[self.current].state = State::Blocked(Block {
+            futex: None,
+            deadline: Some(bus.time() + ticks_for(bus, duration)),
+        });
+        Ok(0)
+    }
+
+    /// Move the break to `addr` if there's room, zeroing anything the heap
+    /// grows into. Like the kernel's, it returns the break either way, so a
+    /// failure shows up as the old one.
+    fn set_brk(&mut self, addr: u64, bus: &mut Bus) -> u32 {
+        let Ok(addr) = u32::try_from(addr) else {
+            return self.brk;
+        };
+        let limit = self
+            .mappings
+            .range(self.brk..)
+            .next()
+            .map_or(self.mmap_top, |(&start, _)| start);
+        if addr < self.heap_start || addr > limit {
+            return self.brk;
+        }
+
+        if addr > self.brk {
+            let zeros = vec![0; (addr - self.brk) as usize];
+            if bus.dma().write(self.brk as u64, &zeros).is_none() {
+                return self.brk;
+            }
+        }
+        self.brk = addr;
+        self.brk
+    }
+
+    fn mmap(&mut self, addr: u64, len: u64, flags: u64, bus: &mut Bus) -> SysResult {
+        if flags & MAP_ANONYMOUS == 0 {
+            return Err(ENODEV);
+        }
+        if len == 0 || flags & (MAP_SHARED | MAP_PRIVATE) == 0 {
+            return Err(EINVAL);
+        }
+        let len = u32::try_from(page_align(len)).map_err(|_| ENOMEM)?;
+
+        let start = if flags & (MAP_FIXED | MAP_FIXED_NOREPLACE) != 0 {
+            let Range { start, end } = range(addr, len as u64)?;
+            if self.overlaps(start, end) {
+                if flags & MAP_FIXED_NOREPLACE != 0 {
+                    return Err(EEXIST);
+                }
+                self.unmap(start, end);
+            }
+            start
+        } else {
+            match range(addr, len as u64) {
+                // A hint is taken if that's free.
+                Ok(hint)
+                    if hint.start >= self.brk
+                        && hint.end <= self.mmap_top
+                        && !self.overlaps(hint.start, hint.end) =>
+                {
+                    hint.start
+                }
+                _ => self.find_gap(len).ok_or(ENOMEM)?,
+            }
+        };
+
+        // Anonymous memory starts out zeroed.
+        bus.dma()
+            .write(start as u64, &vec![0; len as usize])
+            .ok_or(ENOMEM)?;
+        self.mappings.insert(start, start + len);
+        Ok(start as u64)
+    }
+
+    fn munmap(&mut self, addr: u64, len: u64) -> SysResult {
+        let Range { start, end } = range(addr, len)?;
+        self.unmap(start, end);
+        Ok(0)
+    }
+
+    #[cfg(feature = "std")]
+    fn openat(&mut self, dir: i32, path: u64, flags: u64, bus: &mut Bus) -> SysResult {
+        let path = read_path(bus, path)?;
+        let guest = self.guest_path(dir, &path)?;
+        let host = self.host_path(&guest)?;
+
+        let (read, write) = match flags & O_ACCMODE {
+            O_RDONLY => (true, false),
+            O_WRONLY => (false, true),
+            O_RDWR => (true, true),
+            _ => return Err(EINVAL),
+        };
+        let mut options = OpenOptions::new();
+        options
+            .read(read)
+            .write(write)
+            .append(flags & O_APPEND != 0)
+            .truncate(flags & O_TRUNC != 0);
+        if flags & O_CREAT != 0 {
+            if flags & O_EXCL != 0 {
+                options.create_new(true);
+            } else {
+                options.create(true);
+            }
+        }
+
+        let file = options.open(host).map_err(errno)?;
+        if flags & O_DIRECTORY != 0 && !file.metadata().map_err(errno)?.is_dir() {
+            return Err(ENOTDIR);
+        }
+        self.insert(Fd::File(OpenFile {
+            file,
+            path: guest,
+            read,
+            write,
+        }))
+    }
+
+    /// Put `fd` in the lowest free slot, as POSIX wants.
+    #[cfg(feature = "std")]
+    fn insert(&mut self, fd: Fd) -> SysResult {
+        let slot = match self.fds.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None if self.fds.len() < MAX_FDS => {
+                self.fds.push(None);
+                self.fds.len() - 1
+            }
+            None => return Err(EMFILE),
+        };
+        self.fds[slot] = Some(fd);
+        Ok(slot as u64)
+    }
+
+    fn close(&mut self, fd: i32) -> SysResult {
+        usize::try_from(fd)
+            .ok()
+            .and_then(|fd| self.fds.get_mut(fd))
+            .and_then(Option::take)
+            .ok_or(EBADF)?;
+        Ok(0)
+    }
+
+    fn read(&mut self, fd: i32, buf: u64, len: u64, bus: &mut Bus) -> SysResult {
+        let len = len.min(MAX_READ);
+        if !in_ram(bus, buf, len) {
+            return Err(EFAULT);
+        }
+
+        let mut bytes = vec![0; len as usize];
+        let n = match entry(&mut self.fds, fd)? {
+            Fd::Stdin => self.stdin.read(&mut bytes),
+            #[cfg(feature = "std")]
+            Fd::File(open) if open.read => open.file.read(&mut bytes),
+            _ => return Err(EBADF),
+        }
+        .map_err(errno)?;
+        write_guest(bus, buf, &bytes[..n])?;
+        Ok(n as u64)
+    }
+
+    fn write(&mut self, fd: i32, buf: u64, len: u64, bus: &mut Bus) -> SysResult {
+        let bytes = read_guest(bus, buf, len)?;
+        match entry(&mut self.fds, fd)? {
+            Fd::Stdout => console(&mut self.stdout, &bytes),
+            Fd::Stderr => console(self.stderr.as_mut().unwrap_or(&mut self.stdout), &bytes),
+            #[cfg(feature = "std")]
+            Fd::File(open) if open.write => open.file.write_all(&bytes),
+            _ => return Err(EBADF),
+        }
+        .map_err(errno)?;
+        Ok(len)
+    }
+
+    /// `readv` or `writev`: `op` on each buffer in the `iovec` array in
+    /// turn, stopping after a short one.
+    fn vectored(
+        &mut self,
+        fd: i32,
+        iov: u64,
+        count: u64,
+        bus: &mut Bus,
+        op: fn(&mut Self, i32, u64, u64, &mut Bus) -> SysResult,
+    ) -> SysResult {
+        if count > 1024 {
+            return Err(EINVAL);
+        }
+        let width = self.word() as u64;
+        let mut total = 0;
+        for i in 0..count {
+            let entry = iov + i * 2 * width;
+            let base = read_word(bus, entry, width)?;
+            let len = read_word(bus, entry + width, width)?;
+            let n = op(self, fd, base, len, bus)?;
+            total += n;
+            if n < len {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    #[cfg(feature = "std")]
+    fn lseek(&mut self, fd: i32, offset: i64, whence: u64) -> SysResult {
+        let pos = match whence {
+            SEEK_SET => SeekFrom::Start(u64::try_from(offset).map_err(|_| EINVAL)?),
+            SEEK_CUR => SeekFrom::Current(offset),
+            SEEK_END => SeekFrom::End(offset),
+            _ => return Err(EINVAL),
+        };
+        match entry(&mut self.fds, fd)? {
+            Fd::File(open) => open.file.seek(pos).map_err(errno),
+            _ => Err(ESPIPE),
+        }
+    }
+
+    /// RV32's `_llseek`, with the 64-bit offset split in two and the new
+    /// position written to `result`.
+    #[cfg(feature = "std")]
+    fn llseek(
+        &mut self,
+        fd: i32,
+        high: u64,
+        low: u64,
+        result: u64,
+        whence: u64,
+        bus: &mut Bus,
+    ) -> SysResult {
+        let offset = ((high as u32 as u64) << 32 | low as u32 as u64) as i64;
+        let pos = self.lseek(fd, offset, whence)?;
+        write_guest(bus, result, &pos.to_le_bytes())?;
+        Ok(0)
+    }
+
+    fn fstat(&mut self, fd: i32, buf: u64, bus: &mut Bus) -> SysResult {
+        let stat = self.stat_fd(fd)?;
+        write_guest(bus, buf, &stat.stat(self.rv64))?;
+        Ok(0)
+    }
+
+    #[cfg(feature = "std")]
+    fn statx(&mut self, dir: i32, path: u64, flags: u64, buf: u64, bus: &mut Bus) -> SysResult {
+        let path = read_path(bus, path)?;
+        // An empty path with AT_EMPTY_PATH means `dir` itself.
+        let empty = path.is_empty() && flags & AT_EMPTY_PATH != 0;
+        let stat = if empty && dir != AT_FDCWD {
+            self.stat_fd(dir)?
+        } else {
+            let guest = self.guest_path(dir, if empty { "." } else { &path })?;
+            Stat::from(&self.host_path(&guest)?.metadata().map_err(errno)?)
+        };
+        write_guest(bus, buf, &stat.statx())?;
+        Ok(0)
+    }
+
+    fn stat_fd(&mut self, fd: i32) -> Result<Stat, i64> {
+        match entry(&mut self.fds, fd)? {
+            #[cfg(feature = "std")]
+            Fd::File(open) => Ok(Stat::from(&open.file.metadata().map_err(errno)?)),
+            _ => Ok(Stat::console()),
+        }
+    }
+
+    /// `path` as an absolute guest path, relative to the directory open as
+    /// `dir` unless that's `AT_FDCWD`. The working directory is always `/`.
+    #[cfg(feature = "std")]
+    fn guest_path(&mut self, dir: i32, path: &str) -> Result<PathBuf, i64> {
+        if path.is_empty() {
+            return Err(ENOENT);
+        }
+        let base = if path.starts_with('/') || dir == AT_FDCWD {
+            PathBuf::from("/")
+        } else {
+            match entry(&mut self.fds, dir)? {
+                Fd::File(open) if open.file.metadata().is_ok_and(|m| m.is_dir()) => {
+                    open.path.clone()
+                }
+                _ => return Err(ENOTDIR),
+            }
+        };
+
+        let mut resolved = PathBuf::from("/");
+        for component in base.join(path).components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::ParentDir => {
+                    resolved.pop();
+                }
+                _ => {}
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Where `guest` is on the host. The host follows symlinks, so the
+    /// deepest part of the path that exists has to really be under the
+    /// sandbox root.
+    #[cfg(feature = "std")]
+    fn host_path(&self, guest: &Path) -> Result<PathBuf, i64> {
+        let root = self.sandbox.as_ref().ok_or(EACCES)?;
+        let host = root.join(guest.strip_prefix("/").unwrap_or(guest));
+
+        let root = root.canonicalize().map_err(errno)?;
+        let existing = host
+            .ancestors()
+            .find(|path| path.symlink_metadata().is_ok())
+            .ok_or(EACCES)?;
+        // A dangling symlink doesn't canonicalize, and may point anywhere.
+        match existing.canonicalize() {
+            Ok(real) if real.starts_with(&root) => Ok(host),
+            _ => Err(EACCES),
+        }
+    }
+
+    /// The size of a `long` or a pointer.
+    fn word(&self) -> usize {
+        if self.rv64 { 8 } else { 4 }
+    }
+
+    /// The highest free run of `len` bytes below `mmap_top` and above the
+    /// break.
+    fn find_gap(&self, len: u32) -> Option<u32> {
+        let mut end = self.mmap_top;
+        for (&start, &stop) in self.mappings.range(..self.mmap_top).rev() {
+            if stop <= end && end - stop >= len {
+                break;
+            }
+            end = end.min(start);
+        }
+        end.checked_sub(len).filter(|&start| start >= self.brk)
+    }
+
+    fn overlaps(&self, start: u32, end: u32) -> bool {
+        self.mappings
+            .range(..end)
+            .next_back()
+            .is_some_and(|(_, &stop)| stop > start)
+    }
+
+    /// Drop `start..end` from the mappings, splitting any that straddle it.
+    fn unmap(&mut self, start: u32, end: u32) {
+        let overlapping: Vec<(u32, u32)> = self
+            .mappings
+            .range(..end)
+            .filter(|&(_, &stop)| stop > start)
+            .map(|(&s, &e)| (s, e))
+            .collect();
+        for (s, e) in overlapping {
+            self.mappings.remove(&s);
+            if s < start {
+                self.mappings.insert(s, start);
+            }
+            if e > end {
+                self.mappings.insert(end, e);
+            }
+        }
+    }
+}
+
+/// `addr..addr + len` rounded out to whole pages, or `EINVAL` if `addr`
+/// isn't page-aligned, `len` is zero or it doesn't fit in 32 bits.
+fn range(addr: u64, len: u64) -> Result<Range<u32>, i64> {
+    if !addr.is_multiple_of(PAGE_SIZE) || len == 0 {
+        return Err(EINVAL);
+    }
+    let end = addr.checked_add(page_align(len)).ok_or(EINVAL)?;
+    let end = u32::try_from(end).map_err(|_| EINVAL)?;
+    Ok(addr as u32..end)
+}
+
+fn page_align(len: u64) -> u64 {
+    len.saturating_add(PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// The time on `clock`, or `EINVAL` if Linux has no such clock. The CPU
+/// time clocks read the same as the monotonic ones, since the program is
+/// all the hart runs.
+fn clock_time(clock: u64, bus: &Bus) -> Result<Duration, i64> {
+    match clock {
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => Ok(bus.clock().realtime(bus.time())),
+        CLOCK_MONOTONIC..=CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
+            Ok(bus.clock().duration(bus.time()))
+        }
+        _ => Err(EINVAL),
+    }
+}
+
+/// `clock_gettime`, writing a `timespec` whose fields are `width` bytes.
+fn clock_gettime(clock: u64, buf: u64, width: usize, bus: &mut Bus) -> SysResult {
+    let time = clock_time(clock, bus)?;
+    write_timespec(bus, buf, time.as_secs(), time.subsec_nanos(), width)?;
+    Ok(0)
+}
+
+/// A tick, or a nanosecond if that's longer.
+fn clock_getres(clock: u64, buf: u64, width: usize, bus: &mut Bus) -> SysResult {
+    clock_time(clock, bus)?;
+    if buf != 0 {
+        let tick = bus.clock().duration(1).max(Duration::from_nanos(1));
+        write_timespec(bus, buf, tick.as_secs(), tick.subsec_nanos(), width)?;
+    }
+    Ok(0)
+}
+
+fn gettimeofday(buf: u64, width: usize, bus: &mut Bus) -> SysResult {
+    if buf != 0 {
+        let time = bus.clock().realtime(bus.time());
+        write_timespec(bus, buf, time.as_secs(), time.subsec_micros(), width)?;
+    }
+    Ok(0)
+}
+
+/// The ticks `duration` lasts, rounded up.
+fn ticks_for(bus: &Bus, duration: Duration) -> u64 {
+    let ticks = bus.clock().ticks(duration);
+    if bus.clock().duration(ticks) < duration {
+        ticks + 1
+    } else {
+        ticks
+    }
+}
+
+/// A `timespec` or `timeval`: seconds then the fraction, each `width`
+/// bytes.
+fn write_timespec(
+    bus: &mut Bus,
+    addr: u64,
+    secs: u64,
+    fraction: u32,
+    width: usize,
+) -> Result<(), i64> {
+    let mut bytes = secs.to_le_bytes()[..width].to_vec();
+    bytes.extend_from_slice(&(fraction as u64).to_le_bytes()[..width]);
+    write_guest(bus, addr, &bytes)
+}
+
+fn read_timespec(bus: &mut Bus, addr: u64, width: usize) -> Result<Duration, i64> {
+    let sign = 1 << (width * 8 - 1);
+    let secs = read_word(bus, addr, width as u64)?;
+    let nanos = read_word(bus, addr + width as u64, width as u64)?;
+    if secs & sign != 0 || nanos >= 1_000_000_000 {
+        return Err(EINVAL);
+    }
+    Ok(Duration::new(secs, nanos as u32))
+}
+
+/// What `fstat` and `statx` report. Device and inode numbers are zero, and
+/// the permissions only say whether the file is read-only.
+struct Stat {
+    mode: u32,
+    size: u64,
+    accessed: (i64, u32),
+    modified: (i64, u32),
+}
+
+impl Stat {
+    /// The console is a character device, so libc buffers it by line.
+    fn console() -> Self {
+        Self {
+            mode: S_IFCHR | 0o620,
+            size: 0,
+            accessed: (0, 0),
+            modified: (0, 0),
+        }
+    }
+
+    /// `struct stat` on RV64, or `struct stat64` on RV32, which differ only
+    /// in the width of the times.
+    fn stat(&self, rv64: bool) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128);
+        out.extend_from_slice(&0u64.to_le_bytes()); // st_dev
+        out.extend_from_slice(&0u64.to_le_bytes()); // st_ino
+        out.extend_from_slice(&self.mode.to_le_bytes());
+        out.extend_from_slice(&1u32.to_le_bytes()); // st_nlink
+        out.extend_from_slice(&[0; 8]); // st_uid, st_gid
+        out.extend_from_slice(&[0; 16]); // st_rdev, padding
+        out.extend_from_slice(&self.size.to_le_bytes());
+        out.extend_from_slice(&(PAGE_SIZE as u32).to_le_bytes()); // st_blksize
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&self.size.div_ceil(512).to_le_bytes()); // st_blocks
+        let width = if rv64 { 8 } else { 4 };
+        for (secs, nanos) in [self.accessed, self.modified, self.modified] {
+            out.extend_from_slice(&secs.to_le_bytes()[..width]);
+            out.extend_from_slice(&(nanos as u64).to_le_bytes()[..width]);
+        }
+        out.extend_from_slice(&[0; 8]);
+        out
+    }
+
+    #[cfg(feature = "std")]
+    fn statx(&self) -> [u8; 256] {
+        let mut out = [0; 256];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            out[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        put(0, &STATX_BASIC_STATS.to_le_bytes());
+        put(4, &(PAGE_SIZE as u32).to_le_bytes()); // stx_blksize
+        put(16, &1u32.to_le_bytes()); // stx_nlink
+        put(28, &(self.mode as u16).to_le_bytes());
+        put(40, &self.size.to_le_bytes());
+        put(48, &self.size.div_ceil(512).to_le_bytes()); // stx_blocks
+        for (offset, (secs, nanos)) in [
+            (64, self.accessed),
+            (96, self.modified),
+            (112, self.modified),
+        ] {
+            put(offset, &secs.to_le_bytes());
+            put(offset + 8, &nanos.to_le_bytes());
+        }
+        out
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&Metadata> for Stat {
+    fn from(metadata: &Metadata) -> Self {
+        let kind = if metadata.is_dir() { S_IFDIR } else { S_IFREG };
+        let permissions = match (metadata.is_dir(), metadata.permissions().readonly()) {
+            (true, false) => 0o755,
+            (true, true) => 0o555,
+            (false, false) => 0o644,
+            (false, true) => 0o444,
+        };
+        let time = |time: io::Result<std::time::SystemTime>| {
+            time.ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or((0, 0), |d| (d.as_secs() as i64, d.subsec_nanos()))
+        };
+        Self {
+            mode: kind | permissions,
+            size: metadata.len(),
+            accessed: time(metadata.accessed()),
+            modified: time(metadata.modified()),
+        }
+    }
+}
+
+/// The Linux errno for a host error. It goes by the error's kind, so it's
+/// the same whatever the host's own numbers are.
+fn errno(error: io::Error) -> i64 {
+    match error.kind() {
+        io::ErrorKind::NotFound => ENOENT,
+        io::ErrorKind::PermissionDenied => EACCES,
+        io::ErrorKind::AlreadyExists => EEXIST,
+        io::ErrorKind::InvalidInput => EINVAL,
+        io::ErrorKind::NotADirectory => ENOTDIR,
+        io::ErrorKind::IsADirectory => EISDIR,
+        _ => EIO,
+    }
+}
+
+fn entry(fds: &mut [Option<Fd>], fd: i32) -> Result<&mut Fd, i64> {
+    usize::try_from(fd)
+        .ok()
+        .and_then(|fd| fds.get_mut(fd))
+        .and_then(Option::as_mut)
+        .ok_or(EBADF)
+}
+
+fn console(output: &mut Box<dyn Write>, bytes: &[u8]) -> io::Result<()> {
+    output.write_all(bytes)?;
+    output.flush()
+}
+
+fn in_ram(bus: &Bus, addr: u64, len: u64) -> bool {
+    match (u32::try_from(addr), usize::try_from(len)) {
+        (Ok(addr), Ok(len)) => bus.in_ram(addr, len),
+        _ => false,
+    }
+}
+
+fn read_guest(bus: &mut Bus, addr: u64, len: u64) -> Result<Vec<u8>, i64> {
+    if !in_ram(bus, addr, len) {
+        return Err(EFAULT);
+    }
+    let mut bytes = vec![0; len as usize];
+    bus.dma().read(addr, &mut bytes).ok_or(EFAULT)?;
+    Ok(bytes)
+}
+
+fn write_guest(bus: &mut Bus, addr: u64, bytes: &[u8]) -> Result<(), i64> {
+    bus.dma().write(addr, bytes).ok_or(EFAULT)
+}
+
+/// A little-endian value `width` bytes wide.
+fn read_word(bus: &mut Bus, addr: u64, width: u64) -> Result<u64, i64> {
+    let mut bytes = [0; 8];
+    bus.dma()
+        .read(addr, &mut bytes[..width as usize])
+        .ok_or(EFAULT)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// A NUL-terminated path, which must be UTF-8.
+#[cfg(feature = "std")]
+fn read_path(bus: &mut Bus, addr: u64) -> Result<String, i64> {
+    let mut bytes = Vec::new();
+    for i in 0..PATH_MAX {
+        let mut byte = [0];
+        bus.dma().read(addr + i, &mut byte).ok_or(EFAULT)?;
+        if byte[0] == 0 {
+            return String::from_utf8(bytes).map_err(|_| EINVAL);
+        }
+        bytes.push(byte[0]);
+    }
+    Err(ENAMETOOLONG)
+}
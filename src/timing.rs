@@ -0,0 +1,337 @@
+//! A timing model of a classic five-stage in-order pipeline, IF ID EX MEM
+//! WB with full forwarding, for seeing how the shape of code changes its
+//! CPI. Like the cache model, it only counts cycles; it never changes what
+//! the guest sees.
+
+use std::fmt;
+
+use crate::decode::{CryptoInstruction, FloatInstruction, HypervisorInstruction, Instruction};
+use crate::predictor::{BranchPredictor, NeverTaken};
+
+/// Cycles it takes to fill the pipeline before the first instruction
+/// completes.
+const FILL_CYCLES: u64 = 4;
+
+/// What each hazard costs, in cycles. The defaults are the textbook ones:
+/// a load's result is forwarded from MEM, branches resolve in EX and
+/// direct jumps in ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// An instruction using the result of the load just before it.
+    pub load_use_stall: u64,
+    /// A conditional branch the predictor got wrong. With the default
+    /// [`NeverTaken`] predictor, that's every taken branch.
+    pub branch_penalty: u64,
+    /// JAL.
+    pub jump_penalty: u64,
+    /// JALR, whose target isn't known until EX either.
+    pub indirect_jump_penalty: u64,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            load_use_stall: 1,
+            branch_penalty: 2,
+            jump_penalty: 1,
+            indirect_jump_penalty: 2,
+        }
+    }
+}
+
+/// What the pipeline has run since it was created or last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    pub instructions: u64,
+    pub cycles: u64,
+    /// Bubbles inserted for load-use hazards.
+    pub load_use_stalls: u64,
+    /// Control transfers that threw away fetched instructions.
+    pub flushes: u64,
+    /// Cycles lost to those flushes.
+    pub flush_cycles: u64,
+    /// Conditional branches retired.
+    pub branches: u64,
+    /// Conditional branches the predictor got wrong.
+    pub mispredictions: u64,
+}
+
+impl PipelineStats {
+    /// Cycles per instruction.
+    pub fn cpi(&self) -> f64 {
+        match self.instructions {
+            0 => 0.0,
+            n => self.cycles as f64 / n as f64,
+        }
+    }
+
+    /// The fraction of conditional branches predicted right, from 0 to 1.
+    pub fn prediction_accuracy(&self) -> f64 {
+        match self.branches {
+            0 => 0.0,
+            n => (n - self.mispredictions) as f64 / n as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reg {
+    X(u8),
+    F(u8),
+}
+
+/// The register an instruction writes and the ones it reads. `x0` is
+/// left out, as nothing waits for it.
+struct Operands {
+    dest: Option<Reg>,
+    sources: [Option<Reg>; 3],
+}
+
+/// Retires instructions one a cycle, plus whatever the hazards between
+/// them cost. Traps aren't charged, and vector, custom and hypervisor
+/// fence instructions are taken to have no register dependences.
+pub struct Pipeline {
+    config: PipelineConfig,
+    stats: PipelineStats,
+    predictor: Box<dyn BranchPredictor>,
+    filled: bool,
+    /// Where the last instruction was a load, the register it loaded.
+    loaded: Option<Reg>,
+}
+
+impl Pipeline {
+    pub fn new(config: PipelineConfig) -> Self {
+        Self {
+            config,
+            stats: PipelineStats::default(),
+            predictor: Box::new(NeverTaken),
+            filled: false,
+            loaded: None,
+        }
+    }
+
+    pub fn config(&self) -> PipelineConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> PipelineStats {
+        self.stats
+    }
+
+    /// Predict conditional branches with `predictor` from now on.
+    pub fn set_predictor(&mut self, predictor: impl BranchPredictor + 'static) {
+        self.set_boxed_predictor(Box::new(predictor));
+    }
+
+    pub(crate) fn set_boxed_predictor(&mut self, predictor: Box<dyn BranchPredictor>) {
+        self.predictor = predictor;
+    }
+
+    /// Zero the counts. The pipeline and predictor keep their state.
+    pub fn reset_stats(&mut self) {
+        self.stats = PipelineStats::default();
+    }
+
+    /// `instruction` retired from `pc`, and the next one is fetched from
+    /// somewhere other than the following word if `redirected`.
+    pub(crate) fn retire(&mut self, pc: u32, instruction: &Instruction, redirected: bool) {
+        use Instruction::*;
+
+        if !self.filled {
+            self.filled = true;
+            self.stats.cycles += FILL_CYCLES;
+        }
+        self.stats.instructions += 1;
+        self.stats.cycles += 1;
+
+        let operands = operands(instruction);
+        if self
+            .loaded
+            .is_some_and(|reg| operands.sources.contains(&Some(reg)))
+        {
+            self.stats.load_use_stalls += 1;
+            self.stats.cycles += self.config.load_use_stall;
+        }
+        self.loaded = match is_load(instruction) {
+            true => operands.dest,
+            false => None,
+        };
+
+        let penalty = match instruction {
+            Jal { .. } => self.config.jump_penalty,
+            Jalr { .. } => self.config.indirect_jump_penalty,
+            Beq { .. } | Bne { .. } | Blt { .. } | Bge { .. } | Bltu { .. } | Bgeu { .. } => {
+                self.stats.branches += 1;
+                let predicted = self.predictor.predict(pc);
+                self.predictor.update(pc, redirected);
+                if predicted == redirected {
+                    0
+                } else {
+                    self.stats.mispredictions += 1;
+                    self.config.branch_penalty
+                }
+            }
+            _ => 0,
+        };
+        if penalty > 0 {
+            self.stats.flushes += 1;
+            self.stats.flush_cycles += penalty;
+            self.stats.cycles += penalty;
+        }
+    }
+}
+
+impl fmt::Debug for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pipeline")
+            .field("config", &self.config)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+fn is_load(instruction: &Instruction) -> bool {
+    use Instruction::*;
+
+    matches!(
+        instruction,
+        Lb { .. }
+            | Lh { .. }
+            | Lw { .. }
+            | Lbu { .. }
+            | Lhu { .. }
+            | Lwu { .. }
+            | Ld { .. }
+            | LrW { .. }
+            | LrD { .. }
+            | Float(FloatInstruction::Flw { .. } | FloatInstruction::Fld { .. })
+            | Hypervisor(HypervisorInstruction::Load { .. })
+    )
+}
+
+fn operands(instruction: &Instruction) -> Operands {
+    use FloatInstruction as F;
+    use Instruction::*;
+    use Reg::{F as f, X as x};
+
+    let (dest, sources) = match *instruction {
+        Lui { rd, .. } | Auipc { rd, .. } | Jal { rd, .. } => (Some(x(rd)), [None; 3]),
+        Jalr { rd, rs1, .. } => (Some(x(rd)), [Some(x(rs1)), None, None]),
+
+        Beq { rs1, rs2, .. }
+        | Bne { rs1, rs2, .. }
+        | Blt { rs1, rs2, .. }
+        | Bge { rs1, rs2, .. }
+        | Bltu { rs1, rs2, .. }
+        | Bgeu { rs1, rs2, .. }
+        | Sb { rs1, rs2, .. }
+        | Sh { rs1, rs2, .. }
+        | Sw { rs1, rs2, .. }
+        | Sd { rs1, rs2, .. }
+        | SfenceVma { rs1, rs2 } => (None, [Some(x(rs1)), Some(x(rs2)), None]),
+
+        Lb { rd, rs1, .. }
+        | Lh { rd, rs1, .. }
+        | Lw { rd, rs1, .. }
+        | Lbu { rd, rs1, .. }
+        | Lhu { rd, rs1, .. }
+        | Lwu { rd, rs1, .. }
+        | Ld { rd, rs1, .. }
+        | Addi { rd, rs1, .. }
+        | Slti { rd, rs1, .. }
+        | Sltiu { rd, rs1, .. }
+        | Xori { rd, rs1, .. }
+        | Ori { rd, rs1, .. }
+        | Andi { rd, rs1, .. }
+        | Slli { rd, rs1, .. }
+        | Srli { rd, rs1, .. }
+        | Srai { rd, rs1, .. }
+        | Addiw { rd, rs1, .. }
+        | Slliw { rd, rs1, .. }
+        | Srliw { rd, rs1, .. }
+        | Sraiw { rd, rs1, .. }
+        | SlliUw { rd, rs1, .. }
+        | Brev8 { rd, rs1 }
+        | Zip { rd, rs1 }
+        | Unzip { rd, rs1 }
+        | LrW { rd, rs1 }
+        | LrD { rd, rs1 }
+        | Csrrw { rd, rs1, .. }
+        | Csrrs { rd, rs1, .. }
+        | Csrrc { rd, rs1, .. } => (Some(x(rd)), [Some(x(rs1)), None, None]),
+
+        Add { rd, rs1, rs2 }
+        | Sub { rd, rs1, rs2 }
+        | Sll { rd, rs1, rs2 }
+        | Slt { rd, rs1, rs2 }
+        | Sltu { rd, rs1, rs2 }
+        | Xor { rd, rs1, rs2 }
+        | Srl { rd, rs1, rs2 }
+        | Sra { rd, rs1, rs2 }
+        | Or { rd, rs1, rs2 }
+        | And { rd, rs1, rs2 }
+        | Addw { rd, rs1, rs2 }
+        | Subw { rd, rs1, rs2 }
+        | Sllw { rd, rs1, rs2 }
+        | Srlw { rd, rs1, rs2 }
+        | Sraw { rd, rs1, rs2 }
+        | Sh1add { rd, rs1, rs2 }
+        | Sh2add { rd, rs1, rs2 }
+        | Sh3add { rd, rs1, rs2 }
+        | AddUw { rd, rs1, rs2 }
+        | Sh1addUw { rd, rs1, rs2 }
+        | Sh2addUw { rd, rs1, rs2 }
+        | Sh3addUw { rd, rs1, rs2 }
+        | Pack { rd, rs1, rs2 }
+        | Packh { rd, rs1, rs2 }
+        | Packw { rd, rs1, rs2 }
+        | ScW { rd, rs1, rs2 }
+        | ScD { rd, rs1, rs2 }
+        | Crypto(CryptoInstruction::Aes32 { rd, rs1, rs2, .. })
+        | Crypto(CryptoInstruction::Sha512Half { rd, rs1, rs2, .. }) => {
+            (Some(x(rd)), [Some(x(rs1)), Some(x(rs2)), None])
+        }
+        Crypto(CryptoInstruction::Sha { rd, rs1, .. }) => (Some(x(rd)), [Some(x(rs1)), None, None]),
+
+        Csrrwi { rd, .. } | Csrrsi { rd, .. } | Csrrci { rd, .. } => (Some(x(rd)), [None; 3]),
+
+        Float(float) => match float {
+            F::Flw { rd, rs1, .. } | F::Fld { rd, rs1, .. } => {
+                (Some(f(rd)), [Some(x(rs1)), None, None])
+            }
+            F::Fsw { rs1, rs2, .. } | F::Fsd { rs1, rs2, .. } => {
+                (None, [Some(x(rs1)), Some(f(rs2)), None])
+            }
+            F::Farith { rd, rs1, rs2, .. } => (Some(f(rd)), [Some(f(rs1)), Some(f(rs2)), None]),
+            F::Fma {
+                rd, rs1, rs2, rs3, ..
+            } => (Some(f(rd)), [Some(f(rs1)), Some(f(rs2)), Some(f(rs3))]),
+            F::Fcmp { rd, rs1, rs2, .. } => (Some(x(rd)), [Some(f(rs1)), Some(f(rs2)), None]),
+            F::Fsqrt { rd, rs1, .. } | F::FcvtFp { rd, rs1, .. } => {
+                (Some(f(rd)), [Some(f(rs1)), None, None])
+            }
+            F::Fclass { rd, rs1, .. }
+            | F::FcvtToInt { rd, rs1, .. }
+            | F::FmvToInt { rd, rs1, .. } => (Some(x(rd)), [Some(f(rs1)), None, None]),
+            F::FcvtFromInt { rd, rs1, .. } | F::FmvFromInt { rd, rs1, .. } => {
+                (Some(f(rd)), [Some(x(rs1)), None, None])
+            }
+        },
+
+        Hypervisor(HypervisorInstruction::Load { rd, rs1, .. }) => {
+            (Some(x(rd)), [Some(x(rs1)), None, None])
+        }
+        Hypervisor(HypervisorInstruction::Store { rs1, rs2, .. }) => {
+            (None, [Some(x(rs1)), Some(x(rs2)), None])
+        }
+
+        Vector(_) | Hypervisor(_) | Custom(_) | Fence | FenceI | Ecall | Ebreak | Mret | Sret
+        | Wfi | WrsNto | WrsSto => (None, [None; 3]),
+    };
+
+    let live = |reg: Option<Reg>| reg.filter(|&reg| reg != Reg::X(0));
+    Operands {
+        dest: live(dest),
+        sources: sources.map(live),
+    }
+}
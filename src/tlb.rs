@@ -0,0 +1,156 @@
+//! A model of a TLB in front of the page walker, for counting how often
+//! translations would be found without a walk. Like the cache model, it
+//! never changes what the guest sees: the walker still runs on every
+//! access, so a missing SFENCE.VMA costs nothing but accuracy.
+
+/// The shape of the TLB. Both dimensions are powers of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlbConfig {
+    entries: u32,
+    ways: u32,
+}
+
+impl TlbConfig {
+    /// `entries` translations in sets of `ways`. As many ways as entries is
+    /// fully associative.
+    pub fn new(entries: u32, ways: u32) -> Result<Self, String> {
+        for (name, value) in [("entries", entries), ("ways", ways)] {
+            if !value.is_power_of_two() {
+                return Err(format!("TLB: {} {} is not a power of two", name, value));
+            }
+        }
+        if ways > entries {
+            return Err(format!("TLB: {} entries can't make {} ways", entries, ways));
+        }
+        Ok(Self { entries, ways })
+    }
+
+    pub fn entries(&self) -> u32 {
+        self.entries
+    }
+
+    pub fn ways(&self) -> u32 {
+        self.ways
+    }
+}
+
+/// What the TLB has seen since it was created or last reset.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TlbStats {
+    pub hits: u64,
+    /// Lookups that would have needed a page walk.
+    pub misses: u64,
+    /// SFENCE.VMAs executed.
+    pub fences: u64,
+    /// Entries those fences dropped.
+    pub invalidated: u64,
+}
+
+impl TlbStats {
+    pub fn lookups(&self) -> u64 {
+        self.hits + self.misses
+    }
+
+    /// The fraction of lookups that hit, from 0 to 1.
+    pub fn hit_rate(&self) -> f64 {
+        match self.lookups() {
+            0 => 0.0,
+            n => self.hits as f64 / n as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Entry {
+    /// The ASID and virtual page number it translates.
+    tag: Option<(u32, u64)>,
+    /// When it was last used, for picking the least recently used.
+    used: u64,
+}
+
+/// Translations by ASID and 4 KiB virtual page, replaced least recently
+/// used first. A superpage takes an entry for each 4 KiB page of it that's
+/// used. Only translations that succeed are looked up, and guest
+/// translations under the H extension aren't modelled.
+#[derive(Debug, Clone)]
+pub struct Tlb {
+    config: TlbConfig,
+    /// `ways` entries for each set in turn.
+    entries: Vec<Entry>,
+    clock: u64,
+    stats: TlbStats,
+}
+
+impl Tlb {
+    pub fn new(config: TlbConfig) -> Self {
+        Self {
+            config,
+            entries: vec![Entry::default(); config.entries as usize],
+            clock: 0,
+            stats: TlbStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> TlbConfig {
+        self.config
+    }
+
+    pub fn stats(&self) -> TlbStats {
+        self.stats
+    }
+
+    /// Zero the counts but keep the entries.
+    pub fn reset_stats(&mut self) {
+        self.stats = TlbStats::default();
+    }
+
+    /// How many entries hold a translation.
+    pub fn occupied(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.tag.is_some())
+            .count()
+    }
+
+    /// Look up the page `vpn` of address space `asid`, filling an entry on
+    /// a miss.
+    pub(crate) fn lookup(&mut self, asid: u32, vpn: u64) {
+        let ways = self.config.ways as usize;
+        let sets = (self.config.entries / self.config.ways) as u64;
+        let start = (vpn % sets) as usize * ways;
+        let set = &mut self.entries[start..start + ways];
+
+        self.clock += 1;
+        if let Some(entry) = set.iter_mut().find(|e| e.tag == Some((asid, vpn))) {
+            entry.used = self.clock;
+            self.stats.hits += 1;
+            return;
+        }
+
+        self.stats.misses += 1;
+        let victim = set
+            .iter_mut()
+            .min_by_key(|entry| (entry.tag.is_some(), entry.used))
+            .expect("a set has at least one way");
+        *victim = Entry {
+            tag: Some((asid, vpn)),
+            used: self.clock,
+        };
+    }
+
+    /// SFENCE.VMA: drop the entries for page `vpn`, or every page, in
+    /// address space `asid`, or every address space.
+    pub(crate) fn fence(&mut self, vpn: Option<u64>, asid: Option<u32>) {
+        self.stats.fences += 1;
+        for entry in &mut self.entries {
+            let Some((entry_asid, entry_vpn)) = entry.tag else {
+                continue;
+            };
+            if vpn.is_none_or(|vpn| vpn == entry_vpn) && asid.is_none_or(|asid| asid == entry_asid)
+            {
+                entry.tag = None;
+                self.stats.invalidated += 1;
+            }
+        }
+    }
+}
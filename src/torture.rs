@@ -0,0 +1,404 @@
+//! Random self-checking RV32I programs, in the spirit of riscv-torture.
+//!
+//! [`generate`] writes a program from a seed. It loads random values into
+//! the registers, runs a random mix of arithmetic, loads and stores,
+//! forward branches and jumps over them, then stores every register to
+//! [`SIGNATURE`] and checks each against the value it should hold. The
+//! generator works those values out with its own small model of RV32I as
+//! it goes, so a program carries its answers with it and doesn't need a
+//! reference simulator.
+//!
+//! A program reports through [`TOHOST`] as the riscv-tests do: 1 if every
+//! register matched, `(n << 1) | 1` if `xn` was the first that didn't.
+//! [`run`] runs one and compares the whole signature; [`run_many`] runs a
+//! range of seeds.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::encode;
+use crate::fuzz::Generator;
+use crate::program::Program;
+use crate::riscv_tests::{self, TestOutcome};
+use crate::trap::Exception;
+use crate::{MemSize, RiscvCpu};
+
+pub const RAM_SIZE: usize = 256 * 1024;
+pub const DEFAULT_LENGTH: usize = 200;
+pub const DEFAULT_STEP_LIMIT: u64 = 1_000_000;
+
+/// Where `x1` to `x30` are stored, one word each, at the end.
+pub const SIGNATURE: u32 = 0x3_0000;
+pub const TOHOST: u32 = 0x3_0100;
+/// Loads and stores stay within this, through `sp`.
+pub const SCRATCH: u32 = 0x3_1000;
+const SCRATCH_SIZE: usize = 256;
+
+/// Holds [`SCRATCH`] throughout.
+const SP: u8 = 2;
+/// Left alone by the random part, for the checks at the end.
+const CHECK: u8 = 31;
+
+/// A generated program and what it should leave behind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Test {
+    pub seed: u64,
+    /// To be loaded at address 0.
+    pub image: Vec<u8>,
+    /// The expected values of `x1` to `x30`, as stored at [`SIGNATURE`].
+    pub signature: Vec<u32>,
+}
+
+impl Test {
+    /// The expected signature as a signature file: one word per line, in
+    /// hex.
+    pub fn signature_dump(&self) -> String {
+        self.signature
+            .iter()
+            .map(|word| format!("{:08x}\n", word))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TortureOutcome {
+    Pass,
+    /// `(register, expected, actual)` for each register whose signature
+    /// word is wrong.
+    Fail(Vec<(u8, u32, u32)>),
+    /// Never wrote to `tohost` within the step limit.
+    Timeout,
+    Halted(Exception),
+    Error(String),
+}
+
+impl TortureOutcome {
+    pub fn passed(&self) -> bool {
+        *self == TortureOutcome::Pass
+    }
+}
+
+impl fmt::Display for TortureOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TortureOutcome::Pass => write!(f, "PASS"),
+            TortureOutcome::Fail(registers) => {
+                write!(f, "FAIL")?;
+                for (reg, expected, actual) in registers {
+                    write!(f, " x{}={:#x} (expected {:#x})", reg, actual, expected)?;
+                }
+                Ok(())
+            }
+            TortureOutcome::Timeout => write!(f, "TIMEOUT"),
+            TortureOutcome::Halted(e) => write!(f, "HALTED: {}", e),
+            TortureOutcome::Error(e) => write!(f, "ERROR: {}", e),
+        }
+    }
+}
+
+// ── Generator ─────────────────────────────────────────────────────────────────
+
+type RegisterOp = (fn(u8, u8, u8) -> u32, fn(u32, u32) -> u32);
+type ImmediateOp = (fn(u8, u8, i32) -> u32, fn(u32, u32) -> u32);
+type ShiftOp = (fn(u8, u8, u8) -> u32, fn(u32, u32) -> u32);
+type BranchOp = (fn(u8, u8, i32) -> u32, fn(u32, u32) -> bool);
+
+const REGISTER_OPS: [RegisterOp; 10] = [
+    (encode::add, |a, b| a.wrapping_add(b)),
+    (encode::sub, |a, b| a.wrapping_sub(b)),
+    (encode::sll, |a, b| a << (b & 31)),
+    (encode::slt, |a, b| ((a as i32) < (b as i32)) as u32),
+    (encode::sltu, |a, b| (a < b) as u32),
+    (encode::xor, |a, b| a ^ b),
+    (encode::srl, |a, b| a >> (b & 31)),
+    (encode::sra, |a, b| ((a as i32) >> (b & 31)) as u32),
+    (encode::or, |a, b| a | b),
+    (encode::and, |a, b| a & b),
+];
+
+/// The model gets the immediate sign-extended.
+const IMMEDIATE_OPS: [ImmediateOp; 6] = [
+    (encode::addi, |a, b| a.wrapping_add(b)),
+    (encode::slti, |a, b| ((a as i32) < (b as i32)) as u32),
+    (encode::sltiu, |a, b| (a < b) as u32),
+    (encode::xori, |a, b| a ^ b),
+    (encode::ori, |a, b| a | b),
+    (encode::andi, |a, b| a & b),
+];
+
+const SHIFT_OPS: [ShiftOp; 3] = [
+    (encode::slli, |a, b| a << b),
+    (encode::srli, |a, b| a >> b),
+    (encode::srai, |a, b| ((a as i32) >> b) as u32),
+];
+
+const BRANCH_OPS: [BranchOp; 6] = [
+    (encode::beq, |a, b| a == b),
+    (encode::bne, |a, b| a != b),
+    (encode::blt, |a, b| (a as i32) < (b as i32)),
+    (encode::bge, |a, b| (a as i32) >= (b as i32)),
+    (encode::bltu, |a, b| a < b),
+    (encode::bgeu, |a, b| a >= b),
+];
+
+/// Values that tend to find bugs, mixed in with uniformly random ones.
+const INTERESTING: [u32; 8] = [
+    0,
+    1,
+    2,
+    0x7FFF_FFFF,
+    0x8000_0000,
+    0xFFFF_FFFF,
+    0xFFFF_F800,
+    0x0000_07FF,
+];
+
+/// Writes the program and runs it on a model as it goes.
+struct Torture {
+    rng: Generator,
+    words: Vec<u32>,
+    regs: [u32; 32],
+    scratch: [u8; SCRATCH_SIZE],
+}
+
+impl Torture {
+    fn below(&mut self, n: usize) -> usize {
+        (self.rng.next_u64() % n as u64) as usize
+    }
+
+    fn value(&mut self) -> u32 {
+        match self.below(4) {
+            0 => INTERESTING[self.below(INTERESTING.len())],
+            _ => self.rng.next_u64() as u32,
+        }
+    }
+
+    /// Any register the random part may read: everything but the check
+    /// register.
+    fn source(&mut self) -> u8 {
+        self.below(31) as u8
+    }
+
+    /// Any register the random part may write. `x0` is fair game.
+    fn dest(&mut self) -> u8 {
+        match self.below(30) as u8 {
+            SP => 30,
+            reg => reg,
+        }
+    }
+
+    fn pc(&self) -> u32 {
+        4 * self.words.len() as u32
+    }
+
+    /// Emit `word`, and if `live`, apply `result` to `rd` in the model.
+    fn emit(&mut self, word: u32, rd: u8, result: u32, live: bool) {
+        self.words.push(word);
+        if live && rd != 0 {
+            self.regs[rd as usize] = result;
+        }
+    }
+
+    /// An instruction that falls through: arithmetic, a load or a store.
+    /// If it isn't `live` it's being jumped over, and only emitted.
+    fn straight(&mut self, live: bool) {
+        let (rd, rs1, rs2) = (self.dest(), self.source(), self.source());
+        let (a, b) = (self.regs[rs1 as usize], self.regs[rs2 as usize]);
+        let imm = self.below(4096) as i32 - 2048;
+
+        match self.below(6) {
+            0 => {
+                let (encode, model) = REGISTER_OPS[self.below(REGISTER_OPS.len())];
+                self.emit(encode(rd, rs1, rs2), rd, model(a, b), live);
+            }
+            1 => {
+                let (encode, model) = IMMEDIATE_OPS[self.below(IMMEDIATE_OPS.len())];
+                self.emit(encode(rd, rs1, imm), rd, model(a, imm as u32), live);
+            }
+            2 => {
+                let shamt = self.below(32) as u8;
+                let (encode, model) = SHIFT_OPS[self.below(SHIFT_OPS.len())];
+                self.emit(encode(rd, rs1, shamt), rd, model(a, shamt as u32), live);
+            }
+            3 => {
+                let upper = self.below(1 << 20) as u32;
+                match self.below(2) {
+                    0 => self.emit(encode::lui(rd, upper), rd, upper << 12, live),
+                    _ => {
+                        let result = self.pc().wrapping_add(upper << 12);
+                        self.emit(encode::auipc(rd, upper), rd, result, live);
+                    }
+                }
+            }
+            4 => self.load(rd, live),
+            _ => self.store(rs2, live),
+        }
+    }
+
+    fn load(&mut self, rd: u8, live: bool) {
+        let size = 1 << self.below(3);
+        let offset = self.below(SCRATCH_SIZE / size) * size;
+        let bytes = &self.scratch[offset..offset + size];
+        let unsigned = bytes
+            .iter()
+            .rev()
+            .fold(0u32, |value, &byte| (value << 8) | byte as u32);
+        let shift = 32 - 8 * size as u32;
+        let signed = (((unsigned << shift) as i32) >> shift) as u32;
+
+        let offset = offset as i32;
+        let (word, result) = match (size, self.below(2)) {
+            (1, 0) => (encode::lb(rd, SP, offset), signed),
+            (1, _) => (encode::lbu(rd, SP, offset), unsigned),
+            (2, 0) => (encode::lh(rd, SP, offset), signed),
+            (2, _) => (encode::lhu(rd, SP, offset), unsigned),
+            _ => (encode::lw(rd, SP, offset), unsigned),
+        };
+        self.emit(word, rd, result, live);
+    }
+
+    fn store(&mut self, rs2: u8, live: bool) {
+        let size = 1 << self.below(3);
+        let offset = self.below(SCRATCH_SIZE / size) * size;
+        let word = match size {
+            1 => encode::sb(rs2, SP, offset as i32),
+            2 => encode::sh(rs2, SP, offset as i32),
+            _ => encode::sw(rs2, SP, offset as i32),
+        };
+        self.words.push(word);
+        if live {
+            let value = self.regs[rs2 as usize].to_le_bytes();
+            self.scratch[offset..offset + size].copy_from_slice(&value[..size]);
+        }
+    }
+
+    /// A branch or JAL over a few straight-line instructions.
+    fn skip(&mut self) {
+        let skipped = 1 + self.below(4);
+        let offset = 4 * (skipped as i32 + 1);
+        let taken = match self.below(4) {
+            0 => {
+                let rd = self.dest();
+                let link = self.pc() + 4;
+                self.emit(encode::jal(rd, offset), rd, link, true);
+                true
+            }
+            _ => {
+                let (rs1, rs2) = (self.source(), self.source());
+                let (encode, model) = BRANCH_OPS[self.below(BRANCH_OPS.len())];
+                self.words.push(encode(rs1, rs2, offset));
+                model(self.regs[rs1 as usize], self.regs[rs2 as usize])
+            }
+        };
+        for _ in 0..skipped {
+            self.straight(!taken);
+        }
+    }
+}
+
+/// Generate the program for `seed`, with about `length` random
+/// instructions between the setup and the checks.
+pub fn generate(seed: u64, length: usize) -> Test {
+    let mut torture = Torture {
+        rng: Generator::new(seed),
+        words: Vec::new(),
+        regs: [0; 32],
+        scratch: [0; SCRATCH_SIZE],
+    };
+
+    for reg in 1..CHECK {
+        let value = match reg {
+            SP => SCRATCH,
+            _ => torture.value(),
+        };
+        torture.words.extend(encode::li(reg, value as i32));
+        torture.regs[reg as usize] = value;
+    }
+    let setup = torture.words.len();
+    while torture.words.len() < setup + length {
+        match torture.below(8) {
+            0 => torture.skip(),
+            _ => torture.straight(true),
+        }
+    }
+
+    let mut program = torture
+        .words
+        .iter()
+        .fold(Program::at(0), |program, &word| program.word(word))
+        .li(CHECK, SIGNATURE as i32);
+    for reg in 1..CHECK {
+        program = program.sw(reg, CHECK, 4 * (reg as i32 - 1));
+    }
+    for reg in 1..CHECK {
+        let ok = format!("ok{}", reg);
+        program = program
+            .li(CHECK, torture.regs[reg as usize] as i32)
+            .beq(reg, CHECK, &ok)
+            .li(CHECK, ((reg as i32) << 1) | 1)
+            .j("report")
+            .label(&ok);
+    }
+    let image = program
+        .li(CHECK, 1)
+        .label("report")
+        .li(1, TOHOST as i32)
+        .sw(CHECK, 1, 0)
+        .label("halt")
+        .j("halt")
+        .build()
+        .expect("torture programs only use labels they define");
+
+    Test {
+        seed,
+        image,
+        signature: torture.regs[1..CHECK as usize].to_vec(),
+    }
+}
+
+// ── Runner ────────────────────────────────────────────────────────────────────
+
+/// Run `test` until it reports through `tohost`, and check the signature
+/// it stored.
+pub fn run(test: &Test, step_limit: u64) -> TortureOutcome {
+    let built = RiscvCpu::builder()
+        .ram_size(RAM_SIZE)
+        .image(0, test.image.clone())
+        .build();
+    let mut cpu = match built {
+        Ok(cpu) => cpu,
+        Err(e) => return TortureOutcome::Error(e),
+    };
+
+    let reported = riscv_tests::run_to_tohost(&mut cpu, TOHOST, step_limit);
+    let mut wrong = Vec::new();
+    for (i, &expected) in test.signature.iter().enumerate() {
+        let addr = SIGNATURE + 4 * i as u32;
+        match cpu.bus.read(addr, MemSize::Word) {
+            Some(actual) if actual == expected => {}
+            Some(actual) => wrong.push((i as u8 + 1, expected, actual)),
+            None => return TortureOutcome::Error(format!("{:#x} is not in RAM", addr)),
+        }
+    }
+
+    match reported {
+        TestOutcome::Pass | TestOutcome::Fail(_) if !wrong.is_empty() => {
+            TortureOutcome::Fail(wrong)
+        }
+        TestOutcome::Pass => TortureOutcome::Pass,
+        TestOutcome::Fail(reg) => TortureOutcome::Error(format!(
+            "the program's check failed on x{}, but its signature matches",
+            reg
+        )),
+        TestOutcome::Timeout => TortureOutcome::Timeout,
+        TestOutcome::Halted(e) => TortureOutcome::Halted(e),
+        TestOutcome::Error(e) => TortureOutcome::Error(e),
+    }
+}
+
+/// Generate and run a program for each seed in `seeds`.
+pub fn run_many(seeds: Range<u64>, length: usize, step_limit: u64) -> Vec<(u64, TortureOutcome)> {
+    seeds
+        .map(|seed| (seed, run(&generate(seed, length), step_limit)))
+        .collect()
+}
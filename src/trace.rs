@@ -0,0 +1,245 @@
+use std::io::{self, Write};
+
+use crate::MemSize;
+use crate::decode::Instruction;
+#[cfg(feature = "dwarf")]
+use crate::dwarf::LineTable;
+use crate::loader::SymbolTable;
+use crate::trap::{Exception, Interrupt};
+
+/// Receives diagnostics from the CPU. Every method defaults to doing nothing,
+/// so implementors only override what they care about. With no tracer
+/// installed the CPU skips these calls entirely.
+pub trait Tracer {
+    /// An instruction at `pc` has finished executing.
+    fn instruction(&mut self, _pc: u32, _raw: u32, _instruction: &Instruction) {}
+
+    /// The instruction at `pc` uses an opcode the CPU doesn't implement yet
+    /// and, with no unknown opcode handler set, was skipped.
+    fn unknown_opcode(&mut self, _pc: u32, _raw: u32) {}
+
+    fn exception(&mut self, _pc: u32, _exception: &Exception) {}
+
+    fn interrupt(&mut self, _pc: u32, _interrupt: Interrupt) {}
+
+    /// The instruction being executed wrote `value` to integer register
+    /// `reg`, which is never `x0`. Comes before the
+    /// [`instruction`](Self::instruction) call for it, or the
+    /// [`exception`](Self::exception) if it went on to fault.
+    fn register_write(&mut self, _reg: u8, _value: u64) {}
+
+    /// The instruction being executed loaded or stored `value` at virtual
+    /// address `addr`. Doubleword accesses come as two word accesses, low
+    /// word first. Ordered like [`register_write`](Self::register_write).
+    fn memory(&mut self, _addr: u64, _size: MemSize, _value: u32, _store: bool) {}
+}
+
+/// Prints one line per event, with disassembly, to any `Write`. Given
+/// symbols, it labels each PC with the function it's in; given a line
+/// table, each instruction with the source line it came from.
+pub struct PrintTracer {
+    output: Box<dyn Write>,
+    symbols: SymbolTable,
+    #[cfg(feature = "dwarf")]
+    lines: LineTable,
+}
+
+impl PrintTracer {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            symbols: SymbolTable::default(),
+            #[cfg(feature = "dwarf")]
+            lines: LineTable::default(),
+        }
+    }
+
+    /// E.g. `cpu.symbols().clone()` after loading an ELF.
+    pub fn symbols(mut self, symbols: SymbolTable) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// E.g. `cpu.line_table().clone()` after loading an ELF.
+    #[cfg(feature = "dwarf")]
+    pub fn lines(mut self, lines: LineTable) -> Self {
+        self.lines = lines;
+        self
+    }
+
+    /// `0x00000010` or `0x00000010 <main+0x8>`.
+    fn location(&self, pc: u32) -> String {
+        match self.symbols.describe(pc) {
+            Some(symbol) => format!("{:#010x} <{}>", pc, symbol),
+            None => format!("{:#010x}", pc),
+        }
+    }
+}
+
+impl Default for PrintTracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tracer for PrintTracer {
+    fn instruction(&mut self, pc: u32, raw: u32, instruction: &Instruction) {
+        #[cfg(feature = "dwarf")]
+        if let Some(source) = self.lines.location(pc) {
+            let pc = self.location(pc);
+            let _ = writeln!(
+                self.output,
+                "{}: {:08x}  {}  # {}",
+                pc, raw, instruction, source
+            );
+            return;
+        }
+        let pc = self.location(pc);
+        let _ = writeln!(self.output, "{}: {:08x}  {}", pc, raw, instruction);
+    }
+
+    fn unknown_opcode(&mut self, pc: u32, raw: u32) {
+        let pc = self.location(pc);
+        let _ = writeln!(self.output, "{}: {:08x}  <unknown opcode>", pc, raw);
+    }
+
+    fn exception(&mut self, pc: u32, exception: &Exception) {
+        let pc = self.location(pc);
+        let _ = writeln!(self.output, "{}: exception: {}", pc, exception);
+    }
+
+    fn interrupt(&mut self, pc: u32, interrupt: Interrupt) {
+        let pc = self.location(pc);
+        let _ = writeln!(self.output, "{}: interrupt: {:?}", pc, interrupt);
+    }
+}
+
+/// Writes one JSON object per line (NDJSON) for each retired instruction
+/// and each trap, for `jq` or `pandas.read_json(..., lines=True)`:
+///
+/// ```text
+/// {"pc":4,"insn":1081651,"mnemonic":"add","disasm":"add x2, x1, x1","rd":2,"value":10,"mem":[]}
+/// {"pc":8,"insn":270540835,"mnemonic":"sw","disasm":"sw x2, 256(x0)","rd":null,"value":null,"mem":[{"addr":256,"size":4,"value":10,"store":true}]}
+/// {"pc":12,"trap":"exception","cause":3,"tval":12,"message":"EBREAK at 0xc"}
+/// ```
+///
+/// `rd` and `value` are the last integer register written, if any. A
+/// trapping instruction gets only the trap record.
+pub struct JsonTracer {
+    output: Box<dyn Write>,
+    write: Option<(u8, u64)>,
+    memory: Vec<(u64, usize, u32, bool)>,
+}
+
+impl JsonTracer {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            write: None,
+            memory: Vec::new(),
+        }
+    }
+}
+
+impl Default for JsonTracer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `text` as a JSON string literal.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Tracer for JsonTracer {
+    fn instruction(&mut self, pc: u32, raw: u32, instruction: &Instruction) {
+        let disasm = instruction.to_string();
+        let mnemonic = disasm.split_whitespace().next().unwrap_or_default();
+        let (rd, value) = match self.write.take() {
+            Some((rd, value)) => (rd.to_string(), value.to_string()),
+            None => ("null".to_string(), "null".to_string()),
+        };
+        let memory: Vec<String> = self
+            .memory
+            .drain(..)
+            .map(|(addr, size, value, store)| {
+                format!(
+                    r#"{{"addr":{},"size":{},"value":{},"store":{}}}"#,
+                    addr, size, value, store
+                )
+            })
+            .collect();
+        let _ = writeln!(
+            self.output,
+            r#"{{"pc":{},"insn":{},"mnemonic":{},"disasm":{},"rd":{},"value":{},"mem":[{}]}}"#,
+            pc,
+            raw,
+            json_string(mnemonic),
+            json_string(&disasm),
+            rd,
+            value,
+            memory.join(",")
+        );
+    }
+
+    fn unknown_opcode(&mut self, pc: u32, raw: u32) {
+        self.write = None;
+        self.memory.clear();
+        let _ = writeln!(
+            self.output,
+            r#"{{"pc":{},"insn":{},"unknown_opcode":true}}"#,
+            pc, raw
+        );
+    }
+
+    fn exception(&mut self, pc: u32, exception: &Exception) {
+        self.write = None;
+        self.memory.clear();
+        let _ = writeln!(
+            self.output,
+            r#"{{"pc":{},"trap":"exception","cause":{},"tval":{},"message":{}}}"#,
+            pc,
+            exception.cause(),
+            exception.tval(),
+            json_string(&exception.to_string())
+        );
+    }
+
+    fn interrupt(&mut self, pc: u32, interrupt: Interrupt) {
+        let _ = writeln!(
+            self.output,
+            r#"{{"pc":{},"trap":"interrupt","cause":{}}}"#,
+            pc,
+            interrupt.code()
+        );
+    }
+
+    fn register_write(&mut self, reg: u8, value: u64) {
+        self.write = Some((reg, value));
+    }
+
+    fn memory(&mut self, addr: u64, size: MemSize, value: u32, store: bool) {
+        self.memory.push((addr, size.bytes(), value, store));
+    }
+}
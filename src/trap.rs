@@ -0,0 +1,195 @@
+use std::fmt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    SupervisorSoftware,
+    VirtualSupervisorSoftware,
+    MachineSoftware,
+    SupervisorTimer,
+    VirtualSupervisorTimer,
+    MachineTimer,
+    SupervisorExternal,
+    VirtualSupervisorExternal,
+    MachineExternal,
+}
+
+impl Interrupt {
+    /// Highest priority first, as laid out in the privileged spec.
+    pub const PRIORITY: [Interrupt; 9] = [
+        Interrupt::MachineExternal,
+        Interrupt::MachineSoftware,
+        Interrupt::MachineTimer,
+        Interrupt::SupervisorExternal,
+        Interrupt::SupervisorSoftware,
+        Interrupt::SupervisorTimer,
+        Interrupt::VirtualSupervisorExternal,
+        Interrupt::VirtualSupervisorSoftware,
+        Interrupt::VirtualSupervisorTimer,
+    ];
+
+    pub fn code(self) -> u32 {
+        match self {
+            Interrupt::SupervisorSoftware => 1,
+            Interrupt::VirtualSupervisorSoftware => 2,
+            Interrupt::MachineSoftware => 3,
+            Interrupt::SupervisorTimer => 5,
+            Interrupt::VirtualSupervisorTimer => 6,
+            Interrupt::MachineTimer => 7,
+            Interrupt::SupervisorExternal => 9,
+            Interrupt::VirtualSupervisorExternal => 10,
+            Interrupt::MachineExternal => 11,
+        }
+    }
+
+    /// The value written to `mcause` when this interrupt is taken.
+    pub fn cause(self) -> u32 {
+        0x8000_0000 | self.code()
+    }
+
+    /// The bit this interrupt occupies in `mip` and `mie`.
+    pub fn mask(self) -> u32 {
+        1 << self.code()
+    }
+}
+
+/// A synchronous exception raised while executing an instruction. The
+/// payload is what the hardware would put in `mtval`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exception {
+    InstructionAccessFault(u32),
+    IllegalInstruction(u32),
+    Breakpoint(u32),
+    LoadAccessFault(u32),
+    StoreAccessFault(u32),
+    /// ECALL from M-mode. `mtval` is always zero.
+    EnvironmentCall,
+    UserEnvironmentCall,
+    SupervisorEnvironmentCall,
+    /// Address translation failed; the payload is the virtual address.
+    InstructionPageFault(u32),
+    LoadPageFault(u32),
+    StorePageFault(u32),
+    /// ECALL from VS-mode.
+    VirtualSupervisorEnvironmentCall,
+    /// The G-stage of two-stage translation failed. The payloads are the
+    /// guest virtual address and the guest physical address shifted right
+    /// by 2, as written to `htval`/`mtval2`.
+    InstructionGuestPageFault(u32, u32),
+    LoadGuestPageFault(u32, u32),
+    StoreGuestPageFault(u32, u32),
+    /// Something V=1 can't do that HS-mode could. The payload is the
+    /// instruction.
+    VirtualInstruction(u32),
+}
+
+impl Exception {
+    /// The value written to `mcause` when this exception is taken.
+    pub fn cause(self) -> u32 {
+        match self {
+            Exception::InstructionAccessFault(_) => 1,
+            Exception::IllegalInstruction(_) => 2,
+            Exception::Breakpoint(_) => 3,
+            Exception::LoadAccessFault(_) => 5,
+            Exception::StoreAccessFault(_) => 7,
+            Exception::UserEnvironmentCall => 8,
+            Exception::SupervisorEnvironmentCall => 9,
+            Exception::EnvironmentCall => 11,
+            Exception::InstructionPageFault(_) => 12,
+            Exception::LoadPageFault(_) => 13,
+            Exception::StorePageFault(_) => 15,
+            Exception::VirtualSupervisorEnvironmentCall => 10,
+            Exception::InstructionGuestPageFault(..) => 20,
+            Exception::LoadGuestPageFault(..) => 21,
+            Exception::VirtualInstruction(_) => 22,
+            Exception::StoreGuestPageFault(..) => 23,
+        }
+    }
+
+    pub fn tval(self) -> u32 {
+        match self {
+            Exception::InstructionAccessFault(v)
+            | Exception::IllegalInstruction(v)
+            | Exception::Breakpoint(v)
+            | Exception::LoadAccessFault(v)
+            | Exception::StoreAccessFault(v)
+            | Exception::InstructionPageFault(v)
+            | Exception::LoadPageFault(v)
+            | Exception::StorePageFault(v)
+            | Exception::InstructionGuestPageFault(v, _)
+            | Exception::LoadGuestPageFault(v, _)
+            | Exception::StoreGuestPageFault(v, _)
+            | Exception::VirtualInstruction(v) => v,
+            Exception::EnvironmentCall
+            | Exception::UserEnvironmentCall
+            | Exception::SupervisorEnvironmentCall
+            | Exception::VirtualSupervisorEnvironmentCall => 0,
+        }
+    }
+
+    /// What goes in `htval` or `mtval2`: the guest physical address of a
+    /// guest-page fault, shifted right by 2, and zero for anything else.
+    pub fn tval2(self) -> u32 {
+        match self {
+            Exception::InstructionGuestPageFault(_, gpa)
+            | Exception::LoadGuestPageFault(_, gpa)
+            | Exception::StoreGuestPageFault(_, gpa) => gpa,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Exception::InstructionAccessFault(addr) => {
+                write!(f, "Instruction Access Fault: {:#x} is out of bounds", addr)
+            }
+            Exception::IllegalInstruction(inst) => {
+                write!(f, "Illegal Instruction: {:#010x}", inst)
+            }
+            Exception::Breakpoint(pc) => write!(f, "EBREAK at {:#x}", pc),
+            Exception::LoadAccessFault(addr) => {
+                write!(f, "Load Access Fault: {:#x} is out of bounds", addr)
+            }
+            Exception::StoreAccessFault(addr) => {
+                write!(f, "Store Access Fault: {:#x} is out of bounds", addr)
+            }
+            Exception::EnvironmentCall => write!(f, "ECALL"),
+            Exception::UserEnvironmentCall => write!(f, "ECALL from U-mode"),
+            Exception::SupervisorEnvironmentCall => write!(f, "ECALL from S-mode"),
+            Exception::InstructionPageFault(addr) => {
+                write!(f, "Instruction Page Fault at {:#x}", addr)
+            }
+            Exception::LoadPageFault(addr) => {
+                write!(f, "Load Page Fault at {:#x}", addr)
+            }
+            Exception::StorePageFault(addr) => {
+                write!(f, "Store Page Fault at {:#x}", addr)
+            }
+            Exception::VirtualSupervisorEnvironmentCall => write!(f, "ECALL from VS-mode"),
+            Exception::InstructionGuestPageFault(addr, gpa) => write!(
+                f,
+                "Instruction Guest-Page Fault at {:#x} (guest physical {:#x})",
+                addr,
+                (*gpa as u64) << 2
+            ),
+            Exception::LoadGuestPageFault(addr, gpa) => write!(
+                f,
+                "Load Guest-Page Fault at {:#x} (guest physical {:#x})",
+                addr,
+                (*gpa as u64) << 2
+            ),
+            Exception::StoreGuestPageFault(addr, gpa) => write!(
+                f,
+                "Store Guest-Page Fault at {:#x} (guest physical {:#x})",
+                addr,
+                (*gpa as u64) << 2
+            ),
+            Exception::VirtualInstruction(inst) => {
+                write!(f, "Virtual Instruction: {:#010x}", inst)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Exception {}
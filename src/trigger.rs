@@ -0,0 +1,66 @@
+//! Sdtrig's mcontrol triggers: hardware breakpoints and watchpoints the
+//! guest sets itself through `tselect`, `tdata1` and `tdata2`. A trigger
+//! that matches raises a breakpoint exception before the instruction runs
+//! or the access happens, with the address it matched in `xtval`.
+
+use crate::RiscvCpu;
+use crate::csr::{self, Privilege};
+use crate::trap::Exception;
+use crate::xlen::Xlen;
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// Whether any trigger can fire at all. The block engines step one
+    /// instruction at a time while one can.
+    pub(crate) fn triggers_armed(&self) -> bool {
+        self.csrs
+            .triggers()
+            .iter()
+            .any(|&[tdata1, _]| armed::<X>(tdata1))
+    }
+
+    /// Raise a breakpoint if a trigger matches `kind` (one of the mcontrol
+    /// execute, load and store bits) at `addr` in the current mode.
+    pub(crate) fn check_triggers(&mut self, kind: u32, addr: u64) -> Result<(), Exception> {
+        let mode = match self.privilege {
+            Privilege::User => csr::MCONTROL_U,
+            Privilege::Supervisor => csr::MCONTROL_S,
+            Privilege::Machine => csr::MCONTROL_M,
+        };
+        let fired = self.csrs.triggers().iter().position(|&[tdata1, tdata2]| {
+            armed::<X>(tdata1)
+                && tdata1 as u32 & (kind | mode) == kind | mode
+                && matches(tdata1 as u32, tdata2, addr)
+        });
+
+        match fired {
+            Some(n) => {
+                self.csrs.trigger_hit(n);
+                Err(Exception::Breakpoint(addr as u32))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+fn armed<X: Xlen>(tdata1: u64) -> bool {
+    let kinds = csr::MCONTROL_EXECUTE | csr::MCONTROL_LOAD | csr::MCONTROL_STORE;
+    let modes = csr::MCONTROL_M | csr::MCONTROL_S | csr::MCONTROL_U;
+    tdata1 >> (X::BITS - 4) == csr::TRIGGER_MCONTROL
+        && tdata1 as u32 & kinds != 0
+        && tdata1 as u32 & modes != 0
+}
+
+/// Compare `addr` with `tdata2` as mcontrol's match field says.
+fn matches(tdata1: u32, tdata2: u64, addr: u64) -> bool {
+    match (tdata1 & csr::MCONTROL_MATCH) >> 7 {
+        0 => addr == tdata2,
+        // NAPOT: the trailing ones of `tdata2` and the zero above them say
+        // how many low bits are ignored.
+        1 => {
+            let ignored = (tdata2.trailing_ones() + 1).min(63);
+            (addr ^ tdata2) >> ignored == 0
+        }
+        2 => addr >= tdata2,
+        _ => addr < tdata2,
+    }
+}
@@ -0,0 +1,286 @@
+//! Running Linux user-mode programs, as `qemu-riscv32` does: the ELF is
+//! loaded straight into RAM and started the way the kernel's `execve`
+//! would start it, with no kernel or firmware underneath.
+//!
+//! The startup stack follows the psABI. From `sp` up there's `argc`, the
+//! `argv` pointers and a null, the `envp` pointers and a null, then the
+//! auxiliary vector, each entry an XLEN-sized type and value, ending with
+//! `AT_NULL`. The strings and `AT_RANDOM`'s bytes are above that, at the
+//! top of the stack.
+//!
+//! The stack gets [`DEFAULT_STACK_SIZE`] bytes, or what
+//! [`Process::stack_size`] says, and `mmap` hands out memory below that.
+//! The heap starts at the first page after the image. See [`syscall`](crate::syscall).
+
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use crate::loader::ElfFile;
+use crate::syscall::Syscalls;
+use crate::xlen::Xlen;
+use crate::{Reg, RiscvCpu, csr};
+
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_BASE: u64 = 7;
+pub const AT_FLAGS: u64 = 8;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_UID: u64 = 11;
+pub const AT_EUID: u64 = 12;
+pub const AT_GID: u64 = 13;
+pub const AT_EGID: u64 = 14;
+pub const AT_HWCAP: u64 = 16;
+pub const AT_CLKTCK: u64 = 17;
+pub const AT_SECURE: u64 = 23;
+pub const AT_RANDOM: u64 = 25;
+pub const AT_EXECFN: u64 = 31;
+
+pub const PAGE_SIZE: u64 = 4096;
+
+/// Room left for the stack before `mmap` can use memory, as with Linux's
+/// default `ulimit -s`.
+pub const DEFAULT_STACK_SIZE: u32 = 8 << 20;
+
+/// The psABI wants `sp` 16-byte aligned at the entry point.
+const STACK_ALIGN: u64 = 16;
+
+/// The arguments, environment and stack a user-mode program starts with.
+///
+/// ```no_run
+/// # use riscv_emulator_rust::{RiscvCpu, user::Process};
+/// # let elf = Vec::new();
+/// let mut cpu = RiscvCpu::builder().ram_size(64 << 20).build()?;
+/// let process = Process::new("hello").arg("world").env("HOME", "/");
+/// cpu.load_process(&elf, &process)?;
+/// # Ok::<(), String>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Process {
+    args: Vec<String>,
+    env: Vec<String>,
+    stack_top: Option<u32>,
+    stack_size: u32,
+    #[cfg(feature = "std")]
+    sandbox: Option<PathBuf>,
+    random: [u8; 16],
+}
+
+impl Process {
+    /// A program started as `name`, which becomes `argv[0]`.
+    pub fn new(name: &str) -> Self {
+        Self {
+            args: vec![name.to_string()],
+            env: Vec::new(),
+            stack_top: None,
+            stack_size: DEFAULT_STACK_SIZE,
+            #[cfg(feature = "std")]
+            sandbox: None,
+            random: *b"riscv-emu random",
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args<S: AsRef<str>>(mut self, args: impl IntoIterator<Item = S>) -> Self {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_string()));
+        self
+    }
+
+    /// Add `name=value` to the environment.
+    pub fn env(mut self, name: &str, value: &str) -> Self {
+        self.env.push(format!("{}={}", name, value));
+        self
+    }
+
+    /// Build the stack down from here rather than from the end of RAM.
+    pub fn stack_top(mut self, addr: u32) -> Self {
+        self.stack_top = Some(addr);
+        self
+    }
+
+    /// How far the stack may grow down before it would run into memory
+    /// from `mmap`. Shrink it to run in a small RAM.
+    pub fn stack_size(mut self, bytes: u32) -> Self {
+        self.stack_size = bytes;
+        self
+    }
+
+    /// Let the program open files under `root`, which it sees as `/`.
+    /// Without one it can only use the console.
+    #[cfg(feature = "std")]
+    pub fn sandbox(mut self, root: impl Into<PathBuf>) -> Self {
+        self.sandbox = Some(root.into());
+        self
+    }
+
+    /// The 16 bytes `AT_RANDOM` points at, which libc seeds its stack
+    /// protector and pointer mangling from. Fixed by default, so runs are
+    /// repeatable.
+    pub fn random(mut self, bytes: [u8; 16]) -> Self {
+        self.random = bytes;
+        self
+    }
+
+    pub fn arguments(&self) -> &[String] {
+        &self.args
+    }
+
+    /// `name=value` strings, in the order they were added.
+    pub fn environment(&self) -> &[String] {
+        &self.env
+    }
+}
+
+/// Load `bytes` with [`RiscvCpu::load_elf`], which zeroes `.bss`, then
+/// build the startup stack and set `sp`, and `gp` from
+/// `__global_pointer$` if the image has one. The other registers are
+/// cleared, so `a0`, the `atexit` function the kernel can pass, is null.
+/// ECALLs go to a fresh [`Syscalls`] from then on.
+pub(crate) fn start<X: Xlen>(
+    cpu: &mut RiscvCpu<X>,
+    bytes: &[u8],
+    process: &Process,
+) -> Result<(), String> {
+    let elf = ElfFile::parse(bytes)?;
+    cpu.load_elf(bytes)?;
+
+    let top = match process.stack_top {
+        Some(top) => top as u64,
+        None => (cpu.bus.ram_base() as u64 + cpu.bus.len() as u64).min(u32::MAX as u64),
+    };
+    let mut stack = Stack {
+        cpu: &mut *cpu,
+        sp: top,
+    };
+
+    // Strings first, from the top down: the name the program was run as,
+    // the environment, the arguments, then the random bytes.
+    let execfn = stack.push_str(&process.args[0])?;
+    let envp = process
+        .env
+        .iter()
+        .rev()
+        .map(|var| stack.push_str(var))
+        .collect::<Result<Vec<_>, _>>()?;
+    let argv = process
+        .args
+        .iter()
+        .rev()
+        .map(|arg| stack.push_str(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    let random = stack.push(&process.random)?;
+
+    let hwcap = X::widen(stack.cpu.csrs.read(csr::MISA)) & ((1 << 26) - 1);
+    let auxv = [
+        (AT_PHDR, elf.program_headers_addr().unwrap_or(0) as u64),
+        (AT_PHENT, elf.phentsize as u64),
+        (AT_PHNUM, elf.segments.len() as u64),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_BASE, 0),
+        (AT_FLAGS, 0),
+        (AT_ENTRY, elf.entry as u64),
+        (AT_UID, 0),
+        (AT_EUID, 0),
+        (AT_GID, 0),
+        (AT_EGID, 0),
+        (AT_HWCAP, hwcap),
+        (AT_CLKTCK, 100),
+        (AT_SECURE, 0),
+        (AT_RANDOM, random),
+        (AT_EXECFN, execfn),
+        (AT_NULL, 0),
+    ];
+
+    let mut table = vec![argv.len() as u64];
+    table.extend(argv.iter().rev());
+    table.push(0);
+    table.extend(envp.iter().rev());
+    table.push(0);
+    table.extend(auxv.iter().flat_map(|&(kind, value)| [kind, value]));
+
+    let width = X::BITS as u64 / 8;
+    let bytes: Vec<u8> = table
+        .iter()
+        .flat_map(|word| word.to_le_bytes()[..width as usize].to_vec())
+        .collect();
+    let sp = stack.push_aligned(&bytes)?;
+
+    let image_end = elf
+        .loadable_segments()
+        .map(|s| s.vaddr as u64 + s.memsz as u64)
+        .max()
+        .unwrap_or(0);
+    let heap = page_align_up(image_end).min(u32::MAX as u64 & !(PAGE_SIZE - 1));
+    let mmap_top = top.saturating_sub(process.stack_size as u64) & !(PAGE_SIZE - 1);
+    let syscalls = Syscalls::new(heap as u32, mmap_top as u32, X::BITS == 64);
+    #[cfg(feature = "std")]
+    let syscalls = syscalls.sandbox(process.sandbox.clone());
+    cpu.syscalls = Some(syscalls);
+
+    let gp = elf.symbol("__global_pointer$").unwrap_or(0);
+    cpu.regs = [X::truncate(0); 32];
+    cpu.set_reg(Reg::Sp, sp);
+    cpu.set_reg(Reg::Gp, gp as u64);
+
+    Ok(())
+}
+
+/// Pushes things onto a downward-growing stack in guest RAM.
+struct Stack<'a, X: Xlen> {
+    cpu: &'a mut RiscvCpu<X>,
+    sp: u64,
+}
+
+impl<X: Xlen> Stack<'_, X> {
+    /// Push `s` with a terminating NUL, returning where it went.
+    fn push_str(&mut self, s: &str) -> Result<u64, String> {
+        let mut bytes = s.as_bytes().to_vec();
+        bytes.push(0);
+        self.push(&bytes)
+    }
+
+    fn push(&mut self, bytes: &[u8]) -> Result<u64, String> {
+        self.sp = self
+            .sp
+            .checked_sub(bytes.len() as u64)
+            .ok_or_else(too_big)?;
+        let sp = self.sp;
+        self.write(sp, bytes)?;
+        Ok(sp)
+    }
+
+    /// Like [`push`](Self::push), but starting on a [`STACK_ALIGN`]
+    /// boundary.
+    fn push_aligned(&mut self, bytes: &[u8]) -> Result<u64, String> {
+        let sp = self
+            .sp
+            .checked_sub(bytes.len() as u64)
+            .ok_or_else(too_big)?;
+        self.sp = sp & !(STACK_ALIGN - 1);
+        let sp = self.sp;
+        self.write(sp, bytes)?;
+        Ok(sp)
+    }
+
+    fn write(&mut self, addr: u64, bytes: &[u8]) -> Result<(), String> {
+        u32::try_from(addr)
+            .ok()
+            .and_then(|addr| self.cpu.bus.write_bytes(addr, bytes))
+            .ok_or_else(|| format!("Process: stack at {:#x} is outside RAM", addr))
+    }
+}
+
+fn page_align_up(addr: u64) -> u64 {
+    addr.div_ceil(PAGE_SIZE) * PAGE_SIZE
+}
+
+fn too_big() -> String {
+    String::from("Process: arguments and environment don't fit below the stack top")
+}
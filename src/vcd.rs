@@ -0,0 +1,262 @@
+//! Value change dumps of architectural state, for looking at a run in
+//! GTKWave next to an RTL simulation's waveforms.
+//!
+//! Every integer register and the PC are traced, plus whichever CSRs and
+//! memory words are asked for. The state is sampled after each step, so
+//! `pc` is the next instruction to execute and the registers hold what the
+//! previous one left. Time advances by one per step, or with
+//! [`cycles`](Vcd::cycles) by the cycles `mcycle` counted.
+
+use std::io::{self, Write};
+
+use crate::xlen::Xlen;
+use crate::{ExitReason, MemSize, RiscvCpu, csr};
+
+#[derive(Clone, Copy)]
+enum Source {
+    Pc,
+    Reg(u8),
+    Csr(u16),
+    Memory(u64, MemSize),
+}
+
+struct Signal {
+    name: String,
+    source: Source,
+    /// The value last dumped. `None` is unknown, dumped as `x`.
+    last: Option<Option<u64>>,
+}
+
+/// Writes a VCD file as a hart runs. Add signals, then call
+/// [`sample`](Self::sample) after each step or have [`run`](Self::run) do
+/// it.
+///
+/// ```no_run
+/// # use riscv_emulator_rust::{MemSize, RiscvCpu, csr, vcd::Vcd};
+/// # let mut cpu = RiscvCpu::new(64 * 1024);
+/// let file = std::fs::File::create("run.vcd").unwrap();
+/// let mut vcd = Vcd::new(Box::new(std::io::BufWriter::new(file)))
+///     .csr("mcause", csr::MCAUSE)
+///     .memory("counter", 0x1000, MemSize::Word);
+/// vcd.run(&mut cpu, Some(10_000)).unwrap();
+/// ```
+pub struct Vcd {
+    output: Box<dyn Write>,
+    signals: Vec<Signal>,
+    cycles: bool,
+    /// The time last written, once the header has been.
+    time: Option<u64>,
+    steps: u64,
+}
+
+impl Vcd {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        let mut signals = vec![Signal::new("pc", Source::Pc)];
+        signals.extend((1..32).map(|n| Signal::new(&format!("x{}", n), Source::Reg(n))));
+        Self {
+            output,
+            signals,
+            cycles: false,
+            time: None,
+            steps: 0,
+        }
+    }
+
+    /// Also trace the CSR at `addr`, in a `csr` scope.
+    pub fn csr(mut self, name: &str, addr: u16) -> Self {
+        assert!(
+            self.time.is_none(),
+            "Vcd: signals must be added before sampling"
+        );
+        self.signals.push(Signal::new(name, Source::Csr(addr)));
+        self
+    }
+
+    /// Also trace the RAM at physical address `addr`, in a `mem` scope.
+    /// Outside RAM it reads as unknown. Device registers aren't read, as
+    /// reading some has side effects.
+    pub fn memory(mut self, name: &str, addr: u64, size: MemSize) -> Self {
+        assert!(
+            self.time.is_none(),
+            "Vcd: signals must be added before sampling"
+        );
+        self.signals
+            .push(Signal::new(name, Source::Memory(addr, size)));
+        self
+    }
+
+    /// Time the dump by `mcycle` rather than by steps, so cycle costs and
+    /// pipeline stalls stretch it out. Should the guest wind `mcycle`
+    /// back, time stands still until it catches up.
+    pub fn cycles(mut self) -> Self {
+        self.cycles = true;
+        self
+    }
+
+    /// Dump whatever changed since the last sample. The first sample
+    /// writes the header and every signal's value.
+    pub fn sample<X: Xlen>(&mut self, cpu: &mut RiscvCpu<X>) -> io::Result<()> {
+        let now = if self.cycles { cycles(cpu) } else { self.steps };
+        self.steps += 1;
+
+        let values: Vec<Option<u64>> = self
+            .signals
+            .iter()
+            .map(|signal| read(cpu, signal.source))
+            .collect();
+
+        let Some(time) = self.time else {
+            self.header(X::BITS)?;
+            writeln!(self.output, "#{}", now)?;
+            writeln!(self.output, "$dumpvars")?;
+            for (i, value) in values.into_iter().enumerate() {
+                self.change(i, value)?;
+            }
+            writeln!(self.output, "$end")?;
+            self.time = Some(now);
+            return Ok(());
+        };
+
+        let mut stamped = false;
+        for (i, value) in values.into_iter().enumerate() {
+            if self.signals[i].last == Some(value) {
+                continue;
+            }
+            if !stamped && now > time {
+                writeln!(self.output, "#{}", now)?;
+                self.time = Some(now);
+            }
+            stamped = true;
+            self.change(i, value)?;
+        }
+        Ok(())
+    }
+
+    /// Run `cpu` one step at a time, sampling before the first and after
+    /// each, until it stops or has taken `limit` steps.
+    pub fn run<X: Xlen>(
+        &mut self,
+        cpu: &mut RiscvCpu<X>,
+        limit: Option<u64>,
+    ) -> io::Result<ExitReason> {
+        self.sample(cpu)?;
+        let mut steps = 0;
+        loop {
+            if limit.is_some_and(|limit| steps >= limit) {
+                return Ok(ExitReason::StepLimit);
+            }
+            let exit = cpu.run_steps(1);
+            steps += 1;
+            self.sample(cpu)?;
+            if !matches!(exit, ExitReason::StepLimit) {
+                return Ok(exit);
+            }
+        }
+    }
+
+    fn header(&mut self, xlen: u32) -> io::Result<()> {
+        writeln!(self.output, "$version riscv-emulator-rust $end")?;
+        writeln!(self.output, "$timescale 1ns $end")?;
+        writeln!(self.output, "$scope module hart $end")?;
+
+        let mut scope = "hart";
+        for (i, signal) in self.signals.iter().enumerate() {
+            let (wanted, width) = match signal.source {
+                Source::Pc | Source::Reg(_) => ("hart", xlen),
+                Source::Csr(_) => ("csr", xlen),
+                Source::Memory(_, size) => ("mem", 8 * size.bytes() as u32),
+            };
+            if wanted != scope {
+                if scope != "hart" {
+                    writeln!(self.output, "$upscope $end")?;
+                }
+                writeln!(self.output, "$scope module {} $end", wanted)?;
+                scope = wanted;
+            }
+            writeln!(
+                self.output,
+                "$var wire {} {} {} $end",
+                width,
+                identifier(i),
+                signal.name
+            )?;
+        }
+        if scope != "hart" {
+            writeln!(self.output, "$upscope $end")?;
+        }
+
+        writeln!(self.output, "$upscope $end")?;
+        writeln!(self.output, "$enddefinitions $end")
+    }
+
+    fn change(&mut self, i: usize, value: Option<u64>) -> io::Result<()> {
+        self.signals[i].last = Some(value);
+        let id = identifier(i);
+        match value {
+            Some(value) => writeln!(self.output, "b{:b} {}", value, id),
+            None => writeln!(self.output, "bx {}", id),
+        }
+    }
+}
+
+impl Signal {
+    fn new(name: &str, source: Source) -> Self {
+        assert!(
+            !name.is_empty() && !name.contains(char::is_whitespace),
+            "Vcd: signal names can't be empty or contain spaces: {:?}",
+            name
+        );
+        Self {
+            name: name.to_string(),
+            source,
+            last: None,
+        }
+    }
+}
+
+impl Drop for Vcd {
+    fn drop(&mut self) {
+        let _ = self.output.flush();
+    }
+}
+
+fn read<X: Xlen>(cpu: &mut RiscvCpu<X>, source: Source) -> Option<u64> {
+    match source {
+        Source::Pc => Some(X::widen(cpu.pc)),
+        Source::Reg(n) => Some(X::widen(cpu.regs[n as usize])),
+        Source::Csr(addr) => Some(X::widen(cpu.csrs.read(addr))),
+        Source::Memory(addr, size) => {
+            let mut bytes = [0; 4];
+            let bytes = &mut bytes[..size.bytes()];
+            cpu.bus.dma().read(addr, bytes)?;
+            Some(
+                bytes
+                    .iter()
+                    .rev()
+                    .fold(0, |value, &b| value << 8 | b as u64),
+            )
+        }
+    }
+}
+
+fn cycles<X: Xlen>(cpu: &RiscvCpu<X>) -> u64 {
+    let low = X::widen(cpu.csrs.read(csr::MCYCLE));
+    if X::BITS == 32 {
+        X::widen(cpu.csrs.read(csr::MCYCLEH)) << 32 | low
+    } else {
+        low
+    }
+}
+
+/// Short identifiers from the printable ASCII VCD allows: `!`, `"`, ...
+fn identifier(mut n: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (n % 94) as u8) as char);
+        n /= 94;
+        if n == 0 {
+            return id;
+        }
+        n -= 1;
+    }
+}
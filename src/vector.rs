@@ -0,0 +1,417 @@
+//! A practical subset of the V extension: `vset{i}vl{i}`, unit-stride loads
+//! and stores, and integer arithmetic, compares and sums, for any power-of-two
+//! VLEN.
+//!
+//! LMUL may be 1 to 8; fractional LMUL sets `vill`. Masked-off and tail
+//! elements are left undisturbed, which the agnostic policies also allow.
+
+use crate::csr::{self, HpmEvent};
+use crate::debug::WatchKind;
+use crate::decode::{VectorInstruction, VectorOp, VectorOperand};
+use crate::mmu::Access;
+use crate::trap::Exception;
+use crate::xlen::Xlen;
+use crate::{MemSize, RiscvCpu};
+
+/// VLEN when the builder isn't asked for another.
+pub const DEFAULT_VLEN: usize = 128;
+
+/// The 32 vector registers back to back, so a register group is one
+/// contiguous run of bytes.
+pub(crate) struct VectorRegs {
+    vlen: usize,
+    bytes: Vec<u8>,
+}
+
+impl VectorRegs {
+    pub(crate) fn new(vlen: usize) -> Self {
+        Self {
+            vlen,
+            bytes: vec![0; vlen / 8 * 32],
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub(crate) fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    fn offset(&self, reg: u8, index: usize, sew: usize) -> usize {
+        reg as usize * self.vlen / 8 + index * sew / 8
+    }
+
+    /// Element `index` of the group starting at `reg`, zero-extended.
+    fn element(&self, reg: u8, index: usize, sew: usize) -> u64 {
+        let start = self.offset(reg, index, sew);
+        let mut bytes = [0; 8];
+        bytes[..sew / 8].copy_from_slice(&self.bytes[start..start + sew / 8]);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn set_element(&mut self, reg: u8, index: usize, sew: usize, value: u64) {
+        let start = self.offset(reg, index, sew);
+        self.bytes[start..start + sew / 8].copy_from_slice(&value.to_le_bytes()[..sew / 8]);
+    }
+
+    fn mask(&self, reg: u8, index: usize) -> bool {
+        self.bytes[self.offset(reg, index / 8, 8)] >> (index % 8) & 1 != 0
+    }
+
+    fn set_mask(&mut self, reg: u8, index: usize, value: bool) {
+        let byte = self.offset(reg, index / 8, 8);
+        self.bytes[byte] = (self.bytes[byte] & !(1 << (index % 8))) | (value as u8) << (index % 8);
+    }
+}
+
+/// The supported settings of `vtype`.
+#[derive(Clone, Copy)]
+struct Vtype {
+    sew: usize,
+    lmul: usize,
+}
+
+impl Vtype {
+    /// `None` for anything that should set `vill`: reserved bits,
+    /// fractional LMUL or SEW above 64.
+    fn from_bits(bits: u64) -> Option<Self> {
+        if bits >> 8 != 0 || bits & 0x7 > 3 || (bits >> 3) & 0x7 > 3 {
+            return None;
+        }
+        Some(Self {
+            sew: 8 << ((bits >> 3) & 0x7),
+            lmul: 1 << (bits & 0x7),
+        })
+    }
+
+    fn vlmax(self, vlen: usize) -> u64 {
+        (vlen / self.sew * self.lmul) as u64
+    }
+}
+
+fn sew_mask(sew: usize) -> u64 {
+    u64::MAX >> (64 - sew)
+}
+
+fn signed(value: u64, sew: usize) -> i64 {
+    ((value << (64 - sew)) as i64) >> (64 - sew)
+}
+
+impl<X: Xlen> RiscvCpu<X> {
+    /// VLEN in bits.
+    pub fn vlen(&self) -> usize {
+        self.vector.vlen
+    }
+
+    /// The bytes of vector register `n`, element 0 first.
+    pub fn vreg(&self, n: u8) -> &[u8] {
+        let vlenb = self.vlen() / 8;
+        &self.vector.bytes[n as usize * vlenb..][..vlenb]
+    }
+
+    pub fn vreg_mut(&mut self, n: u8) -> &mut [u8] {
+        let vlenb = self.vlen() / 8;
+        &mut self.vector.bytes[n as usize * vlenb..][..vlenb]
+    }
+
+    /// Replace the vector registers with zeroed ones `vlen` bits wide.
+    pub(crate) fn set_vlen(&mut self, vlen: usize) {
+        self.vector = VectorRegs::new(vlen);
+        self.csrs.set_u64(csr::VLENB, (vlen / 8) as u64);
+    }
+
+    fn vtype(&self) -> Option<Vtype> {
+        Vtype::from_bits(self.csrs.read_u64(csr::VTYPE))
+    }
+
+    /// Whether `instruction` can run under the current `vtype`: it must be
+    /// valid, and register groups must be aligned to their size. A masked
+    /// instruction can't overwrite its own mask.
+    pub(crate) fn vector_permitted(&self, instruction: VectorInstruction) -> bool {
+        use VectorInstruction::*;
+
+        let Some(vtype) = self.vtype() else {
+            return matches!(
+                instruction,
+                Vsetvli { .. } | Vsetivli { .. } | Vsetvl { .. }
+            );
+        };
+        let aligned = |reg: u8| (reg as usize).is_multiple_of(vtype.lmul);
+        let operand_aligned = |src: VectorOperand| match src {
+            VectorOperand::Vector(vs1) => aligned(vs1),
+            _ => true,
+        };
+
+        match instruction {
+            Vload {
+                eew, vd, masked, ..
+            }
+            | Vstore {
+                eew,
+                vs3: vd,
+                masked,
+                ..
+            } => {
+                // The data's group size scales with EEW/SEW.
+                let emul = eew as usize * vtype.lmul;
+                let load = matches!(instruction, Vload { .. });
+                emul >= vtype.sew
+                    && emul / vtype.sew <= 8
+                    && (vd as usize).is_multiple_of(emul / vtype.sew)
+                    && !(load && masked && vd == 0)
+            }
+            Varith { op, vs2, src, .. } if op.is_compare() => aligned(vs2) && operand_aligned(src),
+            Varith {
+                vd,
+                vs2,
+                src,
+                masked,
+                ..
+            } => aligned(vd) && aligned(vs2) && operand_aligned(src) && !(masked && vd == 0),
+            Vredsum { vs2, .. } => aligned(vs2),
+            _ => true,
+        }
+    }
+
+    pub(crate) fn execute_vector(
+        &mut self,
+        instruction: VectorInstruction,
+    ) -> Result<(), Exception> {
+        use VectorInstruction::*;
+
+        match instruction {
+            Vsetvli { rd, rs1, vtype } => {
+                let avl = (rs1 != 0).then(|| self.read_reg(rs1));
+                self.set_vtype(rd, avl, vtype as u64);
+                return Ok(());
+            }
+            Vsetivli { rd, avl, vtype } => {
+                self.set_vtype(rd, Some(avl as u64), vtype as u64);
+                return Ok(());
+            }
+            Vsetvl { rd, rs1, rs2 } => {
+                let avl = (rs1 != 0).then(|| self.read_reg(rs1));
+                self.set_vtype(rd, avl, self.read_reg(rs2));
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let vtype = self.vtype().expect("checked by vector_permitted");
+        let sew = vtype.sew;
+        let (start, vl) = (
+            self.csrs.read_u64(csr::VSTART) as usize,
+            self.csrs.read_u64(csr::VL) as usize,
+        );
+        let active = |cpu: &Self, masked: bool, i: usize| !masked || cpu.vector.mask(0, i);
+
+        match instruction {
+            Vload {
+                eew,
+                vd,
+                rs1,
+                masked,
+            } => self.vector_access(eew as usize, vd, rs1, masked, false)?,
+            Vstore {
+                eew,
+                vs3,
+                rs1,
+                masked,
+            } => self.vector_access(eew as usize, vs3, rs1, masked, true)?,
+
+            Varith {
+                op,
+                vd,
+                vs2,
+                src,
+                masked,
+            } => {
+                // Work out every result before writing any, in case the
+                // destination overlaps a source.
+                let mut results = Vec::with_capacity(vl.saturating_sub(start));
+                for i in start..vl {
+                    let merge = op == VectorOp::Merge;
+                    if !merge && !active(self, masked, i) {
+                        continue;
+                    }
+                    let a = self.vector.element(vs2, i, sew);
+                    let b = match src {
+                        VectorOperand::Vector(vs1) => self.vector.element(vs1, i, sew),
+                        VectorOperand::Scalar(rs1) => self.sreg(rs1) as u64,
+                        VectorOperand::Imm(imm) => imm as i64 as u64,
+                    } & sew_mask(sew);
+
+                    let value = match op {
+                        VectorOp::Merge if active(self, masked, i) => b,
+                        VectorOp::Merge => a,
+                        _ => vector_op(op, a, b, sew),
+                    };
+                    results.push((i, value));
+                }
+
+                for (i, value) in results {
+                    if op.is_compare() {
+                        self.vector.set_mask(vd, i, value != 0);
+                    } else {
+                        self.vector.set_element(vd, i, sew, value & sew_mask(sew));
+                    }
+                }
+            }
+            Vredsum {
+                vd,
+                vs2,
+                vs1,
+                masked,
+            } => {
+                if start < vl {
+                    let mut sum = self.vector.element(vs1, 0, sew);
+                    for i in (start..vl).filter(|&i| active(self, masked, i)) {
+                        sum = sum.wrapping_add(self.vector.element(vs2, i, sew));
+                    }
+                    self.vector.set_element(vd, 0, sew, sum & sew_mask(sew));
+                }
+            }
+            VmvXS { rd, vs2 } => {
+                let value = signed(self.vector.element(vs2, 0, sew), sew);
+                self.write_reg(rd, value as u64);
+            }
+            VmvSX { vd, rs1 } => {
+                if start < vl {
+                    let value = self.read_reg(rs1) & sew_mask(sew);
+                    self.vector.set_element(vd, 0, sew, value);
+                }
+            }
+            Vsetvli { .. } | Vsetivli { .. } | Vsetvl { .. } => unreachable!(),
+        }
+
+        self.csrs.set_u64(csr::VSTART, 0);
+        Ok(())
+    }
+
+    /// `vl = min(AVL, VLMAX)`. No AVL means VLMAX if `rd` is written, or
+    /// keeping the current `vl` otherwise.
+    fn set_vtype(&mut self, rd: u8, avl: Option<u64>, vtype: u64) {
+        let (vtype, vl) = match Vtype::from_bits(vtype) {
+            Some(parsed) => {
+                let avl = match avl {
+                    Some(avl) => avl,
+                    None if rd != 0 => u64::MAX,
+                    None => self.csrs.read_u64(csr::VL),
+                };
+                (vtype, avl.min(parsed.vlmax(self.vlen())))
+            }
+            None => (1 << (X::BITS - 1), 0),
+        };
+
+        self.csrs.set_u64(csr::VTYPE, vtype);
+        self.csrs.set_u64(csr::VL, vl);
+        self.csrs.set_u64(csr::VSTART, 0);
+        self.write_reg(rd, vl);
+    }
+
+    /// A unit-stride load or store of elements `vstart..vl`. A fault leaves
+    /// `vstart` at the element that caused it.
+    fn vector_access(
+        &mut self,
+        eew: usize,
+        reg: u8,
+        rs1: u8,
+        masked: bool,
+        store: bool,
+    ) -> Result<(), Exception> {
+        let base = self.read_reg(rs1);
+        let start = self.csrs.read_u64(csr::VSTART) as usize;
+        let vl = self.csrs.read_u64(csr::VL) as usize;
+
+        for i in start..vl {
+            if masked && !self.vector.mask(0, i) {
+                continue;
+            }
+            let vaddr = base.wrapping_add((i * eew / 8) as u64) & X::MASK;
+            let result = match store {
+                true => self.store_element(vaddr, eew, self.vector.element(reg, i, eew)),
+                false => self
+                    .load_element(vaddr, eew)
+                    .map(|value| self.vector.set_element(reg, i, eew, value)),
+            };
+            if let Err(e) = result {
+                self.csrs.set_u64(csr::VSTART, i as u64);
+                return Err(e);
+            }
+        }
+
+        let len = (vl.saturating_sub(start) * eew / 8) as u32;
+        let (kind, event) = match store {
+            true => (WatchKind::Write, HpmEvent::Store),
+            false => (WatchKind::Read, HpmEvent::Load),
+        };
+        if len > 0 {
+            let addr = base.wrapping_add((start * eew / 8) as u64) & X::MASK;
+            self.data_access(addr, len, kind);
+        }
+        self.csrs.count(event);
+
+        Ok(())
+    }
+
+    // The bus is at most word-wide, so 64-bit elements are two accesses.
+
+    fn load_element(&mut self, vaddr: u64, eew: usize) -> Result<u64, Exception> {
+        let size = match eew {
+            8 => MemSize::Byte,
+            16 => MemSize::Half,
+            _ => MemSize::Word,
+        };
+        let low = self.read_virt(vaddr, size, Access::Load)? as u64;
+        if eew < 64 {
+            return Ok(low);
+        }
+        let high = self.read_virt(vaddr.wrapping_add(4) & X::MASK, size, Access::Load)?;
+        Ok((high as u64) << 32 | low)
+    }
+
+    fn store_element(&mut self, vaddr: u64, eew: usize, value: u64) -> Result<(), Exception> {
+        let size = match eew {
+            8 => MemSize::Byte,
+            16 => MemSize::Half,
+            _ => MemSize::Word,
+        };
+        self.write_virt(vaddr, size, value as u32)?;
+        if eew == 64 {
+            self.write_virt(vaddr.wrapping_add(4) & X::MASK, size, (value >> 32) as u32)?;
+        }
+        Ok(())
+    }
+}
+
+/// One element of a `Varith`, with compares giving 0 or 1.
+fn vector_op(op: VectorOp, a: u64, b: u64, sew: usize) -> u64 {
+    let (sa, sb) = (signed(a, sew), signed(b, sew));
+    let shamt = b & (sew as u64 - 1);
+
+    match op {
+        VectorOp::Add => a.wrapping_add(b),
+        VectorOp::Sub => a.wrapping_sub(b),
+        VectorOp::Rsub => b.wrapping_sub(a),
+        VectorOp::Minu => a.min(b),
+        VectorOp::Min => sa.min(sb) as u64,
+        VectorOp::Maxu => a.max(b),
+        VectorOp::Max => sa.max(sb) as u64,
+        VectorOp::And => a & b,
+        VectorOp::Or => a | b,
+        VectorOp::Xor => a ^ b,
+        VectorOp::Sll => a << shamt,
+        VectorOp::Srl => a >> shamt,
+        VectorOp::Sra => (sa >> shamt) as u64,
+        VectorOp::Mul => a.wrapping_mul(b),
+        VectorOp::Mseq => (a == b) as u64,
+        VectorOp::Msne => (a != b) as u64,
+        VectorOp::Msltu => (a < b) as u64,
+        VectorOp::Mslt => (sa < sb) as u64,
+        VectorOp::Msleu => (a <= b) as u64,
+        VectorOp::Msle => (sa <= sb) as u64,
+        VectorOp::Merge => b,
+    }
+}
@@ -0,0 +1,387 @@
+//! A machine laid out like QEMU's `virt` board: CLINT, PLIC, a 16550 UART
+//! and up to eight virtio-mmio slots, with RAM at `0x8000_0000` and a
+//! generated device tree, for booting kernels that expect one.
+
+use crate::devices::virtio::{VirtioDevice, VirtioMmio};
+use crate::devices::{Clint, Device, Plic, Uart16550, plic};
+use crate::fdt::Fdt;
+use crate::isa::{Extension, Extensions};
+use crate::machine::Machine;
+use crate::xlen::Xlen;
+use crate::{Reg, RiscvCpu};
+
+pub const RAM_BASE: u32 = 0x8000_0000;
+
+/// `mtime` ticks once per instruction; this is what the device tree
+/// claims that comes to.
+pub const TIMEBASE_FREQUENCY: u32 = 10_000_000;
+
+pub const UART_IRQ: u32 = 10;
+/// The PLIC source of the first virtio-mmio slot; the rest follow.
+pub const VIRTIO_IRQ: u32 = 1;
+const VIRTIO_SLOTS: usize = 8;
+const VIRTIO_SIZE: u32 = 0x1000;
+const VIRTIO_BASE: u32 = 0x1000_1000;
+
+/// Where firmware expects the kernel, as an offset from [`RAM_BASE`]: the
+/// defaults OpenSBI's generic platform is built with, one megapage up.
+pub const KERNEL_OFFSET_RV32: u32 = 0x40_0000;
+pub const KERNEL_OFFSET_RV64: u32 = 0x20_0000;
+
+/// `struct fw_dynamic_info`: "OSBI", and the version with `boot_hart`.
+const FW_DYNAMIC_MAGIC: u64 = 0x4942_534F;
+const FW_DYNAMIC_VERSION: u64 = 2;
+/// `next_mode`: enter the kernel in S-mode.
+const FW_DYNAMIC_NEXT_MODE_S: u64 = 1;
+
+/// Space kept at the top of RAM for the device tree.
+const FDT_SPACE: u32 = 0x1_0000;
+const PAGE: u32 = 0x1000;
+
+/// Builds a [`Machine`] shaped like QEMU's `virt` board. The kernel goes at
+/// the start of RAM, the device tree at the top, and an initrd just below
+/// it. Every hart starts at the kernel in M-mode with its `mhartid` in
+/// `a0` and the device tree's address in `a1`, the convention Linux,
+/// OpenSBI and other RISC-V boot code expect.
+///
+/// With [`firmware`](Self::firmware), the harts start in the firmware
+/// instead and the kernel moves up to where it looks for one.
+pub struct Virt {
+    harts: usize,
+    ram_size: u32,
+    extensions: Extensions,
+    kernel: Vec<u8>,
+    firmware: Option<Vec<u8>>,
+    initrd: Option<Vec<u8>>,
+    bootargs: String,
+    uart: Option<Uart16550>,
+    virtio: Vec<Box<dyn Device>>,
+}
+
+/// Where [`Virt::build`] put things in RAM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VirtLayout {
+    pub kernel: u32,
+    pub fdt: u32,
+    /// The `struct fw_dynamic_info` passed to firmware in `a2`.
+    pub dynamic_info: Option<u32>,
+    /// Start and end of the initrd, if there is one.
+    pub initrd: Option<(u32, u32)>,
+}
+
+impl Virt {
+    pub fn new() -> Self {
+        Self {
+            harts: 1,
+            ram_size: 128 << 20,
+            extensions: Extensions::all(),
+            kernel: Vec::new(),
+            firmware: None,
+            initrd: None,
+            bootargs: String::new(),
+            uart: None,
+            virtio: Vec::new(),
+        }
+    }
+
+    pub fn harts(mut self, harts: usize) -> Self {
+        self.harts = harts;
+        self
+    }
+
+    /// 128 MiB by default. RAM is sparse, so only what the guest touches
+    /// takes host memory.
+    pub fn ram_size(mut self, bytes: u32) -> Self {
+        self.ram_size = bytes;
+        self
+    }
+
+    pub fn extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// The image loaded at [`RAM_BASE`], where the harts start.
+    pub fn kernel(mut self, image: impl Into<Vec<u8>>) -> Self {
+        self.kernel = image.into();
+        self
+    }
+
+    /// M-mode firmware, such as OpenSBI's `fw_dynamic`, to load at
+    /// [`RAM_BASE`]. The kernel goes at [`KERNEL_OFFSET_RV32`] or
+    /// [`KERNEL_OFFSET_RV64`] above it then, and `a2` points at a
+    /// `struct fw_dynamic_info` telling the firmware to enter the kernel
+    /// there in S-mode.
+    pub fn firmware(mut self, image: impl Into<Vec<u8>>) -> Self {
+        self.firmware = Some(image.into());
+        self
+    }
+
+    pub fn initrd(mut self, image: impl Into<Vec<u8>>) -> Self {
+        self.initrd = Some(image.into());
+        self
+    }
+
+    /// The kernel command line, passed in `/chosen/bootargs`.
+    pub fn bootargs(mut self, bootargs: &str) -> Self {
+        self.bootargs = bootargs.to_string();
+        self
+    }
+
+    /// The UART to use instead of one writing to stdout.
+    pub fn uart(mut self, uart: Uart16550) -> Self {
+        self.uart = Some(uart);
+        self
+    }
+
+    /// Put `device` in the next free virtio-mmio slot.
+    pub fn virtio(mut self, device: impl VirtioDevice + 'static) -> Self {
+        self.virtio.push(Box::new(VirtioMmio::new(device)));
+        self
+    }
+
+    pub fn build<X: Xlen>(self) -> Result<(Machine<X>, VirtLayout), String> {
+        if self.harts == 0 {
+            return Err("a machine needs at least one hart".to_string());
+        }
+        if self.ram_size > u32::MAX - RAM_BASE || self.ram_size < 2 * FDT_SPACE {
+            return Err(format!(
+                "RAM must be at least {} bytes and under 2 GiB, not {}",
+                2 * FDT_SPACE,
+                self.ram_size
+            ));
+        }
+        if self.virtio.len() > VIRTIO_SLOTS {
+            return Err(format!("there are only {} virtio-mmio slots", VIRTIO_SLOTS));
+        }
+
+        let fdt = RAM_BASE + (self.ram_size - FDT_SPACE);
+        let kernel = match &self.firmware {
+            Some(firmware) => {
+                let offset = if X::BITS == 32 {
+                    KERNEL_OFFSET_RV32
+                } else {
+                    KERNEL_OFFSET_RV64
+                };
+                if firmware.len() > offset as usize {
+                    return Err(format!(
+                        "the firmware is {} bytes, but the kernel goes {:#x} bytes in",
+                        firmware.len(),
+                        offset
+                    ));
+                }
+                RAM_BASE + offset
+            }
+            None => RAM_BASE,
+        };
+        let kernel_end = kernel as u64 + self.kernel.len() as u64;
+        let initrd = match &self.initrd {
+            Some(image) => {
+                let start = (fdt as u64)
+                    .checked_sub(image.len() as u64)
+                    .map(|start| start & !(PAGE as u64 - 1))
+                    .filter(|&start| start >= kernel_end)
+                    .ok_or("the kernel and initrd don't fit in RAM")?;
+                Some((start as u32, start as u32 + image.len() as u32))
+            }
+            None if kernel_end > fdt as u64 => {
+                return Err("the kernel doesn't fit in RAM".to_string());
+            }
+            None => None,
+        };
+        let mut layout = VirtLayout {
+            kernel,
+            fdt,
+            dynamic_info: None,
+            initrd,
+        };
+
+        let blob = self.device_tree(X::BITS, layout);
+        // The firmware's boot information goes just after the device tree.
+        let info = self.firmware.as_ref().map(|_| {
+            let word = |value: u64| value.to_le_bytes()[..X::BITS as usize / 8].to_vec();
+            [
+                FW_DYNAMIC_MAGIC,
+                FW_DYNAMIC_VERSION,
+                kernel as u64,
+                FW_DYNAMIC_NEXT_MODE_S,
+                0,
+                u64::MAX,
+            ]
+            .into_iter()
+            .flat_map(word)
+            .collect::<Vec<u8>>()
+        });
+        let info_at = blob.len().next_multiple_of(8);
+        let used = info_at + info.as_ref().map_or(0, Vec::len);
+        if used > FDT_SPACE as usize {
+            return Err(format!(
+                "the device tree is {} bytes, more than the {} kept for it",
+                used, FDT_SPACE
+            ));
+        }
+        if info.is_some() {
+            layout.dynamic_info = Some(fdt + info_at as u32);
+        }
+
+        let plic = Plic::new(self.harts);
+        let lines = plic.lines();
+        let uart = self.uart.unwrap_or_default();
+        let mut builder = RiscvCpu::builder()
+            .xlen::<X>()
+            .ram_base(RAM_BASE)
+            .ram_size(self.ram_size as usize)
+            .sparse_ram(true)
+            .extensions(self.extensions)
+            .image(kernel, self.kernel)
+            .image(fdt, blob)
+            .device(Clint::BASE, Clint::SIZE, Clint::new(self.harts))
+            .device(
+                Uart16550::BASE,
+                Uart16550::SIZE,
+                lines.route(UART_IRQ, uart),
+            );
+        for (i, device) in self.virtio.into_iter().enumerate() {
+            let base = VIRTIO_BASE + i as u32 * VIRTIO_SIZE;
+            let source = VIRTIO_IRQ + i as u32;
+            builder = builder.device(base, VIRTIO_SIZE, lines.route(source, device));
+        }
+        if let (Some(image), Some((start, _))) = (self.initrd, initrd) {
+            builder = builder.image(start, image);
+        }
+        if let Some(firmware) = self.firmware {
+            builder = builder.image(RAM_BASE, firmware);
+        }
+        if let (Some(info), Some(at)) = (info, layout.dynamic_info) {
+            builder = builder.image(at, info);
+        }
+        builder = builder.device(Plic::BASE, Plic::SIZE, plic);
+
+        let mut machine = builder.build_machine(self.harts)?;
+        for id in 0..self.harts {
+            let hart = machine.hart_mut(id);
+            hart.set_reg(Reg::A0, id as u64);
+            hart.set_reg(Reg::A1, fdt as u64);
+            if let Some(info) = layout.dynamic_info {
+                hart.set_reg(Reg::A2, info as u64);
+            }
+        }
+        Ok((machine, layout))
+    }
+
+    fn device_tree(&self, xlen: u32, layout: VirtLayout) -> Vec<u8> {
+        let mut fdt = Fdt::new();
+        fdt.begin_node("");
+        fdt.property_u32("#address-cells", 2);
+        fdt.property_u32("#size-cells", 2);
+        fdt.property_str("compatible", "riscv-virtio");
+        fdt.property_str("model", "riscv-virtio,qemu");
+
+        fdt.begin_node("chosen");
+        if !self.bootargs.is_empty() {
+            fdt.property_str("bootargs", &self.bootargs);
+        }
+        fdt.property_str("stdout-path", &format!("/soc/serial@{:x}", Uart16550::BASE));
+        if let Some((start, end)) = layout.initrd {
+            fdt.property_u64("linux,initrd-start", start as u64);
+            fdt.property_u64("linux,initrd-end", end as u64);
+        }
+        fdt.end_node();
+
+        fdt.begin_node(&format!("memory@{:x}", RAM_BASE));
+        fdt.property_str("device_type", "memory");
+        fdt.property_cells("reg", &reg(RAM_BASE, self.ram_size));
+        fdt.end_node();
+
+        fdt.begin_node("cpus");
+        fdt.property_u32("#address-cells", 1);
+        fdt.property_u32("#size-cells", 0);
+        fdt.property_u32("timebase-frequency", TIMEBASE_FREQUENCY);
+        let isa = self.extensions.isa_string(xlen);
+        let mut intcs = Vec::new();
+        for id in 0..self.harts {
+            fdt.begin_node(&format!("cpu@{}", id));
+            fdt.property_str("device_type", "cpu");
+            fdt.property_u32("reg", id as u32);
+            fdt.property_str("status", "okay");
+            fdt.property_str("compatible", "riscv");
+            fdt.property_str("riscv,isa", &isa);
+            // Only Sv32 is implemented.
+            if xlen == 32 && self.extensions.contains(Extension::S) {
+                fdt.property_str("mmu-type", "riscv,sv32");
+            }
+            fdt.begin_node("interrupt-controller");
+            fdt.property_u32("#interrupt-cells", 1);
+            fdt.property_flag("interrupt-controller");
+            fdt.property_str("compatible", "riscv,cpu-intc");
+            intcs.push(fdt.phandle());
+            fdt.end_node();
+            fdt.end_node();
+        }
+        fdt.end_node();
+
+        fdt.begin_node("soc");
+        fdt.property_u32("#address-cells", 2);
+        fdt.property_u32("#size-cells", 2);
+        fdt.property_str("compatible", "simple-bus");
+        fdt.property_flag("ranges");
+
+        // Interrupts as (hart's controller, cause) pairs.
+        let wired = |causes: &[u32]| -> Vec<u32> {
+            intcs
+                .iter()
+                .flat_map(|&intc| causes.iter().flat_map(move |&cause| [intc, cause]))
+                .collect()
+        };
+
+        fdt.begin_node(&format!("clint@{:x}", Clint::BASE));
+        fdt.property_strs("compatible", &["sifive,clint0", "riscv,clint0"]);
+        fdt.property_cells("reg", &reg(Clint::BASE, Clint::SIZE));
+        fdt.property_cells("interrupts-extended", &wired(&[3, 7]));
+        fdt.end_node();
+
+        fdt.begin_node(&format!("plic@{:x}", Plic::BASE));
+        fdt.property_u32("#interrupt-cells", 1);
+        fdt.property_u32("#address-cells", 0);
+        fdt.property_flag("interrupt-controller");
+        fdt.property_strs("compatible", &["sifive,plic-1.0.0", "riscv,plic0"]);
+        fdt.property_cells("reg", &reg(Plic::BASE, Plic::SIZE));
+        fdt.property_u32("riscv,ndev", plic::SOURCES as u32 - 1);
+        fdt.property_cells("interrupts-extended", &wired(&[11, 9]));
+        let plic = fdt.phandle();
+        fdt.end_node();
+
+        fdt.begin_node(&format!("serial@{:x}", Uart16550::BASE));
+        fdt.property_str("compatible", "ns16550a");
+        fdt.property_cells("reg", &reg(Uart16550::BASE, Uart16550::SIZE));
+        fdt.property_u32("clock-frequency", 3_686_400);
+        fdt.property_u32("interrupt-parent", plic);
+        fdt.property_u32("interrupts", UART_IRQ);
+        fdt.end_node();
+
+        for i in 0..self.virtio.len() as u32 {
+            let base = VIRTIO_BASE + i * VIRTIO_SIZE;
+            fdt.begin_node(&format!("virtio_mmio@{:x}", base));
+            fdt.property_str("compatible", "virtio,mmio");
+            fdt.property_cells("reg", &reg(base, VIRTIO_SIZE));
+            fdt.property_u32("interrupt-parent", plic);
+            fdt.property_u32("interrupts", VIRTIO_IRQ + i);
+            fdt.end_node();
+        }
+
+        fdt.end_node();
+        fdt.end_node();
+        fdt.finish()
+    }
+}
+
+impl Default for Virt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A `reg` entry with two address and two size cells.
+fn reg(base: u32, size: u32) -> [u32; 4] {
+    [0, base, 0, size]
+}
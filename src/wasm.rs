@@ -0,0 +1,99 @@
+//! JavaScript bindings for running the emulator in a browser, built with
+//! `wasm-pack build -- --no-default-features --features wasm`.
+//!
+//! The core builds for `wasm32-unknown-unknown` against its `std`, so
+//! nothing here is `no_std`, but the `std` feature has to be off: host
+//! time, threads and host files are left out at build time rather than
+//! failing in the browser.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{ExitReason, Reg, RiscvCpu, StepOutcome};
+
+/// An RV32 hart with RAM at address 0.
+#[wasm_bindgen]
+pub struct Emulator {
+    cpu: RiscvCpu,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(ram_size: usize) -> Result<Emulator, String> {
+        let cpu = RiscvCpu::builder().ram_size(ram_size).build()?;
+        Ok(Emulator { cpu })
+    }
+
+    /// Load an ELF, an Intel HEX file or else a raw binary at address 0,
+    /// and jump to its entry point.
+    pub fn load(&mut self, program: &[u8]) -> Result<(), String> {
+        if program.starts_with(b"\x7fELF") {
+            self.cpu.load_elf(program)
+        } else if program.starts_with(b":") {
+            self.cpu.load_ihex(&String::from_utf8_lossy(program))
+        } else {
+            self.cpu.load_binary(0, program)?;
+            self.cpu.pc = 0;
+            Ok(())
+        }
+    }
+
+    /// Execute one instruction. Returns what stopped it, or an empty
+    /// string if nothing did.
+    pub fn step(&mut self) -> String {
+        match self.cpu.step() {
+            Ok(StepOutcome::Executed) => String::new(),
+            Ok(outcome) => format!("{:?}", outcome),
+            Err(exception) => exception.to_string(),
+        }
+    }
+
+    /// Run up to `steps` instructions. Returns what stopped it, or an
+    /// empty string if it used them all.
+    pub fn run(&mut self, steps: u32) -> String {
+        match self.cpu.run_steps(steps as u64) {
+            ExitReason::StepLimit => String::new(),
+            ExitReason::Exception(exception) => exception.to_string(),
+            exit => format!("{:?}", exit),
+        }
+    }
+
+    pub fn pc(&self) -> u32 {
+        self.cpu.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u32) {
+        self.cpu.pc = pc;
+    }
+
+    /// x0 to x31.
+    pub fn regs(&self) -> Vec<u32> {
+        self.cpu.regs.to_vec()
+    }
+
+    pub fn set_reg(&mut self, reg: u8, value: u32) {
+        if let Some(reg) = Reg::from_index(reg) {
+            self.cpu.set_reg(reg, value as u64);
+        }
+    }
+
+    /// `len` bytes of RAM from `addr`.
+    pub fn read_memory(&mut self, addr: u32, len: usize) -> Result<Vec<u8>, String> {
+        let mut bytes = vec![0; len];
+        self.cpu
+            .bus
+            .dma()
+            .read(addr as u64, &mut bytes)
+            .ok_or_else(|| format!("{} bytes at {:#x} aren't all RAM", len, addr))?;
+        Ok(bytes)
+    }
+
+    pub fn write_memory(&mut self, addr: u32, bytes: &[u8]) -> Result<(), String> {
+        self.cpu
+            .bus
+            .write_bytes(addr, bytes)
+            .ok_or_else(|| format!("{} bytes at {:#x} aren't all RAM", bytes.len(), addr))?;
+        self.cpu.flush_blocks();
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+/// The integer register width a [`RiscvCpu`](crate::RiscvCpu) is built for.
+///
+/// The CPU does its arithmetic on `u64` and narrows the result through
+/// [`truncate`](Xlen::truncate), so RV32 gets 32-bit wrap-around for free.
+pub trait Xlen: Copy + Default + fmt::Debug + 'static {
+    type Reg: Copy + Default + Eq + Ord + fmt::Debug + fmt::LowerHex + 'static;
+
+    const BITS: u32;
+
+    /// All `BITS` bits set, as a `u64`.
+    const MASK: u64 = u64::MAX >> (64 - Self::BITS);
+
+    /// Keep the low `BITS` bits of `value`.
+    fn truncate(value: u64) -> Self::Reg;
+
+    /// Zero-extend a register value.
+    fn widen(reg: Self::Reg) -> u64;
+
+    /// Sign-extend a register value.
+    fn signed(reg: Self::Reg) -> i64 {
+        let shift = 64 - Self::BITS;
+        ((Self::widen(reg) << shift) as i64) >> shift
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rv32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rv64;
+
+impl Xlen for Rv32 {
+    type Reg = u32;
+
+    const BITS: u32 = 32;
+
+    fn truncate(value: u64) -> u32 {
+        value as u32
+    }
+
+    fn widen(reg: u32) -> u64 {
+        reg as u64
+    }
+}
+
+impl Xlen for Rv64 {
+    type Reg = u64;
+
+    const BITS: u32 = 64;
+
+    fn truncate(value: u64) -> u64 {
+        value
+    }
+
+    fn widen(reg: u64) -> u64 {
+        reg
+    }
+}
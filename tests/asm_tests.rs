@@ -0,0 +1,269 @@
+mod common;
+
+use riscv_emulator_rust::asm::{assemble, assemble_at};
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, RiscvCpu};
+
+use common::{cpu_with, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Assemble `source`, load it at address 0 and step until EBREAK.
+fn run(source: &str) -> RiscvCpu {
+    let mut cpu = cpu_with(source);
+    match cpu.run_steps(10_000) {
+        ExitReason::Exception(Exception::Breakpoint(_)) => cpu,
+        other => panic!("program did not reach ebreak: {:?}", other),
+    }
+}
+
+// ── Encoding ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_matches_known_encodings() {
+    let cases = [
+        ("add x3, x1, x2", 0x002081b3),
+        ("sub t0, t1, t2", 0x407302b3),
+        ("addi ra, zero, -1", 0xfff00093),
+        ("srai x1, x2, 3", 0x40315093),
+        ("lw x5, -4(sp)", 0xffc12283),
+        ("sw t0, 8(x2)", 0x00512423),
+        ("jalr x0, 0(ra)", 0x00008067),
+        ("lui x5, 0x12345", 0x123452b7),
+        ("auipc x1, 0xfffff", 0xfffff097),
+        ("csrrw x1, mscratch, x2", 0x340110f3),
+        ("csrrsi zero, mstatus, 8", 0x30046073),
+        ("ecall", 0x00000073),
+        ("mret", 0x30200073),
+    ];
+
+    for (text, bits) in cases {
+        assert_eq!(assemble(text), Ok(vec![bits]), "{}", text);
+    }
+}
+
+#[test]
+fn test_round_trips_through_disassembler() {
+    let source = "\
+        add x3, x1, x2
+        addi x1, x0, -1
+        lw x5, -4(x2)
+        sw x5, 8(x2)
+        bne x1, x0, -8
+        lui x5, 0x12345
+        ebreak";
+
+    let words = assemble(source).unwrap();
+    let lines: Vec<&str> = source.lines().map(str::trim).collect();
+
+    for (word, line) in words.iter().zip(lines) {
+        assert_eq!(decode(*word).unwrap().to_string(), line);
+    }
+}
+
+// ── Labels ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_backward_and_forward_labels() {
+    let words = assemble(
+        "
+        start:  beq x0, x0, end     # +8
+                jal x0, start       # -4
+        end:    ebreak
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(words[0], 0x00000463, "beq x0, x0, 8");
+    assert_eq!(words[1], 0xffdff06f, "jal x0, -4");
+}
+
+#[test]
+fn test_word_directive_uses_absolute_label_address() {
+    let words = assemble_at(
+        0x8000_0000,
+        "
+        .text
+        nop_slot: addi x0, x0, 0
+        .word nop_slot
+        .word 0xdeadbeef
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(words, vec![0x00000013, 0x8000_0000, 0xdead_beef]);
+}
+
+// ── Pseudo-instructions ───────────────────────────────────────────────────────
+
+#[test]
+fn test_pseudo_instructions_expand() {
+    let cases = [
+        ("nop", "addi zero, zero, 0"),
+        ("mv a0, a1", "addi a0, a1, 0"),
+        ("not t0, t1", "xori t0, t1, -1"),
+        ("neg t0, t1", "sub t0, zero, t1"),
+        ("j -8", "jal zero, -8"),
+        ("jr t0", "jalr zero, 0(t0)"),
+        ("ret", "jalr zero, 0(ra)"),
+        ("li a0, -2048", "addi a0, zero, -2048"),
+        ("li a0, 0xFFFFFFFF", "addi a0, zero, -1"),
+        ("li a0, 0x12345000", "lui a0, 0x12345"),
+    ];
+
+    for (pseudo, real) in cases {
+        assert_eq!(assemble(pseudo), assemble(real), "{}", pseudo);
+    }
+}
+
+#[test]
+fn test_li_expands_to_lui_and_addi() {
+    assert_eq!(
+        assemble("li t0, 0x12345FFF").unwrap(),
+        assemble("lui t0, 0x12346\naddi t0, t0, -1").unwrap()
+    );
+    assert_eq!(
+        assemble("li t0, 0x7FFFFFFF").unwrap(),
+        assemble("lui t0, 0x80000\nxori t0, t0, -1").unwrap()
+    );
+    assert_eq!(
+        assemble("li t0, 0x1_0000_0000").unwrap_err().message,
+        "immediate 4294967296 out of range [-2147483648, 4294967295]"
+    );
+}
+
+#[test]
+fn test_labels_account_for_expansion() {
+    let words = assemble(
+        "
+                li   a0, 0x12345678
+                la   a1, data
+                call func
+        func:   ret
+        data:   .word 7
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(words.len(), 8);
+    assert_eq!(words[2], 0x0000_0597, "auipc a1, 0");
+    assert_eq!(words[3], 0x0145_8593, "addi a1, a1, 20");
+    assert_eq!(words[4], 0x0000_0097, "auipc ra, 0");
+    assert_eq!(words[5], 0x0080_80e7, "jalr ra, 8(ra)");
+}
+
+#[test]
+fn test_pseudo_instructions_run() {
+    let cpu = run("
+                li   s0, 0x7FFFFFFF
+                li   s1, -1
+                li   s2, 0x80000000
+                la   t0, value
+                lw   a0, 0(t0)
+                call double
+                mv   a2, a0
+                not  a3, a2
+                neg  a4, a2
+                j    done
+                nop
+        double: add  a0, a0, a0
+                ret
+        done:   ebreak
+        value:  .word 21
+    ");
+
+    assert_eq!(cpu.regs[8], 0x7FFF_FFFF);
+    assert_eq!(cpu.regs[9], 0xFFFF_FFFF);
+    assert_eq!(cpu.regs[18], 0x8000_0000);
+    assert_eq!(cpu.regs[12], 42);
+    assert_eq!(cpu.regs[13], !42);
+    assert_eq!(cpu.regs[14], (-42i32) as u32);
+}
+
+#[test]
+fn test_li_on_rv64() {
+    let mut cpu = program(
+        "
+        li t0, 0x7FFFFFFF
+        li t1, 0x7FFFF800
+        li t2, 0x80000000
+        li t3, -1
+        ebreak
+        ",
+    )
+    .xlen::<Rv64>()
+    .build()
+    .unwrap();
+    cpu.run_steps(100);
+
+    assert_eq!(cpu.regs[5], 0x7FFF_FFFF);
+    assert_eq!(cpu.regs[6], 0x7FFF_F800);
+    assert_eq!(cpu.regs[7], 0xFFFF_FFFF_8000_0000);
+    assert_eq!(cpu.regs[28], u64::MAX);
+}
+
+// ── Errors ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_errors_report_line_numbers() {
+    let cases = [
+        ("addi x1, x0, 1\nfoo x1", 2, "unknown instruction `foo`"),
+        ("add x1, x2, x32", 1, "unknown register `x32`"),
+        (
+            "addi x1, x0, 4096",
+            1,
+            "immediate 4096 out of range [-2048, 2047]",
+        ),
+        ("\n\nbeq x0, x0, nowhere", 3, "undefined label `nowhere`"),
+        ("a:\na:", 2, "duplicate label `a`"),
+        ("add x1, x2", 1, "`add` takes 3 operand(s), got 2"),
+    ];
+
+    for (source, line, message) in cases {
+        let err = assemble(source).unwrap_err();
+        assert_eq!(
+            (err.line, err.message.as_str()),
+            (line, message),
+            "{:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_branch_target_must_be_in_range() {
+    assert!(assemble("beq x0, x0, 4096").is_err());
+    assert!(assemble("beq x0, x0, 3").is_err(), "odd offset");
+}
+
+// ── Running assembled programs ────────────────────────────────────────────────
+
+#[test]
+fn test_countdown_loop() {
+    let cpu = run("
+                addi a0, zero, 0
+                addi t0, zero, 10
+        loop:   add  a0, a0, t0
+                addi t0, t0, -1
+                bne  t0, zero, loop
+                ebreak
+    ");
+
+    assert_eq!(cpu.regs[10], 55);
+}
+
+#[test]
+fn test_call_and_return() {
+    let cpu = run("
+                addi a0, zero, 6
+                jal  double
+                jal  double
+                ebreak
+        double: add  a0, a0, a0
+                jalr ra
+    ");
+
+    assert_eq!(cpu.regs[10], 24);
+}
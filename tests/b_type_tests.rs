@@ -1,29 +1,5 @@
 use riscv_emulator_rust::RiscvCpu;
-
-/// Encode a B-type instruction (BEQ/BNE/BLT/BGE/BLTU/BGEU).
-///
-/// imm: 13-bit signed immediate (must be a multiple of 2)
-/// rs1: source register 1
-/// rs2: source register 2
-/// funct3: instruction funct3
-/// opcode: 0x63 for branch instructions
-fn encode_btype(imm: i32, rs1: u8, rs2: u8, funct3: u8) -> u32 {
-    let imm = imm as u32;
-    let b12 = (imm >> 12) & 0x1;
-    let b11 = (imm >> 11) & 0x1;
-    let b10_5 = (imm >> 5) & 0x3F;
-    let b4_1 = (imm >> 1) & 0xF;
-    let opcode = 0x63u32;
-
-    (b12 << 31)
-        | (b10_5 << 25)
-        | ((rs2 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | (b4_1 << 8)
-        | (b11 << 7)
-        | opcode
-}
+use riscv_emulator_rust::encode::btype;
 
 mod beq {
     use super::*;
@@ -36,7 +12,7 @@ mod beq {
         cpu.pc = 0x100;
 
         // beq x1, x2, 8 (PC = 0x100 + 8 = 0x108)
-        let instruction = encode_btype(8, 1, 2, 0b000);
+        let instruction = btype(8, 1, 2, 0b000);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -52,7 +28,7 @@ mod beq {
         cpu.pc = 0x100;
 
         // beq x1, x2, 8 (Not taken, PC = 0x100 + 4 = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b000);
+        let instruction = btype(8, 1, 2, 0b000);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -68,7 +44,7 @@ mod beq {
         cpu.pc = 0x100;
 
         // beq x1, x2, -8  (Taken: equal, PC = 0x100 + (-8) = 0x0F8)
-        let instruction = encode_btype(-8, 1, 2, 0b000);
+        let instruction = btype(-8, 1, 2, 0b000);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -82,7 +58,7 @@ mod beq {
         // x0 == x0 always -> BEQ always taken
         cpu.pc = 0x100;
 
-        let instruction = encode_btype(8, 0, 0, 0b000);
+        let instruction = btype(8, 0, 0, 0b000);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -102,7 +78,7 @@ mod bne {
         cpu.pc = 0x100;
 
         // bne x1, x2, 8 (Taken, PC = 0x108)
-        let instruction = encode_btype(8, 1, 2, 0b001);
+        let instruction = btype(8, 1, 2, 0b001);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -118,7 +94,7 @@ mod bne {
         cpu.pc = 0x100;
 
         // bne x1, x2, 8 (Not taken, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b001);
+        let instruction = btype(8, 1, 2, 0b001);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -134,7 +110,7 @@ mod bne {
         cpu.pc = 0x100;
 
         // bne x1, x2, -8  (Taken: 7 != 3, PC = 0x0F8)
-        let instruction = encode_btype(-8, 1, 2, 0b001);
+        let instruction = btype(-8, 1, 2, 0b001);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -150,7 +126,7 @@ mod bne {
         cpu.pc = 0x100;
 
         // bne x1, x2, 8  (Taken: -1 != 1)
-        let instruction = encode_btype(8, 1, 2, 0b001);
+        let instruction = btype(8, 1, 2, 0b001);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -170,7 +146,7 @@ mod blt {
         cpu.pc = 0x100;
 
         // blt x1, x2, 8 (Taken: 5 < 10, PC = 0x108)
-        let instruction = encode_btype(8, 1, 2, 0b100);
+        let instruction = btype(8, 1, 2, 0b100);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -186,7 +162,7 @@ mod blt {
         cpu.pc = 0x100;
 
         // blt x1, x2, 8 (Taken: -10 < 5, PC = 0x108)
-        let instruction = encode_btype(8, 1, 2, 0b100);
+        let instruction = btype(8, 1, 2, 0b100);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -202,7 +178,7 @@ mod blt {
         cpu.pc = 0x100;
 
         // blt x1, x2, 8 (Not taken: 7 < 7 is false, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b100);
+        let instruction = btype(8, 1, 2, 0b100);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -218,7 +194,7 @@ mod blt {
         cpu.pc = 0x100;
 
         // blt x1, x2, 8 (Not taken: 20 < 5 is false, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b100);
+        let instruction = btype(8, 1, 2, 0b100);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -234,7 +210,7 @@ mod blt {
         cpu.pc = 0x100;
 
         // blt x1, x2, -8  (Taken: -5 < 0 signed, PC = 0x0F8)
-        let instruction = encode_btype(-8, 1, 2, 0b100);
+        let instruction = btype(-8, 1, 2, 0b100);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -254,7 +230,7 @@ mod bge {
         cpu.pc = 0x100;
 
         // bge x1, x2, 8 (Taken: 10 >= 5, PC = 0x108)
-        let instruction = encode_btype(8, 1, 2, 0b101);
+        let instruction = btype(8, 1, 2, 0b101);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -270,7 +246,7 @@ mod bge {
         cpu.pc = 0x100;
 
         // bge x1, x2, -4 (Taken: 10 >= 10, PC = 0x0FC)
-        let instruction = encode_btype(-4, 1, 2, 0b101);
+        let instruction = btype(-4, 1, 2, 0b101);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -286,7 +262,7 @@ mod bge {
         cpu.pc = 0x100;
 
         // bge x1, x2, 8 (Not taken: 3 >= 10 is false, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b101);
+        let instruction = btype(8, 1, 2, 0b101);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -302,7 +278,7 @@ mod bge {
         cpu.pc = 0x100;
 
         // bge x1, x2, 8 (Not taken: -5 >= 1 is false signed, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b101);
+        let instruction = btype(8, 1, 2, 0b101);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -322,7 +298,7 @@ mod bltu {
         cpu.pc = 0x100;
 
         // bltu x1, x2, 8 (Taken: 5 < big, PC = 0x108)
-        let instruction = encode_btype(8, 1, 2, 0b110);
+        let instruction = btype(8, 1, 2, 0b110);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -338,7 +314,7 @@ mod bltu {
         cpu.pc = 0x100;
 
         // bltu x1, x2, 8 (Not taken: big < 5 is false, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b110);
+        let instruction = btype(8, 1, 2, 0b110);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -354,7 +330,7 @@ mod bltu {
         cpu.pc = 0x100;
 
         // bltu x1, x2, 8 (Not taken: equal is not < unsigned, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b110);
+        let instruction = btype(8, 1, 2, 0b110);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -370,7 +346,7 @@ mod bltu {
         cpu.pc = 0x100;
 
         // bltu x1, x2, -8  (Taken: 0 < 0xFFFF_FFFF unsigned, PC = 0x0F8)
-        let instruction = encode_btype(-8, 1, 2, 0b110);
+        let instruction = btype(-8, 1, 2, 0b110);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -390,7 +366,7 @@ mod bgeu {
         cpu.pc = 0x100;
 
         // bgeu x1, x2, 8 (Taken: big >= 5, PC = 0x108)
-        let instruction = encode_btype(8, 1, 2, 0b111);
+        let instruction = btype(8, 1, 2, 0b111);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -406,7 +382,7 @@ mod bgeu {
         cpu.pc = 0x100;
 
         // bgeu x1, x2, 12 (Taken: 0xABC >= 0xABC, PC = 0x10C)
-        let instruction = encode_btype(12, 1, 2, 0b111);
+        let instruction = btype(12, 1, 2, 0b111);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -422,7 +398,7 @@ mod bgeu {
         cpu.pc = 0x100;
 
         // bgeu x1, x2, 8 (Not taken: 3 >= big is false unsigned, PC = 0x104)
-        let instruction = encode_btype(8, 1, 2, 0b111);
+        let instruction = btype(8, 1, 2, 0b111);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -438,7 +414,7 @@ mod bgeu {
         cpu.pc = 0x100;
 
         // bgeu x1, x2, -8  (Taken: equal, PC = 0x0F8)
-        let instruction = encode_btype(-8, 1, 2, 0b111);
+        let instruction = btype(-8, 1, 2, 0b111);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -455,7 +431,7 @@ mod bgeu {
         cpu.pc = 0x100;
 
         // bgeu x1, x2, 8  (Taken: 0xFFFF_FFFF >= 1 unsigned)
-        let instruction = encode_btype(8, 1, 2, 0b111);
+        let instruction = btype(8, 1, 2, 0b111);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_btype(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -0,0 +1,221 @@
+mod common;
+
+use riscv_emulator_rust::backtrace::Frame;
+use riscv_emulator_rust::loader::{Symbol, SymbolTable};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::{Rv32, Rv64, Xlen};
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu};
+
+use common::image;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with<X: Xlen>(source: &str, tracking: bool) -> RiscvCpu<X> {
+    RiscvCpu::builder()
+        .xlen::<X>()
+        .ram_size(0x2000)
+        .stack_pointer(0x2000)
+        .image(0, image(source))
+        .call_tracking(tracking)
+        .build()
+        .unwrap()
+}
+
+fn pcs(frames: &[Frame]) -> Vec<u32> {
+    frames.iter().map(|frame| frame.pc).collect()
+}
+
+/// main calls outer, which calls inner, which faults. Every function keeps
+/// ra and the caller's s0 just below its frame pointer, as GCC and LLVM do
+/// with frame pointers enabled.
+const NESTED: &str = "
+    main:
+        jal   ra, outer
+    done:
+        ebreak
+    outer:
+        addi  sp, sp, -16
+        sw    ra, 12(sp)
+        sw    s0, 8(sp)
+        addi  s0, sp, 16
+        jal   ra, inner
+        lw    ra, 12(sp)
+        lw    s0, 8(sp)
+        addi  sp, sp, 16
+        jalr  zero, 0(ra)
+    inner:
+        addi  sp, sp, -16
+        sw    ra, 12(sp)
+        sw    s0, 8(sp)
+        addi  s0, sp, 16
+        ecall
+";
+
+// main at 0x0, outer at 0x8, inner at 0x2c; ecall at 0x3c.
+const RETURN_TO_MAIN: u32 = 0x4;
+const RETURN_TO_OUTER: u32 = 0x1C;
+const FAULT: u32 = 0x3C;
+
+// ── Shadow stack ──────────────────────────────────────────────────────────────
+
+#[test]
+fn test_backtrace_from_tracked_calls() {
+    let mut cpu = cpu_with::<Rv32>(NESTED, true);
+
+    let exit = cpu.run();
+
+    assert_eq!(exit, ExitReason::Exception(Exception::EnvironmentCall));
+    assert_eq!(
+        pcs(&cpu.backtrace()),
+        [FAULT, RETURN_TO_OUTER, RETURN_TO_MAIN]
+    );
+}
+
+#[test]
+fn test_returns_pop_the_shadow_stack() {
+    let mut cpu = cpu_with::<Rv32>(NESTED, true);
+
+    cpu.run_until(0x10); // outer, before it calls inner
+    assert_eq!(pcs(&cpu.backtrace()), [0x10, RETURN_TO_MAIN]);
+
+    let source = "
+        jal   ra, f
+        ebreak
+    f:
+        jalr  zero, 0(ra)
+    ";
+    let mut cpu = cpu_with::<Rv32>(source, true);
+    cpu.run();
+    assert_eq!(pcs(&cpu.backtrace()), [0x4]);
+}
+
+#[test]
+fn test_tail_calls_and_t0_links() {
+    // f tail-calls g, which returns straight to main. h is called through
+    // t0, the alternate link register.
+    let source = "
+        jal   ra, f
+        jal   t0, h
+    f:
+        jal   zero, g
+    g:
+        jalr  zero, 0(ra)
+    h:
+        ebreak
+    ";
+    let mut cpu = cpu_with::<Rv32>(source, true);
+
+    cpu.run_until(0xC);
+    assert_eq!(pcs(&cpu.backtrace()), [0xC, 0x4], "g runs in f's frame");
+
+    cpu.run();
+    assert_eq!(pcs(&cpu.backtrace()), [0x10, 0x8]);
+}
+
+#[test]
+fn test_tracking_works_on_every_engine() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = cpu_with::<Rv32>(NESTED, true);
+        cpu.set_engine(engine);
+
+        cpu.run();
+
+        assert_eq!(
+            pcs(&cpu.backtrace()),
+            [FAULT, RETURN_TO_OUTER, RETURN_TO_MAIN],
+            "{:?}",
+            engine
+        );
+    }
+}
+
+#[test]
+fn test_turning_tracking_on_starts_empty() {
+    let mut cpu = cpu_with::<Rv32>(NESTED, true);
+    cpu.run();
+
+    cpu.set_call_tracking(false);
+    cpu.set_call_tracking(true);
+
+    assert_eq!(pcs(&cpu.backtrace()), [FAULT]);
+}
+
+// ── Frame pointers ────────────────────────────────────────────────────────────
+
+#[test]
+fn test_backtrace_from_frame_pointers() {
+    let mut cpu = cpu_with::<Rv32>(NESTED, false);
+
+    cpu.run();
+
+    // main doesn't set up a frame, so the walk ends at outer's, whose
+    // caller's s0 was zero.
+    assert_eq!(
+        pcs(&cpu.backtrace()),
+        [FAULT, RETURN_TO_OUTER, RETURN_TO_MAIN]
+    );
+}
+
+#[test]
+fn test_frame_pointers_on_rv64() {
+    let source = NESTED.replace("sw ", "sd ").replace("lw ", "ld ");
+    let source = source
+        .replace("12(sp)", "8(sp)")
+        .replace("s0, 8(sp)", "s0, 0(sp)");
+    let mut cpu = cpu_with::<Rv64>(&source, false);
+
+    cpu.run();
+
+    assert_eq!(
+        pcs(&cpu.backtrace()),
+        [FAULT, RETURN_TO_OUTER, RETURN_TO_MAIN]
+    );
+}
+
+#[test]
+fn test_bad_frame_pointer_ends_the_walk() {
+    let mut cpu = cpu_with::<Rv32>("ebreak", false);
+    cpu.regs[8] = 0x1_0000; // outside RAM
+
+    assert_eq!(pcs(&cpu.backtrace()), [0]);
+
+    cpu.regs[8] = 0x1002; // misaligned
+    assert_eq!(pcs(&cpu.backtrace()), [0]);
+}
+
+// ── Symbols ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_frames_are_symbolized() {
+    let mut cpu = cpu_with::<Rv32>(NESTED, true);
+    let symbol = |name: &str, addr, size| Symbol {
+        name: String::from(name),
+        addr,
+        size,
+        function: true,
+    };
+    cpu.set_symbols(SymbolTable::new(vec![
+        symbol("main", 0, 8),
+        symbol("outer", 0x8, 0x24),
+        symbol("inner", 0x2C, 0x14),
+    ]));
+
+    cpu.run();
+
+    let frames: Vec<String> = cpu.backtrace().iter().map(Frame::to_string).collect();
+    assert_eq!(
+        frames,
+        [
+            "0x0000003c <inner+0x10>",
+            "0x0000001c <outer+0x14>",
+            "0x00000004 <main+0x4>",
+        ]
+    );
+}
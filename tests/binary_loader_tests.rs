@@ -0,0 +1,137 @@
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::loader::Image;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+fn words(source: &str) -> Vec<u8> {
+    assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect()
+}
+
+// ── Raw binaries ──────────────────────────────────────────────────────────────
+
+#[test]
+fn test_load_binary_at_base() {
+    let mut cpu = RiscvCpu::builder().ram_base(0x8000_0000).build().unwrap();
+    let code = words("addi a0, zero, 7");
+
+    cpu.load_binary(0x8000_0010, &code).unwrap();
+
+    assert_eq!(&cpu.bus[0x8000_0010..0x8000_0014], &code[..]);
+    assert_eq!(cpu.pc, 0x8000_0000, "a raw binary has no entry point");
+}
+
+#[test]
+fn test_load_binary_outside_ram_is_an_error() {
+    let mut cpu = RiscvCpu::builder()
+        .ram_base(0x1000)
+        .ram_size(0x100)
+        .build()
+        .unwrap();
+
+    assert!(cpu.load_binary(0x10FC, &[0; 4]).is_ok());
+    assert!(cpu.load_binary(0x10FD, &[0; 4]).is_err());
+    assert!(cpu.load_binary(0xFFC, &[0; 4]).is_err());
+    assert!(cpu.load_binary(u32::MAX, &[0; 4]).is_err());
+}
+
+#[test]
+fn test_load_binary_replaces_cached_code() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.load_binary(0, &words("top: addi a0, a0, 1\njal zero, top"))
+        .unwrap();
+    cpu.run_steps(4);
+
+    cpu.load_binary(0, &words("addi a0, a0, 10")).unwrap();
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.regs[10], 2 + 10);
+}
+
+#[test]
+fn test_load_binary_can_fill_rom() {
+    let mut cpu = RiscvCpu::builder()
+        .rom(0x100, [0; 4].as_slice())
+        .build()
+        .unwrap();
+
+    cpu.load_binary(0x100, &[1, 2, 3, 4]).unwrap();
+
+    assert_eq!(cpu.load(0x100, MemSize::Word, false), Ok(0x0403_0201));
+    assert_eq!(
+        cpu.store(0x100, MemSize::Word, 0),
+        Err(Exception::StoreAccessFault(0x100))
+    );
+}
+
+// ── Images ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_load_image_segments_and_entry() {
+    let mut cpu = RiscvCpu::new(0x1000);
+    let image = Image::new()
+        .segment(0x200, words("addi a0, zero, 7"))
+        .segment(0x800, [0xAA; 8].as_slice())
+        .entry(0x200);
+
+    cpu.load_image(&image).unwrap();
+
+    assert_eq!(cpu.pc, 0x200);
+    assert_eq!(&cpu.bus[0x800..0x808], &[0xAA; 8]);
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs[10], 7);
+}
+
+#[test]
+fn test_rom_segments_are_protected() {
+    let mut cpu = RiscvCpu::new(0x1000);
+    let image = Image::new()
+        .rom(0x100, [1, 2, 3, 4].as_slice())
+        .segment(0x200, [0; 4].as_slice());
+
+    cpu.load_image(&image).unwrap();
+
+    assert_eq!(
+        cpu.store(0x100, MemSize::Byte, 0),
+        Err(Exception::StoreAccessFault(0x100))
+    );
+    assert!(cpu.store(0x200, MemSize::Word, 0).is_ok());
+    assert_eq!(cpu.load(0x100, MemSize::Word, false), Ok(0x0403_0201));
+}
+
+#[test]
+fn test_overlapping_segments_are_rejected() {
+    let image = Image::new()
+        .segment(0x100, [0; 8].as_slice())
+        .segment(0x200, [0; 4].as_slice())
+        .segment(0x104, [0; 4].as_slice());
+    let (first, second) = image.overlap().unwrap();
+    assert_eq!((first.addr, second.addr), (0x100, 0x104));
+
+    let mut cpu = RiscvCpu::new(0x1000);
+    assert!(cpu.load_image(&image).is_err());
+
+    let touching = Image::new()
+        .segment(0x100, [0; 4].as_slice())
+        .segment(0x104, [0; 4].as_slice())
+        .segment(0x102, Vec::new());
+    assert!(touching.overlap().is_none());
+}
+
+#[test]
+fn test_bad_image_leaves_memory_alone() {
+    let mut cpu = RiscvCpu::new(0x1000);
+    cpu.pc = 0x40;
+    let image = Image::new()
+        .segment(0x100, [0xAA; 4].as_slice())
+        .segment(0x2000, [0xBB; 4].as_slice())
+        .entry(0x100);
+
+    assert!(cpu.load_image(&image).is_err());
+
+    assert_eq!(&cpu.bus[0x100..0x104], &[0; 4]);
+    assert_eq!(cpu.pc, 0x40);
+}
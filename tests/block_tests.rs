@@ -0,0 +1,173 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::debug::WatchKind;
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu, csr};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source).engine(engine).build().unwrap()
+}
+
+/// Sums 1..=100 into a0, storing the running total after every iteration.
+const SUM_LOOP: &str = "
+            addi t0, zero, 100
+            addi a0, zero, 0
+    loop:   add  a0, a0, t0
+            sw   a0, 0x400(zero)
+            addi t0, t0, -1
+            bne  t0, zero, loop
+            ebreak
+";
+
+// ── Equivalence with the interpreter ──────────────────────────────────────────
+
+#[test]
+fn test_blocks_match_the_interpreter() {
+    let mut interpreted = cpu_with(SUM_LOOP, Engine::Interpreter);
+    let mut blocks = cpu_with(SUM_LOOP, Engine::BasicBlocks);
+
+    let expected = interpreted.run();
+
+    assert_eq!(blocks.run(), expected);
+    assert_eq!(blocks.regs, interpreted.regs);
+    assert_eq!(blocks.pc, interpreted.pc);
+    assert_eq!(blocks.regs[10], 5050);
+}
+
+#[test]
+fn test_step_limit_is_exact_inside_a_block() {
+    let mut cpu = cpu_with(SUM_LOOP, Engine::BasicBlocks);
+
+    assert_eq!(cpu.run_steps(4), ExitReason::StepLimit);
+    assert_eq!(cpu.pc, 0x10, "stopped after the sw, mid-block");
+    assert_eq!(cpu.regs[10], 100);
+}
+
+#[test]
+fn test_run_until_stops_mid_block() {
+    let mut cpu = cpu_with(SUM_LOOP, Engine::BasicBlocks);
+
+    assert_eq!(cpu.run_until(0xC), ExitReason::ReachedPc(0xC));
+    assert_eq!(cpu.regs[10], 100);
+}
+
+// ── Debugging ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_breakpoint_inside_a_cached_block() {
+    let mut cpu = cpu_with(SUM_LOOP, Engine::BasicBlocks);
+    cpu.run_steps(10);
+
+    cpu.add_breakpoint(0x10);
+
+    assert_eq!(cpu.run(), ExitReason::Breakpoint(0x10));
+    assert_eq!(cpu.regs[5], 98);
+    assert_eq!(cpu.run(), ExitReason::Breakpoint(0x10), "next iteration");
+    assert_eq!(cpu.regs[5], 97);
+}
+
+#[test]
+fn test_watchpoint_fires_in_block_mode() {
+    let mut cpu = cpu_with(SUM_LOOP, Engine::BasicBlocks);
+    cpu.add_watchpoint(0x400, 4, WatchKind::Write);
+
+    match cpu.run() {
+        ExitReason::Watchpoint(hit) => assert_eq!(hit.pc, 0xC),
+        other => panic!("expected a watchpoint, got {:?}", other),
+    }
+    assert_eq!(cpu.pc, 0x10);
+}
+
+// ── Coherence ─────────────────────────────────────────────────────────────────
+
+/// The second pass through the loop must run the patched instruction even
+/// though the block holding it was already decoded.
+#[test]
+fn test_guest_store_invalidates_blocks() {
+    let patch = assemble("addi a0, a0, 100").unwrap()[0];
+    let mut cpu = cpu_with(
+        &format!(
+            "
+                    lui   t0, {upper:#x}
+                    addi  t0, t0, {lower}
+                    addi  t1, zero, 2
+            body:   addi  a0, a0, 1
+                    sw    t0, 12(zero)
+                    addi  t1, t1, -1
+                    bne   t1, zero, body
+                    ebreak
+            ",
+            upper = (patch + 0x800) >> 12,
+            lower = ((patch & 0xFFF) as i32) << 20 >> 20,
+        ),
+        Engine::BasicBlocks,
+    );
+
+    cpu.run();
+
+    assert_eq!(cpu.regs[10], 1 + 100);
+}
+
+#[test]
+fn test_host_patch_needs_a_flush() {
+    let mut cpu = cpu_with("top: addi a0, a0, 1\njal zero, top", Engine::BasicBlocks);
+    cpu.run_steps(4);
+
+    let patch = assemble("addi a0, a0, 10").unwrap()[0];
+    cpu.bus[0..4].copy_from_slice(&patch.to_le_bytes());
+    cpu.flush_blocks();
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.regs[10], 2 + 10);
+}
+
+// ── Traps and interrupts ──────────────────────────────────────────────────────
+
+#[test]
+fn test_exception_mid_block_traps_at_the_right_pc() {
+    let mut cpu = cpu_with(
+        "
+        lui  a0, 0x10
+        lw   a1, 0(a0)
+        addi a0, zero, 2
+        ",
+        Engine::BasicBlocks,
+    );
+    cpu.set_guest_traps(true);
+    cpu.csrs.write(csr::MTVEC, 0x100);
+
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0x4);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 5);
+}
+
+#[test]
+fn test_pending_interrupt_is_taken_before_the_next_block() {
+    let mut cpu = cpu_with(SUM_LOOP, Engine::BasicBlocks);
+    cpu.csrs.write(csr::MTVEC, 0x200);
+    cpu.csrs.write(csr::MIE, csr::MIP_MTIP);
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE);
+    cpu.run_steps(3);
+
+    cpu.raise_interrupt(Interrupt::MachineTimer);
+    cpu.run_steps(1);
+
+    assert_eq!(cpu.pc, 0x200);
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0xC);
+}
+
+#[test]
+fn test_exception_without_guest_traps_is_returned() {
+    let mut cpu = cpu_with("addi a0, zero, 1\necall", Engine::BasicBlocks);
+
+    assert_eq!(cpu.run(), ExitReason::Exception(Exception::EnvironmentCall));
+    assert_eq!(cpu.pc, 0x4);
+    assert_eq!(cpu.regs[10], 1);
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use riscv_emulator_rust::devices::Uart16550;
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+use common::le_bytes;
+
+#[test]
+fn test_defaults() {
+    let cpu = RiscvCpu::builder().build().unwrap();
+
+    assert_eq!(cpu.pc, 0);
+    assert_eq!(cpu.bus.ram_base(), 0);
+    assert_eq!(cpu.bus.len(), 64 * 1024);
+    assert_eq!(cpu.regs, [0; 32]);
+}
+
+#[test]
+fn test_ram_base_and_size() {
+    let mut cpu = RiscvCpu::builder()
+        .ram_base(0x8000_0000)
+        .ram_size(1024 * 1024)
+        .build()
+        .unwrap();
+
+    assert_eq!(cpu.bus.len(), 1024 * 1024);
+    assert_eq!(cpu.pc, 0x8000_0000, "reset vector defaults to start of RAM");
+    assert!(cpu.store(0x800F_FFFC, MemSize::Word, 1).is_ok());
+    assert!(cpu.store(0x8010_0000, MemSize::Word, 1).is_err());
+    assert!(cpu.store(0x0, MemSize::Word, 1).is_err());
+}
+
+#[test]
+fn test_reset_vector_and_stack_pointer() {
+    let cpu = RiscvCpu::builder()
+        .ram_size(4096)
+        .reset_vector(0x100)
+        .stack_pointer(0x1000)
+        .build()
+        .unwrap();
+
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.regs[2], 0x1000);
+}
+
+#[test]
+fn test_images_are_preloaded() {
+    let cpu = RiscvCpu::builder()
+        .ram_base(0x1000)
+        .ram_size(0x100)
+        .image(0x1000, vec![1, 2, 3, 4])
+        .image(0x1080, [0xAA, 0xBB].as_slice())
+        .build()
+        .unwrap();
+
+    assert_eq!(&cpu.bus[0x1000..0x1004], &[1, 2, 3, 4]);
+    assert_eq!(&cpu.bus[0x1080..0x1082], &[0xAA, 0xBB]);
+}
+
+#[test]
+fn test_image_outside_ram_is_an_error() {
+    let result = RiscvCpu::builder()
+        .ram_size(0x100)
+        .image(0xFE, vec![0; 4])
+        .build();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_devices_are_mapped() {
+    let mut cpu = RiscvCpu::builder()
+        .device(Uart16550::BASE, Uart16550::SIZE, Uart16550::new())
+        .build()
+        .unwrap();
+
+    // LSR.THRE should be readable through the bus.
+    let lsr = cpu.load(Uart16550::BASE + 5, MemSize::Byte, false).unwrap();
+    assert_ne!(lsr & 0x20, 0);
+}
+
+/// addi x1, x0, 10
+/// addi x2, x2, -16    ; push a frame off the initial sp
+#[test]
+fn test_program_runs_from_reset_vector() {
+    let mut cpu = RiscvCpu::builder()
+        .ram_base(0x8000_0000)
+        .ram_size(0x1000)
+        .stack_pointer(0x8000_1000)
+        .image(0x8000_0000, le_bytes(&[0x00a00093, 0xff010113]))
+        .build()
+        .unwrap();
+
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.regs[1], 10);
+    assert_eq!(cpu.regs[2], 0x8000_0FF0);
+    assert_eq!(cpu.pc, 0x8000_0008);
+}
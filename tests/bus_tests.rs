@@ -0,0 +1,284 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use riscv_emulator_rust::bus::Bus;
+use riscv_emulator_rust::devices::{Device, Ram};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// (kind, offset, bytes, value)
+type Access = (&'static str, u32, usize, u32);
+
+/// Records every access it sees so tests can check offsets and sizes.
+#[derive(Clone, Default)]
+struct Recorder {
+    log: Rc<RefCell<Vec<Access>>>,
+}
+
+impl Device for Recorder {
+    fn read(&mut self, offset: u32, size: MemSize) -> u32 {
+        self.log.borrow_mut().push(("r", offset, size.bytes(), 0));
+        0xC0DE_0000 | offset
+    }
+
+    fn write(&mut self, offset: u32, size: MemSize, value: u32) {
+        self.log
+            .borrow_mut()
+            .push(("w", offset, size.bytes(), value));
+    }
+}
+
+// ── RAM ───────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_ram_device_is_little_endian() {
+    let mut ram = Ram::new(16);
+
+    ram.write(0, MemSize::Word, 0x1122_3344);
+
+    assert_eq!(&ram.as_slice()[0..4], &[0x44, 0x33, 0x22, 0x11]);
+    assert_eq!(ram.read(0, MemSize::Half), 0x3344);
+    assert_eq!(ram.read(3, MemSize::Byte), 0x11);
+}
+
+#[test]
+fn test_ram_at_nonzero_base() {
+    let mut bus = Bus::new(0x8000_0000, 0x100);
+
+    bus.write(0x8000_0010, MemSize::Word, 0xCAFE_BABE).unwrap();
+
+    assert_eq!(bus.read(0x8000_0010, MemSize::Word), Some(0xCAFE_BABE));
+    assert_eq!(bus[0x8000_0010], 0xBE, "indexing uses absolute addresses");
+    assert_eq!(bus.read(0x0, MemSize::Byte), None, "nothing is mapped at 0");
+}
+
+#[test]
+fn test_access_straddling_ram_end_is_rejected() {
+    let mut bus = Bus::new(0, 0x100);
+
+    assert_eq!(bus.read(0xFE, MemSize::Word), None);
+    assert_eq!(bus.write(0xFE, MemSize::Half, 0), Some(()));
+    assert_eq!(bus.write(0xFF, MemSize::Half, 0), None);
+}
+
+#[test]
+fn test_write_bytes() {
+    let mut bus = Bus::new(0x1000, 0x10);
+
+    assert_eq!(bus.write_bytes(0x1004, &[1, 2, 3]), Some(()));
+    assert_eq!(&bus[0x1004..0x1007], &[1, 2, 3]);
+    assert_eq!(bus.write_bytes(0x100E, &[1, 2, 3]), None);
+}
+
+// ── Sparse RAM ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_sparse_ram_allocates_pages_on_first_write() {
+    let mut ram = Ram::sparse(1 << 32);
+
+    assert_eq!(ram.read(0xFFFF_FFFC, MemSize::Word), 0);
+    assert_eq!(ram.resident_bytes(), 0, "reads don't allocate");
+
+    ram.write(0xFFFF_FFFC, MemSize::Word, 0x1122_3344);
+    ram.write(0x1000, MemSize::Byte, 0xAA);
+
+    assert_eq!(ram.read(0xFFFF_FFFE, MemSize::Half), 0x1122);
+    assert_eq!(ram.read(0x1000, MemSize::Word), 0xAA);
+    assert_eq!(ram.resident_bytes(), 2 * 4096);
+}
+
+#[test]
+fn test_sparse_ram_access_across_a_page_boundary() {
+    let mut ram = Ram::sparse(0x10000);
+
+    ram.write(0xFFE, MemSize::Word, 0xDEAD_BEEF);
+
+    assert_eq!(ram.read(0xFFE, MemSize::Word), 0xDEAD_BEEF);
+    assert_eq!(ram.read(0x1000, MemSize::Half), 0xDEAD);
+    assert_eq!(ram.resident_bytes(), 2 * 4096);
+}
+
+#[test]
+fn test_sparse_ram_skips_zero_writes_to_untouched_pages() {
+    let mut ram = Ram::sparse(0x10000);
+
+    ram.write_bytes(0, &[0; 0x8000]);
+    ram.write_bytes(0x8000, &[0, 0, 7]);
+
+    assert_eq!(ram.resident_bytes(), 4096);
+    assert_eq!(ram.to_vec()[0x8002], 7);
+}
+
+#[test]
+fn test_cpu_with_four_gib_of_sparse_ram() {
+    let mut cpu = RiscvCpu::builder()
+        .sparse_ram(true)
+        .ram_size(1 << 32)
+        .image(0, [0x13, 0x00, 0x00, 0x00].as_slice()) // nop
+        .build()
+        .unwrap();
+
+    cpu.store(0xF000_0000, MemSize::Word, 42).unwrap();
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.load(0xF000_0000, MemSize::Word, false), Ok(42));
+    assert!(cpu.bus.ram().is_sparse());
+    assert_eq!(cpu.bus.ram().resident_bytes(), 2 * 4096);
+}
+
+// ── File-backed RAM ───────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+#[test]
+fn test_file_backed_ram_persists_guest_writes() {
+    let path = std::env::temp_dir().join(format!("ram-{}.bin", std::process::id()));
+    std::fs::write(&path, [0x13, 0x00, 0x00, 0x00]).unwrap(); // nop
+
+    let mut cpu = RiscvCpu::builder()
+        .ram_file(&path)
+        .ram_size(0x2000)
+        .build()
+        .unwrap();
+    cpu.step().unwrap();
+    cpu.store(0x1FFC, MemSize::Word, 0xFEED_F00D).unwrap();
+
+    assert!(cpu.bus.ram().is_mapped());
+    assert_eq!(cpu.bus[0], 0x13, "the file is the initial image");
+    cpu.bus.ram().flush().unwrap();
+    drop(cpu);
+
+    let contents = std::fs::read(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(contents.len(), 0x2000, "the file grows to fit RAM");
+    assert_eq!(&contents[0x1FFC..], &0xFEED_F00Du32.to_le_bytes());
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_unmappable_ram_file_is_a_build_error() {
+    let result = RiscvCpu::builder()
+        .ram_file("/nonexistent/dir/ram.bin")
+        .build();
+
+    assert!(result.is_err());
+}
+
+// ── Read-only regions ─────────────────────────────────────────────────────────
+
+#[test]
+fn test_protected_range_rejects_writes_but_not_reads() {
+    let mut bus = Bus::new(0, 0x100);
+    bus.write_bytes(0x10, &[1, 2, 3, 4]).unwrap();
+    bus.protect(0x10, 0x10);
+
+    assert_eq!(bus.write(0x10, MemSize::Byte, 0xFF), None);
+    assert_eq!(
+        bus.write(0x0E, MemSize::Word, 0),
+        None,
+        "partial overlap counts"
+    );
+    assert_eq!(bus.write(0x20, MemSize::Word, 0), Some(()));
+    assert_eq!(bus.read(0x10, MemSize::Word), Some(0x0403_0201));
+
+    bus.write_bytes(0x10, &[9]).unwrap();
+    assert_eq!(bus[0x10], 9, "the host can still patch it");
+}
+
+#[test]
+fn test_guest_store_to_rom_faults() {
+    let mut cpu = RiscvCpu::builder()
+        .rom(0x1000, [0xAA; 0x100].as_slice())
+        .build()
+        .unwrap();
+    cpu.map_device(0x4000, 0x100, Recorder::default());
+    cpu.bus.protect(0x4000, 0x100);
+
+    assert_eq!(
+        cpu.store(0x10FC, MemSize::Word, 0),
+        Err(Exception::StoreAccessFault(0x10FC))
+    );
+    assert_eq!(
+        cpu.store(0x4000, MemSize::Byte, 0),
+        Err(Exception::StoreAccessFault(0x4000))
+    );
+    assert_eq!(cpu.load(0x1000, MemSize::Byte, false), Ok(0xAA));
+    assert!(cpu.store(0x1100, MemSize::Word, 0).is_ok());
+}
+
+// ── Devices ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_device_receives_relative_offsets() {
+    let recorder = Recorder::default();
+    let mut bus = Bus::new(0, 0x100);
+    bus.map(0x2000, 0x10, Box::new(recorder.clone()));
+
+    assert_eq!(bus.read(0x2004, MemSize::Word), Some(0xC0DE_0004));
+    bus.write(0x200C, MemSize::Byte, 0x5A).unwrap();
+
+    assert_eq!(
+        *recorder.log.borrow(),
+        vec![("r", 0x4, 4, 0), ("w", 0xC, 1, 0x5A)]
+    );
+}
+
+#[test]
+fn test_unmapped_address_is_rejected() {
+    let mut bus = Bus::new(0, 0x100);
+    bus.map(0x2000, 0x10, Box::new(Recorder::default()));
+
+    assert_eq!(bus.read(0x2010, MemSize::Byte), None);
+    assert_eq!(
+        bus.read(0x200E, MemSize::Word),
+        None,
+        "runs past the device"
+    );
+    assert_eq!(bus.write(0x1000, MemSize::Byte, 0), None);
+}
+
+#[test]
+fn test_multiple_devices() {
+    let a = Recorder::default();
+    let b = Recorder::default();
+    let mut bus = Bus::new(0, 0x100);
+    bus.map(0x1000, 0x100, Box::new(a.clone()));
+    bus.map(0x2000, 0x100, Box::new(b.clone()));
+
+    bus.write(0x1000, MemSize::Word, 1).unwrap();
+    bus.write(0x2000, MemSize::Word, 2).unwrap();
+
+    assert_eq!(a.log.borrow().len(), 1);
+    assert_eq!(b.log.borrow()[0].3, 2);
+}
+
+// ── CPU integration ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_cpu_loads_and_stores_go_through_devices() {
+    let recorder = Recorder::default();
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.map_device(0x4000, 0x100, recorder.clone());
+
+    cpu.store(0x4008, MemSize::Half, 0xBEEF).unwrap();
+    let value = cpu.load(0x4010, MemSize::Byte, false).unwrap();
+
+    assert_eq!(value, 0x10, "byte load truncates the device's return value");
+    assert_eq!(recorder.log.borrow()[0], ("w", 0x8, 2, 0xBEEF));
+}
+
+#[test]
+fn test_cpu_faults_on_unmapped_device_space() {
+    let mut cpu = RiscvCpu::new(1024);
+
+    assert_eq!(
+        cpu.load(0x4000, MemSize::Word, false),
+        Err(Exception::LoadAccessFault(0x4000))
+    );
+    assert_eq!(
+        cpu.store(0x4000, MemSize::Word, 0),
+        Err(Exception::StoreAccessFault(0x4000))
+    );
+}
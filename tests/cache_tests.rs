@@ -0,0 +1,247 @@
+mod common;
+
+use riscv_emulator_rust::cache::{CacheConfig, CacheModel, CacheStats};
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::{Engine, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, caches: CacheModel, engine: Engine) -> RiscvCpu {
+    program(source)
+        .ram_size(0x4000)
+        .engine(engine)
+        .cache_model(caches)
+        .build()
+        .unwrap()
+}
+
+fn config(size: u32, ways: u32, line_size: u32) -> CacheConfig {
+    CacheConfig::new(size, ways, line_size).unwrap()
+}
+
+fn stats(hits: u64, misses: u64, writebacks: u64) -> CacheStats {
+    CacheStats {
+        hits,
+        misses,
+        writebacks,
+    }
+}
+
+/// Loads 0x1000 and 0x1040 in turn, four times each. They're 64 bytes
+/// apart, so they land in the same set of a small cache.
+const PING_PONG: &str = "
+        lui   s0, 1
+        addi  a0, zero, 4
+    top:
+        lw    t0, 0(s0)
+        lw    t1, 0x40(s0)
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        ebreak
+";
+
+// ── Configuration ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_config_must_be_powers_of_two() {
+    assert!(CacheConfig::new(32 * 1024, 4, 64).is_ok());
+    assert!(CacheConfig::new(256, 4, 64).is_ok(), "fully associative");
+
+    let error = CacheConfig::new(3000, 4, 64).unwrap_err();
+    assert!(error.contains("size 3000"), "{}", error);
+    assert!(CacheConfig::new(1024, 3, 64).is_err());
+    assert!(CacheConfig::new(1024, 4, 2).is_err(), "lines too short");
+    assert!(
+        CacheConfig::new(128, 4, 64).is_err(),
+        "too small for its ways"
+    );
+}
+
+// ── Data cache ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_conflicting_lines_miss_in_a_direct_mapped_cache() {
+    let caches = CacheModel::new().dcache(config(64, 1, 16));
+    let mut cpu = cpu_with(PING_PONG, caches, Engine::Interpreter);
+
+    cpu.run();
+
+    let model = cpu.cache_model().unwrap();
+    assert_eq!(model.dcache_stats(), Some(stats(0, 8, 0)));
+    assert_eq!(model.icache_stats(), None);
+}
+
+#[test]
+fn test_two_ways_hold_both_lines() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let caches = CacheModel::new().dcache(config(64, 2, 16));
+        let mut cpu = cpu_with(PING_PONG, caches, engine);
+        cpu.run();
+
+        let dcache = cpu.cache_model().unwrap().dcache_stats().unwrap();
+        assert_eq!(dcache, stats(6, 2, 0), "{:?}", engine);
+        assert_eq!(dcache.accesses(), 8, "{:?}", engine);
+        assert_eq!(dcache.hit_rate(), 0.75, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_least_recently_used_line_is_evicted() {
+    // 0x1000 is touched again after 0x1040, so 0x1080 evicts 0x1040.
+    let source = "
+        lui   s0, 1
+        lw    t0, 0(s0)
+        lw    t0, 0x40(s0)
+        lw    t0, 0(s0)
+        lw    t0, 0x80(s0)
+        lw    t0, 0(s0)
+        lw    t0, 0x40(s0)
+        ebreak
+    ";
+    let caches = CacheModel::new().dcache(config(64, 2, 16));
+    let mut cpu = cpu_with(source, caches, Engine::Interpreter);
+
+    cpu.run();
+
+    assert_eq!(
+        cpu.cache_model().unwrap().dcache_stats(),
+        Some(stats(2, 4, 0))
+    );
+}
+
+#[test]
+fn test_dirty_lines_are_written_back() {
+    let source = "
+        lui   s0, 1
+        sw    zero, 0(s0)
+        lw    t0, 0x40(s0)
+        lw    t0, 0(s0)
+        ebreak
+    ";
+    let caches = CacheModel::new().dcache(config(64, 1, 16));
+    let mut cpu = cpu_with(source, caches, Engine::Interpreter);
+
+    cpu.run();
+
+    assert_eq!(
+        cpu.cache_model().unwrap().dcache_stats(),
+        Some(stats(0, 3, 1)),
+        "only the stored line was dirty"
+    );
+}
+
+#[test]
+fn test_access_spanning_lines_touches_each() {
+    let source = "
+        lui   s0, 1
+        fsd   f0, 0(s0)
+        fld   f1, 4(s0)
+        ebreak
+    ";
+    let caches = CacheModel::new().dcache(config(64, 1, 4));
+    let mut cpu = cpu_with(source, caches, Engine::Interpreter);
+
+    cpu.run();
+
+    assert_eq!(
+        cpu.cache_model().unwrap().dcache_stats(),
+        Some(stats(1, 3, 0))
+    );
+}
+
+// ── Instruction cache and L2 ──────────────────────────────────────────────────
+
+#[test]
+fn test_fetches_go_through_the_icache() {
+    // Seven instructions in two 16-byte lines, the loop body run four
+    // times: 18 fetches, the ebreak traps before it retires.
+    let caches = CacheModel::new().icache(config(1024, 2, 16));
+    let mut cpu = cpu_with(PING_PONG, caches, Engine::Interpreter);
+
+    cpu.run();
+
+    assert_eq!(
+        cpu.cache_model().unwrap().icache_stats(),
+        Some(stats(16, 2, 0))
+    );
+}
+
+#[test]
+fn test_l2_sees_l1_misses() {
+    let caches = CacheModel::new()
+        .icache(config(1024, 2, 16))
+        .dcache(config(64, 1, 16))
+        .l2(config(1024, 4, 16));
+    let mut cpu = cpu_with(PING_PONG, caches, Engine::Interpreter);
+
+    cpu.run();
+
+    let model = cpu.cache_model().unwrap();
+    let (icache, dcache, l2) = (
+        model.icache_stats().unwrap(),
+        model.dcache_stats().unwrap(),
+        model.l2_stats().unwrap(),
+    );
+    assert_eq!(l2.accesses(), icache.misses + dcache.misses);
+    assert_eq!(l2, stats(6, 4, 0));
+}
+
+// ── Reports ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_reset_keeps_lines_cached() {
+    let caches = CacheModel::new().dcache(config(64, 2, 16));
+    let mut cpu = cpu_with(PING_PONG, caches, Engine::Interpreter);
+    cpu.run_steps(4);
+
+    cpu.cache_model_mut().unwrap().reset_stats();
+    cpu.run();
+
+    assert_eq!(
+        cpu.cache_model().unwrap().dcache_stats(),
+        Some(stats(6, 0, 0))
+    );
+}
+
+#[test]
+fn test_report_lists_each_cache() {
+    let caches = CacheModel::new()
+        .dcache(config(64, 2, 16))
+        .l2(config(1024, 4, 16));
+    let mut cpu = cpu_with(PING_PONG, caches, Engine::Interpreter);
+    cpu.run();
+
+    let report = cpu.take_cache_model().unwrap().to_string();
+    let lines: Vec<Vec<&str>> = report
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1], ["L1D", "6", "2", "0", "75.00%"]);
+    assert_eq!(lines[2][0], "L2");
+    assert!(cpu.cache_model().is_none());
+}
+
+#[test]
+fn test_machine_harts_get_their_own_caches() {
+    let caches = CacheModel::new().dcache(config(64, 2, 16));
+    let mut cpu = cpu_with(PING_PONG, caches, Engine::Interpreter);
+    cpu.run_steps(3);
+
+    let machine = Machine::new(cpu, 2);
+
+    let hart0 = machine.hart(0).cache_model().unwrap();
+    let hart1 = machine.hart(1).cache_model().unwrap();
+    assert_eq!(hart0.dcache_stats(), Some(stats(0, 1, 0)));
+    assert_eq!(hart1.dcache_stats(), Some(stats(0, 0, 0)));
+}
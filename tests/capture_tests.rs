@@ -0,0 +1,117 @@
+use std::io::Write;
+
+use riscv_emulator_rust::capture::OutputCapture;
+use riscv_emulator_rust::devices::Uart16550;
+use riscv_emulator_rust::program::Program;
+use riscv_emulator_rust::semihosting::{SYS_WRITE0, Semihosting};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{ExitReason, RiscvCpu};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const DATA: u32 = 0x400;
+
+/// Writes `text` a byte at a time to the UART's THR.
+fn print_uart(mut program: Program, text: &str) -> Program {
+    program = program.lui(5, Uart16550::BASE >> 12);
+    for b in text.bytes() {
+        program = program.addi(6, 0, b as i32).sb(6, 5, 0);
+    }
+    program
+}
+
+/// Prints the NUL-terminated string at [`DATA`] through semihosting.
+fn print_semihosting(program: Program) -> Program {
+    program
+        .addi(10, 0, SYS_WRITE0 as i32)
+        .addi(11, 0, DATA as i32)
+        .slli(0, 0, 0x1f)
+        .ebreak()
+        .srai(0, 0, 7)
+}
+
+fn cpu(program: Program, data: &[u8]) -> RiscvCpu {
+    RiscvCpu::builder()
+        .image(0, program.ebreak().build().unwrap())
+        .image(DATA, data)
+        .device(Uart16550::BASE, Uart16550::SIZE, Uart16550::new())
+        .semihosting(Semihosting::new())
+        .build()
+        .unwrap()
+}
+
+// ── OutputCapture ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_clones_share_the_buffer() {
+    let capture = OutputCapture::new();
+    let mut sink = capture.clone();
+    sink.write_all(b"hello ").unwrap();
+    sink.write_all(&[0xff, b'\n']).unwrap();
+
+    assert_eq!(capture.bytes(), b"hello \xff\n");
+    assert_eq!(capture.text(), "hello \u{fffd}\n");
+    assert_eq!(capture.take(), b"hello \xff\n");
+    assert!(capture.bytes().is_empty());
+
+    sink.write_all(b"again").unwrap();
+    capture.clear();
+    assert_eq!(capture.text(), "");
+}
+
+#[test]
+fn test_can_be_sent_to_another_thread() {
+    let capture = OutputCapture::new();
+    let mut sink = capture.clone();
+    std::thread::spawn(move || sink.write_all(b"from a thread").unwrap())
+        .join()
+        .unwrap();
+
+    assert_eq!(capture.text(), "from a thread");
+}
+
+// ── Capturing a hart's output ─────────────────────────────────────────────────
+
+#[test]
+fn test_captures_the_uart_and_semihosting_in_order() {
+    let program = print_semihosting(print_uart(Program::at(0), "uart, "));
+    let mut cpu = cpu(print_uart(program, "!\n"), b"semihosting\0");
+
+    let output = cpu.capture_output();
+    let exit = cpu.run();
+
+    assert!(matches!(
+        exit,
+        ExitReason::Exception(Exception::Breakpoint(_))
+    ));
+    assert_eq!(output.text(), "uart, semihosting!\n");
+}
+
+#[test]
+fn test_captures_only_what_comes_after() {
+    let mut cpu = cpu(print_uart(Program::at(0), "one"), b"");
+    let first = cpu.capture_output();
+    cpu.run_steps(4);
+
+    let second = cpu.capture_output();
+    cpu.run();
+
+    assert_eq!(first.text(), "o");
+    assert_eq!(second.text(), "ne");
+}
+
+#[test]
+fn test_machine_captures_the_shared_uart() {
+    let image = print_uart(Program::at(0), "hi").ebreak().build().unwrap();
+    let mut machine = RiscvCpu::builder()
+        .image(0, image)
+        .device(Uart16550::BASE, Uart16550::SIZE, Uart16550::new())
+        .build_machine(2)
+        .unwrap();
+    machine.set_quantum(1);
+
+    let output = machine.capture_output();
+    machine.run();
+
+    assert_eq!(output.text(), "hhii");
+}
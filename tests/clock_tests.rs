@@ -0,0 +1,136 @@
+#[cfg(feature = "std")]
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use riscv_emulator_rust::clock::{Clock, TimeMode};
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::program::Program;
+use riscv_emulator_rust::{ExitReason, Reg, RiscvCpu};
+
+// ── Clock ─────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_converts_ticks_at_its_frequency() {
+    let clock = Clock::new(TimeMode::Virtual);
+    assert_eq!(clock.ticks_per_second(), 10_000_000);
+    assert_eq!(clock.duration(10_000_000), Duration::from_secs(1));
+    assert_eq!(clock.duration(15), Duration::from_nanos(1500));
+
+    let clock = clock.frequency(32_768);
+    assert_eq!(clock.ticks(Duration::from_millis(500)), 16_384);
+    assert_eq!(clock.ticks(Duration::from_nanos(1)), 0, "rounds down");
+}
+
+#[test]
+#[should_panic(expected = "Clock: the frequency must be at least 1 Hz")]
+fn test_frequency_cant_be_zero() {
+    let _ = Clock::new(TimeMode::Virtual).frequency(0);
+}
+
+#[test]
+fn test_virtual_wall_clock_starts_at_the_epoch() {
+    let clock = Clock::new(TimeMode::Virtual);
+    assert_eq!(clock.realtime(10_000_000), Duration::from_secs(1));
+
+    let clock = clock.epoch(Duration::from_secs(1_700_000_000));
+    assert_eq!(
+        clock.realtime(5_000_000),
+        Duration::from_millis(1_700_000_000_500)
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_host_wall_clock_is_the_hosts() {
+    let clock = Clock::new(TimeMode::Host).epoch(Duration::ZERO);
+    assert!(clock.realtime(0) > Duration::from_secs(1_600_000_000));
+}
+
+// ── Guest time ────────────────────────────────────────────────────────────────
+
+/// Reads `time` into a0, spins `spins` times, then reads it into a1.
+fn timer_program(spins: i32) -> Vec<u8> {
+    Program::at(0)
+        .csrrs(10, csr::TIME, 0)
+        .addi(5, 0, spins)
+        .label("spin")
+        .addi(5, 5, -1)
+        .bne(5, 0, "spin")
+        .csrrs(11, csr::TIME, 0)
+        .ebreak()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_virtual_time_is_the_same_every_run() {
+    let times: Vec<(u64, u64)> = (0..2)
+        .map(|_| {
+            let mut cpu = RiscvCpu::builder()
+                .image(0, timer_program(100))
+                .build()
+                .unwrap();
+            assert!(matches!(cpu.run(), ExitReason::Exception(_)));
+            (cpu.reg(Reg::A0), cpu.reg(Reg::A1))
+        })
+        .collect();
+
+    assert_eq!(times[0], times[1]);
+    assert_eq!(times[0].1 - times[0].0, 202, "one tick per instruction");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_host_time_follows_the_host_clock() {
+    let mut cpu = RiscvCpu::builder()
+        .image(0, timer_program(1))
+        .clock(Clock::new(TimeMode::Host).frequency(1_000))
+        .build()
+        .unwrap();
+    assert_eq!(cpu.clock().mode(), TimeMode::Host);
+
+    cpu.step().unwrap();
+    thread::sleep(Duration::from_millis(20));
+    assert!(matches!(cpu.run(), ExitReason::Exception(_)));
+
+    let elapsed = cpu.reg(Reg::A1) - cpu.reg(Reg::A0);
+    assert!(elapsed >= 20, "{} ms passed", elapsed);
+    assert!(elapsed < 10_000, "not one tick per instruction");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_skipping_host_time_sleeps() {
+    let mut cpu = RiscvCpu::builder()
+        .clock(Clock::new(TimeMode::Host).frequency(1_000))
+        .build()
+        .unwrap();
+
+    let started = Instant::now();
+    cpu.bus.skip(30);
+
+    assert!(started.elapsed() >= Duration::from_millis(30));
+    assert!(cpu.bus.time() >= 30);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_switching_clocks_keeps_counting_from_where_it_was() {
+    let mut cpu = RiscvCpu::builder()
+        .image(0, timer_program(1000))
+        .build()
+        .unwrap();
+    cpu.run_steps(500);
+    let before = cpu.bus.time();
+
+    cpu.set_clock(Clock::new(TimeMode::Host));
+    cpu.run_steps(10);
+
+    assert!(cpu.bus.time() >= before);
+    assert!(
+        cpu.bus.time() < before + 10_000_000,
+        "not the host's uptime"
+    );
+}
@@ -0,0 +1,58 @@
+//! Fixtures shared by the integration tests. Each test file pulls this in
+//! with `mod common;` and uses only some of it.
+
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use riscv_emulator_rust::asm::assemble_at;
+use riscv_emulator_rust::{RiscvCpu, RiscvCpuBuilder};
+
+/// `source` assembled into little-endian bytes to load at address 0.
+pub fn image(source: &str) -> Vec<u8> {
+    image_at(0, source)
+}
+
+/// `source` assembled into little-endian bytes to load at `base`.
+pub fn image_at(base: u32, source: &str) -> Vec<u8> {
+    le_bytes(&assemble_at(base, source).expect("assembly failed"))
+}
+
+/// `words` laid out little-endian, as RAM holds them.
+pub fn le_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+/// A builder with `source` loaded at address 0, to configure further.
+pub fn program(source: &str) -> RiscvCpuBuilder {
+    RiscvCpu::builder().image(0, image(source))
+}
+
+/// A default RV32 hart with `source` loaded at address 0.
+pub fn cpu_with(source: &str) -> RiscvCpu {
+    program(source).build().unwrap()
+}
+
+/// A `Write` sink the test can keep a handle to after handing it over.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Everything written so far, as UTF-8.
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
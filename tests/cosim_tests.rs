@@ -0,0 +1,310 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use riscv_emulator_rust::RiscvCpu;
+use riscv_emulator_rust::cosim::{
+    CONTEXT, Commit, Format, Parser, Reference, compare, compare_traces, read_trace, record,
+    write_trace,
+};
+use riscv_emulator_rust::encode::*;
+use riscv_emulator_rust::program::Program;
+
+use common::le_bytes;
+
+fn cpu(words: &[u32]) -> RiscvCpu {
+    RiscvCpu::builder()
+        .image(0, le_bytes(words))
+        .build()
+        .unwrap()
+}
+
+fn commit(pc: u64, raw: u32, writes: &[(u8, u64)]) -> Commit {
+    Commit {
+        pc,
+        raw,
+        writes: writes.to_vec(),
+    }
+}
+
+/// `x1 = 5; x2 = x1 + x1; x1 = 5 again; ebreak`.
+fn program() -> Vec<u32> {
+    vec![addi(1, 0, 5), add(2, 1, 1), addi(1, 0, 5), ebreak()]
+}
+
+/// What a reference would report for the first three instructions.
+fn trace(program: &[u32]) -> Vec<Commit> {
+    vec![
+        commit(0, program[0], &[(1, 5)]),
+        commit(4, program[1], &[(2, 10)]),
+        commit(8, program[2], &[(1, 5)]),
+    ]
+}
+
+// ── Parsing ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_parses_spike_commit_logs() {
+    let log = "\
+core   0: 3 0x80000000 (0x00000297) x5  0x80000000
+core   0: 3 0x80000004 (0x0062a023) mem 0x80001000 0x00000000
+core   0: 3 0x80000008 (0x0002a303) x6  0x00000007 mem 0x80001000
+core   0: exception trap_illegal_instruction, epc 0x8000000c
+core   0: 3 0x80000010 (0x30529073) c773_mtvec 0x80000010
+core   0: 0x80000014 (0x00000013) x0  0x00000000
+";
+    assert_eq!(
+        Parser::parse(Format::Spike, log),
+        vec![
+            commit(0x8000_0000, 0x0000_0297, &[(5, 0x8000_0000)]),
+            commit(0x8000_0004, 0x0062_a023, &[]),
+            commit(0x8000_0008, 0x0002_a303, &[(6, 7)]),
+            commit(0x8000_0010, 0x3052_9073, &[]),
+            commit(0x8000_0014, 0x0000_0013, &[]),
+        ]
+    );
+}
+
+#[test]
+fn test_parses_sail_traces() {
+    let log = "\
+Running file rv32ui-p-add.elf.
+[0] [M]: 0x0000000080000000 (0x00000297) auipc t0, 0
+x5 <- 0x0000000080000000
+[1] [M]: 0x0000000080000004 (0x00000000) illegal
+trapping from M to M to handle illegal-instruction
+[2] [M]: 0x0000000080000100 (0x34202f73) csrrs t5, mcause, zero
+CSR mcause -> 0x0000000000000002
+x30 <- 0x0000000000000002
+";
+    assert_eq!(
+        Parser::parse(Format::Sail, log),
+        vec![
+            commit(0x8000_0000, 0x0000_0297, &[(5, 0x8000_0000)]),
+            commit(0x8000_0100, 0x3420_2f73, &[(30, 2)]),
+        ]
+    );
+}
+
+// ── Comparison ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_agreeing_traces_compare_clean() {
+    let program = program();
+    let mut cpu = cpu(&program);
+    assert_eq!(compare(&mut cpu, trace(&program), 100), Ok(3));
+    assert_eq!(cpu.pc, 12);
+}
+
+#[test]
+fn test_stops_at_max_steps() {
+    let program = program();
+    let mut cpu = cpu(&program);
+    assert_eq!(compare(&mut cpu, trace(&program), 2), Ok(2));
+    assert_eq!(cpu.pc, 8);
+}
+
+#[test]
+fn test_reports_a_wrong_value() {
+    let program = program();
+    let mut expected = trace(&program);
+    expected[1].writes = vec![(2, 11)];
+
+    let divergence = compare(&mut cpu(&program), expected.clone(), 100).unwrap_err();
+    assert_eq!(divergence.step, 1);
+    assert_eq!(divergence.expected, Some(expected[1].clone()));
+    assert_eq!(divergence.actual, Some(commit(4, program[1], &[(2, 10)])));
+    assert_eq!(divergence.context, vec![expected[0].clone()]);
+}
+
+#[test]
+fn test_checks_writes_that_leave_a_register_unchanged() {
+    // The third instruction writes x1 with the 5 it already holds.
+    let program = program();
+    let mut expected = trace(&program);
+    expected[2].writes = vec![(1, 6)];
+
+    let divergence = compare(&mut cpu(&program), expected, 100).unwrap_err();
+    assert_eq!(divergence.step, 2);
+    assert_eq!(divergence.actual, Some(commit(8, program[2], &[(1, 5)])));
+}
+
+#[test]
+fn test_reports_a_missing_write() {
+    let program = program();
+    let mut expected = trace(&program);
+    expected[1].writes.clear();
+
+    let divergence = compare(&mut cpu(&program), expected, 100).unwrap_err();
+    assert_eq!(divergence.step, 1);
+}
+
+#[test]
+fn test_reports_a_different_path() {
+    let program = program();
+    let mut expected = trace(&program);
+    expected[1].pc = 8;
+
+    let divergence = compare(&mut cpu(&program), expected, 100).unwrap_err();
+    assert_eq!(divergence.step, 1);
+    assert_eq!(divergence.actual.unwrap().pc, 4);
+}
+
+#[test]
+fn test_reports_the_emulator_stopping() {
+    let program = program();
+    let mut expected = trace(&program);
+    expected.push(commit(12, ebreak(), &[]));
+
+    // EBREAK goes to the host here, so it never retires.
+    let divergence = compare(&mut cpu(&program), expected, 100).unwrap_err();
+    assert_eq!(divergence.step, 3);
+    assert_eq!(divergence.actual, None);
+    assert!(divergence.stopped.unwrap().starts_with("EBREAK"));
+}
+
+#[test]
+fn test_context_is_bounded() {
+    let mut program = Program::at(0);
+    for _ in 0..20 {
+        program = program.addi(1, 1, 1);
+    }
+    let words: Vec<u32> = (0..20).map(|_| addi(1, 1, 1)).collect();
+    let image = program.build().unwrap();
+    let mut cpu = RiscvCpu::builder().image(0, image).build().unwrap();
+
+    let mut expected: Vec<Commit> = (0..20)
+        .map(|i| commit(4 * i, words[i as usize], &[(1, i + 1)]))
+        .collect();
+    expected[15].writes = vec![(1, 99)];
+
+    let divergence = compare(&mut cpu, expected.clone(), 100).unwrap_err();
+    assert_eq!(divergence.step, 15);
+    assert_eq!(divergence.context, expected[15 - CONTEXT..15].to_vec());
+    assert!(
+        divergence
+            .to_string()
+            .starts_with("diverged after 15 instructions\n")
+    );
+}
+
+#[test]
+fn test_replays_commits_before_the_entry_point() {
+    // Spike runs a boot ROM at 0x1000 before jumping to the program.
+    let program = vec![add(3, 10, 11), ebreak()];
+    let mut expected = vec![
+        commit(0x1000, auipc(5, 0), &[(5, 0x1000)]),
+        commit(0x1004, addi(11, 5, 32), &[(11, 0x1020)]),
+        commit(0x1008, jalr(0, 5, 0), &[]),
+    ];
+    expected.push(commit(0, program[0], &[(3, 0x1020)]));
+
+    let mut cpu = cpu(&program);
+    assert_eq!(compare(&mut cpu, expected, 100), Ok(1));
+    assert_eq!(cpu.regs[11], 0x1020);
+}
+
+#[test]
+fn test_traps_are_not_commits() {
+    // ECALL traps to the handler at 0x100 without retiring.
+    let image = Program::at(0).csrrw(0, 0x305, 1).ecall().build().unwrap();
+    let mut cpu = RiscvCpu::builder()
+        .image(0, image)
+        .image(0x100, addi(2, 0, 1).to_le_bytes().to_vec())
+        .guest_traps(true)
+        .build()
+        .unwrap();
+    cpu.regs[1] = 0x100;
+
+    let expected = vec![
+        commit(0, csrrw(0, 0x305, 1), &[]),
+        commit(0x100, addi(2, 0, 1), &[(2, 1)]),
+    ];
+    assert_eq!(compare(&mut cpu, expected, 100), Ok(2));
+}
+
+#[test]
+fn test_missing_reference_is_an_error() {
+    let reference = Reference::spike("a.elf").program("no-such-reference-simulator");
+    let error = reference.spawn().err().unwrap();
+    assert!(error.starts_with("Reference: couldn't run no-such-reference-simulator"));
+}
+
+// ── Golden traces ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_records_what_retired() {
+    let program = program();
+    assert_eq!(record(&mut cpu(&program), 100), trace(&program));
+    assert_eq!(record(&mut cpu(&program), 2), trace(&program)[..2].to_vec());
+}
+
+#[test]
+fn test_traces_round_trip_through_text() {
+    let program = program();
+    let text = write_trace(&trace(&program));
+    assert_eq!(text.lines().next(), Some("0x00000000 (0x00500093) x1 0x5"));
+    assert_eq!(read_trace(&text), Ok(trace(&program)));
+    assert_eq!(
+        read_trace(&format!("# golden\n\n{}", text)),
+        Ok(trace(&program))
+    );
+}
+
+#[test]
+fn test_malformed_trace_lines_are_errors() {
+    assert_eq!(
+        read_trace("0x0 (0x13)\n0x4 (0x13) x1\n"),
+        Err("line 2: Commit: expected `xN value` in \"0x4 (0x13) x1\"".to_string())
+    );
+    assert!(
+        read_trace("addi x1, x0, 5")
+            .unwrap_err()
+            .starts_with("line 1: Commit:")
+    );
+}
+
+#[test]
+fn test_identical_traces_compare_clean() {
+    let program = program();
+    let golden = record(&mut cpu(&program), 100);
+    assert_eq!(
+        compare_traces(&golden, &record(&mut cpu(&program), 100)),
+        Ok(())
+    );
+}
+
+#[test]
+fn test_trace_comparison_reports_the_first_difference() {
+    let program = program();
+    let golden = trace(&program);
+    let mut actual = golden.clone();
+    actual[1].writes = vec![(2, 11)];
+    actual[2].pc = 12;
+
+    let divergence = compare_traces(&golden, &actual).unwrap_err();
+    assert_eq!(divergence.step, 1);
+    assert_eq!(divergence.expected, Some(golden[1].clone()));
+    assert_eq!(divergence.actual, Some(actual[1].clone()));
+    assert_eq!(divergence.context, golden[..1].to_vec());
+    assert_eq!(
+        divergence.to_string(),
+        "diverged after 1 instructions\n\
+         \x20            0x00000000 (0x00500093) x1 0x5\n\
+         \x20  expected: 0x00000004 (0x00108133) x2 0xa\n\
+         \x20    actual: 0x00000004 (0x00108133) x2 0xb"
+    );
+}
+
+#[test]
+fn test_trace_comparison_reports_a_short_trace() {
+    let program = program();
+    let golden = trace(&program);
+
+    let divergence = compare_traces(&golden, &golden[..2]).unwrap_err();
+    assert_eq!(divergence.step, 2);
+    assert_eq!(divergence.actual, None);
+
+    let divergence = compare_traces(&golden[..2], &golden).unwrap_err();
+    assert_eq!(divergence.expected, None);
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::devices::Clint;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu};
+
+use common::{image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source).engine(engine).build().unwrap()
+}
+
+/// Nine instructions, then reads of all three counters.
+const COUNT: &str = "
+            addi  t0, zero, 4
+    loop:   addi  t0, t0, -1
+            bne   t0, zero, loop
+            csrrs a0, cycle, zero
+            csrrs a1, instret, zero
+            csrrs a2, time, zero
+            ebreak
+";
+
+// ── Counting ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_counters_count_instructions() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = cpu_with(COUNT, engine);
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x18))
+        );
+        assert_eq!(cpu.regs[10], 9, "{:?}", engine);
+        assert_eq!(cpu.regs[11], 10, "{:?}", engine);
+        assert_eq!(cpu.regs[12], 11, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_time_reads_the_clint_clock() {
+    let mut cpu = RiscvCpu::builder()
+        .image(
+            0,
+            image("csrrs a0, time, zero\nlui t0, 0x200c\nlw a1, -8(t0)"),
+        )
+        .device(Clint::BASE, Clint::SIZE, Clint::new(1))
+        .build()
+        .unwrap();
+    cpu.bus.tick(1000);
+
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.regs[10], 1000);
+    assert_eq!(cpu.regs[11], 1002, "mtime counts the same ticks");
+}
+
+#[test]
+fn test_waiting_in_wfi_takes_time_but_no_cycles() {
+    let mut cpu = cpu_with("wfi\nebreak", Engine::Interpreter);
+    for _ in 0..10 {
+        cpu.step().unwrap();
+    }
+
+    assert_eq!(cpu.csrs.read(csr::MCYCLE), 1);
+    assert_eq!(cpu.csrs.read(csr::MINSTRET), 1);
+    assert_eq!(cpu.bus.time(), 10);
+}
+
+// ── Writing and width ─────────────────────────────────────────────────────────
+
+#[test]
+fn test_machine_counters_are_writable() {
+    let mut cpu = cpu_with(
+        "
+        addi  t0, zero, 100
+        csrrw zero, minstret, t0
+        csrrs a0, instret, zero
+        ",
+        Engine::Interpreter,
+    );
+
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.regs[10], 101, "the write itself still retires");
+}
+
+#[test]
+fn test_rv32_counters_carry_into_the_upper_half() {
+    let mut cpu = cpu_with(
+        "addi zero, zero, 0\ncsrrs a0, cycle, zero\ncsrrs a1, cycleh, zero",
+        Engine::Interpreter,
+    );
+    cpu.csrs.set(csr::MCYCLE, 0xFFFF_FFFF);
+    cpu.csrs.set(csr::MCYCLEH, 0x7);
+
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.regs[10], 0);
+    assert_eq!(cpu.regs[11], 0x8);
+}
+
+#[test]
+fn test_rv64_counters_are_full_width_with_no_upper_half() {
+    let mut cpu = program("csrrs a0, mcycle, zero\ncsrrs a1, cycleh, zero")
+        .xlen::<Rv64>()
+        .build()
+        .unwrap();
+    cpu.csrs.set(csr::MCYCLE, 0x1_0000_0000);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs[10], 0x1_0000_0000);
+
+    let cycleh = assemble("csrrs a1, cycleh, zero").unwrap()[0];
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(cycleh)));
+}
+
+// ── Access control ────────────────────────────────────────────────────────────
+
+#[test]
+fn test_user_counters_are_read_only() {
+    let mut cpu = cpu_with("csrrw zero, cycle, t0", Engine::Interpreter);
+
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+}
+
+#[test]
+fn test_mcounteren_gates_user_mode_reads() {
+    let source = "csrrs a0, instret, zero\ncsrrs a1, cycle, zero";
+
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    cpu.set_privilege(Privilege::User);
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    cpu.csrs.write(csr::MCOUNTEREN, csr::COUNTEREN_IR);
+    cpu.csrs.write(csr::SCOUNTEREN, u32::MAX);
+    cpu.set_privilege(Privilege::User);
+    assert!(cpu.step().is_ok());
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+}
+
+#[test]
+fn test_counters_survive_a_snapshot() {
+    let mut cpu = cpu_with(COUNT, Engine::Interpreter);
+    cpu.csrs.set(csr::MINSTRETH, 3);
+    cpu.run();
+    let snapshot = cpu.save_snapshot();
+
+    let mut other = cpu_with(COUNT, Engine::Interpreter);
+    other.restore(&snapshot).unwrap();
+
+    assert_eq!(other.csrs.read(csr::MINSTRET), cpu.csrs.read(csr::MINSTRET));
+    assert_eq!(other.csrs.read(csr::MINSTRETH), 3);
+    assert_eq!(other.csrs.read(csr::MCYCLE), cpu.csrs.read(csr::MCYCLE));
+}
+
+#[test]
+fn test_counter_csrs_assemble_by_name() {
+    let words = assemble("csrrs a0, timeh, zero\ncsrrw zero, mcounteren, t0").unwrap();
+
+    assert_eq!(words[0] >> 20, csr::TIMEH as u32);
+    assert_eq!(words[1] >> 20, csr::MCOUNTEREN as u32);
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use riscv_emulator_rust::coverage::Coverage;
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::{Engine, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source)
+        .engine(engine)
+        .coverage(true)
+        .build()
+        .unwrap()
+}
+
+/// Counts a0 down from 3; the `skipped` instruction never runs.
+const LOOP: &str = "
+        addi  a0, zero, 3
+    top:
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        jal   zero, done
+    skipped:
+        addi  a1, zero, 1
+    done:
+        ebreak
+";
+
+// ── Recording ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_executed_addresses_are_recorded() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = cpu_with(LOOP, engine);
+        cpu.run();
+
+        let coverage = cpu.coverage().unwrap();
+        assert_eq!(coverage.addresses(), [0x0, 0x4, 0x8, 0xC], "{:?}", engine);
+        assert_eq!(coverage.hits(0x4), 3, "{:?}", engine);
+        assert_eq!(coverage.hits(0x0), 1, "{:?}", engine);
+        assert!(!coverage.is_covered(0x10), "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_trapping_instructions_dont_count() {
+    let mut cpu = cpu_with(LOOP, Engine::Interpreter);
+
+    cpu.run();
+
+    assert!(
+        !cpu.coverage().unwrap().is_covered(0x14),
+        "the ebreak stopped the hart"
+    );
+}
+
+#[test]
+fn test_off_by_default() {
+    let mut cpu = program(LOOP).build().unwrap();
+    cpu.run_steps(1);
+    assert!(cpu.coverage().is_none());
+
+    cpu.enable_coverage();
+    cpu.run_steps(2);
+    assert_eq!(
+        cpu.coverage().unwrap().len(),
+        2,
+        "counted from when it's enabled"
+    );
+
+    let coverage = cpu.take_coverage().unwrap();
+    assert_eq!(coverage.addresses(), [0x4, 0x8]);
+    assert!(cpu.coverage().is_none());
+}
+
+// ── Combining runs ────────────────────────────────────────────────────────────
+
+#[test]
+fn test_merge_adds_counts() {
+    let mut first = cpu_with(LOOP, Engine::Interpreter);
+    first.run();
+    let mut second = cpu_with("addi a0, zero, 1\nebreak", Engine::Interpreter);
+    second.run();
+
+    let mut total = Coverage::new();
+    total.merge(first.coverage().unwrap());
+    total.merge(second.coverage().unwrap());
+
+    assert_eq!(total.hits(0x0), 2);
+    assert_eq!(total.hits(0x4), 3);
+    assert_eq!(total.len(), 4);
+}
+
+#[test]
+fn test_machine_combines_its_harts() {
+    // Hart 1 skips the first instruction.
+    let source = "
+        csrrs t0, mhartid, zero
+        bne   t0, zero, other
+        addi  a0, zero, 1
+    other:
+        ebreak
+    ";
+    let mut machine = Machine::new(cpu_with(source, Engine::Interpreter), 2);
+    machine.set_quantum(1);
+
+    machine.run_steps(6);
+
+    let coverage = machine.coverage().unwrap();
+    assert_eq!(coverage.hits(0x0), 2);
+    assert_eq!(coverage.hits(0x8), 1);
+    assert!(machine.hart(1).coverage().is_some());
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::decode::{DecodeError, decode, decode_rv64};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu};
+
+use common::{image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::BasicBlocks];
+
+/// Runs `source` to its closing `ebreak` with `a0`-`a3` preset.
+fn run_with(source: &str, args: [u32; 4], engine: Engine) -> RiscvCpu {
+    let mut cpu = program(source).engine(engine).build().unwrap();
+    cpu.regs[10..14].copy_from_slice(&args);
+
+    assert!(matches!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(_))
+    ));
+    cpu
+}
+
+// ── AES ───────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_aes_round_column() {
+    // FIPS-197 appendix B, round 1: the state after the initial AddRoundKey,
+    // one column per register, and column 0 after MixColumns.
+    let state = [0xBEE3_3D19, 0x2BE2_F4A0, 0x2A8D_C69A, 0x0848_F8E9];
+
+    for engine in ENGINES {
+        let cpu = run_with(
+            "
+            aes32esmi t0, zero, a0, 0
+            aes32esmi t0, t0, a1, 1
+            aes32esmi t0, t0, a2, 2
+            aes32esmi t0, t0, a3, 3
+            aes32esi  t1, zero, a0, 0
+            aes32esi  t1, t1, a1, 1
+            ebreak
+            ",
+            state,
+            engine,
+        );
+
+        assert_eq!(cpu.regs[5], 0xE581_6604, "{:?}", engine);
+        // The final round skips MixColumns: S(0x19), S(0xF4) in bytes 0 and 1.
+        assert_eq!(cpu.regs[6], 0x0000_BFD4);
+    }
+}
+
+#[test]
+fn test_aes_decrypt_inverts_encrypt() {
+    let cpu = run_with(
+        "
+        aes32esi  t0, zero, a0, 2
+        aes32dsi  t1, zero, t0, 2
+        aes32dsmi t2, a1, a2, 0
+        aes32dsmi t3, zero, a3, 1
+        ebreak
+        ",
+        [0x00AB_0000, 0xFFFF_FFFF, 0x7C, 0x7C00],
+        Engine::Interpreter,
+    );
+
+    assert_eq!(cpu.regs[6], 0x00AB_0000);
+    // InvSubBytes(0x7C) is 1, so this is InvMixColumns' own coefficients.
+    assert_eq!(cpu.regs[7], !0x0B0D_090E);
+    assert_eq!(cpu.regs[28], 0x0D09_0E0B);
+}
+
+#[test]
+fn test_aes32_is_rv32_only() {
+    let word = assemble("aes32esmi a0, a1, a2, 3").unwrap()[0];
+
+    assert_eq!(word, 0xE6C5_8533);
+    assert_eq!(
+        decode(word).unwrap().to_string(),
+        "aes32esmi x10, x11, x12, 3"
+    );
+    assert_eq!(
+        decode_rv64(word),
+        Err(DecodeError::IllegalInstruction(word))
+    );
+    assert!(assemble("aes32dsi a0, a1, a2, 4").is_err());
+}
+
+// ── SHA-2 ─────────────────────────────────────────────────────────────────────
+
+fn sha512_sum0(x: u64) -> u64 {
+    x.rotate_right(28) ^ x.rotate_right(34) ^ x.rotate_right(39)
+}
+
+fn sha512_sig0(x: u64) -> u64 {
+    x.rotate_right(1) ^ x.rotate_right(8) ^ (x >> 7)
+}
+
+fn sha512_sig1(x: u64) -> u64 {
+    x.rotate_right(19) ^ x.rotate_right(61) ^ (x >> 6)
+}
+
+#[test]
+fn test_sha256_message_schedule() {
+    // W[17] for the one-block message "abc" is sig1(W[15] = 0x18).
+    for engine in ENGINES {
+        let cpu = run_with(
+            "
+            sha256sig1 t0, a0
+            sha256sig0 t1, a1
+            sha256sum0 t2, a1
+            sha256sum1 t3, a1
+            ebreak
+            ",
+            [0x18, 0x6A09_E667, 0, 0],
+            engine,
+        );
+
+        let x: u32 = 0x6A09_E667;
+        assert_eq!(cpu.regs[5], 0x000F_0000, "{:?}", engine);
+        assert_eq!(
+            cpu.regs[6],
+            x.rotate_right(7) ^ x.rotate_right(18) ^ (x >> 3)
+        );
+        assert_eq!(
+            cpu.regs[7],
+            x.rotate_right(2) ^ x.rotate_right(13) ^ x.rotate_right(22)
+        );
+        assert_eq!(
+            cpu.regs[28],
+            x.rotate_right(6) ^ x.rotate_right(11) ^ x.rotate_right(25)
+        );
+    }
+}
+
+#[test]
+fn test_rv32_sha512_halves() {
+    let x: u64 = 0x6A09_E667_F3BC_C908;
+    let (lo, hi) = (x as u32, (x >> 32) as u32);
+
+    let cpu = run_with(
+        "
+        sha512sum0r t0, a0, a1
+        sha512sum0r t1, a1, a0
+        sha512sig0l t2, a0, a1
+        sha512sig0h t3, a1, a0
+        sha512sig1l t4, a0, a1
+        sha512sig1h t5, a1, a0
+        ebreak
+        ",
+        [lo, hi, 0, 0],
+        Engine::Interpreter,
+    );
+
+    let halves = |a: u32, b: u32| (b as u64) << 32 | a as u64;
+    assert_eq!(halves(cpu.regs[5], cpu.regs[6]), sha512_sum0(x));
+    assert_eq!(halves(cpu.regs[7], cpu.regs[28]), sha512_sig0(x));
+    assert_eq!(halves(cpu.regs[29], cpu.regs[30]), sha512_sig1(x));
+}
+
+#[test]
+fn test_rv64_sha() {
+    let x: u64 = 0x510E_527F_ADE6_82D1;
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .image(
+            0,
+            image(
+                "
+                sha512sum0 t0, a0
+                sha512sum1 t1, a0
+                sha512sig0 t2, a0
+                sha256sig0 t3, a0
+                ebreak
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+    cpu.regs[10] = x;
+
+    cpu.run();
+
+    assert_eq!(cpu.regs[5], sha512_sum0(x));
+    assert_eq!(
+        cpu.regs[6],
+        x.rotate_right(14) ^ x.rotate_right(18) ^ x.rotate_right(41)
+    );
+    assert_eq!(cpu.regs[7], sha512_sig0(x));
+    let low = x as u32;
+    let sig0 = low.rotate_right(7) ^ low.rotate_right(18) ^ (low >> 3);
+    assert_eq!(cpu.regs[28], sig0 as i32 as u64, "sign-extended");
+}
+
+#[test]
+fn test_sha_encodings_depend_on_xlen() {
+    let sum0 = assemble("sha512sum0 a0, a1").unwrap()[0];
+    let sum0r = assemble("sha512sum0r a0, a1, a2").unwrap()[0];
+
+    assert_eq!(assemble("sha256sig0 a0, a1").unwrap()[0], 0x1025_9513);
+    assert_eq!(decode(sum0), Err(DecodeError::IllegalInstruction(sum0)));
+    assert_eq!(
+        decode_rv64(sum0).unwrap().to_string(),
+        "sha512sum0 x10, x11"
+    );
+    assert_eq!(
+        decode(sum0r).unwrap().to_string(),
+        "sha512sum0r x10, x11, x12"
+    );
+    assert_eq!(
+        decode_rv64(sum0r),
+        Err(DecodeError::IllegalInstruction(sum0r))
+    );
+}
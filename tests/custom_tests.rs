@@ -0,0 +1,274 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::custom::{CustomOpcode, Hart};
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu, csr};
+
+use common::{cpu_with, image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// An R-type instruction in `space`, as a `.word` directive.
+fn custom(space: CustomOpcode, rd: u32, rs1: u32, rs2: u32) -> String {
+    let word = (rs2 << 20) | (rs1 << 15) | (rd << 7) | space.opcode();
+    format!(".word {:#x}", word)
+}
+
+fn fields(raw: u32) -> (u8, u8, u8) {
+    let field = |shift: u32| ((raw >> shift) & 0x1F) as u8;
+    (field(7), field(15), field(20))
+}
+
+/// custom-0: `rd = popcount(rs1) + rs2`.
+fn popcount_add(hart: &mut dyn Hart, raw: u32) -> Result<bool, Exception> {
+    let (rd, rs1, rs2) = fields(raw);
+    let count = hart.reg(rs1).count_ones() as u64;
+    hart.set_reg(rd, count + hart.reg(rs2));
+    Ok(true)
+}
+
+// ── Handlers ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_handler_computes_into_registers() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let source = format!(
+            "
+            addi a0, zero, 0x7F
+            addi a1, zero, 100
+            {}
+            ebreak
+            ",
+            custom(CustomOpcode::Custom0, 12, 10, 11)
+        );
+        let mut cpu = program(&source)
+            .custom(CustomOpcode::Custom0, popcount_add)
+            .engine(engine)
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(0xC)));
+        assert_eq!(cpu.regs[12], 107, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_handler_uses_memory() {
+    // custom-1: copy the word at rs1 to rs2.
+    let copy = |hart: &mut dyn Hart, raw: u32| {
+        let (_, rs1, rs2) = fields(raw);
+        let word = hart.load(hart.reg(rs1), MemSize::Word)?;
+        hart.store(hart.reg(rs2), MemSize::Word, word)?;
+        Ok(true)
+    };
+    let source = format!(
+        "
+        lui a0, 1
+        lui a1, 2
+        {0}
+        lui a0, 0x10000
+        {0}
+        ",
+        custom(CustomOpcode::Custom1, 0, 10, 11)
+    );
+    let mut cpu = program(&source)
+        .custom(CustomOpcode::Custom1, copy)
+        .build()
+        .unwrap();
+    cpu.bus.write(0x1000, MemSize::Word, 0xDEAD_BEEF).unwrap();
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::LoadAccessFault(0x1000_0000))
+    );
+    assert_eq!(cpu.bus.read(0x2000, MemSize::Word), Some(0xDEAD_BEEF));
+    assert_eq!(cpu.pc, 0x10, "the faulting instruction");
+}
+
+#[test]
+fn test_handler_can_jump() {
+    // custom-2: jump to rs1, linking rd.
+    let jump = |hart: &mut dyn Hart, raw: u32| {
+        let (rd, rs1, _) = fields(raw);
+        let (target, link) = (hart.reg(rs1), hart.pc() + 4);
+        hart.jump(target);
+        hart.set_reg(rd, link);
+        Ok(true)
+    };
+
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let source = format!(
+            "
+            addi a0, zero, 0x10
+            {}
+            addi a1, zero, 1
+            ebreak
+            addi a1, zero, 2
+            ebreak
+            ",
+            custom(CustomOpcode::Custom2, 1, 10, 0)
+        );
+        let mut cpu = program(&source)
+            .custom(CustomOpcode::Custom2, jump)
+            .engine(engine)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x14))
+        );
+        assert_eq!(cpu.regs[11], 2, "{:?}", engine);
+        assert_eq!(cpu.regs[1], 8);
+    }
+}
+
+#[test]
+fn test_handler_sees_the_xlen() {
+    let source = format!(
+        "addi a0, zero, -1\n{}",
+        custom(CustomOpcode::Custom3, 11, 10, 0)
+    );
+    let mut cpu = program(&source)
+        .xlen::<Rv64>()
+        .custom(CustomOpcode::Custom3, |hart: &mut dyn Hart, raw: u32| {
+            let (rd, rs1, _) = fields(raw);
+            hart.set_reg(rd, hart.reg(rs1).count_ones() as u64 + hart.xlen() as u64);
+            Ok(true)
+        })
+        .build()
+        .unwrap();
+
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.regs[11], 128);
+}
+
+// ── Illegal instructions ──────────────────────────────────────────────────────
+
+#[test]
+fn test_unhandled_custom_instructions_are_illegal() {
+    let word = assemble(&custom(CustomOpcode::Custom0, 1, 2, 3)).unwrap()[0];
+    let mut cpu = RiscvCpu::builder()
+        .image(0, word.to_le_bytes())
+        .build()
+        .unwrap();
+
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(word)));
+
+    // A handler that doesn't know the encoding says so.
+    cpu.set_custom(CustomOpcode::Custom0, |_: &mut dyn Hart, _: u32| Ok(false));
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(word)));
+
+    cpu.set_custom(CustomOpcode::Custom0, popcount_add);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 4);
+
+    cpu.pc = 0;
+    cpu.clear_custom(CustomOpcode::Custom0);
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(word)));
+}
+
+#[test]
+fn test_guest_sees_an_illegal_instruction_trap() {
+    let mut cpu = program(&custom(CustomOpcode::Custom1, 0, 0, 0))
+        .image(0x100, image("ebreak"))
+        .guest_traps(true)
+        .build()
+        .unwrap();
+    cpu.csrs.write(csr::MTVEC, 0x100);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 2);
+}
+
+#[test]
+fn test_custom_disassembly() {
+    let word = assemble(&custom(CustomOpcode::Custom3, 1, 2, 3)).unwrap()[0];
+
+    assert_eq!(decode(word).unwrap().to_string(), "custom-3 0x003100fb");
+}
+
+// ── Unknown opcodes ───────────────────────────────────────────────────────────
+
+/// An R-type instruction in the reserved major opcode 0x77, as a `.word`
+/// directive.
+fn unknown(rd: u32, rs1: u32, rs2: u32) -> String {
+    format!(".word {:#x}", (rs2 << 20) | (rs1 << 15) | (rd << 7) | 0x77)
+}
+
+#[test]
+fn test_unknown_opcode_handler_emulates() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let source = format!(
+            "
+            addi a0, zero, 6
+            addi a1, zero, 7
+            {}
+            ebreak
+            ",
+            unknown(12, 10, 11)
+        );
+        // Pretend 0x77 is a multiply.
+        let mut cpu = program(&source)
+            .unknown_opcode(|hart: &mut dyn Hart, raw: u32| {
+                let (rd, rs1, rs2) = fields(raw);
+                hart.set_reg(rd, hart.reg(rs1) * hart.reg(rs2));
+                Ok(true)
+            })
+            .engine(engine)
+            .build()
+            .unwrap();
+
+        assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(0xC)));
+        assert_eq!(cpu.regs[12], 42, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_unknown_opcode_handler_can_refuse() {
+    let word = assemble(&unknown(1, 2, 3)).unwrap()[0];
+    let mut cpu = cpu_with(&unknown(1, 2, 3));
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 4, "skipped without a handler");
+
+    cpu.pc = 0;
+    cpu.set_unknown_opcode_handler(|_: &mut dyn Hart, _: u32| Ok(false));
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(word)));
+
+    cpu.clear_unknown_opcode_handler();
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 4);
+}
+
+#[test]
+fn test_unknown_opcode_handler_only_sees_unknown_opcodes() {
+    let seen = std::rc::Rc::new(std::cell::Cell::new(0));
+    let count = seen.clone();
+    let source = format!(
+        "addi a0, zero, 1\n{}\n{}",
+        custom(CustomOpcode::Custom0, 0, 0, 0),
+        unknown(0, 0, 0)
+    );
+    let mut cpu = program(&source)
+        .unknown_opcode(move |_: &mut dyn Hart, _: u32| {
+            count.set(count.get() + 1);
+            Ok(true)
+        })
+        .build()
+        .unwrap();
+
+    cpu.step().unwrap();
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+    assert_eq!(seen.get(), 0, "custom-0 is known, just unhandled");
+
+    cpu.pc = 8;
+    cpu.step().unwrap();
+    assert_eq!(seen.get(), 1);
+}
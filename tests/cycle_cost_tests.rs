@@ -0,0 +1,149 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::costs::{CycleCosts, InstructionClass};
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::{Engine, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, costs: Option<CycleCosts>, engine: Engine) -> RiscvCpu {
+    let mut builder = program(source).ram_size(0x4000).engine(engine);
+    if let Some(costs) = costs {
+        builder = builder.cycle_costs(costs);
+    }
+    builder.build().unwrap()
+}
+
+/// `mcycle` and `minstret` after running `source` to its `ebreak`.
+fn run(source: &str, costs: Option<CycleCosts>, engine: Engine) -> (u32, u32) {
+    let mut cpu = cpu_with(source, costs, engine);
+    cpu.run();
+    (cpu.csrs.read(csr::MCYCLE), cpu.csrs.read(csr::MINSTRET))
+}
+
+/// Two loads, a float divide and four other instructions, then `ebreak`.
+const PROGRAM: &str = "
+        lui     s0, 1
+        lw      t0, 0(s0)
+        lw      t1, 4(s0)
+        fcvt.s.w f1, t0
+        fdiv.s  f2, f1, f1
+        fadd.s  f3, f1, f1
+        addi    a0, zero, 1
+        ebreak
+";
+
+// ── Costs ─────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_without_costs_every_instruction_takes_a_cycle() {
+    let (cycles, instret) = run(PROGRAM, None, Engine::Interpreter);
+    assert_eq!(cycles, instret);
+
+    assert_eq!(
+        run(PROGRAM, Some(CycleCosts::new()), Engine::Interpreter),
+        (cycles, instret),
+        "an empty table changes nothing"
+    );
+}
+
+#[test]
+fn test_classes_charge_their_cost() {
+    let (base, instret) = run(PROGRAM, None, Engine::Interpreter);
+    let costs = CycleCosts::new()
+        .class(InstructionClass::Load, 3)
+        .class(InstructionClass::FloatDivide, 20);
+
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let (cycles, retired) = run(PROGRAM, Some(costs.clone()), engine);
+        assert_eq!(cycles, base + 2 * 2 + 19, "{:?}", engine);
+        assert_eq!(retired, instret, "instructions still count once");
+    }
+}
+
+#[test]
+fn test_a_mnemonic_overrides_its_class() {
+    let (base, _) = run(PROGRAM, None, Engine::Interpreter);
+    let costs = CycleCosts::new()
+        .class(InstructionClass::Float, 4)
+        .mnemonic("FADD.S", 2);
+
+    let (cycles, _) = run(PROGRAM, Some(costs), Engine::Interpreter);
+    assert_eq!(cycles, base + 3 + 1, "fcvt.s.w takes 4, fadd.s 2");
+}
+
+#[test]
+fn test_the_guest_reads_the_charged_cycles() {
+    let costs = CycleCosts::new().class(InstructionClass::Load, 10);
+    let mut cpu = cpu_with(
+        "lui s0, 1\nlw t0, 0(s0)\ncsrrs a0, cycle, zero\nebreak",
+        Some(costs),
+        Engine::Interpreter,
+    );
+    cpu.run();
+    assert_eq!(cpu.regs[10], 1 + 10);
+}
+
+#[test]
+fn test_taking_the_costs_goes_back_to_one_a_cycle() {
+    let mut cpu = cpu_with(
+        PROGRAM,
+        Some(CycleCosts::new().class(InstructionClass::Alu, 5)),
+        Engine::Interpreter,
+    );
+    assert!(cpu.take_cycle_costs().is_some());
+    cpu.run();
+    assert_eq!(cpu.csrs.read(csr::MCYCLE), cpu.csrs.read(csr::MINSTRET));
+}
+
+#[test]
+fn test_machine_harts_share_the_table() {
+    let costs = CycleCosts::new().class(InstructionClass::Load, 3);
+    let machine = Machine::new(
+        cpu_with(PROGRAM, Some(costs.clone()), Engine::Interpreter),
+        2,
+    );
+    assert_eq!(machine.hart(1).cycle_costs(), Some(&costs));
+}
+
+// ── Tables ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_parse_reads_classes_and_mnemonics() {
+    let costs = CycleCosts::parse("load=2, Store = 3\nfdiv=20,fsqrt.d=30\n").unwrap();
+    let cost = |word: u32| costs.cost(&decode(word).unwrap());
+
+    assert_eq!(cost(0x0002_a283), 2, "lw t0, 0(t0)");
+    assert_eq!(cost(0x0052_a023), 3, "sw t0, 0(t0)");
+    assert_eq!(cost(0x1810_70d3), 20, "fdiv.s f1, f0, f1");
+    assert_eq!(cost(0x5a00_70d3), 30, "fsqrt.d f1, f0");
+    assert_eq!(cost(0x5800_70d3), 20, "fsqrt.s is still fdiv");
+    assert_eq!(cost(0x0010_0093), 1, "addi x1, x0, 1");
+}
+
+#[test]
+fn test_parse_rejects_malformed_entries() {
+    assert!(CycleCosts::parse("load").unwrap_err().contains("Costs"));
+    assert!(CycleCosts::parse("load=two").unwrap_err().contains("two"));
+    assert_eq!(CycleCosts::parse("").unwrap(), CycleCosts::new());
+}
+
+#[test]
+fn test_instructions_fall_into_classes() {
+    let class = |source: &str| InstructionClass::of(&decode(assemble(source).unwrap()[0]).unwrap());
+
+    assert_eq!(class("add a0, a1, a2"), InstructionClass::Alu);
+    assert_eq!(class("beq a0, a1, 8"), InstructionClass::Branch);
+    assert_eq!(class("jalr ra, 0(a0)"), InstructionClass::Jump);
+    assert_eq!(class("flw f1, 0(a0)"), InstructionClass::Load);
+    assert_eq!(class("sw a0, 0(a1)"), InstructionClass::Store);
+    assert_eq!(class("lr.w a0, (a1)"), InstructionClass::Atomic);
+    assert_eq!(class("csrrs a0, cycle, zero"), InstructionClass::Csr);
+    assert_eq!(class("ecall"), InstructionClass::System);
+    assert_eq!(class("fmul.d f1, f2, f3"), InstructionClass::Float);
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use riscv_emulator_rust::debug::{RegisterWrite, WatchHit, WatchKind};
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu, StepOutcome};
+
+use common::cpu_with;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Step until something other than a plain instruction happens.
+fn step_until_stop(cpu: &mut RiscvCpu) -> StepOutcome {
+    for _ in 0..1000 {
+        match cpu.step().unwrap() {
+            StepOutcome::Executed => {}
+            other => return other,
+        }
+    }
+    panic!("cpu never stopped");
+}
+
+/// True if the program reaches EBREAK without any debug stop.
+fn runs_to_ebreak(cpu: &mut RiscvCpu) -> bool {
+    for _ in 0..1000 {
+        match cpu.step() {
+            Ok(StepOutcome::Executed) => {}
+            Ok(_) => return false,
+            Err(_) => return true,
+        }
+    }
+    false
+}
+
+const COUNTER: &str = "
+            addi t0, zero, 3
+    loop:   addi t0, t0, -1
+            sw   t0, 0x100(zero)
+            bne  t0, zero, loop
+            lw   t1, 0x100(zero)
+            ebreak
+";
+
+// ── Breakpoints ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_breakpoint_stops_before_executing() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_breakpoint(0x4);
+
+    assert_eq!(step_until_stop(&mut cpu), StepOutcome::Breakpoint(0x4));
+    assert_eq!(cpu.pc, 0x4);
+    assert_eq!(cpu.regs[5], 3, "instruction at the breakpoint hasn't run");
+}
+
+#[test]
+fn test_resuming_executes_the_instruction_and_hits_again() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_breakpoint(0x4);
+
+    step_until_stop(&mut cpu);
+    assert_eq!(cpu.step().unwrap(), StepOutcome::Executed);
+    assert_eq!(cpu.regs[5], 2);
+
+    assert_eq!(step_until_stop(&mut cpu), StepOutcome::Breakpoint(0x4));
+    assert_eq!(cpu.regs[5], 2, "stopped on the second loop iteration");
+}
+
+#[test]
+fn test_remove_breakpoint() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_breakpoint(0x4);
+    cpu.add_breakpoint(0x10);
+
+    assert!(cpu.remove_breakpoint(0x4));
+    assert!(!cpu.remove_breakpoint(0x4));
+    assert_eq!(cpu.breakpoints().collect::<Vec<_>>(), vec![0x10]);
+
+    assert_eq!(step_until_stop(&mut cpu), StepOutcome::Breakpoint(0x10));
+    assert_eq!(cpu.regs[5], 0);
+}
+
+// ── Watchpoints ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_write_watchpoint_reports_after_the_store() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_watchpoint(0x100, 4, WatchKind::Write);
+
+    assert_eq!(
+        step_until_stop(&mut cpu),
+        StepOutcome::Watchpoint(WatchHit {
+            pc: 0x8,
+            addr: 0x100,
+            kind: WatchKind::Write
+        })
+    );
+    assert_eq!(cpu.pc, 0xC, "the store has completed");
+    assert_eq!(cpu.bus[0x100], 2);
+}
+
+#[test]
+fn test_read_watchpoint_ignores_writes() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_watchpoint(0x100, 4, WatchKind::Read);
+
+    assert_eq!(
+        step_until_stop(&mut cpu),
+        StepOutcome::Watchpoint(WatchHit {
+            pc: 0x10,
+            addr: 0x100,
+            kind: WatchKind::Read
+        })
+    );
+}
+
+#[test]
+fn test_watchpoint_matches_partial_overlap() {
+    let mut cpu = cpu_with(COUNTER);
+    // Only the top byte of the stored word.
+    cpu.add_watchpoint(0x103, 1, WatchKind::ReadWrite);
+
+    assert!(matches!(
+        step_until_stop(&mut cpu),
+        StepOutcome::Watchpoint(WatchHit { addr: 0x100, .. })
+    ));
+}
+
+#[test]
+fn test_watchpoint_outside_access_never_fires() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_watchpoint(0x104, 4, WatchKind::ReadWrite);
+
+    assert!(runs_to_ebreak(&mut cpu));
+}
+
+#[test]
+fn test_remove_watchpoint() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_watchpoint(0x100, 4, WatchKind::Write);
+
+    assert!(cpu.remove_watchpoint(0x100, 4, WatchKind::Write));
+    assert!(cpu.watchpoints().is_empty());
+    assert!(runs_to_ebreak(&mut cpu));
+}
+
+// ── Register watches ──────────────────────────────────────────────────────────
+
+#[test]
+fn test_register_watch_reports_old_and_new_values() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_register_watch(5);
+
+    assert_eq!(
+        step_until_stop(&mut cpu),
+        StepOutcome::RegisterWrite(RegisterWrite {
+            pc: 0x0,
+            reg: 5,
+            old: 0,
+            new: 3
+        })
+    );
+    assert_eq!(
+        step_until_stop(&mut cpu),
+        StepOutcome::RegisterWrite(RegisterWrite {
+            pc: 0x4,
+            reg: 5,
+            old: 3,
+            new: 2
+        })
+    );
+    assert_eq!(cpu.pc, 0x8, "the write has completed");
+}
+
+#[test]
+fn test_register_watch_ignores_other_registers_and_reads() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_register_watch(6);
+
+    assert_eq!(
+        step_until_stop(&mut cpu),
+        StepOutcome::RegisterWrite(RegisterWrite {
+            pc: 0x10,
+            reg: 6,
+            old: 0,
+            new: 0
+        }),
+        "writing the value it held still counts"
+    );
+}
+
+#[test]
+fn test_register_watch_stops_every_engine() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = cpu_with("addi sp, zero, 64\naddi t0, zero, 1\naddi sp, sp, -16\nebreak");
+        cpu.set_engine(engine);
+        cpu.add_register_watch(2);
+
+        cpu.run();
+        assert_eq!(
+            cpu.run(),
+            ExitReason::RegisterWrite(RegisterWrite {
+                pc: 0x8,
+                reg: 2,
+                old: 64,
+                new: 48
+            }),
+            "{:?}",
+            engine
+        );
+        assert_eq!(cpu.pc, 0xC);
+    }
+}
+
+#[test]
+fn test_remove_register_watch() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.add_register_watch(0);
+    cpu.add_register_watch(5);
+    assert_eq!(cpu.register_watches().collect::<Vec<_>>(), [0, 5]);
+
+    assert!(cpu.remove_register_watch(5));
+    assert!(!cpu.remove_register_watch(5));
+    assert!(runs_to_ebreak(&mut cpu), "x0 is never written");
+}
@@ -0,0 +1,199 @@
+use riscv_emulator_rust::decode::{DecodeError, Instruction, decode};
+
+// ── Per-format decoding ───────────────────────────────────────────────────────
+
+#[test]
+fn test_decode_rtype() {
+    // add x3, x1, x2
+    assert_eq!(
+        decode(0x002081b3),
+        Ok(Instruction::Add {
+            rd: 3,
+            rs1: 1,
+            rs2: 2
+        })
+    );
+    // sub x5, x6, x7
+    assert_eq!(
+        decode(0x407302b3),
+        Ok(Instruction::Sub {
+            rd: 5,
+            rs1: 6,
+            rs2: 7
+        })
+    );
+}
+
+#[test]
+fn test_decode_itype_sign_extends() {
+    // addi x1, x0, -1
+    assert_eq!(
+        decode(0xfff00093),
+        Ok(Instruction::Addi {
+            rd: 1,
+            rs1: 0,
+            imm: -1
+        })
+    );
+}
+
+#[test]
+fn test_decode_shifts() {
+    // srai x1, x2, 3
+    assert_eq!(
+        decode(0x40315093),
+        Ok(Instruction::Srai {
+            rd: 1,
+            rs1: 2,
+            shamt: 3
+        })
+    );
+    // slli with a non-zero funct7 is reserved
+    assert_eq!(
+        decode(0x40311093),
+        Err(DecodeError::IllegalInstruction(0x40311093))
+    );
+}
+
+#[test]
+fn test_decode_load_and_store() {
+    // lw x5, -4(x2)
+    assert_eq!(
+        decode(0xffc12283),
+        Ok(Instruction::Lw {
+            rd: 5,
+            rs1: 2,
+            imm: -4
+        })
+    );
+    // sw x5, 8(x2)
+    assert_eq!(
+        decode(0x00512423),
+        Ok(Instruction::Sw {
+            rs1: 2,
+            rs2: 5,
+            imm: 8
+        })
+    );
+}
+
+#[test]
+fn test_decode_branch_and_jumps() {
+    // bne x1, x0, -8
+    assert_eq!(
+        decode(0xfe009ce3),
+        Ok(Instruction::Bne {
+            rs1: 1,
+            rs2: 0,
+            imm: -8
+        })
+    );
+    // jal x1, 2048
+    assert_eq!(
+        decode(0x001000ef),
+        Ok(Instruction::Jal { rd: 1, imm: 2048 })
+    );
+    // jalr x0, 0(x1)
+    assert_eq!(
+        decode(0x00008067),
+        Ok(Instruction::Jalr {
+            rd: 0,
+            rs1: 1,
+            imm: 0
+        })
+    );
+}
+
+#[test]
+fn test_decode_upper_immediates() {
+    // lui x5, 0x12345
+    assert_eq!(
+        decode(0x123452b7),
+        Ok(Instruction::Lui {
+            rd: 5,
+            imm: 0x1234_5000
+        })
+    );
+    // auipc x1, 0xfffff
+    assert_eq!(
+        decode(0xfffff097),
+        Ok(Instruction::Auipc {
+            rd: 1,
+            imm: 0xFFFF_F000
+        })
+    );
+}
+
+#[test]
+fn test_decode_system() {
+    assert_eq!(decode(0x00000073), Ok(Instruction::Ecall));
+    assert_eq!(decode(0x00100073), Ok(Instruction::Ebreak));
+    assert_eq!(decode(0x30200073), Ok(Instruction::Mret));
+    assert_eq!(decode(0x10500073), Ok(Instruction::Wfi));
+    // csrrw x1, mscratch, x2
+    assert_eq!(
+        decode(0x340110f3),
+        Ok(Instruction::Csrrw {
+            rd: 1,
+            rs1: 2,
+            csr: 0x340
+        })
+    );
+    // csrrsi x0, mstatus, 8
+    assert_eq!(
+        decode(0x30046073),
+        Ok(Instruction::Csrrsi {
+            rd: 0,
+            uimm: 8,
+            csr: 0x300
+        })
+    );
+}
+
+// ── Errors ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_unknown_opcode() {
+    // opcode 0x77 is reserved
+    assert_eq!(
+        decode(0x00002077),
+        Err(DecodeError::UnknownOpcode(0x00002077))
+    );
+}
+
+#[test]
+fn test_illegal_funct_combinations() {
+    for bits in [
+        0xfe0000b3, // R-type with funct7 = 0x7F
+        0x00003083, // LD on RV32
+        0x00003023, // SD on RV32
+        0x00002063, // branch funct3 = 0b010
+        0x00001067, // JALR funct3 != 0
+    ] {
+        assert_eq!(
+            decode(bits),
+            Err(DecodeError::IllegalInstruction(bits)),
+            "{:#010x}",
+            bits
+        );
+    }
+}
+
+// ── Disassembly ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_display_disassembles() {
+    let cases = [
+        (0x002081b3, "add x3, x1, x2"),
+        (0xfff00093, "addi x1, x0, -1"),
+        (0xffc12283, "lw x5, -4(x2)"),
+        (0x00512423, "sw x5, 8(x2)"),
+        (0xfe009ce3, "bne x1, x0, -8"),
+        (0x123452b7, "lui x5, 0x12345"),
+        (0x00100073, "ebreak"),
+    ];
+
+    for (bits, text) in cases {
+        assert_eq!(decode(bits).unwrap().to_string(), text);
+    }
+}
@@ -0,0 +1,186 @@
+mod common;
+
+use riscv_emulator_rust::RiscvCpu;
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::isa::{Extension, Extensions};
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::xlen::Rv64;
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const MTVEC: u32 = 0x400;
+const STVEC: u32 = 0x800;
+
+/// A hart with guest traps on, both trap vectors set, and `source` running
+/// at address 0 in `privilege`.
+fn cpu_with(source: &str, privilege: Privilege) -> RiscvCpu {
+    let mut cpu = program(source).guest_traps(true).build().unwrap();
+    cpu.csrs.write(csr::MTVEC, MTVEC);
+    cpu.csrs.write(csr::STVEC, STVEC);
+    cpu.set_privilege(privilege);
+    cpu
+}
+
+fn ecall_bit(exception: Exception) -> u32 {
+    1 << exception.cause()
+}
+
+// ── Exceptions ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_delegated_exception_traps_to_supervisor() {
+    let mut cpu = cpu_with("addi zero, zero, 0\necall", Privilege::User);
+    cpu.csrs
+        .write(csr::MEDELEG, ecall_bit(Exception::UserEnvironmentCall));
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_SIE);
+
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+    assert_eq!(cpu.pc, STVEC);
+    assert_eq!(cpu.csrs.read(csr::SEPC), 4);
+    assert_eq!(cpu.csrs.read(csr::SCAUSE), 8);
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0, "M-mode CSRs untouched");
+
+    let mstatus = cpu.csrs.read(csr::MSTATUS);
+    assert_eq!(mstatus & csr::MSTATUS_SIE, 0);
+    assert_ne!(mstatus & csr::MSTATUS_SPIE, 0, "SPIE <- SIE");
+    assert_eq!(mstatus & csr::MSTATUS_SPP, 0, "came from U");
+}
+
+#[test]
+fn test_delegated_fault_sets_stval() {
+    let mut cpu = cpu_with("lw a0, 0(a1)", Privilege::Supervisor);
+    cpu.csrs.write(csr::MEDELEG, 1 << 5);
+    cpu.regs[11] = 0xF000_0000;
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.pc, STVEC);
+    assert_eq!(cpu.csrs.read(csr::SCAUSE), 5);
+    assert_eq!(cpu.csrs.read(csr::STVAL), 0xF000_0000);
+    assert_ne!(cpu.csrs.read(csr::MSTATUS) & csr::MSTATUS_SPP, 0);
+}
+
+#[test]
+fn test_machine_mode_traps_are_never_delegated() {
+    let mut cpu = cpu_with("ecall", Privilege::Machine);
+    cpu.csrs.write(csr::MEDELEG, u32::MAX);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+    assert_eq!(cpu.pc, MTVEC);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 11);
+    assert_eq!(
+        cpu.csrs.read(csr::MEDELEG) & ecall_bit(Exception::EnvironmentCall),
+        0,
+        "M-mode ECALL can't be delegated"
+    );
+}
+
+#[test]
+fn test_undelegated_exception_goes_to_machine() {
+    let mut cpu = cpu_with("ecall", Privilege::Supervisor);
+    cpu.csrs
+        .write(csr::MEDELEG, ecall_bit(Exception::UserEnvironmentCall));
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+    assert_eq!(cpu.pc, MTVEC);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 9);
+    assert_eq!(
+        cpu.csrs.read(csr::MSTATUS) & csr::MSTATUS_MPP,
+        (Privilege::Supervisor as u32) << 11
+    );
+}
+
+// ── Interrupts ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_delegated_interrupt_respects_sie() {
+    let mut cpu = cpu_with(
+        "addi zero, zero, 0\naddi zero, zero, 0",
+        Privilege::Supervisor,
+    );
+    cpu.csrs.write(csr::MIDELEG, csr::MIP_STIP);
+    cpu.csrs.write(csr::MIE, csr::MIP_STIP);
+    cpu.raise_interrupt(Interrupt::SupervisorTimer);
+
+    assert_eq!(cpu.pending_interrupt(), None, "SIE is clear in S-mode");
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 4);
+
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_SIE);
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.pc, STVEC);
+    assert_eq!(cpu.csrs.read(csr::SCAUSE), 0x8000_0005);
+    assert_eq!(cpu.csrs.read(csr::SEPC), 4);
+}
+
+#[test]
+fn test_delegated_interrupts_wait_in_machine_mode() {
+    let mut cpu = cpu_with("addi zero, zero, 0", Privilege::Machine);
+    cpu.csrs.write(csr::MIDELEG, csr::MIP_SSIP);
+    cpu.csrs.write(csr::MIE, csr::MIP_SSIP);
+    cpu.csrs
+        .write(csr::MSTATUS, csr::MSTATUS_MIE | csr::MSTATUS_SIE);
+    cpu.raise_interrupt(Interrupt::SupervisorSoftware);
+    assert_eq!(cpu.pending_interrupt(), None);
+
+    let mut cpu = cpu_with("addi zero, zero, 0", Privilege::User);
+    cpu.csrs.write(csr::MIDELEG, csr::MIP_SSIP);
+    cpu.csrs.write(csr::MIE, csr::MIP_SSIP);
+    cpu.raise_interrupt(Interrupt::SupervisorSoftware);
+    assert_eq!(
+        cpu.pending_interrupt(),
+        Some(Interrupt::SupervisorSoftware),
+        "U-mode can't mask S interrupts"
+    );
+}
+
+#[test]
+fn test_vectored_stvec() {
+    let mut cpu = cpu_with("addi zero, zero, 0", Privilege::User);
+    cpu.csrs.write(csr::STVEC, STVEC | 1);
+    cpu.csrs.write(csr::MIDELEG, csr::MIP_SEIP);
+    cpu.csrs.write(csr::MIE, csr::MIP_SEIP);
+    cpu.csrs.write(csr::MIP, csr::MIP_SEIP);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.pc, STVEC + 4 * 9);
+}
+
+#[test]
+fn test_delegation_csrs() {
+    let mut cpu = cpu_with("", Privilege::Machine);
+    cpu.csrs.write(csr::MIDELEG, u32::MAX);
+    assert_eq!(
+        cpu.csrs.read(csr::MIDELEG),
+        csr::MIP_SSIP | csr::MIP_STIP | csr::MIP_SEIP | 0x1444,
+        "the VS interrupts and SGEIP are always delegated"
+    );
+
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .image(
+            0,
+            assemble("csrrs a0, medeleg, zero").unwrap()[0]
+                .to_le_bytes()
+                .to_vec(),
+        )
+        .extensions(
+            Extensions::all()
+                .without(Extension::H)
+                .without(Extension::S),
+        )
+        .build()
+        .unwrap();
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::DmaController;
+use riscv_emulator_rust::devices::dma::{
+    CONTROL_ABORT, CONTROL_IE, CONTROL_START, STATUS_BUSY, STATUS_DONE, STATUS_ERROR,
+};
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const BASE: u32 = DmaController::BASE;
+
+const DESC_LO: u32 = 0x00;
+const CONTROL: u32 = 0x08;
+const STATUS: u32 = 0x0C;
+const CURRENT_LO: u32 = 0x10;
+const COPIED: u32 = 0x18;
+
+fn cpu_with(dma: DmaController) -> RiscvCpu {
+    RiscvCpu::builder()
+        .device(BASE, DmaController::SIZE, dma)
+        .build()
+        .unwrap()
+}
+
+fn reg(cpu: &mut RiscvCpu, offset: u32) -> u32 {
+    cpu.bus.read(BASE + offset, MemSize::Word).unwrap()
+}
+
+fn set_reg(cpu: &mut RiscvCpu, offset: u32, value: u32) {
+    cpu.bus.write(BASE + offset, MemSize::Word, value).unwrap();
+}
+
+fn write_desc(cpu: &mut RiscvCpu, at: u32, src: u32, dst: u32, len: u32, next: u32) {
+    let mut memory = cpu.bus.dma();
+    for (offset, value) in [(0, src), (8, dst), (16, len), (24, next)] {
+        memory.write(at as u64 + offset, &(value as u64).to_le_bytes());
+    }
+}
+
+fn fill(cpu: &mut RiscvCpu, at: usize, len: usize) {
+    for i in 0..len {
+        cpu.bus[at + i] = i as u8;
+    }
+}
+
+fn start(cpu: &mut RiscvCpu, desc: u32, control: u32) {
+    set_reg(cpu, DESC_LO, desc);
+    set_reg(cpu, CONTROL, CONTROL_START | control);
+}
+
+// ── Copies ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_copy_runs_in_the_background() {
+    let mut cpu = cpu_with(DmaController::new());
+    fill(&mut cpu, 0x2000, 1024);
+    write_desc(&mut cpu, 0x1000, 0x2000, 0x8000, 1024, 0);
+
+    start(&mut cpu, 0x1000, 0);
+    assert_eq!(reg(&mut cpu, STATUS), STATUS_BUSY);
+    assert_eq!(cpu.bus[0x8000], 0, "nothing until time passes");
+
+    // 64 bytes a tick, the first 32 of them on the descriptor.
+    cpu.bus.tick(8);
+    assert_eq!(reg(&mut cpu, COPIED), 8 * 64 - 32);
+    assert_eq!(reg(&mut cpu, STATUS), STATUS_BUSY);
+
+    cpu.bus.tick(9);
+    assert_eq!(reg(&mut cpu, STATUS), STATUS_DONE);
+    assert_eq!(reg(&mut cpu, COPIED), 1024);
+    assert_eq!(cpu.bus[0x8000..0x8400], cpu.bus[0x2000..0x2400]);
+}
+
+#[test]
+fn test_descriptor_chain() {
+    let mut cpu = cpu_with(DmaController::new().with_rate(4096));
+    fill(&mut cpu, 0x2000, 16);
+    write_desc(&mut cpu, 0x1000, 0x2000, 0x3000, 8, 0x1020);
+    write_desc(&mut cpu, 0x1020, 0x2000, 0x3100, 0, 0x1040);
+    write_desc(&mut cpu, 0x1040, 0x2008, 0x3200, 8, 0);
+
+    start(&mut cpu, 0x1000, 0);
+    cpu.bus.tick(1);
+
+    assert_eq!(reg(&mut cpu, STATUS), STATUS_DONE);
+    assert_eq!(reg(&mut cpu, COPIED), 16);
+    assert_eq!(cpu.bus[0x3000..0x3008], [0, 1, 2, 3, 4, 5, 6, 7]);
+    assert_eq!(cpu.bus[0x3200..0x3208], [8, 9, 10, 11, 12, 13, 14, 15]);
+}
+
+#[test]
+fn test_copy_outside_ram_is_an_error() {
+    let mut cpu = cpu_with(DmaController::new().with_rate(4096));
+    write_desc(&mut cpu, 0x1000, 0x2000, 0x3000, 8, 0x1020);
+    write_desc(&mut cpu, 0x1020, 0x2000, BASE, 8, 0);
+
+    start(&mut cpu, 0x1000, 0);
+    cpu.bus.tick(1);
+
+    assert_eq!(reg(&mut cpu, STATUS), STATUS_ERROR);
+    assert_eq!(reg(&mut cpu, CURRENT_LO), 0x1020);
+
+    set_reg(&mut cpu, STATUS, STATUS_ERROR);
+    assert_eq!(reg(&mut cpu, STATUS), 0, "write one to clear");
+}
+
+#[test]
+fn test_abort() {
+    let mut cpu = cpu_with(DmaController::new());
+    write_desc(&mut cpu, 0x1000, 0x2000, 0x8000, 4096, 0);
+
+    start(&mut cpu, 0x1000, 0);
+    cpu.bus.tick(2);
+    set_reg(&mut cpu, CONTROL, CONTROL_ABORT);
+    cpu.bus.tick(100);
+
+    assert_eq!(reg(&mut cpu, STATUS), 0);
+    assert_eq!(reg(&mut cpu, COPIED), 96);
+}
+
+// ── Interrupts ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_completion_interrupt() {
+    let mut cpu = cpu_with(DmaController::new().with_interrupt(0, csr::MIP_SEIP));
+    write_desc(&mut cpu, 0x1000, 0x2000, 0x3000, 64, 0);
+
+    start(&mut cpu, 0x1000, CONTROL_IE);
+    cpu.bus.tick(1);
+    assert_eq!(cpu.bus.interrupts(0), 0);
+    cpu.bus.tick(1);
+    assert_eq!(cpu.bus.interrupts(0), csr::MIP_SEIP);
+
+    set_reg(&mut cpu, STATUS, STATUS_DONE);
+    assert_eq!(cpu.bus.interrupts(0), 0);
+}
+
+#[test]
+fn test_guest_sleeps_until_the_copy_is_done() {
+    let source = "
+        lui   t0, 0x10009
+        addi  t1, zero, 0x100
+        sw    t1, 0(t0)
+        lui   t1, 1
+        srli  t1, t1, 1
+        csrrs zero, mie, t1
+        addi  t1, zero, 3
+        sw    t1, 8(t0)
+        wfi
+        lw    a0, 24(t0)
+        lw    a1, 12(t0)
+        ";
+    let mut cpu = program(source)
+        .device(BASE, DmaController::SIZE, DmaController::new())
+        .build()
+        .unwrap();
+    write_desc(&mut cpu, 0x100, 0x4000, 0x8000, 0x4000, 0);
+
+    cpu.run_steps(9);
+    assert!(cpu.is_waiting());
+
+    cpu.run_steps(3);
+    assert_eq!(cpu.regs[10], 0x4000);
+    assert_eq!(cpu.regs[11], STATUS_DONE);
+    assert!(
+        cpu.bus.time() < 0x4000 / 64 + 16,
+        "skipped ahead while waiting"
+    );
+}
@@ -0,0 +1,321 @@
+#![cfg(feature = "dwarf")]
+
+mod common;
+
+use riscv_emulator_rust::RiscvCpu;
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::coverage::Coverage;
+use riscv_emulator_rust::dwarf::LineTable;
+use riscv_emulator_rust::loader::ElfFile;
+use riscv_emulator_rust::trace::PrintTracer;
+
+use common::SharedBuffer;
+
+// ── Helpers: DWARF 4 line programs in an ELF32 image ─────────────────────────
+
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNS_SET_FILE: u8 = 0x04;
+
+fn set_address(addr: u32) -> Vec<u8> {
+    let mut op = vec![0, 5, 0x02];
+    op.extend_from_slice(&addr.to_le_bytes());
+    op
+}
+
+fn end_sequence() -> Vec<u8> {
+    vec![0, 1, 0x01]
+}
+
+/// Emit a row `line` lines further on, then move `advance` bytes ahead.
+/// Both have to fit in one byte of LEB128.
+fn row(line: i8, advance: u8) -> Vec<u8> {
+    vec![
+        DW_LNS_ADVANCE_LINE,
+        line as u8 & 0x7F,
+        DW_LNS_COPY,
+        DW_LNS_ADVANCE_PC,
+        advance,
+    ]
+}
+
+/// A DWARF 4 line number program for `files` (name, include directory
+/// index) in `dirs`, running `ops`.
+fn line_program(dirs: &[&str], files: &[(&str, u8)], ops: &[Vec<u8>]) -> Vec<u8> {
+    let mut header = vec![
+        1,            // minimum_instruction_length
+        1,            // maximum_operations_per_instruction
+        1,            // default_is_stmt
+        (-5i8) as u8, // line_base
+        14,           // line_range
+        13,           // opcode_base
+    ];
+    header.extend_from_slice(&[0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+    for dir in dirs {
+        header.extend_from_slice(dir.as_bytes());
+        header.push(0);
+    }
+    header.push(0);
+    for (name, dir) in files {
+        header.extend_from_slice(name.as_bytes());
+        header.extend_from_slice(&[0, *dir, 0, 0]);
+    }
+    header.push(0);
+
+    let program: Vec<u8> = ops.concat();
+    let mut out = Vec::new();
+    let unit_length = 2 + 4 + header.len() + program.len();
+    out.extend_from_slice(&(unit_length as u32).to_le_bytes());
+    out.extend_from_slice(&4u16.to_le_bytes());
+    out.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(&program);
+    out
+}
+
+/// Build an ELF32 RISC-V executable with `code` loaded at 0 and, if given,
+/// a `.debug_line` section.
+fn build_elf(code: &[u8], debug_line: Option<&[u8]>) -> Vec<u8> {
+    let code_off = 52 + 32;
+    let shstrtab = b"\0.debug_line\0.shstrtab\0";
+    let debug_line = debug_line.unwrap_or(&[]);
+    let debug_line_off = code_off + code.len();
+    let shstrtab_off = debug_line_off + debug_line.len();
+    let shoff = shstrtab_off + shstrtab.len();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+    out.extend_from_slice(&243u16.to_le_bytes()); // e_machine = EM_RISCV
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_entry
+    out.extend_from_slice(&52u32.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&(shoff as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&52u16.to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&32u16.to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&3u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_shstrndx
+
+    let size = code.len() as u32;
+    for field in [1, code_off as u32, 0, 0, size, size, 0x7, 4] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+
+    out.extend_from_slice(code);
+    out.extend_from_slice(debug_line);
+    out.extend_from_slice(shstrtab);
+
+    // section headers: null, .debug_line, .shstrtab
+    let debug_line_sh = [
+        1,
+        1,
+        0,
+        0,
+        debug_line_off as u32,
+        debug_line.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ];
+    let shstrtab_sh = [
+        13,
+        3,
+        0,
+        0,
+        shstrtab_off as u32,
+        shstrtab.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ];
+    for section in [[0; 10], debug_line_sh, shstrtab_sh] {
+        for field in section {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+
+    out
+}
+
+fn code(source: &str) -> Vec<u8> {
+    assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect()
+}
+
+fn table(debug_line: &[u8]) -> LineTable {
+    let elf = build_elf(&[0; 16], Some(debug_line));
+    LineTable::parse(&ElfFile::parse(&elf).unwrap()).unwrap()
+}
+
+fn location(table: &LineTable, addr: u32) -> Option<String> {
+    table.location(addr).map(|location| location.to_string())
+}
+
+// ── Line tables ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_rows_cover_until_the_next_one() {
+    let table = table(&line_program(
+        &[],
+        &[("main.c", 0)],
+        &[set_address(0x100), row(2, 8), row(3, 4), end_sequence()],
+    ));
+
+    assert_eq!(location(&table, 0xFC), None);
+    assert_eq!(location(&table, 0x100).as_deref(), Some("main.c:3"));
+    assert_eq!(location(&table, 0x104).as_deref(), Some("main.c:3"));
+    assert_eq!(location(&table, 0x108).as_deref(), Some("main.c:6"));
+    assert_eq!(
+        location(&table, 0x10C),
+        None,
+        "past the end of the sequence"
+    );
+}
+
+#[test]
+fn test_include_directories_are_joined() {
+    let table = table(&line_program(
+        &["src", "/usr/include"],
+        &[("main.c", 1), ("stdio.h", 2), ("start.S", 0)],
+        &[
+            set_address(0),
+            row(9, 4),
+            vec![DW_LNS_SET_FILE, 2],
+            row(10, 4),
+            vec![DW_LNS_SET_FILE, 3],
+            row(-15, 4),
+            end_sequence(),
+        ],
+    ));
+
+    assert_eq!(location(&table, 0).as_deref(), Some("src/main.c:10"));
+    assert_eq!(
+        location(&table, 4).as_deref(),
+        Some("/usr/include/stdio.h:20")
+    );
+    assert_eq!(location(&table, 8).as_deref(), Some("start.S:5"));
+}
+
+#[test]
+fn test_every_program_in_the_section_is_read() {
+    let debug_line = [
+        line_program(
+            &[],
+            &[("a.c", 0)],
+            &[set_address(0x200), row(0, 4), end_sequence()],
+        ),
+        line_program(
+            &[],
+            &[("b.c", 0)],
+            &[set_address(0x100), row(6, 4), end_sequence()],
+        ),
+    ]
+    .concat();
+
+    let table = table(&debug_line);
+
+    assert_eq!(location(&table, 0x100).as_deref(), Some("b.c:7"));
+    assert_eq!(location(&table, 0x200).as_deref(), Some("a.c:1"));
+    assert_eq!(location(&table, 0x180), None);
+}
+
+#[test]
+fn test_image_without_debug_info_has_an_empty_table() {
+    let elf = build_elf(&[0; 4], None);
+    let table = LineTable::parse(&ElfFile::parse(&elf).unwrap()).unwrap();
+
+    assert!(table.is_empty());
+}
+
+#[test]
+fn test_truncated_program_is_an_error() {
+    let mut debug_line = line_program(&[], &[("main.c", 0)], &[set_address(0), row(0, 4)]);
+    debug_line.truncate(debug_line.len() - 8);
+    let elf = build_elf(&[0; 4], Some(&debug_line));
+
+    assert!(LineTable::parse(&ElfFile::parse(&elf).unwrap()).is_err());
+}
+
+// ── On the hart ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_trace_annotates_source_lines() {
+    let debug_line = line_program(
+        &[],
+        &[("start.S", 0)],
+        &[set_address(0), row(1, 4), row(2, 4), end_sequence()],
+    );
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.load_elf(&build_elf(
+        &code("addi a0, zero, 1\naddi a0, a0, 1"),
+        Some(&debug_line),
+    ))
+    .unwrap();
+    assert_eq!(cpu.source_at(4).unwrap().line, 4);
+
+    let output = SharedBuffer::default();
+    cpu.set_tracer(
+        PrintTracer::with_output(Box::new(output.clone())).lines(cpu.line_table().clone()),
+    );
+    cpu.run_steps(2);
+
+    let text = output.contents();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].ends_with("  # start.S:2"), "{}", text);
+    assert!(lines[1].ends_with("  # start.S:4"), "{}", text);
+}
+
+#[test]
+fn test_coverage_by_source_line() {
+    let debug_line = line_program(
+        &[],
+        &[("main.c", 0)],
+        &[
+            set_address(0),
+            row(0, 8),
+            row(1, 4),
+            row(1, 4),
+            end_sequence(),
+        ],
+    );
+    let table = table(&debug_line);
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.load_binary(
+        0,
+        &code("addi a0, zero, 1\naddi a0, a0, 1\naddi a0, a0, 1\nebreak"),
+    )
+    .unwrap();
+    cpu.enable_coverage();
+    cpu.run(); // the ebreak on line 3 traps
+
+    let coverage: &Coverage = cpu.coverage().unwrap();
+
+    let lines: Vec<(String, u32, u64)> = coverage
+        .lines(&table)
+        .into_iter()
+        .map(|((file, line), hits)| (file.to_string(), line, hits))
+        .collect();
+    assert_eq!(
+        lines,
+        [
+            (String::from("main.c"), 1, 2),
+            (String::from("main.c"), 2, 1)
+        ]
+    );
+    let missed: Vec<u32> = coverage
+        .missed_lines(&table)
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect();
+    assert_eq!(missed, [3]);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use riscv_emulator_rust::RiscvCpu;
+use riscv_emulator_rust::loader::ElfFile;
+
+use common::le_bytes;
+
+// ── Helper: assemble a minimal ELF32 RISC-V executable ───────────────────────
+
+/// One PT_LOAD segment: (vaddr, file bytes, memsz)
+type SegmentSpec<'a> = (u32, &'a [u8], u32);
+
+/// Build an ELF32 little-endian RISC-V executable with one PT_LOAD program
+/// header per segment. Segment data is laid out back to back after the
+/// program header table.
+fn build_elf(entry: u32, segments: &[SegmentSpec]) -> Vec<u8> {
+    let ehsize = 52u32;
+    let phentsize = 32u32;
+    let phoff = ehsize;
+    let mut data_off = phoff + phentsize * segments.len() as u32;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+    out.extend_from_slice(&243u16.to_le_bytes()); // e_machine = EM_RISCV
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&entry.to_le_bytes());
+    out.extend_from_slice(&phoff.to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&(ehsize as u16).to_le_bytes());
+    out.extend_from_slice(&(phentsize as u16).to_le_bytes());
+    out.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+    out.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    for (vaddr, bytes, memsz) in segments {
+        for field in [
+            1, // PT_LOAD
+            data_off,
+            *vaddr,
+            *vaddr,
+            bytes.len() as u32,
+            *memsz,
+            0x7, // RWX
+            4,
+        ] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        data_off += bytes.len() as u32;
+    }
+
+    for (_, bytes, _) in segments {
+        out.extend_from_slice(bytes);
+    }
+
+    out
+}
+
+// ── Parsing ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_parse_header_and_segments() {
+    let code = le_bytes(&[0x00a00093]);
+    let elf = build_elf(0x100, &[(0x100, &code, 4)]);
+
+    let parsed = ElfFile::parse(&elf).unwrap();
+
+    assert_eq!(parsed.entry, 0x100);
+    assert_eq!(parsed.segments.len(), 1);
+    assert_eq!(parsed.segments[0].vaddr, 0x100);
+    assert_eq!(parsed.segments[0].filesz, 4);
+}
+
+#[test]
+fn test_rejects_bad_magic() {
+    let mut elf = build_elf(0, &[]);
+    elf[0] = 0;
+
+    assert!(ElfFile::parse(&elf).is_err());
+}
+
+#[test]
+fn test_rejects_64_bit_images() {
+    let mut elf = build_elf(0, &[]);
+    elf[4] = 2; // ELFCLASS64
+
+    assert!(ElfFile::parse(&elf).is_err());
+}
+
+#[test]
+fn test_rejects_other_machines() {
+    let mut elf = build_elf(0, &[]);
+    elf[18] = 62; // EM_X86_64
+
+    assert!(ElfFile::parse(&elf).is_err());
+}
+
+#[test]
+fn test_rejects_truncated_file() {
+    let elf = build_elf(0, &[(0, &[1, 2, 3, 4], 4)]);
+
+    assert!(ElfFile::parse(&elf[..60]).is_err());
+}
+
+// ── Loading ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_load_sets_pc_to_entry() {
+    let mut cpu = RiscvCpu::new(1024);
+    let code = le_bytes(&[0x00a00093]);
+    let elf = build_elf(0x200, &[(0x200, &code, 4)]);
+
+    cpu.load_elf(&elf).unwrap();
+
+    assert_eq!(cpu.pc, 0x200);
+    assert_eq!(&cpu.bus[0x200..0x204], &code[..]);
+}
+
+#[test]
+fn test_load_zero_fills_bss() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.bus[0x300..0x310].fill(0xAA);
+    let data = [1, 2, 3, 4];
+    let elf = build_elf(0, &[(0x300, &data, 16)]);
+
+    cpu.load_elf(&elf).unwrap();
+
+    assert_eq!(&cpu.bus[0x300..0x304], &data);
+    assert!(
+        cpu.bus[0x304..0x310].iter().all(|&b| b == 0),
+        ".bss must be zeroed"
+    );
+}
+
+#[test]
+fn test_load_multiple_segments() {
+    let mut cpu = RiscvCpu::new(1024);
+    let text = le_bytes(&[0x00a00093, 0x01400113]);
+    let data = [0xDE, 0xAD, 0xBE, 0xEF];
+    let elf = build_elf(0x0, &[(0x0, &text, 8), (0x380, &data, 4)]);
+
+    cpu.load_elf(&elf).unwrap();
+
+    assert_eq!(&cpu.bus[0x0..0x8], &text[..]);
+    assert_eq!(&cpu.bus[0x380..0x384], &data);
+}
+
+#[test]
+fn test_load_rejects_segment_outside_memory() {
+    let mut cpu = RiscvCpu::new(1024);
+    let elf = build_elf(0, &[(0x3FE, &[1, 2, 3, 4], 4)]);
+
+    assert!(cpu.load_elf(&elf).is_err());
+}
+
+/// addi x1, x0, 10
+/// addi x2, x0, 20
+/// add  x3, x1, x2
+#[test]
+fn test_loaded_program_runs() {
+    let mut cpu = RiscvCpu::new(1024);
+    let code = le_bytes(&[0x00a00093, 0x01400113, 0x002081b3]);
+    let elf = build_elf(0x100, &[(0x100, &code, code.len() as u32)]);
+
+    cpu.load_elf(&elf).unwrap();
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+
+    assert_eq!(cpu.regs[3], 30);
+    assert_eq!(cpu.pc, 0x10C);
+}
@@ -0,0 +1,152 @@
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::encode::*;
+
+fn asm(source: &str) -> u32 {
+    assemble(source).expect("assembly failed")[0]
+}
+
+// ── Agreement with the assembler ──────────────────────────────────────────────
+
+#[test]
+fn test_rv32i_matches_the_assembler() {
+    let cases = [
+        (lui(5, 0xFFFFF), "lui t0, 0xFFFFF"),
+        (auipc(1, 0x12345), "auipc ra, 0x12345"),
+        (jal(1, -2048), "jal ra, -2048"),
+        (jal(0, 0xFFFFE), "jal zero, 0xFFFFE"),
+        (jalr(0, 1, 0), "jalr zero, 0(ra)"),
+        (jalr(5, 6, -4), "jalr t0, -4(t1)"),
+        (beq(1, 2, 8), "beq ra, sp, 8"),
+        (bne(5, 0, -4), "bne t0, zero, -4"),
+        (blt(1, 2, 4094), "blt ra, sp, 4094"),
+        (bge(1, 2, -4096), "bge ra, sp, -4096"),
+        (bltu(3, 4, 12), "bltu gp, tp, 12"),
+        (bgeu(3, 4, 12), "bgeu gp, tp, 12"),
+        (lb(10, 2, -1), "lb a0, -1(sp)"),
+        (lh(10, 2, 2), "lh a0, 2(sp)"),
+        (lw(10, 2, 2047), "lw a0, 2047(sp)"),
+        (lbu(10, 2, 0), "lbu a0, 0(sp)"),
+        (lhu(10, 2, -2048), "lhu a0, -2048(sp)"),
+        (sb(11, 2, 1), "sb a1, 1(sp)"),
+        (sh(11, 2, -2), "sh a1, -2(sp)"),
+        (sw(11, 2, 100), "sw a1, 100(sp)"),
+        (addi(5, 0, 10), "addi t0, zero, 10"),
+        (slti(5, 6, -1), "slti t0, t1, -1"),
+        (sltiu(5, 6, 1), "sltiu t0, t1, 1"),
+        (xori(5, 6, -1), "xori t0, t1, -1"),
+        (ori(5, 6, 0x7FF), "ori t0, t1, 0x7FF"),
+        (andi(5, 6, 0xFF), "andi t0, t1, 0xFF"),
+        (slli(5, 6, 31), "slli t0, t1, 31"),
+        (srli(5, 6, 1), "srli t0, t1, 1"),
+        (srai(5, 6, 4), "srai t0, t1, 4"),
+        (add(1, 2, 3), "add ra, sp, gp"),
+        (sub(1, 2, 3), "sub ra, sp, gp"),
+        (sll(1, 2, 3), "sll ra, sp, gp"),
+        (slt(1, 2, 3), "slt ra, sp, gp"),
+        (sltu(1, 2, 3), "sltu ra, sp, gp"),
+        (xor(1, 2, 3), "xor ra, sp, gp"),
+        (srl(1, 2, 3), "srl ra, sp, gp"),
+        (sra(1, 2, 3), "sra ra, sp, gp"),
+        (or(1, 2, 3), "or ra, sp, gp"),
+        (and(1, 2, 3), "and ra, sp, gp"),
+        (fence(), "fence"),
+        (ecall(), "ecall"),
+        (ebreak(), "ebreak"),
+    ];
+    for (word, source) in cases {
+        assert_eq!(word, asm(source), "{}", source);
+    }
+}
+
+#[test]
+fn test_zicsr_and_privileged_match_the_assembler() {
+    let cases = [
+        (csrrw(1, csr::MSCRATCH, 2), "csrrw ra, mscratch, sp"),
+        (csrrs(1, csr::MSTATUS, 0), "csrrs ra, mstatus, zero"),
+        (csrrc(0, csr::MIE, 5), "csrrc zero, mie, t0"),
+        (csrrwi(1, csr::MTVEC, 31), "csrrwi ra, mtvec, 31"),
+        (csrrsi(0, csr::MSTATUS, 8), "csrrsi zero, mstatus, 8"),
+        (csrrci(0, csr::MSTATUS, 8), "csrrci zero, mstatus, 8"),
+        (csrrs(10, 0xC00, 0), "csrrs a0, cycle, zero"),
+        (mret(), "mret"),
+        (sret(), "sret"),
+        (wfi(), "wfi"),
+    ];
+    for (word, source) in cases {
+        assert_eq!(word, asm(source), "{}", source);
+    }
+}
+
+#[test]
+fn test_formats() {
+    assert_eq!(rtype(0x00, 3, 2, 0x0, 1, 0x33), add(1, 2, 3));
+    assert_eq!(itype(-1, 0, 0x0, 1, 0x13), addi(1, 0, -1));
+    assert_eq!(itype(0xFFF, 0, 0x0, 1, 0x13), addi(1, 0, -1));
+    assert_eq!(stype(4, 2, 1, 0x2), sw(2, 1, 4));
+    assert_eq!(btype(-8, 1, 2, 0x0), beq(1, 2, -8));
+    assert_eq!(utype(0x10000, 5, 0x37), lui(5, 0x10000));
+    assert_eq!(jtype(16, 1), jal(1, 16));
+}
+
+// ── Range checks ──────────────────────────────────────────────────────────────
+
+#[test]
+#[should_panic(expected = "immediate 2048 is out of range [-2048, 2047]")]
+fn test_addi_immediate_too_big() {
+    addi(1, 0, 2048);
+}
+
+#[test]
+#[should_panic(expected = "offset -2049 is out of range [-2048, 2047]")]
+fn test_load_offset_too_small() {
+    lw(1, 2, -2049);
+}
+
+#[test]
+#[should_panic(expected = "branch offset 4096 is out of range [-4096, 4094]")]
+fn test_branch_out_of_range() {
+    beq(1, 2, 4096);
+}
+
+#[test]
+#[should_panic(expected = "branch offset 3 isn't 2-byte aligned")]
+fn test_branch_misaligned() {
+    bne(1, 2, 3);
+}
+
+#[test]
+#[should_panic(expected = "jump offset 1048576 is out of range [-1048576, 1048574]")]
+fn test_jump_out_of_range() {
+    jal(0, 1 << 20);
+}
+
+#[test]
+#[should_panic(expected = "upper immediate 0x100000 doesn't fit in 20 bits")]
+fn test_upper_immediate_too_big() {
+    lui(1, 0x10_0000);
+}
+
+#[test]
+#[should_panic(expected = "shift amount 32 is out of range [0, 31]")]
+fn test_shift_amount_too_big() {
+    slli(1, 1, 32);
+}
+
+#[test]
+#[should_panic(expected = "x32 isn't a register")]
+fn test_register_out_of_range() {
+    add(32, 0, 0);
+}
+
+#[test]
+#[should_panic(expected = "CSR immediate 0x20 doesn't fit in 5 bits")]
+fn test_csr_immediate_too_big() {
+    csrrwi(0, csr::MSCRATCH, 32);
+}
+
+#[test]
+#[should_panic(expected = "immediate 4096 is out of range [-2048, 4095]")]
+fn test_raw_field_too_big() {
+    itype(0x1000, 0, 0, 0, 0x13);
+}
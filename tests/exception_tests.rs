@@ -0,0 +1,227 @@
+mod common;
+
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::encode::{itype, rtype};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{ExitReason, MemSize, RiscvCpu};
+
+use common::{le_bytes, program};
+
+fn load_program(cpu: &mut RiscvCpu, instructions: &[u32]) {
+    cpu.load_binary(0, &le_bytes(instructions)).unwrap();
+}
+
+// ── Illegal instructions ──────────────────────────────────────────────────────
+
+#[test]
+fn test_bad_rtype_funct7_is_illegal() {
+    let mut cpu = RiscvCpu::new(1024);
+
+    // add with funct7 = 0x7F
+    let instruction = rtype(0x7F, 2, 1, 0b000, 3, 0x33);
+
+    assert_eq!(
+        cpu.handle_rtype(instruction),
+        Err(Exception::IllegalInstruction(instruction))
+    );
+}
+
+#[test]
+fn test_bad_load_funct3_is_illegal() {
+    let mut cpu = RiscvCpu::new(1024);
+
+    // funct3 = 0b011 is LD, which doesn't exist on RV32
+    let instruction = itype(0, 0, 0b011, 1, 0x03);
+
+    assert_eq!(
+        cpu.handle_load(instruction),
+        Err(Exception::IllegalInstruction(instruction))
+    );
+}
+
+#[test]
+fn test_step_reports_illegal_instruction() {
+    let mut cpu = RiscvCpu::new(1024);
+    let instruction = rtype(0x7F, 2, 1, 0b101, 3, 0x33);
+    load_program(&mut cpu, &[instruction]);
+
+    let err = cpu.step().unwrap_err();
+
+    assert_eq!(err, Exception::IllegalInstruction(instruction));
+    assert_eq!(err.cause(), 2);
+    assert_eq!(err.tval(), instruction);
+    assert_eq!(cpu.pc, 0, "PC must not advance past a faulting instruction");
+}
+
+// ── Memory faults ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_out_of_bounds_load_faults() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.regs[1] = 0x1000;
+
+    // lw x2, 0(x1)
+    let result = cpu.handle_load(itype(0, 1, 0b010, 2, 0x03));
+
+    assert_eq!(result, Err(Exception::LoadAccessFault(0x1000)));
+    assert_eq!(cpu.regs[2], 0, "rd must not be written");
+}
+
+#[test]
+fn test_load_straddling_end_of_memory_faults() {
+    let mut cpu = RiscvCpu::new(1024);
+
+    assert_eq!(
+        cpu.load(0x3FE, MemSize::Word, false),
+        Err(Exception::LoadAccessFault(0x3FE))
+    );
+    assert!(cpu.load(0x3FC, MemSize::Word, false).is_ok());
+}
+
+#[test]
+fn test_out_of_bounds_store_faults() {
+    let mut cpu = RiscvCpu::new(1024);
+
+    assert_eq!(
+        cpu.store(0xFFFF_FFFF, MemSize::Byte, 0xAB),
+        Err(Exception::StoreAccessFault(0xFFFF_FFFF))
+    );
+}
+
+#[test]
+fn test_fetch_out_of_bounds_is_instruction_fault() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.pc = 0x400;
+
+    assert_eq!(cpu.step(), Err(Exception::InstructionAccessFault(0x400)));
+}
+
+// ── ECALL / EBREAK ────────────────────────────────────────────────────────────
+
+/// Assembles `source` at 0 into a CPU with guest traps set as requested.
+fn cpu_with(source: &str, guest_traps: bool) -> RiscvCpu {
+    program(source).guest_traps(guest_traps).build().unwrap()
+}
+
+#[test]
+fn test_ecall_halts_run_by_default() {
+    let mut cpu = cpu_with(
+        "
+        addi a7, zero, 93
+        ecall
+        addi a0, zero, 1
+        ",
+        false,
+    );
+
+    assert_eq!(cpu.run(), ExitReason::Exception(Exception::EnvironmentCall));
+    assert_eq!(cpu.pc, 0x4, "pc stays on the ecall");
+    assert_eq!(cpu.regs[17], 93);
+    assert_eq!(cpu.regs[10], 0);
+}
+
+#[test]
+fn test_ecall_traps_to_guest_when_enabled() {
+    let mut cpu = cpu_with(
+        "
+                addi t0, zero, 0x20
+                csrrw zero, mtvec, t0
+                ecall
+                addi a0, a0, 1
+        done:   jal zero, done
+                .word 0
+                .word 0
+                .word 0
+        handler:
+                csrrs t1, mepc, zero
+                addi t1, t1, 4
+                csrrw zero, mepc, t1
+                csrrs a1, mcause, zero
+                mret
+        ",
+        true,
+    );
+
+    assert_eq!(cpu.run_until(0x10), ExitReason::ReachedPc(0x10));
+    assert_eq!(cpu.regs[11], 11, "mcause = ECALL from M-mode");
+    assert_eq!(cpu.regs[10], 1, "handler resumed after the ecall");
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0xC);
+}
+
+#[test]
+fn test_guest_trap_sets_mtval() {
+    // add with funct7 = 0x7F
+    let mut cpu = cpu_with(".word 0xfe0000b3", true);
+    cpu.csrs.write(csr::MTVEC, 0x100);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0x0);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 2);
+    assert_eq!(cpu.csrs.read(csr::MTVAL), 0xfe0000b3);
+}
+
+#[test]
+fn test_access_past_end_of_memory_traps_to_guest() {
+    let mut cpu = cpu_with(
+        "
+        lui  t0, 0x10
+        lw   a0, 0(t0)
+        sb   a0, -1(t0)
+        sb   a0, 0(t0)
+        ",
+        true,
+    );
+    cpu.csrs.write(csr::MTVEC, 0x100);
+
+    cpu.run_steps(2);
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 5);
+    assert_eq!(cpu.csrs.read(csr::MTVAL), 0x10000);
+
+    cpu.pc = 0x8;
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 0xC, "the last byte of RAM is still writable");
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 7);
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0xC);
+}
+
+// ── Everything else ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_ebreak_reports_breakpoint_pc() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.pc = 0x8;
+    cpu.load_binary(0x8, &0x0010_0073u32.to_le_bytes()).unwrap();
+
+    let err = cpu.step().unwrap_err();
+
+    assert_eq!(err, Exception::Breakpoint(0x8));
+    assert_eq!(err.cause(), 3);
+}
+
+#[test]
+fn test_addi_overflow_wraps_instead_of_panicking() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.regs[1] = 0x7FFF_FFFF;
+
+    cpu.handle_itype(itype(1, 1, 0b000, 2, 0x13)).unwrap();
+
+    assert_eq!(cpu.regs[2], 0x8000_0000);
+}
+
+#[test]
+fn test_display_messages() {
+    assert_eq!(
+        Exception::LoadAccessFault(0x1000).to_string(),
+        "Load Access Fault: 0x1000 is out of bounds"
+    );
+    assert_eq!(
+        Exception::IllegalInstruction(0xFFFF_FFFF).to_string(),
+        "Illegal Instruction: 0xffffffff"
+    );
+}
@@ -0,0 +1,154 @@
+#![cfg(feature = "ffi")]
+
+mod common;
+
+use std::ffi::{CStr, c_void};
+use std::ptr;
+
+use riscv_emulator_rust::ffi::*;
+
+use common::image;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn emu_with(source: &str) -> *mut RiscvEmu {
+    let program = image(source);
+    let emu = riscv_emu_new(0, 0x1000);
+    assert!(!emu.is_null());
+    assert_eq!(
+        unsafe { riscv_emu_load(emu, program.as_ptr(), program.len()) },
+        0
+    );
+    emu
+}
+
+fn last_error(emu: *const RiscvEmu) -> String {
+    unsafe { CStr::from_ptr(riscv_emu_last_error(emu)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+// ── Running ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_step_and_registers() {
+    let emu = emu_with(
+        "
+        addi t0, zero, 5
+        add  t1, t0, t0
+        ebreak
+        ",
+    );
+    unsafe {
+        assert_eq!(riscv_emu_step(emu), RiscvEmuStatus::Executed);
+        assert_eq!(riscv_emu_get_pc(emu), 4);
+        assert_eq!(riscv_emu_get_reg(emu, 5), 5);
+
+        riscv_emu_set_reg(emu, 5, 20);
+        riscv_emu_set_reg(emu, 0, 1);
+        assert_eq!(riscv_emu_run(emu, 100), RiscvEmuStatus::Exception);
+        assert_eq!(riscv_emu_get_reg(emu, 6), 40);
+        assert_eq!(riscv_emu_get_reg(emu, 0), 0);
+        assert_eq!(last_error(emu), "EBREAK at 0x8");
+
+        riscv_emu_set_pc(emu, 0);
+        assert_eq!(riscv_emu_run(emu, 1), RiscvEmuStatus::StepLimit);
+        riscv_emu_free(emu);
+    }
+}
+
+#[test]
+fn test_memory() {
+    let emu = emu_with("ebreak");
+    let mut buf = [0u8; 4];
+    unsafe {
+        assert_eq!(riscv_emu_write_mem(emu, 0x100, [1, 2, 3, 4].as_ptr(), 4), 0);
+        assert_eq!(riscv_emu_read_mem(emu, 0x100, buf.as_mut_ptr(), 4), 0);
+        assert_eq!(buf, [1, 2, 3, 4]);
+
+        assert_eq!(riscv_emu_read_mem(emu, 0xFFE, buf.as_mut_ptr(), 4), -1);
+        assert_eq!(last_error(emu), "4 bytes at 0xffe aren't all RAM");
+        riscv_emu_free(emu);
+    }
+}
+
+#[test]
+fn test_bad_image_sets_the_error() {
+    let emu = riscv_emu_new(0, 0x1000);
+    let program = vec![0u8; 0x2000];
+    unsafe {
+        riscv_emu_set_pc(emu, 0x40);
+        assert_eq!(riscv_emu_load(emu, program.as_ptr(), program.len()), -1);
+        assert!(last_error(emu).contains("doesn't fit in RAM"));
+        assert_eq!(riscv_emu_get_pc(emu), 0x40, "a failed load leaves the PC");
+        riscv_emu_free(emu);
+        riscv_emu_free(ptr::null_mut());
+    }
+}
+
+// ── MMIO ──────────────────────────────────────────────────────────────────────
+
+/// The last write the MMIO region saw.
+#[derive(Default)]
+struct Register {
+    written: Option<(u32, u32, u32)>,
+}
+
+extern "C" fn mmio_read(_ctx: *mut c_void, offset: u32, size: u32) -> u32 {
+    0x1000 + offset * 0x10 + size
+}
+
+extern "C" fn mmio_write(ctx: *mut c_void, offset: u32, size: u32, value: u32) {
+    let register = unsafe { &mut *(ctx as *mut Register) };
+    register.written = Some((offset, size, value));
+}
+
+#[test]
+fn test_mmio_callbacks() {
+    let emu = emu_with(
+        "
+        lui  t0, 0x10000
+        lw   t1, 4(t0)
+        sb   t1, 8(t0)
+        ebreak
+        ",
+    );
+    let mut register = Register::default();
+    unsafe {
+        riscv_emu_add_mmio(
+            emu,
+            0x1000_0000,
+            0x100,
+            mmio_read,
+            mmio_write,
+            &mut register as *mut Register as *mut c_void,
+        );
+        assert_eq!(riscv_emu_run(emu, 100), RiscvEmuStatus::Exception);
+        assert_eq!(riscv_emu_get_reg(emu, 6), 0x1044);
+        riscv_emu_free(emu);
+    }
+    assert_eq!(register.written, Some((8, 1, 0x1044)));
+}
+
+// ── Header ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_header_declares_every_function() {
+    let source = include_str!("../src/ffi.rs");
+    let header = include_str!("../include/riscv_emu.h");
+
+    let exported: Vec<&str> = source
+        .split("extern \"C\" fn ")
+        .skip(1)
+        .filter_map(|rest| rest.split('(').next())
+        .filter(|name| name.starts_with("riscv_emu_"))
+        .collect();
+    assert!(exported.len() >= 12);
+    for name in exported {
+        assert!(
+            header.contains(&format!(" {}(", name)) || header.contains(&format!("*{}(", name)),
+            "{} is missing from include/riscv_emu.h; rerun cbindgen",
+            name
+        );
+    }
+}
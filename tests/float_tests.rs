@@ -0,0 +1,567 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::float::FloatRegs;
+use riscv_emulator_rust::snapshot::Snapshot;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu};
+
+use common::{cpu_with, image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn zfinx(source: &str) -> RiscvCpu {
+    program(source)
+        .float_regs(FloatRegs::Integer)
+        .build()
+        .unwrap()
+}
+
+fn boxed(value: f32) -> u64 {
+    0xFFFF_FFFF_0000_0000 | value.to_bits() as u64
+}
+
+// ── F and D ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_single_precision_arithmetic() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = RiscvCpu::builder()
+            .image(
+                0,
+                image(
+                    "
+                    addi     t0, zero, 3
+                    fcvt.s.w ft0, t0
+                    addi     t0, zero, 4
+                    fcvt.s.w ft1, t0
+                    fadd.s   ft2, ft0, ft1
+                    fmul.s   ft3, ft0, ft1
+                    fdiv.s   ft4, ft0, ft1
+                    fsqrt.s  ft5, ft1
+                    fmadd.s  ft6, ft0, ft1, ft0
+                    flt.s    a0, ft0, ft1
+                    fcvt.w.s a1, ft6
+                    fmv.x.w  a2, ft4
+                    ebreak
+                    ",
+                ),
+            )
+            .engine(engine)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x30))
+        );
+        assert_eq!(cpu.fregs[2], boxed(7.0), "{:?}", engine);
+        assert_eq!(cpu.fregs[3], boxed(12.0));
+        assert_eq!(cpu.fregs[4], boxed(0.75));
+        assert_eq!(cpu.fregs[5], boxed(2.0));
+        assert_eq!(cpu.fregs[6], boxed(15.0));
+        assert_eq!(cpu.regs[10], 1);
+        assert_eq!(cpu.regs[11], 15);
+        assert_eq!(cpu.regs[12], 0.75f32.to_bits());
+    }
+}
+
+#[test]
+fn test_double_precision_and_conversion_between_formats() {
+    let mut cpu = cpu_with(
+        "
+        addi     t0, zero, -7
+        fcvt.d.w fa0, t0
+        fcvt.s.d fa1, fa0
+        fcvt.d.s fa2, fa1
+        fsgnjn.d fa3, fa0, fa0
+        fmax.d   fa4, fa0, fa3
+        feq.d    a0, fa0, fa2
+        ",
+    );
+
+    cpu.run_steps(7);
+
+    assert_eq!(cpu.fregs[10], (-7.0f64).to_bits());
+    assert_eq!(cpu.fregs[11], boxed(-7.0));
+    assert_eq!(cpu.fregs[12], (-7.0f64).to_bits());
+    assert_eq!(cpu.fregs[13], 7.0f64.to_bits());
+    assert_eq!(cpu.fregs[14], 7.0f64.to_bits());
+    assert_eq!(cpu.regs[10], 1);
+}
+
+#[test]
+fn test_loads_and_stores() {
+    let mut cpu = cpu_with(
+        "
+        lui t0, 0x1
+        flw ft0, 0(t0)
+        fld ft1, 8(t0)
+        fsw ft0, 16(t0)
+        fsd ft1, 24(t0)
+        ",
+    );
+    cpu.bus.write_bytes(0x1000, &1.5f32.to_le_bytes()).unwrap();
+    cpu.bus
+        .write_bytes(0x1008, &(-2.25f64).to_le_bytes())
+        .unwrap();
+
+    cpu.run_steps(5);
+
+    assert_eq!(cpu.fregs[0], boxed(1.5));
+    assert_eq!(cpu.fregs[1], (-2.25f64).to_bits());
+    assert_eq!(cpu.bus.read(0x1010, MemSize::Word), Some(1.5f32.to_bits()));
+    assert_eq!(cpu.bus.read(0x1018, MemSize::Word), Some(0));
+    assert_eq!(
+        cpu.bus.read(0x101C, MemSize::Word),
+        Some(((-2.25f64).to_bits() >> 32) as u32)
+    );
+}
+
+#[test]
+fn test_improperly_boxed_singles_read_as_nan() {
+    let mut cpu = cpu_with("fadd.s ft2, ft1, ft1\nfclass.s a0, ft1");
+    cpu.fregs[1] = 1.0f32.to_bits() as u64;
+
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.fregs[2], 0xFFFF_FFFF_7FC0_0000);
+    assert_eq!(cpu.regs[10], 1 << 9, "a quiet NaN");
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), 0);
+}
+
+// ── Rounding and flags ────────────────────────────────────────────────────────
+
+#[test]
+fn test_conversion_to_integer_rounds_and_saturates() {
+    let mut cpu = cpu_with(
+        "
+        fcvt.w.s  a0, ft0, rne
+        fcvt.w.s  a1, ft0, rtz
+        fcvt.w.s  a2, ft0, rup
+        fcvt.w.s  a3, ft0, rmm
+        fcvt.w.s  a4, ft1, rdn
+        fcvt.wu.s a5, ft1
+        fcvt.w.s  a6, ft2
+        fcvt.w.s  a7, ft3
+        csrrwi    zero, frm, 3
+        fcvt.w.s  s2, ft0
+        ",
+    );
+    cpu.fregs[0] = boxed(2.5);
+    cpu.fregs[1] = boxed(-1.5);
+    cpu.fregs[2] = boxed(1e10);
+    cpu.fregs[3] = boxed(f32::NAN);
+
+    cpu.run_steps(10);
+
+    assert_eq!(cpu.regs[10], 2);
+    assert_eq!(cpu.regs[11], 2);
+    assert_eq!(cpu.regs[12], 3);
+    assert_eq!(cpu.regs[13], 3);
+    assert_eq!(cpu.regs[14], -2i32 as u32);
+    assert_eq!(cpu.regs[15], 0, "negative to unsigned saturates at zero");
+    assert_eq!(cpu.regs[16], i32::MAX as u32);
+    assert_eq!(cpu.regs[17], i32::MAX as u32, "NaN converts to the maximum");
+    assert_eq!(cpu.regs[18], 3, "dynamic rounding uses frm");
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), csr::FFLAG_NV | csr::FFLAG_NX);
+}
+
+#[test]
+fn test_exception_flags_accrue_in_fcsr() {
+    let mut cpu = cpu_with(
+        "
+        fdiv.s  ft2, ft0, ft1
+        csrrs   a0, fflags, zero
+        fsqrt.s ft3, ft4
+        csrrwi  zero, frm, 1
+        csrrs   a1, fcsr, zero
+        csrrw   a2, fflags, zero
+        csrrs   a3, fcsr, zero
+        ",
+    );
+    cpu.fregs[0] = boxed(1.0);
+    cpu.fregs[1] = boxed(0.0);
+    cpu.fregs[4] = boxed(-1.0);
+
+    cpu.run_steps(7);
+
+    assert_eq!(cpu.fregs[2], boxed(f32::INFINITY));
+    assert_eq!(cpu.fregs[3], 0xFFFF_FFFF_7FC0_0000);
+    assert_eq!(cpu.regs[10], csr::FFLAG_DZ);
+    assert_eq!(cpu.regs[11], 1 << 5 | csr::FFLAG_DZ | csr::FFLAG_NV);
+    assert_eq!(cpu.regs[12], csr::FFLAG_DZ | csr::FFLAG_NV);
+    assert_eq!(cpu.regs[13], 1 << 5, "clearing fflags leaves frm");
+}
+
+#[test]
+fn test_dynamic_rounding_with_a_reserved_frm_is_illegal() {
+    let mut cpu = cpu_with("csrrwi zero, frm, 5\nfadd.s ft0, ft1, ft2\nfadd.s ft0, ft1, ft2, rne");
+
+    cpu.step().unwrap();
+    let fadd = assemble("fadd.s ft0, ft1, ft2").unwrap()[0];
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(fadd)));
+
+    cpu.pc = 8;
+    assert!(cpu.step().is_ok(), "a static rounding mode still works");
+}
+
+#[test]
+fn test_a_reserved_static_rounding_mode_is_illegal() {
+    let fadd = assemble("fadd.s ft0, ft1, ft2, rne").unwrap()[0];
+    for rm in [5, 6] {
+        let word = fadd | rm << 12;
+        let mut cpu = RiscvCpu::builder()
+            .image(0, word.to_le_bytes().to_vec())
+            .build()
+            .unwrap();
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(word)),
+            "rm {}",
+            rm
+        );
+    }
+}
+
+#[test]
+fn test_arithmetic_honors_the_rounding_mode() {
+    let mut cpu = cpu_with(
+        "
+        fdiv.s   ft2, ft0, ft1, rne
+        fdiv.s   ft3, ft0, ft1, rtz
+        fdiv.s   ft4, ft0, ft1, rdn
+        fdiv.s   ft5, ft0, ft1, rup
+        fdiv.s   ft6, ft0, ft1, rmm
+        fnmadd.s ft7, ft0, ft0, ft1, rdn
+        csrrwi   zero, frm, 3
+        fsqrt.s  ft8, ft1
+        fdiv.d   ft9, fs0, fs1
+        ",
+    );
+    cpu.fregs[0] = boxed(1.0);
+    cpu.fregs[1] = boxed(3.0);
+    cpu.fregs[8] = 1.0f64.to_bits();
+    cpu.fregs[9] = 3.0f64.to_bits();
+
+    cpu.run_steps(9);
+
+    assert_eq!(cpu.fregs[2], boxed(f32::from_bits(0x3EAA_AAAB)));
+    assert_eq!(cpu.fregs[3], boxed(f32::from_bits(0x3EAA_AAAA)));
+    assert_eq!(cpu.fregs[4], boxed(f32::from_bits(0x3EAA_AAAA)));
+    assert_eq!(cpu.fregs[5], boxed(f32::from_bits(0x3EAA_AAAB)));
+    assert_eq!(cpu.fregs[6], boxed(f32::from_bits(0x3EAA_AAAB)));
+    assert_eq!(cpu.fregs[7], boxed(-4.0), "exact results aren't rounded");
+    assert_eq!(
+        cpu.fregs[28],
+        boxed(f32::from_bits(0x3FDD_B3D8)),
+        "sqrt(3) rounded up by frm"
+    );
+    assert_eq!(cpu.fregs[29], 0x3FD5_5555_5555_5556);
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), csr::FFLAG_NX);
+}
+
+#[test]
+fn test_ties_round_to_even_or_away() {
+    let mut cpu = cpu_with(
+        "
+        fadd.s ft2, ft0, ft1, rne
+        fadd.s ft3, ft0, ft1, rmm
+        fsub.s ft4, ft5, ft0, rmm
+        ",
+    );
+    // 2^24 + 1 is halfway between 2^24 and 2^24 + 2.
+    cpu.fregs[0] = boxed(16_777_216.0);
+    cpu.fregs[1] = boxed(1.0);
+    cpu.fregs[5] = boxed(-1.0);
+
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.fregs[2], boxed(16_777_216.0));
+    assert_eq!(cpu.fregs[3], boxed(16_777_218.0));
+    assert_eq!(
+        cpu.fregs[4],
+        boxed(-16_777_218.0),
+        "RMM ties away from zero"
+    );
+}
+
+#[test]
+fn test_overflow_depends_on_the_rounding_mode() {
+    let mut cpu = cpu_with(
+        "
+        fmul.s ft2, ft0, ft1, rne
+        fmul.s ft3, ft0, ft1, rtz
+        fmul.s ft4, ft0, ft1, rdn
+        fmul.s ft5, ft6, ft1, rup
+        ",
+    );
+    cpu.fregs[0] = boxed(f32::MAX);
+    cpu.fregs[1] = boxed(2.0);
+    cpu.fregs[6] = boxed(-f32::MAX);
+
+    cpu.run_steps(4);
+
+    assert_eq!(cpu.fregs[2], boxed(f32::INFINITY));
+    assert_eq!(cpu.fregs[3], boxed(f32::MAX));
+    assert_eq!(cpu.fregs[4], boxed(f32::MAX));
+    assert_eq!(cpu.fregs[5], boxed(-f32::MAX));
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), csr::FFLAG_OF | csr::FFLAG_NX);
+}
+
+#[test]
+fn test_underflow_means_tiny_after_rounding_and_inexact() {
+    let mut cpu = cpu_with(
+        "
+        fmul.s ft2, ft0, ft1, rne
+        csrrs  a0, fflags, zero
+        fmul.s ft3, ft0, ft1, rtz
+        csrrw  a1, fflags, zero
+        fmul.s ft4, ft5, ft5
+        csrrs  a2, fflags, zero
+        ",
+    );
+    // (1 + 2^-23)(1 - 2^-23) * 2^-126, just below the smallest normal.
+    cpu.fregs[0] = boxed(f32::from_bits(0x2000_0001));
+    cpu.fregs[1] = boxed(f32::from_bits(0x1FFF_FFFE));
+    cpu.fregs[5] = boxed(2f32.powi(-70));
+
+    cpu.run_steps(6);
+
+    assert_eq!(cpu.fregs[2], boxed(f32::MIN_POSITIVE));
+    assert_eq!(cpu.regs[10], csr::FFLAG_NX, "rounds up to a normal");
+    assert_eq!(cpu.fregs[3], boxed(f32::from_bits(0x007F_FFFF)));
+    assert_eq!(cpu.regs[11], csr::FFLAG_UF | csr::FFLAG_NX);
+    assert_eq!(cpu.fregs[4], boxed(f32::from_bits(0x200)), "2^-140");
+    assert_eq!(cpu.regs[12], 0, "an exact subnormal doesn't underflow");
+}
+
+#[test]
+fn test_exact_zero_sums_are_negative_only_rounding_down() {
+    let mut cpu = cpu_with(
+        "
+        fsub.s   ft2, ft0, ft0, rne
+        fsub.s   ft3, ft0, ft0, rdn
+        fnmsub.s ft4, ft0, ft0, ft1, rdn
+        fadd.s   ft5, ft6, ft6, rup
+        ",
+    );
+    cpu.fregs[0] = boxed(1.5);
+    cpu.fregs[1] = boxed(2.25);
+    cpu.fregs[6] = boxed(-0.0);
+
+    cpu.run_steps(4);
+
+    assert_eq!(cpu.fregs[2], boxed(0.0));
+    assert_eq!(cpu.fregs[3], boxed(-0.0));
+    assert_eq!(cpu.fregs[4], boxed(-0.0));
+    assert_eq!(cpu.fregs[5], boxed(-0.0), "-0 + -0 is -0 in any mode");
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), 0);
+}
+
+#[test]
+fn test_conversions_honor_the_rounding_mode() {
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .image(
+            0,
+            image(
+                "
+                fcvt.s.d ft1, fs0, rtz
+                fcvt.s.d ft2, fs0, rup
+                fcvt.s.w ft3, a0
+                fcvt.s.w ft4, a0, rup
+                fcvt.d.l ft5, a1, rdn
+                fcvt.l.d a2, fs1
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+    cpu.fregs[8] = (1.0f64 / 3.0).to_bits();
+    cpu.fregs[9] = 2f64.powi(63).to_bits();
+    cpu.regs[10] = 16_777_217;
+    cpu.regs[11] = i64::MAX as u64;
+
+    cpu.run_steps(5);
+    assert_eq!(cpu.fregs[1], boxed(f32::from_bits(0x3EAA_AAAA)));
+    assert_eq!(cpu.fregs[2], boxed(f32::from_bits(0x3EAA_AAAB)));
+    assert_eq!(cpu.fregs[3], boxed(16_777_216.0));
+    assert_eq!(cpu.fregs[4], boxed(16_777_218.0));
+    assert_eq!(cpu.fregs[5], (2f64.powi(63) - 1024.0).to_bits());
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), csr::FFLAG_NX as u64);
+
+    cpu.run_steps(1);
+    assert_eq!(cpu.regs[12], i64::MAX as u64, "2^63 saturates");
+    assert_eq!(
+        cpu.csrs.read(csr::FFLAGS),
+        (csr::FFLAG_NX | csr::FFLAG_NV) as u64
+    );
+}
+
+// ── Zfinx and Zdinx ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_zfinx_computes_in_integer_registers() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = RiscvCpu::builder()
+            .image(
+                0,
+                image(
+                    "
+                    addi     a0, zero, 3
+                    fcvt.s.w a1, a0
+                    fadd.s   a2, a1, a1
+                    fcvt.w.s a3, a2
+                    flt.s    a4, a1, a2
+                    ebreak
+                    ",
+                ),
+            )
+            .float_regs(FloatRegs::Integer)
+            .engine(engine)
+            .build()
+            .unwrap();
+
+        cpu.run();
+
+        assert_eq!(cpu.regs[11], 3.0f32.to_bits(), "{:?}", engine);
+        assert_eq!(cpu.regs[12], 6.0f32.to_bits());
+        assert_eq!(cpu.regs[13], 6);
+        assert_eq!(cpu.regs[14], 1);
+        assert_eq!(cpu.fregs, [0; 32]);
+    }
+}
+
+#[test]
+fn test_zfinx_has_no_float_loads_stores_or_moves() {
+    for source in [
+        "flw a0, 0(zero)",
+        "fsd a0, 0(zero)",
+        "fmv.x.w a0, a1",
+        "fmv.w.x a0, a1",
+    ] {
+        let mut cpu = zfinx(source);
+        let word = assemble(source).unwrap()[0];
+
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(word)),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_zdinx_uses_register_pairs_on_rv32() {
+    let mut cpu = zfinx(
+        "
+        addi     a0, zero, 3
+        fcvt.d.w a2, a0
+        fadd.d   a4, a2, a2
+        fcvt.w.d a0, a4
+        fcvt.s.d a1, a4
+        fadd.d   zero, a2, a2
+        ",
+    );
+
+    cpu.run_steps(6);
+
+    let three = 3.0f64.to_bits();
+    assert_eq!(
+        [cpu.regs[12], cpu.regs[13]],
+        [three as u32, (three >> 32) as u32]
+    );
+    assert_eq!(cpu.regs[15], (6.0f64.to_bits() >> 32) as u32);
+    assert_eq!(cpu.regs[10], 6);
+    assert_eq!(cpu.regs[11], 6.0f32.to_bits());
+    assert_eq!(cpu.regs[0], 0);
+    assert_eq!(cpu.regs[1], 0, "writes to the x0 pair are discarded");
+}
+
+#[test]
+fn test_zdinx_odd_registers_are_illegal_on_rv32() {
+    let source = "fadd.d a1, a2, a2";
+    let mut cpu = zfinx(source);
+
+    assert_eq!(
+        cpu.step(),
+        Err(Exception::IllegalInstruction(assemble(source).unwrap()[0]))
+    );
+}
+
+#[test]
+fn test_zfinx_on_rv64() {
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .image(
+            0,
+            image(
+                "
+                addi     a0, zero, -1
+                fcvt.s.w a1, a0
+                fcvt.d.l a3, a0
+                fadd.d   a3, a3, a3
+                fcvt.l.d a4, a3
+                ",
+            ),
+        )
+        .float_regs(FloatRegs::Integer)
+        .build()
+        .unwrap();
+
+    cpu.run_steps(5);
+
+    assert_eq!(
+        cpu.regs[11],
+        (-1.0f32).to_bits() as i32 as i64 as u64,
+        "singles are sign-extended"
+    );
+    assert_eq!(cpu.regs[13], (-2.0f64).to_bits());
+    assert_eq!(cpu.regs[14], -2i64 as u64);
+}
+
+#[test]
+fn test_misa_reports_f_and_d_only_with_separate_registers() {
+    let fd = csr::MISA_F | csr::MISA_D;
+
+    assert_eq!(cpu_with("").csrs.read(csr::MISA) & fd, fd);
+    assert_eq!(zfinx("").csrs.read(csr::MISA) & fd, 0);
+}
+
+// ── Snapshots and disassembly ─────────────────────────────────────────────────
+
+#[test]
+fn test_float_registers_survive_a_snapshot() {
+    let mut cpu = cpu_with("");
+    cpu.fregs[7] = boxed(1.25);
+    cpu.csrs.write(csr::FCSR, 0x45);
+
+    let snapshot = Snapshot::from_bytes(&cpu.save_snapshot().to_bytes()).unwrap();
+    let restored = RiscvCpu::from_snapshot(&snapshot);
+
+    assert_eq!(restored.fregs[7], boxed(1.25));
+    assert_eq!(restored.csrs.read(csr::FCSR), 0x45);
+    assert_eq!(restored.csrs.read(csr::FRM), 2);
+}
+
+#[test]
+fn test_disassembly() {
+    let cases = [
+        ("fmadd.d f1, f2, f3, f4, rtz", "fmadd.d f1, f2, f3, f4"),
+        ("fcvt.wu.s a0, fa1", "fcvt.wu.s x10, f11"),
+        ("fcvt.s.d ft0, fs0", "fcvt.s.d f0, f8"),
+        ("fle.d t0, ft11, fs11", "fle.d x5, f31, f27"),
+        ("fsw fa0, -4(sp)", "fsw f10, -4(x2)"),
+        ("fmv.w.x ft0, a0", "fmv.w.x f0, x10"),
+    ];
+
+    for (source, expected) in cases {
+        let word = assemble(source).unwrap()[0];
+        assert_eq!(decode(word).unwrap().to_string(), expected, "{}", source);
+    }
+}
@@ -0,0 +1,76 @@
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::encode;
+use riscv_emulator_rust::fuzz::{
+    Generator, Limits, Violation, check_bytes, check_decode, check_program, check_random,
+};
+use riscv_emulator_rust::xlen::Rv32;
+
+// ── Generator ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_generator_is_deterministic() {
+    assert_eq!(Generator::new(7).program(64), Generator::new(7).program(64));
+    assert_ne!(Generator::new(7).program(64), Generator::new(8).program(64));
+}
+
+#[test]
+fn test_generated_programs_decode() {
+    for seed in 0..50 {
+        let words = Generator::new(seed).program(100);
+        assert_eq!(words.len(), 101);
+        assert_eq!(words[100], encode::ebreak());
+        for &word in &words {
+            assert!(decode(word).is_ok(), "seed {}: {:#010x}", seed, word);
+        }
+    }
+}
+
+// ── Invariants ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_random_programs_hold_invariants() {
+    let limits = Limits {
+        max_steps: 2_000,
+        ..Limits::default()
+    };
+    for seed in 0..200 {
+        if let Err(violation) = check_random(seed, 200, &limits) {
+            panic!("seed {}: {}", seed, violation);
+        }
+    }
+}
+
+#[test]
+fn test_random_words_decode_without_panicking() {
+    let mut generator = Generator::new(1);
+    for _ in 0..100_000 {
+        check_decode(generator.next_u64() as u32).unwrap();
+    }
+}
+
+#[test]
+fn test_random_bytes_run_without_panicking() {
+    let mut generator = Generator::new(2);
+    for _ in 0..100 {
+        let data: Vec<u8> = (0..256).map(|_| generator.next_u64() as u8).collect();
+        check_bytes(&data).unwrap();
+    }
+    check_bytes(&[]).unwrap();
+    check_bytes(&[0x13]).unwrap();
+}
+
+#[test]
+fn test_pc_leaving_bounds_is_reported() {
+    // jal x0, +8 jumps past the end of a two-word program.
+    let words = [encode::jal(0, 8), encode::ebreak()];
+    assert_eq!(
+        check_program::<Rv32>(&words, &Limits::default(), Some((0, 8))),
+        Err(Violation::PcOutOfBounds { step: 1, pc: 8 })
+    );
+}
+
+#[test]
+fn test_violations_display() {
+    let violation = Violation::PcOutOfBounds { step: 3, pc: 0x40 };
+    assert_eq!(violation.to_string(), "pc 0x40 out of bounds after step 3");
+}
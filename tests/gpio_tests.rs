@@ -0,0 +1,155 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use riscv_emulator_rust::MemSize;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::{Device, Gpio};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const INPUT_VAL: u32 = 0x00;
+const INPUT_EN: u32 = 0x04;
+const OUTPUT_EN: u32 = 0x08;
+const OUTPUT_VAL: u32 = 0x0C;
+const RISE_IE: u32 = 0x18;
+const RISE_IP: u32 = 0x1C;
+const HIGH_IE: u32 = 0x28;
+const HIGH_IP: u32 = 0x2C;
+const OUT_XOR: u32 = 0x40;
+
+/// Output changes as (pin, level).
+type Log = Rc<RefCell<Vec<(u32, bool)>>>;
+
+/// A GPIO block that logs every output change.
+fn logged_gpio() -> (Gpio, Log) {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let sink = log.clone();
+    let gpio = Gpio::new().on_output(move |pin, high| sink.borrow_mut().push((pin, high)));
+    (gpio, log)
+}
+
+// ── Outputs ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_guest_blinks_an_led() {
+    let (gpio, log) = logged_gpio();
+    let pins = gpio.pins();
+    let source = "
+        lui   t0, 0x10012
+        addi  t1, zero, 0x20
+        sw    t1, 8(t0)
+        sw    t1, 12(t0)
+        sw    zero, 12(t0)
+        sw    t1, 12(t0)
+        ";
+    let mut cpu = program(source)
+        .device(Gpio::BASE, Gpio::SIZE, gpio)
+        .build()
+        .unwrap();
+
+    cpu.run_steps(4);
+    assert_eq!(*log.borrow(), [(5, true)]);
+    assert!(pins.output(5));
+
+    cpu.run_steps(2);
+    assert_eq!(*log.borrow(), [(5, true), (5, false), (5, true)]);
+    assert_eq!(pins.outputs(), 0x20);
+}
+
+#[test]
+fn test_outputs_need_output_en_and_honor_out_xor() {
+    let (mut gpio, log) = logged_gpio();
+
+    gpio.write(OUTPUT_VAL, MemSize::Word, 0b11);
+    assert!(log.borrow().is_empty(), "not enabled yet");
+
+    gpio.write(OUT_XOR, MemSize::Word, 0b10);
+    gpio.write(OUTPUT_EN, MemSize::Word, 0b11);
+    assert_eq!(*log.borrow(), [(0, true)], "pin 1 is inverted");
+    assert_eq!(gpio.pins().outputs(), 0b01);
+}
+
+// ── Inputs ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_host_drives_inputs() {
+    let mut gpio = Gpio::new();
+    let pins = gpio.pins();
+
+    pins.set(3, true);
+    assert_eq!(gpio.read(INPUT_VAL, MemSize::Word), 0, "input disabled");
+
+    gpio.write(INPUT_EN, MemSize::Word, 1 << 3);
+    assert_eq!(gpio.read(INPUT_VAL, MemSize::Word), 1 << 3);
+
+    pins.set(3, false);
+    assert_eq!(gpio.read(INPUT_VAL, MemSize::Word), 0);
+}
+
+#[test]
+fn test_button_press_interrupts() {
+    let mut gpio = Gpio::new();
+    let pins = gpio.pins();
+    gpio.write(INPUT_EN, MemSize::Word, 1);
+    gpio.write(RISE_IE, MemSize::Word, 1);
+
+    gpio.tick(1);
+    assert_eq!(gpio.interrupts(0), 0);
+
+    pins.set(0, true);
+    gpio.tick(1);
+    assert_eq!(gpio.read(RISE_IP, MemSize::Word), 1);
+    assert_eq!(gpio.interrupts(0), csr::MIP_MEIP);
+    assert_eq!(gpio.interrupts(1), 0, "only hart 0");
+
+    gpio.write(RISE_IP, MemSize::Word, 1);
+    assert_eq!(gpio.interrupts(0), 0, "write one to clear");
+}
+
+#[test]
+fn test_level_interrupts_stay_pending() {
+    let mut gpio = Gpio::new().with_interrupt(0, csr::MIP_SEIP);
+    let pins = gpio.pins();
+    gpio.write(INPUT_EN, MemSize::Word, 1);
+    gpio.write(HIGH_IE, MemSize::Word, 1);
+    pins.set(0, true);
+    gpio.tick(1);
+
+    gpio.write(HIGH_IP, MemSize::Word, 1);
+    assert_eq!(gpio.read(HIGH_IP, MemSize::Word), 1, "still high");
+    assert_eq!(gpio.interrupts(0), csr::MIP_SEIP);
+
+    pins.set(0, false);
+    gpio.write(HIGH_IP, MemSize::Word, 1);
+    assert_eq!(gpio.interrupts(0), 0);
+}
+
+#[test]
+fn test_guest_waits_for_a_button() {
+    let gpio = Gpio::new();
+    let pins = gpio.pins();
+    let source = "
+        lui   t0, 0x10012
+        addi  t1, zero, 1
+        sw    t1, 4(t0)
+wait:   lw    t2, 0(t0)
+        beq   t2, zero, wait
+        addi  a0, zero, 42
+        ";
+    let mut cpu = program(source)
+        .device(Gpio::BASE, Gpio::SIZE, gpio)
+        .build()
+        .unwrap();
+
+    cpu.run_steps(50);
+    assert_eq!(cpu.regs[10], 0);
+
+    // Mid-loop, so it takes one more pass to see the press.
+    pins.set(0, true);
+    cpu.run_steps(4);
+    assert_eq!(cpu.regs[10], 42);
+}
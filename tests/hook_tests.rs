@@ -0,0 +1,174 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use riscv_emulator_rust::custom::Hart;
+use riscv_emulator_rust::decode::Instruction;
+use riscv_emulator_rust::hooks::StepInfo;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source)
+        .ram_size(0x4000)
+        .engine(engine)
+        .build()
+        .unwrap()
+}
+
+const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::BasicBlocks];
+
+const PROGRAM: &str = "
+        addi  a0, zero, 5
+        addi  a1, a0, 1
+        add   a2, a0, a1
+        ebreak
+";
+
+// ── Hooks ─────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_hooks_see_every_instruction_in_order() {
+    for engine in ENGINES {
+        let mut cpu = cpu_with(PROGRAM, engine);
+        let log = Rc::new(RefCell::new(Vec::new()));
+
+        let pre = log.clone();
+        cpu.on_pre_step(move |_, step| pre.borrow_mut().push(("pre", *step)));
+        let post = log.clone();
+        cpu.on_post_step(move |_, step| post.borrow_mut().push(("post", *step)));
+        cpu.run();
+
+        let log = log.borrow();
+        assert_eq!(
+            log.len(),
+            7,
+            "{:?}: ebreak traps, so it never retires",
+            engine
+        );
+        assert_eq!(
+            log[0],
+            (
+                "pre",
+                StepInfo {
+                    pc: 0,
+                    raw: 0x0050_0513,
+                    instruction: Instruction::Addi {
+                        rd: 10,
+                        rs1: 0,
+                        imm: 5
+                    },
+                }
+            )
+        );
+        assert_eq!(log[1].0, "post");
+        assert_eq!(log[1].1, log[0].1);
+        let pcs: Vec<u32> = log.iter().map(|(_, step)| step.pc).collect();
+        assert_eq!(pcs, [0, 0, 4, 4, 8, 8, 12]);
+    }
+}
+
+#[test]
+fn test_hooks_see_registers_before_and_after() {
+    let mut cpu = cpu_with(PROGRAM, Engine::Interpreter);
+    let seen = Rc::new(RefCell::new(Vec::new()));
+
+    let before = seen.clone();
+    cpu.on_pre_step(move |hart, step| {
+        if step.pc == 8 {
+            before.borrow_mut().push(hart.reg(12));
+        }
+    });
+    let after = seen.clone();
+    cpu.on_post_step(move |hart, step| {
+        if step.pc == 8 {
+            after.borrow_mut().push(hart.reg(12));
+        }
+    });
+    cpu.run();
+
+    assert_eq!(*seen.borrow(), [0, 11]);
+}
+
+#[test]
+fn test_a_pre_step_hook_can_inject_a_fault() {
+    for engine in ENGINES {
+        let mut cpu = cpu_with(PROGRAM, engine);
+        // Flip a bit of a0 just before it's read.
+        cpu.on_pre_step(|hart: &mut dyn Hart, step: &StepInfo| {
+            if step.pc == 4 {
+                hart.set_reg(10, hart.reg(10) ^ 0x100);
+            }
+        });
+        cpu.run();
+
+        assert_eq!(cpu.regs[11], 0x106, "{:?}", engine);
+        assert_eq!(cpu.regs[12], 0x105 + 0x106);
+    }
+}
+
+#[test]
+fn test_a_post_step_hook_can_redirect() {
+    for engine in ENGINES {
+        let mut cpu = cpu_with(PROGRAM, engine);
+        cpu.on_post_step(|hart, step| {
+            if step.pc == 0 {
+                hart.jump(8);
+            }
+        });
+
+        assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(12)));
+        assert_eq!(cpu.regs[11], 0, "{:?}: addi a1 was skipped", engine);
+        assert_eq!(cpu.regs[12], 5);
+    }
+}
+
+#[test]
+fn test_hooks_run_in_the_order_added_and_can_be_cleared() {
+    let mut cpu = cpu_with(PROGRAM, Engine::Interpreter);
+    let order = Rc::new(RefCell::new(Vec::new()));
+    for n in 0..3 {
+        let order = order.clone();
+        cpu.on_pre_step(move |_, step| {
+            if step.pc == 0 {
+                order.borrow_mut().push(n);
+            }
+        });
+    }
+    cpu.step().unwrap();
+    assert_eq!(*order.borrow(), [0, 1, 2]);
+
+    cpu.clear_step_hooks();
+    cpu.pc = 0;
+    cpu.step().unwrap();
+    assert_eq!(order.borrow().len(), 3);
+}
+
+#[test]
+fn test_hooks_can_count_instructions() {
+    let mut cpu = cpu_with(
+        "
+            addi  t0, zero, 10
+        top:
+            addi  t0, t0, -1
+            bne   t0, zero, top
+            ebreak
+        ",
+        Engine::BasicBlocks,
+    );
+    let branches = Rc::new(RefCell::new(0));
+    let counter = branches.clone();
+    cpu.on_post_step(move |_, step| {
+        if matches!(step.instruction, Instruction::Bne { .. }) {
+            *counter.borrow_mut() += 1;
+        }
+    });
+    cpu.run();
+
+    assert_eq!(*branches.borrow(), 10);
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::{self, HpmEvent, Privilege};
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu};
+
+use common::{image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source).engine(engine).build().unwrap()
+}
+
+/// Point counter `n` at `event`.
+fn select(cpu: &mut RiscvCpu, n: u16, event: HpmEvent) {
+    cpu.csrs.write(csr::MHPMEVENT3 + n - 3, event as u32);
+}
+
+fn counter(cpu: &RiscvCpu, n: u16) -> u32 {
+    cpu.csrs.read(csr::MHPMCOUNTER3 + n - 3)
+}
+
+/// Five loop iterations with a load and a store each.
+const LOOP: &str = "
+            addi  t0, zero, 5
+            lui   t1, 0x1
+    loop:   lw    t2, 0(t1)
+            sw    t2, 4(t1)
+            addi  t0, t0, -1
+            bne   t0, zero, loop
+            ebreak
+";
+
+// ── Events ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_loads_stores_and_branches_are_counted() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = cpu_with(LOOP, engine);
+        select(&mut cpu, 3, HpmEvent::Load);
+        select(&mut cpu, 4, HpmEvent::Store);
+        select(&mut cpu, 5, HpmEvent::Branch);
+        select(&mut cpu, 6, HpmEvent::BranchTaken);
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x18))
+        );
+
+        assert_eq!(counter(&cpu, 3), 5, "{:?}", engine);
+        assert_eq!(counter(&cpu, 4), 5, "{:?}", engine);
+        assert_eq!(counter(&cpu, 5), 5, "{:?}", engine);
+        assert_eq!(counter(&cpu, 6), 4, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_exceptions_and_interrupts_are_counted() {
+    // The handler skips whatever trapped, or returns to it after an
+    // interrupt.
+    let handler = "
+                csrrs t6, mcause, zero
+                blt   t6, zero, done
+                csrrs t6, mepc, zero
+                addi  t6, t6, 4
+                csrrw zero, mepc, t6
+        done:   mret
+    ";
+    let mut cpu = RiscvCpu::builder()
+        .image(
+            0,
+            image(
+                "
+                ecall
+                csrrw zero, mhartid, t0
+                addi  t3, zero, 8
+                csrrs zero, mie, t3
+                csrrsi zero, mstatus, 8
+                addi  a0, zero, 1
+                ebreak
+                ",
+            ),
+        )
+        .image(0x100, image(handler))
+        .guest_traps(true)
+        .build()
+        .unwrap();
+    cpu.csrs.write(csr::MTVEC, 0x100);
+    select(&mut cpu, 3, HpmEvent::Exception);
+    select(&mut cpu, 4, HpmEvent::Interrupt);
+
+    assert_eq!(cpu.run_until(0x14), ExitReason::ReachedPc(0x14));
+    cpu.raise_interrupt(Interrupt::MachineSoftware);
+    cpu.step().unwrap();
+    cpu.clear_interrupt(Interrupt::MachineSoftware);
+
+    assert_eq!(counter(&cpu, 3), 2);
+    assert_eq!(counter(&cpu, 4), 1);
+}
+
+#[test]
+fn test_two_counters_can_watch_the_same_event() {
+    let mut cpu = cpu_with(LOOP, Engine::Interpreter);
+    select(&mut cpu, 3, HpmEvent::Load);
+    select(&mut cpu, 31, HpmEvent::Load);
+    cpu.csrs.set(csr::MHPMCOUNTER3 + 28, 100);
+
+    cpu.run_steps(23);
+
+    assert_eq!(counter(&cpu, 3), 5);
+    assert_eq!(counter(&cpu, 31), 105);
+}
+
+// ── CSRs ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_unknown_events_count_nothing() {
+    let mut cpu = cpu_with(LOOP, Engine::Interpreter);
+    cpu.csrs.write(csr::MHPMEVENT3, 0x99);
+
+    assert_eq!(cpu.csrs.read(csr::MHPMEVENT3), 0);
+    cpu.run_steps(23);
+    assert_eq!(counter(&cpu, 3), 0);
+}
+
+#[test]
+fn test_guest_can_program_and_read_counters() {
+    let mut cpu = cpu_with(
+        "
+        addi  t0, zero, 2
+        csrrw zero, mhpmevent7, t0
+        lui   t1, 0x1
+        sw    zero, 0(t1)
+        sw    zero, 4(t1)
+        csrrs a0, hpmcounter7, zero
+        csrrs a1, mhpmcounter7h, zero
+        ",
+        Engine::Interpreter,
+    );
+
+    cpu.run_steps(7);
+
+    assert_eq!(cpu.regs[10], 2);
+    assert_eq!(cpu.regs[11], 0);
+}
+
+#[test]
+fn test_mcounteren_gates_user_access_per_counter() {
+    let source = "csrrs a0, hpmcounter4, zero\ncsrrs a0, hpmcounter5, zero";
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    cpu.csrs.write(csr::MCOUNTEREN, 1 << 4);
+    cpu.csrs.write(csr::SCOUNTEREN, u32::MAX);
+    cpu.set_privilege(Privilege::User);
+
+    assert!(cpu.step().is_ok());
+    assert_eq!(
+        cpu.step(),
+        Err(Exception::IllegalInstruction(assemble(source).unwrap()[1]))
+    );
+}
@@ -0,0 +1,379 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::mmu::{PTE_A, PTE_D, PTE_R, PTE_U, PTE_V, PTE_W, PTE_X};
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+use common::{image, image_at, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const STVEC: u32 = 0x800;
+const VSTVEC: u32 = 0xC00;
+
+/// A hart with guest traps on, `source` at address 0, and `guest` at 0x100
+/// if there is one. Starts in HS-mode.
+fn cpu_with(source: &str, guest: &str) -> RiscvCpu {
+    let mut builder = RiscvCpu::builder()
+        .ram_size(0x10000)
+        .image(0, image(source))
+        .guest_traps(true);
+    if !guest.is_empty() {
+        builder = builder.image(0x100, image_at(0x100, guest));
+    }
+    let mut cpu = builder.build().unwrap();
+    cpu.csrs.write(csr::STVEC, STVEC);
+    cpu.csrs.write(csr::VSTVEC, VSTVEC);
+    cpu.set_privilege(Privilege::Supervisor);
+    cpu
+}
+
+/// The same, but already running the guest in VS-mode.
+fn guest_with(source: &str) -> RiscvCpu {
+    let mut cpu = cpu_with(source, "");
+    cpu.set_virtualized(true);
+    cpu
+}
+
+fn pte(pa: u32, flags: u32) -> u32 {
+    ((pa >> 12) << 10) | flags
+}
+
+// ── CSRs ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_hypervisor_csrs() {
+    let mut cpu = cpu_with("", "");
+    cpu.csrs.write(csr::HIDELEG, u32::MAX);
+    assert_eq!(cpu.csrs.read(csr::HIDELEG), 0x444, "only VS interrupts");
+    cpu.csrs.write(csr::HEDELEG, u32::MAX);
+    assert_eq!(
+        cpu.csrs.read(csr::HEDELEG) & 0xF << 20,
+        0,
+        "guest page faults stay in HS-mode"
+    );
+
+    cpu.csrs.write(csr::HVIP, csr::MIP_VSTIP | csr::MIP_MTIP);
+    assert_eq!(cpu.csrs.read(csr::MIP), csr::MIP_VSTIP);
+    assert_eq!(cpu.csrs.read(csr::HIP), csr::MIP_VSTIP);
+    cpu.csrs.write(csr::HIE, u32::MAX);
+    assert_eq!(cpu.csrs.read(csr::MIE), 0x444);
+
+    cpu.csrs.write(csr::HIDELEG, csr::MIP_VSTIP);
+    assert_eq!(cpu.csrs.read(csr::VSIP), csr::MIP_STIP, "shifted down");
+    assert_eq!(cpu.csrs.read(csr::VSIE), csr::MIP_STIP);
+}
+
+#[test]
+fn test_guest_supervisor_csrs_are_redirected() {
+    let mut cpu = guest_with(
+        "
+        addi  t0, zero, 42
+        csrrw zero, sscratch, t0
+        csrrsi zero, sstatus, 2
+        ",
+    );
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.csrs.read(csr::VSSCRATCH), 42);
+    assert_eq!(cpu.csrs.read(csr::SSCRATCH), 0);
+    assert_ne!(cpu.csrs.read(csr::VSSTATUS) & csr::MSTATUS_SIE, 0);
+    assert_eq!(cpu.csrs.read(csr::MSTATUS) & csr::MSTATUS_SIE, 0);
+}
+
+#[test]
+fn test_guest_time_is_offset_by_htimedelta() {
+    let mut cpu = guest_with("csrrs a0, time, zero");
+    cpu.csrs.write(csr::MCOUNTEREN, csr::COUNTEREN_TM);
+    cpu.csrs.write(csr::HCOUNTEREN, csr::COUNTEREN_TM);
+    cpu.csrs.write(csr::HTIMEDELTA, 1000);
+
+    cpu.step().unwrap();
+
+    assert!(cpu.regs[10] >= 1000);
+}
+
+// ── Virtual-instruction exceptions ────────────────────────────────────────────
+
+#[test]
+fn test_virtual_instruction_exceptions() {
+    let cases = [
+        ("csrrs a0, hstatus, zero", 0, true),
+        ("hlv.w a0, (a1)", 0, true),
+        ("hfence.gvma zero, zero", 0, true),
+        ("wfi", csr::HSTATUS_VTW, true),
+        ("sret", csr::HSTATUS_VTSR, true),
+        ("csrrs a0, satp, zero", csr::HSTATUS_VTVM, true),
+        ("csrrs a0, mstatus, zero", 0, false),
+        ("mret", 0, false),
+    ];
+
+    for (source, hstatus, virtual_instruction) in cases {
+        let mut cpu = guest_with(source);
+        cpu.csrs.write(csr::HSTATUS, hstatus);
+        let word = assemble(source).unwrap()[0];
+        let expected = match virtual_instruction {
+            true => Exception::VirtualInstruction(word),
+            false => Exception::IllegalInstruction(word),
+        };
+
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.privilege(), Privilege::Machine, "{}", source);
+        assert!(!cpu.virtualized(), "{}", source);
+        assert_eq!(cpu.csrs.read(csr::MCAUSE), expected.cause(), "{}", source);
+        assert_ne!(
+            cpu.csrs.read(csr::MSTATUSH) & csr::MSTATUSH_MPV,
+            0,
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_hypervisor_instructions_need_hs_mode() {
+    let source = "hlv.w a0, (a1)";
+    let word = assemble(source).unwrap()[0];
+
+    let mut cpu = cpu_with(source, "");
+    cpu.set_privilege(Privilege::User);
+    cpu.step().unwrap();
+    assert_eq!(
+        cpu.csrs.read(csr::MCAUSE),
+        Exception::IllegalInstruction(word).cause()
+    );
+
+    let mut cpu = cpu_with(source, "");
+    cpu.set_privilege(Privilege::User);
+    cpu.csrs.write(csr::HSTATUS, csr::HSTATUS_HU);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 4, "HU lets U-mode use HLV");
+}
+
+// ── Two-stage translation ─────────────────────────────────────────────────────
+
+/// The G-stage identity-maps the first 4 MiB of guest physical memory. The
+/// guest maps 0x4000_0000 to guest physical 0x5000, and 0x4000_1000 to
+/// 0x80_0000, which the G-stage doesn't map.
+fn map_guest(cpu: &mut RiscvCpu) {
+    const G_ROOT: u32 = 0x4000;
+    const VS_ROOT: u32 = 0x1000;
+    const VS_LEAVES: u32 = 0x2000;
+
+    let all = PTE_V | PTE_R | PTE_W | PTE_X | PTE_A | PTE_D;
+    cpu.bus
+        .write(G_ROOT, MemSize::Word, pte(0, all | PTE_U))
+        .unwrap();
+    cpu.bus
+        .write(VS_ROOT + 0x100 * 4, MemSize::Word, pte(VS_LEAVES, PTE_V))
+        .unwrap();
+    cpu.bus
+        .write(VS_LEAVES, MemSize::Word, pte(0x5000, all))
+        .unwrap();
+    cpu.bus
+        .write(VS_LEAVES + 4, MemSize::Word, pte(0x80_0000, all))
+        .unwrap();
+
+    cpu.csrs
+        .write(csr::HGATP, csr::HGATP_SV32X4 | (G_ROOT >> 12));
+    cpu.csrs.write(csr::VSATP, csr::SATP_SV32 | (VS_ROOT >> 12));
+}
+
+#[test]
+fn test_hlv_and_hsv_translate_in_two_stages() {
+    let mut cpu = cpu_with(
+        "
+        lui   t0, 0x40000
+        hlv.w a0, (t0)
+        addi  a0, a0, 1
+        hsv.w a0, (t0)
+        ",
+        "",
+    );
+    map_guest(&mut cpu);
+    cpu.csrs.write(csr::HSTATUS, csr::HSTATUS_SPVP);
+    cpu.bus.write(0x5000, MemSize::Word, 41).unwrap();
+
+    cpu.run_steps(4);
+
+    assert_eq!(cpu.pc, 16);
+    assert_eq!(cpu.regs[10], 42);
+    assert_eq!(cpu.bus.read(0x5000, MemSize::Word), Some(42));
+}
+
+#[test]
+fn test_guest_page_fault_sets_htval() {
+    let mut cpu = cpu_with(
+        "
+        lui   t0, 0x40001
+        hlv.w a0, (t0)
+        ",
+        "",
+    );
+    map_guest(&mut cpu);
+    cpu.csrs.write(csr::HSTATUS, csr::HSTATUS_SPVP);
+    cpu.csrs.write(csr::MEDELEG, 1 << 21);
+
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.pc, STVEC);
+    assert_eq!(cpu.csrs.read(csr::SCAUSE), 21);
+    assert_eq!(cpu.csrs.read(csr::STVAL), 0x4000_1000);
+    assert_eq!(cpu.csrs.read(csr::HTVAL), 0x80_0000 >> 2);
+    let hstatus = cpu.csrs.read(csr::HSTATUS);
+    assert_ne!(hstatus & csr::HSTATUS_GVA, 0, "HLV's address is a guest's");
+    assert_eq!(hstatus & csr::HSTATUS_SPV, 0, "came from V=0");
+}
+
+#[test]
+fn test_guest_fetches_go_through_both_stages() {
+    let mut cpu = cpu_with("", "");
+    map_guest(&mut cpu);
+    cpu.bus
+        .write(
+            0x5000,
+            MemSize::Word,
+            assemble("addi a0, zero, 7").unwrap()[0],
+        )
+        .unwrap();
+    cpu.set_virtualized(true);
+    cpu.pc = 0x4000_0000;
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.regs[10], 7);
+    assert_eq!(cpu.pc, 0x4000_0004);
+}
+
+// ── Traps and returns ─────────────────────────────────────────────────────────
+
+#[test]
+fn test_sret_enters_the_guest_and_its_traps_return_to_hs() {
+    let mut cpu = cpu_with("sret", "ecall");
+    cpu.csrs
+        .write(csr::HSTATUS, csr::HSTATUS_SPV | csr::HSTATUS_SPVP);
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_SPP);
+    cpu.csrs.write(csr::SEPC, 0x100);
+    cpu.csrs.write(
+        csr::MEDELEG,
+        1 << Exception::VirtualSupervisorEnvironmentCall.cause(),
+    );
+
+    cpu.step().unwrap();
+    assert!(cpu.virtualized());
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+    assert_eq!(cpu.csrs.read(csr::HSTATUS) & csr::HSTATUS_SPV, 0);
+
+    cpu.step().unwrap();
+    assert!(!cpu.virtualized());
+    assert_eq!(cpu.pc, STVEC);
+    assert_eq!(cpu.csrs.read(csr::SCAUSE), 10);
+    assert_eq!(cpu.csrs.read(csr::SEPC), 0x100);
+    let hstatus = cpu.csrs.read(csr::HSTATUS);
+    assert_ne!(hstatus & csr::HSTATUS_SPV, 0);
+    assert_ne!(hstatus & csr::HSTATUS_SPVP, 0);
+}
+
+#[test]
+fn test_hedeleg_sends_guest_traps_to_vs_mode() {
+    let mut cpu = program("addi zero, zero, 0\necall")
+        .image(VSTVEC, image_at(VSTVEC, "sret"))
+        .guest_traps(true)
+        .build()
+        .unwrap();
+    cpu.csrs.write(csr::VSTVEC, VSTVEC);
+    let ecall = 1 << Exception::UserEnvironmentCall.cause();
+    cpu.csrs.write(csr::MEDELEG, ecall);
+    cpu.csrs.write(csr::HEDELEG, ecall);
+    cpu.set_privilege(Privilege::User);
+    cpu.set_virtualized(true);
+
+    cpu.run_steps(2);
+
+    assert!(cpu.virtualized());
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+    assert_eq!(cpu.pc, VSTVEC);
+    assert_eq!(cpu.csrs.read(csr::VSCAUSE), 8);
+    assert_eq!(cpu.csrs.read(csr::VSEPC), 4);
+    assert_eq!(cpu.csrs.read(csr::SCAUSE), 0, "HS-mode never saw it");
+
+    cpu.step().unwrap();
+    assert!(cpu.virtualized(), "the guest's SRET keeps V");
+    assert_eq!(cpu.privilege(), Privilege::User);
+    assert_eq!(cpu.pc, 4);
+}
+
+#[test]
+fn test_mret_restores_v_from_mpv() {
+    let mut cpu = cpu_with("mret", "");
+    cpu.set_privilege(Privilege::Machine);
+    cpu.csrs
+        .write(csr::MSTATUS, (Privilege::Supervisor as u32) << 11);
+    cpu.csrs.write(csr::MSTATUSH, csr::MSTATUSH_MPV);
+    cpu.csrs.write(csr::MEPC, 0x100);
+
+    cpu.step().unwrap();
+
+    assert!(cpu.virtualized());
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+    assert_eq!(cpu.csrs.read(csr::MSTATUSH) & csr::MSTATUSH_MPV, 0);
+}
+
+#[test]
+fn test_vs_interrupts_go_to_the_guest() {
+    let mut cpu = guest_with("addi zero, zero, 0\naddi zero, zero, 0");
+    cpu.csrs.write(csr::HIDELEG, csr::MIP_VSTIP);
+    cpu.csrs.write(csr::HIE, csr::MIP_VSTIP);
+    cpu.raise_interrupt(Interrupt::VirtualSupervisorTimer);
+
+    assert_eq!(cpu.pending_interrupt(), None, "the guest's SIE is clear");
+    cpu.step().unwrap();
+
+    cpu.csrs.write(csr::VSSTATUS, csr::MSTATUS_SIE);
+    assert_eq!(
+        cpu.pending_interrupt(),
+        Some(Interrupt::VirtualSupervisorTimer)
+    );
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.pc, VSTVEC);
+    assert_eq!(cpu.csrs.read(csr::VSCAUSE), 0x8000_0005, "as an STI");
+    assert_eq!(cpu.csrs.read(csr::VSEPC), 4);
+
+    cpu.set_virtualized(false);
+    assert_eq!(cpu.pending_interrupt(), None, "only while V=1");
+}
+
+// ── Encoding ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_hypervisor_assembly_and_disassembly() {
+    let cases = [
+        ("hlv.w a0, (t0)", 0x6802_C573, "hlv.w x10, (x5)"),
+        ("hlv.bu a0, (t0)", 0x6012_C573, "hlv.bu x10, (x5)"),
+        ("hlvx.hu a0, (t0)", 0x6432_C573, "hlvx.hu x10, (x5)"),
+        ("hsv.h a0, (t0)", 0x66A2_C073, "hsv.h x10, (x5)"),
+        ("hfence.vvma zero, zero", 0x2200_0073, "hfence.vvma x0, x0"),
+        ("hfence.gvma zero, zero", 0x6200_0073, "hfence.gvma x0, x0"),
+    ];
+
+    for (source, word, text) in cases {
+        assert_eq!(assemble(source).unwrap(), vec![word], "{}", source);
+        assert_eq!(decode(word).unwrap().to_string(), text, "{}", source);
+    }
+}
+
+#[test]
+fn test_snapshot_keeps_v() {
+    let mut cpu = guest_with("");
+    let snapshot = cpu.save_snapshot();
+    assert!(snapshot.virt);
+
+    cpu.set_virtualized(false);
+    cpu.restore(&snapshot).unwrap();
+    assert!(cpu.virtualized());
+}
@@ -1,33 +1,5 @@
 use riscv_emulator_rust::RiscvCpu;
-
-/// Encode a generic I-type ALU instruction (ADDI/SLTI/SLTIU/XORI/ORI/ANDI).
-///
-/// imm: 12-bit signed immediate
-/// rs1: source register
-/// funct3: instruction funct3
-/// rd: destination register
-/// opcode: usually 0x13 for ALU-immediate
-fn encode_itype_imm(imm: i32, rs1: u8, funct3: u8, rd: u8, opcode: u8) -> u32 {
-    let imm12 = (imm as i32) & 0xFFF; // take low 12 bits (two's complement)
-    ((imm12 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | ((rd as u32) << 7)
-        | (opcode as u32)
-}
-
-/// Encode shift-immediate instructions (SLLI/SRLI/SRAI).
-///
-/// shamt: shift amount (0–31 for RV32)
-/// rs1: source register
-/// rd: destination register
-/// funct3: 0b001 for SLLI, 0b101 for SRLI/SRAI
-/// arith: true for SRAI (funct7=0b0100000), false for SLLI/SRLI (funct7=0)
-fn encode_shift_itype(shamt: u8, rs1: u8, rd: u8, funct3: u8, arith: bool) -> u32 {
-    let funct7 = if arith { 0b0100000u32 } else { 0b0000000u32 };
-    let imm12 = (funct7 << 5) | (shamt as u32);
-    (imm12 << 20) | ((rs1 as u32) << 15) | ((funct3 as u32) << 12) | ((rd as u32) << 7) | 0x13u32 // opcode for OP-IMM
-}
+use riscv_emulator_rust::encode::{itype, slli, srai, srli};
 
 mod addi {
     use super::*;
@@ -38,7 +10,7 @@ mod addi {
         cpu.regs[1] = 10;
 
         // addi x2, x1, 5  (x2 = 10 + 5 = 15)
-        let instruction = encode_itype_imm(5, 1, 0b000, 2, 0x13);
+        let instruction = itype(5, 1, 0b000, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -52,7 +24,7 @@ mod addi {
         cpu.regs[1] = 10;
 
         // addi x2, x1, -1  (x2 = 10 + (-1) = 9)
-        let instruction = encode_itype_imm(-1, 1, 0b000, 2, 0x13);
+        let instruction = itype(-1, 1, 0b000, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -65,7 +37,7 @@ mod addi {
         cpu.regs[3] = 0x1234_5678;
 
         // addi x4, x3, 0  (MV pseudo-op)
-        let instruction = encode_itype_imm(0, 3, 0b000, 4, 0x13);
+        let instruction = itype(0, 3, 0b000, 4, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -78,7 +50,7 @@ mod addi {
         cpu.regs[1] = 123;
 
         // addi x0, x1, 5  (must be ignored; x0 always zero)
-        let instruction = encode_itype_imm(5, 1, 0b000, 0, 0x13);
+        let instruction = itype(5, 1, 0b000, 0, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -91,7 +63,7 @@ mod addi {
         cpu.regs[1] = 0xFFFF_FFFF;
 
         // addi x2, x1, 1  (0xFFFF_FFFF + 1 wraps to 0)
-        let instruction = encode_itype_imm(1, 1, 0b000, 2, 0x13);
+        let instruction = itype(1, 1, 0b000, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -108,7 +80,7 @@ mod slti {
         cpu.regs[1] = 5;
 
         // slti x2, x1, 10  (5 < 10 => x2 = 1)
-        let instruction = encode_itype_imm(10, 1, 0b010, 2, 0x13);
+        let instruction = itype(10, 1, 0b010, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -121,7 +93,7 @@ mod slti {
         cpu.regs[1] = 20;
 
         // slti x2, x1, 10  (20 < 10? false => x2 = 0)
-        let instruction = encode_itype_imm(10, 1, 0b010, 2, 0x13);
+        let instruction = itype(10, 1, 0b010, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -134,7 +106,7 @@ mod slti {
         cpu.regs[1] = -5i32 as u32;
 
         // slti x2, x1, 0  (-5 < 0 => x2 = 1, signed compare)
-        let instruction = encode_itype_imm(0, 1, 0b010, 2, 0x13);
+        let instruction = itype(0, 1, 0b010, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -147,7 +119,7 @@ mod slti {
         cpu.regs[1] = 10;
 
         // slti x2, x1, 10  (10 < 10? false => x2 = 0)
-        let instruction = encode_itype_imm(10, 1, 0b010, 2, 0x13);
+        let instruction = itype(10, 1, 0b010, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -164,7 +136,7 @@ mod sltiu {
         cpu.regs[1] = 5;
 
         // sltiu x2, x1, 10  (5 < 10 => x2 = 1, unsigned)
-        let instruction = encode_itype_imm(10, 1, 0b011, 2, 0x13);
+        let instruction = itype(10, 1, 0b011, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -177,7 +149,7 @@ mod sltiu {
         cpu.regs[1] = 0xFFFF_FFFF; // -1 as signed, max as unsigned
 
         // sltiu x2, x1, 0  (0xFFFF_FFFF < 0 ? false => 0, unsigned)
-        let instruction = encode_itype_imm(0, 1, 0b011, 2, 0x13);
+        let instruction = itype(0, 1, 0b011, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -190,7 +162,7 @@ mod sltiu {
         cpu.regs[1] = 10;
 
         // sltiu x2, x1, 10  (10 < 10? false => 0, unsigned)
-        let instruction = encode_itype_imm(10, 1, 0b011, 2, 0x13);
+        let instruction = itype(10, 1, 0b011, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -203,7 +175,7 @@ mod sltiu {
         cpu.regs[1] = 0b1010;
 
         // xori x2, x1, 0b0110  => 0b1010 ^ 0b0110 = 0b1100 (12)
-        let instruction = encode_itype_imm(0b0110, 1, 0b100, 2, 0x13);
+        let instruction = itype(0b0110, 1, 0b100, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -216,7 +188,7 @@ mod sltiu {
         cpu.regs[1] = 0x1234_5678;
 
         // xori x2, x1, -1  => bitwise NOT
-        let instruction = encode_itype_imm(-1, 1, 0b100, 2, 0x13);
+        let instruction = itype(-1, 1, 0b100, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -233,7 +205,7 @@ mod ori {
         cpu.regs[1] = 0b1001;
 
         // ori x2, x1, 0b0110 => 0b1001 | 0b0110 = 0b1111
-        let instruction = encode_itype_imm(0b0110, 1, 0b110, 2, 0x13);
+        let instruction = itype(0b0110, 1, 0b110, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -246,7 +218,7 @@ mod ori {
         cpu.regs[1] = 0xDEAD_BEEF;
 
         // ori x2, x1, 0 => x2 = x1
-        let instruction = encode_itype_imm(0, 1, 0b110, 2, 0x13);
+        let instruction = itype(0, 1, 0b110, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -259,7 +231,7 @@ mod ori {
         cpu.regs[1] = 0x0000_0000;
 
         // ori x2, x1, -1 (sign-extended all-ones => result is all-ones)
-        let instruction = encode_itype_imm(-1, 1, 0b110, 2, 0x13);
+        let instruction = itype(-1, 1, 0b110, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -276,7 +248,7 @@ mod andi {
         cpu.regs[1] = 0b1101;
 
         // andi x2, x1, 0b0110 => 0b1101 & 0b0110 = 0b0100
-        let instruction = encode_itype_imm(0b0110, 1, 0b111, 2, 0x13);
+        let instruction = itype(0b0110, 1, 0b111, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -289,7 +261,7 @@ mod andi {
         cpu.regs[1] = 0xFFFF_FFFF;
 
         // andi x2, x1, 0x0FF  => low 8 bits set, others cleared
-        let instruction = encode_itype_imm(0x0FF, 1, 0b111, 2, 0x13);
+        let instruction = itype(0x0FF, 1, 0b111, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -302,7 +274,7 @@ mod andi {
         cpu.regs[1] = 0xDEAD_BEEF;
 
         // andi x2, x1, 0 => 0 & anything = 0
-        let instruction = encode_itype_imm(0, 1, 0b111, 2, 0x13);
+        let instruction = itype(0, 1, 0b111, 2, 0x13);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -319,7 +291,7 @@ mod slli {
         cpu.regs[1] = 0b1;
 
         // slli x2, x1, 3  => 1 << 3 = 8
-        let instruction = encode_shift_itype(3, 1, 2, 0b001, false);
+        let instruction = slli(2, 1, 3);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -332,7 +304,7 @@ mod slli {
         cpu.regs[1] = 0x1234_5678;
 
         // slli x2, x1, 0  => no change
-        let instruction = encode_shift_itype(0, 1, 2, 0b001, false);
+        let instruction = slli(2, 1, 0);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -345,7 +317,7 @@ mod slli {
         cpu.regs[1] = 1;
 
         // slli x2, x1, 31  => 1 << 31 = 0x8000_0000
-        let instruction = encode_shift_itype(31, 1, 2, 0b001, false);
+        let instruction = slli(2, 1, 31);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -358,7 +330,7 @@ mod slli {
         cpu.regs[1] = 0xFFFF_FFFF;
 
         // slli x2, x1, 1  => 0xFFFF_FFFF << 1 = 0xFFFF_FFFE (low bit drops)
-        let instruction = encode_shift_itype(1, 1, 2, 0b001, false);
+        let instruction = slli(2, 1, 1);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -375,7 +347,7 @@ mod srli {
         cpu.regs[1] = 0b1000;
 
         // srli x2, x1, 3  => 0b1000 >> 3 = 0b1
-        let instruction = encode_shift_itype(3, 1, 2, 0b101, false);
+        let instruction = srli(2, 1, 3);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -388,7 +360,7 @@ mod srli {
         cpu.regs[1] = 0x8000_0000; // MSB set
 
         // srli x2, x1, 1 => logical shift, new MSB must be 0
-        let instruction = encode_shift_itype(1, 1, 2, 0b101, false);
+        let instruction = srli(2, 1, 1);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -401,7 +373,7 @@ mod srli {
         cpu.regs[1] = 0x8000_0000; // only MSB set
 
         // srli x2, x1, 31  => 0x8000_0000 >> 31 = 1 (logical zero-fill)
-        let instruction = encode_shift_itype(31, 1, 2, 0b101, false);
+        let instruction = srli(2, 1, 31);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -414,7 +386,7 @@ mod srli {
         cpu.regs[1] = 0xFFFF_FFFF;
 
         // srli x2, x1, 4  => 0x0FFF_FFFF
-        let instruction = encode_shift_itype(4, 1, 2, 0b101, false);
+        let instruction = srli(2, 1, 4);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -432,7 +404,7 @@ mod srai {
         cpu.regs[1] = 0xFFFF_FFF6;
 
         // srai x2, x1, 1  => -10 >> 1 = -5 (0xFFFFFFFB)
-        let instruction = encode_shift_itype(1, 1, 2, 0b101, true);
+        let instruction = srai(2, 1, 1);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -445,7 +417,7 @@ mod srai {
         cpu.regs[1] = 0x0000_0008;
 
         // srai x2, x1, 1  => 8 >> 1 = 4
-        let instruction = encode_shift_itype(1, 1, 2, 0b101, true);
+        let instruction = srai(2, 1, 1);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -458,7 +430,7 @@ mod srai {
         cpu.regs[1] = 0x8000_0000; // -2147483648
 
         // srai x2, x1, 4  => arithmetic shift keeps sign bit set
-        let instruction = encode_shift_itype(4, 1, 2, 0b101, true);
+        let instruction = srai(2, 1, 4);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -471,7 +443,7 @@ mod srai {
         cpu.regs[1] = 0xDEAD_BEEF;
 
         // srai x2, x1, 0  => no shift, value unchanged
-        let instruction = encode_shift_itype(0, 1, 2, 0b101, true);
+        let instruction = srai(2, 1, 0);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -484,7 +456,7 @@ mod srai {
         cpu.regs[1] = 0x8000_0000; // most negative value
 
         // srai x2, x1, 31  => all sign bits => 0xFFFF_FFFF (-1)
-        let instruction = encode_shift_itype(31, 1, 2, 0b101, true);
+        let instruction = srai(2, 1, 31);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -497,7 +469,7 @@ mod srai {
         cpu.regs[1] = 0x7FFF_FFFF; // max positive
 
         // srai x2, x1, 31  => all zeros (sign bit is 0)
-        let instruction = encode_shift_itype(31, 1, 2, 0b101, true);
+        let instruction = srai(2, 1, 31);
 
         cpu.handle_itype(instruction).unwrap();
 
@@ -514,7 +486,7 @@ mod load {
         cpu.bus[0x100] = 0x42;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(0, 1, 0b000, 2, 0x03);
+        let instruction = itype(0, 1, 0b000, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0x42);
@@ -526,7 +498,7 @@ mod load {
         cpu.bus[0x100] = 0xFE;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(0, 1, 0b000, 2, 0x03);
+        let instruction = itype(0, 1, 0b000, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0xFFFF_FFFE);
@@ -538,7 +510,7 @@ mod load {
         cpu.bus[0x100] = 0xFE;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(0, 1, 0b100, 2, 0x03);
+        let instruction = itype(0, 1, 0b100, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0x0000_00FE);
@@ -551,7 +523,7 @@ mod load {
         cpu.bus[0x101] = 0x12;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(0, 1, 0b001, 2, 0x03);
+        let instruction = itype(0, 1, 0b001, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0x1234);
@@ -564,7 +536,7 @@ mod load {
         cpu.bus[0x101] = 0x82;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(0, 1, 0b001, 2, 0x03);
+        let instruction = itype(0, 1, 0b001, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0xFFFF_8234);
@@ -577,7 +549,7 @@ mod load {
         cpu.bus[0x101] = 0x82;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(0, 1, 0b101, 2, 0x03);
+        let instruction = itype(0, 1, 0b101, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0x0000_8234);
@@ -592,7 +564,7 @@ mod load {
         cpu.bus[0x103] = 0x12;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(0, 1, 0b010, 2, 0x03);
+        let instruction = itype(0, 1, 0b010, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0x1234_5678);
@@ -607,7 +579,7 @@ mod load {
         cpu.bus[0x107] = 0xDD;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(4, 1, 0b010, 2, 0x03);
+        let instruction = itype(4, 1, 0b010, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0xDDCC_BBAA);
@@ -622,7 +594,7 @@ mod load {
         cpu.bus[0x0FF] = 0x44;
         cpu.regs[1] = 0x100;
 
-        let instruction = encode_itype_imm(-4, 1, 0b010, 2, 0x03);
+        let instruction = itype(-4, 1, 0b010, 2, 0x03);
         cpu.handle_load(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0x4433_2211);
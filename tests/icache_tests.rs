@@ -0,0 +1,71 @@
+mod common;
+
+use riscv_emulator_rust::ExitReason;
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::Privilege;
+use riscv_emulator_rust::trap::Exception;
+
+use common::cpu_with;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn encoding(source: &str) -> u32 {
+    assemble(source).unwrap()[0]
+}
+
+// ── Coherence ─────────────────────────────────────────────────────────────────
+
+/// The loop body runs once as `addi a0, a0, 1`, then patches itself into
+/// `addi a0, a0, 100` and runs again.
+#[test]
+fn test_self_modifying_code_sees_the_new_instruction() {
+    let patch = encoding("addi a0, a0, 100");
+    let mut cpu = cpu_with(&format!(
+        "
+                lui   t0, {upper:#x}
+                addi  t0, t0, {lower}
+                addi  t1, zero, 2
+        body:   addi  a0, a0, 1
+                sw    t0, 12(zero)
+                fence.i
+                addi  t1, t1, -1
+                bne   t1, zero, body
+                ebreak
+        ",
+        upper = (patch + 0x800) >> 12,
+        lower = ((patch & 0xFFF) as i32) << 20 >> 20,
+    ));
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(0x20))
+    );
+    assert_eq!(cpu.regs[10], 1 + 100);
+}
+
+#[test]
+fn test_host_patch_between_runs_is_picked_up() {
+    let mut cpu = cpu_with("top: addi a0, a0, 1\njal zero, top");
+
+    cpu.run_steps(3);
+    assert_eq!(cpu.regs[10], 2);
+
+    cpu.bus[0..4].copy_from_slice(&encoding("addi a0, a0, 10").to_le_bytes());
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.regs[10], 12, "no fence.i needed for host writes");
+}
+
+#[test]
+fn test_cached_instruction_still_checks_privilege() {
+    let mut cpu = cpu_with("mret");
+    cpu.step().unwrap();
+
+    cpu.pc = 0;
+    cpu.set_privilege(Privilege::User);
+
+    assert_eq!(
+        cpu.step(),
+        Err(Exception::IllegalInstruction(encoding("mret")))
+    );
+}
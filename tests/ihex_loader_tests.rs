@@ -0,0 +1,149 @@
+use riscv_emulator_rust::RiscvCpu;
+use riscv_emulator_rust::loader::IntelHex;
+
+// ── Helper: build Intel HEX records ──────────────────────────────────────────
+
+fn record(kind: u8, offset: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&offset.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes.push(sum.wrapping_neg());
+
+    let digits: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(":{}\n", digits)
+}
+
+const EOF: &str = ":00000001FF\n";
+
+// ── Parsing ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_parse_data_records() {
+    // `li x1, 10; li x2, 20`
+    let text = format!(":0800000093000A001301400106\n{}", EOF);
+
+    let hex = IntelHex::parse(&text).unwrap();
+
+    assert_eq!(
+        hex.chunks,
+        [(0, vec![0x93, 0x00, 0x0A, 0x00, 0x13, 0x01, 0x40, 0x01])]
+    );
+    assert_eq!(hex.entry, None);
+}
+
+#[test]
+fn test_consecutive_records_are_merged() {
+    let text = [
+        record(0, 0x10, &[1, 2]),
+        record(0, 0x12, &[3, 4]),
+        record(0, 0x20, &[5]),
+        EOF.to_string(),
+    ]
+    .concat();
+
+    let hex = IntelHex::parse(&text).unwrap();
+
+    assert_eq!(hex.chunks, [(0x10, vec![1, 2, 3, 4]), (0x20, vec![5])]);
+}
+
+#[test]
+fn test_extended_address_records() {
+    let text = [
+        record(4, 0, &[0x80, 0x00]),
+        record(0, 0x0100, &[0xAA]),
+        record(2, 0, &[0x12, 0x34]),
+        record(0, 0x0002, &[0xBB]),
+        record(5, 0, &[0x80, 0x00, 0x01, 0x00]),
+        EOF.to_string(),
+    ]
+    .concat();
+
+    let hex = IntelHex::parse(&text).unwrap();
+
+    assert_eq!(
+        hex.chunks,
+        [(0x8000_0100, vec![0xAA]), (0x1_2342, vec![0xBB])]
+    );
+    assert_eq!(hex.entry, Some(0x8000_0100));
+}
+
+#[test]
+fn test_start_segment_address() {
+    let text = [record(3, 0, &[0x10, 0x00, 0x00, 0x20]), EOF.to_string()].concat();
+
+    assert_eq!(IntelHex::parse(&text).unwrap().entry, Some(0x1_0020));
+}
+
+#[test]
+fn test_records_after_end_of_file_are_ignored() {
+    let text = [EOF.to_string(), record(0, 0, &[1]), "garbage\n".to_string()].concat();
+
+    assert!(IntelHex::parse(&text).unwrap().chunks.is_empty());
+}
+
+#[test]
+fn test_rejects_malformed_records() {
+    for text in [
+        "0000000001FF\n",
+        ":00000001F\n",
+        ":0000000GFF\n",
+        ":00000001FE\n",
+        ":01000000FF\n",
+        ":00000006FA\n",
+        ":0100000400FB\n",
+    ] {
+        assert!(IntelHex::parse(text).is_err(), "{:?}", text);
+    }
+
+    let error = IntelHex::parse(&format!("{}:00000001FE\n", record(0, 0, &[1]))).unwrap_err();
+    assert!(error.contains("line 2"), "{}", error);
+}
+
+// ── Loading ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_load_writes_memory_and_sets_pc() {
+    let mut cpu = RiscvCpu::new(1024);
+    let code = 0x00a00093u32.to_le_bytes();
+    let text = [
+        record(0, 0x200, &code),
+        record(5, 0, &[0, 0, 0x02, 0x00]),
+        EOF.to_string(),
+    ]
+    .concat();
+
+    cpu.load_ihex(&text).unwrap();
+
+    assert_eq!(cpu.pc, 0x200);
+    assert_eq!(&cpu.bus[0x200..0x204], &code[..]);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs[1], 10);
+}
+
+#[test]
+fn test_load_without_start_address_keeps_pc() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.pc = 0x40;
+
+    cpu.load_ihex(&[record(0, 0x100, &[1, 2, 3]), EOF.to_string()].concat())
+        .unwrap();
+
+    assert_eq!(cpu.pc, 0x40);
+    assert_eq!(&cpu.bus[0x100..0x103], &[1, 2, 3]);
+}
+
+#[test]
+fn test_load_rejects_data_outside_memory() {
+    let mut cpu = RiscvCpu::new(1024);
+    let text = [
+        record(4, 0, &[0x00, 0x01]),
+        record(0, 0, &[1]),
+        EOF.to_string(),
+    ]
+    .concat();
+
+    assert!(cpu.load_ihex(&text).is_err());
+}
@@ -1,94 +1,12 @@
 use riscv_emulator_rust::RiscvCpu;
-
-// ── Instruction encoders ──────────────────────────────────────────────────────
-
-fn encode_rtype(funct7: u8, rs2: u8, rs1: u8, funct3: u8, rd: u8) -> u32 {
-    ((funct7 as u32) << 25)
-        | ((rs2 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | ((rd as u32) << 7)
-        | 0x33
-}
-
-fn encode_itype(imm: i32, rs1: u8, funct3: u8, rd: u8) -> u32 {
-    let imm12 = (imm as i32) & 0xFFF;
-    ((imm12 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | ((rd as u32) << 7)
-        | 0x13
-}
-
-fn encode_load(imm: i32, rs1: u8, funct3: u8, rd: u8) -> u32 {
-    let imm12 = (imm as i32) & 0xFFF;
-    ((imm12 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | ((rd as u32) << 7)
-        | 0x03
-}
-
-fn encode_btype(imm: i32, rs1: u8, rs2: u8, funct3: u8) -> u32 {
-    let imm = imm as u32;
-    let b12 = (imm >> 12) & 0x1;
-    let b11 = (imm >> 11) & 0x1;
-    let b10_5 = (imm >> 5) & 0x3F;
-    let b4_1 = (imm >> 1) & 0xF;
-
-    (b12 << 31)
-        | (b10_5 << 25)
-        | ((rs2 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | (b4_1 << 8)
-        | (b11 << 7)
-        | 0x63
-}
-
-fn encode_stype(imm: i32, rs2: u8, rs1: u8, funct3: u8) -> u32 {
-    let imm11_5 = ((imm >> 5) & 0x7F) as u32;
-    let imm4_0 = (imm & 0x1F) as u32;
-
-    (imm11_5 << 25)
-        | ((rs2 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | (imm4_0 << 7)
-        | 0x23
-}
-
-fn encode_jal(imm: i32, rd: u8) -> u32 {
-    let imm = imm as u32;
-    let i20 = (imm >> 20) & 0x1;
-    let i19_12 = (imm >> 12) & 0xFF;
-    let i11 = (imm >> 11) & 0x1;
-    let i10_1 = (imm >> 1) & 0x3FF;
-
-    (i20 << 31) | (i10_1 << 21) | (i11 << 20) | (i19_12 << 12) | ((rd as u32) << 7) | 0x6F
-}
-
-fn encode_jalr(imm: i32, rs1: u8, rd: u8) -> u32 {
-    let imm12 = (imm & 0xFFF) as u32;
-    (imm12 << 20) | ((rs1 as u32) << 15) | (0b000u32 << 12) | ((rd as u32) << 7) | 0x67
-}
-
-fn encode_lui(imm: u32, rd: u8) -> u32 {
-    ((imm & 0xFFFFF) << 12) | ((rd as u32) << 7) | 0x37
-}
-
-fn encode_auipc(imm: u32, rd: u8) -> u32 {
-    ((imm & 0xFFFFF) << 12) | ((rd as u32) << 7) | 0x17
-}
+use riscv_emulator_rust::encode::{auipc, itype, lui, rtype, slli, srli, stype};
+use riscv_emulator_rust::program::Program;
 
 // ── Helper: write a program into the CPU's bus starting at address 0 ──────────
 
 fn load_program(cpu: &mut RiscvCpu, instructions: &[u32]) {
-    for (i, &inst) in instructions.iter().enumerate() {
-        let addr = i * 4;
-        let bytes = inst.to_le_bytes();
-        cpu.bus[addr..addr + 4].copy_from_slice(&bytes);
-    }
+    let bytes: Vec<u8> = instructions.iter().flat_map(|i| i.to_le_bytes()).collect();
+    cpu.load_binary(0, &bytes).unwrap();
 }
 
 // ── Tests ─────────────────────────────────────────────────────────────────────
@@ -104,9 +22,9 @@ fn load_program(cpu: &mut RiscvCpu, instructions: &[u32]) {
 fn test_itype_chain() {
     let mut cpu = RiscvCpu::new(1024);
     let program = [
-        encode_itype(10, 0, 0b000, 1), // addi x1, x0, 10
-        encode_itype(5, 1, 0b000, 2),  // addi x2, x1, 5
-        encode_itype(-3, 2, 0b000, 3), // addi x3, x2, -3
+        itype(10, 0, 0b000, 1, 0x13), // addi x1, x0, 10
+        itype(5, 1, 0b000, 2, 0x13),  // addi x2, x1, 5
+        itype(-3, 2, 0b000, 3, 0x13), // addi x3, x2, -3
     ];
     load_program(&mut cpu, &program);
 
@@ -130,10 +48,10 @@ fn test_itype_chain() {
 fn test_itype_then_rtype() {
     let mut cpu = RiscvCpu::new(1024);
     let program = [
-        encode_itype(20, 0, 0b000, 1),           // addi x1, x0, 20
-        encode_itype(7, 0, 0b000, 2),            // addi x2, x0, 7
-        encode_rtype(0b0000000, 2, 1, 0b000, 3), // add  x3, x1, x2
-        encode_rtype(0b0100000, 2, 3, 0b000, 4), // sub  x4, x3, x2
+        itype(20, 0, 0b000, 1, 0x13),           // addi x1, x0, 20
+        itype(7, 0, 0b000, 2, 0x13),            // addi x2, x0, 7
+        rtype(0b0000000, 2, 1, 0b000, 3, 0x33), // add  x3, x1, x2
+        rtype(0b0100000, 2, 3, 0b000, 4, 0x33), // sub  x4, x3, x2
     ];
     load_program(&mut cpu, &program);
 
@@ -156,11 +74,11 @@ fn test_itype_then_rtype() {
 fn test_rtype_chain() {
     let mut cpu = RiscvCpu::new(1024);
     let program = [
-        encode_itype(0b1010, 0, 0b000, 1),       // addi x1, x0, 10
-        encode_itype(0b1100, 0, 0b000, 2),       // addi x2, x0, 12
-        encode_rtype(0b0000000, 2, 1, 0b100, 3), // xor  x3, x1, x2
-        encode_rtype(0b0000000, 2, 1, 0b110, 4), // or   x4, x1, x2
-        encode_rtype(0b0000000, 4, 3, 0b111, 5), // and  x5, x3, x4
+        itype(0b1010, 0, 0b000, 1, 0x13),       // addi x1, x0, 10
+        itype(0b1100, 0, 0b000, 2, 0x13),       // addi x2, x0, 12
+        rtype(0b0000000, 2, 1, 0b100, 3, 0x33), // xor  x3, x1, x2
+        rtype(0b0000000, 2, 1, 0b110, 4, 0x33), // or   x4, x1, x2
+        rtype(0b0000000, 4, 3, 0b111, 5, 0x33), // and  x5, x3, x4
     ];
     load_program(&mut cpu, &program);
 
@@ -187,14 +105,16 @@ fn test_rtype_chain() {
 #[test]
 fn test_branch_taken_skips_instruction() {
     let mut cpu = RiscvCpu::new(1024);
-    let program = [
-        encode_itype(5, 0, 0b000, 1),  // 0x00: addi x1, x0, 5
-        encode_itype(5, 0, 0b000, 2),  // 0x04: addi x2, x0, 5
-        encode_btype(8, 1, 2, 0b000),  // 0x08: beq  x1, x2, +8  → 0x10
-        encode_itype(99, 0, 0b000, 3), // 0x0C: addi x3, x0, 99  (skipped)
-        encode_itype(42, 0, 0b000, 4), // 0x10: addi x4, x0, 42
-    ];
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, 5) // 0x00
+        .addi(2, 0, 5) // 0x04
+        .beq(1, 2, "skip") // 0x08
+        .addi(3, 0, 99) // 0x0C: skipped
+        .label("skip")
+        .addi(4, 0, 42) // 0x10
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     cpu.step().unwrap(); // addi x1
     cpu.step().unwrap(); // addi x2
@@ -215,13 +135,15 @@ fn test_branch_taken_skips_instruction() {
 #[test]
 fn test_branch_not_taken_falls_through() {
     let mut cpu = RiscvCpu::new(1024);
-    let program = [
-        encode_itype(5, 0, 0b000, 1),  // 0x00: addi x1, x0, 5
-        encode_itype(9, 0, 0b000, 2),  // 0x04: addi x2, x0, 9
-        encode_btype(8, 1, 2, 0b000),  // 0x08: beq  x1, x2, +8 (not taken)
-        encode_itype(77, 0, 0b000, 3), // 0x0C: addi x3, x0, 77
-    ];
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, 5)
+        .addi(2, 0, 9)
+        .beq(1, 2, "skip") // not taken
+        .addi(3, 0, 77)
+        .label("skip")
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     for _ in 0..4 {
         cpu.step().unwrap();
@@ -245,21 +167,16 @@ fn test_branch_not_taken_falls_through() {
 fn test_countdown_loop() {
     let mut cpu = RiscvCpu::new(1024);
 
-    // Initialisation (before loop)
-    //   0x00: addi x1, x0, 5
-    //   0x04: addi x2, x0, 0   (x2 stays 0)
-    // Loop body starts at 0x08
-    //   0x08: addi x1, x1, -1
-    //   0x0C: addi x3, x3, 1
-    //   0x10: bne  x1, x2, -8  ; branch back to 0x08 while x1 ≠ 0
-    let program = [
-        encode_itype(5, 0, 0b000, 1),  // 0x00: addi x1, x0, 5
-        encode_itype(0, 0, 0b000, 2),  // 0x04: addi x2, x0, 0
-        encode_itype(-1, 1, 0b000, 1), // 0x08: addi x1, x1, -1
-        encode_itype(1, 3, 0b000, 3),  // 0x0C: addi x3, x3, 1
-        encode_btype(-8, 1, 2, 0b001), // 0x10: bne  x1, x2, -8
-    ];
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, 5)
+        .addi(2, 0, 0)
+        .label("loop")
+        .addi(1, 1, -1)
+        .addi(3, 3, 1)
+        .bne(1, 2, "loop")
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     // 2 setup instructions
     cpu.step().unwrap();
@@ -281,18 +198,18 @@ fn test_countdown_loop() {
 fn test_blt_bge_loop() {
     // Tests a loop that goes from x1 = 0 to x1 = 5.
     let mut cpu = RiscvCpu::new(1024);
-    let program = [
-        encode_itype(0, 0, 0b000, 1), // 0x00: addi x1, x0, 0
-        encode_itype(5, 0, 0b000, 2), // 0x04: addi x2, x0, 5
-        // Loop start (0x08)
-        encode_btype(12, 1, 2, 0b101), // 0x08: bge x1, x2, +12 (to 0x14)
-        encode_itype(1, 1, 0b000, 1),  // 0x0C: addi x1, x1, 1
-        encode_btype(-8, 1, 2, 0b100), // 0x10: blt x1, x2, -8 (to 0x08)
-        // End (0x14)
-        encode_itype(100, 0, 0b000, 3), // 0x14: addi x3, x0, 100
-    ];
-
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, 0)
+        .addi(2, 0, 5)
+        .label("loop")
+        .bge(1, 2, "end")
+        .addi(1, 1, 1)
+        .blt(1, 2, "loop")
+        .label("end")
+        .addi(3, 0, 100)
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     // Initial setup: 2 instructions
     cpu.step().unwrap(); // x1 = 0
@@ -317,18 +234,19 @@ fn test_blt_bge_loop() {
 fn test_bltu_bgeu_unsigned_comparisons() {
     let mut cpu = RiscvCpu::new(1024);
 
-    // unsigned comparison with negative numbers (which are large positive in unsigned)
-    let program = [
-        encode_itype(-1, 0, 0b000, 1), // 0x00: addi x1, x0, -1
-        encode_itype(1, 0, 0b000, 2),  // 0x04: addi x2, x0, 1
-        encode_btype(8, 1, 2, 0b111),  // 0x08: bgeu x1, x2, +8 (to 0x10)
-        encode_itype(99, 0, 0b000, 3), // 0x0C: addi x3, x0, 99 (skipped)
-        encode_btype(8, 2, 1, 0b110),  // 0x10: bltu x2, x1, +8 (to 0x18)
-        encode_itype(99, 0, 0b000, 4), // 0x14: addi x4, x0, 99 (skipped)
-        encode_itype(42, 0, 0b000, 5), // 0x18: addi x5, x0, 42
-    ];
-
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, -1)
+        .addi(2, 0, 1)
+        .bgeu(1, 2, "second")
+        .addi(3, 0, 99) // skipped
+        .label("second")
+        .bltu(2, 1, "end")
+        .addi(4, 0, 99) // skipped
+        .label("end")
+        .addi(5, 0, 42)
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     cpu.step().unwrap(); // addi x1
     cpu.step().unwrap(); // addi x2
@@ -357,11 +275,11 @@ fn test_load_instructions_integration() {
     cpu.bus[0x207] = 0xCC; // Word at 0x204 is 0xCCDDEEFF
 
     let program = [
-        encode_itype(0x200, 0, 0b000, 1),        // 0x00: addi x1, x0, 0x200
-        encode_load(0, 1, 0b010, 2),             // 0x04: lw x2, 0(x1)      (x2 = 0x44332211)
-        encode_load(4, 1, 0b001, 3), // 0x08: lh x3, 4(x1)      (x3 = sign_extend(0xEEFF) = 0xFFFFEEFF)
-        encode_load(5, 1, 0b100, 4), // 0x0C: lbu x4, 5(x1)     (x4 = lbu from 0x205 = 0xEE)
-        encode_rtype(0b0000000, 4, 3, 0b000, 5), // 0x10: add x5, x3, x4 (x5 = 0xFFFFEEFF + 0xEE = 0xFFFFEFED)
+        itype(0x200, 0, 0b000, 1, 0x13),        // 0x00: addi x1, x0, 0x200
+        itype(0, 1, 0b010, 2, 0x03),            // 0x04: lw x2, 0(x1)      (x2 = 0x44332211)
+        itype(4, 1, 0b001, 3, 0x03), // 0x08: lh x3, 4(x1)      (x3 = sign_extend(0xEEFF) = 0xFFFFEEFF)
+        itype(5, 1, 0b100, 4, 0x03), // 0x0C: lbu x4, 5(x1)     (x4 = lbu from 0x205 = 0xEE)
+        rtype(0b0000000, 4, 3, 0b000, 5, 0x33), // 0x10: add x5, x3, x4 (x5 = 0xFFFFEEFF + 0xEE = 0xFFFFEFED)
     ];
 
     load_program(&mut cpu, &program);
@@ -398,13 +316,13 @@ fn test_store_instructions_integration() {
     cpu.regs[4] = 0x0E;
 
     let program = [
-        encode_stype(0, 2, 1, 0b010), // 0x00: sw x2, 0(x1)
-        encode_stype(4, 3, 1, 0b001), // 0x04: sh x3, 4(x1)
-        encode_stype(6, 4, 1, 0b000), // 0x08: sb x4, 6(x1)
+        stype(0, 2, 1, 0b010), // 0x00: sw x2, 0(x1)
+        stype(4, 3, 1, 0b001), // 0x04: sh x3, 4(x1)
+        stype(6, 4, 1, 0b000), // 0x08: sb x4, 6(x1)
         // Now load them back into new registers to verify memory AND load interactions work
-        encode_load(0, 1, 0b010, 5), // 0x0C: lw x5, 0(x1)
-        encode_load(4, 1, 0b001, 6), // 0x10: lh x6, 4(x1)
-        encode_load(6, 1, 0b100, 7), // 0x14: lbu x7, 6(x1)
+        itype(0, 1, 0b010, 5, 0x03), // 0x0C: lw x5, 0(x1)
+        itype(4, 1, 0b001, 6, 0x03), // 0x10: lh x6, 4(x1)
+        itype(6, 1, 0b100, 7, 0x03), // 0x14: lbu x7, 6(x1)
     ];
 
     load_program(&mut cpu, &program);
@@ -446,14 +364,14 @@ fn test_fibonacci_sequence() {
     let mut cpu = RiscvCpu::new(1024);
 
     let program = [
-        encode_itype(0, 0, 0b000, 1),            // 0x00: addi x1, x0, 0  (F0=0)
-        encode_itype(1, 0, 0b000, 2),            // 0x04: addi x2, x0, 1  (F1=1)
-        encode_rtype(0b0000000, 2, 1, 0b000, 3), // 0x08: add  x3, x1, x2 (F2=1)
-        encode_rtype(0b0000000, 3, 2, 0b000, 4), // 0x0C: add  x4, x2, x3 (F3=2)
-        encode_rtype(0b0000000, 4, 3, 0b000, 5), // 0x10: add  x5, x3, x4 (F4=3)
-        encode_rtype(0b0000000, 5, 4, 0b000, 6), // 0x14: add  x6, x4, x5 (F5=5)
-        encode_rtype(0b0000000, 6, 5, 0b000, 7), // 0x18: add  x7, x5, x6 (F6=8)
-        encode_rtype(0b0000000, 7, 6, 0b000, 8), // 0x1C: add  x8, x6, x7 (F7=13)
+        itype(0, 0, 0b000, 1, 0x13),            // 0x00: addi x1, x0, 0  (F0=0)
+        itype(1, 0, 0b000, 2, 0x13),            // 0x04: addi x2, x0, 1  (F1=1)
+        rtype(0b0000000, 2, 1, 0b000, 3, 0x33), // 0x08: add  x3, x1, x2 (F2=1)
+        rtype(0b0000000, 3, 2, 0b000, 4, 0x33), // 0x0C: add  x4, x2, x3 (F3=2)
+        rtype(0b0000000, 4, 3, 0b000, 5, 0x33), // 0x10: add  x5, x3, x4 (F4=3)
+        rtype(0b0000000, 5, 4, 0b000, 6, 0x33), // 0x14: add  x6, x4, x5 (F5=5)
+        rtype(0b0000000, 6, 5, 0b000, 7, 0x33), // 0x18: add  x7, x5, x6 (F6=8)
+        rtype(0b0000000, 7, 6, 0b000, 8, 0x33), // 0x1C: add  x8, x6, x7 (F7=13)
     ];
     load_program(&mut cpu, &program);
 
@@ -481,16 +399,18 @@ fn test_fibonacci_sequence() {
 #[test]
 fn test_data_dependent_branch() {
     let mut cpu = RiscvCpu::new(1024);
-    let program = [
-        encode_itype(3, 0, 0b000, 1),            // 0x00: addi x1, x0, 3
-        encode_itype(4, 0, 0b000, 2),            // 0x04: addi x2, x0, 4
-        encode_rtype(0b0000000, 2, 1, 0b000, 3), // 0x08: add  x3, x1, x2  (x3=7)
-        encode_itype(-7, 3, 0b000, 4),           // 0x0C: addi x4, x3, -7  (x4=0)
-        encode_btype(8, 4, 0, 0b000),            // 0x10: beq  x4, x0, +8  → 0x18
-        encode_itype(99, 0, 0b000, 5),           // 0x14: addi x5, x0, 99  (skipped)
-        encode_itype(42, 0, 0b000, 5),           // 0x18: addi x5, x0, 42
-    ];
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, 3)
+        .addi(2, 0, 4)
+        .add(3, 1, 2) // x3 = 7
+        .addi(4, 3, -7) // x4 = 0
+        .beq(4, 0, "taken")
+        .addi(5, 0, 99) // skipped
+        .label("taken")
+        .addi(5, 0, 42)
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     for _ in 0..6 {
         cpu.step().unwrap();
@@ -510,8 +430,8 @@ fn test_x0_write_protection_via_step() {
     cpu.regs[1] = 100;
 
     let program = [
-        encode_itype(5, 1, 0b000, 0), // 0x00: addi x0, x1, 5  (rd=0, ignored)
-        encode_itype(7, 0, 0b000, 2), // 0x04: addi x2, x0, 7  (x2 must read 0)
+        itype(5, 1, 0b000, 0, 0x13), // 0x00: addi x0, x1, 5  (rd=0, ignored)
+        itype(7, 0, 0b000, 2, 0x13), // 0x04: addi x2, x0, 7  (x2 must read 0)
     ];
     load_program(&mut cpu, &program);
 
@@ -533,24 +453,12 @@ fn test_x0_write_protection_via_step() {
 fn test_shift_and_bitwise_chain() {
     let mut cpu = RiscvCpu::new(1024);
 
-    fn encode_slli_step(shamt: u8, rs1: u8, rd: u8) -> u32 {
-        let funct7 = 0b0000000u32;
-        let imm12 = (funct7 << 5) | (shamt as u32);
-        (imm12 << 20) | ((rs1 as u32) << 15) | (0b001u32 << 12) | ((rd as u32) << 7) | 0x13u32
-    }
-
-    fn encode_srli_step(shamt: u8, rs1: u8, rd: u8) -> u32 {
-        let funct7 = 0b0000000u32;
-        let imm12 = (funct7 << 5) | (shamt as u32);
-        (imm12 << 20) | ((rs1 as u32) << 15) | (0b101u32 << 12) | ((rd as u32) << 7) | 0x13u32
-    }
-
     let program = [
-        encode_itype(1, 0, 0b000, 1),     // addi x1, x0, 1
-        encode_slli_step(4, 1, 2),        // slli x2, x1, 4  => 16
-        encode_itype(0b111, 2, 0b110, 3), // ori  x3, x2, 7  => 16 | 7 = 23
-        encode_srli_step(1, 3, 4),        // srli x4, x3, 1  => 23 >> 1 = 11
-        encode_itype(0xF, 4, 0b111, 5),   // andi x5, x4, 15 => 11 & 15 = 11
+        itype(1, 0, 0b000, 1, 0x13),     // addi x1, x0, 1
+        slli(2, 1, 4),                   // slli x2, x1, 4  => 16
+        itype(0b111, 2, 0b110, 3, 0x13), // ori  x3, x2, 7  => 16 | 7 = 23
+        srli(4, 3, 1),                   // srli x4, x3, 1  => 23 >> 1 = 11
+        itype(0xF, 4, 0b111, 5, 0x13),   // andi x5, x4, 15 => 11 & 15 = 11
     ];
     load_program(&mut cpu, &program);
 
@@ -575,13 +483,15 @@ fn test_shift_and_bitwise_chain() {
 #[test]
 fn test_jal_forward_jump_and_link() {
     let mut cpu = RiscvCpu::new(1024);
-    let program = [
-        encode_itype(1, 0, 0b000, 1),  // 0x00: addi x1, x0, 1
-        encode_jal(8, 5),              // 0x04: jal  x5, +8 -> 0x0C; x5=0x08
-        encode_itype(99, 0, 0b000, 2), // 0x08: addi x2, x0, 99  (skipped)
-        encode_itype(42, 0, 0b000, 3), // 0x0C: addi x3, x0, 42
-    ];
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, 1) // 0x00
+        .jal(5, "target") // 0x04: x5 = 0x08
+        .addi(2, 0, 99) // 0x08: skipped
+        .label("target")
+        .addi(3, 0, 42) // 0x0C
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     cpu.step().unwrap(); // addi x1
     cpu.step().unwrap(); // jal  x5  (jumps to 0x0C)
@@ -602,13 +512,15 @@ fn test_jal_forward_jump_and_link() {
 #[test]
 fn test_jal_jalr_call_return() {
     let mut cpu = RiscvCpu::new(1024);
-    let program = [
-        encode_jal(8, 1),             // 0x00: jal  x1, +8 -> 0x08; x1=0x04
-        encode_itype(7, 0, 0b000, 2), // 0x04: addi x2, x0, 7
-        encode_itype(5, 0, 0b000, 3), // 0x08: addi x3, x0, 5 (subroutine)
-        encode_jalr(0, 1, 0),         // 0x0C: jalr x0, x1, 0 (return)
-    ];
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .jal(1, "subroutine") // 0x00: x1 = 0x04
+        .addi(2, 0, 7) // 0x04
+        .label("subroutine")
+        .addi(3, 0, 5) // 0x08
+        .jalr(0, 1, 0) // 0x0C: return
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     cpu.step().unwrap(); // jal  x1  (call, jumps to 0x08)
     cpu.step().unwrap(); // addi x3  (subroutine body)
@@ -628,8 +540,8 @@ fn test_jal_jalr_call_return() {
 fn test_lui_addi_load_32bit_constant() {
     let mut cpu = RiscvCpu::new(1024);
     let program = [
-        encode_lui(0x12345, 1),           // lui  x1, 0x12345 -> 0x12345000
-        encode_itype(0x678, 1, 0b000, 1), // addi x1, x1, 0x678
+        lui(1, 0x12345),                 // lui  x1, 0x12345 -> 0x12345000
+        itype(0x678, 1, 0b000, 1, 0x13), // addi x1, x1, 0x678
     ];
     load_program(&mut cpu, &program);
 
@@ -654,7 +566,7 @@ fn test_lui_addi_sign_compensation() {
     let upper: u32 = 0xDEADC;
     let lower: i32 = 0xEEF_u32 as i32 - 0x1000; // = -0x111
 
-    let program = [encode_lui(upper, 1), encode_itype(lower, 1, 0b000, 1)];
+    let program = [lui(1, upper), itype(lower, 1, 0b000, 1, 0x13)];
     load_program(&mut cpu, &program);
 
     cpu.step().unwrap();
@@ -680,12 +592,12 @@ fn test_auipc_pc_relative_memory_access() {
     let mut cpu = RiscvCpu::new(1024);
 
     let program = [
-        encode_auipc(0, 1),               // 0x00: auipc x1, 0     -> x1=0x00
-        encode_itype(0x200, 1, 0b000, 2), // 0x04: addi  x2, x1, 0x200 -> x2=0x200
-        encode_itype(0xAB, 0, 0b000, 3),  // 0x08: addi  x3, x0, 0xAB
-        encode_stype(0, 3, 2, 0b000),     // 0x0C: sb    x3, 0(x2)
-        encode_load(0, 2, 0b000, 4),      // 0x10: lb    x4, 0(x2) signed
-        encode_load(0, 2, 0b100, 5),      // 0x14: lbu   x5, 0(x2) unsigned
+        auipc(1, 0),                     // 0x00: auipc x1, 0     -> x1=0x00
+        itype(0x200, 1, 0b000, 2, 0x13), // 0x04: addi  x2, x1, 0x200 -> x2=0x200
+        itype(0xAB, 0, 0b000, 3, 0x13),  // 0x08: addi  x3, x0, 0xAB
+        stype(0, 3, 2, 0b000),           // 0x0C: sb    x3, 0(x2)
+        itype(0, 2, 0b000, 4, 0x03),     // 0x10: lb    x4, 0(x2) signed
+        itype(0, 2, 0b100, 5, 0x03),     // 0x14: lbu   x5, 0(x2) unsigned
     ];
     load_program(&mut cpu, &program);
 
@@ -712,16 +624,19 @@ fn test_auipc_pc_relative_memory_access() {
 #[test]
 fn test_jal_after_loop() {
     let mut cpu = RiscvCpu::new(1024);
-    let program = [
-        encode_itype(3, 0, 0b000, 1),  // 0x00: addi x1, x0, 3
-        encode_itype(1, 2, 0b000, 2),  // 0x04: addi x2, x2, 1
-        encode_itype(-1, 1, 0b000, 1), // 0x08: addi x1, x1, -1
-        encode_btype(-8, 1, 0, 0b001), // 0x0C: bne  x1, x0, -8 -> 0x04
-        encode_jal(8, 3),              // 0x10: jal  x3, +8 -> 0x18; x3=0x14
-        encode_itype(99, 0, 0b000, 5), // 0x14: addi x5, x0, 99 (skipped)
-        encode_itype(42, 0, 0b000, 4), // 0x18: addi x4, x0, 42
-    ];
-    load_program(&mut cpu, &program);
+    let program = Program::at(0)
+        .addi(1, 0, 3) // 0x00
+        .label("loop")
+        .addi(2, 2, 1) // 0x04
+        .addi(1, 1, -1) // 0x08
+        .bne(1, 0, "loop") // 0x0C
+        .jal(3, "end") // 0x10: x3 = 0x14
+        .addi(5, 0, 99) // 0x14: skipped
+        .label("end")
+        .addi(4, 0, 42) // 0x18
+        .build()
+        .unwrap();
+    cpu.load_binary(0, &program).unwrap();
 
     cpu.step().unwrap(); // setup: addi x1 = 3
     for _ in 0..9 {
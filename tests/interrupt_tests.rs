@@ -0,0 +1,258 @@
+mod common;
+
+use riscv_emulator_rust::RiscvCpu;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::encode::{addi, csrrc, csrrci, csrrs, csrrsi, csrrw, csrrwi, mret};
+use riscv_emulator_rust::trap::Interrupt;
+
+use common::le_bytes;
+
+// ── Helper: write a program into the CPU's bus starting at `base` ─────────────
+
+fn load_program(cpu: &mut RiscvCpu, base: u32, instructions: &[u32]) {
+    cpu.load_binary(base, &le_bytes(instructions)).unwrap();
+}
+
+/// Point mtvec at `handler`, then set mstatus.MIE and the given bits of mie.
+fn enable_interrupts(cpu: &mut RiscvCpu, handler: u32, mie: u32) {
+    cpu.csrs.write(csr::MTVEC, handler);
+    cpu.csrs.write(csr::MIE, mie);
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE);
+}
+
+// ── Zicsr ─────────────────────────────────────────────────────────────────────
+
+mod zicsr {
+    use super::*;
+
+    #[test]
+    fn test_csrrw_swaps_values() {
+        let mut cpu = RiscvCpu::new(1024);
+        cpu.csrs.write(csr::MSCRATCH, 0x1234);
+        cpu.regs[1] = 0xABCD;
+
+        // csrrw x2, mscratch, x1
+        let mut next_pc = cpu.pc + 4;
+        cpu.handle_system(csrrw(2, csr::MSCRATCH, 1), &mut next_pc)
+            .unwrap();
+
+        assert_eq!(cpu.regs[2], 0x1234, "rd gets the old CSR value");
+        assert_eq!(cpu.csrs.read(csr::MSCRATCH), 0xABCD);
+    }
+
+    #[test]
+    fn test_csrrs_and_csrrc() {
+        let mut cpu = RiscvCpu::new(1024);
+        cpu.csrs.write(csr::MSCRATCH, 0b1010);
+        cpu.regs[1] = 0b0101;
+        cpu.regs[2] = 0b0011;
+
+        let mut next_pc = cpu.pc + 4;
+        // csrrs x0, mscratch, x1 → 0b1111
+        cpu.handle_system(csrrs(0, csr::MSCRATCH, 1), &mut next_pc)
+            .unwrap();
+        assert_eq!(cpu.csrs.read(csr::MSCRATCH), 0b1111);
+
+        // csrrc x3, mscratch, x2 → 0b1100
+        cpu.handle_system(csrrc(3, csr::MSCRATCH, 2), &mut next_pc)
+            .unwrap();
+        assert_eq!(cpu.regs[3], 0b1111);
+        assert_eq!(cpu.csrs.read(csr::MSCRATCH), 0b1100);
+    }
+
+    #[test]
+    fn test_csr_immediate_forms() {
+        let mut cpu = RiscvCpu::new(1024);
+
+        let mut next_pc = cpu.pc + 4;
+        // csrrwi x0, mscratch, 31
+        cpu.handle_system(csrrwi(0, csr::MSCRATCH, 31), &mut next_pc)
+            .unwrap();
+        assert_eq!(cpu.csrs.read(csr::MSCRATCH), 31);
+
+        // csrrci x0, mscratch, 1
+        cpu.handle_system(csrrci(0, csr::MSCRATCH, 1), &mut next_pc)
+            .unwrap();
+        assert_eq!(cpu.csrs.read(csr::MSCRATCH), 30);
+
+        // csrrsi x4, mscratch, 1
+        cpu.handle_system(csrrsi(4, csr::MSCRATCH, 1), &mut next_pc)
+            .unwrap();
+        assert_eq!(cpu.regs[4], 30);
+        assert_eq!(cpu.csrs.read(csr::MSCRATCH), 31);
+    }
+
+    #[test]
+    fn test_mip_machine_bits_are_read_only() {
+        let mut cpu = RiscvCpu::new(1024);
+        cpu.regs[1] = csr::MIP_MTIP | csr::MIP_MSIP | csr::MIP_MEIP;
+
+        // csrrw x0, mip, x1
+        let mut next_pc = cpu.pc + 4;
+        cpu.handle_system(csrrw(0, csr::MIP, 1), &mut next_pc)
+            .unwrap();
+
+        assert_eq!(
+            cpu.csrs.read(csr::MIP),
+            0,
+            "guest can't raise M-mode interrupts"
+        );
+    }
+}
+
+// ── Interrupt delivery ────────────────────────────────────────────────────────
+
+mod delivery {
+    use super::*;
+
+    #[test]
+    fn test_timer_interrupt_taken() {
+        let mut cpu = RiscvCpu::new(1024);
+        cpu.pc = 0x40;
+        enable_interrupts(&mut cpu, 0x200, csr::MIP_MTIP);
+
+        cpu.raise_interrupt(Interrupt::MachineTimer);
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.pc, 0x200, "PC should jump to mtvec");
+        assert_eq!(
+            cpu.csrs.read(csr::MEPC),
+            0x40,
+            "mepc holds the interrupted PC"
+        );
+        assert_eq!(cpu.csrs.read(csr::MCAUSE), 0x8000_0007);
+
+        let mstatus = cpu.csrs.read(csr::MSTATUS);
+        assert_eq!(mstatus & csr::MSTATUS_MIE, 0, "MIE is cleared on entry");
+        assert_ne!(mstatus & csr::MSTATUS_MPIE, 0, "MPIE keeps the old MIE");
+        assert_eq!(mstatus & csr::MSTATUS_MPP, csr::MSTATUS_MPP, "MPP = M");
+    }
+
+    #[test]
+    fn test_cause_codes() {
+        let cases = [
+            (Interrupt::MachineSoftware, 0x8000_0003),
+            (Interrupt::MachineTimer, 0x8000_0007),
+            (Interrupt::MachineExternal, 0x8000_000B),
+        ];
+
+        for (interrupt, cause) in cases {
+            let mut cpu = RiscvCpu::new(1024);
+            enable_interrupts(&mut cpu, 0x100, interrupt.mask());
+
+            cpu.raise_interrupt(interrupt);
+            cpu.step().unwrap();
+
+            assert_eq!(cpu.csrs.read(csr::MCAUSE), cause, "{:?}", interrupt);
+        }
+    }
+
+    #[test]
+    fn test_global_disable_masks_interrupts() {
+        let mut cpu = RiscvCpu::new(1024);
+        load_program(&mut cpu, 0, &[addi(1, 0, 5)]);
+        cpu.csrs.write(csr::MTVEC, 0x200);
+        cpu.csrs.write(csr::MIE, csr::MIP_MTIP);
+
+        cpu.raise_interrupt(Interrupt::MachineTimer);
+        cpu.step().unwrap();
+
+        assert_eq!(
+            cpu.pc, 0x4,
+            "mstatus.MIE = 0 should leave the program running"
+        );
+        assert_eq!(cpu.regs[1], 5);
+    }
+
+    #[test]
+    fn test_mie_masks_individual_interrupts() {
+        let mut cpu = RiscvCpu::new(1024);
+        load_program(&mut cpu, 0, &[addi(1, 0, 5)]);
+        enable_interrupts(&mut cpu, 0x200, csr::MIP_MEIP);
+
+        cpu.raise_interrupt(Interrupt::MachineTimer);
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.pc, 0x4, "MTIP is pending but not enabled in mie");
+    }
+
+    #[test]
+    fn test_priority_external_over_software_over_timer() {
+        let mut cpu = RiscvCpu::new(1024);
+        enable_interrupts(
+            &mut cpu,
+            0x100,
+            csr::MIP_MSIP | csr::MIP_MTIP | csr::MIP_MEIP,
+        );
+
+        cpu.raise_interrupt(Interrupt::MachineTimer);
+        cpu.raise_interrupt(Interrupt::MachineSoftware);
+        cpu.raise_interrupt(Interrupt::MachineExternal);
+        assert_eq!(cpu.pending_interrupt(), Some(Interrupt::MachineExternal));
+
+        cpu.clear_interrupt(Interrupt::MachineExternal);
+        assert_eq!(cpu.pending_interrupt(), Some(Interrupt::MachineSoftware));
+
+        cpu.clear_interrupt(Interrupt::MachineSoftware);
+        assert_eq!(cpu.pending_interrupt(), Some(Interrupt::MachineTimer));
+    }
+
+    #[test]
+    fn test_vectored_mode() {
+        let mut cpu = RiscvCpu::new(1024);
+        // mtvec.MODE = 1 (vectored): PC = base + 4 * cause
+        enable_interrupts(&mut cpu, 0x100 | 0x1, csr::MIP_MEIP);
+
+        cpu.raise_interrupt(Interrupt::MachineExternal);
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.pc, 0x100 + 4 * 11);
+    }
+}
+
+// ── Handler round trip ────────────────────────────────────────────────────────
+
+/// The handler acknowledges nothing itself, so the host clears the interrupt
+/// before the handler returns. MRET should restore MIE and resume at mepc.
+#[test]
+fn test_mret_resumes_interrupted_program() {
+    let mut cpu = RiscvCpu::new(1024);
+    load_program(
+        &mut cpu,
+        0,
+        &[
+            addi(1, 0, 1), // addi x1, x0, 1
+            addi(2, 0, 2), // addi x2, x0, 2
+        ],
+    );
+    load_program(
+        &mut cpu,
+        0x200,
+        &[
+            addi(10, 0, 42), // addi x10, x0, 42
+            mret(),
+        ],
+    );
+    enable_interrupts(&mut cpu, 0x200, csr::MIP_MTIP);
+
+    cpu.step().unwrap(); // addi x1
+    cpu.raise_interrupt(Interrupt::MachineTimer);
+    cpu.step().unwrap(); // trap into handler
+    assert_eq!(cpu.pc, 0x200);
+
+    cpu.clear_interrupt(Interrupt::MachineTimer);
+    cpu.step().unwrap(); // addi x10
+    cpu.step().unwrap(); // mret
+
+    assert_eq!(cpu.pc, 0x4, "MRET should return to the interrupted PC");
+    assert_ne!(
+        cpu.csrs.read(csr::MSTATUS) & csr::MSTATUS_MIE,
+        0,
+        "MRET should restore MIE from MPIE"
+    );
+
+    cpu.step().unwrap(); // addi x2
+    assert_eq!(cpu.regs[1], 1);
+    assert_eq!(cpu.regs[2], 2);
+    assert_eq!(cpu.regs[10], 42);
+}
@@ -0,0 +1,269 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::float::FloatRegs;
+use riscv_emulator_rust::isa::{Extension, Extensions};
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu};
+
+use common::{image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, extensions: Extensions) -> RiscvCpu {
+    program(source).extensions(extensions).build().unwrap()
+}
+
+const MISA_LETTERS: u32 = 0x3FF_FFFF;
+
+// ── misa ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_misa_reports_enabled_extensions() {
+    let cpu = RiscvCpu::builder().build().unwrap();
+    assert_eq!(
+        cpu.csrs.read(csr::MISA) & MISA_LETTERS,
+        0x34_01A8,
+        "DFHISUV"
+    );
+    assert_eq!(cpu.csrs.read(csr::MISA) >> 30, 1, "MXL");
+
+    let cpu = cpu_with("", Extensions::none());
+    assert_eq!(cpu.csrs.read(csr::MISA) & MISA_LETTERS, csr::MISA_I);
+
+    let cpu = cpu_with("", Extensions::all().without(Extension::V));
+    assert_eq!(cpu.csrs.read(csr::MISA) & csr::MISA_V, 0);
+
+    let cpu = RiscvCpu::builder()
+        .float_regs(FloatRegs::Integer)
+        .build()
+        .unwrap();
+    assert_eq!(
+        cpu.csrs.read(csr::MISA) & (csr::MISA_F | csr::MISA_D),
+        0,
+        "Zfinx isn't reported"
+    );
+}
+
+#[test]
+fn test_misa_is_read_only() {
+    let mut cpu = cpu_with("csrrw zero, misa, zero", Extensions::all());
+    let before = cpu.csrs.read(csr::MISA);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.csrs.read(csr::MISA), before);
+}
+
+// ── Disabled extensions ───────────────────────────────────────────────────────
+
+#[test]
+fn test_disabled_extensions_raise_illegal_instruction() {
+    let cases = [
+        ("sh1add a0, a1, a2", Extension::Zba),
+        ("pack a0, a1, a2", Extension::Zbkb),
+        ("lr.w a0, (a1)", Extension::Zalrsc),
+        ("wrs.nto", Extension::Zawrs),
+        ("aes32esi a0, a0, a1, 0", Extension::Zkne),
+        ("aes32dsi a0, a0, a1, 0", Extension::Zknd),
+        ("sha256sig0 a0, a1", Extension::Zknh),
+        ("fadd.s ft0, ft1, ft2", Extension::F),
+        ("fadd.d ft0, ft1, ft2", Extension::D),
+        ("fld ft0, 0(zero)", Extension::D),
+        ("vsetvli t0, zero, e32, m1", Extension::V),
+        ("sfence.vma zero, zero", Extension::S),
+    ];
+
+    for (source, extension) in cases {
+        let word = assemble(source).unwrap()[0];
+        let mut without = Extensions::all().without(extension);
+        match extension {
+            Extension::F => without = without.without(Extension::D),
+            Extension::S => without = without.without(Extension::H),
+            _ => {}
+        }
+
+        let mut cpu = cpu_with(source, without);
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(word)),
+            "{}",
+            source
+        );
+
+        let mut cpu = cpu_with(source, Extensions::all());
+        assert!(cpu.step().is_ok(), "{}", source);
+    }
+}
+
+#[test]
+fn test_disabled_extensions_in_blocks() {
+    let engines = [
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = RiscvCpu::builder()
+            .image(
+                0,
+                image(
+                    "
+                    addi   a1, zero, 1
+                    addi   a2, zero, 2
+                    sh1add a0, a1, a2
+                    ",
+                ),
+            )
+            .engine(engine)
+            .extensions(Extensions::none())
+            .build()
+            .unwrap();
+
+        let word = assemble("sh1add a0, a1, a2").unwrap()[0];
+        assert_eq!(
+            cpu.run_steps(3),
+            ExitReason::Exception(Exception::IllegalInstruction(word)),
+            "{:?}",
+            engine
+        );
+        assert_eq!(cpu.pc, 8, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_set_extensions_drops_decoded_code() {
+    let mut cpu = cpu_with(
+        "
+top:    sh2add a0, a1, a2
+        jal    zero, top
+        ",
+        Extensions::all(),
+    );
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+
+    cpu.set_extensions(Extensions::all().without(Extension::Zba))
+        .unwrap();
+
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+    assert!(!cpu.has_extension(Extension::Zba));
+}
+
+#[test]
+fn test_disabled_extension_csrs_are_inaccessible() {
+    let cases = [
+        ("csrrs a0, fcsr, zero", Extension::F),
+        ("csrrs a0, vl, zero", Extension::V),
+        ("csrrs a0, satp, zero", Extension::S),
+    ];
+
+    for (source, extension) in cases {
+        let mut without = Extensions::all().without(extension);
+        match extension {
+            Extension::F => without = without.without(Extension::D),
+            Extension::S => without = without.without(Extension::H),
+            _ => {}
+        }
+
+        let mut cpu = cpu_with(source, without);
+        assert!(
+            matches!(cpu.step(), Err(Exception::IllegalInstruction(_))),
+            "{}",
+            source
+        );
+    }
+}
+
+// ── Privilege modes ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_mpp_only_holds_implemented_modes() {
+    let source = "
+        lui   t0, 0x2
+        addi  t0, t0, -0x800
+        csrrc zero, mstatus, t0
+        csrrs a0, mstatus, zero
+        ";
+    let mpp = |cpu: &RiscvCpu| cpu.regs[10] >> 11 & 0b11;
+
+    let mut cpu = cpu_with(source, Extensions::all());
+    cpu.run_steps(4);
+    assert_eq!(mpp(&cpu), 0, "U");
+
+    let machine_only = Extensions::all()
+        .without(Extension::H)
+        .without(Extension::S)
+        .without(Extension::U);
+    let mut cpu = cpu_with(source, machine_only);
+    cpu.run_steps(4);
+    assert_eq!(mpp(&cpu), 3, "stays M");
+}
+
+// ── Configuration ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_parse_isa_strings() {
+    let parsed = Extensions::parse("rv32ifd_zba_zicsr_zbkb").unwrap();
+    let expected: Extensions = [Extension::F, Extension::D, Extension::Zba, Extension::Zbkb]
+        .into_iter()
+        .collect();
+    assert_eq!(parsed, expected);
+
+    assert_eq!(
+        Extensions::parse("RV64GV").unwrap(),
+        [Extension::F, Extension::D, Extension::V]
+            .into_iter()
+            .collect()
+    );
+    assert_eq!(Extensions::parse("i").unwrap(), Extensions::none());
+
+    for bad in ["rv32imac", "rv32i_zbb", "rv32id", "rv32is", "rv32e"] {
+        assert!(Extensions::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_isa_strings_round_trip() {
+    assert_eq!(
+        Extensions::all().isa_string(32),
+        "rv32ifdvh_zicntr_zicsr_zifencei_zihpm_zalrsc_zawrs_zba_zbkb_zknd_zkne_zknh"
+    );
+    assert_eq!(
+        Extensions::none().isa_string(64),
+        "rv64i_zicntr_zicsr_zifencei_zihpm"
+    );
+
+    let extensions = Extensions::parse("rv32ifd_zbkb_zba").unwrap();
+    assert_eq!(
+        Extensions::parse(&extensions.isa_string(32)).unwrap(),
+        extensions
+    );
+}
+
+#[test]
+fn test_builder_rejects_inconsistent_sets() {
+    let result = RiscvCpu::builder()
+        .extensions(Extensions::none().with(Extension::D))
+        .build();
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_rv64_and_machine_harts() {
+    let extensions = Extensions::parse("rv64i_zba").unwrap();
+    let cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .extensions(extensions)
+        .build()
+        .unwrap();
+    assert_eq!(cpu.csrs.read(csr::MISA) >> 62, 2);
+    assert_eq!(cpu.csrs.read(csr::MISA) & MISA_LETTERS as u64, 1 << 8);
+
+    let machine = Machine::new(cpu, 2);
+    assert_eq!(machine.hart(1).extensions(), extensions);
+}
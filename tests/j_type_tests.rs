@@ -1,47 +1,5 @@
 use riscv_emulator_rust::RiscvCpu;
-
-// ── Instruction encoders ──────────────────────────────────────────────────────
-
-/// Encode a J-type (JAL) instruction.
-///
-/// imm: 21-bit signed immediate (must be a multiple of 2), PC-relative offset
-/// rd:  destination register (receives PC+4)
-/// opcode: 0x6F for JAL
-fn encode_jal(imm: i32, rd: u8) -> u32 {
-    let imm = imm as u32;
-    let i20 = (imm >> 20) & 0x1;
-    let i19_12 = (imm >> 12) & 0xFF;
-    let i11 = (imm >> 11) & 0x1;
-    let i10_1 = (imm >> 1) & 0x3FF;
-
-    (i20 << 31) | (i10_1 << 21) | (i11 << 20) | (i19_12 << 12) | ((rd as u32) << 7) | 0x6F
-}
-
-/// Encode a JALR instruction.
-///
-/// imm:   12-bit signed immediate offset added to rs1
-/// rs1:   base register
-/// rd:    destination register (receives PC+4)
-/// funct3 is always 0x0 for JALR; opcode is 0x67.
-fn encode_jalr(imm: i32, rs1: u8, rd: u8) -> u32 {
-    let imm12 = (imm & 0xFFF) as u32;
-    (imm12 << 20) | ((rs1 as u32) << 15) | (0b000u32 << 12) | ((rd as u32) << 7) | 0x67
-}
-
-/// Encode a LUI instruction.
-///
-/// imm: upper 20 bits placed in bits [31:12] of rd.  Pass the raw 20-bit value
-///      (before shifting) – the encoder shifts it for you.
-fn encode_lui(imm: u32, rd: u8) -> u32 {
-    ((imm & 0xFFFFF) << 12) | ((rd as u32) << 7) | 0x37
-}
-
-/// Encode an AUIPC instruction.
-///
-/// imm: upper 20 bits added to PC. Same convention as encode_lui.
-fn encode_auipc(imm: u32, rd: u8) -> u32 {
-    ((imm & 0xFFFFF) << 12) | ((rd as u32) << 7) | 0x17
-}
+use riscv_emulator_rust::encode::{auipc, jal, jalr, lui};
 
 // ── JAL ───────────────────────────────────────────────────────────────────────
 
@@ -54,7 +12,7 @@ mod jal {
         cpu.pc = 0x100;
 
         // jal x1, +8  →  rd = PC+4 = 0x104, next_pc = 0x100 + 8 = 0x108
-        let instruction = encode_jal(8, 1);
+        let instruction = jal(1, 8);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jal(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -69,7 +27,7 @@ mod jal {
         cpu.pc = 0x100;
 
         // jal x1, -8  →  rd = 0x104, next_pc = 0x100 + (-8) = 0x0F8
-        let instruction = encode_jal(-8, 1);
+        let instruction = jal(1, -8);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jal(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -84,7 +42,7 @@ mod jal {
         cpu.pc = 0x000;
 
         // jal x2, +0x100  →  rd = 0x4, next_pc = 0x100
-        let instruction = encode_jal(0x100, 2);
+        let instruction = jal(2, 0x100);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jal(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -99,7 +57,7 @@ mod jal {
         cpu.pc = 0x200;
 
         // jal x0, +4  →  x0 must remain 0 (write to x0 is a no-op)
-        let instruction = encode_jal(4, 0);
+        let instruction = jal(0, 4);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jal(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -114,7 +72,7 @@ mod jal {
         cpu.pc = 0x3FC;
 
         // Whatever the offset, rd must always be old_pc + 4
-        let instruction = encode_jal(16, 5);
+        let instruction = jal(5, 16);
         let old_pc = cpu.pc;
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jal(instruction, &mut next_pc).unwrap();
@@ -129,7 +87,7 @@ mod jal {
         // pc = 0 (default)
 
         // jal x3, +20  →  rd = 4, next_pc = 20
-        let instruction = encode_jal(20, 3);
+        let instruction = jal(3, 20);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jal(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -151,7 +109,7 @@ mod jalr {
         cpu.regs[1] = 0x200; // base address
 
         // jalr x2, x1, 0  →  rd = 0x104, next_pc = 0x200 + 0 = 0x200
-        let instruction = encode_jalr(0, 1, 2);
+        let instruction = jalr(2, 1, 0);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jalr(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -167,7 +125,7 @@ mod jalr {
         cpu.regs[1] = 0x200;
 
         // jalr x2, x1, 8  →  rd = 0x104, next_pc = 0x200 + 8 = 0x208
-        let instruction = encode_jalr(8, 1, 2);
+        let instruction = jalr(2, 1, 8);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jalr(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -183,7 +141,7 @@ mod jalr {
         cpu.regs[1] = 0x200;
 
         // jalr x2, x1, -8  →  rd = 0x104, next_pc = 0x200 + (-8) = 0x1F8
-        let instruction = encode_jalr(-8, 1, 2);
+        let instruction = jalr(2, 1, -8);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jalr(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -199,7 +157,7 @@ mod jalr {
         cpu.regs[1] = 0x400;
 
         // jalr x0, x1, 0  →  x0 stays 0, next_pc = 0x400
-        let instruction = encode_jalr(0, 1, 0);
+        let instruction = jalr(0, 1, 0);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jalr(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -218,7 +176,7 @@ mod jalr {
 
         // jalr x1, x1, 0 → rd/rs1 are the same register (x1)
         // Expected: PC jumps to old rs1 value (0x300), x1 = old PC+4 = 0x104
-        let instruction = encode_jalr(0, 1, 1);
+        let instruction = jalr(1, 1, 0);
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jalr(instruction, &mut next_pc).unwrap();
         cpu.pc = next_pc;
@@ -235,7 +193,7 @@ mod jalr {
         cpu.pc = 0x5FC;
         cpu.regs[3] = 0x800;
 
-        let instruction = encode_jalr(4, 3, 4);
+        let instruction = jalr(4, 3, 4);
         let old_pc = cpu.pc;
         let mut next_pc = cpu.pc + 4;
         cpu.handle_jalr(instruction, &mut next_pc).unwrap();
@@ -256,7 +214,7 @@ mod lui {
         let mut cpu = RiscvCpu::new(1024);
 
         // lui x1, 1  →  x1 = 1 << 12 = 0x1000
-        let instruction = encode_lui(1, 1);
+        let instruction = lui(1, 1);
         cpu.handle_lui(instruction).unwrap();
 
         assert_eq!(cpu.regs[1], 0x1000, "x1 = 1 shifted left 12 bits");
@@ -267,7 +225,7 @@ mod lui {
         let mut cpu = RiscvCpu::new(1024);
 
         // lui x1, 0xFFFFF  →  x1 = 0xFFFFF000
-        let instruction = encode_lui(0xFFFFF, 1);
+        let instruction = lui(1, 0xFFFFF);
         cpu.handle_lui(instruction).unwrap();
 
         assert_eq!(cpu.regs[1], 0xFFFFF000, "x1 should be 0xFFFFF000");
@@ -279,7 +237,7 @@ mod lui {
         cpu.regs[2] = 0xDEAD; // pre-set to something non-zero
 
         // lui x2, 0  →  x2 = 0
-        let instruction = encode_lui(0, 2);
+        let instruction = lui(2, 0);
         cpu.handle_lui(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0, "LUI with imm=0 clears the register");
@@ -290,7 +248,7 @@ mod lui {
         let mut cpu = RiscvCpu::new(1024);
 
         // lui x0, 0xABCDE  →  x0 stays 0
-        let instruction = encode_lui(0xABCDE, 0);
+        let instruction = lui(0, 0xABCDE);
         cpu.handle_lui(instruction).unwrap();
 
         assert_eq!(cpu.regs[0], 0, "x0 must always be 0");
@@ -301,7 +259,7 @@ mod lui {
         let mut cpu = RiscvCpu::new(1024);
 
         // lui x3, 0x12345  →  x3 = 0x12345000 (lower 12 bits must be 0)
-        let instruction = encode_lui(0x12345, 3);
+        let instruction = lui(3, 0x12345);
         cpu.handle_lui(instruction).unwrap();
 
         assert_eq!(
@@ -316,9 +274,9 @@ mod lui {
     fn test_lui_multiple_registers() {
         let mut cpu = RiscvCpu::new(1024);
 
-        cpu.handle_lui(encode_lui(0x00001, 1)).unwrap();
-        cpu.handle_lui(encode_lui(0x00010, 2)).unwrap();
-        cpu.handle_lui(encode_lui(0x00100, 3)).unwrap();
+        cpu.handle_lui(lui(1, 0x00001)).unwrap();
+        cpu.handle_lui(lui(2, 0x00010)).unwrap();
+        cpu.handle_lui(lui(3, 0x00100)).unwrap();
 
         assert_eq!(cpu.regs[1], 0x0000_1000, "x1 = 0x1000");
         assert_eq!(cpu.regs[2], 0x0001_0000, "x2 = 0x10000");
@@ -337,7 +295,7 @@ mod auipc {
         cpu.pc = 0x1000;
 
         // auipc x1, 1  →  x1 = PC + (1 << 12) = 0x1000 + 0x1000 = 0x2000
-        let instruction = encode_auipc(1, 1);
+        let instruction = auipc(1, 1);
         cpu.handle_auipc(instruction).unwrap();
 
         assert_eq!(cpu.regs[1], 0x2000, "x1 = PC + 0x1000");
@@ -349,7 +307,7 @@ mod auipc {
         cpu.pc = 0x300;
 
         // auipc x2, 0  →  x2 = PC + 0 = 0x300
-        let instruction = encode_auipc(0, 2);
+        let instruction = auipc(2, 0);
         cpu.handle_auipc(instruction).unwrap();
 
         assert_eq!(cpu.regs[2], 0x300, "auipc with imm=0 copies PC into rd");
@@ -361,7 +319,7 @@ mod auipc {
         // PC = 0 (default)
 
         // auipc x1, 2  →  x1 = 0 + (2 << 12) = 0x2000
-        let instruction = encode_auipc(2, 1);
+        let instruction = auipc(1, 2);
         cpu.handle_auipc(instruction).unwrap();
 
         assert_eq!(cpu.regs[1], 0x2000, "x1 = 0 + 0x2000");
@@ -373,7 +331,7 @@ mod auipc {
         cpu.pc = 0x0;
 
         // auipc x1, 0xFFFFF  →  x1 = 0 + 0xFFFFF000
-        let instruction = encode_auipc(0xFFFFF, 1);
+        let instruction = auipc(1, 0xFFFFF);
         cpu.handle_auipc(instruction).unwrap();
 
         assert_eq!(cpu.regs[1], 0xFFFFF000, "x1 = 0xFFFFF000");
@@ -385,7 +343,7 @@ mod auipc {
         cpu.pc = 0xFFFF_F000;
 
         // auipc x1, 1  →  with wrapping: 0xFFFF_F000 + 0x1000 wraps to 0x0000_0000
-        let instruction = encode_auipc(1, 1);
+        let instruction = auipc(1, 1);
         cpu.handle_auipc(instruction).unwrap();
 
         let expected = 0xFFFF_F000u32.wrapping_add(0x1000);
@@ -398,7 +356,7 @@ mod auipc {
         cpu.pc = 0x100;
 
         // auipc x0, 5  →  x0 stays 0
-        let instruction = encode_auipc(5, 0);
+        let instruction = auipc(0, 5);
         cpu.handle_auipc(instruction).unwrap();
 
         assert_eq!(cpu.regs[0], 0, "x0 must always be 0");
@@ -412,11 +370,11 @@ mod auipc {
         let imm: u32 = 0x10; // 0x10 << 12 = 0x10000
 
         cpu.pc = 0x0;
-        cpu.handle_auipc(encode_auipc(imm, 1)).unwrap();
+        cpu.handle_auipc(auipc(1, imm)).unwrap();
         let result_at_0 = cpu.regs[1];
 
         cpu.pc = 0x200;
-        cpu.handle_auipc(encode_auipc(imm, 2)).unwrap();
+        cpu.handle_auipc(auipc(2, imm)).unwrap();
         let result_at_200 = cpu.regs[2];
 
         assert_eq!(result_at_0, 0x0000_0000u32.wrapping_add(imm << 12));
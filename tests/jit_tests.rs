@@ -0,0 +1,292 @@
+#![cfg(feature = "jit")]
+
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::debug::WatchKind;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::{Rv32, Rv64, Xlen};
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu, csr};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with<X: Xlen>(source: &str, engine: Engine) -> RiscvCpu<X> {
+    program(source).xlen::<X>().engine(engine).build().unwrap()
+}
+
+/// Exercises every kind of ALU op plus loads and stores in a hot loop, so
+/// the body gets compiled partway through.
+const MIXED_LOOP: &str = "
+            addi t0, zero, 200
+            lui  s0, 0x12345
+            addi s1, zero, -7
+    loop:   add  a0, a0, t0
+            sub  a1, a1, s1
+            xor  a2, a2, s0
+            slli a3, t0, 3
+            srai a4, s1, 1
+            srl  a5, s0, t0
+            slt  a6, s1, t0
+            sltu a7, s1, t0
+            sltiu s2, t0, 100
+            andi s3, s0, 0x7f0
+            ori  s4, t0, -16
+            auipc s5, 1
+            sw   a0, 0x400(zero)
+            lw   s6, 0x400(zero)
+            lbu  s7, 0x401(zero)
+            sh   s1, 0x404(zero)
+            lh   s8, 0x404(zero)
+            addi t0, t0, -1
+            bne  t0, zero, loop
+            jal  ra, done
+            addi a0, zero, 0
+    done:   ebreak
+";
+
+// ── Equivalence with the interpreter ──────────────────────────────────────────
+
+fn check_matches_interpreter<X: Xlen>() {
+    let mut interpreted = cpu_with::<X>(MIXED_LOOP, Engine::Interpreter);
+    let mut jit = cpu_with::<X>(MIXED_LOOP, Engine::Jit);
+
+    let expected = interpreted.run();
+
+    assert_eq!(jit.run(), expected);
+    assert_eq!(jit.regs, interpreted.regs);
+    assert_eq!(jit.pc, interpreted.pc);
+    assert_eq!(jit.bus[0x400..0x408], interpreted.bus[0x400..0x408]);
+}
+
+#[test]
+fn test_jit_matches_interpreter_on_rv32() {
+    check_matches_interpreter::<Rv32>();
+}
+
+#[test]
+fn test_jit_matches_interpreter_on_rv64() {
+    check_matches_interpreter::<Rv64>();
+}
+
+#[test]
+fn test_word_ops_on_rv64() {
+    let source = "
+                addi t0, zero, 100
+                lui  s0, 0x80000
+        loop:   addiw a0, a0, 7
+                addw  a1, a1, s0
+                subw  a2, a2, t0
+                sllw  a3, s0, t0
+                sraiw a4, s0, 3
+                srliw a5, s0, 3
+                addi  t0, t0, -1
+                bne   t0, zero, loop
+                ebreak
+    ";
+    let mut interpreted = cpu_with::<Rv64>(source, Engine::Interpreter);
+    let mut jit = cpu_with::<Rv64>(source, Engine::Jit);
+
+    interpreted.run();
+    jit.run();
+
+    assert_eq!(jit.regs, interpreted.regs);
+}
+
+#[test]
+fn test_zba_ops_on_rv64() {
+    let source = "
+                addi t0, zero, 100
+                addi s0, zero, -3
+        loop:   sh1add    a0, t0, a0
+                sh2add    a1, s0, a1
+                sh3add.uw a2, s0, a2
+                add.uw    a3, s0, t0
+                slli.uw   a4, s0, 33
+                addi  t0, t0, -1
+                bne   t0, zero, loop
+                ebreak
+    ";
+    let mut interpreted = cpu_with::<Rv64>(source, Engine::Interpreter);
+    let mut jit = cpu_with::<Rv64>(source, Engine::Jit);
+
+    interpreted.run();
+    jit.run();
+
+    assert_eq!(jit.regs, interpreted.regs);
+}
+
+const PACK_LOOP: &str = "
+            addi t0, zero, 100
+            lui  s0, 0xABCDE
+    loop:   pack  a0, s0, t0
+            packh a1, t0, s0
+            pack  a2, a0, zero
+            add   s0, s0, a0
+            addi  t0, t0, -1
+            bne   t0, zero, loop
+            ebreak
+";
+
+fn check_pack_matches_interpreter<X: Xlen>() {
+    let mut interpreted = cpu_with::<X>(PACK_LOOP, Engine::Interpreter);
+    let mut jit = cpu_with::<X>(PACK_LOOP, Engine::Jit);
+
+    interpreted.run();
+    jit.run();
+
+    assert_eq!(jit.regs, interpreted.regs);
+}
+
+#[test]
+fn test_zbkb_pack_ops() {
+    check_pack_matches_interpreter::<Rv32>();
+    check_pack_matches_interpreter::<Rv64>();
+}
+
+// ── Self-looping blocks ───────────────────────────────────────────────────────
+
+const COUNT_LOOP: &str = "
+    loop:   addi a0, a0, 1
+            addi a1, a1, 2
+            jal  zero, loop
+";
+
+#[test]
+fn test_step_limit_is_exact_while_looping() {
+    let mut cpu = cpu_with::<Rv32>(COUNT_LOOP, Engine::Jit);
+
+    assert_eq!(cpu.run_steps(1000), ExitReason::StepLimit);
+    assert_eq!(cpu.regs[10], 334);
+    assert_eq!(cpu.regs[11], 2 * 333);
+    assert_eq!(cpu.pc, 0x4);
+}
+
+#[test]
+fn test_run_until_the_loop_head_stops_each_pass() {
+    let mut cpu = cpu_with::<Rv32>(COUNT_LOOP, Engine::Jit);
+    cpu.run_steps(300);
+
+    assert_eq!(cpu.run_until(0x0), ExitReason::ReachedPc(0x0));
+    assert_eq!(cpu.regs[10], 101);
+}
+
+// ── Precise exits ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_fault_inside_compiled_block_is_precise() {
+    // The 100th iteration loads from past the end of RAM.
+    let mut cpu = cpu_with::<Rv32>(
+        "
+                lui  t1, 0xf
+        loop:   addi a0, a0, 1
+                lw   a1, 0(t1)
+                addi t1, t1, 0x10
+                jal  zero, loop
+        ",
+        Engine::Jit,
+    );
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::LoadAccessFault(0x10000))
+    );
+    assert_eq!(cpu.pc, 0x8);
+    assert_eq!(cpu.regs[10], 0x100 + 1);
+}
+
+#[test]
+fn test_watchpoint_inside_compiled_block() {
+    let mut cpu = cpu_with::<Rv32>(
+        "
+        loop:   addi a0, a0, 1
+                sw   a0, 0x400(zero)
+                addi a1, a1, 1
+                jal  zero, loop
+        ",
+        Engine::Jit,
+    );
+    cpu.run_steps(400);
+    cpu.add_watchpoint(0x400, 4, WatchKind::Write);
+
+    match cpu.run() {
+        ExitReason::Watchpoint(hit) => assert_eq!(hit.pc, 0x4),
+        other => panic!("expected a watchpoint, got {:?}", other),
+    }
+    assert_eq!(cpu.pc, 0x8, "stopped right after the store");
+    assert_eq!(cpu.regs[10], cpu.regs[11] + 1);
+}
+
+#[test]
+fn test_register_watch_inside_compiled_block() {
+    let mut cpu = cpu_with::<Rv32>(
+        "
+        loop:   addi a0, a0, 1
+                addi a1, a1, 1
+                jal  zero, loop
+        ",
+        Engine::Jit,
+    );
+    cpu.run_steps(400);
+    cpu.add_register_watch(11);
+
+    match cpu.run() {
+        ExitReason::RegisterWrite(write) => {
+            assert_eq!((write.pc, write.reg), (0x4, 11));
+            assert_eq!(write.new, write.old + 1);
+        }
+        other => panic!("expected a register write, got {:?}", other),
+    }
+    assert_eq!(cpu.pc, 0x8);
+}
+
+#[test]
+fn test_self_modifying_store_leaves_compiled_code() {
+    let patch = assemble("addi a0, a0, 100").unwrap()[0];
+    let mut cpu = cpu_with::<Rv32>(
+        &format!(
+            "
+                    lui   t0, {upper:#x}
+                    addi  t0, t0, {lower}
+                    addi  t1, zero, 40
+            body:   addi  a0, a0, 1
+                    addi  t1, t1, -1
+                    bne   t1, zero, body
+                    sw    t0, 12(zero)
+                    addi  t1, zero, 1
+                    jal   zero, body
+            ",
+            upper = (patch + 0x800) >> 12,
+            lower = ((patch & 0xFFF) as i32) << 20 >> 20,
+        ),
+        Engine::Jit,
+    );
+
+    cpu.run_steps(3 + 40 * 3 + 3 + 3);
+
+    assert_eq!(cpu.regs[10], 40 + 100);
+}
+
+#[test]
+fn test_privileged_tail_runs_in_the_interpreter() {
+    let mut cpu = cpu_with::<Rv32>(
+        "
+                addi t0, zero, 50
+        loop:   addi a0, a0, 1
+                csrrs a1, mscratch, zero
+                addi t0, t0, -1
+                bne  t0, zero, loop
+                ebreak
+        ",
+        Engine::Jit,
+    );
+    cpu.csrs.write(csr::MSCRATCH, 0x77);
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(0x14))
+    );
+    assert_eq!(cpu.regs[10], 50);
+    assert_eq!(cpu.regs[11], 0x77);
+}
@@ -0,0 +1,213 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::decode::{Instruction, decode, decode_rv64};
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize};
+
+use common::{cpu_with, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn machine_with(source: &str, harts: usize) -> Machine {
+    program(source)
+        .engine(Engine::BasicBlocks)
+        .build_machine(harts)
+        .unwrap()
+}
+
+fn encoding(source: &str) -> u32 {
+    assemble(source).unwrap()[0]
+}
+
+// ── Encoding ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_lr_sc_round_trip_through_the_assembler() {
+    assert_eq!(
+        decode(encoding("lr.w a0, (a1)")),
+        Ok(Instruction::LrW { rd: 10, rs1: 11 })
+    );
+    assert_eq!(
+        decode(encoding("sc.w.aqrl t0, a2, 0(s0)")),
+        Ok(Instruction::ScW {
+            rd: 5,
+            rs1: 8,
+            rs2: 12
+        })
+    );
+    assert_eq!(encoding("lr.w.aq a0, (a1)") >> 25 & 0b11, 0b10);
+    assert_eq!(
+        decode(encoding("sc.w t0, a2, (s0)")).unwrap().to_string(),
+        "sc.w x5, x12, (x8)"
+    );
+}
+
+#[test]
+fn test_doubleword_forms_are_rv64_only() {
+    let lr_d = encoding("lr.d a0, (a1)");
+
+    assert_eq!(decode_rv64(lr_d), Ok(Instruction::LrD { rd: 10, rs1: 11 }));
+    assert!(decode(lr_d).is_err());
+    assert!(assemble("lr.w a0, 4(a1)").is_err(), "no offsets");
+}
+
+// ── One hart ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_sc_after_lr_succeeds() {
+    let mut cpu = cpu_with(
+        "
+        lui   s0, 1
+        addi  t0, zero, 7
+        sw    t0, 0(s0)
+        lr.w  a0, (s0)
+        addi  t1, a0, 1
+        sc.w  a1, t1, (s0)
+        ebreak
+        ",
+    );
+    cpu.run();
+
+    assert_eq!(cpu.regs[10], 7);
+    assert_eq!(cpu.regs[11], 0, "success");
+    assert_eq!(cpu.load(0x1000, MemSize::Word, false), Ok(8));
+}
+
+#[test]
+fn test_sc_without_reservation_fails() {
+    let mut cpu = cpu_with(
+        "
+        lui   s0, 1
+        addi  t1, zero, 5
+        sc.w  a1, t1, (s0)
+        ebreak
+        ",
+    );
+    cpu.run();
+
+    assert_eq!(cpu.regs[11], 1);
+    assert_eq!(cpu.load(0x1000, MemSize::Word, false), Ok(0));
+}
+
+#[test]
+fn test_store_to_the_line_breaks_the_reservation() {
+    let mut cpu = cpu_with(
+        "
+        lui   s0, 1
+        lr.w  a0, (s0)
+        sw    zero, 60(s0)
+        sc.w  a1, a0, (s0)
+        lr.w  a0, (s0)
+        sw    zero, 64(s0)
+        sc.w  a2, a0, (s0)
+        ebreak
+        ",
+    );
+    cpu.run();
+
+    assert_eq!(cpu.regs[11], 1, "same 64-byte line");
+    assert_eq!(cpu.regs[12], 0, "next line over");
+}
+
+#[test]
+fn test_sc_consumes_the_reservation() {
+    let mut cpu = cpu_with(
+        "
+        lui   s0, 1
+        lr.w  a0, (s0)
+        sc.w  a1, a0, (s0)
+        sc.w  a2, a0, (s0)
+        ebreak
+        ",
+    );
+    cpu.run();
+
+    assert_eq!((cpu.regs[11], cpu.regs[12]), (0, 1));
+}
+
+#[test]
+fn test_misaligned_lr_faults() {
+    let mut cpu = cpu_with("addi s0, zero, 0x102\nlr.w a0, (s0)");
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::LoadAccessFault(0x102))
+    );
+}
+
+// ── Several harts ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_another_harts_store_breaks_the_reservation() {
+    // Hart 0 reserves, hart 1 stores to the line, then hart 0 tries SC.
+    let mut machine = machine_with(
+        "
+                lui   s0, 1
+                csrrs t0, mhartid, zero
+                bne   t0, zero, other
+                lr.w  a0, (s0)
+                sc.w  a1, t0, (s0)
+                ebreak
+        other:  sw    t0, 8(s0)
+                ebreak
+        ",
+        2,
+    );
+    for _ in 0..4 {
+        machine.step_hart(0).unwrap();
+    }
+    for _ in 0..4 {
+        machine.step_hart(1).unwrap();
+    }
+    machine.step_hart(0).unwrap();
+
+    assert_eq!(machine.hart(0).regs[11], 1);
+}
+
+#[test]
+fn test_cas_loop_counts_correctly_under_contention() {
+    // Both harts add 1 to the counter at 0x1000 a hundred times.
+    let mut machine = machine_with(
+        "
+                lui   s0, 1
+                addi  s1, zero, 100
+        retry:  lr.w  t0, (s0)
+                addi  t0, t0, 1
+                sc.w  t1, t0, (s0)
+                bne   t1, zero, retry
+                addi  s1, s1, -1
+                bne   s1, zero, retry
+                lui   t2, 2
+                csrrs t3, mhartid, zero
+                slli  t3, t3, 2
+                add   t2, t2, t3
+                addi  t0, zero, 1
+                sw    t0, 0(t2)
+        done:   jal   zero, done
+        ",
+        2,
+    );
+
+    for quantum in [1, 2, 3] {
+        machine.bus.write(0x1000, MemSize::Word, 0).unwrap();
+        machine.bus.write(0x2000, MemSize::Word, 0).unwrap();
+        machine.bus.write(0x2004, MemSize::Word, 0).unwrap();
+        for id in 0..2 {
+            machine.hart_mut(id).pc = 0;
+        }
+        machine.set_quantum(quantum);
+
+        machine.run_steps(10_000);
+
+        assert_eq!(machine.bus.read(0x2000, MemSize::Word), Some(1));
+        assert_eq!(machine.bus.read(0x2004, MemSize::Word), Some(1));
+        assert_eq!(
+            machine.bus.read(0x1000, MemSize::Word),
+            Some(200),
+            "quantum {}",
+            quantum
+        );
+    }
+}
@@ -0,0 +1,235 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::{Clint, Device};
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::{Engine, ExitReason, MemSize};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn machine_with(source: &str, harts: usize, engine: Engine) -> Machine {
+    program(source).engine(engine).build_machine(harts).unwrap()
+}
+
+fn word(machine: &mut Machine, addr: u32) -> u32 {
+    machine.bus.read(addr, MemSize::Word).unwrap()
+}
+
+/// Each hart stores its ID plus one into its own slot at 0x1000.
+const HELLO: &str = "
+            csrrs a0, mhartid, zero
+            slli  t0, a0, 2
+            addi  a1, a0, 1
+            lui   t1, 1
+            add   t0, t0, t1
+            sw    a1, 0(t0)
+    spin:   jal   zero, spin
+";
+
+// ── Construction ──────────────────────────────────────────────────────────────
+
+#[test]
+fn test_harts_have_their_own_ids_and_state() {
+    let machine = machine_with(HELLO, 4, Engine::Interpreter);
+
+    assert_eq!(machine.hart_count(), 4);
+    for id in 0..4 {
+        assert_eq!(machine.hart(id).csrs.read(csr::MHARTID), id as u32);
+        assert_eq!(machine.hart(id).pc, 0);
+    }
+}
+
+#[test]
+fn test_every_hart_runs_on_the_shared_bus() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut machine = machine_with(HELLO, 3, engine);
+
+        assert_eq!(machine.run_steps(300).1, ExitReason::StepLimit);
+
+        for id in 0..3 {
+            assert_eq!(word(&mut machine, 0x1000 + 4 * id), id + 1, "{:?}", engine);
+            assert_eq!(machine.hart(id as usize).regs[10], id);
+        }
+    }
+}
+
+// ── Scheduling ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_quantum_of_one_interleaves_instructions() {
+    let mut machine = machine_with("top: addi a0, a0, 1\njal zero, top", 2, Engine::Interpreter);
+    machine.set_quantum(1);
+
+    machine.run_steps(9);
+
+    assert_eq!(machine.hart(0).regs[10], 3);
+    assert_eq!(machine.hart(1).regs[10], 2);
+}
+
+#[test]
+fn test_run_reports_which_hart_stopped() {
+    // Hart 1 hits an illegal write to mhartid; hart 0 spins.
+    let mut machine = machine_with(
+        "
+                csrrs a0, mhartid, zero
+                beq   a0, zero, spin
+                ecall
+        spin:   jal   zero, spin
+        ",
+        2,
+        Engine::Interpreter,
+    );
+
+    assert_eq!(
+        machine.run(),
+        (1, ExitReason::Exception(Exception::EnvironmentCall))
+    );
+    assert_eq!(machine.hart(1).pc, 0x8);
+}
+
+#[test]
+fn test_harts_communicate_through_memory() {
+    // Hart 0 publishes a value and sets a flag; hart 1 waits for the flag.
+    let mut machine = machine_with(
+        "
+                csrrs a0, mhartid, zero
+                lui   s0, 1
+                bne   a0, zero, wait
+                addi  t0, zero, 42
+                sw    t0, 4(s0)
+                addi  t0, zero, 1
+                sw    t0, 0(s0)
+        done:   jal   zero, done
+        wait:   lw    t0, 0(s0)
+                beq   t0, zero, wait
+                lw    a1, 4(s0)
+                ebreak
+        ",
+        2,
+        Engine::BasicBlocks,
+    );
+    machine.set_quantum(3);
+
+    assert_eq!(
+        machine.run(),
+        (1, ExitReason::Exception(Exception::Breakpoint(0x2C)))
+    );
+    assert_eq!(machine.hart(1).regs[11], 42);
+}
+
+#[test]
+fn test_store_by_one_hart_invalidates_another_harts_blocks() {
+    // Hart 1 spins in `body` long enough to cache it before hart 0 patches
+    // its first instruction.
+    let patch = assemble("addi a0, a0, 100").unwrap()[0];
+    let mut machine = machine_with(
+        &format!(
+            "
+                    csrrs a1, mhartid, zero
+                    bne   a1, zero, body
+                    addi  t2, zero, 20
+            delay:  addi  t2, t2, -1
+                    bne   t2, zero, delay
+                    lui   t0, {upper:#x}
+                    addi  t0, t0, {lower}
+                    sw    t0, 0x24(zero)
+            done:   jal   zero, done
+            body:   addi  a0, a0, 1
+                    jal   zero, body
+            ",
+            upper = (patch + 0x800) >> 12,
+            lower = ((patch & 0xFFF) as i32) << 20 >> 20,
+        ),
+        2,
+        Engine::BasicBlocks,
+    );
+    machine.set_quantum(8);
+
+    machine.run_steps(400);
+
+    assert!(
+        machine.hart(1).regs[10] > 1000,
+        "hart 1 ran the patched code"
+    );
+}
+
+// ── Interrupts ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_interrupt_targets_one_hart() {
+    let mut machine = machine_with("top: jal zero, top", 2, Engine::Interpreter);
+    for id in 0..2 {
+        let hart = machine.hart_mut(id);
+        hart.csrs.write(csr::MTVEC, 0x100);
+        hart.csrs.write(csr::MIE, csr::MIP_MSIP);
+        hart.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE);
+    }
+
+    machine
+        .hart_mut(1)
+        .raise_interrupt(Interrupt::MachineSoftware);
+    machine.step_hart(0).unwrap();
+    machine.step_hart(1).unwrap();
+
+    assert_eq!(machine.hart(0).pc, 0);
+    assert_eq!(machine.hart(1).pc, 0x100);
+}
+
+#[test]
+fn test_ipi_wakes_a_hart_waiting_in_wfi() {
+    // Hart 1 sleeps until hart 0 writes its msip, then records a0 = 7.
+    let mut machine = program(
+        "
+                csrrs a0, mhartid, zero
+                lui   t0, 0x2000
+                bne   a0, zero, sleep
+                addi  t2, zero, 50
+        delay:  addi  t2, t2, -1
+                bne   t2, zero, delay
+                addi  t1, zero, 1
+                sw    t1, 4(t0)
+        done:   jal   zero, done
+        sleep:  addi  t1, zero, 8
+                csrrs zero, mie, t1
+                wfi
+                addi  a0, zero, 7
+                ebreak
+        ",
+    )
+    .device(Clint::BASE, Clint::SIZE, Clint::new(2))
+    .build_machine(2)
+    .unwrap();
+    machine.set_quantum(4);
+
+    machine.run_steps(30);
+    assert!(machine.hart(1).is_waiting());
+
+    assert_eq!(
+        machine.run(),
+        (1, ExitReason::Exception(Exception::Breakpoint(0x34)))
+    );
+    assert_eq!(machine.hart(1).regs[10], 7);
+}
+
+#[test]
+fn test_all_harts_idle_skips_to_the_next_timer() {
+    let mut clint = Clint::new(2);
+    clint.write(0x4008, MemSize::Word, 5000);
+    clint.write(0x400C, MemSize::Word, 0);
+
+    let mut machine = program("addi t1, zero, 0x80\ncsrrs zero, mie, t1\nwfi\nebreak")
+        .device(Clint::BASE, Clint::SIZE, clint)
+        .build_machine(2)
+        .unwrap();
+
+    assert_eq!(
+        machine.run(),
+        (1, ExitReason::Exception(Exception::Breakpoint(0xC)))
+    );
+    assert!(machine.hart(0).is_waiting(), "hart 0's timer never fires");
+    assert!(machine.bus.read(0x0200_BFF8, MemSize::Word).unwrap() >= 5000);
+}
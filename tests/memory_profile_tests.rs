@@ -0,0 +1,172 @@
+mod common;
+
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::profile::{AccessCounts, MemoryProfile};
+use riscv_emulator_rust::{Engine, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source)
+        .ram_size(0x4000)
+        .engine(engine)
+        .memory_profile(true)
+        .build()
+        .unwrap()
+}
+
+fn counts(loads: u64, stores: u64) -> AccessCounts {
+    AccessCounts { loads, stores }
+}
+
+/// Bumps the word at 0x1000 three times, stores once to 0x1004 and loads
+/// once each from 0x1040 and 0x2040.
+const COUNTER: &str = "
+        lui   s0, 1
+        addi  a0, zero, 3
+    top:
+        lw    t0, 0(s0)
+        addi  t0, t0, 1
+        sw    t0, 0(s0)
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        sb    t0, 4(s0)
+        lui   s1, 2
+        lw    t1, 0x40(s0)
+        lw    t1, 0x40(s1)
+        ebreak
+";
+
+// ── Recording ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_loads_and_stores_are_counted_per_address() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = cpu_with(COUNTER, engine);
+        cpu.run();
+
+        let profile = cpu.memory_profile().unwrap();
+        assert_eq!(profile.counts(0x1000), counts(3, 3), "{:?}", engine);
+        assert_eq!(profile.counts(0x1004), counts(0, 1), "{:?}", engine);
+        assert_eq!(profile.counts(0x2040), counts(1, 0), "{:?}", engine);
+        assert_eq!(profile.total(), counts(5, 4), "{:?}", engine);
+        assert_eq!(profile.len(), 4, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_faulting_accesses_dont_count() {
+    let mut cpu = cpu_with("lui s0, 0x10\nlw t0, 0(s0)", Engine::Interpreter);
+
+    cpu.run();
+
+    assert!(cpu.memory_profile().unwrap().is_empty());
+}
+
+#[test]
+fn test_off_by_default() {
+    let mut cpu = RiscvCpu::new(0x4000);
+    assert!(cpu.memory_profile().is_none());
+
+    cpu.enable_memory_profile();
+    assert!(cpu.take_memory_profile().unwrap().is_empty());
+    assert!(cpu.memory_profile().is_none());
+}
+
+// ── Reports ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_hottest_addresses_come_first() {
+    let mut cpu = cpu_with(COUNTER, Engine::Interpreter);
+    cpu.run();
+    let profile = cpu.memory_profile().unwrap();
+
+    assert_eq!(
+        profile.hottest(3),
+        [
+            (0x1000, counts(3, 3)),
+            (0x1004, counts(0, 1)),
+            (0x1040, counts(1, 0)),
+        ]
+    );
+}
+
+#[test]
+fn test_pages_and_lines_group_addresses() {
+    let mut cpu = cpu_with(COUNTER, Engine::Interpreter);
+    cpu.run();
+    let profile = cpu.memory_profile().unwrap();
+
+    let pages: Vec<(u32, AccessCounts)> = profile.pages().into_iter().collect();
+    assert_eq!(pages, [(0x1000, counts(4, 4)), (0x2000, counts(1, 0))]);
+
+    let lines: Vec<(u32, AccessCounts)> = profile.regions(64).into_iter().collect();
+    assert_eq!(
+        lines,
+        [
+            (0x1000, counts(3, 4)),
+            (0x1040, counts(1, 0)),
+            (0x2040, counts(1, 0)),
+        ]
+    );
+}
+
+#[test]
+fn test_report_lists_addresses_then_pages() {
+    let mut cpu = cpu_with(COUNTER, Engine::Interpreter);
+    cpu.run();
+
+    let report = cpu.memory_profile().unwrap().to_string();
+    let lines: Vec<&str> = report.lines().collect();
+
+    assert!(lines[0].starts_with("address"));
+    assert_eq!(
+        lines[1].split_whitespace().collect::<Vec<_>>(),
+        ["0x00001000", "3", "3"]
+    );
+    let pages = lines
+        .iter()
+        .position(|line| line.starts_with("page"))
+        .unwrap();
+    assert_eq!(pages, 6);
+    assert_eq!(
+        lines[pages + 1].split_whitespace().collect::<Vec<_>>(),
+        ["0x00001000", "4", "4"]
+    );
+}
+
+// ── Combining ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_merge_adds_counts() {
+    let mut total = MemoryProfile::new();
+    for _ in 0..2 {
+        let mut cpu = cpu_with(COUNTER, Engine::Interpreter);
+        cpu.run();
+        total.merge(cpu.memory_profile().unwrap());
+    }
+
+    assert_eq!(total.counts(0x1000), counts(6, 6));
+}
+
+#[test]
+fn test_machine_combines_its_harts() {
+    let mut machine = Machine::new(cpu_with(COUNTER, Engine::Interpreter), 2);
+    machine.set_quantum(1);
+
+    machine.run_steps(40);
+
+    assert!(machine.hart(1).memory_profile().is_some());
+    let profile = machine.memory_profile().unwrap();
+    assert_eq!(profile.counts(0x1004).stores, 2);
+    assert_eq!(profile.counts(0x1000).total(), 12);
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::mmu::{PTE_A, PTE_D, PTE_R, PTE_U, PTE_V, PTE_W, PTE_X};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, MemSize, RiscvCpu};
+
+use common::{image, image_at};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const ROOT: u32 = 0x1000;
+const LEAF_TABLE: u32 = 0x2000;
+const CODE: u32 = 0x3000;
+const DATA: u32 = 0x5000;
+
+const CODE_VA: u32 = 0x4000_0000;
+const DATA_VA: u32 = 0x4000_1000;
+
+fn pte(pa: u32, flags: u32) -> u32 {
+    ((pa >> 12) << 10) | flags
+}
+
+fn read_word(cpu: &mut RiscvCpu, addr: u32) -> u32 {
+    cpu.bus.read(addr, MemSize::Word).unwrap()
+}
+
+/// Map one 4 KiB page through the shared second-level table.
+fn map(cpu: &mut RiscvCpu, va: u32, pa: u32, flags: u32) {
+    let root_entry = ROOT + (va >> 22) * 4;
+    cpu.bus
+        .write(root_entry, MemSize::Word, pte(LEAF_TABLE, PTE_V))
+        .unwrap();
+
+    let leaf_entry = LEAF_TABLE + ((va >> 12) & 0x3FF) * 4;
+    cpu.bus
+        .write(leaf_entry, MemSize::Word, pte(pa, flags | PTE_V))
+        .unwrap();
+}
+
+fn leaf_pte(cpu: &mut RiscvCpu, va: u32) -> u32 {
+    read_word(cpu, LEAF_TABLE + ((va >> 12) & 0x3FF) * 4)
+}
+
+/// An S-mode CPU with Sv32 on, `source` mapped executable at `CODE_VA` and
+/// the data page mapped with `data_flags`.
+fn paged_cpu(source: &str, data_flags: u32) -> RiscvCpu {
+    let mut cpu = RiscvCpu::builder()
+        .ram_size(0x10000)
+        .image(CODE, image_at(CODE_VA, source))
+        .build()
+        .unwrap();
+
+    map(&mut cpu, CODE_VA, CODE, PTE_R | PTE_X);
+    map(&mut cpu, DATA_VA, DATA, data_flags);
+    cpu.csrs.write(csr::SATP, csr::SATP_SV32 | (ROOT >> 12));
+    cpu.set_privilege(Privilege::Supervisor);
+    cpu.pc = CODE_VA;
+
+    cpu
+}
+
+// ── Translation ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_fetch_load_and_store_are_translated() {
+    let mut cpu = paged_cpu(
+        "
+        lui  t0, 0x40001
+        lw   a0, 0(t0)
+        addi a0, a0, 1
+        sw   a0, 4(t0)
+        ebreak
+        ",
+        PTE_R | PTE_W,
+    );
+    cpu.bus.write(DATA, MemSize::Word, 41).unwrap();
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(CODE_VA + 0x10))
+    );
+    assert_eq!(cpu.regs[10], 41 + 1);
+    assert_eq!(read_word(&mut cpu, DATA + 4), 42);
+}
+
+#[test]
+fn test_accessed_and_dirty_bits_are_set() {
+    let mut cpu = paged_cpu("lui t0, 0x40001\nlw a0, 0(t0)\nsw a0, 0(t0)", PTE_R | PTE_W);
+
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(leaf_pte(&mut cpu, CODE_VA) & PTE_A, PTE_A);
+    assert_eq!(leaf_pte(&mut cpu, DATA_VA) & (PTE_A | PTE_D), PTE_A);
+
+    cpu.step().unwrap();
+    assert_eq!(leaf_pte(&mut cpu, DATA_VA) & PTE_D, PTE_D);
+}
+
+#[test]
+fn test_megapage_maps_four_mebibytes() {
+    let mut cpu = paged_cpu("lui t0, 0x80008\nlw a0, 0x7c(t0)", 0);
+    // 0x8000_0000.. -> 0x0.. as a single leaf in the root table
+    cpu.bus
+        .write(ROOT + 0x200 * 4, MemSize::Word, pte(0, PTE_R | PTE_V))
+        .unwrap();
+    cpu.bus.write(0x807C, MemSize::Word, 0x1234).unwrap();
+
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.regs[10], 0x1234);
+}
+
+#[test]
+fn test_misaligned_megapage_faults() {
+    let mut cpu = paged_cpu("lui t0, 0x80000\nlw a0, 0(t0)", 0);
+    cpu.bus
+        .write(ROOT + 0x200 * 4, MemSize::Word, pte(0x1000, PTE_R | PTE_V))
+        .unwrap();
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.step(), Err(Exception::LoadPageFault(0x8000_0000)));
+}
+
+#[test]
+fn test_machine_mode_ignores_satp() {
+    let mut cpu = paged_cpu("", 0);
+    cpu.set_privilege(Privilege::Machine);
+    cpu.pc = CODE;
+    cpu.bus
+        .write(CODE, MemSize::Word, 0x0010_0073) // ebreak
+        .unwrap();
+
+    assert_eq!(cpu.step(), Err(Exception::Breakpoint(CODE)));
+}
+
+// ── Page faults ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_unmapped_and_read_only_pages_fault() {
+    let mut cpu = paged_cpu(
+        "
+        lui t0, 0x40001
+        lw  a0, 0(t0)
+        sw  a0, 0(t0)
+        ",
+        PTE_R,
+    );
+
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    assert_eq!(cpu.step(), Err(Exception::StorePageFault(DATA_VA)));
+
+    cpu.pc = 0x5000_0000;
+    assert_eq!(
+        cpu.step(),
+        Err(Exception::InstructionPageFault(0x5000_0000))
+    );
+}
+
+#[test]
+fn test_page_fault_traps_to_machine_mode() {
+    let mut cpu = paged_cpu("lui t0, 0x40002\nlw a0, 8(t0)", 0);
+    cpu.set_guest_traps(true);
+    cpu.csrs.write(csr::MTVEC, 0x100);
+
+    cpu.run_steps(2);
+
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 13);
+    assert_eq!(cpu.csrs.read(csr::MTVAL), 0x4000_2008);
+    assert_eq!(cpu.csrs.read(csr::MEPC), CODE_VA + 4);
+    assert_eq!(
+        cpu.csrs.read(csr::MSTATUS) & csr::MSTATUS_MPP,
+        1 << 11,
+        "MPP = S"
+    );
+}
+
+#[test]
+fn test_user_pages_need_sum_in_supervisor_mode() {
+    let mut cpu = paged_cpu("lui t0, 0x40001\nlw a0, 0(t0)\nlw a0, 0(t0)", PTE_R | PTE_U);
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.step(), Err(Exception::LoadPageFault(DATA_VA)));
+
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_SUM);
+    cpu.step().unwrap();
+}
+
+#[test]
+fn test_user_mode_only_sees_user_pages() {
+    let mut cpu = paged_cpu("ebreak", PTE_R);
+    cpu.set_privilege(Privilege::User);
+
+    assert_eq!(cpu.step(), Err(Exception::InstructionPageFault(CODE_VA)));
+
+    map(&mut cpu, CODE_VA, CODE, PTE_R | PTE_X | PTE_U);
+    assert_eq!(cpu.step(), Err(Exception::Breakpoint(CODE_VA)));
+}
+
+// ── Privilege transitions ─────────────────────────────────────────────────────
+
+#[test]
+fn test_mret_enters_supervisor_mode_and_ecall_reports_it() {
+    let source = "
+        addi  t0, zero, 1
+        slli  t0, t0, 11
+        csrrw zero, mstatus, t0
+        addi  t0, zero, 0x20
+        csrrw zero, mepc, t0
+        addi  t0, zero, 0x40
+        csrrw zero, mtvec, t0
+        mret
+        ecall
+    ";
+    let mut cpu = RiscvCpu::builder()
+        .guest_traps(true)
+        .image(0, image(source))
+        .build()
+        .unwrap();
+
+    cpu.run_steps(8);
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+    assert_eq!(cpu.csrs.read(csr::MSTATUS) & csr::MSTATUS_MPP, 0, "MPP = U");
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 9);
+}
+
+#[test]
+fn test_rv64_satp_stays_bare() {
+    let mut cpu = RiscvCpu::builder().xlen::<Rv64>().build().unwrap();
+
+    cpu.csrs.write(csr::SATP, 8 << 60); // Sv39
+
+    assert_eq!(cpu.csrs.read(csr::SATP), 0);
+}
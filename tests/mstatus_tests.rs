@@ -0,0 +1,277 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::float::FloatRegs;
+use riscv_emulator_rust::isa::{Extension, Extensions};
+use riscv_emulator_rust::mmu::{PTE_A, PTE_D, PTE_R, PTE_V, PTE_W};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+use common::{cpu_with, image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn mstatus(cpu: &RiscvCpu) -> u32 {
+    cpu.csrs.read(csr::MSTATUS)
+}
+
+fn fs(cpu: &RiscvCpu) -> u32 {
+    mstatus(cpu) >> 13 & 0b11
+}
+
+fn vs(cpu: &RiscvCpu) -> u32 {
+    mstatus(cpu) >> 9 & 0b11
+}
+
+const SD: u32 = 1 << 31;
+
+// ── Fields ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_write_mask_follows_the_extensions() {
+    let source = "
+        addi  t0, zero, -1
+        csrrw zero, mstatus, t0
+        csrrs a0, mstatus, zero
+        ";
+
+    let mut cpu = cpu_with(source);
+    cpu.run_steps(3);
+    let all = csr::MSTATUS_SIE
+        | csr::MSTATUS_MIE
+        | csr::MSTATUS_SPIE
+        | csr::MSTATUS_MPIE
+        | csr::MSTATUS_SPP
+        | csr::MSTATUS_VS
+        | csr::MSTATUS_MPP
+        | csr::MSTATUS_FS
+        | csr::MSTATUS_MPRV
+        | csr::MSTATUS_SUM
+        | csr::MSTATUS_MXR
+        | csr::MSTATUS_TVM
+        | csr::MSTATUS_TW
+        | csr::MSTATUS_TSR;
+    assert_eq!(cpu.regs[10], all | SD);
+
+    let mut cpu = program(source)
+        .extensions(Extensions::none())
+        .build()
+        .unwrap();
+    cpu.run_steps(3);
+    assert_eq!(
+        cpu.regs[10],
+        csr::MSTATUS_MIE | csr::MSTATUS_MPIE | csr::MSTATUS_MPP
+    );
+}
+
+#[test]
+fn test_rv64_fixed_fields() {
+    let cpu = RiscvCpu::builder().xlen::<Rv64>().build().unwrap();
+    let mstatus = cpu.csrs.read(csr::MSTATUS);
+    assert_eq!(mstatus >> 32 & 0b11, 2, "UXL");
+    assert_eq!(mstatus >> 34 & 0b11, 2, "SXL");
+
+    let mut cpu = program("csrrs a0, mstatush, zero")
+        .xlen::<Rv64>()
+        .build()
+        .unwrap();
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+
+    let mut cpu = cpu_with(
+        "
+        addi  t0, zero, -1
+        csrrw zero, mstatush, t0
+        csrrs a0, mstatush, zero
+        ",
+    );
+    cpu.run_steps(3);
+    assert_eq!(
+        cpu.regs[10],
+        csr::MSTATUSH_GVA | csr::MSTATUSH_MPV,
+        "RV32 mstatush only has the H fields"
+    );
+}
+
+// ── FS and VS ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_floating_point_dirties_fs() {
+    let mut cpu = cpu_with(
+        "
+        fsw      ft0, 0x100(zero)
+        fadd.s   ft0, ft1, ft2
+        ",
+    );
+    assert_eq!(fs(&cpu), csr::EXT_INITIAL);
+
+    cpu.step().unwrap();
+    assert_eq!(fs(&cpu), csr::EXT_INITIAL, "stores don't dirty");
+    assert_eq!(mstatus(&cpu) & SD, 0);
+
+    cpu.step().unwrap();
+    assert_eq!(fs(&cpu), csr::EXT_DIRTY);
+    assert_ne!(mstatus(&cpu) & SD, 0);
+}
+
+#[test]
+fn test_fs_off_disables_floating_point() {
+    let source = "
+        lui    t0, 0x6
+        csrrc  zero, mstatus, t0
+        fadd.s ft0, ft1, ft2
+        ";
+    let mut cpu = cpu_with(source);
+    cpu.run_steps(2);
+    assert_eq!(fs(&cpu), csr::EXT_OFF);
+    assert_eq!(
+        cpu.step(),
+        Err(Exception::IllegalInstruction(
+            assemble("fadd.s ft0, ft1, ft2").unwrap()[0]
+        ))
+    );
+
+    let mut cpu = cpu_with("csrrs a0, fcsr, zero");
+    cpu.csrs
+        .write(csr::MSTATUS, csr::MSTATUS_MPP | csr::EXT_OFF << 13);
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+
+    let mut cpu = program(source)
+        .float_regs(FloatRegs::Integer)
+        .build()
+        .unwrap();
+    cpu.run_steps(3);
+    assert_eq!(cpu.pc, 12, "Zfinx has no FS to turn off");
+}
+
+#[test]
+fn test_fcsr_writes_dirty_fs() {
+    let mut cpu = cpu_with("csrrwi zero, frm, 1");
+    cpu.csrs
+        .write(csr::MSTATUS, csr::MSTATUS_MPP | csr::EXT_CLEAN << 13);
+
+    cpu.step().unwrap();
+
+    assert_eq!(fs(&cpu), csr::EXT_DIRTY);
+}
+
+#[test]
+fn test_vector_state_tracking() {
+    let mut cpu = cpu_with(
+        "
+        vsetivli zero, 4, e32, m1
+        vsetivli zero, 4, e32, m1
+        ",
+    );
+    assert_eq!(vs(&cpu), csr::EXT_INITIAL);
+
+    cpu.step().unwrap();
+    assert_eq!(vs(&cpu), csr::EXT_DIRTY);
+
+    cpu.csrs
+        .write(csr::MSTATUS, csr::MSTATUS_MPP | csr::EXT_OFF << 9);
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+
+    let cpu = RiscvCpu::builder()
+        .extensions(Extensions::all().without(Extension::V))
+        .build()
+        .unwrap();
+    assert_eq!(vs(&cpu), 0, "no V, no VS");
+}
+
+// ── Privilege changes ─────────────────────────────────────────────────────────
+
+#[test]
+fn test_mret_resets_mpp_and_mprv() {
+    let mut cpu = cpu_with("mret");
+    let mpp_s = (Privilege::Supervisor as u32) << 11;
+    cpu.csrs.write(csr::MSTATUS, mpp_s | csr::MSTATUS_MPRV);
+    cpu.csrs.write(csr::MEPC, 0x100);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+    assert_eq!(mstatus(&cpu) & csr::MSTATUS_MPP, 0, "MPP <- U");
+    assert_eq!(mstatus(&cpu) & csr::MSTATUS_MPRV, 0);
+
+    let mut cpu = program("mret")
+        .extensions(Extensions::none())
+        .build()
+        .unwrap();
+    cpu.step().unwrap();
+    assert_eq!(
+        mstatus(&cpu) & csr::MSTATUS_MPP,
+        csr::MSTATUS_MPP,
+        "no U-mode to drop to"
+    );
+}
+
+#[test]
+fn test_mprv_translates_machine_loads_and_stores() {
+    const ROOT: u32 = 0x1000;
+    const LEAF_TABLE: u32 = 0x2000;
+    const DATA: u32 = 0x5000;
+
+    let mut cpu = RiscvCpu::builder()
+        .ram_size(0x10000)
+        .image(
+            0,
+            image(
+                "
+                lui  t0, 0x40001
+                lw   a0, 0(t0)
+                sw   a0, 4(t0)
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+    let pte = |pa: u32, flags: u32| ((pa >> 12) << 10) | flags;
+    cpu.bus
+        .write(ROOT + 0x100 * 4, MemSize::Word, pte(LEAF_TABLE, PTE_V))
+        .unwrap();
+    cpu.bus
+        .write(
+            LEAF_TABLE + 4,
+            MemSize::Word,
+            pte(DATA, PTE_V | PTE_R | PTE_W | PTE_A | PTE_D),
+        )
+        .unwrap();
+    cpu.bus.write(DATA, MemSize::Word, 42).unwrap();
+    cpu.csrs.write(csr::SATP, csr::SATP_SV32 | (ROOT >> 12));
+    let mpp_s = (Privilege::Supervisor as u32) << 11;
+    cpu.csrs.write(csr::MSTATUS, mpp_s | csr::MSTATUS_MPRV);
+
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+    assert_eq!(cpu.pc, 12, "fetches stay physical");
+    assert_eq!(cpu.regs[10], 42);
+    assert_eq!(cpu.bus.read(DATA + 4, MemSize::Word), Some(42));
+}
+
+#[test]
+fn test_tw_and_tvm_trap_supervisor_mode() {
+    let cases = [
+        ("wfi", csr::MSTATUS_TW),
+        ("sfence.vma zero, zero", csr::MSTATUS_TVM),
+        ("csrrs a0, satp, zero", csr::MSTATUS_TVM),
+    ];
+
+    for (source, bit) in cases {
+        let mut cpu = cpu_with(source);
+        cpu.set_privilege(Privilege::Supervisor);
+        cpu.csrs.write(csr::MSTATUS, bit);
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(assemble(source).unwrap()[0])),
+            "{}",
+            source
+        );
+
+        let mut cpu = cpu_with(source);
+        cpu.csrs.write(csr::MSTATUS, bit);
+        assert!(cpu.step().is_ok(), "M-mode ignores it: {}", source);
+    }
+}
@@ -0,0 +1,89 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use std::time::Duration;
+
+use riscv_emulator_rust::perf::PerfStats;
+use riscv_emulator_rust::{Engine, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source).engine(engine).build().unwrap()
+}
+
+const SPIN: &str = "top: addi a0, a0, 1\njal zero, top";
+
+// ── Counting ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_nothing_is_counted_until_started() {
+    let mut cpu = cpu_with(SPIN, Engine::Interpreter);
+    cpu.run_steps(100);
+
+    assert!(!cpu.perf_running());
+    assert_eq!(cpu.perf_stats(), PerfStats::default());
+}
+
+#[test]
+fn test_counts_executed_instructions_with_every_engine() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = cpu_with(SPIN, engine);
+        cpu.perf_start();
+        cpu.run_steps(1000);
+        cpu.perf_stop();
+
+        let stats = cpu.perf_stats();
+        assert_eq!(stats.instructions, 1000, "{:?}", engine);
+        assert!(stats.elapsed > Duration::ZERO);
+        assert!(stats.mips() > 0.0);
+    }
+}
+
+#[test]
+fn test_stop_pauses_and_start_resumes() {
+    let mut cpu = cpu_with(SPIN, Engine::Interpreter);
+    cpu.perf_start();
+    cpu.run_steps(10);
+    cpu.perf_stop();
+
+    let paused = cpu.perf_stats();
+    cpu.run_steps(10);
+    assert_eq!(cpu.perf_stats(), paused, "frozen while stopped");
+
+    cpu.perf_start();
+    cpu.run_steps(5);
+    assert_eq!(cpu.perf_stats().instructions, 15);
+}
+
+#[test]
+fn test_reset_clears_counts() {
+    let mut cpu = cpu_with(SPIN, Engine::Interpreter);
+    cpu.perf_start();
+    cpu.run_steps(10);
+
+    cpu.perf_reset();
+    assert_eq!(cpu.perf_stats().instructions, 0);
+    assert!(cpu.perf_running(), "reset doesn't stop the counter");
+
+    cpu.run_steps(3);
+    assert_eq!(cpu.perf_stats().instructions, 3);
+}
+
+// ── Derived figures ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_derived_figures() {
+    let stats = PerfStats {
+        instructions: 5_000_000,
+        elapsed: Duration::from_millis(500),
+    };
+
+    assert_eq!(stats.mips(), 10.0);
+    assert_eq!(stats.time_per_instruction(), Duration::from_nanos(100));
+    assert_eq!(PerfStats::default().mips(), 0.0);
+    assert_eq!(PerfStats::default().time_per_instruction(), Duration::ZERO);
+}
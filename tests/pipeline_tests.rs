@@ -0,0 +1,166 @@
+mod common;
+
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::timing::{PipelineConfig, PipelineStats};
+use riscv_emulator_rust::{Engine, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, config: PipelineConfig, engine: Engine) -> RiscvCpu {
+    program(source)
+        .ram_size(0x4000)
+        .engine(engine)
+        .pipeline(config)
+        .build()
+        .unwrap()
+}
+
+/// Run `source` to its `ebreak` on the default pipeline.
+fn run(source: &str) -> PipelineStats {
+    let mut cpu = cpu_with(source, PipelineConfig::default(), Engine::Interpreter);
+    cpu.run();
+    cpu.pipeline().unwrap().stats()
+}
+
+/// Counts a0 down from 3: seven instructions and two taken branches.
+const LOOP: &str = "
+        addi  a0, zero, 3
+    top:
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        ebreak
+";
+
+// ── Cycles ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_straight_line_code_retires_one_a_cycle_after_filling() {
+    let stats = run("addi a0, zero, 1\naddi a1, a0, 1\naddi a2, a1, 1\nebreak");
+
+    assert_eq!(stats.instructions, 3);
+    assert_eq!(stats.cycles, 4 + 3, "ALU results are forwarded");
+    assert_eq!(stats.load_use_stalls, 0);
+}
+
+#[test]
+fn test_using_a_load_right_away_stalls() {
+    let stats = run("lui s0, 1\nlw t0, 0(s0)\naddi t1, t0, 1\nebreak");
+    assert_eq!(stats.load_use_stalls, 1);
+    assert_eq!(stats.cycles, 4 + 3 + 1);
+
+    let scheduled = run("lui s0, 1\nlw t0, 0(s0)\naddi t2, zero, 1\naddi t1, t0, 1\nebreak");
+    assert_eq!(
+        scheduled.load_use_stalls, 0,
+        "an instruction in between hides it"
+    );
+    assert_eq!(scheduled.cycles, 4 + 4);
+}
+
+#[test]
+fn test_load_use_counts_float_and_store_operands() {
+    assert_eq!(
+        run("lui s0, 1\nflw f1, 0(s0)\nfadd.s f2, f1, f1\nebreak").load_use_stalls,
+        1
+    );
+    assert_eq!(
+        run("lui s0, 1\nlw t0, 0(s0)\nsw t0, 4(s0)\nebreak").load_use_stalls,
+        1
+    );
+    assert_eq!(
+        run("lui s0, 1\nlw zero, 0(s0)\naddi t1, zero, 1\nebreak").load_use_stalls,
+        0,
+        "nothing waits for x0"
+    );
+    assert_eq!(
+        run("lui s0, 1\nflw f5, 0(s0)\naddi t1, t0, 1\nebreak").load_use_stalls,
+        0,
+        "f5 isn't x5"
+    );
+}
+
+#[test]
+fn test_taken_branches_flush() {
+    let stats = run(LOOP);
+
+    assert_eq!(stats.instructions, 7);
+    assert_eq!((stats.flushes, stats.flush_cycles), (2, 4));
+    assert_eq!(stats.cycles, 4 + 7 + 4);
+    assert!((stats.cpi() - 15.0 / 7.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_jumps_always_flush() {
+    let stats = run("
+        jal   ra, function
+        ebreak
+    function:
+        jalr  zero, 0(ra)
+    ");
+
+    assert_eq!(stats.instructions, 2);
+    assert_eq!((stats.flushes, stats.flush_cycles), (2, 1 + 2));
+}
+
+#[test]
+fn test_hazard_costs_are_configurable() {
+    let config = PipelineConfig {
+        load_use_stall: 2,
+        branch_penalty: 3,
+        jump_penalty: 0,
+        indirect_jump_penalty: 0,
+    };
+    let mut cpu = cpu_with(LOOP, config, Engine::Interpreter);
+    cpu.run();
+
+    let stats = cpu.pipeline().unwrap().stats();
+    assert_eq!(stats.flush_cycles, 6);
+    assert_eq!(stats.cycles, 4 + 7 + 6);
+    assert_eq!(cpu.pipeline().unwrap().config(), config);
+}
+
+#[test]
+fn test_every_engine_agrees() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = cpu_with(LOOP, PipelineConfig::default(), engine);
+        cpu.run();
+
+        assert_eq!(cpu.pipeline().unwrap().stats(), run(LOOP), "{:?}", engine);
+    }
+}
+
+// ── Resetting ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_reset_keeps_the_pipeline_full() {
+    let mut cpu = cpu_with(LOOP, PipelineConfig::default(), Engine::Interpreter);
+    cpu.run_steps(1);
+
+    cpu.pipeline_mut().unwrap().reset_stats();
+    cpu.run();
+
+    let stats = cpu.pipeline().unwrap().stats();
+    assert_eq!(stats.instructions, 6);
+    assert_eq!(stats.cycles, 6 + 4, "no second fill");
+}
+
+#[test]
+fn test_each_hart_gets_an_empty_pipeline() {
+    let mut cpu = cpu_with(LOOP, PipelineConfig::default(), Engine::Interpreter);
+    cpu.run_steps(1);
+
+    let machine = Machine::new(cpu, 2);
+
+    let (hart0, hart1) = (machine.hart(0).pipeline(), machine.hart(1).pipeline());
+    assert_eq!(hart1.unwrap().stats(), PipelineStats::default());
+    assert_eq!(hart1.unwrap().config(), hart0.unwrap().config());
+    assert_eq!(hart0.unwrap().stats().instructions, 1);
+}
@@ -0,0 +1,148 @@
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::plic::{PlicLines, Routed};
+use riscv_emulator_rust::devices::{Device, Plic, Uart16550};
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const PENDING: u32 = 0x1000;
+const ENABLE: u32 = 0x2000;
+const CONTEXT: u32 = 0x20_0000;
+
+fn plic(harts: usize) -> (Plic, PlicLines) {
+    let plic = Plic::new(harts);
+    let lines = plic.lines();
+    (plic, lines)
+}
+
+fn set_priority(plic: &mut Plic, source: u32, priority: u32) {
+    plic.write(4 * source, MemSize::Word, priority);
+}
+
+fn enable(plic: &mut Plic, context: u32, sources: &[u32]) {
+    let bits = sources.iter().fold(0, |bits, source| bits | 1 << source);
+    plic.write(ENABLE + 0x80 * context, MemSize::Word, bits);
+}
+
+fn set_threshold(plic: &mut Plic, context: u32, threshold: u32) {
+    plic.write(CONTEXT + 0x1000 * context, MemSize::Word, threshold);
+}
+
+fn claim(plic: &mut Plic, context: u32) -> u32 {
+    plic.read(CONTEXT + 0x1000 * context + 4, MemSize::Word)
+}
+
+fn complete(plic: &mut Plic, context: u32, source: u32) {
+    plic.write(CONTEXT + 0x1000 * context + 4, MemSize::Word, source);
+}
+
+// ── Claims ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_claim_and_complete() {
+    let (mut plic, lines) = plic(1);
+    set_priority(&mut plic, 3, 1);
+    enable(&mut plic, 0, &[3]);
+    assert_eq!(plic.interrupts(0), 0);
+
+    lines.set(3, true);
+    assert_eq!(plic.interrupts(0), csr::MIP_MEIP);
+    assert_eq!(plic.read(PENDING, MemSize::Word), 1 << 3);
+
+    assert_eq!(claim(&mut plic, 0), 3);
+    assert_eq!(plic.interrupts(0), 0, "claimed sources aren't pending");
+    assert_eq!(plic.read(PENDING, MemSize::Word), 0);
+    assert_eq!(claim(&mut plic, 0), 0);
+
+    // The line is still high, so completing makes it pending again.
+    complete(&mut plic, 0, 3);
+    assert_eq!(plic.interrupts(0), csr::MIP_MEIP);
+
+    lines.set(3, false);
+    assert_eq!(plic.interrupts(0), 0);
+}
+
+#[test]
+fn test_priority_and_threshold() {
+    let (mut plic, lines) = plic(1);
+    for (source, priority) in [(2, 1), (5, 3), (7, 3), (9, 0)] {
+        set_priority(&mut plic, source, priority);
+        lines.set(source, true);
+    }
+    enable(&mut plic, 0, &[2, 5, 7, 9]);
+
+    set_threshold(&mut plic, 0, 3);
+    assert_eq!(plic.interrupts(0), 0, "only priorities above the threshold");
+
+    set_threshold(&mut plic, 0, 0);
+    assert_eq!(claim(&mut plic, 0), 5, "lowest number wins a tie");
+    assert_eq!(claim(&mut plic, 0), 7);
+    assert_eq!(claim(&mut plic, 0), 2);
+    assert_eq!(claim(&mut plic, 0), 0, "priority 0 never interrupts");
+}
+
+#[test]
+fn test_contexts_per_hart_and_mode() {
+    let (mut plic, lines) = plic(2);
+    set_priority(&mut plic, 1, 1);
+    lines.set(1, true);
+
+    enable(&mut plic, 1, &[1]);
+    assert_eq!(plic.interrupts(0), csr::MIP_SEIP);
+    assert_eq!(plic.interrupts(1), 0);
+
+    enable(&mut plic, 2, &[1]);
+    assert_eq!(plic.interrupts(1), csr::MIP_MEIP);
+
+    // Hart 1's M-mode context claims it away from hart 0's S-mode one.
+    assert_eq!(claim(&mut plic, 2), 1);
+    assert_eq!(plic.interrupts(0), 0);
+}
+
+// ── Routing ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_routed_device_drives_its_source() {
+    let (mut plic, lines) = plic(1);
+    let mut uart: Routed<Uart16550> =
+        lines.route(10, Uart16550::with_output(Box::new(std::io::sink())));
+    set_priority(&mut plic, 10, 1);
+    enable(&mut plic, 0, &[10]);
+
+    uart.write(1, MemSize::Byte, 1);
+    uart.inner().input().send(b'x').unwrap();
+    assert_eq!(uart.interrupts(0), 0, "it goes through the PLIC instead");
+    assert_eq!(plic.interrupts(0), csr::MIP_MEIP);
+
+    assert_eq!(claim(&mut plic, 0), 10);
+    assert_eq!(uart.read(0, MemSize::Byte), b'x' as u32);
+    complete(&mut plic, 0, 10);
+    assert_eq!(plic.interrupts(0), 0);
+}
+
+#[test]
+fn test_bus_sees_routed_interrupts() {
+    let plic = Plic::new(1);
+    let lines = plic.lines();
+    let uart = Uart16550::with_output(Box::new(std::io::sink()));
+    let input = uart.input();
+    let mut cpu = RiscvCpu::builder()
+        .device(Uart16550::BASE, Uart16550::SIZE, lines.route(10, uart))
+        .device(Plic::BASE, Plic::SIZE, plic)
+        .build()
+        .unwrap();
+
+    cpu.bus.write(Plic::BASE + 40, MemSize::Word, 1).unwrap();
+    cpu.bus
+        .write(Plic::BASE + ENABLE, MemSize::Word, 1 << 10)
+        .unwrap();
+    cpu.bus
+        .write(Uart16550::BASE + 1, MemSize::Byte, 1)
+        .unwrap();
+    assert_eq!(cpu.bus.interrupts(0), 0);
+
+    input.send(b'!').unwrap();
+    assert_eq!(cpu.bus.interrupts(0), csr::MIP_MEIP);
+    let claim = Plic::BASE + CONTEXT + 4;
+    assert_eq!(cpu.bus.read(claim, MemSize::Word).unwrap(), 10);
+}
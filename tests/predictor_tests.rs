@@ -0,0 +1,163 @@
+mod common;
+
+use riscv_emulator_rust::builder::RiscvCpuBuilder;
+use riscv_emulator_rust::predictor::{AlwaysTaken, Bimodal, BranchPredictor, Gshare, NeverTaken};
+use riscv_emulator_rust::timing::{PipelineConfig, PipelineStats};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn builder(source: &str) -> RiscvCpuBuilder {
+    program(source).ram_size(0x4000)
+}
+
+fn run_with(source: &str, predictor: impl BranchPredictor + 'static) -> PipelineStats {
+    let mut cpu = builder(source).branch_predictor(predictor).build().unwrap();
+    cpu.run();
+    cpu.pipeline().unwrap().stats()
+}
+
+/// Counts a0 down from 10: the branch is taken nine times, then falls
+/// through.
+const LOOP: &str = "
+        addi  a0, zero, 10
+    top:
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        ebreak
+";
+
+/// A loop of 32 whose body holds a branch that goes the other way each
+/// time round.
+const ALTERNATING: &str = "
+        addi  a0, zero, 32
+    top:
+        xori  t1, t1, 1
+        beq   t1, zero, skip
+        addi  t2, t2, 1
+    skip:
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        ebreak
+";
+
+// ── Predictors ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_the_default_predicts_not_taken() {
+    let stats = run_with(LOOP, NeverTaken);
+    assert_eq!(stats.branches, 10);
+    assert_eq!(stats.mispredictions, 9);
+    assert_eq!(
+        stats.flush_cycles,
+        9 * PipelineConfig::default().branch_penalty
+    );
+
+    let mut cpu = builder(LOOP)
+        .pipeline(PipelineConfig::default())
+        .build()
+        .unwrap();
+    cpu.run();
+    assert_eq!(cpu.pipeline().unwrap().stats(), stats);
+}
+
+#[test]
+fn test_always_taken_only_misses_the_loop_exit() {
+    let stats = run_with(LOOP, AlwaysTaken);
+    assert_eq!(stats.mispredictions, 1);
+    assert!((stats.prediction_accuracy() - 0.9).abs() < 1e-9);
+}
+
+#[test]
+fn test_bimodal_learns_the_loop_branch() {
+    let stats = run_with(LOOP, Bimodal::new(64).unwrap());
+    assert_eq!(
+        stats.mispredictions, 2,
+        "the first, while it warms up, and the exit"
+    );
+}
+
+#[test]
+fn test_gshare_learns_an_alternating_branch() {
+    let bimodal = run_with(ALTERNATING, Bimodal::new(64).unwrap());
+    let gshare = run_with(ALTERNATING, Gshare::new(4).unwrap());
+
+    assert_eq!(gshare.branches, 64);
+    assert!(
+        bimodal.mispredictions >= 16,
+        "a counter can't follow it: {}",
+        bimodal.mispredictions
+    );
+    assert!(
+        gshare.mispredictions * 2 < bimodal.mispredictions,
+        "history can, once it's warmed up: {}",
+        gshare.mispredictions
+    );
+}
+
+#[test]
+fn test_a_custom_predictor_sees_every_branch() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Predicts backward branches taken, and logs what it's told.
+    struct Backward(Rc<RefCell<Vec<(u32, bool)>>>);
+
+    impl BranchPredictor for Backward {
+        fn predict(&mut self, pc: u32) -> bool {
+            pc == 8
+        }
+
+        fn update(&mut self, pc: u32, taken: bool) {
+            self.0.borrow_mut().push((pc, taken));
+        }
+    }
+
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let stats = run_with(LOOP, Backward(log.clone()));
+
+    assert_eq!(stats.mispredictions, 1);
+    let log = log.borrow();
+    assert_eq!(log.len(), 10);
+    assert!(log[..9].iter().all(|&entry| entry == (8, true)));
+    assert_eq!(log[9], (8, false));
+}
+
+#[test]
+fn test_resetting_stats_keeps_what_the_predictor_learned() {
+    let mut cpu = builder(LOOP)
+        .branch_predictor(Bimodal::new(64).unwrap())
+        .build()
+        .unwrap();
+    cpu.run();
+
+    cpu.pipeline_mut().unwrap().reset_stats();
+    cpu.pc = 0;
+    cpu.run();
+    assert_eq!(
+        cpu.pipeline().unwrap().stats().mispredictions,
+        1,
+        "only the exit, now the counter knows the loop"
+    );
+}
+
+#[test]
+fn test_set_predictor_replaces_the_default() {
+    let mut cpu = builder(LOOP)
+        .pipeline(PipelineConfig::default())
+        .build()
+        .unwrap();
+    cpu.pipeline_mut().unwrap().set_predictor(AlwaysTaken);
+    cpu.run();
+    assert_eq!(cpu.pipeline().unwrap().stats().mispredictions, 1);
+}
+
+#[test]
+fn test_predictor_sizes_are_checked() {
+    assert!(Bimodal::new(100).unwrap_err().contains("Bimodal"));
+    assert!(Bimodal::new(0).is_err());
+    assert!(Gshare::new(0).unwrap_err().contains("Gshare"));
+    assert!(Gshare::new(25).is_err());
+    assert!(Gshare::new(24).is_ok());
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, privilege: Privilege) -> RiscvCpu {
+    let mut cpu = common::cpu_with(source);
+    cpu.set_privilege(privilege);
+    cpu
+}
+
+/// The raw encoding of the single instruction in `source`.
+fn encoding(source: &str) -> u32 {
+    assemble(source).unwrap()[0]
+}
+
+// ── Privileged instructions ───────────────────────────────────────────────────
+
+#[test]
+fn test_mret_is_machine_only() {
+    for privilege in [Privilege::User, Privilege::Supervisor] {
+        let mut cpu = cpu_with("mret", privilege);
+
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(encoding("mret"))),
+            "{:?}",
+            privilege
+        );
+    }
+}
+
+#[test]
+fn test_wfi_and_sfence_are_illegal_in_user_mode() {
+    for source in ["wfi", "sfence.vma"] {
+        let mut cpu = cpu_with(source, Privilege::User);
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(encoding(source)))
+        );
+
+        let mut cpu = cpu_with(source, Privilege::Supervisor);
+        assert!(cpu.step().is_ok(), "{} is allowed in S-mode", source);
+    }
+}
+
+// ── CSR access ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_machine_csrs_are_hidden_from_lower_modes() {
+    let source = "csrrs a0, mstatus, zero";
+
+    for privilege in [Privilege::User, Privilege::Supervisor] {
+        let mut cpu = cpu_with(source, privilege);
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(encoding(source)))
+        );
+    }
+
+    let mut cpu = cpu_with(source, Privilege::Machine);
+    cpu.step().unwrap();
+    let initial = csr::EXT_INITIAL << 13 | csr::EXT_INITIAL << 9;
+    assert_eq!(
+        cpu.regs[10],
+        csr::MSTATUS_MPP | initial,
+        "FS and VS Initial"
+    );
+}
+
+#[test]
+fn test_supervisor_csrs_need_supervisor_mode() {
+    let source = "csrrw a0, satp, zero";
+
+    let mut cpu = cpu_with(source, Privilege::User);
+    assert!(cpu.step().is_err());
+
+    let mut cpu = cpu_with(source, Privilege::Supervisor);
+    assert!(cpu.step().is_ok());
+}
+
+#[test]
+fn test_read_only_csrs_reject_writes() {
+    let mut cpu = cpu_with(
+        "csrrs a0, mhartid, zero\ncsrrsi a0, mhartid, 1",
+        Privilege::Machine,
+    );
+
+    cpu.step().unwrap();
+    assert_eq!(
+        cpu.step(),
+        Err(Exception::IllegalInstruction(encoding(
+            "csrrsi a0, mhartid, 1"
+        )))
+    );
+}
+
+#[test]
+fn test_unimplemented_csrs_are_illegal() {
+    // stimecmp (no Sstc), dcsr (no debug mode) and the mtime gap between
+    // mcycle and minstret.
+    for source in [
+        "csrrs a0, 0x14d, zero",
+        "csrrs a0, 0x7b0, zero",
+        "csrrs a0, 0xb01, zero",
+    ] {
+        let mut cpu = cpu_with(source, Privilege::Machine);
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(encoding(source))),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_firmware_csrs_exist_but_do_little() {
+    let mut cpu = cpu_with(
+        "
+        addi  t0, zero, -1
+        csrrw zero, pmpaddr0, t0
+        csrrs a0, pmpaddr0, zero
+        csrrw zero, pmpcfg0, t0
+        csrrs a1, pmpcfg0, zero
+        csrrw zero, menvcfg, t0
+        csrrs a2, menvcfg, zero
+        csrrw zero, mcountinhibit, t0
+        csrrs a3, mcountinhibit, zero
+        csrrs a4, mvendorid, zero
+        ",
+        Privilege::Machine,
+    );
+
+    cpu.run_steps(10);
+
+    assert_eq!(cpu.regs[10], 0, "no PMP entries");
+    assert_eq!(cpu.regs[11], 0);
+    assert_eq!(cpu.regs[12], csr::ENVCFG_FIOM, "no STCE without Sstc");
+    assert_eq!(cpu.regs[13], 0);
+    assert_eq!(cpu.regs[14], 0);
+}
+
+#[test]
+fn test_rv64_has_only_even_pmpcfgs() {
+    let source = "csrrs a0, pmpcfg1, zero";
+    let mut cpu = program(&format!("csrrs a0, pmpcfg2, zero\n{}", source))
+        .xlen::<Rv64>()
+        .build()
+        .unwrap();
+
+    cpu.step().unwrap();
+    assert_eq!(
+        cpu.step(),
+        Err(Exception::IllegalInstruction(encoding(source)))
+    );
+}
+
+// ── Traps from user mode ──────────────────────────────────────────────────────
+
+#[test]
+fn test_ecall_from_user_mode() {
+    let mut cpu = cpu_with("ecall", Privilege::User);
+
+    assert_eq!(cpu.step(), Err(Exception::UserEnvironmentCall));
+    assert_eq!(Exception::UserEnvironmentCall.cause(), 8);
+}
+
+/// M-mode drops into a user program that pokes at mstatus; the violation
+/// traps back to the M-mode handler with the instruction in mtval.
+#[test]
+fn test_violation_traps_back_to_machine_mode() {
+    let mut cpu = cpu_with(
+        "
+                addi  t0, zero, 0x20
+                csrrw zero, mepc, t0
+                addi  t0, zero, 0x40
+                csrrw zero, mtvec, t0
+                mret
+                .word 0
+                .word 0
+                .word 0
+        user:   csrrs a0, mstatus, zero
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+                .word 0
+        handler:
+                csrrs a1, mcause, zero
+                csrrs a2, mtval, zero
+                ebreak
+        ",
+        Privilege::Machine,
+    );
+    cpu.set_guest_traps(true);
+    // Reset leaves MPP = M, so clear it to return to U-mode.
+    cpu.csrs.write(csr::MSTATUS, 0);
+
+    assert_eq!(cpu.run_until(0x48), ExitReason::ReachedPc(0x48));
+    assert_eq!(cpu.regs[11], 2);
+    assert_eq!(cpu.regs[12], encoding("csrrs a0, mstatus, zero"));
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+    assert_eq!(
+        cpu.csrs.read(csr::MSTATUS) & csr::MSTATUS_MPP,
+        0,
+        "trapped from U-mode"
+    );
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use riscv_emulator_rust::program::Program;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{ExitReason, RiscvCpu};
+
+use common::{image, image_at};
+
+// ── Layout ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_matches_the_assembler() {
+    let program = Program::at(0)
+        .addi(1, 0, 10)
+        .label("loop")
+        .addi(1, 1, -1)
+        .bne(1, 0, "loop")
+        .beq(0, 0, "end")
+        .jal(0, "loop")
+        .label("end")
+        .ebreak()
+        .build()
+        .unwrap();
+
+    let source = "
+                addi x1, x0, 10
+        loop:   addi x1, x1, -1
+                bne  x1, x0, loop
+                beq  x0, x0, end
+                jal  x0, loop
+        end:    ebreak
+    ";
+    assert_eq!(program, image(source));
+}
+
+#[test]
+fn test_pseudo_instructions_match_the_assembler() {
+    let program = Program::at(0x8000_0000)
+        .li(10, 0x1234_5678)
+        .la(11, "data")
+        .call("func")
+        .mv(12, 10)
+        .nop()
+        .j("end")
+        .label("func")
+        .ret()
+        .label("end")
+        .ebreak()
+        .label("data")
+        .word(0xdead_beef)
+        .build()
+        .unwrap();
+
+    let source = "
+                li   a0, 0x12345678
+                la   a1, data
+                call func
+                mv   a2, a0
+                nop
+                j    end
+        func:   ret
+        end:    ebreak
+        data:   .word 0xdeadbeef
+    ";
+    assert_eq!(program, image_at(0x8000_0000, source));
+}
+
+#[test]
+fn test_here() {
+    let program = Program::at(0x100).nop().li(1, 0x12345);
+    assert_eq!(program.here(), 0x10C);
+}
+
+// ── Errors ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_undefined_label() {
+    assert_eq!(
+        Program::at(0).j("nowhere").build(),
+        Err("Program: undefined label `nowhere`".to_string())
+    );
+}
+
+#[test]
+fn test_duplicate_label() {
+    assert_eq!(
+        Program::at(0).label("a").nop().label("a").build(),
+        Err("Program: duplicate label `a`".to_string())
+    );
+}
+
+#[test]
+fn test_branch_out_of_range() {
+    let mut program = Program::at(0).beq(0, 0, "far");
+    for _ in 0..1023 {
+        program = program.nop();
+    }
+    assert_eq!(
+        program.label("far").build(),
+        Err("Program: `far` is out of range of the branch at 0x0".to_string())
+    );
+
+    let mut program = Program::at(0).beq(0, 0, "far");
+    for _ in 0..1022 {
+        program = program.nop();
+    }
+    assert!(program.label("far").build().is_ok());
+}
+
+// ── Running ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_runs() {
+    let image = Program::at(0)
+        .li(5, 10)
+        .li(10, 0)
+        .label("loop")
+        .add(10, 10, 5)
+        .addi(5, 5, -1)
+        .bne(5, 0, "loop")
+        .call("double")
+        .ebreak()
+        .label("double")
+        .add(10, 10, 10)
+        .ret()
+        .build()
+        .unwrap();
+
+    let mut cpu = RiscvCpu::builder().image(0, image).build().unwrap();
+    assert!(matches!(
+        cpu.run_steps(1000),
+        ExitReason::Exception(Exception::Breakpoint(_))
+    ));
+    assert_eq!(cpu.regs[10], 110);
+}
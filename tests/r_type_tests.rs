@@ -1,21 +1,5 @@
 use riscv_emulator_rust::RiscvCpu;
-
-/// Encode a generic R-type ALU instruction.
-///
-/// funct7: high 7 bits of funct (e.g., 0b0000000 for ADD, 0b0100000 for SUB)
-/// rs2: second source register
-/// rs1: first source register
-/// funct3: middle 3 bits of funct (e.g., 0b000 for ADD/SUB)
-/// rd: destination register
-/// opcode: usually 0x33 for OP
-fn encode_rtype(funct7: u8, rs2: u8, rs1: u8, funct3: u8, rd: u8, opcode: u8) -> u32 {
-    ((funct7 as u32) << 25)
-        | ((rs2 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | ((rd as u32) << 7)
-        | (opcode as u32)
-}
+use riscv_emulator_rust::encode::rtype;
 
 mod add_sub {
     use super::*;
@@ -27,7 +11,7 @@ mod add_sub {
         cpu.regs[2] = 5;
 
         // add x3, x1, x2  (x3 = 10 + 5 = 15)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b000, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b000, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 15);
@@ -41,7 +25,7 @@ mod add_sub {
         cpu.regs[2] = 3;
 
         // add x3, x1, x2  (x3 = -10 + 3 = -7)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b000, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b000, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3] as i32, -7);
@@ -54,7 +38,7 @@ mod add_sub {
         cpu.regs[2] = 1;
 
         // add x3, x1, x2  (x3 = 0xFFFF_FFFF + 1 = 0 (wrap))
-        let inst = encode_rtype(0b0000000, 2, 1, 0b000, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b000, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -67,7 +51,7 @@ mod add_sub {
         cpu.regs[2] = 5;
 
         // sub x3, x1, x2  (x3 = 10 - 5 = 5)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b000, 3, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b000, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 5);
@@ -80,7 +64,7 @@ mod add_sub {
         cpu.regs[2] = 10;
 
         // sub x3, x1, x2  (x3 = 5 - 10 = -5)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b000, 3, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b000, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3] as i32, -5);
@@ -93,7 +77,7 @@ mod add_sub {
         cpu.regs[2] = 1;
 
         // sub x3, x1, x2  (0 - 1 wraps to 0xFFFF_FFFF)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b000, 3, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b000, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0xFFFF_FFFF);
@@ -106,7 +90,7 @@ mod add_sub {
         cpu.regs[2] = 456;
 
         // add x0, x1, x2  (must not modify x0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b000, 0, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b000, 0, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[0], 0);
@@ -119,7 +103,7 @@ mod add_sub {
         cpu.regs[2] = 456;
 
         // sub x0, x1, x2  (must not modify x0)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b000, 0, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b000, 0, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[0], 0);
@@ -131,7 +115,7 @@ mod add_sub {
         cpu.regs[1] = 5;
 
         // add x1, x1, x1  (x1 = 5 + 5 = 10, all three regs are the same)
-        let inst = encode_rtype(0b0000000, 1, 1, 0b000, 1, 0x33);
+        let inst = rtype(0b0000000, 1, 1, 0b000, 1, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[1], 10);
@@ -144,7 +128,7 @@ mod add_sub {
         cpu.regs[2] = 7;
 
         // add x1, x1, x2  (rd == rs1, x1 = 3 + 7 = 10)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b000, 1, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b000, 1, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[1], 10);
@@ -162,7 +146,7 @@ mod sll {
         cpu.regs[2] = 3; // shift amount in low 5 bits
 
         // sll x3, x1, x2  (x3 = 1 << 3 = 8)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 8);
@@ -175,7 +159,7 @@ mod sll {
         cpu.regs[2] = 0b1_00000; // bit 5 set, low 5 bits = 0
 
         // sll x3, x1, x2  (x3 = 1 << (0) = 1)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1);
@@ -188,7 +172,7 @@ mod sll {
         cpu.regs[2] = 31;
 
         // sll x3, x1, x2  (x3 = 1 << 31)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1u32 << 31);
@@ -201,7 +185,7 @@ mod sll {
         cpu.regs[2] = 5;
 
         // sll x3, x1, x2  (0 << anything = 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b001, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -218,7 +202,7 @@ mod slt_sltu {
         cpu.regs[2] = 10;
 
         // slt x3, x1, x2  (5 < 10 => 1)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1);
@@ -231,7 +215,7 @@ mod slt_sltu {
         cpu.regs[2] = 10;
 
         // slt x3, x1, x2  (10 < 10 => 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -244,7 +228,7 @@ mod slt_sltu {
         cpu.regs[2] = 1;
 
         // slt x3, x1, x2  (-1 < 1 => 1)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1);
@@ -257,7 +241,7 @@ mod slt_sltu {
         cpu.regs[2] = 0xFFFF_FFFF;
 
         // sltu x3, x1, x2  (1 < 0xFFFF_FFFF unsigned => 1)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1);
@@ -270,7 +254,7 @@ mod slt_sltu {
         cpu.regs[2] = 123;
 
         // sltu x3, x1, x2  (0 < 123 => 1)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1);
@@ -283,7 +267,7 @@ mod slt_sltu {
         cpu.regs[2] = 0xFFFF_FFFF;
 
         // sltu x3, x1, x2  (equal => 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -296,7 +280,7 @@ mod slt_sltu {
         cpu.regs[2] = 1;
 
         // sltu x3, x1, x2  (0xFFFF_FFFF < 1? false => 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b011, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -309,7 +293,7 @@ mod slt_sltu {
         cpu.regs[2] = 42;
 
         // slt x3, x1, x2  (42 < 42? false => 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b010, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -326,7 +310,7 @@ mod xor_or_and {
         cpu.regs[2] = 0b0110;
 
         // xor x3, x1, x2  (1010 ^ 0110 = 1100)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b100, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b100, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0b1100);
@@ -339,7 +323,7 @@ mod xor_or_and {
         cpu.regs[2] = 0xDEAD_BEEF;
 
         // xor x3, x1, x2  (x ^ x = 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b100, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b100, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -352,7 +336,7 @@ mod xor_or_and {
         cpu.regs[2] = 0b0110;
 
         // or x3, x1, x2  (1010 | 0110 = 1110)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b110, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b110, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0b1110);
@@ -365,7 +349,7 @@ mod xor_or_and {
         cpu.regs[2] = 0x1234_5678;
 
         // or x3, x1, x2  (0 | x2 = x2)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b110, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b110, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0x1234_5678);
@@ -378,7 +362,7 @@ mod xor_or_and {
         cpu.regs[2] = 0b0110;
 
         // and x3, x1, x2  (1010 & 0110 = 0010)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b111, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b111, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0b0010);
@@ -391,7 +375,7 @@ mod xor_or_and {
         cpu.regs[2] = 0;
 
         // and x3, x1, x2  (x & 0 = 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b111, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b111, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0);
@@ -404,7 +388,7 @@ mod xor_or_and {
         cpu.regs[2] = 0x0000_0000;
 
         // xor x3, x1, x2  (0xFFFF_FFFF ^ 0 = 0xFFFF_FFFF)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b100, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b100, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0xFFFF_FFFF);
@@ -417,7 +401,7 @@ mod xor_or_and {
         cpu.regs[2] = 0x1234_5678;
 
         // or x3, x1, x2  (all-ones | anything = all-ones)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b110, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b110, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0xFFFF_FFFF);
@@ -430,7 +414,7 @@ mod xor_or_and {
         cpu.regs[2] = 0xFFFF_FFFF;
 
         // and x3, x1, x2  (all-ones & all-ones = all-ones)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b111, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b111, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0xFFFF_FFFF);
@@ -447,7 +431,7 @@ mod srl_sra {
         cpu.regs[2] = 3;
 
         // srl x3, x1, x2  (1000 >> 3 = 1)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1);
@@ -460,7 +444,7 @@ mod srl_sra {
         cpu.regs[2] = 31;
 
         // srl x3, x1, x2  (logical shift, result = 1)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 1);
@@ -473,7 +457,7 @@ mod srl_sra {
         cpu.regs[2] = 0b1_00000; // low 5 bits = 0
 
         // srl x3, x1, x2  (shift by 0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0xF000_0000);
@@ -486,7 +470,7 @@ mod srl_sra {
         cpu.regs[2] = 1;
 
         // sra x3, x1, x2  (arithmetic shift, preserve sign)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         // -8 >> 1 = -4
@@ -500,7 +484,7 @@ mod srl_sra {
         cpu.regs[2] = 31;
 
         // sra x3, x1, x2  (-1 >> 31 = -1 for arithmetic shift)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3] as i32, -1);
@@ -513,7 +497,7 @@ mod srl_sra {
         cpu.regs[2] = 0b1_00000; // low 5 bits = 0
 
         // sra x3, x1, x2  (shift by 0)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], cpu.regs[1]);
@@ -526,7 +510,7 @@ mod srl_sra {
         cpu.regs[2] = 2;
 
         // sra x3, x1, x2  (64 >> 2 = 16, positive: behaves same as SRL)
-        let inst = encode_rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0100000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 16);
@@ -541,7 +525,7 @@ mod srl_sra {
         cpu.regs[2] = 4;
 
         // srl x3, x1, x2  (0xFFFF_FFFF >> 4 = 0x0FFF_FFFF, zero-fill)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b101, 3, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[3], 0x0FFF_FFFF);
@@ -558,7 +542,7 @@ mod x0_behavior {
         cpu.regs[1] = 123;
 
         // add x2, x0, x1  (x2 = 0 + 123)
-        let inst = encode_rtype(0b0000000, 1, 0, 0b000, 2, 0x33);
+        let inst = rtype(0b0000000, 1, 0, 0b000, 2, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[2], 123);
@@ -572,7 +556,7 @@ mod x0_behavior {
         cpu.regs[2] = 0xFFFF_FFFF;
 
         // and x0, x1, x2  (should not change x0)
-        let inst = encode_rtype(0b0000000, 2, 1, 0b111, 0, 0x33);
+        let inst = rtype(0b0000000, 2, 1, 0b111, 0, 0x33);
         cpu.handle_rtype(inst).unwrap();
 
         assert_eq!(cpu.regs[0], 0);
@@ -0,0 +1,84 @@
+mod common;
+
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, Reg, RiscvCpu};
+
+use common::{cpu_with, image};
+
+// ── Names ─────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_indices_and_names() {
+    assert_eq!(Reg::Zero.index(), 0);
+    assert_eq!(Reg::Sp.index(), 2);
+    assert_eq!(Reg::S0.index(), 8);
+    assert_eq!(Reg::A0.index(), 10);
+    assert_eq!(Reg::S2.index(), 18);
+    assert_eq!(Reg::T6.index(), 31);
+    assert_eq!(u8::from(Reg::A7), 17);
+
+    assert_eq!(Reg::A0.to_string(), "a0");
+    assert_eq!(Reg::S11.name(), "s11");
+    for (i, reg) in Reg::ALL.iter().enumerate() {
+        assert_eq!(reg.index() as usize, i);
+        assert_eq!(Reg::from_index(i as u8), Some(*reg));
+    }
+    assert_eq!(Reg::from_index(32), None);
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!("a0".parse(), Ok(Reg::A0));
+    assert_eq!("x10".parse(), Ok(Reg::A0));
+    assert_eq!("fp".parse(), Ok(Reg::S0));
+    assert_eq!("zero".parse(), Ok(Reg::Zero));
+    assert_eq!(
+        "x32".parse::<Reg>(),
+        Err("Reg: no register named \"x32\"".to_string())
+    );
+    assert!("a8".parse::<Reg>().is_err());
+    assert!("x+1".parse::<Reg>().is_err());
+}
+
+// ── Accessors ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_reg_and_set_reg() {
+    let mut cpu = cpu_with("add a0, a1, a2\nebreak");
+    cpu.set_reg(Reg::A1, 40);
+    cpu.set_reg(Reg::A2, 2);
+    cpu.run();
+    assert_eq!(cpu.reg(Reg::A0), 42);
+    assert_eq!(cpu.regs[10], 42);
+}
+
+#[test]
+fn test_zero_stays_zero() {
+    let mut cpu = cpu_with("addi a0, zero, 1\nebreak");
+    cpu.set_reg(Reg::Zero, 5);
+    assert_eq!(cpu.reg(Reg::Zero), 0);
+    cpu.run();
+    assert_eq!(cpu.reg(Reg::A0), 1);
+}
+
+#[test]
+fn test_set_reg_truncates_to_xlen() {
+    let mut cpu = RiscvCpu::builder().build().unwrap();
+    cpu.set_reg(Reg::T0, 0x1_2345_6789);
+    assert_eq!(cpu.reg(Reg::T0), 0x2345_6789);
+
+    let mut cpu = RiscvCpu::builder().xlen::<Rv64>().build().unwrap();
+    cpu.set_reg(Reg::T0, u64::MAX);
+    assert_eq!(cpu.reg(Reg::T0), u64::MAX);
+}
+
+#[test]
+fn test_stack_pointer_is_sp() {
+    let mut cpu = RiscvCpu::builder()
+        .stack_pointer(0x800)
+        .image(0, image("ebreak"))
+        .build()
+        .unwrap();
+    assert_eq!(cpu.reg(Reg::Sp), 0x800);
+    assert!(matches!(cpu.run(), ExitReason::Exception(_)));
+}
@@ -0,0 +1,117 @@
+#![cfg(feature = "std")]
+
+use std::thread;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::remote::HartThread;
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::xlen::Rv32;
+use riscv_emulator_rust::{ExitReason, RiscvCpu, csr};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn spawn(source: &'static str) -> HartThread {
+    HartThread::spawn(move || {
+        let words = assemble(source).map_err(|e| format!("{:?}", e))?;
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        RiscvCpu::builder()
+            .image(0, bytes)
+            .guest_traps(true)
+            .build()
+    })
+    .unwrap()
+}
+
+/// Count in t0 forever.
+const SPIN: &str = "
+    loop:   addi t0, t0, 1
+            jal  zero, loop
+";
+
+// ── Commands ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_starts_paused_and_runs_closures() {
+    let hart = spawn(SPIN);
+
+    assert_eq!(hart.with(|cpu| cpu.pc).unwrap(), 0);
+    hart.with(|cpu| cpu.regs[5] = 41).unwrap();
+    assert_eq!(hart.with(|cpu| cpu.regs[5]).unwrap(), 41);
+    assert_eq!(hart.stopped(), None);
+}
+
+#[test]
+fn test_inspect_while_running_from_another_thread() {
+    let hart = spawn(SPIN);
+    hart.resume().unwrap();
+
+    let handle = hart.handle();
+    let counted = thread::spawn(move || {
+        let first = handle.with(|cpu| cpu.regs[5]).unwrap();
+        let mut later = first;
+        while later == first {
+            later = handle.with(|cpu| cpu.regs[5]).unwrap();
+        }
+        later > first
+    })
+    .join()
+    .unwrap();
+    assert!(counted);
+
+    hart.pause().unwrap();
+    let paused = hart.with(|cpu| cpu.regs[5]).unwrap();
+    assert_eq!(hart.with(|cpu| cpu.regs[5]).unwrap(), paused);
+}
+
+#[test]
+fn test_stops_are_reported() {
+    let hart = spawn(
+        "
+        addi t0, zero, 1
+        ecall
+        ",
+    );
+    hart.with(|cpu| cpu.set_guest_traps(false)).unwrap();
+    hart.resume().unwrap();
+
+    assert_eq!(
+        hart.wait().unwrap(),
+        ExitReason::Exception(Exception::EnvironmentCall)
+    );
+    assert_eq!(hart.with(|cpu| cpu.regs[5]).unwrap(), 1);
+}
+
+#[test]
+fn test_interrupts_wake_a_waiting_hart() {
+    let hart = spawn(
+        "
+                wfi
+        park:   jal  zero, park
+        handler:
+                addi a0, zero, 7
+                jal  zero, handler
+        ",
+    );
+    hart.with(|cpu| {
+        cpu.csrs.write(csr::MTVEC, 0x8);
+        cpu.csrs.write(csr::MIE, csr::MIP_MSIP);
+        cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE);
+    })
+    .unwrap();
+    hart.resume().unwrap();
+    hart.raise_interrupt(Interrupt::MachineSoftware).unwrap();
+
+    while hart.with(|cpu| cpu.regs[10]).unwrap() != 7 {
+        thread::yield_now();
+    }
+    assert_eq!(
+        hart.with(|cpu| cpu.csrs.read(csr::MCAUSE)).unwrap(),
+        0x8000_0003
+    );
+}
+
+#[test]
+fn test_build_errors_are_returned() {
+    let spawned = HartThread::<Rv32>::spawn(|| Err("no hart today".to_string()));
+    assert_eq!(spawned.err(), Some("no hart today".to_string()));
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu, csr};
+
+use common::cpu_with;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn boxed(value: f32) -> u64 {
+    0xFFFF_FFFF_0000_0000 | value.to_bits() as u64
+}
+
+const COUNTER: &str = "
+            addi t0, zero, 3
+    loop:   addi t0, t0, -1
+            sw   t0, 0x100(zero)
+            bne  t0, zero, loop
+            csrrw zero, mscratch, t0
+            lw   t1, 0x100(zero)
+            ebreak
+";
+
+// ── Stepping back ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_step_back_undoes_registers_and_pc() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.enable_reverse(100);
+
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.pc, 0xC);
+    assert_eq!(cpu.reverse_depth(), 3);
+
+    assert_eq!(cpu.step_back(2), 2);
+    assert_eq!(cpu.pc, 0x4);
+    assert_eq!(cpu.regs[5], 3);
+    assert_eq!(cpu.reverse_depth(), 1);
+}
+
+#[test]
+fn test_step_back_undoes_stores_and_csrs() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.csrs.write(csr::MSCRATCH, 0x55);
+    cpu.enable_reverse(100);
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(0x18))
+    );
+    assert_eq!(cpu.bus.read(0x100, MemSize::Word), Some(0));
+    assert_eq!(cpu.csrs.read(csr::MSCRATCH), 0);
+    let steps = cpu.reverse_depth() as u64;
+
+    // Back over the LW and CSRRW, and the last store.
+    assert_eq!(cpu.step_back(4), 4);
+    assert_eq!(cpu.pc, 0x8);
+    assert_eq!(cpu.regs[5], 0);
+    assert_eq!(cpu.bus.read(0x100, MemSize::Word), Some(1));
+    assert_eq!(cpu.csrs.read(csr::MSCRATCH), 0x55);
+
+    let mut fresh = cpu_with(COUNTER);
+    fresh.csrs.write(csr::MSCRATCH, 0x55);
+    fresh.run_steps(steps - 4);
+    assert_eq!(cpu.regs, fresh.regs);
+    assert_eq!(cpu.pc, fresh.pc);
+    assert_eq!(cpu.csrs.read(csr::MINSTRET), fresh.csrs.read(csr::MINSTRET));
+}
+
+#[test]
+fn test_step_back_to_the_start() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.enable_reverse(100);
+    cpu.run();
+
+    let steps = cpu.reverse_depth();
+    assert_eq!(cpu.step_back(1000), steps);
+    assert_eq!(cpu.pc, 0);
+    assert!(cpu.regs.iter().all(|&reg| reg == 0));
+    assert_eq!(cpu.bus.read(0x100, MemSize::Word), Some(0));
+    assert_eq!(cpu.csrs.read(csr::MINSTRET), 0);
+    assert_eq!(cpu.step_back(1), 0);
+}
+
+#[test]
+fn test_running_forward_again_repeats_the_same_steps() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.enable_reverse(100);
+    cpu.run();
+    let regs = cpu.regs;
+
+    cpu.step_back(6);
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(0x18))
+    );
+    assert_eq!(cpu.regs, regs);
+}
+
+#[test]
+fn test_capacity_bounds_how_far_back() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.enable_reverse(3);
+    cpu.run();
+
+    assert_eq!(cpu.reverse_depth(), 3);
+    assert_eq!(cpu.step_back(10), 3);
+    assert_eq!(cpu.pc, 0xC, "only the last three steps are undone");
+}
+
+#[test]
+fn test_step_back_undoes_float_registers() {
+    let mut cpu = cpu_with(
+        "
+        addi     t0, zero, 3
+        fcvt.s.w ft0, t0
+        fadd.s   ft0, ft0, ft0
+        ebreak
+        ",
+    );
+    cpu.enable_reverse(100);
+    cpu.run();
+    assert_eq!(cpu.fregs[0], boxed(6.0));
+
+    cpu.step_back(1);
+    assert_eq!(cpu.fregs[0], boxed(3.0));
+    cpu.step_back(1);
+    assert_eq!(cpu.fregs[0], 0);
+}
+
+#[test]
+fn test_breakpoints_are_not_steps() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.enable_reverse(100);
+    cpu.add_breakpoint(0x8);
+
+    assert_eq!(cpu.run(), ExitReason::Breakpoint(0x8));
+    assert_eq!(cpu.reverse_depth(), 2);
+    assert_eq!(cpu.step_back(1), 1);
+    assert_eq!(cpu.pc, 0x4);
+}
+
+#[test]
+fn test_journaling_runs_one_instruction_at_a_time() {
+    let mut cpu = RiscvCpu::builder()
+        .image(
+            0,
+            assemble(COUNTER)
+                .unwrap()
+                .iter()
+                .flat_map(|w| w.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        )
+        .engine(Engine::BasicBlocks)
+        .build()
+        .unwrap();
+    cpu.enable_reverse(100);
+    cpu.run();
+
+    assert_eq!(cpu.step_back(3), 3);
+    assert_eq!(cpu.pc, 0xC);
+}
+
+#[test]
+fn test_disable_reverse_forgets_the_journal() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.enable_reverse(100);
+    cpu.step().unwrap();
+    cpu.disable_reverse();
+
+    assert_eq!(cpu.reverse_depth(), 0);
+    assert_eq!(cpu.step_back(1), 0);
+    assert_eq!(cpu.pc, 0x4);
+}
@@ -0,0 +1,198 @@
+#![cfg(feature = "std")]
+
+mod common;
+
+use std::fs;
+
+use riscv_emulator_rust::loader::ElfFile;
+use riscv_emulator_rust::riscv_tests::{RAM_BASE, TestOutcome, run_elf, run_suite};
+use riscv_emulator_rust::trap::Exception;
+
+use common::image_at;
+
+// ── Helper: a miniature riscv-tests style ELF ─────────────────────────────────
+
+const TOHOST: u32 = RAM_BASE + 0x1000;
+
+/// Build an ELF32 RISC-V executable with one PT_LOAD segment at `RAM_BASE`
+/// (sized to cover `TOHOST`) and, if `with_tohost`, a symbol table defining
+/// `tohost`.
+fn build_elf(code: &[u8], with_tohost: bool) -> Vec<u8> {
+    let code_off = 52 + 32;
+    let strtab = b"\0tohost\0";
+    let strtab_off = code_off + code.len();
+    let symtab_off = strtab_off + strtab.len();
+    let shoff = symtab_off + 32;
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+    out.extend_from_slice(&243u16.to_le_bytes()); // e_machine = EM_RISCV
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&RAM_BASE.to_le_bytes()); // e_entry
+    out.extend_from_slice(&52u32.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&(shoff as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&52u16.to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&32u16.to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&(if with_tohost { 3u16 } else { 0 }).to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    let memsz = TOHOST + 4 - RAM_BASE;
+    for field in [
+        1,
+        code_off as u32,
+        RAM_BASE,
+        RAM_BASE,
+        code.len() as u32,
+        memsz,
+        0x7,
+        4,
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+
+    out.extend_from_slice(code);
+    out.extend_from_slice(strtab);
+
+    // symtab: the null symbol, then `tohost`
+    out.extend_from_slice(&[0; 16]);
+    for field in [1, TOHOST, 4] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend_from_slice(&[0x11, 0, 1, 0]); // STB_GLOBAL | STT_OBJECT, shndx 1
+
+    // section headers: null, .symtab (link -> 2), .strtab
+    let sections: [[u32; 10]; 3] = [
+        [0; 10],
+        [0, 2, 0, 0, symtab_off as u32, 32, 2, 1, 4, 16],
+        [
+            0,
+            3,
+            0,
+            0,
+            strtab_off as u32,
+            strtab.len() as u32,
+            0,
+            0,
+            1,
+            0,
+        ],
+    ];
+    for section in sections {
+        for field in section {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+
+    out
+}
+
+/// A test whose trap handler stores `gp` to `tohost`, like the `p`
+/// environment's `write_tohost`.
+fn test_program(body: &str) -> Vec<u8> {
+    let source = format!(
+        "
+                jal   zero, start
+        handler:
+                lui   t5, 0x80001
+                sw    gp, 0(t5)
+        spin:   jal   zero, spin
+        start:  auipc t0, 0
+                addi  t0, t0, -12
+                csrrw zero, mtvec, t0
+                {body}
+        "
+    );
+    let code = image_at(RAM_BASE, &source);
+
+    build_elf(&code, true)
+}
+
+// ── Single tests ──────────────────────────────────────────────────────────────
+
+#[test]
+fn test_symbol_lookup() {
+    let elf = test_program("ecall");
+    let parsed = ElfFile::parse(&elf).unwrap();
+
+    assert_eq!(parsed.symbol("tohost"), Some(TOHOST));
+    assert_eq!(parsed.symbol("fromhost"), None);
+}
+
+#[test]
+fn test_pass() {
+    let elf = test_program("addi gp, zero, 1\necall");
+
+    assert_eq!(run_elf(&elf, 1000), TestOutcome::Pass);
+}
+
+#[test]
+fn test_fail_reports_case_number() {
+    let elf = test_program("addi gp, zero, 7\necall");
+
+    assert_eq!(run_elf(&elf, 1000), TestOutcome::Fail(3));
+}
+
+#[test]
+fn test_timeout() {
+    let elf = test_program("forever: jal zero, forever");
+
+    assert_eq!(run_elf(&elf, 1000), TestOutcome::Timeout);
+}
+
+#[test]
+fn test_unhandled_fetch_fault_halts() {
+    let elf = test_program("jalr zero, 0(zero)");
+
+    assert_eq!(
+        run_elf(&elf, 1000),
+        TestOutcome::Halted(Exception::InstructionAccessFault(0))
+    );
+}
+
+#[test]
+fn test_missing_tohost_is_an_error() {
+    let elf = build_elf(&[0x73, 0, 0, 0], false);
+
+    assert_eq!(
+        run_elf(&elf, 1000),
+        TestOutcome::Error(String::from("no `tohost` symbol"))
+    );
+}
+
+// ── Suites ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_run_suite_filters_and_sorts() {
+    let dir = std::env::temp_dir().join(format!("riscv-tests-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(
+        dir.join("rv32ui-p-sub"),
+        test_program("addi gp, zero, 5\necall"),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("rv32ui-p-add"),
+        test_program("addi gp, zero, 1\necall"),
+    )
+    .unwrap();
+    fs::write(dir.join("rv32ui-p-add.dump"), "not an elf").unwrap();
+    fs::write(dir.join("rv32um-p-mul"), "not an elf").unwrap();
+
+    let results = run_suite(&dir, "rv32ui-p-").unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(
+        results,
+        vec![
+            (String::from("rv32ui-p-add"), TestOutcome::Pass),
+            (String::from("rv32ui-p-sub"), TestOutcome::Fail(2)),
+        ]
+    );
+    assert_eq!(TestOutcome::Fail(2).to_string(), "FAIL (test 2)");
+}
@@ -0,0 +1,177 @@
+mod common;
+
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use riscv_emulator_rust::ExitReason;
+#[cfg(feature = "std")]
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::debug::{WatchHit, WatchKind};
+use riscv_emulator_rust::trap::Exception;
+
+use common::cpu_with;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Sums 5..1 into a0.
+const SUM: &str = "
+            addi a0, zero, 0
+            addi t0, zero, 5
+    loop:   add  a0, a0, t0
+            addi t0, t0, -1
+            bne  t0, zero, loop
+    done:   ebreak
+";
+
+// ── run ───────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_run_stops_on_exception() {
+    let mut cpu = cpu_with(SUM);
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(0x14))
+    );
+    assert_eq!(cpu.regs[10], 15);
+}
+
+#[test]
+fn test_run_stops_on_breakpoint_and_resumes() {
+    let mut cpu = cpu_with(SUM);
+    cpu.add_breakpoint(0x8);
+
+    assert_eq!(cpu.run(), ExitReason::Breakpoint(0x8));
+    assert_eq!(cpu.regs[10], 0);
+
+    assert_eq!(cpu.run(), ExitReason::Breakpoint(0x8));
+    assert_eq!(cpu.regs[10], 5, "one loop iteration later");
+}
+
+#[test]
+fn test_run_stops_on_watchpoint() {
+    let mut cpu = cpu_with(
+        "
+        addi t0, zero, 7
+        sw   t0, 0x200(zero)
+        ebreak
+    ",
+    );
+    cpu.add_watchpoint(0x200, 4, WatchKind::Write);
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Watchpoint(WatchHit {
+            pc: 0x4,
+            addr: 0x200,
+            kind: WatchKind::Write
+        })
+    );
+}
+
+// ── run_steps ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_run_steps_executes_exactly_n() {
+    let mut cpu = cpu_with(SUM);
+
+    assert_eq!(cpu.run_steps(3), ExitReason::StepLimit);
+    assert_eq!(cpu.pc, 0xC);
+    assert_eq!(cpu.regs[10], 5);
+
+    assert_eq!(cpu.run_steps(0), ExitReason::StepLimit);
+    assert_eq!(cpu.pc, 0xC);
+}
+
+#[test]
+fn test_run_steps_returns_early_on_exception() {
+    let mut cpu = cpu_with(SUM);
+
+    assert_eq!(
+        cpu.run_steps(1000),
+        ExitReason::Exception(Exception::Breakpoint(0x14))
+    );
+}
+
+// ── run_until ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_run_until_stops_before_target() {
+    let mut cpu = cpu_with(SUM);
+
+    assert_eq!(cpu.run_until(0x14), ExitReason::ReachedPc(0x14));
+    assert_eq!(cpu.pc, 0x14);
+    assert_eq!(cpu.regs[10], 15);
+}
+
+#[test]
+fn test_run_until_from_target_runs_to_next_visit() {
+    let mut cpu = cpu_with(SUM);
+
+    assert_eq!(cpu.run_until(0x8), ExitReason::ReachedPc(0x8));
+    assert_eq!(cpu.run_until(0x8), ExitReason::ReachedPc(0x8));
+    assert_eq!(cpu.regs[10], 5);
+}
+
+#[test]
+fn test_run_until_unreached_target_falls_through_to_exception() {
+    let mut cpu = cpu_with(SUM);
+
+    assert_eq!(
+        cpu.run_until(0x400),
+        ExitReason::Exception(Exception::Breakpoint(0x14))
+    );
+}
+
+// ── run_with_limits ───────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+#[test]
+fn test_run_with_limits_stops_at_the_instruction_budget() {
+    let mut cpu = cpu_with("loop: jal zero, loop");
+
+    assert_eq!(
+        cpu.run_with_limits(Some(1000), Some(Duration::from_secs(60))),
+        ExitReason::StepLimit
+    );
+    assert_eq!(cpu.csrs.read(csr::MINSTRET), 1000);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_run_with_limits_stops_at_the_deadline() {
+    let mut cpu = cpu_with("loop: jal zero, loop");
+    let started = Instant::now();
+
+    assert_eq!(
+        cpu.run_with_limits(None, Some(Duration::from_millis(50))),
+        ExitReason::TimeLimit
+    );
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(50));
+    assert!(elapsed < Duration::from_secs(10), "took {:?}", elapsed);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_run_with_limits_out_of_time_from_the_start() {
+    let mut cpu = cpu_with("loop: jal zero, loop");
+
+    assert_eq!(
+        cpu.run_with_limits(None, Some(Duration::ZERO)),
+        ExitReason::TimeLimit
+    );
+    assert_eq!(cpu.csrs.read(csr::MINSTRET), 0);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_run_with_limits_still_stops_on_exceptions() {
+    let mut cpu = cpu_with(SUM);
+
+    assert_eq!(
+        cpu.run_with_limits(Some(1000), Some(Duration::from_secs(60))),
+        ExitReason::Exception(Exception::Breakpoint(0x14))
+    );
+    assert_eq!(cpu.run_with_limits(None, None), cpu.run());
+}
@@ -0,0 +1,250 @@
+mod common;
+
+use riscv_emulator_rust::decode::{DecodeError, Instruction, decode, decode_rv64};
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, RiscvCpu, csr};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Build an RV64 CPU with `source` assembled at address 0 and run it to the
+/// trailing `ebreak`.
+fn run(source: &str) -> RiscvCpu<Rv64> {
+    let mut cpu = cpu_with(source);
+    match cpu.run() {
+        ExitReason::Exception(Exception::Breakpoint(_)) => cpu,
+        other => panic!("program stopped early: {:?}", other),
+    }
+}
+
+fn cpu_with(source: &str) -> RiscvCpu<Rv64> {
+    program(source)
+        .xlen::<Rv64>()
+        .ram_size(0x1000)
+        .build()
+        .unwrap()
+}
+
+// ── Base integer ops at 64 bits ───────────────────────────────────────────────
+
+#[test]
+fn test_immediates_sign_extend_to_64_bits() {
+    let cpu = run("
+        addi t0, zero, -1
+        lui  t1, 0x80000
+        auipc t2, 0xfffff
+        ebreak
+    ");
+
+    assert_eq!(cpu.regs[5], u64::MAX);
+    assert_eq!(cpu.regs[6], 0xFFFF_FFFF_8000_0000);
+    assert_eq!(cpu.regs[7], 0xFFFF_FFFF_FFFF_F008);
+}
+
+#[test]
+fn test_shifts_use_six_bit_amounts() {
+    let cpu = run("
+        addi t0, zero, 1
+        slli t1, t0, 40
+        srli t2, t1, 39
+        addi t3, zero, -16
+        srai t4, t3, 62
+        srli t5, t3, 60
+        addi a0, zero, 63
+        sll  a1, t0, a0
+        ebreak
+    ");
+
+    assert_eq!(cpu.regs[6], 1 << 40);
+    assert_eq!(cpu.regs[7], 2);
+    assert_eq!(cpu.regs[29], u64::MAX, "arithmetic shift keeps the sign");
+    assert_eq!(cpu.regs[30], 0xF);
+    assert_eq!(cpu.regs[11], 1 << 63);
+}
+
+#[test]
+fn test_comparisons_see_all_64_bits() {
+    let cpu = run("
+        addi t0, zero, 1
+        slli t0, t0, 63
+        addi t1, zero, 1
+        slt  a0, t0, t1
+        sltu a1, t0, t1
+        sltiu a2, t1, -1
+        ebreak
+    ");
+
+    assert_eq!(cpu.regs[10], 1, "1 << 63 is negative");
+    assert_eq!(cpu.regs[11], 0);
+    assert_eq!(cpu.regs[12], 1, "-1 is all ones as unsigned");
+}
+
+// ── *W instructions ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_word_ops_sign_extend_32_bit_results() {
+    let cpu = run("
+        lui   t0, 0x80000
+        addiw t0, t0, -1
+        addiw t1, t0, 1
+        addi  t2, zero, 1
+        slli  t2, t2, 32
+        addw  t3, t2, t2
+        subw  t4, zero, t0
+        slliw t5, t0, 1
+        ebreak
+    ");
+
+    assert_eq!(cpu.regs[5], 0x7FFF_FFFF);
+    assert_eq!(
+        cpu.regs[6], 0xFFFF_FFFF_8000_0000,
+        "overflow wraps at 32 bits"
+    );
+    assert_eq!(cpu.regs[28], 0, "upper halves are ignored");
+    assert_eq!(cpu.regs[29], 0xFFFF_FFFF_8000_0001);
+    assert_eq!(cpu.regs[30], 0xFFFF_FFFF_FFFF_FFFE);
+}
+
+#[test]
+fn test_word_shifts_use_five_bit_amounts() {
+    let cpu = run("
+        lui   t0, 0x80000
+        addi  t1, zero, 33
+        sraw  t2, t0, t1
+        srlw  t3, t0, t1
+        sllw  t4, t0, t1
+        sraiw t5, t0, 4
+        srliw t6, t0, 4
+        ebreak
+    ");
+
+    assert_eq!(cpu.regs[7], 0xFFFF_FFFF_C000_0000);
+    assert_eq!(cpu.regs[28], 0x4000_0000);
+    assert_eq!(cpu.regs[29], 0);
+    assert_eq!(cpu.regs[30], 0xFFFF_FFFF_F800_0000);
+    assert_eq!(cpu.regs[31], 0x0800_0000);
+}
+
+// ── Loads and stores ──────────────────────────────────────────────────────────
+
+#[test]
+fn test_doubleword_loads_and_stores() {
+    let cpu = run("
+        lui  t0, 0x80000
+        addi t0, t0, 0x123
+        slli t1, t0, 32
+        slli t0, t0, 32
+        srli t0, t0, 32
+        or   t1, t1, t0
+        sd   t1, 0x400(zero)
+        ld   a0, 0x400(zero)
+        lw   a1, 0x404(zero)
+        lwu  a2, 0x404(zero)
+        ebreak
+    ");
+
+    assert_eq!(cpu.regs[10], 0x8000_0123_8000_0123);
+    assert_eq!(cpu.regs[11], 0xFFFF_FFFF_8000_0123, "lw sign-extends");
+    assert_eq!(cpu.regs[12], 0x8000_0123, "lwu zero-extends");
+    assert_eq!(cpu.bus[0x407], 0x80);
+}
+
+#[test]
+fn test_access_beyond_32_bit_address_space_faults() {
+    let mut cpu = cpu_with(
+        "
+        addi t0, zero, 1
+        slli t0, t0, 32
+        lw   a0, 0x10(t0)
+    ",
+    );
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::LoadAccessFault(0x10))
+    );
+}
+
+// ── Decoding ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_rv64_encodings_are_rejected_on_rv32() {
+    // ld x1, 0(x0)
+    assert_eq!(
+        decode_rv64(0x00003083),
+        Ok(Instruction::Ld {
+            rd: 1,
+            rs1: 0,
+            imm: 0
+        })
+    );
+    // slli x1, x1, 32
+    assert_eq!(
+        decode_rv64(0x02009093),
+        Ok(Instruction::Slli {
+            rd: 1,
+            rs1: 1,
+            shamt: 32
+        })
+    );
+    assert_eq!(
+        decode(0x02009093),
+        Err(DecodeError::IllegalInstruction(0x02009093))
+    );
+    // addiw x1, x1, 1
+    assert_eq!(
+        decode(0x0010809b),
+        Err(DecodeError::UnknownOpcode(0x0010809b))
+    );
+    assert_eq!(
+        decode_rv64(0x0010809b).unwrap().to_string(),
+        "addiw x1, x1, 1"
+    );
+}
+
+// ── CSRs and traps ────────────────────────────────────────────────────────────
+
+#[test]
+fn test_misa_reports_rv64() {
+    let cpu = cpu_with("ebreak");
+
+    assert_eq!(cpu.csrs.read(csr::MISA) >> 62, 2);
+}
+
+#[test]
+fn test_interrupt_cause_sets_bit_63() {
+    let mut cpu = cpu_with("addi x0, x0, 0");
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE as u64);
+    cpu.csrs.write(csr::MIE, csr::MIP_MTIP as u64);
+    cpu.raise_interrupt(Interrupt::MachineTimer);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), (1 << 63) | 7);
+}
+
+#[test]
+fn test_csrs_hold_64_bit_values() {
+    let cpu = run("
+        addi  t0, zero, -2
+        csrrw zero, mscratch, t0
+        csrrs a0, mscratch, zero
+        ebreak
+    ");
+
+    assert_eq!(cpu.regs[10], 0xFFFF_FFFF_FFFF_FFFE);
+}
+
+// ── Snapshots ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_snapshot_records_xlen() {
+    let cpu = run("addi t0, zero, -1\nebreak");
+    let snapshot = cpu.save_snapshot();
+
+    assert_eq!(snapshot.xlen, 64);
+    assert_eq!(snapshot.regs[5], u64::MAX);
+    assert!(RiscvCpu::new(0x1000).restore(&snapshot).is_err());
+}
@@ -1,23 +1,5 @@
 use riscv_emulator_rust::RiscvCpu;
-
-/// Encode an S-type instruction (SB/SH/SW).
-///
-/// imm: 12-bit signed immediate
-/// rs2: source register 2 (value to store)
-/// rs1: source register 1 (base address)
-/// funct3: instruction funct3
-/// opcode: 0x23 for store instructions
-fn encode_stype(imm: i32, rs2: u8, rs1: u8, funct3: u8) -> u32 {
-    let imm11_5 = ((imm >> 5) & 0x7F) as u32;
-    let imm4_0 = (imm & 0x1F) as u32;
-
-    (imm11_5 << 25)
-        | ((rs2 as u32) << 20)
-        | ((rs1 as u32) << 15)
-        | ((funct3 as u32) << 12)
-        | (imm4_0 << 7)
-        | 0x23
-}
+use riscv_emulator_rust::encode::stype;
 
 mod sb {
     use super::*;
@@ -31,7 +13,7 @@ mod sb {
         cpu.regs[2] = 0x12345678;
 
         // sb x2, 4(x1) -> store byte 0x78 at 0x104
-        let instruction = encode_stype(4, 2, 1, 0b000);
+        let instruction = stype(4, 2, 1, 0b000);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x104], 0x78);
@@ -45,7 +27,7 @@ mod sb {
         cpu.regs[2] = 0xFF;
 
         // sb x2, -4(x1) -> store byte 0xFF at 0xFC
-        let instruction = encode_stype(-4, 2, 1, 0b000);
+        let instruction = stype(-4, 2, 1, 0b000);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0xFC], 0xFF);
@@ -58,7 +40,7 @@ mod sb {
         cpu.regs[2] = 0xAB;
 
         // sb x2, 0(x1) -> store byte 0xAB at 0x200
-        let instruction = encode_stype(0, 2, 1, 0b000);
+        let instruction = stype(0, 2, 1, 0b000);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x200], 0xAB);
@@ -71,7 +53,7 @@ mod sb {
         cpu.regs[2] = 0xDEADBEEF;
 
         // sb should only store the lowest byte (0xEF)
-        let instruction = encode_stype(0, 2, 1, 0b000);
+        let instruction = stype(0, 2, 1, 0b000);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x100], 0xEF, "Only low byte should be stored");
@@ -88,7 +70,7 @@ mod sb {
         cpu.regs[2] = 0x00;
 
         // sb x2, 0(x1) -> store 0x00, overwriting existing value
-        let instruction = encode_stype(0, 2, 1, 0b000);
+        let instruction = stype(0, 2, 1, 0b000);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x100], 0x00);
@@ -100,7 +82,7 @@ mod sb {
         // x0 is always 0, so base = 0, imm = 4, addr = 4
         cpu.regs[2] = 0x42;
 
-        let instruction = encode_stype(4, 2, 0, 0b000);
+        let instruction = stype(4, 2, 0, 0b000);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[4], 0x42);
@@ -117,7 +99,7 @@ mod sh {
         cpu.regs[2] = 0x12345678;
 
         // sh x2, 4(x1) -> store halfword 0x5678 at 0x104 (little-endian: 0x78 at 0x104, 0x56 at 0x105)
-        let instruction = encode_stype(4, 2, 1, 0b001);
+        let instruction = stype(4, 2, 1, 0b001);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x104], 0x78);
@@ -132,7 +114,7 @@ mod sh {
         cpu.regs[2] = 0xABCD;
 
         // sh x2, -2(x1) -> store halfword at 0xFE
-        let instruction = encode_stype(-2, 2, 1, 0b001);
+        let instruction = stype(-2, 2, 1, 0b001);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0xFE], 0xCD);
@@ -146,7 +128,7 @@ mod sh {
         cpu.regs[2] = 0xBEEF;
 
         // sh x2, 0(x1) -> store halfword at 0x200
-        let instruction = encode_stype(0, 2, 1, 0b001);
+        let instruction = stype(0, 2, 1, 0b001);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x200], 0xEF);
@@ -161,7 +143,7 @@ mod sh {
         cpu.regs[2] = 0xDEADBEEF;
 
         // sh should only write the low 16 bits (0xBEEF)
-        let instruction = encode_stype(0, 2, 1, 0b001);
+        let instruction = stype(0, 2, 1, 0b001);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x100], 0xEF, "Low byte of halfword");
@@ -176,7 +158,7 @@ mod sh {
         cpu.regs[1] = 0x100;
         cpu.regs[2] = 0xFFFF;
 
-        let instruction = encode_stype(0, 2, 1, 0b001);
+        let instruction = stype(0, 2, 1, 0b001);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x100], 0xFF);
@@ -195,7 +177,7 @@ mod sw {
         cpu.regs[2] = 0x12345678;
 
         // sw x2, 4(x1) -> store word 0x12345678 at 0x104 (little-endian: 0x78, 0x56, 0x34, 0x12)
-        let instruction = encode_stype(4, 2, 1, 0b010);
+        let instruction = stype(4, 2, 1, 0b010);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x104], 0x78);
@@ -212,7 +194,7 @@ mod sw {
         cpu.regs[2] = 0xDEADBEEF;
 
         // sw x2, -4(x1) -> store word 0xDEADBEEF at 0xFC
-        let instruction = encode_stype(-4, 2, 1, 0b010);
+        let instruction = stype(-4, 2, 1, 0b010);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0xFC], 0xEF);
@@ -228,7 +210,7 @@ mod sw {
         cpu.regs[2] = 0xCAFEBABE;
 
         // sw x2, 0(x1) -> store at 0x200
-        let instruction = encode_stype(0, 2, 1, 0b010);
+        let instruction = stype(0, 2, 1, 0b010);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x200], 0xBE);
@@ -250,7 +232,7 @@ mod sw {
         cpu.regs[2] = 0x0000_0000;
 
         // sw x2, 0(x1) -> overwrite with zeros
-        let instruction = encode_stype(0, 2, 1, 0b010);
+        let instruction = stype(0, 2, 1, 0b010);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x100], 0x00);
@@ -265,7 +247,7 @@ mod sw {
         cpu.regs[1] = 0x100;
         cpu.regs[2] = 0xFFFF_FFFF;
 
-        let instruction = encode_stype(0, 2, 1, 0b010);
+        let instruction = stype(0, 2, 1, 0b010);
         cpu.handle_store(instruction).unwrap();
 
         assert_eq!(cpu.bus[0x100], 0xFF);
@@ -0,0 +1,216 @@
+mod common;
+
+#[cfg(feature = "std")]
+use riscv_emulator_rust::semihosting::{SYS_CLOSE, SYS_ERRNO, SYS_READ};
+use riscv_emulator_rust::semihosting::{
+    SYS_EXIT, SYS_EXIT_EXTENDED, SYS_GET_CMDLINE, SYS_OPEN, SYS_WRITE, SYS_WRITE0, Semihosting,
+};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::{ExitReason, RiscvCpu};
+
+use common::{SharedBuffer, cpu_with, le_bytes, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const DATA: u32 = 0x400;
+
+/// A semihosting call with `a0 = op` and `a1 = arg`, result left in `a0`.
+fn call(op: u32, arg: u32) -> String {
+    format!(
+        "
+        addi a0, zero, {op}
+        addi a1, zero, {arg}
+        slli x0, x0, 0x1f
+        ebreak
+        srai x0, x0, 7
+        "
+    )
+}
+
+/// Assemble `source` at 0, put `data` at [`DATA`] and run it.
+fn run(source: &str, data: &[u8], semihosting: Semihosting) -> (RiscvCpu, ExitReason) {
+    let mut cpu = program(source)
+        .image(DATA, data)
+        .semihosting(semihosting)
+        .build()
+        .unwrap();
+
+    let exit = cpu.run_steps(10_000);
+    (cpu, exit)
+}
+
+// ── Console ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_write0_prints_string() {
+    let out = SharedBuffer::default();
+    let program = call(SYS_WRITE0, DATA) + "ebreak";
+
+    let (_, exit) = run(
+        &program,
+        b"hello\n\0",
+        Semihosting::with_output(Box::new(out.clone())),
+    );
+
+    assert_eq!(out.contents(), "hello\n");
+    assert_eq!(exit, ExitReason::Exception(Exception::Breakpoint(0x14)));
+}
+
+#[test]
+fn test_open_tt_and_write() {
+    let out = SharedBuffer::default();
+    // a0 = open(":tt", "w"); write(a0, "hi", 2)
+    let program = call(SYS_OPEN, DATA)
+        + "
+        addi t0, zero, 0x410
+        sw   a0, 0(t0)
+        "
+        + &call(SYS_WRITE, 0x410)
+        + "ebreak";
+
+    let mut data = le_bytes(&[DATA + 0x20, 4, 3]);
+    data.resize(0x10, 0);
+    data.extend(le_bytes(&[0, DATA + 0x24, 2]));
+    data.resize(0x20, 0);
+    data.extend(b":tt\0hi");
+
+    let (cpu, _) = run(
+        &program,
+        &data,
+        Semihosting::with_output(Box::new(out.clone())),
+    );
+
+    assert_eq!(out.contents(), "hi");
+    assert_eq!(cpu.regs[10], 0, "no bytes left unwritten");
+}
+
+// ── Exit ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_exit_extended_reports_status() {
+    let program = call(SYS_EXIT_EXTENDED, DATA) + "ebreak";
+
+    let (cpu, exit) = run(&program, &le_bytes(&[0x20026, 3]), Semihosting::new());
+
+    assert_eq!(exit, ExitReason::Exited(3));
+    assert_eq!(cpu.pc, 0x10, "stopped just past the ebreak");
+}
+
+#[test]
+fn test_exit_with_application_exit_reason_is_success() {
+    let program = "
+        lui  a1, 0x20
+        addi a1, a1, 0x26
+    "
+    .to_string()
+        + &call(SYS_EXIT, 0).replace("addi a1, zero, 0", "");
+
+    let (_, exit) = run(&program, &[], Semihosting::new());
+
+    assert_eq!(exit, ExitReason::Exited(0));
+}
+
+#[test]
+fn test_exit_with_other_reason_is_failure() {
+    let program = call(SYS_EXIT, 0x123);
+
+    let (_, exit) = run(&program, &[], Semihosting::new());
+
+    assert_eq!(exit, ExitReason::Exited(1));
+}
+
+// ── Not semihosting ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_plain_ebreak_is_still_a_breakpoint() {
+    let (_, exit) = run("nop: addi x0, x0, 0\nebreak", &[], Semihosting::new());
+
+    assert_eq!(exit, ExitReason::Exception(Exception::Breakpoint(0x4)));
+}
+
+#[test]
+fn test_magic_sequence_without_semihosting_is_a_breakpoint() {
+    let mut cpu = cpu_with(&call(SYS_EXIT, 0));
+
+    assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(0xC)));
+}
+
+// ── Files ─────────────────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+#[test]
+fn test_file_round_trip() {
+    let path = std::env::temp_dir().join(format!("semihost-{}.txt", std::process::id()));
+    let name = path.to_str().unwrap().as_bytes().to_vec();
+    let len = name.len() as u32;
+
+    // DATA+0x00: open block  (name, mode, len)
+    // DATA+0x10: io block    (handle, buf, len)
+    // DATA+0x20: close block (handle)
+    // DATA+0x30: "abc"
+    // DATA+0x40: read buffer
+    // DATA+0x80: file name
+    let mut data = le_bytes(&[DATA + 0x80, 4, len]);
+    data.resize(0x10, 0);
+    data.extend(le_bytes(&[0, DATA + 0x30, 3]));
+    data.resize(0x30, 0);
+    data.extend(b"abc");
+    data.resize(0x80, 0);
+    data.extend(&name);
+
+    let save_handle = "
+        addi t0, zero, 0x410
+        sw   a0, 0(t0)
+        sw   a0, 16(t0)
+    ";
+    let program = call(SYS_OPEN, DATA)
+        + save_handle
+        + &call(SYS_WRITE, 0x410)
+        + &call(SYS_CLOSE, 0x420)
+        // reopen read-only and read into DATA+0x40
+        + "
+        addi t0, zero, 0x404
+        sw   zero, 0(t0)
+        addi t1, zero, 0x440
+        sw   t1, 0x10(t0)
+        "
+        + &call(SYS_OPEN, DATA)
+        + save_handle
+        + &call(SYS_READ, 0x410)
+        + &call(SYS_CLOSE, 0x420)
+        + "ebreak";
+
+    let (cpu, _) = run(&program, &data, Semihosting::new());
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(cpu.regs[10], 0, "close succeeded");
+    assert_eq!(&cpu.bus[0x440..0x443], b"abc");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_failed_open_sets_errno() {
+    let name = b"/nonexistent/definitely/not/here";
+    let mut data = le_bytes(&[DATA + 0x10, 0, name.len() as u32]);
+    data.resize(0x10, 0);
+    data.extend(name);
+
+    let program = call(SYS_OPEN, DATA) + "add s0, a0, zero" + &call(SYS_ERRNO, 0) + "ebreak";
+
+    let (cpu, _) = run(&program, &data, Semihosting::new());
+
+    assert_eq!(cpu.regs[8], u32::MAX, "open returns -1");
+    assert_eq!(cpu.regs[10], 2, "ENOENT");
+}
+
+#[test]
+fn test_get_cmdline() {
+    let program = call(SYS_GET_CMDLINE, DATA) + "ebreak";
+    let data = le_bytes(&[DATA + 0x10, 0x40]);
+
+    let (cpu, _) = run(&program, &data, Semihosting::new().cmdline("prog -v"));
+
+    assert_eq!(cpu.regs[10], 0);
+    assert_eq!(&cpu.bus[0x410..0x418], b"prog -v\0");
+    assert_eq!(cpu.bus[0x404], 7, "length is written back");
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use riscv_emulator_rust::MemSize;
+use riscv_emulator_rust::bus::Bus;
+use riscv_emulator_rust::riscv_tests::{RAM_BASE, TestOutcome};
+use riscv_emulator_rust::signature::{dump, run};
+
+use common::image_at;
+
+// ── Helper: an ELF32 image with a symbol table ────────────────────────────────
+
+/// Build an ELF32 RISC-V executable with `code` loaded at `RAM_BASE`, a
+/// 0x1004-byte segment, and a `.symtab` defining `symbols`.
+fn build_elf(code: &[u8], symbols: &[(&str, u32)]) -> Vec<u8> {
+    let code_off = 52 + 32;
+
+    let mut strtab = vec![0u8];
+    let mut symtab = vec![0u8; 16];
+    for (name, value) in symbols {
+        for field in [strtab.len() as u32, *value, 0] {
+            symtab.extend_from_slice(&field.to_le_bytes());
+        }
+        symtab.extend_from_slice(&[0x10, 0, 1, 0]); // STB_GLOBAL, shndx 1
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+
+    let symtab_off = code_off + code.len();
+    let strtab_off = symtab_off + symtab.len();
+    let shoff = strtab_off + strtab.len();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+    out.extend_from_slice(&243u16.to_le_bytes()); // e_machine = EM_RISCV
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&RAM_BASE.to_le_bytes()); // e_entry
+    out.extend_from_slice(&52u32.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&(shoff as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&52u16.to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&32u16.to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&3u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    for field in [
+        1,
+        code_off as u32,
+        RAM_BASE,
+        RAM_BASE,
+        code.len() as u32,
+        0x1004,
+        0x7,
+        4,
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+
+    out.extend_from_slice(code);
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab);
+
+    // section headers: null, .symtab (link -> 2), .strtab
+    let symtab_sh = [
+        0,
+        2,
+        0,
+        0,
+        symtab_off as u32,
+        symtab.len() as u32,
+        2,
+        1,
+        4,
+        16,
+    ];
+    let strtab_sh = [
+        0,
+        3,
+        0,
+        0,
+        strtab_off as u32,
+        strtab.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ];
+    for section in [[0; 10], symtab_sh, strtab_sh] {
+        for field in section {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+
+    out
+}
+
+const BEGIN: u32 = RAM_BASE + 0x800;
+const END: u32 = RAM_BASE + 0x810;
+const TOHOST: u32 = RAM_BASE + 0x1000;
+
+/// Stores two words into the signature region, then halts through `tohost`.
+fn arch_test() -> Vec<u8> {
+    let source = "
+        lui   t0, 0x80001
+        addi  t1, zero, 0x11
+        sw    t1, -0x800(t0)
+        lui   t1, 0xdeadc
+        addi  t1, t1, -0x111
+        sw    t1, -0x7f4(t0)
+        addi  t1, zero, 1
+        sw    t1, 0(t0)
+    spin:
+        jal   zero, spin
+    ";
+    let code = image_at(RAM_BASE, source);
+
+    build_elf(
+        &code,
+        &[
+            ("begin_signature", BEGIN),
+            ("end_signature", END),
+            ("tohost", TOHOST),
+        ],
+    )
+}
+
+// ── Dumping ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_dump_one_word_per_line() {
+    let mut bus = Bus::new(0x1000, 0x100);
+    bus.write(0x1000, MemSize::Word, 0xDEAD_BEEF).unwrap();
+    bus.write(0x1004, MemSize::Word, 0x1).unwrap();
+
+    assert_eq!(
+        dump(&mut bus, 0x1000, 0x1008, 4).unwrap(),
+        "deadbeef\n00000001\n"
+    );
+}
+
+#[test]
+fn test_dump_wider_granularity_puts_high_word_first() {
+    let mut bus = Bus::new(0x1000, 0x100);
+    bus.write(0x1000, MemSize::Word, 0x2222_2222).unwrap();
+    bus.write(0x1004, MemSize::Word, 0x1111_1111).unwrap();
+
+    assert_eq!(
+        dump(&mut bus, 0x1000, 0x1008, 8).unwrap(),
+        "1111111122222222\n"
+    );
+}
+
+#[test]
+fn test_dump_rejects_bad_granularity_and_unmapped_memory() {
+    let mut bus = Bus::new(0x1000, 0x100);
+
+    assert!(dump(&mut bus, 0x1000, 0x1004, 2).is_err());
+    assert!(dump(&mut bus, 0x10FC, 0x1104, 4).is_err());
+}
+
+// ── Running ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_run_produces_signature() {
+    let (outcome, signature) = run(&arch_test(), 4, 1000).unwrap();
+
+    assert_eq!(outcome, TestOutcome::Pass);
+    assert_eq!(signature, "00000011\n00000000\n00000000\ndeadbeef\n");
+}
+
+#[test]
+fn test_run_requires_signature_symbols() {
+    let elf = build_elf(&[0x6F, 0, 0, 0], &[("tohost", TOHOST)]);
+
+    assert_eq!(
+        run(&elf, 4, 1000),
+        Err(String::from("no `begin_signature` symbol"))
+    );
+}
@@ -0,0 +1,221 @@
+mod common;
+
+use riscv_emulator_rust::snapshot::{MemoryChange, Snapshot};
+use riscv_emulator_rust::{ExitReason, RiscvCpu, csr};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Counts t0 up forever, storing each value to 0x100.
+const COUNTER: &str = "
+    loop:   addi t0, t0, 1
+            sw   t0, 0x100(zero)
+            jal  zero, loop
+";
+
+fn cpu_with(source: &str) -> RiscvCpu {
+    program(source).ram_size(0x1000).build().unwrap()
+}
+
+// ── Save / restore ────────────────────────────────────────────────────────────
+
+#[test]
+fn test_snapshot_captures_registers_pc_csrs_and_ram() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.csrs.write(csr::MSCRATCH, 0x1234);
+    cpu.run_steps(6);
+
+    let snapshot = cpu.save_snapshot();
+
+    assert_eq!(snapshot.regs[5], 2);
+    assert_eq!(snapshot.pc, 0x0);
+    assert_eq!(snapshot.csr(csr::MSCRATCH), 0x1234);
+    assert_eq!(snapshot.ram[0x100], 2);
+    assert_eq!(snapshot.ram.len(), 0x1000);
+}
+
+#[test]
+fn test_restore_rewinds_execution() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.run_steps(3);
+    let snapshot = cpu.save_snapshot();
+
+    cpu.run_steps(30);
+    cpu.csrs.write(csr::MSCRATCH, 0xFFFF);
+    assert_eq!(cpu.regs[5], 11);
+
+    cpu.restore(&snapshot).unwrap();
+
+    assert_eq!(cpu.regs[5], 1);
+    assert_eq!(cpu.bus[0x100], 1);
+    assert_eq!(cpu.csrs.read(csr::MSCRATCH), 0);
+
+    cpu.run_steps(30);
+    assert_eq!(cpu.regs[5], 11, "replays identically");
+}
+
+#[test]
+fn test_restore_keeps_breakpoints() {
+    let mut cpu = cpu_with(COUNTER);
+    let snapshot = cpu.save_snapshot();
+    cpu.add_breakpoint(0x8);
+
+    cpu.run_steps(10);
+    cpu.restore(&snapshot).unwrap();
+
+    assert_eq!(cpu.run(), ExitReason::Breakpoint(0x8));
+}
+
+#[test]
+fn test_restore_rejects_different_ram_layout() {
+    let snapshot = cpu_with(COUNTER).save_snapshot();
+    let mut other = RiscvCpu::new(0x2000);
+
+    assert!(other.restore(&snapshot).is_err());
+}
+
+#[test]
+fn test_from_snapshot_builds_matching_machine() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.run_steps(9);
+
+    let mut copy = RiscvCpu::from_snapshot(&cpu.save_snapshot());
+    cpu.run_steps(9);
+    copy.run_steps(9);
+
+    assert_eq!(copy.regs, cpu.regs);
+    assert_eq!(copy.save_snapshot(), cpu.save_snapshot());
+}
+
+// ── Serialization ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_bytes_round_trip() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.csrs.write(csr::MTVEC, 0x80);
+    cpu.run_steps(5);
+    let snapshot = cpu.save_snapshot();
+
+    assert_eq!(Snapshot::from_bytes(&snapshot.to_bytes()), Ok(snapshot));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_file_round_trip() {
+    let path = std::env::temp_dir().join(format!("snapshot-{}.bin", std::process::id()));
+    let mut cpu = cpu_with(COUNTER);
+    cpu.run_steps(4);
+    let snapshot = cpu.save_snapshot();
+
+    snapshot.save(&path).unwrap();
+    let loaded = Snapshot::load(&path);
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(loaded, Ok(snapshot));
+}
+
+#[test]
+fn test_from_bytes_rejects_garbage() {
+    let bytes = cpu_with(COUNTER).save_snapshot().to_bytes();
+
+    assert!(Snapshot::from_bytes(b"not a snapshot").is_err());
+    assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+}
+
+// ── Memory diffs ──────────────────────────────────────────────────────────────
+
+#[test]
+fn test_memory_diff_of_unchanged_ram_is_empty() {
+    let mut cpu = cpu_with("addi t0, t0, 1");
+    let before = cpu.memory_snapshot();
+    cpu.run_steps(1);
+
+    assert!(cpu.memory_diff(&before).is_empty());
+    assert!(before.diff(&cpu.memory_snapshot()).is_empty());
+}
+
+#[test]
+fn test_memory_diff_finds_changed_runs() {
+    let mut cpu = cpu_with(COUNTER);
+    cpu.bus.write_bytes(0x200, &[1, 2, 3, 4]).unwrap();
+    let before = cpu.memory_snapshot();
+
+    cpu.run_steps(6);
+    // Only the middle two bytes change, then a separate run further on.
+    cpu.bus.write_bytes(0x200, &[1, 9, 9, 4]).unwrap();
+    cpu.bus.write_bytes(0x300, &[7]).unwrap();
+
+    let diff = cpu.memory_diff(&before);
+    assert_eq!(diff, before.diff(&cpu.memory_snapshot()));
+    assert_eq!(diff.len(), 4);
+    assert_eq!(
+        diff.changes,
+        vec![
+            MemoryChange {
+                addr: 0x100,
+                before: vec![0],
+                after: vec![2],
+            },
+            MemoryChange {
+                addr: 0x201,
+                before: vec![2, 3],
+                after: vec![9, 9],
+            },
+            MemoryChange {
+                addr: 0x300,
+                before: vec![0],
+                after: vec![7],
+            },
+        ]
+    );
+    assert_eq!(
+        diff.to_string(),
+        "0x00000100: 00 -> 02\n0x00000201: 02 03 -> 09 09\n0x00000300: 00 -> 07"
+    );
+}
+
+#[test]
+fn test_memory_diff_outside_a_buffer() {
+    let mut cpu = cpu_with("");
+    let before = cpu.memory_snapshot();
+    cpu.bus.write_bytes(0x1fe, &[0xff; 8]).unwrap();
+    cpu.bus.write_bytes(0x400, &[0xaa; 4]).unwrap();
+
+    let diff = cpu.memory_diff(&before);
+    assert!(diff.outside(0x100..0x500).is_empty());
+
+    let outside = diff.outside(0x200..0x204);
+    assert_eq!(outside.len(), 8);
+    assert_eq!(
+        outside.changes.iter().map(|c| c.addr).collect::<Vec<_>>(),
+        vec![0x1fe, 0x204, 0x400]
+    );
+    assert_eq!(outside.changes[1].after, vec![0xff; 2]);
+}
+
+#[test]
+fn test_memory_diff_uses_ram_addresses() {
+    let mut cpu = RiscvCpu::builder()
+        .ram_base(0x8000_0000)
+        .ram_size(0x20000)
+        .sparse_ram(true)
+        .build()
+        .unwrap();
+    let before = cpu.memory_snapshot();
+    // Straddles the boundary between the chunks live RAM is read in.
+    cpu.bus.write_bytes(0x8000_fffe, &[1, 2, 3, 4]).unwrap();
+
+    let diff = cpu.memory_diff(&before);
+    assert_eq!(diff.changes.len(), 1);
+    assert_eq!(diff.changes[0].addr, 0x8000_fffe);
+    assert_eq!(diff.changes[0].after, vec![1, 2, 3, 4]);
+}
+
+#[test]
+#[should_panic(expected = "MemorySnapshot: can't diff")]
+fn test_memory_diff_needs_the_same_ram() {
+    let before = cpu_with("").memory_snapshot();
+    let other = RiscvCpu::builder().ram_size(0x2000).build().unwrap();
+    other.memory_diff(&before);
+}
@@ -0,0 +1,210 @@
+#![cfg(feature = "softfloat")]
+
+mod common;
+
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::fuzz::Generator;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Reg, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str) -> RiscvCpu<Rv64> {
+    program(source).xlen::<Rv64>().build().unwrap()
+}
+
+fn boxed(value: f32) -> u64 {
+    0xFFFF_FFFF_0000_0000 | value.to_bits() as u64
+}
+
+/// Random bits, weighted towards zeros, infinities, NaNs, subnormals and
+/// the extremes of the exponent range.
+fn operand(rng: &mut Generator, exponent_bits: u32, fraction_bits: u32) -> u64 {
+    let max_exponent = (1 << exponent_bits) - 1;
+    let bits = rng.next_u64();
+    let exponent = match bits % 8 {
+        0 => 0,
+        1 => max_exponent,
+        2 => 1 + bits % 3,
+        3 => max_exponent - 1 - bits % 3,
+        _ => rng.next_u64() % max_exponent,
+    };
+    let fraction = match bits % 5 {
+        0 => 0,
+        _ => rng.next_u64() & ((1 << fraction_bits) - 1),
+    };
+    (bits >> 63) << (exponent_bits + fraction_bits) | exponent << fraction_bits | fraction
+}
+
+fn canonical32(x: f32) -> u64 {
+    boxed(if x.is_nan() {
+        f32::from_bits(0x7FC0_0000)
+    } else {
+        x
+    })
+}
+
+fn canonical64(x: f64) -> u64 {
+    if x.is_nan() {
+        0x7FF8_0000_0000_0000
+    } else {
+        x.to_bits()
+    }
+}
+
+// ── Soft-float ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_matches_the_hosts_ieee_arithmetic() {
+    let mut cpu = cpu_with(
+        "
+        fadd.d   fa0, ft0, ft1
+        fsub.d   fa1, ft0, ft1
+        fmul.d   fa2, ft0, ft1
+        fdiv.d   fa3, ft0, ft1
+        fsqrt.d  fa4, ft0
+        fmadd.d  fa5, ft0, ft1, ft2
+        fcvt.s.d fa6, ft0
+        flt.d    a0, ft0, ft1
+        feq.d    a1, ft0, ft1
+        fcvt.l.d a2, ft0, rtz
+        fadd.s   fs2, ft3, ft4
+        fsub.s   fs3, ft3, ft4
+        fmul.s   fs4, ft3, ft4
+        fdiv.s   fs5, ft3, ft4
+        fsqrt.s  fs6, ft3
+        fmadd.s  fs7, ft3, ft4, ft5
+        fcvt.d.s fs8, ft3
+        fle.s    a3, ft3, ft4
+        fcvt.w.s a4, ft3, rtz
+        ",
+    );
+    let mut rng = Generator::new(1);
+
+    for _ in 0..20_000 {
+        let a = f64::from_bits(operand(&mut rng, 11, 52));
+        // Close to a as often as not, to cancel.
+        let b = match rng.next_u64() % 2 {
+            0 => f64::from_bits(a.to_bits() ^ (rng.next_u64() & 0xFFFF)),
+            _ => f64::from_bits(operand(&mut rng, 11, 52)),
+        };
+        let c = f64::from_bits(operand(&mut rng, 11, 52));
+        let (x, y, z) = (
+            f32::from_bits(operand(&mut rng, 8, 23) as u32),
+            f32::from_bits(operand(&mut rng, 8, 23) as u32),
+            f32::from_bits(operand(&mut rng, 8, 23) as u32),
+        );
+        cpu.fregs[..6].copy_from_slice(&[
+            a.to_bits(),
+            b.to_bits(),
+            c.to_bits(),
+            boxed(x),
+            boxed(y),
+            boxed(z),
+        ]);
+        cpu.pc = 0;
+        cpu.run_steps(19);
+
+        let operands = format!("{:?} {:?} {:?} / {:?} {:?} {:?}", a, b, c, x, y, z);
+        let expected = [
+            (10, canonical64(a + b)),
+            (11, canonical64(a - b)),
+            (12, canonical64(a * b)),
+            (13, canonical64(a / b)),
+            (14, canonical64(a.sqrt())),
+            (15, canonical64(a.mul_add(b, c))),
+            (16, canonical32(a as f32)),
+            (18, canonical32(x + y)),
+            (19, canonical32(x - y)),
+            (20, canonical32(x * y)),
+            (21, canonical32(x / y)),
+            (22, canonical32(x.sqrt())),
+            (23, canonical32(x.mul_add(y, z))),
+            (24, canonical64(x as f64)),
+        ];
+        for (reg, value) in expected {
+            assert_eq!(cpu.fregs[reg], value, "f{} for {}", reg, operands);
+        }
+
+        assert_eq!(cpu.reg(Reg::A0), (a < b) as u64, "{}", operands);
+        assert_eq!(cpu.reg(Reg::A1), (a == b) as u64, "{}", operands);
+        assert_eq!(cpu.reg(Reg::A3), (x <= y) as u64, "{}", operands);
+        if !a.is_nan() {
+            assert_eq!(cpu.reg(Reg::A2), a as i64 as u64, "{}", operands);
+        }
+        if !x.is_nan() {
+            assert_eq!(cpu.reg(Reg::A4), x as i32 as i64 as u64, "{}", operands);
+        }
+    }
+}
+
+#[test]
+fn test_nan_results_are_canonical_whatever_the_payload() {
+    let mut cpu = cpu_with(
+        "
+        fadd.s   ft2, ft0, ft1
+        fmul.d   ft3, fs0, fs0
+        fcvt.d.s ft4, ft0
+        fcvt.s.d ft5, fs0
+        fsqrt.d  ft6, fs1
+        ",
+    );
+    cpu.fregs[0] = 0xFFFF_FFFF_FFA1_2345;
+    cpu.fregs[1] = boxed(1.0);
+    cpu.fregs[8] = 0x7FF4_5678_9ABC_DEF0;
+    cpu.fregs[9] = (-4.0f64).to_bits();
+
+    cpu.run_steps(5);
+
+    assert_eq!(cpu.fregs[2], boxed(f32::from_bits(0x7FC0_0000)));
+    assert_eq!(cpu.fregs[3], 0x7FF8_0000_0000_0000);
+    assert_eq!(cpu.fregs[4], 0x7FF8_0000_0000_0000);
+    assert_eq!(cpu.fregs[5], boxed(f32::from_bits(0x7FC0_0000)));
+    assert_eq!(cpu.fregs[6], 0x7FF8_0000_0000_0000);
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), csr::FFLAG_NV as u64);
+}
+
+#[test]
+fn test_rounds_in_every_mode_bit_for_bit() {
+    let mut cpu = cpu_with(
+        "
+        fdiv.d   fa0, ft0, ft1, rne
+        fdiv.d   fa1, ft0, ft1, rtz
+        fdiv.d   fa2, ft0, ft1, rdn
+        fdiv.d   fa3, ft0, ft1, rup
+        fdiv.d   fa4, ft0, ft1, rmm
+        fmadd.d  fa5, ft2, ft2, ft3, rup
+        fsqrt.s  fa6, ft4, rup
+        fcvt.s.l fa7, a0, rtz
+        ",
+    );
+    cpu.fregs[0] = (-2.0f64).to_bits();
+    cpu.fregs[1] = 3.0f64.to_bits();
+    // (1 + 2^-52)^2 - 1 = 2^-51 + 2^-104, which only a fused add keeps.
+    cpu.fregs[2] = (1.0 + f64::EPSILON).to_bits();
+    cpu.fregs[3] = (-1.0f64).to_bits();
+    cpu.fregs[4] = boxed(f32::from_bits(1));
+    cpu.regs[10] = u64::MAX >> 1;
+
+    cpu.run_steps(8);
+
+    assert_eq!(cpu.fregs[10], 0xBFE5_5555_5555_5555);
+    assert_eq!(cpu.fregs[11], 0xBFE5_5555_5555_5555);
+    assert_eq!(cpu.fregs[12], 0xBFE5_5555_5555_5556);
+    assert_eq!(cpu.fregs[13], 0xBFE5_5555_5555_5555);
+    assert_eq!(cpu.fregs[14], 0xBFE5_5555_5555_5555);
+    assert_eq!(cpu.fregs[15], (2.0 * f64::EPSILON).to_bits() + 1);
+    assert_eq!(
+        cpu.fregs[16],
+        boxed(f32::from_bits(0x1A35_04F4)),
+        "sqrt(2^-149)"
+    );
+    assert_eq!(
+        cpu.fregs[17],
+        boxed(f32::from_bits(0x5EFF_FFFF)),
+        "2^63 - 1"
+    );
+    assert_eq!(cpu.csrs.read(csr::FFLAGS), csr::FFLAG_NX as u64);
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::spi::FlashXip;
+use riscv_emulator_rust::devices::{Device, Spi, SpiFlash};
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+use common::image;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const CSMODE: u32 = 0x18;
+const FMT: u32 = 0x40;
+const TXDATA: u32 = 0x48;
+const RXDATA: u32 = 0x4C;
+const RXMARK: u32 = 0x54;
+const IE: u32 = 0x70;
+const IP: u32 = 0x74;
+
+const CSMODE_AUTO: u32 = 0;
+const CSMODE_HOLD: u32 = 2;
+const RX_EMPTY: u32 = 1 << 31;
+
+/// Send `bytes` as one command with chip select held, and return what came
+/// back for each.
+fn command(spi: &mut Spi, bytes: &[u8]) -> Vec<u8> {
+    spi.write(CSMODE, MemSize::Word, CSMODE_HOLD);
+    let received = bytes
+        .iter()
+        .map(|&byte| {
+            spi.write(TXDATA, MemSize::Word, byte as u32);
+            spi.read(RXDATA, MemSize::Word) as u8
+        })
+        .collect();
+    spi.write(CSMODE, MemSize::Word, CSMODE_AUTO);
+    received
+}
+
+fn read(spi: &mut Spi, addr: u32, len: usize) -> Vec<u8> {
+    let mut bytes = vec![0x03, (addr >> 16) as u8, (addr >> 8) as u8, addr as u8];
+    bytes.resize(4 + len, 0);
+    command(spi, &bytes)[4..].to_vec()
+}
+
+fn program(spi: &mut Spi, addr: u32, data: &[u8]) {
+    command(spi, &[0x06]);
+    let mut bytes = vec![0x02, (addr >> 16) as u8, (addr >> 8) as u8, addr as u8];
+    bytes.extend_from_slice(data);
+    command(spi, &bytes);
+}
+
+// ── Flash commands ────────────────────────────────────────────────────────────
+
+#[test]
+fn test_guest_reads_the_jedec_id() {
+    let source = "
+        lui   t0, 0x10014
+        addi  t1, zero, 2
+        sw    t1, 24(t0)
+        addi  t1, zero, 0x9F
+        sw    t1, 72(t0)
+        sw    zero, 72(t0)
+        sw    zero, 72(t0)
+        sw    zero, 72(t0)
+        sw    zero, 24(t0)
+        lw    a0, 76(t0)
+        lw    a1, 76(t0)
+        lw    a2, 76(t0)
+        lw    a3, 76(t0)
+        lw    a4, 76(t0)
+        ";
+    let mut cpu = common::program(source)
+        .device(
+            Spi::BASE,
+            Spi::SIZE,
+            Spi::new().attach(0, SpiFlash::new(1 << 20)),
+        )
+        .build()
+        .unwrap();
+
+    cpu.run_steps(14);
+    assert_eq!(cpu.regs[11..14], [0xEF, 0x40, 0x14]);
+    assert_eq!(cpu.regs[14], RX_EMPTY);
+}
+
+#[test]
+fn test_read_and_fast_read() {
+    let flash = SpiFlash::with_image(4096, b"hello, flash").unwrap();
+    let mut spi = Spi::new().attach(0, flash);
+
+    assert_eq!(read(&mut spi, 7, 5), b"flash");
+    let fast = command(&mut spi, &[0x0B, 0, 0, 0, 0xA5, 0, 0, 0, 0, 0]);
+    assert_eq!(&fast[5..], b"hello");
+}
+
+#[test]
+fn test_program_needs_write_enable() {
+    let flash = SpiFlash::new(4096);
+    let mut spi = Spi::new().attach(0, flash.clone());
+
+    command(&mut spi, &[0x02, 0, 0, 0x10, 0x12, 0x34]);
+    assert_eq!(
+        read(&mut spi, 0x10, 2),
+        [0xFF, 0xFF],
+        "write enable not set"
+    );
+
+    program(&mut spi, 0x10, &[0x12, 0x34]);
+    assert_eq!(read(&mut spi, 0x10, 2), [0x12, 0x34]);
+    assert_eq!(command(&mut spi, &[0x05, 0])[1], 0, "latch cleared");
+
+    // Programming only clears bits.
+    program(&mut spi, 0x10, &[0xF0]);
+    assert_eq!(read(&mut spi, 0x10, 1), [0x10]);
+    assert_eq!(flash.contents()[0x10..0x12], [0x10, 0x34]);
+}
+
+#[test]
+fn test_page_program_wraps_within_the_page() {
+    let mut spi = Spi::new().attach(0, SpiFlash::new(4096));
+
+    program(&mut spi, 0xFF, &[1, 2]);
+    assert_eq!(read(&mut spi, 0xFF, 1), [1]);
+    assert_eq!(read(&mut spi, 0x00, 1), [2]);
+    assert_eq!(read(&mut spi, 0x100, 1), [0xFF]);
+}
+
+#[test]
+fn test_sector_and_chip_erase() {
+    let flash = SpiFlash::with_image(16 << 10, &[0; 16 << 10]).unwrap();
+    let mut spi = Spi::new().attach(0, flash.clone());
+
+    command(&mut spi, &[0x06]);
+    command(&mut spi, &[0x20, 0, 0x10, 0x23]);
+    let contents = flash.contents();
+    assert!(contents[0x1000..0x2000].iter().all(|&b| b == 0xFF));
+    assert!(contents[..0x1000].iter().all(|&b| b == 0));
+    assert!(contents[0x2000..].iter().all(|&b| b == 0));
+
+    command(&mut spi, &[0xC7]);
+    assert_eq!(flash.contents()[0], 0, "write enable not set");
+    command(&mut spi, &[0x06]);
+    command(&mut spi, &[0xC7]);
+    assert!(flash.contents().iter().all(|&b| b == 0xFF));
+}
+
+// ── Controller ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_auto_mode_ends_the_command_after_each_byte() {
+    let mut spi = Spi::new().attach(0, SpiFlash::new(4096));
+
+    for byte in [0x9F, 0] {
+        spi.write(TXDATA, MemSize::Word, byte);
+    }
+    assert_eq!(spi.read(RXDATA, MemSize::Word), 0xFF);
+    assert_eq!(spi.read(RXDATA, MemSize::Word), 0xFF, "a new command");
+    assert_eq!(spi.read(RXDATA, MemSize::Word), RX_EMPTY);
+}
+
+#[test]
+fn test_nothing_on_a_chip_select_reads_ones() {
+    let mut spi = Spi::new().attach(1, SpiFlash::new(4096));
+
+    assert_eq!(command(&mut spi, &[0x9F, 0]), [0xFF, 0xFF]);
+}
+
+#[test]
+fn test_transmit_only_frames_skip_the_fifo() {
+    let mut spi = Spi::new();
+    spi.write(FMT, MemSize::Word, 0x0008_0008);
+
+    spi.write(TXDATA, MemSize::Word, 0x42);
+    assert_eq!(spi.read(RXDATA, MemSize::Word), RX_EMPTY);
+}
+
+#[test]
+fn test_receive_watermark_interrupt() {
+    let mut spi = Spi::new().with_interrupt(0, csr::MIP_SEIP);
+    spi.write(IE, MemSize::Word, 2);
+    spi.write(RXMARK, MemSize::Word, 1);
+
+    spi.write(TXDATA, MemSize::Word, 0);
+    assert_eq!(spi.interrupts(0), 0);
+    spi.write(TXDATA, MemSize::Word, 0);
+    assert_eq!(spi.read(IP, MemSize::Word), 2);
+    assert_eq!(spi.interrupts(0), csr::MIP_SEIP);
+
+    spi.read(RXDATA, MemSize::Word);
+    assert_eq!(spi.interrupts(0), 0);
+}
+
+// ── Execute in place ──────────────────────────────────────────────────────────
+
+#[test]
+fn test_guest_executes_from_flash() {
+    let mut contents = image(
+        "
+        addi  a0, zero, 7
+        lui   t0, 0x20000
+        lw    a1, 12(t0)
+        ",
+    );
+    contents.extend_from_slice(&0xCAFEu32.to_le_bytes());
+    let flash = SpiFlash::with_image(1 << 16, &contents).unwrap();
+
+    let mut cpu = RiscvCpu::builder()
+        .reset_vector(FlashXip::BASE)
+        .device(FlashXip::BASE, flash.size() as u32, flash.xip())
+        .device(Spi::BASE, Spi::SIZE, Spi::new().attach(0, flash))
+        .build()
+        .unwrap();
+
+    cpu.run_steps(3);
+    assert_eq!(cpu.regs[10], 7);
+    assert_eq!(cpu.regs[11], 0xCAFE);
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use riscv_emulator_rust::csr::Privilege;
+use riscv_emulator_rust::state::ABI_NAMES;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, RiscvCpu};
+
+use common::{cpu_with, image};
+
+const PROGRAM: &str = "
+        addi a0, zero, 42
+        addi sp, zero, 0x100
+        addi t6, zero, -1
+        ebreak
+        addi a1, zero, 1
+";
+
+#[test]
+fn test_abi_names() {
+    assert_eq!(ABI_NAMES[0], "zero");
+    assert_eq!(ABI_NAMES[2], "sp");
+    assert_eq!(ABI_NAMES[8], "s0");
+    assert_eq!(ABI_NAMES[10], "a0");
+    assert_eq!(ABI_NAMES[18], "s2");
+    assert_eq!(ABI_NAMES[31], "t6");
+}
+
+#[test]
+fn test_state_captures_the_hart() {
+    let mut cpu = cpu_with(PROGRAM);
+    cpu.run();
+
+    let state = cpu.state();
+    assert_eq!(state.xlen, 32);
+    assert_eq!(state.pc, 0xC);
+    assert_eq!(state.regs[10], 42);
+    assert_eq!(state.regs[31], 0xFFFF_FFFF);
+    assert_eq!(state.privilege, Privilege::Machine);
+    assert_eq!(state.code.first(), Some(&(0x0, Some(0x02A0_0513))));
+    assert_eq!(state.code.len(), 7);
+}
+
+#[test]
+fn test_display_names_registers_and_marks_the_pc() {
+    let mut cpu = cpu_with(PROGRAM);
+    cpu.run();
+    let text = cpu.state().to_string();
+
+    assert!(text.contains("  a0 0x0000002a"), "{}", text);
+    assert!(text.contains("  sp 0x00000100"));
+    assert!(text.contains("  t6 0xffffffff"));
+    assert!(text.contains("  pc 0x0000000c (M-mode)"));
+    assert!(text.contains("=> 0000000c:  00100073  ebreak"), "{}", text);
+    assert!(
+        text.contains("   00000010:  00100593  addi x11, x0, 1"),
+        "{}",
+        text
+    );
+    assert_eq!(text.lines().filter(|l| l.starts_with("=>")).count(), 1);
+}
+
+#[test]
+fn test_code_stops_at_the_edges_of_ram() {
+    let mut cpu = RiscvCpu::builder()
+        .ram_size(0x10)
+        .image(0, image(&PROGRAM.replace("addi a1, zero, 1", "")))
+        .build()
+        .unwrap();
+    assert!(matches!(cpu.run(), ExitReason::Exception(_)));
+
+    let state = cpu.state();
+    assert_eq!(state.code[0], (0x0, Some(0x02A0_0513)));
+    assert_eq!(state.code.last(), Some(&(0x18, None)));
+    assert!(state.to_string().contains("   00000018:  ????????"));
+}
+
+#[test]
+fn test_rv64_is_wider() {
+    let cpu = RiscvCpu::builder().xlen::<Rv64>().build().unwrap();
+    let text = cpu.state().to_string();
+
+    assert!(text.contains("zero 0x0000000000000000"), "{}", text);
+}
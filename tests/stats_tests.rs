@@ -0,0 +1,153 @@
+mod common;
+
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::{Engine, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source).engine(engine).stats(true).build().unwrap()
+}
+
+/// One `lui`, four `addi`s, three `bne`s and a `jal`; the `ebreak` traps.
+const LOOP: &str = "
+        lui   a1, 1
+        addi  a0, zero, 3
+    top:
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        jal   zero, done
+    done:
+        ebreak
+";
+
+// ── Instruction mix ───────────────────────────────────────────────────────────
+
+#[test]
+fn test_instruction_mix_counts_mnemonics() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = cpu_with(LOOP, engine);
+        cpu.run();
+
+        let mix = cpu.stats().instruction_mix();
+        let counts: Vec<(&str, u64)> = mix.iter().collect();
+        assert_eq!(
+            counts,
+            [("addi", 4), ("bne", 3), ("jal", 1), ("lui", 1)],
+            "{:?}",
+            engine
+        );
+        assert_eq!(mix.total(), 9, "{:?}", engine);
+        assert_eq!(cpu.stats().instructions(), 9, "{:?}", engine);
+        assert_eq!(mix.count("ebreak"), 0, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_histogram_scales_to_the_most_executed() {
+    let mut cpu = cpu_with(LOOP, Engine::Interpreter);
+    cpu.run();
+
+    let histogram = cpu.stats().instruction_mix().to_string();
+    let lines: Vec<&str> = histogram.lines().collect();
+
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("addi"));
+    assert!(lines[0].contains("44.44%"));
+    assert!(lines[0].ends_with(&"#".repeat(40)));
+    assert!(lines[1].ends_with(&format!(" {}", "#".repeat(30))));
+}
+
+// ── Branches ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_branch_sites_count_each_direction() {
+    // The loop branch at 0xC is taken twice then falls through; the beq at
+    // 0x14 never is.
+    let source = "
+        addi  a0, zero, 3
+        addi  a1, zero, 0
+    top:
+        addi  a0, a0, -1
+        bne   a0, zero, top
+        addi  a1, a1, 1
+        beq   a1, zero, top
+        ebreak
+    ";
+    let mut cpu = cpu_with(source, Engine::BasicBlocks);
+    cpu.run();
+
+    let stats = cpu.stats();
+    let sites: Vec<(u32, u64, u64)> = stats
+        .branch_sites()
+        .map(|(pc, counts)| (pc, counts.executed, counts.taken))
+        .collect();
+    assert_eq!(sites, [(0xC, 3, 2), (0x14, 1, 0)]);
+
+    let looping = stats.branch_site(0xC).unwrap();
+    assert_eq!(looping.not_taken(), 1);
+    assert!((looping.taken_rate() - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(stats.branch_site(0x14).unwrap().taken_rate(), 0.0);
+    assert_eq!(stats.branch_site(0x8), None);
+
+    let total = stats.branches();
+    assert_eq!((total.executed, total.taken, total.not_taken()), (4, 2, 2));
+    assert_eq!(cpu.stats().instruction_mix().count("bne"), 3);
+}
+
+#[test]
+fn test_jumps_arent_branches() {
+    let mut cpu = cpu_with(LOOP, Engine::Interpreter);
+    cpu.run();
+
+    assert_eq!(cpu.stats().branches().executed, 3);
+    assert_eq!(cpu.stats().branch_sites().count(), 1);
+}
+
+#[test]
+fn test_off_by_default() {
+    let mut cpu = program(LOOP).build().unwrap();
+
+    cpu.run_steps(2);
+    assert!(!cpu.stats_enabled());
+    assert!(cpu.stats().instruction_mix().is_empty());
+
+    cpu.set_stats_enabled(true);
+    cpu.run_steps(2);
+    cpu.set_stats_enabled(false);
+    cpu.run_steps(2);
+
+    let mix = cpu.stats().instruction_mix();
+    assert_eq!(mix.count("addi"), 1);
+    assert_eq!(mix.count("bne"), 1);
+    assert_eq!(mix.len(), 2);
+
+    assert_eq!(cpu.stats().branches().executed, 1);
+
+    cpu.reset_stats();
+    assert_eq!(cpu.stats().instructions(), 0);
+    assert_eq!(cpu.stats().branches().executed, 0);
+}
+
+#[test]
+fn test_machine_adds_up_its_harts() {
+    let mut machine = Machine::new(cpu_with(LOOP, Engine::Interpreter), 2);
+    machine.set_quantum(1);
+
+    machine.run_steps(18);
+
+    assert!(machine.hart(1).stats_enabled());
+    let mix = machine.stats().instruction_mix();
+    assert_eq!(mix.count("addi"), 8);
+    assert_eq!(mix.count("lui"), 2);
+    assert_eq!(machine.stats().branch_site(0xC).unwrap().taken, 4);
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, RiscvCpu};
+
+use common::image_at;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, privilege: Privilege) -> RiscvCpu {
+    let mut cpu = common::cpu_with(source);
+    cpu.set_privilege(privilege);
+    cpu
+}
+
+// ── Views ─────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_sstatus_is_a_view_of_mstatus() {
+    let mut cpu = cpu_with(
+        "
+        addi  t0, zero, -1
+        csrrw zero, sstatus, t0
+        csrrs a0, sstatus, zero
+        csrrs a1, mstatus, zero
+        ",
+        Privilege::Machine,
+    );
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MPP);
+
+    cpu.run_steps(4);
+
+    let supervisor = csr::MSTATUS_SIE
+        | csr::MSTATUS_SPIE
+        | csr::MSTATUS_SPP
+        | csr::MSTATUS_VS
+        | csr::MSTATUS_FS
+        | csr::MSTATUS_SUM
+        | csr::MSTATUS_MXR;
+    let sd = 1 << 31;
+    assert_eq!(cpu.regs[10], supervisor | sd);
+    assert_eq!(cpu.regs[11], supervisor | sd | csr::MSTATUS_MPP);
+}
+
+#[test]
+fn test_rv64_sstatus_shows_uxl() {
+    let cpu = RiscvCpu::builder().xlen::<Rv64>().build().unwrap();
+    assert_eq!(cpu.csrs.read(csr::SSTATUS) >> 32, 2);
+}
+
+#[test]
+fn test_sie_and_sip_only_show_delegated_interrupts() {
+    let mut cpu = cpu_with("", Privilege::Machine);
+    cpu.csrs.write(csr::MIE, csr::MIP_STIP | csr::MIP_MTIP);
+    cpu.raise_interrupt(Interrupt::SupervisorSoftware);
+    assert_eq!(cpu.csrs.read(csr::SIE), 0);
+    assert_eq!(cpu.csrs.read(csr::SIP), 0);
+
+    cpu.csrs.write(csr::MIDELEG, csr::MIP_STIP | csr::MIP_SSIP);
+    assert_eq!(cpu.csrs.read(csr::SIE), csr::MIP_STIP);
+    assert_eq!(cpu.csrs.read(csr::SIP), csr::MIP_SSIP);
+
+    cpu.csrs.write(csr::SIE, 0);
+    assert_eq!(cpu.csrs.read(csr::MIE), csr::MIP_MTIP, "MTIP untouched");
+
+    cpu.csrs.write(csr::SIP, 0);
+    assert_eq!(cpu.csrs.read(csr::MIP), 0, "SSIP is writable");
+    cpu.csrs.write(csr::MIP, csr::MIP_STIP);
+    cpu.csrs.write(csr::SIP, 0);
+    assert_eq!(cpu.csrs.read(csr::MIP), csr::MIP_STIP, "STIP isn't");
+}
+
+// ── SRET ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_sret_returns_to_spp() {
+    let mut cpu = cpu_with("sret", Privilege::Supervisor);
+    cpu.csrs.write(csr::SEPC, 0x100);
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_SPIE);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.pc, 0x100);
+    assert_eq!(cpu.privilege(), Privilege::User);
+    let mstatus = cpu.csrs.read(csr::MSTATUS);
+    assert_ne!(mstatus & csr::MSTATUS_SIE, 0, "SIE <- SPIE");
+    assert_ne!(mstatus & csr::MSTATUS_SPIE, 0);
+
+    let mut cpu = cpu_with("sret", Privilege::Machine);
+    cpu.csrs.write(csr::SEPC, 0x200);
+    cpu.csrs
+        .write(csr::MSTATUS, csr::MSTATUS_SPP | csr::MSTATUS_MPRV);
+
+    cpu.step().unwrap();
+
+    assert_eq!(cpu.privilege(), Privilege::Supervisor);
+    let mstatus = cpu.csrs.read(csr::MSTATUS);
+    assert_eq!(mstatus & csr::MSTATUS_SPP, 0, "SPP <- U");
+    assert_eq!(mstatus & csr::MSTATUS_MPRV, 0);
+}
+
+#[test]
+fn test_sret_permissions() {
+    let sret = assemble("sret").unwrap()[0];
+
+    let mut cpu = cpu_with("sret", Privilege::User);
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(sret)));
+
+    let mut cpu = cpu_with("sret", Privilege::Supervisor);
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_TSR);
+    assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(sret)));
+
+    let mut cpu = cpu_with("sret", Privilege::Machine);
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_TSR);
+    assert!(cpu.step().is_ok(), "TSR doesn't apply to M-mode");
+}
+
+#[test]
+fn test_user_ecall_round_trip_through_supervisor() {
+    let mut cpu = RiscvCpu::builder()
+        .image(
+            0,
+            image_at(
+                0,
+                "
+                addi  a0, zero, 1
+                ecall
+                addi  a0, a0, 100
+                ebreak
+                ",
+            ),
+        )
+        .image(
+            0x800,
+            image_at(
+                0x800,
+                "
+                addi  a0, a0, 10
+                csrrs t0, sepc, zero
+                addi  t0, t0, 4
+                csrrw zero, sepc, t0
+                sret
+                ",
+            ),
+        )
+        .guest_traps(true)
+        .build()
+        .unwrap();
+    cpu.csrs.write(csr::STVEC, 0x800);
+    cpu.csrs.write(csr::MTVEC, 0x400);
+    cpu.csrs
+        .write(csr::MEDELEG, 1 << Exception::UserEnvironmentCall.cause());
+    cpu.set_privilege(Privilege::User);
+
+    cpu.run_steps(9);
+
+    assert_eq!(
+        cpu.privilege(),
+        Privilege::Machine,
+        "EBREAK isn't delegated"
+    );
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0xC);
+    assert_eq!(cpu.regs[10], 111);
+}
+
+// ── Counters and scratch ──────────────────────────────────────────────────────
+
+#[test]
+fn test_scounteren_gates_user_mode() {
+    let source = "csrrs a0, cycle, zero";
+
+    let mut cpu = cpu_with(source, Privilege::User);
+    cpu.csrs.write(csr::MCOUNTEREN, csr::COUNTEREN_CY);
+    assert!(matches!(cpu.step(), Err(Exception::IllegalInstruction(_))));
+
+    let mut cpu = cpu_with(source, Privilege::Supervisor);
+    cpu.csrs.write(csr::MCOUNTEREN, csr::COUNTEREN_CY);
+    assert!(cpu.step().is_ok(), "S-mode only needs mcounteren");
+
+    let mut cpu = cpu_with(source, Privilege::User);
+    cpu.csrs.write(csr::MCOUNTEREN, csr::COUNTEREN_CY);
+    cpu.csrs.write(csr::SCOUNTEREN, csr::COUNTEREN_CY);
+    assert!(cpu.step().is_ok());
+}
+
+#[test]
+fn test_sscratch_and_disassembly() {
+    let mut cpu = cpu_with(
+        "
+        addi  t0, zero, 42
+        csrrw zero, sscratch, t0
+        csrrs a0, sscratch, zero
+        ",
+        Privilege::Supervisor,
+    );
+    cpu.run_steps(3);
+    assert_eq!(cpu.regs[10], 42);
+
+    let sret = assemble("sret").unwrap()[0];
+    assert_eq!(sret, 0x1020_0073);
+    assert_eq!(decode(sret).unwrap().to_string(), "sret");
+
+    let mut cpu = cpu_with("csrrs a0, sstatus, zero", Privilege::User);
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::IllegalInstruction(
+            assemble("csrrs a0, sstatus, zero").unwrap()[0]
+        ))
+    );
+}
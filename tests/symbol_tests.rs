@@ -0,0 +1,264 @@
+mod common;
+
+use riscv_emulator_rust::RiscvCpu;
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::loader::{ElfFile, Symbol, SymbolTable};
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::trace::PrintTracer;
+
+use common::SharedBuffer;
+
+// ── Helper: an ELF32 image with a symbol table ────────────────────────────────
+
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+/// (name, value, size, type, section index)
+type SymbolSpec<'a> = (&'a str, u32, u32, u8, u16);
+
+/// Build an ELF32 RISC-V executable with `code` loaded at 0 and a `.symtab`
+/// holding `symbols`, all global.
+fn build_elf(code: &[u8], symbols: &[SymbolSpec]) -> Vec<u8> {
+    let code_off = 52 + 32;
+
+    let mut strtab = vec![0u8];
+    let mut symtab = vec![0u8; 16];
+    for (name, value, size, kind, shndx) in symbols {
+        for field in [strtab.len() as u32, *value, *size] {
+            symtab.extend_from_slice(&field.to_le_bytes());
+        }
+        symtab.extend_from_slice(&[0x10 | kind, 0]);
+        symtab.extend_from_slice(&shndx.to_le_bytes());
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+
+    let symtab_off = code_off + code.len();
+    let strtab_off = symtab_off + symtab.len();
+    let shoff = strtab_off + strtab.len();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+    out.extend_from_slice(&243u16.to_le_bytes()); // e_machine = EM_RISCV
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_entry
+    out.extend_from_slice(&52u32.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&(shoff as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&52u16.to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&32u16.to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&3u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    let size = code.len() as u32;
+    for field in [1, code_off as u32, 0, 0, size, size, 0x7, 4] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+
+    out.extend_from_slice(code);
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab);
+
+    // section headers: null, .symtab (link -> 2), .strtab
+    let symtab_sh = [
+        0,
+        2,
+        0,
+        0,
+        symtab_off as u32,
+        symtab.len() as u32,
+        2,
+        1,
+        4,
+        16,
+    ];
+    let strtab_sh = [
+        0,
+        3,
+        0,
+        0,
+        strtab_off as u32,
+        strtab.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ];
+    for section in [[0; 10], symtab_sh, strtab_sh] {
+        for field in section {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+
+    out
+}
+
+fn code(source: &str) -> Vec<u8> {
+    assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect()
+}
+
+fn symbol(name: &str, addr: u32, size: u32, function: bool) -> Symbol {
+    Symbol {
+        name: String::from(name),
+        addr,
+        size,
+        function,
+    }
+}
+
+// ── Parsing ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_symbols_are_read_from_symtab() {
+    let elf = build_elf(
+        &[0; 16],
+        &[
+            ("main", 0x0, 8, STT_FUNC, 1),
+            ("counter", 0x8, 4, STT_OBJECT, 1),
+            ("loop", 0x4, 0, STT_NOTYPE, 1),
+        ],
+    );
+
+    let symbols = ElfFile::parse(&elf).unwrap().symbols();
+
+    let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["main", "loop", "counter"], "sorted by address");
+    assert_eq!(symbols.get("main"), Some(&symbol("main", 0, 8, true)));
+    assert_eq!(
+        symbols.get("counter"),
+        Some(&symbol("counter", 8, 4, false))
+    );
+}
+
+#[test]
+fn test_uninteresting_symbols_are_skipped() {
+    let elf = build_elf(
+        &[0; 16],
+        &[
+            ("printf", 0, 0, STT_FUNC, 0),
+            (".text", 0, 0, STT_SECTION, 1),
+            ("$x", 0, 0, STT_NOTYPE, 1),
+            (".L3", 4, 0, STT_NOTYPE, 1),
+            ("_start", 0, 0, STT_NOTYPE, 1),
+        ],
+    );
+
+    let symbols = ElfFile::parse(&elf).unwrap().symbols();
+
+    assert_eq!(symbols.len(), 1);
+    assert!(symbols.get("_start").is_some());
+}
+
+#[test]
+fn test_stripped_image_has_no_symbols() {
+    let mut elf = build_elf(&[0; 4], &[("main", 0, 4, STT_FUNC, 1)]);
+    elf[48] = 0; // e_shnum
+
+    assert!(ElfFile::parse(&elf).unwrap().symbols().is_empty());
+}
+
+// ── Lookups ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_symbol_at_uses_sizes() {
+    let table = SymbolTable::new(vec![
+        symbol("main", 0x100, 0x20, true),
+        symbol("helper", 0x140, 0x10, true),
+    ]);
+
+    assert_eq!(table.symbol_at(0xFC), None);
+    assert_eq!(
+        table.symbol_at(0x100).map(|(s, o)| (&*s.name, o)),
+        Some(("main", 0))
+    );
+    assert_eq!(
+        table.symbol_at(0x11C).map(|(s, o)| (&*s.name, o)),
+        Some(("main", 0x1C))
+    );
+    assert_eq!(table.symbol_at(0x120), None, "the gap after main");
+    assert_eq!(table.describe(0x148).as_deref(), Some("helper+0x8"));
+    assert_eq!(table.describe(0x140).as_deref(), Some("helper"));
+}
+
+#[test]
+fn test_unsized_label_runs_to_the_next_symbol() {
+    let table = SymbolTable::new(vec![
+        symbol("_start", 0, 0, false),
+        symbol("trap", 0x40, 0, false),
+    ]);
+
+    assert_eq!(table.describe(0x3C).as_deref(), Some("_start+0x3c"));
+    assert_eq!(table.describe(0x1000).as_deref(), Some("trap+0xfc0"));
+}
+
+#[test]
+fn test_functions_win_over_labels_at_the_same_address() {
+    let table = SymbolTable::new(vec![
+        symbol("main", 0x10, 8, true),
+        symbol("entry", 0x10, 0, false),
+    ]);
+
+    assert_eq!(table.describe(0x14).as_deref(), Some("main+0x4"));
+}
+
+// ── On the hart ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_load_elf_keeps_symbols() {
+    let mut cpu = RiscvCpu::new(1024);
+    let elf = build_elf(
+        &code("addi a0, zero, 1\naddi a0, a0, 1"),
+        &[("main", 0, 8, STT_FUNC, 1)],
+    );
+
+    cpu.load_elf(&elf).unwrap();
+
+    let (symbol, offset) = cpu.symbol_at(4).unwrap();
+    assert_eq!((symbol.name.as_str(), offset), ("main", 4));
+
+    cpu.load_elf(&build_elf(&[0; 4], &[])).unwrap();
+    assert!(cpu.symbols().is_empty(), "replaced by the new image's");
+}
+
+#[test]
+fn test_trace_shows_function_names() {
+    let mut cpu = RiscvCpu::new(1024);
+    let elf = build_elf(
+        &code("addi a0, zero, 1\njal zero, helper\nhelper: addi a0, a0, 1"),
+        &[("main", 0, 8, STT_FUNC, 1), ("helper", 8, 4, STT_FUNC, 1)],
+    );
+    cpu.load_elf(&elf).unwrap();
+    let output = SharedBuffer::default();
+    cpu.set_tracer(
+        PrintTracer::with_output(Box::new(output.clone())).symbols(cpu.symbols().clone()),
+    );
+
+    cpu.run_steps(3);
+
+    let text = output.contents();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].starts_with("0x00000000 <main>: "), "{}", text);
+    assert!(lines[1].starts_with("0x00000004 <main+0x4>: "), "{}", text);
+    assert!(lines[2].starts_with("0x00000008 <helper>: "), "{}", text);
+}
+
+#[test]
+fn test_every_hart_gets_the_symbols() {
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.load_elf(&build_elf(&[0; 8], &[("main", 0, 8, STT_FUNC, 1)]))
+        .unwrap();
+
+    let machine = Machine::new(cpu, 2);
+
+    assert_eq!(machine.hart(1).symbols().len(), 1);
+}
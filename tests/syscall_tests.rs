@@ -0,0 +1,1132 @@
+#[cfg(feature = "std")]
+use std::fs;
+use std::io::Cursor;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+use std::time::Duration;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::clock::{Clock, TimeMode};
+use riscv_emulator_rust::syscall::*;
+use riscv_emulator_rust::user::Process;
+#[cfg(feature = "std")]
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, MemSize, Reg, RiscvCpu};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const BASE: u32 = 0x10000;
+const RAM: usize = 0x100000;
+const STACK: u32 = 0x10000;
+/// The first page after the images below.
+const HEAP: u32 = 0x11000;
+/// Where mappings start, below the stack.
+const MMAP_TOP: u32 = RAM as u32 - STACK;
+/// Where tests put strings for the guest, in the middle of nowhere.
+const DATA: u32 = 0x20000;
+/// Where the guest reads into.
+const BUF: u32 = 0x21000;
+
+/// A minimal ELF32 executable with `code` as its only segment, at [`BASE`].
+fn build_elf(code: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+    out.extend_from_slice(&243u16.to_le_bytes()); // e_machine = EM_RISCV
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&BASE.to_le_bytes()); // e_entry
+    out.extend_from_slice(&52u32.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_shoff
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&52u16.to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&32u16.to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    let size = code.len() as u32;
+    for field in [1, 84, BASE, BASE, size, size, 0x5, 0x1000] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend_from_slice(code);
+    out
+}
+
+fn process(source: &str) -> RiscvCpu {
+    start(source, Process::new("prog"))
+}
+
+fn start(source: &str, process: Process) -> RiscvCpu {
+    let code: Vec<u8> = assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect();
+    let mut cpu = RiscvCpu::builder().ram_size(RAM).build().unwrap();
+    cpu.load_process(&build_elf(&code), &process.stack_size(STACK))
+        .unwrap();
+    cpu
+}
+
+/// Assembly for system call `nr` with `args`, each a number or the
+/// register holding it, leaving the result in `result`.
+fn call(nr: u64, args: &[&str], result: &str) -> String {
+    let mut source = String::new();
+    for (i, arg) in args.iter().enumerate() {
+        let op = if arg.starts_with(char::is_alphabetic) {
+            "mv"
+        } else {
+            "li"
+        };
+        source += &format!("{} a{}, {}\n", op, i, arg);
+    }
+    source + &format!("li a7, {}\necall\nmv {}, a0\n", nr, result)
+}
+
+/// A fresh, empty directory to use as a sandbox root.
+#[cfg(feature = "std")]
+fn sandbox(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("syscall-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `a0` as the signed value a syscall returns.
+fn result(cpu: &RiscvCpu, reg: Reg) -> i64 {
+    cpu.reg(reg) as u32 as i32 as i64
+}
+
+fn stops_at_ebreak(cpu: &mut RiscvCpu) {
+    let exit = cpu.run();
+    assert!(matches!(exit, ExitReason::Exception(_)), "{:?}", exit);
+}
+
+// ── Exiting ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_exit_stops_the_run_with_its_status() {
+    let mut cpu = process(
+        "
+        li  a0, 3
+        li  a7, 93
+        ecall
+        ebreak
+        ",
+    );
+
+    assert_eq!(cpu.run(), ExitReason::Exited(3));
+}
+
+#[test]
+fn test_exit_group_keeps_the_low_byte() {
+    let mut cpu = process(
+        "
+        li  a0, 257
+        li  a7, 94
+        ecall
+        ",
+    );
+
+    assert_eq!(cpu.run(), ExitReason::Exited(1));
+}
+
+#[test]
+fn test_unknown_syscalls_return_enosys() {
+    let mut cpu = process(
+        "
+        li  a7, 1999
+        ecall
+        ebreak
+        ",
+    );
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::A0), -ENOSYS);
+}
+
+#[test]
+fn test_ecall_traps_without_a_process() {
+    let mut cpu = RiscvCpu::builder()
+        .image(0, 0x0000_0073u32.to_le_bytes())
+        .build()
+        .unwrap();
+
+    assert!(matches!(cpu.run(), ExitReason::Exception(_)));
+    assert!(cpu.syscalls().is_none());
+}
+
+// ── brk ───────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_brk_grows_the_heap() {
+    let mut cpu = process(
+        "
+        li   a0, 0
+        li   a7, 214
+        ecall
+        mv   s0, a0
+        li   t0, 0x2000
+        add  a0, s0, t0
+        ecall
+        mv   s1, a0
+        li   t1, 0x55
+        sb   t1, -1(s1)
+        ebreak
+        ",
+    );
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), HEAP as u64);
+    assert_eq!(cpu.reg(Reg::S1), HEAP as u64 + 0x2000);
+    assert_eq!(cpu.syscalls().unwrap().heap(), HEAP..HEAP + 0x2000);
+    assert_eq!(cpu.bus.read(HEAP + 0x1fff, MemSize::Byte), Some(0x55));
+}
+
+#[test]
+fn test_brk_zeroes_what_the_heap_grows_into() {
+    let mut cpu = process(
+        "
+        li   a0, 0x11100
+        li   a7, 214
+        ecall
+        ebreak
+        ",
+    );
+    cpu.bus.write_bytes(HEAP, &[0xAA; 0x100]).unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.syscalls().unwrap().brk(), HEAP + 0x100);
+    assert_eq!(cpu.bus.read(HEAP, MemSize::Word), Some(0));
+    assert_eq!(cpu.bus.read(HEAP + 0xFC, MemSize::Word), Some(0));
+}
+
+#[test]
+fn test_brk_failure_returns_the_old_break() {
+    let mut cpu = process(
+        "
+        li   a0, 0x11800
+        li   a7, 214
+        ecall
+        li   a0, 0x200000
+        ecall
+        mv   s0, a0
+        li   a0, 0x1000
+        ecall
+        mv   s1, a0
+        ebreak
+        ",
+    );
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 0x11800, "past the end of RAM");
+    assert_eq!(cpu.reg(Reg::S1), 0x11800, "below the heap");
+}
+
+// ── mmap ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_mmap_allocates_zeroed_pages_below_the_stack() {
+    let mut cpu = process(
+        "
+        li   a0, 0
+        li   a1, 0x2800
+        li   a2, 3
+        li   a3, 0x22
+        li   a4, -1
+        li   a5, 0
+        li   a7, 222
+        ecall
+        mv   s0, a0
+        li   a0, 0
+        li   a1, 0x1000
+        ecall
+        mv   s1, a0
+        ebreak
+        ",
+    );
+    let first = MMAP_TOP - 0x3000;
+    cpu.bus.write_bytes(first, &[0xAA; 0x3000]).unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), first as u64);
+    assert_eq!(cpu.reg(Reg::S1), first as u64 - 0x1000);
+    assert_eq!(cpu.bus.read(first, MemSize::Word), Some(0));
+    assert_eq!(cpu.bus.read(MMAP_TOP - 4, MemSize::Word), Some(0));
+
+    let mappings: Vec<_> = cpu.syscalls().unwrap().mappings().collect();
+    assert_eq!(mappings, [first - 0x1000..first, first..MMAP_TOP]);
+}
+
+#[test]
+fn test_munmap_splits_a_mapping_and_frees_the_gap() {
+    let mut cpu = process(
+        "
+        li   a0, 0
+        li   a1, 0x3000
+        li   a2, 3
+        li   a3, 0x22
+        li   a4, -1
+        li   a5, 0
+        li   a7, 222
+        ecall
+        mv   s0, a0
+        li   t0, 0x1000
+        add  a0, s0, t0
+        li   a1, 0x1000
+        li   a7, 215
+        ecall
+        mv   s1, a0
+        li   a0, 0
+        li   a1, 0x1000
+        li   a7, 222
+        ecall
+        mv   s2, a0
+        ebreak
+        ",
+    );
+
+    stops_at_ebreak(&mut cpu);
+    let start = MMAP_TOP - 0x3000;
+    assert_eq!(cpu.reg(Reg::S1), 0);
+    assert_eq!(cpu.reg(Reg::S2), start as u64 + 0x1000, "reuses the gap");
+
+    let mappings: Vec<_> = cpu.syscalls().unwrap().mappings().collect();
+    assert_eq!(
+        mappings,
+        [
+            start..start + 0x1000,
+            start + 0x1000..start + 0x2000,
+            start + 0x2000..MMAP_TOP
+        ]
+    );
+}
+
+#[test]
+fn test_mmap_fixed() {
+    let mut cpu = process(
+        "
+        li   a0, 0x40000
+        li   a1, 0x2000
+        li   a2, 3
+        li   a3, 0x32
+        li   a4, -1
+        li   a5, 0
+        li   a7, 222
+        ecall
+        mv   s0, a0
+        li   a0, 0x41000
+        li   a1, 0x2000
+        li   a3, 0x100022
+        ecall
+        mv   s1, a0
+        li   a0, 0x41000
+        li   a3, 0x32
+        ecall
+        mv   s2, a0
+        ebreak
+        ",
+    );
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 0x40000);
+    assert_eq!(result(&cpu, Reg::S1), -EEXIST);
+    assert_eq!(cpu.reg(Reg::S2), 0x41000);
+
+    let mappings: Vec<_> = cpu.syscalls().unwrap().mappings().collect();
+    assert_eq!(mappings, [0x40000..0x41000, 0x41000..0x43000]);
+}
+
+#[test]
+fn test_brk_stops_at_a_mapping() {
+    let mut cpu = process(
+        "
+        li   a0, 0x13000
+        li   a1, 0x1000
+        li   a2, 3
+        li   a3, 0x32
+        li   a4, -1
+        li   a5, 0
+        li   a7, 222
+        ecall
+        li   a0, 0x13000
+        li   a7, 214
+        ecall
+        mv   s0, a0
+        li   a0, 0x13001
+        ecall
+        mv   s1, a0
+        ebreak
+        ",
+    );
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 0x13000);
+    assert_eq!(cpu.reg(Reg::S1), 0x13000);
+}
+
+#[test]
+fn test_mmap_rejects_what_it_cant_map() {
+    let mut cpu = process(
+        "
+        li   a0, 0
+        li   a1, 0x1000
+        li   a2, 3
+        li   a3, 0x02
+        li   a4, 3
+        li   a5, 0
+        li   a7, 222
+        ecall
+        mv   s0, a0
+        li   a1, 0
+        li   a3, 0x22
+        ecall
+        mv   s1, a0
+        li   a1, 0x200000
+        ecall
+        mv   s2, a0
+        li   a0, 0x40001
+        li   a1, 0x1000
+        li   a3, 0x32
+        ecall
+        mv   s3, a0
+        ebreak
+        ",
+    );
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -ENODEV, "file mapping");
+    assert_eq!(result(&cpu, Reg::S1), -EINVAL, "zero length");
+    assert_eq!(result(&cpu, Reg::S2), -ENOMEM, "bigger than RAM");
+    assert_eq!(result(&cpu, Reg::S3), -EINVAL, "misaligned");
+    assert_eq!(cpu.syscalls().unwrap().mappings().count(), 0);
+}
+
+// ── Files ─────────────────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+#[test]
+fn test_open_read_and_close_a_file_in_the_sandbox() {
+    let root = sandbox("read");
+    fs::write(root.join("input.txt"), "hello").unwrap();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_READ, &["s0", "0x21000", "100"], "s1"),
+        call(SYS_READ, &["s0", "0x21000", "100"], "s2"),
+        call(SYS_CLOSE, &["s0"], "s3"),
+        call(SYS_CLOSE, &["s0"], "s4"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"/input.txt\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 3, "the lowest free descriptor");
+    assert_eq!(cpu.reg(Reg::S1), 5);
+    assert_eq!(cpu.reg(Reg::S2), 0, "end of file");
+    assert_eq!(cpu.reg(Reg::S3), 0);
+    assert_eq!(result(&cpu, Reg::S4), -EBADF);
+    assert_eq!(
+        cpu.bus.read(BUF, MemSize::Word),
+        Some(u32::from_le_bytes(*b"hell"))
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_write_creates_a_file() {
+    let root = sandbox("write");
+    fs::create_dir(root.join("out")).unwrap();
+    let flags = (O_WRONLY | O_CREAT | O_TRUNC).to_string();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", &flags], "s0"),
+        call(SYS_WRITE, &["s0", "0x20100", "6"], "s1"),
+        call(SYS_CLOSE, &["s0"], "s2"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"out/./result.txt\0").unwrap();
+    cpu.bus.write_bytes(DATA + 0x100, b"42\nok\n").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S1), 6);
+    assert_eq!(
+        fs::read_to_string(root.join("out/result.txt")).unwrap(),
+        "42\nok\n"
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_opening_what_isnt_there() {
+    let root = sandbox("missing");
+    fs::write(root.join("file"), "").unwrap();
+    let flags = (O_RDONLY | O_DIRECTORY).to_string();
+    let excl = (O_WRONLY | O_CREAT | O_EXCL).to_string();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_OPENAT, &["-100", "0x20100", &flags], "s1"),
+        call(SYS_OPENAT, &["-100", "0x20100", &excl], "s2"),
+        call(SYS_OPENAT, &["-100", "0x20100", "3"], "s3"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"/nope\0").unwrap();
+    cpu.bus.write_bytes(DATA + 0x100, b"/file\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -ENOENT);
+    assert_eq!(result(&cpu, Reg::S1), -ENOTDIR);
+    assert_eq!(result(&cpu, Reg::S2), -EEXIST);
+    assert_eq!(result(&cpu, Reg::S3), -EINVAL, "no such access mode");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_paths_relative_to_a_directory_descriptor() {
+    let root = sandbox("dirfd");
+    fs::create_dir(root.join("sub")).unwrap();
+    fs::write(root.join("sub/data"), "xyz").unwrap();
+    let flags = (O_RDONLY | O_DIRECTORY).to_string();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", &flags], "s0"),
+        call(SYS_OPENAT, &["s0", "0x20100", "0"], "s1"),
+        call(SYS_READ, &["s1", "0x21000", "3"], "s2"),
+        call(SYS_OPENAT, &["s1", "0x20100", "0"], "s3"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"sub\0").unwrap();
+    cpu.bus.write_bytes(DATA + 0x100, b"data\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 3);
+    assert_eq!(cpu.reg(Reg::S1), 4);
+    assert_eq!(cpu.reg(Reg::S2), 3);
+    assert_eq!(result(&cpu, Reg::S3), -ENOTDIR, "relative to a file");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_lseek_on_rv32_writes_the_position() {
+    let root = sandbox("llseek");
+    fs::write(root.join("f"), "hello").unwrap();
+    let end = SEEK_END.to_string();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_LSEEK, &["s0", "-1", "-2", "0x21100", &end], "s1"),
+        call(SYS_READ, &["s0", "0x21000", "10"], "s2"),
+        call(SYS_LSEEK, &["1", "0", "0", "0x21100", "0"], "s3"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"f\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S1), 0);
+    assert_eq!(cpu.bus.read(BUF + 0x100, MemSize::Word), Some(3));
+    assert_eq!(cpu.bus.read(BUF + 0x104, MemSize::Word), Some(0));
+    assert_eq!(cpu.reg(Reg::S2), 2);
+    assert_eq!(
+        cpu.bus.read(BUF, MemSize::Half),
+        Some(u16::from_le_bytes(*b"lo") as u32)
+    );
+    assert_eq!(result(&cpu, Reg::S3), -ESPIPE);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_lseek_on_rv64_returns_the_position() {
+    let root = sandbox("lseek");
+    fs::write(root.join("f"), "hello").unwrap();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_LSEEK, &["s0", "1", "0"], "s1"),
+        call(SYS_LSEEK, &["s0", "2", "1"], "s2"),
+    ]
+    .concat();
+    let code: Vec<u8> = assemble(&(source + "ebreak"))
+        .unwrap()
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect();
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .ram_size(RAM)
+        .build()
+        .unwrap();
+    let process = Process::new("prog").stack_size(STACK).sandbox(&root);
+    cpu.load_process(&build_elf(&code), &process).unwrap();
+    cpu.bus.write_bytes(DATA, b"f\0").unwrap();
+
+    assert!(matches!(cpu.run(), ExitReason::Exception(_)));
+    assert_eq!(cpu.reg(Reg::S1), 1);
+    assert_eq!(cpu.reg(Reg::S2), 3);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_fstat_and_statx_report_the_size_and_kind() {
+    let root = sandbox("stat");
+    fs::write(root.join("f"), "0123456789").unwrap();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_FSTAT, &["s0", "0x21000"], "s1"),
+        call(
+            SYS_STATX,
+            &["-100", "0x20000", "0", "0x7ff", "0x21100"],
+            "s2",
+        ),
+        call(SYS_FSTAT, &["1", "0x21200"], "s3"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"/f\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S1), 0);
+    let mode = cpu.bus.read(BUF + 16, MemSize::Word).unwrap();
+    assert_eq!(mode & 0o170000, 0o100000, "a regular file");
+    assert_eq!(cpu.bus.read(BUF + 48, MemSize::Word), Some(10));
+
+    assert_eq!(cpu.reg(Reg::S2), 0);
+    assert_eq!(cpu.bus.read(BUF + 0x100 + 40, MemSize::Word), Some(10));
+    let mode = cpu.bus.read(BUF + 0x100 + 28, MemSize::Half).unwrap();
+    assert_eq!(mode & 0o170000, 0o100000);
+
+    let mode = cpu.bus.read(BUF + 0x200 + 16, MemSize::Word).unwrap();
+    assert_eq!(
+        mode & 0o170000,
+        0o020000,
+        "the console is a character device"
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_descriptors_are_reused_lowest_first() {
+    let root = sandbox("reuse");
+    fs::write(root.join("f"), "").unwrap();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s1"),
+        call(SYS_CLOSE, &["s0"], "t0"),
+        call(SYS_CLOSE, &["0"], "t0"),
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s2"),
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s3"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"f\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(
+        [Reg::S0, Reg::S1, Reg::S2, Reg::S3].map(|r| cpu.reg(r)),
+        [3, 4, 0, 3]
+    );
+}
+
+// ── The sandbox ───────────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+#[test]
+fn test_dot_dot_stops_at_the_sandbox_root() {
+    let outer = sandbox("escape");
+    let root = outer.join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(outer.join("secret"), "no").unwrap();
+    fs::write(root.join("secret"), "yes").unwrap();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_READ, &["s0", "0x21000", "3"], "s1"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"../../secret\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S1), 3);
+    assert_eq!(
+        cpu.bus.read(BUF, MemSize::Half),
+        Some(u16::from_le_bytes(*b"ye") as u32)
+    );
+}
+
+#[cfg(feature = "std")]
+#[cfg(unix)]
+#[test]
+fn test_symlinks_cant_lead_out_of_the_sandbox() {
+    let outer = sandbox("symlink");
+    let root = outer.join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(outer.join("secret"), "no").unwrap();
+    std::os::unix::fs::symlink(outer.join("secret"), root.join("link")).unwrap();
+    std::os::unix::fs::symlink(outer.join("new"), root.join("dangling")).unwrap();
+    let create = (O_WRONLY | O_CREAT).to_string();
+    let source = [
+        call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0"),
+        call(SYS_OPENAT, &["-100", "0x20100", &create], "s1"),
+    ]
+    .concat();
+    let mut cpu = start(&(source + "ebreak"), Process::new("prog").sandbox(&root));
+    cpu.bus.write_bytes(DATA, b"/link\0").unwrap();
+    cpu.bus.write_bytes(DATA + 0x100, b"/dangling\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -EACCES);
+    assert_eq!(result(&cpu, Reg::S1), -EACCES);
+    assert!(!outer.join("new").exists());
+}
+
+#[test]
+fn test_no_files_without_a_sandbox() {
+    let mut cpu = process(&(call(SYS_OPENAT, &["-100", "0x20000", "0"], "s0") + "ebreak"));
+    cpu.bus.write_bytes(DATA, b"/etc/passwd\0").unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -EACCES);
+}
+
+// ── The console ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_stdout_and_stderr_are_captured_in_order() {
+    let source = [
+        call(SYS_WRITE, &["1", "0x20000", "4"], "s0"),
+        call(SYS_WRITE, &["2", "0x20004", "4"], "s1"),
+        call(SYS_WRITEV, &["1", "0x20100", "2"], "s2"),
+        call(SYS_WRITE, &["0", "0x20000", "4"], "s3"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+    cpu.bus.write_bytes(DATA, b"out err ab\ncd\n").unwrap();
+    // Two iovecs: "ab\n" and "cd\n".
+    for (i, word) in [DATA + 8, 3, DATA + 11, 3].iter().enumerate() {
+        cpu.bus
+            .write_bytes(DATA + 0x100 + 4 * i as u32, &word.to_le_bytes())
+            .unwrap();
+    }
+
+    let output = cpu.capture_output();
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(output.text(), "out err ab\ncd\n");
+    assert_eq!(cpu.reg(Reg::S2), 6);
+    assert_eq!(result(&cpu, Reg::S3), -EBADF, "stdin isn't writable");
+}
+
+#[test]
+fn test_stdin_reads_from_what_was_set() {
+    let source = [
+        call(SYS_READ, &["0", "0x21000", "3"], "s0"),
+        call(SYS_READ, &["0", "0x21000", "3"], "s1"),
+        call(SYS_READ, &["0", "0x21000", "3"], "s2"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+    cpu.syscalls_mut()
+        .unwrap()
+        .set_stdin(Box::new(Cursor::new(b"abcd".to_vec())));
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!([Reg::S0, Reg::S1, Reg::S2].map(|r| cpu.reg(r)), [3, 1, 0]);
+    assert_eq!(cpu.bus.read(BUF, MemSize::Byte), Some(b'd' as u32));
+}
+
+#[test]
+fn test_reads_and_writes_outside_ram_fault() {
+    let source = [
+        call(SYS_WRITE, &["1", "0x7ffffff0", "4"], "s0"),
+        call(SYS_READ, &["0", "0x7ffffff0", "4"], "s1"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -EFAULT);
+    assert_eq!(result(&cpu, Reg::S1), -EFAULT);
+}
+
+// ── Time ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_clock_gettime_reads_guest_time() {
+    let source = [
+        call(SYS_CLOCK_GETTIME64, &["1", "0x21000"], "s0"),
+        call(SYS_CLOCK_GETTIME, &["0", "0x21010"], "s1"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+    cpu.set_clock(
+        Clock::new(TimeMode::Virtual)
+            .frequency(1_000)
+            .epoch(Duration::from_secs(1_700_000_000)),
+    );
+    cpu.bus.skip(2_500);
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!([cpu.reg(Reg::S0), cpu.reg(Reg::S1)], [0, 0]);
+    assert_eq!(cpu.bus.read(BUF, MemSize::Word), Some(2));
+    assert_eq!(cpu.bus.read(BUF + 4, MemSize::Word), Some(0));
+    let nanos = cpu.bus.read(BUF + 8, MemSize::Word).unwrap();
+    assert!(
+        (500_000_000..510_000_000).contains(&nanos),
+        "{} ns past the second",
+        nanos
+    );
+    assert_eq!(
+        cpu.bus.read(BUF + 0x10, MemSize::Word),
+        Some(1_700_000_002),
+        "32-bit timespec"
+    );
+}
+
+#[test]
+fn test_clock_getres_is_a_tick() {
+    let source = [
+        call(SYS_CLOCK_GETRES_TIME64, &["1", "0x21000"], "s0"),
+        call(SYS_CLOCK_GETRES, &["0", "0"], "s1"),
+        call(SYS_CLOCK_GETRES, &["99", "0x21000"], "s2"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!([cpu.reg(Reg::S0), cpu.reg(Reg::S1)], [0, 0]);
+    assert_eq!(cpu.bus.read(BUF, MemSize::Word), Some(0));
+    assert_eq!(cpu.bus.read(BUF + 8, MemSize::Word), Some(100), "10 MHz");
+    assert_eq!(result(&cpu, Reg::S2), -EINVAL);
+}
+
+#[test]
+fn test_gettimeofday_writes_microseconds() {
+    let mut cpu = process(&(call(SYS_GETTIMEOFDAY, &["0x21000", "0"], "s0") + "ebreak"));
+    cpu.set_clock(Clock::new(TimeMode::Virtual).epoch(Duration::from_millis(1_500)));
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 0);
+    assert_eq!(cpu.bus.read(BUF, MemSize::Word), Some(1));
+    assert_eq!(cpu.bus.read(BUF + 4, MemSize::Word), Some(500_000));
+}
+
+#[test]
+fn test_nanosleep_lets_guest_time_pass() {
+    let source = [
+        call(SYS_NANOSLEEP, &["0x20000", "0"], "s0"),
+        call(SYS_CLOCK_NANOSLEEP, &["1", "0", "0x20008", "0"], "s1"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+    // 2 s, then 1 ns: a whole tick.
+    for (i, word) in [2u32, 0, 0, 1].into_iter().enumerate() {
+        cpu.bus
+            .write(DATA + 4 * i as u32, MemSize::Word, word)
+            .unwrap();
+    }
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!([cpu.reg(Reg::S0), cpu.reg(Reg::S1)], [0, 0]);
+    let time = cpu.bus.time();
+    assert!(
+        (20_000_001..20_000_100).contains(&time),
+        "{} ticks at 10 MHz",
+        time
+    );
+}
+
+#[test]
+fn test_sleeping_until_a_time_already_past_returns_at_once() {
+    let abstime = TIMER_ABSTIME.to_string();
+    let source = call(SYS_CLOCK_NANOSLEEP, &["1", &abstime, "0x20000", "0"], "s0");
+    let mut cpu = process(&(source + "ebreak"));
+    cpu.bus.skip(20_000_000);
+    cpu.bus.write(DATA, MemSize::Word, 1).unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 0);
+    assert!(cpu.bus.time() < 20_000_100);
+}
+
+#[test]
+fn test_sleep_rejects_a_bad_timespec() {
+    let source = [
+        call(SYS_NANOSLEEP, &["0x20000", "0"], "s0"),
+        call(SYS_CLOCK_NANOSLEEP, &["42", "0", "0x20008", "0"], "s1"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+    cpu.bus
+        .write(DATA + 4, MemSize::Word, 1_000_000_000)
+        .unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -EINVAL);
+    assert_eq!(result(&cpu, Reg::S1), -EINVAL);
+}
+
+// ── Threads ───────────────────────────────────────────────────────────────────
+
+/// What pthread_create passes to `clone`.
+const THREAD_FLAGS: u64 = CLONE_VM
+    | CLONE_FS
+    | CLONE_FILES
+    | CLONE_SIGHAND
+    | CLONE_THREAD
+    | CLONE_SYSVSEM
+    | CLONE_SETTLS
+    | CLONE_PARENT_SETTID
+    | CLONE_CHILD_CLEARTID;
+
+/// Wait on the futex at `addr` until it reads 0, as `pthread_join` waits
+/// on a thread's TID.
+fn join(addr: u32) -> String {
+    format!(
+        "join_{addr}: li t1, {addr}\nlw t0, 0(t1)\nbeq t0, zero, joined_{addr}\n{}j join_{addr}\njoined_{addr}:\n",
+        call(SYS_FUTEX_TIME64, &["t1", "0", "t0", "0"], "t2"),
+    )
+}
+
+#[test]
+fn test_clone_starts_a_thread_on_its_own_stack() {
+    let flags = THREAD_FLAGS.to_string();
+    let source = [
+        call(
+            SYS_CLONE,
+            &[&flags, "0x30000", "0x21000", "0x1234", "0x21000"],
+            "s0",
+        ),
+        "bne s0, zero, parent\n".to_string(),
+        "li t0, 0x21010\nsw tp, 0(t0)\nsw sp, 4(t0)\nsw a0, 8(t0)\n".to_string(),
+        call(SYS_GETTID, &[], "s1"),
+        "sw s1, 12(t0)\nli t1, 0x21000\nlw t1, 0(t1)\nsw t1, 16(t0)\n".to_string(),
+        call(SYS_EXIT, &["7"], "a0"),
+        "parent:\n".to_string(),
+        join(0x21000),
+        call(SYS_GETTID, &[], "s1"),
+        "ebreak".to_string(),
+    ]
+    .concat();
+    let mut cpu = process(&source);
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S0), 2, "the parent gets the new TID");
+    assert_eq!(cpu.reg(Reg::S1), 1);
+    assert_eq!(cpu.bus.read(BUF, MemSize::Word), Some(0), "cleared on exit");
+    // tp, sp, a0, its TID, and the TID as CLONE_PARENT_SETTID left it.
+    let child =
+        [0x10, 0x14, 0x18, 0x1c, 0x20].map(|offset| cpu.bus.read(BUF + offset, MemSize::Word));
+    assert_eq!(child.map(Option::unwrap), [0x1234, 0x30000, 0, 2, 2]);
+
+    let syscalls = cpu.syscalls().unwrap();
+    assert_eq!(syscalls.threads().collect::<Vec<_>>(), [1]);
+    assert_eq!(syscalls.current_thread(), 1);
+}
+
+#[test]
+fn test_clone_only_makes_threads() {
+    let flags = (CLONE_VM | CLONE_SIGHAND).to_string();
+    let source = call(SYS_CLONE, &[&flags, "0", "0", "0", "0"], "s0");
+    let mut cpu = process(&(source + "ebreak"));
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -ENOSYS);
+    assert_eq!(cpu.syscalls().unwrap().threads().count(), 1);
+}
+
+#[test]
+fn test_thread_ids() {
+    let source = [
+        call(SYS_GETPID, &[], "s0"),
+        call(SYS_GETTID, &[], "s1"),
+        call(SYS_SET_TID_ADDRESS, &["0x21000"], "s2"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!([Reg::S0, Reg::S1, Reg::S2].map(|r| cpu.reg(r)), [1, 1, 1]);
+}
+
+#[test]
+fn test_threads_take_turns() {
+    // The parent spins on a flag only the child sets, so it only gets
+    // anywhere if the child gets a turn without being waited for.
+    let flags = (THREAD_FLAGS & !CLONE_SETTLS).to_string();
+    let source = [
+        call(SYS_CLONE, &[&flags, "0", "0x21000", "0", "0x21000"], "s0"),
+        "li t1, 0x21008\nbne s0, zero, spin\n".to_string(),
+        "li t0, 1\nsw t0, 0(t1)\n".to_string(),
+        call(SYS_EXIT, &["0"], "a0"),
+        "spin: lw t0, 0(t1)\nbeq t0, zero, spin\n".to_string(),
+        join(0x21000),
+        "ebreak".to_string(),
+    ]
+    .concat();
+    let mut cpu = process(&source);
+    let syscalls = cpu.syscalls_mut().unwrap();
+    assert_eq!(syscalls.quantum(), 10_000);
+    syscalls.set_quantum(3);
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.bus.read(BUF + 8, MemSize::Word), Some(1));
+}
+
+#[test]
+fn test_exit_ends_the_run_only_with_the_last_thread() {
+    let flags = THREAD_FLAGS.to_string();
+    let source = [
+        call(SYS_CLONE, &[&flags, "0", "0x21000", "0", "0x21000"], "s0"),
+        "bne s0, zero, parent\n".to_string(),
+        call(SYS_EXIT, &["5"], "a0"),
+        "parent:\n".to_string(),
+        join(0x21000),
+        call(SYS_EXIT, &["6"], "a0"),
+    ]
+    .concat();
+    assert_eq!(process(&source).run(), ExitReason::Exited(6));
+
+    let source = [
+        call(SYS_CLONE, &[&flags, "0", "0x21000", "0", "0x21000"], "s0"),
+        "bne s0, zero, parent\n".to_string(),
+        "child: j child\n".to_string(),
+        "parent:\n".to_string(),
+        call(SYS_EXIT_GROUP, &["9"], "a0"),
+    ]
+    .concat();
+    assert_eq!(process(&source).run(), ExitReason::Exited(9));
+}
+
+#[test]
+fn test_futex_wakes_waiters_oldest_first() {
+    // Two children wait on the word at BUF, recording the order they wake
+    // in at BUF + 8, counted at BUF + 4.
+    let flags = (THREAD_FLAGS & !(CLONE_SETTLS | CLONE_PARENT_SETTID)).to_string();
+    let child = |tid: &str| {
+        [
+            call(SYS_FUTEX_TIME64, &["0x21000", "0", "0", "0"], "s1"),
+            format!(
+                "li t1, 0x21004\nlw t0, 0(t1)\nslli t2, t0, 2\nadd t2, t2, t1\nli t3, {tid}\nsw t3, 4(t2)\naddi t0, t0, 1\nsw t0, 0(t1)\n"
+            ),
+            call(SYS_EXIT, &["0"], "a0"),
+        ]
+        .concat()
+    };
+    let source = [
+        call(SYS_CLONE, &[&flags, "0", "0", "0", "0"], "s0"),
+        "beq s0, zero, first\n".to_string(),
+        call(SYS_CLONE, &[&flags, "0", "0", "0", "0"], "s0"),
+        "beq s0, zero, second\n".to_string(),
+        // Both are waiting once they've each had a turn.
+        call(SYS_SCHED_YIELD, &[], "s2"),
+        call(SYS_FUTEX_TIME64, &["0x21000", "1", "1", "0"], "s3"),
+        call(SYS_SCHED_YIELD, &[], "s2"),
+        call(SYS_FUTEX_TIME64, &["0x21000", "129", "10", "0"], "s4"),
+        call(SYS_SCHED_YIELD, &[], "s2"),
+        call(SYS_FUTEX_TIME64, &["0x21000", "1", "10", "0"], "s5"),
+        "ebreak\n".to_string(),
+        "first:\n".to_string(),
+        child("2"),
+        "second:\n".to_string(),
+        child("3"),
+    ]
+    .concat();
+    let mut cpu = process(&source);
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!([Reg::S3, Reg::S4, Reg::S5].map(|r| cpu.reg(r)), [1, 1, 0]);
+    assert_eq!(cpu.bus.read(BUF + 4, MemSize::Word), Some(2));
+    assert_eq!(cpu.bus.read(BUF + 8, MemSize::Word), Some(2));
+    assert_eq!(cpu.bus.read(BUF + 12, MemSize::Word), Some(3));
+}
+
+#[test]
+fn test_futex_requeue_moves_waiters() {
+    let flags = (THREAD_FLAGS & !(CLONE_SETTLS | CLONE_PARENT_SETTID)).to_string();
+    let requeue = FUTEX_CMP_REQUEUE.to_string();
+    let source = [
+        call(SYS_CLONE, &[&flags, "0", "0", "0", "0"], "s0"),
+        "beq s0, zero, child\n".to_string(),
+        call(SYS_CLONE, &[&flags, "0", "0", "0", "0"], "s0"),
+        "beq s0, zero, child\n".to_string(),
+        call(SYS_SCHED_YIELD, &[], "s2"),
+        call(
+            SYS_FUTEX_TIME64,
+            &["0x21000", &requeue, "0", "5", "0x21008", "1"],
+            "s3",
+        ),
+        call(
+            SYS_FUTEX_TIME64,
+            &["0x21000", &requeue, "0", "5", "0x21008", "0"],
+            "s4",
+        ),
+        call(SYS_FUTEX_TIME64, &["0x21000", "1", "10", "0"], "s5"),
+        call(SYS_FUTEX_TIME64, &["0x21008", "1", "10", "0"], "s6"),
+        "ebreak\n".to_string(),
+        "child:\n".to_string(),
+        call(SYS_FUTEX_TIME64, &["0x21000", "0", "0", "0"], "s1"),
+        call(SYS_EXIT, &["0"], "a0"),
+    ]
+    .concat();
+    let mut cpu = process(&source);
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S3), -EAGAIN, "the word isn't 1");
+    assert_eq!(
+        [Reg::S4, Reg::S5, Reg::S6].map(|r| cpu.reg(r)),
+        [2, 0, 2],
+        "both moved"
+    );
+}
+
+#[test]
+fn test_futex_wait_checks_the_word_and_can_time_out() {
+    let source = [
+        call(SYS_FUTEX_TIME64, &["0x21000", "0", "1", "0"], "s0"),
+        call(SYS_FUTEX_TIME64, &["0x21002", "0", "0", "0"], "s1"),
+        call(SYS_FUTEX_TIME64, &["0x21000", "0", "0", "0x20000"], "s2"),
+    ]
+    .concat();
+    let mut cpu = process(&(source + "ebreak"));
+    // A relative timeout of 1.5 s.
+    cpu.bus.write(DATA, MemSize::Word, 1).unwrap();
+    cpu.bus.write(DATA + 8, MemSize::Word, 500_000_000).unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(result(&cpu, Reg::S0), -EAGAIN);
+    assert_eq!(result(&cpu, Reg::S1), -EINVAL, "misaligned");
+    assert_eq!(result(&cpu, Reg::S2), -ETIMEDOUT);
+    let time = cpu.bus.time();
+    assert!((15_000_000..15_000_100).contains(&time), "{} ticks", time);
+}
+
+#[test]
+fn test_a_sleeping_thread_lets_the_others_run() {
+    // The child counts to 100 while the parent sleeps for a second.
+    let flags = THREAD_FLAGS.to_string();
+    let source = [
+        call(SYS_CLONE, &[&flags, "0", "0x21000", "0", "0x21000"], "s0"),
+        "beq s0, zero, child\n".to_string(),
+        call(SYS_NANOSLEEP, &["0x20000", "0"], "s1"),
+        "li t1, 0x21008\nlw s2, 0(t1)\nebreak\n".to_string(),
+        "child: li t1, 0x21008\nli t2, 100\n".to_string(),
+        "count: lw t0, 0(t1)\naddi t0, t0, 1\nsw t0, 0(t1)\nbne t0, t2, count\n".to_string(),
+        call(SYS_EXIT, &["0"], "a0"),
+    ]
+    .concat();
+    let mut cpu = process(&source);
+    cpu.bus.write(DATA, MemSize::Word, 1).unwrap();
+
+    stops_at_ebreak(&mut cpu);
+    assert_eq!(cpu.reg(Reg::S2), 100);
+    assert!(cpu.bus.time() >= 10_000_000);
+}
+
+#[test]
+fn test_threads_all_waiting_for_good_is_idle() {
+    let source = call(SYS_FUTEX_TIME64, &["0x21000", "0", "0", "0"], "s0");
+    let mut cpu = process(&(source + "ebreak"));
+
+    assert_eq!(cpu.run(), ExitReason::Idle);
+    assert_eq!(cpu.run(), ExitReason::Idle, "still");
+}
@@ -0,0 +1,215 @@
+mod common;
+
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::mmu::{PTE_R, PTE_V, PTE_W, PTE_X};
+use riscv_emulator_rust::tlb::{TlbConfig, TlbStats};
+use riscv_emulator_rust::{Engine, MemSize, RiscvCpu};
+
+use common::image_at;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const ROOT: u32 = 0x1000;
+const LEAF_TABLE: u32 = 0x2000;
+const CODE: u32 = 0x3000;
+const DATA: u32 = 0x5000;
+
+const CODE_VA: u32 = 0x4000_0000;
+
+fn pte(pa: u32, flags: u32) -> u32 {
+    ((pa >> 12) << 10) | flags
+}
+
+fn map(cpu: &mut RiscvCpu, va: u32, pa: u32, flags: u32) {
+    let root_entry = ROOT + (va >> 22) * 4;
+    cpu.bus
+        .write(root_entry, MemSize::Word, pte(LEAF_TABLE, PTE_V))
+        .unwrap();
+
+    let leaf_entry = LEAF_TABLE + ((va >> 12) & 0x3FF) * 4;
+    cpu.bus
+        .write(leaf_entry, MemSize::Word, pte(pa, flags | PTE_V))
+        .unwrap();
+}
+
+/// An S-mode CPU with Sv32 on in address space `asid`, `source` mapped at
+/// `CODE_VA` and a data page after it.
+fn paged_cpu(source: &str, tlb: TlbConfig, asid: u32, engine: Engine) -> RiscvCpu {
+    let bytes = image_at(CODE_VA, source);
+    let mut cpu = RiscvCpu::builder()
+        .ram_size(0x10000)
+        .image(CODE, bytes)
+        .engine(engine)
+        .tlb(tlb)
+        .build()
+        .unwrap();
+
+    map(&mut cpu, CODE_VA, CODE, PTE_R | PTE_X);
+    map(&mut cpu, CODE_VA + 0x1000, DATA, PTE_R | PTE_W);
+    cpu.csrs
+        .write(csr::SATP, csr::SATP_SV32 | asid << 22 | (ROOT >> 12));
+    cpu.set_privilege(Privilege::Supervisor);
+    cpu.pc = CODE_VA;
+
+    cpu
+}
+
+fn config(entries: u32, ways: u32) -> TlbConfig {
+    TlbConfig::new(entries, ways).unwrap()
+}
+
+fn stats(cpu: &RiscvCpu) -> TlbStats {
+    cpu.tlb().unwrap().stats()
+}
+
+// ── Lookups ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_config_must_be_powers_of_two() {
+    assert!(TlbConfig::new(32, 32).is_ok());
+
+    let error = TlbConfig::new(24, 4).unwrap_err();
+    assert!(error.contains("entries 24"), "{}", error);
+    assert!(TlbConfig::new(16, 3).is_err());
+    assert!(TlbConfig::new(4, 8).is_err());
+}
+
+#[test]
+fn test_each_page_misses_once() {
+    let source = "
+        lui  t0, 0x40001
+        lw   a0, 0(t0)
+        lw   a1, 4(t0)
+        sw   a0, 8(t0)
+        ebreak
+    ";
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = paged_cpu(source, config(16, 4), 0, engine);
+        cpu.run();
+
+        let stats = stats(&cpu);
+        assert_eq!((stats.hits, stats.misses), (5, 2), "{:?}", engine);
+        assert_eq!(stats.lookups(), 7, "{:?}", engine);
+        assert_eq!(cpu.tlb().unwrap().occupied(), 2, "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_bare_addressing_skips_the_tlb() {
+    let mut cpu = paged_cpu("addi a0, zero, 1", config(16, 4), 0, Engine::Interpreter);
+    cpu.csrs.write(csr::SATP, 0);
+    cpu.set_privilege(Privilege::Machine);
+    cpu.pc = CODE;
+
+    cpu.step().unwrap();
+
+    assert_eq!(stats(&cpu).lookups(), 0);
+}
+
+#[test]
+fn test_conflicting_pages_evict_each_other() {
+    // Code and data share the one entry of a single-entry TLB.
+    let source = "
+        lui  t0, 0x40001
+        lw   a0, 0(t0)
+        lw   a0, 0(t0)
+        ebreak
+    ";
+    let mut cpu = paged_cpu(source, config(1, 1), 0, Engine::Interpreter);
+
+    cpu.run();
+
+    assert_eq!((stats(&cpu).hits, stats(&cpu).misses), (0, 5));
+}
+
+// ── SFENCE.VMA ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_fence_drops_every_entry() {
+    let source = "
+        lui  t0, 0x40001
+        lw   a0, 0(t0)
+        sfence.vma zero, zero
+        lw   a0, 0(t0)
+        ebreak
+    ";
+    let mut cpu = paged_cpu(source, config(16, 4), 0, Engine::Interpreter);
+
+    cpu.run();
+
+    let stats = stats(&cpu);
+    assert_eq!((stats.fences, stats.invalidated), (1, 2));
+    assert_eq!((stats.hits, stats.misses), (2, 4));
+}
+
+#[test]
+fn test_fence_by_address_drops_that_page() {
+    let source = "
+        lui  t0, 0x40001
+        lw   a0, 0(t0)
+        sfence.vma t0, zero
+        ebreak
+    ";
+    let mut cpu = paged_cpu(source, config(16, 4), 0, Engine::Interpreter);
+
+    cpu.run();
+
+    assert_eq!(stats(&cpu).invalidated, 1);
+    assert_eq!(cpu.tlb().unwrap().occupied(), 1);
+}
+
+#[test]
+fn test_fence_by_asid_spares_other_address_spaces() {
+    let source = "
+        lui  t0, 0x40001
+        lw   a0, 0(t0)
+        addi t1, zero, 6
+        sfence.vma zero, t1
+        addi t1, zero, 5
+        sfence.vma zero, t1
+        ebreak
+    ";
+    let mut cpu = paged_cpu(source, config(16, 4), 5, Engine::Interpreter);
+
+    cpu.run_steps(4);
+    assert_eq!(stats(&cpu).invalidated, 0);
+
+    cpu.run();
+    assert_eq!(stats(&cpu).invalidated, 2);
+    assert_eq!(stats(&cpu).fences, 2);
+}
+
+#[test]
+fn test_address_spaces_dont_share_entries() {
+    let mut cpu = paged_cpu(
+        "addi a0, zero, 1\naddi a0, zero, 2",
+        config(16, 4),
+        1,
+        Engine::Interpreter,
+    );
+    cpu.step().unwrap();
+
+    cpu.csrs
+        .write(csr::SATP, csr::SATP_SV32 | 2 << 22 | (ROOT >> 12));
+    cpu.step().unwrap();
+
+    assert_eq!(stats(&cpu).misses, 2);
+    cpu.tlb_mut().unwrap().reset_stats();
+    assert_eq!(stats(&cpu), TlbStats::default());
+    assert_eq!(cpu.tlb().unwrap().occupied(), 2);
+}
+
+// ── Machines ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_each_hart_gets_an_empty_tlb() {
+    let mut cpu = paged_cpu("addi a0, zero, 1", config(16, 4), 0, Engine::Interpreter);
+    cpu.step().unwrap();
+
+    let machine = Machine::new(cpu, 2);
+
+    assert_eq!(machine.hart(1).tlb().unwrap().config(), config(16, 4));
+    assert_eq!(machine.hart(1).tlb().unwrap().occupied(), 0);
+    assert_eq!(machine.hart(0).tlb().unwrap().occupied(), 1);
+}
@@ -0,0 +1,84 @@
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::encode;
+use riscv_emulator_rust::torture::{
+    DEFAULT_LENGTH, DEFAULT_STEP_LIMIT, SCRATCH, TortureOutcome, generate, run, run_many,
+};
+
+// ── Generator ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_generation_is_deterministic() {
+    assert_eq!(generate(3, 100), generate(3, 100));
+    assert_ne!(generate(3, 100).image, generate(4, 100).image);
+}
+
+#[test]
+fn test_programs_are_valid_rv32i() {
+    let test = generate(11, 500);
+    assert_eq!(test.signature.len(), 30);
+    assert_eq!(test.signature[1], SCRATCH);
+    for word in test.image.chunks_exact(4) {
+        let word = u32::from_le_bytes(word.try_into().unwrap());
+        assert!(decode(word).is_ok(), "{:#010x}", word);
+    }
+}
+
+#[test]
+fn test_signature_dump() {
+    let test = generate(5, 10);
+    let dump = test.signature_dump();
+    assert_eq!(dump.lines().count(), 30);
+    assert_eq!(dump.lines().nth(1), Some("00031000"));
+}
+
+// ── Runner ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_generated_programs_pass() {
+    for (seed, outcome) in run_many(0..100, DEFAULT_LENGTH, DEFAULT_STEP_LIMIT) {
+        assert_eq!(outcome, TortureOutcome::Pass, "seed {}", seed);
+    }
+}
+
+#[test]
+fn test_long_programs_pass() {
+    assert!(run(&generate(42, 5_000), DEFAULT_STEP_LIMIT).passed());
+}
+
+#[test]
+fn test_reports_a_wrong_signature() {
+    let mut test = generate(7, 100);
+    let expected = test.signature[4];
+    test.signature[4] ^= 1;
+
+    assert_eq!(
+        run(&test, DEFAULT_STEP_LIMIT),
+        TortureOutcome::Fail(vec![(5, expected ^ 1, expected)])
+    );
+}
+
+#[test]
+fn test_catches_a_miscomputed_register() {
+    // Overwrite the last random instruction with one that leaves t0
+    // holding something the model didn't expect.
+    let mut test = generate(9, 100);
+    let store = encode::sw(1, 31, 0).to_le_bytes();
+    let first_store = 4 * test.image.chunks_exact(4).position(|w| w == store).unwrap();
+    // Before the stores is the LUI of the signature address.
+    let last = first_store - 8;
+    test.image[last..last + 4].copy_from_slice(&encode::addi(5, 5, 1).to_le_bytes());
+
+    match run(&test, DEFAULT_STEP_LIMIT) {
+        TortureOutcome::Fail(registers) => assert!(registers.iter().any(|r| r.0 == 5)),
+        other => panic!("expected a failure, got {}", other),
+    }
+}
+
+#[test]
+fn test_outcomes_display() {
+    assert_eq!(
+        TortureOutcome::Fail(vec![(5, 1, 2)]).to_string(),
+        "FAIL x5=0x2 (expected 0x1)"
+    );
+    assert_eq!(TortureOutcome::Timeout.to_string(), "TIMEOUT");
+}
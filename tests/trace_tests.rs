@@ -0,0 +1,256 @@
+mod common;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use riscv_emulator_rust::decode::Instruction;
+use riscv_emulator_rust::trace::{JsonTracer, PrintTracer, Tracer};
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::{MemSize, RiscvCpu, csr};
+
+use common::{SharedBuffer, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// Keeps a log of events as strings the tests can compare against.
+#[derive(Clone, Default)]
+struct Recorder(Rc<RefCell<Vec<String>>>);
+
+impl Tracer for Recorder {
+    fn instruction(&mut self, pc: u32, _raw: u32, instruction: &Instruction) {
+        self.0
+            .borrow_mut()
+            .push(format!("{:#x} {}", pc, instruction));
+    }
+
+    fn unknown_opcode(&mut self, pc: u32, raw: u32) {
+        self.0
+            .borrow_mut()
+            .push(format!("{:#x} unknown {:#x}", pc, raw));
+    }
+
+    fn exception(&mut self, pc: u32, exception: &Exception) {
+        self.0.borrow_mut().push(format!("{:#x} {}", pc, exception));
+    }
+
+    fn interrupt(&mut self, pc: u32, interrupt: Interrupt) {
+        self.0
+            .borrow_mut()
+            .push(format!("{:#x} {:?}", pc, interrupt));
+    }
+}
+
+/// Logs register writes and memory accesses, and which instruction they
+/// were part of.
+#[derive(Clone, Default)]
+struct DataRecorder(Rc<RefCell<Vec<String>>>);
+
+impl Tracer for DataRecorder {
+    fn instruction(&mut self, pc: u32, _raw: u32, _instruction: &Instruction) {
+        self.0.borrow_mut().push(format!("retired {:#x}", pc));
+    }
+
+    fn exception(&mut self, pc: u32, _exception: &Exception) {
+        self.0.borrow_mut().push(format!("trapped {:#x}", pc));
+    }
+
+    fn register_write(&mut self, reg: u8, value: u64) {
+        self.0.borrow_mut().push(format!("x{} = {:#x}", reg, value));
+    }
+
+    fn memory(&mut self, addr: u64, size: MemSize, value: u32, store: bool) {
+        let op = if store { "store" } else { "load" };
+        self.0.borrow_mut().push(format!(
+            "{} {} {:#x} @ {:#x}",
+            op,
+            size.bytes(),
+            value,
+            addr
+        ));
+    }
+}
+
+fn cpu_with(source: &str, tracer: impl Tracer + 'static) -> RiscvCpu {
+    program(source).tracer(tracer).build().unwrap()
+}
+
+// ── Events ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_traces_each_instruction_and_the_final_exception() {
+    let recorder = Recorder::default();
+    let mut cpu = cpu_with(
+        "
+        addi x1, x0, 8
+        srli x2, x1, 2
+        ebreak
+        ",
+        recorder.clone(),
+    );
+
+    cpu.run();
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "0x0 addi x1, x0, 8",
+            "0x4 srli x2, x1, 2",
+            "0x8 EBREAK at 0x8"
+        ]
+    );
+}
+
+#[test]
+fn test_traces_unknown_opcodes() {
+    let recorder = Recorder::default();
+    // opcode 0x07 is LOAD-FP, which isn't implemented
+    let mut cpu = cpu_with(".word 0x00002077", recorder.clone());
+
+    cpu.step().unwrap();
+
+    assert_eq!(*recorder.0.borrow(), vec!["0x0 unknown 0x2077"]);
+}
+
+#[test]
+fn test_traces_interrupts() {
+    let recorder = Recorder::default();
+    let mut cpu = cpu_with("addi x0, x0, 0", recorder.clone());
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE);
+    cpu.csrs.write(csr::MIE, csr::MIP_MTIP);
+    cpu.raise_interrupt(Interrupt::MachineTimer);
+
+    cpu.step().unwrap();
+
+    assert_eq!(*recorder.0.borrow(), vec!["0x0 MachineTimer"]);
+}
+
+#[test]
+fn test_clear_tracer() {
+    let recorder = Recorder::default();
+    let mut cpu = cpu_with("addi x1, x0, 1\naddi x1, x1, 1", recorder.clone());
+
+    cpu.step().unwrap();
+    cpu.clear_tracer();
+    cpu.step().unwrap();
+
+    assert_eq!(recorder.0.borrow().len(), 1);
+}
+
+#[test]
+fn test_traces_register_writes_and_memory() {
+    let recorder = DataRecorder::default();
+    let mut cpu = cpu_with(
+        "
+        addi x1, x0, 0x100
+        addi x2, x0, -1
+        sh   x2, 2(x1)
+        lbu  x3, 3(x1)
+        addi x0, x3, 1
+        lw   x4, 0(x0)
+        ",
+        recorder.clone(),
+    );
+    cpu.add_watchpoint(0x1000, 4, riscv_emulator_rust::debug::WatchKind::Write);
+
+    for _ in 0..6 {
+        cpu.step().unwrap();
+    }
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec![
+            "x1 = 0x100",
+            "retired 0x0",
+            "x2 = 0xffffffff",
+            "retired 0x4",
+            "store 2 0xffffffff @ 0x102",
+            "retired 0x8",
+            "load 1 0xff @ 0x103",
+            "x3 = 0xff",
+            "retired 0xc",
+            "retired 0x10",
+            "load 4 0x10000093 @ 0x0",
+            "x4 = 0x10000093",
+            "retired 0x14",
+        ]
+    );
+}
+
+#[test]
+fn test_faulting_accesses_are_not_traced() {
+    let recorder = DataRecorder::default();
+    let mut cpu = cpu_with("lui x1, 0xfff00\nlw x2, 0(x1)", recorder.clone());
+
+    cpu.run();
+
+    assert_eq!(
+        *recorder.0.borrow(),
+        vec!["x1 = 0xfff00000", "retired 0x0", "trapped 0x4"]
+    );
+}
+
+// ── PrintTracer ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_print_tracer_format() {
+    let out = SharedBuffer::default();
+    let mut cpu = cpu_with(
+        "addi x1, x0, -1\nebreak",
+        PrintTracer::with_output(Box::new(out.clone())),
+    );
+
+    cpu.run();
+
+    assert_eq!(
+        out.contents(),
+        "0x00000000: fff00093  addi x1, x0, -1\n\
+         0x00000004: exception: EBREAK at 0x4\n"
+    );
+}
+
+// ── JsonTracer ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_json_tracer_format() {
+    let out = SharedBuffer::default();
+    let mut cpu = cpu_with(
+        "
+        addi x1, x0, 5
+        add  x2, x1, x1
+        sw   x2, 0x100(x0)
+        ebreak
+        ",
+        JsonTracer::with_output(Box::new(out.clone())),
+    );
+
+    cpu.run();
+
+    let text = out.contents();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            r#"{"pc":0,"insn":5243027,"mnemonic":"addi","disasm":"addi x1, x0, 5","rd":1,"value":5,"mem":[]}"#,
+            r#"{"pc":4,"insn":1081651,"mnemonic":"add","disasm":"add x2, x1, x1","rd":2,"value":10,"mem":[]}"#,
+            r#"{"pc":8,"insn":270540835,"mnemonic":"sw","disasm":"sw x2, 256(x0)","rd":null,"value":null,"mem":[{"addr":256,"size":4,"value":10,"store":true}]}"#,
+            r#"{"pc":12,"trap":"exception","cause":3,"tval":12,"message":"EBREAK at 0xc"}"#,
+        ]
+    );
+}
+
+#[test]
+fn test_json_tracer_traces_interrupts() {
+    let out = SharedBuffer::default();
+    let mut cpu = cpu_with(
+        "addi x0, x0, 0",
+        JsonTracer::with_output(Box::new(out.clone())),
+    );
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE);
+    cpu.csrs.write(csr::MIE, csr::MIP_MTIP);
+    cpu.raise_interrupt(Interrupt::MachineTimer);
+
+    cpu.step().unwrap();
+
+    let text = out.contents();
+    assert_eq!(text, "{\"pc\":0,\"trap\":\"interrupt\",\"cause\":7}\n");
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use riscv_emulator_rust::csr::{self, Privilege};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu};
+
+use common::program;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const MTVEC: u32 = 0x400;
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    let mut cpu = program(source)
+        .engine(engine)
+        .guest_traps(true)
+        .build()
+        .unwrap();
+    cpu.csrs.write(csr::MTVEC, MTVEC);
+    cpu
+}
+
+/// An mcontrol `tdata1` on RV32.
+fn mcontrol(fields: u32) -> u32 {
+    2 << 28 | fields
+}
+
+/// Point trigger `n` at `addr` with `fields`.
+fn set_trigger(cpu: &mut RiscvCpu, n: u32, fields: u32, addr: u32) {
+    cpu.csrs.write(csr::TSELECT, n);
+    cpu.csrs.write(csr::TDATA1, mcontrol(fields));
+    cpu.csrs.write(csr::TDATA2, addr);
+}
+
+// ── CSRs ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_trigger_csrs() {
+    let mut cpu = cpu_with("", Engine::Interpreter);
+    assert_eq!(cpu.csrs.read(csr::TINFO), 1 << 2 | 1 << 15);
+
+    for n in 0..csr::TRIGGERS as u32 {
+        cpu.csrs.write(csr::TSELECT, n);
+        assert_eq!(cpu.csrs.read(csr::TSELECT), n);
+    }
+    cpu.csrs.write(csr::TSELECT, csr::TRIGGERS as u32);
+    assert_eq!(
+        cpu.csrs.read(csr::TSELECT),
+        csr::TRIGGERS as u32 - 1,
+        "no such trigger"
+    );
+
+    let tdata1 = cpu.csrs.read(csr::TDATA1);
+    assert_eq!(tdata1 >> 28, 2, "mcontrol");
+    assert_eq!(tdata1 >> 21 & 0x3F, 31, "maskmax");
+
+    // action = 1, chain and match = 4 can't be had.
+    cpu.csrs
+        .write(csr::TDATA1, mcontrol(1 << 12 | 1 << 11 | 4 << 7 | 0x45));
+    assert_eq!(cpu.csrs.read(csr::TDATA1) & 0xFFFF, 0x45);
+
+    cpu.csrs.write(csr::TDATA1, 0);
+    assert_eq!(cpu.csrs.read(csr::TDATA1), 15 << 28, "disabled");
+
+    let cpu = RiscvCpu::builder().xlen::<Rv64>().build().unwrap();
+    assert_eq!(cpu.csrs.read(csr::TDATA1) >> 60, 2);
+}
+
+#[test]
+fn test_triggers_are_per_tselect() {
+    let mut cpu = cpu_with("", Engine::Interpreter);
+    set_trigger(&mut cpu, 0, csr::MCONTROL_LOAD, 0x100);
+    set_trigger(&mut cpu, 1, csr::MCONTROL_STORE, 0x200);
+
+    cpu.csrs.write(csr::TSELECT, 0);
+    assert_eq!(cpu.csrs.read(csr::TDATA2), 0x100);
+    assert_eq!(cpu.csrs.read(csr::TDATA1) & 0xF, csr::MCONTROL_LOAD);
+}
+
+// ── Execute triggers ──────────────────────────────────────────────────────────
+
+#[test]
+fn test_guest_sets_a_hardware_breakpoint() {
+    let source = "
+        addi  t0, zero, 0x10
+        csrrw zero, tdata2, t0
+        addi  t1, zero, 0x44
+        csrrs zero, tdata1, t1
+        addi  a0, zero, 1
+        ";
+
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    cpu.run_steps(5);
+
+    assert_eq!(cpu.pc, MTVEC);
+    assert_eq!(cpu.regs[10], 0, "stops before the instruction");
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 3);
+    assert_eq!(cpu.csrs.read(csr::MEPC), 0x10);
+    assert_eq!(cpu.csrs.read(csr::MTVAL), 0x10);
+    assert_ne!(cpu.csrs.read(csr::TDATA1) & csr::MCONTROL_HIT, 0);
+
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    cpu.set_guest_traps(false);
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(0x10))
+    );
+}
+
+#[test]
+fn test_triggers_only_fire_in_enabled_modes() {
+    let source = "addi a0, zero, 1";
+
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    set_trigger(&mut cpu, 0, csr::MCONTROL_EXECUTE | csr::MCONTROL_U, 0);
+    cpu.step().unwrap();
+    assert_eq!(cpu.regs[10], 1, "M-mode isn't enabled");
+
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    set_trigger(&mut cpu, 0, csr::MCONTROL_EXECUTE | csr::MCONTROL_U, 0);
+    cpu.set_privilege(Privilege::User);
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, MTVEC);
+    assert_eq!(cpu.privilege(), Privilege::Machine);
+}
+
+#[test]
+fn test_triggers_stop_blocks_midway() {
+    let engines = [
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+    let source = "
+        addi  a0, zero, 0
+loop:   addi  a0, a0, 1
+        addi  a1, a0, 0
+        jal   zero, loop
+        ";
+
+    for engine in engines {
+        let mut cpu = cpu_with(source, engine);
+        cpu.run_steps(300);
+        set_trigger(&mut cpu, 0, csr::MCONTROL_EXECUTE | csr::MCONTROL_M, 8);
+        cpu.set_guest_traps(false);
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(8)),
+            "{:?}",
+            engine
+        );
+        assert_eq!(cpu.regs[11], cpu.regs[10] - 1, "{:?}", engine);
+    }
+}
+
+// ── Load and store triggers ───────────────────────────────────────────────────
+
+#[test]
+fn test_watchpoint_triggers() {
+    let source = "
+        lw  a0, 0x204(zero)
+        sw  a0, 0x300(zero)
+        sw  a0, 0x204(zero)
+        ";
+
+    let mut cpu = cpu_with(source, Engine::Interpreter);
+    // NAPOT: 0x200..0x208.
+    set_trigger(
+        &mut cpu,
+        0,
+        csr::MCONTROL_STORE | csr::MCONTROL_M | 1 << 7,
+        0x203,
+    );
+    cpu.bus.write(0x204, MemSize::Word, 7).unwrap();
+
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.pc, MTVEC, "loads don't match a store trigger");
+    assert_eq!(cpu.csrs.read(csr::MEPC), 8);
+    assert_eq!(cpu.csrs.read(csr::MTVAL), 0x204);
+    assert_eq!(cpu.bus.read(0x300, MemSize::Word), Some(7));
+    assert_eq!(
+        cpu.bus.read(0x204, MemSize::Word),
+        Some(7),
+        "the store never happened"
+    );
+}
+
+#[test]
+fn test_range_matches() {
+    let cases = [(2, 0x300, true), (2, 0x301, false), (3, 0x300, false)];
+
+    for (match_kind, tdata2, fires) in cases {
+        let mut cpu = cpu_with("lw a0, 0x300(zero)", Engine::Interpreter);
+        set_trigger(
+            &mut cpu,
+            0,
+            csr::MCONTROL_LOAD | csr::MCONTROL_M | match_kind << 7,
+            tdata2,
+        );
+
+        cpu.step().unwrap();
+
+        assert_eq!(
+            cpu.pc == MTVEC,
+            fires,
+            "match {} against {:#x}",
+            match_kind,
+            tdata2
+        );
+    }
+}
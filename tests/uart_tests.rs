@@ -0,0 +1,170 @@
+mod common;
+
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::{Device, Uart16550, uart};
+use riscv_emulator_rust::encode::{addi, lbu, lui, sb};
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+use common::{SharedBuffer, le_bytes};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn load_program(cpu: &mut RiscvCpu, instructions: &[u32]) {
+    cpu.load_binary(0, &le_bytes(instructions)).unwrap();
+}
+
+// ── Register model ────────────────────────────────────────────────────────────
+
+#[test]
+fn test_thr_write_goes_to_output() {
+    let out = SharedBuffer::default();
+    let mut uart = Uart16550::with_output(Box::new(out.clone()));
+
+    for b in b"ok\n" {
+        uart.write(0, MemSize::Byte, *b as u32);
+    }
+
+    assert_eq!(out.contents(), "ok\n");
+}
+
+#[test]
+fn test_lsr_reports_transmitter_empty() {
+    let mut uart = Uart16550::with_output(Box::new(SharedBuffer::default()));
+
+    let lsr = uart.read(5, MemSize::Byte) as u8;
+
+    assert_ne!(lsr & uart::LSR_THRE, 0, "THR should always be ready");
+    assert_ne!(lsr & uart::LSR_TEMT, 0);
+    assert_eq!(lsr & uart::LSR_DR, 0, "no data received yet");
+}
+
+#[test]
+fn test_input_feeds_rbr() {
+    let mut uart = Uart16550::with_output(Box::new(SharedBuffer::default()));
+    let input = uart.input();
+
+    input.send(b'h').unwrap();
+    input.send(b'i').unwrap();
+
+    assert_ne!(uart.read(5, MemSize::Byte) as u8 & uart::LSR_DR, 0);
+    assert_eq!(uart.read(0, MemSize::Byte), b'h' as u32);
+    assert_eq!(uart.read(0, MemSize::Byte), b'i' as u32);
+    assert_eq!(
+        uart.read(5, MemSize::Byte) as u8 & uart::LSR_DR,
+        0,
+        "DR clears once the FIFO is drained"
+    );
+}
+
+#[test]
+fn test_dlab_exposes_divisor_latch() {
+    let out = SharedBuffer::default();
+    let mut uart = Uart16550::with_output(Box::new(out.clone()));
+
+    uart.write(3, MemSize::Byte, 0x80); // LCR.DLAB = 1
+    uart.write(0, MemSize::Byte, 0x03); // DLL
+    uart.write(1, MemSize::Byte, 0x00); // DLM
+    uart.write(3, MemSize::Byte, 0x03); // 8N1, DLAB = 0
+
+    assert_eq!(out.contents(), "", "divisor writes must not be transmitted");
+
+    uart.write(3, MemSize::Byte, 0x83);
+    assert_eq!(uart.read(0, MemSize::Byte), 0x03);
+}
+
+#[test]
+fn test_iir_reports_received_data() {
+    let mut uart = Uart16550::with_output(Box::new(SharedBuffer::default()));
+    uart.write(1, MemSize::Byte, 0x01); // IER: received data available
+
+    assert_eq!(uart.read(2, MemSize::Byte) & 0x0F, 0x01, "nothing pending");
+    assert!(!uart.interrupt_pending());
+
+    uart.input().send(b'x').unwrap();
+
+    assert_eq!(uart.read(2, MemSize::Byte) & 0x0F, 0x04);
+    assert!(uart.interrupt_pending());
+}
+
+#[test]
+fn test_enabled_interrupts_raise_meip() {
+    let mut uart = Uart16550::with_output(Box::new(SharedBuffer::default()));
+    uart.write(1, MemSize::Byte, 0x01); // IER: received data available
+    assert_eq!(uart.interrupts(0), 0);
+
+    uart.input().send(b'x').unwrap();
+    uart.tick(1);
+    assert_eq!(uart.interrupts(0), csr::MIP_MEIP);
+
+    uart.read(0, MemSize::Byte);
+    assert_eq!(uart.interrupts(0), 0, "drained");
+
+    let mut uart =
+        Uart16550::with_output(Box::new(SharedBuffer::default())).with_interrupt(1, csr::MIP_SEIP);
+    uart.write(1, MemSize::Byte, 0x02); // IER: transmitter empty
+    assert_eq!(uart.interrupts(0), 0);
+    assert_eq!(uart.interrupts(1), csr::MIP_SEIP);
+}
+
+#[test]
+fn test_scratch_register() {
+    let mut uart = Uart16550::with_output(Box::new(SharedBuffer::default()));
+    uart.write(7, MemSize::Byte, 0x5A);
+    assert_eq!(uart.read(7, MemSize::Byte), 0x5A);
+}
+
+// ── Guest access through the bus ──────────────────────────────────────────────
+
+/// lui  x1, 0x10000      ; x1 = UART base
+/// addi x2, x0, 'H'
+/// sb   x2, 0(x1)
+/// addi x2, x0, 'i'
+/// sb   x2, 0(x1)
+#[test]
+fn test_guest_prints_through_uart() {
+    let out = SharedBuffer::default();
+    let mut cpu = RiscvCpu::new(1024);
+    cpu.map_device(
+        Uart16550::BASE,
+        Uart16550::SIZE,
+        Uart16550::with_output(Box::new(out.clone())),
+    );
+
+    let program = [
+        lui(1, 0x10000),
+        addi(2, 0, b'H' as i32),
+        sb(2, 1, 0),
+        addi(2, 0, b'i' as i32),
+        sb(2, 1, 0),
+    ];
+    load_program(&mut cpu, &program);
+
+    for _ in 0..program.len() {
+        cpu.step().unwrap();
+    }
+
+    assert_eq!(out.contents(), "Hi");
+}
+
+/// lui  x1, 0x10000
+/// lbu  x2, 5(x1)        ; LSR
+/// lbu  x3, 0(x1)        ; RBR
+#[test]
+fn test_guest_reads_host_input() {
+    let mut cpu = RiscvCpu::new(1024);
+    let uart = Uart16550::with_output(Box::new(SharedBuffer::default()));
+    let input = uart.input();
+    cpu.map_device(Uart16550::BASE, Uart16550::SIZE, uart);
+
+    input.send(b'Z').unwrap();
+
+    let program = [lui(1, 0x10000), lbu(2, 1, 5), lbu(3, 1, 0)];
+    load_program(&mut cpu, &program);
+
+    for _ in 0..program.len() {
+        cpu.step().unwrap();
+    }
+
+    assert_ne!(cpu.regs[2] as u8 & uart::LSR_DR, 0, "LSR.DR should be set");
+    assert_eq!(cpu.regs[3], b'Z' as u32);
+}
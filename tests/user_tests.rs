@@ -0,0 +1,268 @@
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::user::*;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{ExitReason, MemSize, Reg, RiscvCpu};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const BASE: u32 = 0x10000;
+/// Where the code starts: after the ELF header and one program header.
+const CODE: u32 = BASE + 52 + 32;
+const GP: u32 = 0x10800;
+
+/// An ELF32 executable laid out like a linker would: one segment at
+/// [`BASE`] holding the headers, then `code`, then `bss` zeroed bytes, and
+/// a `__global_pointer$` symbol.
+fn build_elf(code: &[u8], bss: u32) -> Vec<u8> {
+    let code_off = 52 + 32;
+    let symtab_off = code_off + code.len();
+    let mut symtab = vec![0u8; 16];
+    for field in [1, GP, 0] {
+        symtab.extend_from_slice(&field.to_le_bytes());
+    }
+    symtab.extend_from_slice(&[0x10, 0, 0xf1, 0xff]); // global, SHN_ABS
+    let strtab = b"\0__global_pointer$\0";
+    let strtab_off = symtab_off + symtab.len();
+    let shoff = strtab_off + strtab.len();
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0]);
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(&2u16.to_le_bytes()); // e_type = ET_EXEC
+    out.extend_from_slice(&243u16.to_le_bytes()); // e_machine = EM_RISCV
+    out.extend_from_slice(&1u32.to_le_bytes()); // e_version
+    out.extend_from_slice(&CODE.to_le_bytes()); // e_entry
+    out.extend_from_slice(&52u32.to_le_bytes()); // e_phoff
+    out.extend_from_slice(&(shoff as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+    out.extend_from_slice(&52u16.to_le_bytes()); // e_ehsize
+    out.extend_from_slice(&32u16.to_le_bytes()); // e_phentsize
+    out.extend_from_slice(&1u16.to_le_bytes()); // e_phnum
+    out.extend_from_slice(&40u16.to_le_bytes()); // e_shentsize
+    out.extend_from_slice(&3u16.to_le_bytes()); // e_shnum
+    out.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+
+    let filesz = (code_off + code.len()) as u32;
+    for field in [1, 0, BASE, BASE, filesz, filesz + bss, 0x7, 0x1000] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    out.extend_from_slice(code);
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(strtab);
+
+    let symtab_sh = [0, 2, 0, 0, symtab_off as u32, 32, 2, 1, 4, 16];
+    let strtab_sh = [
+        0,
+        3,
+        0,
+        0,
+        strtab_off as u32,
+        strtab.len() as u32,
+        0,
+        0,
+        1,
+        0,
+    ];
+    for section in [[0; 10], symtab_sh, strtab_sh] {
+        for field in section {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    out
+}
+
+fn code(source: &str) -> Vec<u8> {
+    assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .collect()
+}
+
+fn cpu() -> RiscvCpu {
+    RiscvCpu::builder().ram_size(0x40000).build().unwrap()
+}
+
+fn word(cpu: &mut RiscvCpu, addr: u64) -> u64 {
+    cpu.bus.read(addr as u32, MemSize::Word).unwrap() as u64
+}
+
+fn string(cpu: &mut RiscvCpu, mut addr: u64) -> String {
+    let mut s = String::new();
+    loop {
+        match cpu.bus.read(addr as u32, MemSize::Byte).unwrap() {
+            0 => return s,
+            c => s.push(c as u8 as char),
+        }
+        addr += 1;
+    }
+}
+
+/// The auxiliary vector that starts at `addr`, up to `AT_NULL`.
+fn auxv(cpu: &mut RiscvCpu, mut addr: u64) -> Vec<(u64, u64)> {
+    let mut entries = Vec::new();
+    loop {
+        let entry = (word(cpu, addr), word(cpu, addr + 4));
+        if entry.0 == AT_NULL {
+            return entries;
+        }
+        entries.push(entry);
+        addr += 8;
+    }
+}
+
+// ── Startup stack ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_stack_holds_argc_argv_and_envp() {
+    let mut cpu = cpu();
+    let process = Process::new("prog")
+        .args(["one", "two"])
+        .env("HOME", "/root")
+        .env("LANG", "C");
+    cpu.load_process(&build_elf(&code("ebreak"), 0), &process)
+        .unwrap();
+
+    let sp = cpu.reg(Reg::Sp);
+    assert_eq!(sp % 16, 0);
+    assert!(sp < 0x40000 && sp > 0x3f000);
+    assert_eq!(word(&mut cpu, sp), 3);
+
+    let args: Vec<String> = (0..3)
+        .map(|i| {
+            let arg = word(&mut cpu, sp + 4 + 4 * i);
+            string(&mut cpu, arg)
+        })
+        .collect();
+    assert_eq!(args, ["prog", "one", "two"]);
+    assert_eq!(word(&mut cpu, sp + 16), 0);
+
+    let env: Vec<String> = (0..2)
+        .map(|i| {
+            let var = word(&mut cpu, sp + 20 + 4 * i);
+            string(&mut cpu, var)
+        })
+        .collect();
+    assert_eq!(env, ["HOME=/root", "LANG=C"]);
+    assert_eq!(word(&mut cpu, sp + 28), 0);
+}
+
+#[test]
+fn test_auxiliary_vector() {
+    let mut cpu = cpu();
+    let process = Process::new("/bin/prog").random(*b"0123456789abcdef");
+    cpu.load_process(&build_elf(&code("ebreak"), 0), &process)
+        .unwrap();
+
+    let sp = cpu.reg(Reg::Sp);
+    let auxv = auxv(&mut cpu, sp + 16);
+    let get = |kind| auxv.iter().find(|e| e.0 == kind).map(|e| e.1);
+
+    assert_eq!(get(AT_PHDR), Some(BASE as u64 + 52));
+    assert_eq!(get(AT_PHENT), Some(32));
+    assert_eq!(get(AT_PHNUM), Some(1));
+    assert_eq!(get(AT_PAGESZ), Some(4096));
+    assert_eq!(get(AT_ENTRY), Some(CODE as u64));
+    assert_eq!(get(AT_UID), Some(0));
+    assert_eq!(get(AT_SECURE), Some(0));
+    // RV32IMAC and friends: at least I.
+    assert_ne!(get(AT_HWCAP).unwrap() & 1 << (b'I' - b'A'), 0);
+
+    let random = get(AT_RANDOM).unwrap();
+    let bytes: Vec<u8> = (0..16)
+        .map(|i| cpu.bus.read((random + i) as u32, MemSize::Byte).unwrap() as u8)
+        .collect();
+    assert_eq!(bytes, b"0123456789abcdef");
+
+    let execfn = get(AT_EXECFN).unwrap();
+    assert_eq!(string(&mut cpu, execfn), "/bin/prog");
+}
+
+#[test]
+fn test_sets_sp_gp_and_the_entry_point() {
+    let mut cpu = cpu();
+    cpu.regs[10] = 0x1234;
+    cpu.load_process(&build_elf(&code("ebreak"), 0), &Process::new("prog"))
+        .unwrap();
+
+    assert_eq!(cpu.pc, CODE);
+    assert_eq!(cpu.reg(Reg::Gp), GP as u64);
+    assert_eq!(cpu.reg(Reg::A0), 0, "no atexit function");
+}
+
+#[test]
+fn test_main_sees_its_arguments() {
+    // argc into a0, the first letter of argv[1] into a1.
+    let program = code(
+        "
+        lw   a0, 0(sp)
+        lw   t0, 8(sp)
+        lbu  a1, 0(t0)
+        ebreak
+        ",
+    );
+    let mut cpu = cpu();
+    cpu.load_process(&build_elf(&program, 0), &Process::new("prog").arg("world"))
+        .unwrap();
+
+    assert!(matches!(cpu.run(), ExitReason::Exception(_)));
+    assert_eq!(cpu.reg(Reg::A0), 2);
+    assert_eq!(cpu.reg(Reg::A1), b'w' as u64);
+}
+
+#[test]
+fn test_bss_is_zeroed() {
+    let program = code("ebreak");
+    let elf = build_elf(&program, 0x100);
+    let bss = CODE + program.len() as u32;
+    let mut cpu = cpu();
+    cpu.bus.write_bytes(bss, &[0xAA; 0x100]).unwrap();
+
+    cpu.load_process(&elf, &Process::new("prog")).unwrap();
+
+    assert_eq!(cpu.bus.read(bss, MemSize::Word), Some(0));
+    assert_eq!(cpu.bus.read(bss + 0xFC, MemSize::Word), Some(0));
+}
+
+#[test]
+fn test_rv64_stack_uses_doublewords() {
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .ram_size(0x40000)
+        .build()
+        .unwrap();
+    cpu.load_process(
+        &build_elf(&code("ebreak"), 0),
+        &Process::new("prog").arg("x"),
+    )
+    .unwrap();
+
+    let sp = cpu.reg(Reg::Sp) as u32;
+    assert_eq!(sp % 16, 0);
+    assert_eq!(cpu.bus.read(sp, MemSize::Word), Some(2));
+    assert_eq!(cpu.bus.read(sp + 4, MemSize::Word), Some(0));
+    let argv1 = cpu.bus.read(sp + 16, MemSize::Word).unwrap();
+    assert_eq!(cpu.bus.read(argv1, MemSize::Half), Some(b'x' as u32));
+}
+
+#[test]
+fn test_stack_top_can_be_moved() {
+    let mut cpu = cpu();
+    let process = Process::new("prog").stack_top(0x30000);
+    cpu.load_process(&build_elf(&code("ebreak"), 0), &process)
+        .unwrap();
+
+    let sp = cpu.reg(Reg::Sp);
+    assert!(sp < 0x30000 && sp > 0x2f000);
+}
+
+#[test]
+fn test_arguments_must_fit() {
+    let mut cpu = cpu();
+    let process = Process::new("prog").stack_top(0x20);
+
+    let error = cpu
+        .load_process(&build_elf(&code("ebreak"), 0), &process)
+        .unwrap_err();
+    assert!(error.starts_with("Process: "), "{}", error);
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use std::io::{self};
+
+use riscv_emulator_rust::costs::CycleCosts;
+use riscv_emulator_rust::encode::*;
+use riscv_emulator_rust::vcd::Vcd;
+use riscv_emulator_rust::{ExitReason, MemSize, RiscvCpu, csr};
+
+use common::{SharedBuffer, le_bytes};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// `x1 = 5; x2 = x1 + x1; [0x100] = x2; ebreak`.
+fn cpu() -> RiscvCpu {
+    let words = [addi(1, 0, 5), add(2, 1, 1), sw(2, 0, 0x100), ebreak()];
+    RiscvCpu::builder()
+        .ram_size(4096)
+        .image(0, le_bytes(&words))
+        .build()
+        .unwrap()
+}
+
+/// The lines after the header.
+fn changes(text: &str) -> Vec<&str> {
+    text.lines()
+        .skip_while(|line| *line != "$enddefinitions $end")
+        .skip(1)
+        .collect()
+}
+
+// ── Header ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_declares_the_registers_and_added_signals() {
+    let out = SharedBuffer::default();
+    let mut vcd = Vcd::new(Box::new(out.clone()))
+        .csr("mstatus", csr::MSTATUS)
+        .memory("result", 0x100, MemSize::Half);
+    vcd.sample(&mut cpu()).unwrap();
+
+    let text = out.contents();
+    let header: Vec<&str> = text
+        .lines()
+        .take_while(|line| !line.starts_with('#'))
+        .collect();
+    assert_eq!(
+        header[..5].to_vec(),
+        vec![
+            "$version riscv-emulator-rust $end",
+            "$timescale 1ns $end",
+            "$scope module hart $end",
+            "$var wire 32 ! pc $end",
+            "$var wire 32 \" x1 $end",
+        ]
+    );
+    assert_eq!(header[4 + 30], "$var wire 32 @ x31 $end");
+    assert_eq!(
+        header[35..].to_vec(),
+        vec![
+            "$scope module csr $end",
+            "$var wire 32 A mstatus $end",
+            "$upscope $end",
+            "$scope module mem $end",
+            "$var wire 16 B result $end",
+            "$upscope $end",
+            "$upscope $end",
+            "$enddefinitions $end",
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "Vcd: signal names")]
+fn test_signal_names_cant_have_spaces() {
+    let _ = Vcd::new(Box::new(io::sink())).csr("machine status", csr::MSTATUS);
+}
+
+// ── Changes ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_dumps_only_what_changed_each_step() {
+    let out = SharedBuffer::default();
+    let mut cpu = cpu();
+    let mut vcd = Vcd::new(Box::new(out.clone())).memory("result", 0x100, MemSize::Word);
+
+    let exit = vcd.run(&mut cpu, None).unwrap();
+    assert!(matches!(exit, ExitReason::Exception(_)));
+
+    let text = out.contents();
+    let changes = changes(&text);
+    assert_eq!(changes[..3].to_vec(), vec!["#0", "$dumpvars", "b0 !"]);
+    assert_eq!(
+        changes[34..].to_vec(),
+        vec![
+            "b0 A", "$end", "#1", "b100 !", "b101 \"", "#2", "b1000 !", "b1010 #", "#3", "b1100 !",
+            "b1010 A",
+        ]
+    );
+}
+
+#[test]
+fn test_stops_at_the_limit() {
+    let out = SharedBuffer::default();
+    let mut vcd = Vcd::new(Box::new(out.clone()));
+
+    assert!(matches!(
+        vcd.run(&mut cpu(), Some(2)).unwrap(),
+        ExitReason::StepLimit
+    ));
+    assert!(out.contents().ends_with("#2\nb1000 !\nb1010 #\n"));
+}
+
+#[test]
+fn test_memory_outside_ram_is_unknown() {
+    let out = SharedBuffer::default();
+    let mut vcd = Vcd::new(Box::new(out.clone())).memory("uart", 0x1000_0000, MemSize::Byte);
+    vcd.sample(&mut cpu()).unwrap();
+
+    assert!(out.contents().contains("$var wire 8 A uart $end"));
+    assert!(out.contents().contains("\nbx A\n"));
+}
+
+#[test]
+fn test_times_by_mcycle() {
+    let out = SharedBuffer::default();
+    let mut cpu = cpu();
+    cpu.set_cycle_costs(CycleCosts::new().mnemonic("sw", 5));
+    let mut vcd = Vcd::new(Box::new(out.clone())).cycles();
+
+    vcd.run(&mut cpu, Some(3)).unwrap();
+
+    let text = out.contents();
+    let times: Vec<&str> = changes(&text)
+        .into_iter()
+        .filter(|line| line.starts_with('#'))
+        .collect();
+    assert_eq!(times, vec!["#0", "#1", "#2", "#7"]);
+}
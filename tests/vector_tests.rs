@@ -0,0 +1,308 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu};
+
+use common::{cpu_with, image, le_bytes};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// The 32-bit elements of vector register `n`.
+fn words(cpu: &RiscvCpu, n: u8) -> Vec<u32> {
+    cpu.vreg(n)
+        .chunks(4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .collect()
+}
+
+fn set_words(cpu: &mut RiscvCpu, n: u8, values: &[u32]) {
+    let bytes = le_bytes(values);
+    cpu.vreg_mut(n)[..bytes.len()].copy_from_slice(&bytes);
+}
+
+// ── Kernels ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_strip_mined_array_add() {
+    let engines = [
+        Engine::Interpreter,
+        Engine::BasicBlocks,
+        #[cfg(feature = "jit")]
+        Engine::Jit,
+    ];
+
+    for engine in engines {
+        let mut cpu = RiscvCpu::builder()
+            .image(
+                0,
+                image(
+                    "
+                    addi    a0, zero, 10
+                    lui     a1, 0x1
+                    lui     a2, 0x2
+                    lui     a3, 0x3
+            loop:   vsetvli t0, a0, e32, m1, ta, ma
+                    vle32.v v1, (a1)
+                    vle32.v v2, (a2)
+                    vadd.vv v3, v1, v2
+                    vse32.v v3, (a3)
+                    sub     a0, a0, t0
+                    slli    t1, t0, 2
+                    add     a1, a1, t1
+                    add     a2, a2, t1
+                    add     a3, a3, t1
+                    bne     a0, zero, loop
+                    ebreak
+                    ",
+                ),
+            )
+            .engine(engine)
+            .build()
+            .unwrap();
+        for i in 0..10 {
+            cpu.bus.write(0x1000 + 4 * i, MemSize::Word, i).unwrap();
+            cpu.bus
+                .write(0x2000 + 4 * i, MemSize::Word, 100 * i)
+                .unwrap();
+        }
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x3C))
+        );
+        for i in 0..10 {
+            assert_eq!(
+                cpu.bus.read(0x3000 + 4 * i, MemSize::Word),
+                Some(101 * i),
+                "{:?}",
+                engine
+            );
+        }
+        assert_eq!(cpu.bus.read(0x3028, MemSize::Word), Some(0));
+        assert_eq!(cpu.csrs.read(csr::VL), 2, "the last strip is short");
+    }
+}
+
+#[test]
+fn test_integer_operations() {
+    let mut cpu = cpu_with(
+        "
+        addi     a0, zero, 5
+        vsetivli zero, 4, e32, m1, tu, mu
+        vmv.v.i  v1, -3
+        vmv.v.x  v2, a0
+        vmul.vv  v3, v1, v2
+        vmax.vv  v4, v1, v2
+        vmaxu.vv v5, v1, v2
+        vsra.vi  v6, v1, 1
+        vsrl.vi  v7, v1, 1
+        vrsub.vx v8, v2, zero
+        ",
+    );
+
+    cpu.run_steps(10);
+
+    let splat = |value: i32| vec![value as u32; 4];
+    assert_eq!(words(&cpu, 3), splat(-15));
+    assert_eq!(words(&cpu, 4), splat(5));
+    assert_eq!(words(&cpu, 5), splat(-3));
+    assert_eq!(words(&cpu, 6), splat(-2));
+    assert_eq!(words(&cpu, 7), splat(0x7FFF_FFFE));
+    assert_eq!(words(&cpu, 8), splat(-5));
+}
+
+#[test]
+fn test_masks_merges_and_reductions() {
+    let mut cpu = cpu_with(
+        "
+        vsetivli  zero, 4, e32, m1, tu, mu
+        vmsle.vi  v0, v6, 1
+        vadd.vi   v7, v6, 10, v0.t
+        vmerge.vim v9, v6, 7, v0
+        vredsum.vs v10, v6, v1
+        vmv.x.s   a0, v10
+        vsetivli  zero, 2, e32, m1, tu, mu
+        vadd.vv   v11, v6, v6
+        ",
+    );
+    set_words(&mut cpu, 6, &[0, 1, 2, 3]);
+    set_words(&mut cpu, 7, &[0xAA; 4]);
+    set_words(&mut cpu, 1, &[-3i32 as u32, 99, 99, 99]);
+    set_words(&mut cpu, 11, &[0xBB; 4]);
+
+    cpu.run_steps(8);
+
+    assert_eq!(cpu.vreg(0)[0], 0b0011);
+    assert_eq!(
+        words(&cpu, 7),
+        [10, 11, 0xAA, 0xAA],
+        "masked-off undisturbed"
+    );
+    assert_eq!(words(&cpu, 9), [7, 7, 2, 3]);
+    assert_eq!(words(&cpu, 10)[0], 3);
+    assert_eq!(cpu.regs[10], 3);
+    assert_eq!(words(&cpu, 11), [0, 2, 0xBB, 0xBB], "tail undisturbed");
+}
+
+#[test]
+fn test_rv64_wide_elements() {
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .image(
+            0,
+            image(
+                "
+                addi     a0, zero, -1
+                vsetivli zero, 2, e64, m1, ta, ma
+                vmv.v.x  v1, a0
+                vadd.vx  v2, v1, a0
+                vmv.x.s  a1, v2
+                vsetivli zero, 2, e8, m1, ta, ma
+                vmv.x.s  a2, v2
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+
+    cpu.run_steps(7);
+
+    assert_eq!(cpu.regs[11], -2i64 as u64);
+    assert_eq!(cpu.regs[12], -2i64 as u64, "narrow elements sign-extend");
+}
+
+// ── Configuration ─────────────────────────────────────────────────────────────
+
+#[test]
+fn test_vsetvl_follows_vlen_and_lmul() {
+    let mut cpu = RiscvCpu::builder()
+        .vlen(256)
+        .image(
+            0,
+            image(
+                "
+                addi     t0, zero, 1000
+                vsetvli  a0, zero, e16, m2
+                vsetivli a1, 5, e8, m1
+                vsetvli  a2, t0, e64, m8
+                csrrs    a3, vlenb, zero
+                vsetvli  a4, t0, e32, mf2
+                csrrs    a5, vtype, zero
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+
+    cpu.run_steps(7);
+
+    assert_eq!(cpu.vlen(), 256);
+    assert_eq!(cpu.regs[10], 32);
+    assert_eq!(cpu.regs[11], 5);
+    assert_eq!(cpu.regs[12], 32);
+    assert_eq!(cpu.regs[13], 32);
+    assert_eq!(cpu.regs[14], 0, "fractional LMUL isn't supported");
+    assert_eq!(cpu.regs[15], 1 << 31, "vill");
+}
+
+#[test]
+fn test_invalid_vlen_is_rejected() {
+    for vlen in [32, 96, 1 << 17] {
+        assert!(RiscvCpu::builder().vlen(vlen).build().is_err(), "{}", vlen);
+    }
+}
+
+#[test]
+fn test_illegal_configurations() {
+    let cases = [
+        // No vset yet, so vtype is vill.
+        ("vadd.vv v1, v2, v3", ""),
+        ("vadd.vv v1, v2, v4", "vsetvli zero, zero, e32, m2"),
+        ("vadd.vv v0, v2, v4, v0.t", "vsetvli zero, zero, e32, m1"),
+        ("vle64.v v1, (a0)", "vsetvli zero, zero, e32, m1"),
+    ];
+
+    for (instruction, setup) in cases {
+        let mut cpu = cpu_with(&format!("{}\n{}", setup, instruction));
+        if !setup.is_empty() {
+            cpu.step().unwrap();
+        }
+
+        let word = assemble(instruction).unwrap()[0];
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::IllegalInstruction(word)),
+            "{}",
+            instruction
+        );
+    }
+}
+
+// ── Memory and state ──────────────────────────────────────────────────────────
+
+#[test]
+fn test_faulting_load_records_vstart() {
+    let mut cpu = cpu_with(
+        "
+        lui      a0, 0x10
+        addi     a0, a0, -8
+        vsetivli zero, 4, e32, m1
+        vle32.v  v1, (a0)
+        ",
+    );
+    cpu.bus.write(0xFFF8, MemSize::Word, 7).unwrap();
+    cpu.bus.write(0xFFFC, MemSize::Word, 8).unwrap();
+
+    cpu.run_steps(3);
+
+    assert_eq!(cpu.step(), Err(Exception::LoadAccessFault(0x10000)));
+    assert_eq!(cpu.csrs.read(csr::VSTART), 2);
+    assert_eq!(words(&cpu, 1)[..2], [7, 8]);
+}
+
+#[test]
+fn test_vector_state_survives_a_snapshot() {
+    let mut cpu = RiscvCpu::builder().vlen(64).build().unwrap();
+    cpu.vreg_mut(31).copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+    let snapshot = cpu.save_snapshot();
+    let restored = RiscvCpu::from_snapshot(&snapshot);
+    assert_eq!(restored.vlen(), 64);
+    assert_eq!(restored.vreg(31), [1, 2, 3, 4, 5, 6, 7, 8]);
+
+    let mut other = RiscvCpu::builder().build().unwrap();
+    assert!(other.restore(&snapshot).is_err(), "VLEN differs");
+}
+
+#[test]
+fn test_disassembly() {
+    let cases = [
+        (
+            "vsetvli a0, a1, e32, m2, ta, ma",
+            "vsetvli x10, x11, e32, m2, ta, ma",
+        ),
+        (
+            "vsetivli zero, 7, e8, mf2",
+            "vsetivli x0, 7, e8, mf2, tu, mu",
+        ),
+        ("vle16.v v4, (sp), v0.t", "vle16.v v4, (x2), v0.t"),
+        ("vse64.v v8, (a0)", "vse64.v v8, (x10)"),
+        ("vsub.vx v1, v2, t0", "vsub.vx v1, v2, x5"),
+        ("vand.vi v1, v2, -16", "vand.vi v1, v2, -16"),
+        ("vsll.vi v1, v2, 31", "vsll.vi v1, v2, 31"),
+        ("vmerge.vvm v1, v2, v3, v0", "vmerge.vvm v1, v2, v3, v0"),
+        ("vmv.v.i v5, 3", "vmv.v.i v5, 3"),
+        ("vmul.vx v1, v2, a0", "vmul.vx v1, v2, x10"),
+        ("vredsum.vs v1, v2, v3", "vredsum.vs v1, v2, v3"),
+        ("vmv.s.x v1, a0", "vmv.s.x v1, x10"),
+    ];
+
+    for (source, expected) in cases {
+        let word = assemble(source).unwrap()[0];
+        assert_eq!(decode(word).unwrap().to_string(), expected, "{}", source);
+    }
+}
@@ -0,0 +1,386 @@
+mod common;
+
+use std::collections::HashMap;
+
+use riscv_emulator_rust::csr::Privilege;
+use riscv_emulator_rust::devices::Uart16550;
+use riscv_emulator_rust::devices::virtio::VirtioInput;
+use riscv_emulator_rust::isa::{Extension, Extensions};
+use riscv_emulator_rust::machine::Machine;
+use riscv_emulator_rust::virt::{self, Virt, VirtLayout};
+use riscv_emulator_rust::xlen::{Rv32, Rv64};
+
+use common::{SharedBuffer, image};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn read_ram(machine: &mut Machine, addr: u32, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    machine.bus.dma().read(addr as u64, &mut buf).unwrap();
+    buf
+}
+
+/// The properties of every node in a device tree blob, by path.
+type Tree = HashMap<String, HashMap<String, Vec<u8>>>;
+
+fn be32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn parse_fdt(blob: &[u8]) -> Tree {
+    assert_eq!(be32(blob, 0), 0xD00D_FEED);
+    assert_eq!(be32(blob, 4) as usize, blob.len());
+    let structure = be32(blob, 8) as usize;
+    let strings = be32(blob, 12) as usize;
+    let c_str = |at: usize| {
+        let end = blob[at..].iter().position(|&b| b == 0).unwrap();
+        String::from_utf8(blob[at..at + end].to_vec()).unwrap()
+    };
+
+    let mut tree = Tree::new();
+    let mut path: Vec<String> = Vec::new();
+    let mut at = structure;
+    loop {
+        let token = be32(blob, at);
+        at += 4;
+        match token {
+            1 => {
+                let name = c_str(at);
+                at = (at + name.len() + 1).next_multiple_of(4);
+                path.push(name);
+                tree.entry(join(&path)).or_default();
+            }
+            2 => {
+                path.pop();
+            }
+            3 => {
+                let len = be32(blob, at) as usize;
+                let name = c_str(strings + be32(blob, at + 4) as usize);
+                let value = blob[at + 8..at + 8 + len].to_vec();
+                at = (at + 8 + len).next_multiple_of(4);
+                tree.get_mut(&join(&path)).unwrap().insert(name, value);
+            }
+            9 => return tree,
+            token => panic!("bad token {}", token),
+        }
+    }
+}
+
+fn join(path: &[String]) -> String {
+    match path.len() {
+        1 => "/".to_string(),
+        _ => path.join("/"),
+    }
+}
+
+fn prop<'a>(tree: &'a Tree, node: &str, name: &str) -> &'a [u8] {
+    &tree[node][name]
+}
+
+fn prop_str<'a>(tree: &'a Tree, node: &str, name: &str) -> &'a str {
+    std::str::from_utf8(prop(tree, node, name))
+        .unwrap()
+        .trim_end_matches('\0')
+}
+
+fn prop_cells(tree: &Tree, node: &str, name: &str) -> Vec<u32> {
+    let value = prop(tree, node, name);
+    (0..value.len())
+        .step_by(4)
+        .map(|at| be32(value, at))
+        .collect()
+}
+
+fn device_tree(machine: &mut Machine, layout: VirtLayout) -> Tree {
+    let header = read_ram(machine, layout.fdt, 8);
+    let blob = read_ram(machine, layout.fdt, be32(&header, 4) as usize);
+    parse_fdt(&blob)
+}
+
+// ── Device tree ───────────────────────────────────────────────────────────────
+
+#[test]
+fn test_device_tree_describes_the_board() {
+    let (mut machine, layout) = Virt::new()
+        .harts(2)
+        .bootargs("console=ttyS0")
+        .virtio(VirtioInput::keyboard())
+        .virtio(VirtioInput::mouse())
+        .build::<Rv32>()
+        .unwrap();
+    let tree = device_tree(&mut machine, layout);
+
+    assert_eq!(prop_str(&tree, "/", "compatible"), "riscv-virtio");
+    assert_eq!(prop_str(&tree, "/chosen", "bootargs"), "console=ttyS0");
+    assert_eq!(
+        prop_str(&tree, "/chosen", "stdout-path"),
+        "/soc/serial@10000000"
+    );
+    assert_eq!(
+        prop_cells(&tree, "/memory@80000000", "reg"),
+        [0, 0x8000_0000, 0, 128 << 20]
+    );
+
+    for cpu in ["/cpus/cpu@0", "/cpus/cpu@1"] {
+        assert_eq!(
+            prop_str(&tree, cpu, "riscv,isa"),
+            Extensions::all().isa_string(32)
+        );
+        assert_eq!(prop_str(&tree, cpu, "mmu-type"), "riscv,sv32");
+    }
+    assert_eq!(prop_cells(&tree, "/cpus/cpu@1", "reg"), [1]);
+
+    let serial = "/soc/serial@10000000";
+    assert_eq!(prop_str(&tree, serial, "compatible"), "ns16550a");
+    assert_eq!(prop_cells(&tree, serial, "interrupts"), [virt::UART_IRQ]);
+
+    let plic = prop_cells(&tree, "/soc/plic@c000000", "phandle");
+    assert_eq!(prop_cells(&tree, serial, "interrupt-parent"), plic);
+    // M and S external interrupts on each of the two harts.
+    let wired = prop_cells(&tree, "/soc/plic@c000000", "interrupts-extended");
+    assert_eq!(wired.len(), 8);
+    assert_eq!(wired[1], 11);
+    assert_eq!(wired[3], 9);
+
+    assert_eq!(
+        prop_cells(&tree, "/soc/virtio_mmio@10002000", "interrupts"),
+        [virt::VIRTIO_IRQ + 1]
+    );
+    assert!(!tree.contains_key("/soc/virtio_mmio@10003000"));
+}
+
+#[test]
+fn test_device_tree_follows_the_configuration() {
+    let extensions = Extensions::none().with(Extension::Zba);
+    let (mut machine, layout) = Virt::new().extensions(extensions).build::<Rv32>().unwrap();
+    let tree = device_tree(&mut machine, layout);
+
+    assert_eq!(
+        prop_str(&tree, "/cpus/cpu@0", "riscv,isa"),
+        "rv32i_zicntr_zicsr_zifencei_zihpm_zba"
+    );
+    assert!(!tree["/cpus/cpu@0"].contains_key("mmu-type"));
+    assert!(!tree["/chosen"].contains_key("bootargs"));
+    assert!(!tree["/chosen"].contains_key("linux,initrd-start"));
+}
+
+#[test]
+fn test_initrd_sits_below_the_device_tree() {
+    let initrd: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+    let (mut machine, layout) = Virt::new()
+        .kernel(vec![0x13, 0, 0, 0])
+        .initrd(initrd.clone())
+        .build::<Rv32>()
+        .unwrap();
+
+    let (start, end) = layout.initrd.unwrap();
+    assert_eq!(start % 0x1000, 0);
+    assert_eq!(end - start, 5000);
+    assert!(end <= layout.fdt);
+    assert_eq!(read_ram(&mut machine, start, 5000), initrd);
+
+    let tree = device_tree(&mut machine, layout);
+    assert_eq!(
+        prop_cells(&tree, "/chosen", "linux,initrd-start"),
+        [0, start]
+    );
+    assert_eq!(prop_cells(&tree, "/chosen", "linux,initrd-end"), [0, end]);
+}
+
+// ── Boot ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_harts_boot_with_hart_id_and_device_tree() {
+    let (machine, layout) = Virt::new().harts(3).build::<Rv32>().unwrap();
+    for id in 0..3 {
+        let hart = machine.hart(id);
+        assert_eq!(hart.pc, virt::RAM_BASE);
+        assert_eq!(hart.regs[10], id as u32);
+        assert_eq!(hart.regs[11], layout.fdt);
+    }
+
+    let (machine, layout) = Virt::new().build::<Rv64>().unwrap();
+    assert_eq!(machine.hart(0).regs[11], layout.fdt as u64);
+}
+
+#[test]
+fn test_kernel_finds_the_device_tree_and_prints() {
+    // Checks the first byte of the blob `a1` points at, then says so.
+    let kernel = image(
+        "
+        lbu   t0, 0(a1)
+        addi  t1, zero, 0xd0
+        bne   t0, t1, hang
+        lui   t2, 0x10000
+        addi  t0, zero, 0x6f
+        sb    t0, 0(t2)
+        addi  t0, zero, 0x6b
+        sb    t0, 0(t2)
+        hang:
+        jal   zero, hang
+        ",
+    );
+    let output = SharedBuffer::default();
+    let (mut machine, _) = Virt::new()
+        .kernel(kernel)
+        .uart(Uart16550::with_output(Box::new(output.clone())))
+        .build::<Rv32>()
+        .unwrap();
+
+    machine.run_steps(20);
+    assert_eq!(output.contents(), "ok");
+}
+
+#[test]
+fn test_uart_interrupt_through_the_plic() {
+    // Enable PLIC source 10 for hart 0's M-mode context and the UART's
+    // receive interrupt, sleep, then claim, read and complete.
+    let kernel = image(
+        "
+        lui   t0, 0xc000
+        addi  t1, zero, 1
+        sw    t1, 40(t0)
+        addi  t1, zero, 0x400
+        lui   t2, 2
+        add   t2, t2, t0
+        sw    t1, 0(t2)
+        lui   t3, 0x10000
+        addi  t1, zero, 1
+        sb    t1, 1(t3)
+        lui   t1, 1
+        srli  t1, t1, 1
+        csrrs zero, mie, t1
+        wfi
+        lui   t2, 0x200
+        add   t2, t2, t0
+        lw    a0, 4(t2)
+        lbu   a1, 0(t3)
+        sw    a0, 4(t2)
+        lw    a2, 4(t2)
+        ",
+    );
+    let uart = Uart16550::with_output(Box::new(std::io::sink()));
+    let input = uart.input();
+    let (mut machine, _) = Virt::new()
+        .kernel(kernel)
+        .uart(uart)
+        .build::<Rv32>()
+        .unwrap();
+
+    machine.run_steps(100);
+    assert!(machine.hart(0).is_waiting());
+
+    input.send(b'x').unwrap();
+    machine.run_steps(6);
+    let hart = machine.hart(0);
+    assert_eq!(hart.regs[10], virt::UART_IRQ);
+    assert_eq!(hart.regs[11], b'x' as u32);
+    assert_eq!(hart.regs[12], 0, "nothing left to claim");
+}
+
+// ── Firmware ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_firmware_hands_over_to_the_kernel_in_supervisor_mode() {
+    // Like OpenSBI's fw_dynamic: check the info's magic, then mret to
+    // next_addr in S-mode with a0 and a1 untouched.
+    let firmware = image(
+        "
+        ld    t0, 0(a2)
+        lui   t1, 0x49425
+        addi  t1, t1, 0x34f
+        bne   t0, t1, hang
+        ld    t2, 16(a2)
+        csrrw zero, mepc, t2
+        lui   t0, 1
+        addi  t0, t0, -2048
+        csrrw zero, mstatus, t0
+        mret
+        hang:
+        jal   zero, hang
+        ",
+    );
+    let kernel = image(
+        "
+        lui   t2, 0x10000
+        addi  t0, a0, 0x30
+        sb    t0, 0(t2)
+        lbu   t0, 0(a1)
+        addi  t1, zero, 0xd0
+        bne   t0, t1, hang
+        addi  t0, zero, 0x53
+        sb    t0, 0(t2)
+        hang:
+        jal   zero, hang
+        ",
+    );
+    let output = SharedBuffer::default();
+    let (mut machine, layout) = Virt::new()
+        .firmware(firmware)
+        .kernel(kernel)
+        .uart(Uart16550::with_output(Box::new(output.clone())))
+        .build::<Rv64>()
+        .unwrap();
+    assert_eq!(layout.kernel, virt::RAM_BASE + virt::KERNEL_OFFSET_RV64);
+    assert_eq!(machine.hart(0).pc, virt::RAM_BASE as u64);
+    assert_eq!(
+        machine.hart(0).regs[12],
+        layout.dynamic_info.unwrap() as u64
+    );
+
+    machine.run_steps(40);
+
+    assert_eq!(output.contents(), "0S");
+    assert_eq!(machine.hart(0).privilege(), Privilege::Supervisor);
+}
+
+#[test]
+fn test_dynamic_info_is_xlen_wide() {
+    let (mut machine, layout) = Virt::new()
+        .harts(2)
+        .firmware(vec![0; 16])
+        .build::<Rv32>()
+        .unwrap();
+    let info = layout.dynamic_info.unwrap();
+    assert!(info > layout.fdt && info % 8 == 0);
+
+    let words: Vec<u32> = read_ram(&mut machine, info, 24)
+        .chunks(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    let kernel = virt::RAM_BASE + virt::KERNEL_OFFSET_RV32;
+    assert_eq!(words, [0x4942_534F, 2, kernel, 1, 0, u32::MAX]);
+    assert_eq!(layout.kernel, kernel);
+    for id in 0..2 {
+        assert_eq!(machine.hart(id).regs[12], info);
+    }
+
+    let (machine, layout) = Virt::new().build::<Rv32>().unwrap();
+    assert_eq!(layout.dynamic_info, None);
+    assert_eq!(machine.hart(0).regs[12], 0);
+}
+
+// ── Errors ────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_configurations_that_dont_fit() {
+    assert!(Virt::new().harts(0).build::<Rv32>().is_err());
+
+    let too_many = (0..9).fold(Virt::new(), |virt, _| virt.virtio(VirtioInput::keyboard()));
+    assert!(too_many.build::<Rv32>().is_err());
+
+    let small = || Virt::new().ram_size(0x4_0000);
+    assert!(small().kernel(vec![0; 0x4_0000]).build::<Rv32>().is_err());
+    assert!(
+        small()
+            .kernel(vec![0; 0x2_0000])
+            .initrd(vec![0; 0x1_0001])
+            .build::<Rv32>()
+            .is_err()
+    );
+    assert!(small().kernel(vec![0; 0x2_0000]).build::<Rv32>().is_ok());
+    assert!(Virt::new().ram_size(0x1000).build::<Rv32>().is_err());
+
+    let firmware = vec![0; virt::KERNEL_OFFSET_RV32 as usize + 1];
+    assert!(Virt::new().firmware(firmware).build::<Rv32>().is_err());
+}
@@ -0,0 +1,777 @@
+use std::cell::RefCell;
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
+#[cfg(feature = "std")]
+use std::net::{TcpListener, UdpSocket};
+use std::rc::Rc;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+use riscv_emulator_rust::bus::Dma;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::virtio::{
+    self, VirtioDevice, VirtioInput, VirtioMmio, Virtqueue, input,
+};
+#[cfg(feature = "std")]
+use riscv_emulator_rust::devices::virtio::{DiskMode, NetBackend, UserNet, VirtioBlk, VirtioNet};
+use riscv_emulator_rust::{MemSize, RiscvCpu};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const BASE: u32 = VirtioMmio::<Reverser>::BASE;
+
+const DESC: u32 = 0x1000;
+const AVAIL: u32 = 0x1100;
+const USED: u32 = 0x1200;
+const QUEUE_SIZE: u32 = 8;
+
+const DEVICE_FEATURES_SEL: u32 = 0x014;
+const DEVICE_FEATURES: u32 = 0x010;
+const DRIVER_FEATURES: u32 = 0x020;
+const DRIVER_FEATURES_SEL: u32 = 0x024;
+const QUEUE_NUM_MAX: u32 = 0x034;
+const QUEUE_NUM: u32 = 0x038;
+const QUEUE_READY: u32 = 0x044;
+const QUEUE_NOTIFY: u32 = 0x050;
+const INTERRUPT_STATUS: u32 = 0x060;
+const INTERRUPT_ACK: u32 = 0x064;
+const STATUS: u32 = 0x070;
+
+/// Writes each request's bytes back reversed, and counts resets.
+#[derive(Clone, Default)]
+struct Reverser {
+    resets: Rc<RefCell<u32>>,
+}
+
+impl VirtioDevice for Reverser {
+    fn device_id(&self) -> u32 {
+        42
+    }
+
+    fn features(&self) -> u64 {
+        1 << 3
+    }
+
+    fn queue_size(&self) -> u16 {
+        QUEUE_SIZE as u16
+    }
+
+    fn read_config(&mut self, offset: u32, _size: MemSize) -> u32 {
+        0xC0F0_0000 | offset
+    }
+
+    fn reset(&mut self) {
+        *self.resets.borrow_mut() += 1;
+    }
+
+    fn notify(&mut self, queue: usize, queues: &mut [Virtqueue], memory: &mut Dma<'_>) {
+        let queue = &mut queues[queue];
+        while let Some(chain) = queue.pop(memory) {
+            let mut bytes = chain.read_all(memory);
+            bytes.reverse();
+            let written = chain.write(memory, 0, &bytes);
+            queue.push(memory, chain.head, written as u32);
+        }
+    }
+}
+
+fn cpu_with(device: impl VirtioDevice + 'static) -> RiscvCpu {
+    RiscvCpu::builder()
+        .device(BASE, VirtioMmio::<Reverser>::SIZE, VirtioMmio::new(device))
+        .build()
+        .unwrap()
+}
+
+fn reg(cpu: &mut RiscvCpu, offset: u32) -> u32 {
+    cpu.bus.read(BASE + offset, MemSize::Word).unwrap()
+}
+
+fn set_reg(cpu: &mut RiscvCpu, offset: u32, value: u32) {
+    cpu.bus.write(BASE + offset, MemSize::Word, value).unwrap();
+}
+
+/// Go through the driver's side of initialization, accepting VERSION_1,
+/// and set up queue 0.
+fn bring_up(cpu: &mut RiscvCpu) {
+    set_up_queue(cpu);
+    set_reg(cpu, STATUS, 0xF);
+}
+
+/// Everything `bring_up` does short of setting DRIVER_OK.
+fn set_up_queue(cpu: &mut RiscvCpu) {
+    set_reg(
+        cpu,
+        STATUS,
+        virtio::STATUS_ACKNOWLEDGE | virtio::STATUS_DRIVER,
+    );
+    set_reg(cpu, DRIVER_FEATURES_SEL, 1);
+    set_reg(cpu, DRIVER_FEATURES, 1);
+    set_reg(cpu, STATUS, 0xB);
+
+    set_reg(cpu, QUEUE_NUM, QUEUE_SIZE);
+    set_reg(cpu, 0x080, DESC);
+    set_reg(cpu, 0x090, AVAIL);
+    set_reg(cpu, 0x0A0, USED);
+    set_reg(cpu, QUEUE_READY, 1);
+}
+
+fn write_desc(cpu: &mut RiscvCpu, index: u32, addr: u32, len: u32, flags: u32, next: u32) {
+    let desc = DESC + 16 * index;
+    cpu.bus.write(desc, MemSize::Word, addr).unwrap();
+    cpu.bus.write(desc + 4, MemSize::Word, 0).unwrap();
+    cpu.bus.write(desc + 8, MemSize::Word, len).unwrap();
+    cpu.bus.write(desc + 12, MemSize::Half, flags).unwrap();
+    cpu.bus.write(desc + 14, MemSize::Half, next).unwrap();
+}
+
+/// Put `head` on the available ring and tell the device.
+fn make_available(cpu: &mut RiscvCpu, head: u32) {
+    let idx = cpu.bus.read(AVAIL + 2, MemSize::Half).unwrap();
+    let slot = AVAIL + 4 + 2 * (idx % QUEUE_SIZE);
+    cpu.bus.write(slot, MemSize::Half, head).unwrap();
+    cpu.bus.write(AVAIL + 2, MemSize::Half, idx + 1).unwrap();
+    set_reg(cpu, QUEUE_NOTIFY, 0);
+}
+
+// ── Registers ─────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_probe_registers() {
+    let mut cpu = cpu_with(Reverser::default());
+
+    assert_eq!(reg(&mut cpu, 0x000), 0x7472_6976, "magic");
+    assert_eq!(reg(&mut cpu, 0x004), 2, "version");
+    assert_eq!(reg(&mut cpu, 0x008), 42, "device ID");
+    assert_eq!(reg(&mut cpu, QUEUE_NUM_MAX), QUEUE_SIZE);
+    assert_eq!(reg(&mut cpu, 0x100), 0xC0F0_0000, "config space");
+    assert_eq!(reg(&mut cpu, 0x108), 0xC0F0_0008);
+
+    assert_eq!(reg(&mut cpu, DEVICE_FEATURES), 1 << 3);
+    set_reg(&mut cpu, DEVICE_FEATURES_SEL, 1);
+    assert_eq!(reg(&mut cpu, DEVICE_FEATURES), 1, "VERSION_1");
+
+    set_reg(&mut cpu, 0x030, 1);
+    assert_eq!(reg(&mut cpu, QUEUE_NUM_MAX), 0, "only one queue");
+}
+
+#[test]
+fn test_feature_negotiation() {
+    // (low features, high features, accepted)
+    let cases = [(1 << 3, 1, true), (0, 0, false), (1 << 4, 1, false)];
+
+    for (low, high, accepted) in cases {
+        let mut cpu = cpu_with(Reverser::default());
+        set_reg(&mut cpu, DRIVER_FEATURES, low);
+        set_reg(&mut cpu, DRIVER_FEATURES_SEL, 1);
+        set_reg(&mut cpu, DRIVER_FEATURES, high);
+        set_reg(&mut cpu, STATUS, 0xB);
+
+        assert_eq!(
+            reg(&mut cpu, STATUS) & virtio::STATUS_FEATURES_OK != 0,
+            accepted,
+            "{:#x}:{:#x}",
+            high,
+            low
+        );
+    }
+}
+
+#[test]
+fn test_reset() {
+    let device = Reverser::default();
+    let mut cpu = cpu_with(device.clone());
+    bring_up(&mut cpu);
+    assert_eq!(reg(&mut cpu, QUEUE_READY), 1);
+
+    set_reg(&mut cpu, STATUS, 0);
+
+    assert_eq!(reg(&mut cpu, STATUS), 0);
+    assert_eq!(reg(&mut cpu, QUEUE_READY), 0);
+    assert_eq!(reg(&mut cpu, QUEUE_NUM), 0);
+    assert_eq!(*device.resets.borrow(), 1);
+}
+
+// ── Virtqueues ────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_request_round_trip() {
+    let mut cpu = cpu_with(Reverser::default());
+    bring_up(&mut cpu);
+    cpu.bus.write_bytes(0x2000, b"hello, ").unwrap();
+    cpu.bus.write_bytes(0x2100, b"world").unwrap();
+
+    // Two readable buffers, then the answer scattered over two more.
+    write_desc(&mut cpu, 3, 0x2000, 7, 1, 5);
+    write_desc(&mut cpu, 5, 0x2100, 5, 1, 0);
+    write_desc(&mut cpu, 0, 0x3000, 4, 3, 1);
+    write_desc(&mut cpu, 1, 0x3100, 16, 2, 0);
+    make_available(&mut cpu, 3);
+
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(1), "used idx");
+    assert_eq!(cpu.bus.read(USED + 4, MemSize::Word), Some(3), "head");
+    assert_eq!(cpu.bus.read(USED + 8, MemSize::Word), Some(12), "length");
+    assert_eq!(&cpu.bus[0x3000..0x3004], b"dlro");
+    assert_eq!(&cpu.bus[0x3100..0x3108], b"w ,olleh");
+}
+
+#[test]
+fn test_used_buffers_interrupt() {
+    let mut cpu = cpu_with(Reverser::default());
+    bring_up(&mut cpu);
+    cpu.csrs.write(csr::MTVEC, 0x400);
+    cpu.csrs.write(csr::MIE, csr::MIP_MEIP);
+    cpu.csrs.write(csr::MSTATUS, csr::MSTATUS_MIE);
+
+    write_desc(&mut cpu, 0, 0x2000, 4, 0, 0);
+    make_available(&mut cpu, 0);
+    assert_eq!(
+        reg(&mut cpu, INTERRUPT_STATUS),
+        virtio::INTERRUPT_USED_BUFFER
+    );
+
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 0x400);
+    assert_eq!(cpu.csrs.read(csr::MCAUSE), 0x8000_000B);
+
+    set_reg(&mut cpu, INTERRUPT_ACK, virtio::INTERRUPT_USED_BUFFER);
+    assert_eq!(reg(&mut cpu, INTERRUPT_STATUS), 0);
+    cpu.step().unwrap();
+    assert_eq!(cpu.csrs.read(csr::MIP) & csr::MIP_MEIP, 0);
+}
+
+#[test]
+fn test_driver_can_suppress_interrupts() {
+    let mut cpu = cpu_with(Reverser::default());
+    bring_up(&mut cpu);
+    cpu.bus.write(AVAIL, MemSize::Half, 1).unwrap();
+
+    write_desc(&mut cpu, 0, 0x2000, 4, 0, 0);
+    make_available(&mut cpu, 0);
+
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(1));
+    assert_eq!(reg(&mut cpu, INTERRUPT_STATUS), 0);
+}
+
+#[test]
+fn test_looping_chain_needs_reset() {
+    let mut cpu = cpu_with(Reverser::default());
+    bring_up(&mut cpu);
+
+    write_desc(&mut cpu, 0, 0x2000, 4, 1, 1);
+    write_desc(&mut cpu, 1, 0x2000, 4, 1, 0);
+    make_available(&mut cpu, 0);
+
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(0));
+    assert_ne!(reg(&mut cpu, STATUS) & virtio::STATUS_NEEDS_RESET, 0);
+    assert_eq!(
+        reg(&mut cpu, INTERRUPT_STATUS),
+        virtio::INTERRUPT_CONFIG_CHANGE
+    );
+}
+
+#[test]
+fn test_nothing_happens_before_driver_ok() {
+    let mut cpu = cpu_with(Reverser::default());
+    set_up_queue(&mut cpu);
+
+    write_desc(&mut cpu, 0, 0x2000, 4, 0, 0);
+    make_available(&mut cpu, 0);
+
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(0));
+}
+
+// ── Block device ──────────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+const T_IN: u32 = 0;
+#[cfg(feature = "std")]
+const T_OUT: u32 = 1;
+#[cfg(feature = "std")]
+const T_GET_ID: u32 = 8;
+
+/// A four-sector image whose sector `n` is filled with `n + 1`.
+#[cfg(feature = "std")]
+fn disk_image(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("{}-{}.img", name, std::process::id()));
+    let bytes: Vec<u8> = (1..=4).flat_map(|n| [n; 512]).collect();
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+/// Send a request for `len` bytes of data at `sector`, returning its status
+/// and the used length. Data to write comes from 0x3000 and data read lands
+/// there.
+#[cfg(feature = "std")]
+fn blk_request(cpu: &mut RiscvCpu, kind: u32, sector: u64, len: u32) -> (u32, u32) {
+    cpu.bus.write(0x2000, MemSize::Word, kind).unwrap();
+    cpu.bus.write(0x2008, MemSize::Word, sector as u32).unwrap();
+    cpu.bus
+        .write(0x200C, MemSize::Word, (sector >> 32) as u32)
+        .unwrap();
+
+    let data_flags = if kind == T_OUT { 1 } else { 3 };
+    write_desc(cpu, 0, 0x2000, 16, 1, 1);
+    write_desc(cpu, 1, 0x3000, len, data_flags, 2);
+    write_desc(cpu, 2, 0x2010, 1, 2, 0);
+    cpu.bus.write(0x2010, MemSize::Byte, 0xFF).unwrap();
+    make_available(cpu, 0);
+
+    let idx = cpu.bus.read(USED + 2, MemSize::Half).unwrap();
+    let elem = USED + 4 + 8 * ((idx - 1) % QUEUE_SIZE);
+    (
+        cpu.bus.read(0x2010, MemSize::Byte).unwrap(),
+        cpu.bus.read(elem + 4, MemSize::Word).unwrap(),
+    )
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_blk_config() {
+    let path = disk_image("blk-config");
+    let mut cpu = cpu_with(VirtioBlk::open(&path, DiskMode::ReadOnly).unwrap());
+
+    assert_eq!(reg(&mut cpu, 0x008), 2, "block device");
+    assert_eq!(reg(&mut cpu, 0x100), 4, "capacity");
+    assert_eq!(reg(&mut cpu, 0x104), 0);
+    assert_ne!(reg(&mut cpu, DEVICE_FEATURES) & 1 << 5, 0, "read-only");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_blk_read() {
+    let path = disk_image("blk-read");
+    let mut cpu = cpu_with(VirtioBlk::open(&path, DiskMode::ReadWrite).unwrap());
+    bring_up(&mut cpu);
+
+    assert_eq!(blk_request(&mut cpu, T_IN, 2, 1024), (0, 1025));
+    assert!(cpu.bus[0x3000..0x3200].iter().all(|&b| b == 3));
+    assert!(cpu.bus[0x3200..0x3400].iter().all(|&b| b == 4));
+
+    assert_eq!(blk_request(&mut cpu, T_IN, 3, 1024).0, 1, "past the end");
+    assert_eq!(blk_request(&mut cpu, T_IN, 0, 100).0, 1, "partial sector");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_blk_write_modes() {
+    // (mode, status, what the guest reads back, what the file holds)
+    let cases = [
+        (DiskMode::ReadWrite, 0, 0xAA, 0xAA),
+        (DiskMode::CopyOnWrite, 0, 0xAA, 2),
+        (DiskMode::ReadOnly, 1, 2, 2),
+    ];
+
+    for (mode, status, reads, file) in cases {
+        let path = disk_image("blk-write");
+        let mut cpu = cpu_with(VirtioBlk::open(&path, mode).unwrap());
+        bring_up(&mut cpu);
+        cpu.bus.write_bytes(0x3000, &[0xAA; 512]).unwrap();
+
+        assert_eq!(
+            blk_request(&mut cpu, T_OUT, 1, 512),
+            (status, 1),
+            "{:?}",
+            mode
+        );
+
+        cpu.bus.write_bytes(0x3000, &[0; 512]).unwrap();
+        blk_request(&mut cpu, T_IN, 1, 512);
+        assert_eq!(cpu.bus[0x3000], reads, "{:?}", mode);
+        assert_eq!(cpu.bus[0x31FF], reads, "{:?}", mode);
+        assert_eq!(std::fs::read(&path).unwrap()[512], file, "{:?}", mode);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_blk_get_id() {
+    let path = disk_image("blk-id");
+    let blk = VirtioBlk::open(&path, DiskMode::ReadOnly)
+        .unwrap()
+        .with_serial("rootfs");
+    let mut cpu = cpu_with(blk);
+    bring_up(&mut cpu);
+
+    assert_eq!(blk_request(&mut cpu, T_GET_ID, 0, 20), (0, 21));
+    assert_eq!(&cpu.bus[0x3000..0x3007], b"rootfs\0");
+
+    assert_eq!(blk_request(&mut cpu, 99, 0, 4).0, 2, "unsupported");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+// ── Network ───────────────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+const GUEST_MAC: [u8; 6] = [0x52, 0x54, 0x00, 0x12, 0x34, 0x56];
+#[cfg(feature = "std")]
+const GUEST_IP: [u8; 4] = [10, 0, 2, 15];
+#[cfg(feature = "std")]
+const GATEWAY_IP: [u8; 4] = [10, 0, 2, 2];
+
+#[cfg(feature = "std")]
+const SYN: u8 = 0x02;
+#[cfg(feature = "std")]
+const ACK: u8 = 0x10;
+#[cfg(feature = "std")]
+const FIN: u8 = 0x01;
+#[cfg(feature = "std")]
+const RST: u8 = 0x04;
+
+#[cfg(feature = "std")]
+fn frame(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0xFF; 6];
+    frame.extend_from_slice(&GUEST_MAC);
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// An IPv4 frame from the guest. The user-mode stack doesn't check
+/// checksums, so they're left as zero.
+#[cfg(feature = "std")]
+fn ip_frame(dst: [u8; 4], proto: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0x45, 0];
+    packet.extend_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0x40, 0, 64, proto, 0, 0]);
+    packet.extend_from_slice(&GUEST_IP);
+    packet.extend_from_slice(&dst);
+    packet.extend_from_slice(payload);
+    frame(0x0800, &packet)
+}
+
+#[cfg(feature = "std")]
+fn udp_frame(src_port: u16, dst: [u8; 4], dst_port: u16, payload: &[u8]) -> Vec<u8> {
+    let mut datagram = src_port.to_be_bytes().to_vec();
+    datagram.extend_from_slice(&dst_port.to_be_bytes());
+    datagram.extend_from_slice(&(8 + payload.len() as u16).to_be_bytes());
+    datagram.extend_from_slice(&[0, 0]);
+    datagram.extend_from_slice(payload);
+    ip_frame(dst, 17, &datagram)
+}
+
+#[cfg(feature = "std")]
+fn tcp_frame(
+    src_port: u16,
+    dst_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut segment = src_port.to_be_bytes().to_vec();
+    segment.extend_from_slice(&dst_port.to_be_bytes());
+    segment.extend_from_slice(&seq.to_be_bytes());
+    segment.extend_from_slice(&ack.to_be_bytes());
+    segment.extend_from_slice(&[5 << 4, flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+    segment.extend_from_slice(payload);
+    ip_frame(GATEWAY_IP, 6, &segment)
+}
+
+/// The internet checksum over `data`; zero if `data` includes a correct one.
+#[cfg(feature = "std")]
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Poll `net` until it has a frame for the guest.
+#[cfg(feature = "std")]
+fn next_frame(net: &mut UserNet) -> Vec<u8> {
+    let start = Instant::now();
+    loop {
+        if let Some(frame) = net.recv() {
+            return frame;
+        }
+        assert!(start.elapsed() < Duration::from_secs(5), "no frame arrived");
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+/// (flags, seq, ack, payload) of a TCP frame to the guest, after checking
+/// its checksums.
+#[cfg(feature = "std")]
+fn tcp_fields(frame: &[u8]) -> (u8, u32, u32, Vec<u8>) {
+    let ip = &frame[14..];
+    assert_eq!(checksum(&ip[..20]), 0, "IP header checksum");
+    assert_eq!(ip[9], 6);
+
+    let segment = &ip[20..];
+    let mut pseudo = ip[12..20].to_vec();
+    pseudo.extend_from_slice(&[0, 6]);
+    pseudo.extend_from_slice(&(segment.len() as u16).to_be_bytes());
+    pseudo.extend_from_slice(segment);
+    assert_eq!(checksum(&pseudo), 0, "TCP checksum");
+
+    let header = (segment[12] >> 4) as usize * 4;
+    let word = |at: usize| u32::from_be_bytes(segment[at..at + 4].try_into().unwrap());
+    (segment[13], word(4), word(8), segment[header..].to_vec())
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_net_config() {
+    let mut cpu = cpu_with(VirtioNet::new(UserNet::new()));
+
+    assert_eq!(reg(&mut cpu, 0x008), 1, "network card");
+    assert_ne!(reg(&mut cpu, DEVICE_FEATURES) & 1 << 5, 0, "has a MAC");
+    assert_eq!(reg(&mut cpu, 0x100), 0x1200_5452);
+    assert_eq!(reg(&mut cpu, 0x104) & 0xFFFF, 0x5634);
+
+    set_reg(&mut cpu, 0x030, 1);
+    assert_eq!(reg(&mut cpu, QUEUE_NUM_MAX), 256, "a transmit queue");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_arp_through_the_device() {
+    const TX_DESC: u32 = 0x1400;
+    const TX_AVAIL: u32 = 0x1500;
+    const TX_USED: u32 = 0x1600;
+
+    let mut cpu = cpu_with(VirtioNet::new(UserNet::new()));
+    set_up_queue(&mut cpu);
+    set_reg(&mut cpu, 0x030, 1);
+    set_reg(&mut cpu, QUEUE_NUM, QUEUE_SIZE);
+    set_reg(&mut cpu, 0x080, TX_DESC);
+    set_reg(&mut cpu, 0x090, TX_AVAIL);
+    set_reg(&mut cpu, 0x0A0, TX_USED);
+    set_reg(&mut cpu, QUEUE_READY, 1);
+    set_reg(&mut cpu, STATUS, 0xF);
+
+    // A receive buffer on queue 0.
+    write_desc(&mut cpu, 0, 0x3000, 1526, 2, 0);
+    make_available(&mut cpu, 0);
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(0));
+
+    // Who has 10.0.2.2?
+    let mut arp = vec![0, 1, 8, 0, 6, 4, 0, 1];
+    arp.extend_from_slice(&GUEST_MAC);
+    arp.extend_from_slice(&GUEST_IP);
+    arp.extend_from_slice(&[0; 6]);
+    arp.extend_from_slice(&GATEWAY_IP);
+    let mut packet = vec![0; 12];
+    packet.extend_from_slice(&frame(0x0806, &arp));
+    cpu.bus.write_bytes(0x2000, &packet).unwrap();
+
+    cpu.bus.write(TX_DESC, MemSize::Word, 0x2000).unwrap();
+    cpu.bus
+        .write(TX_DESC + 8, MemSize::Word, packet.len() as u32)
+        .unwrap();
+    cpu.bus.write(TX_AVAIL + 2, MemSize::Half, 1).unwrap();
+    set_reg(&mut cpu, QUEUE_NOTIFY, 1);
+
+    assert_eq!(cpu.bus.read(TX_USED + 2, MemSize::Half), Some(1), "sent");
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(1), "received");
+    assert_eq!(cpu.bus.read(USED + 8, MemSize::Word), Some(12 + 42));
+    assert_eq!(cpu.bus.read(0x300A, MemSize::Half), Some(1), "num_buffers");
+
+    let reply = &cpu.bus[0x300C..0x300C + 42];
+    assert_eq!(&reply[0..6], &GUEST_MAC);
+    assert_eq!(&reply[12..14], &[8, 6]);
+    assert_eq!(&reply[20..22], &[0, 2], "an ARP reply");
+    assert_eq!(&reply[28..32], &GATEWAY_IP);
+    assert_eq!(
+        reg(&mut cpu, INTERRUPT_STATUS),
+        virtio::INTERRUPT_USED_BUFFER
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_user_net_ping_and_dhcp() {
+    let mut net = UserNet::new();
+
+    net.send(&ip_frame(
+        GATEWAY_IP,
+        1,
+        &[8, 0, 0, 0, 0x12, 0x34, 0, 1, 0xAB],
+    ));
+    let reply = next_frame(&mut net);
+    let icmp = &reply[34..];
+    assert_eq!(icmp[0], 0, "echo reply");
+    assert_eq!(&icmp[4..], &[0x12, 0x34, 0, 1, 0xAB]);
+    assert_eq!(checksum(icmp), 0);
+
+    let mut discover = vec![0; 240];
+    discover[0] = 1;
+    discover[4..8].copy_from_slice(&[1, 2, 3, 4]);
+    discover[28..34].copy_from_slice(&GUEST_MAC);
+    discover[236..240].copy_from_slice(&[99, 130, 83, 99]);
+    discover.extend_from_slice(&[53, 1, 1, 255]);
+    net.send(&udp_frame(68, [255; 4], 67, &discover));
+
+    let offer = next_frame(&mut net);
+    let dhcp = &offer[42..];
+    assert_eq!(&offer[0..6], &[0xFF; 6]);
+    assert_eq!(dhcp[0], 2);
+    assert_eq!(&dhcp[4..8], &[1, 2, 3, 4], "xid");
+    assert_eq!(&dhcp[16..20], &GUEST_IP, "yiaddr");
+    assert_eq!(&dhcp[240..243], &[53, 1, 2], "an offer");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_user_net_udp() {
+    let host = UdpSocket::bind("127.0.0.1:0").unwrap();
+    host.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let port = host.local_addr().unwrap().port();
+    let mut net = UserNet::new();
+
+    net.send(&udp_frame(5000, GATEWAY_IP, port, b"question"));
+    let mut buf = [0; 64];
+    let (n, guest) = host.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"question");
+
+    host.send_to(b"answer", guest).unwrap();
+    let reply = next_frame(&mut net);
+    assert_eq!(&reply[26..30], &GATEWAY_IP, "from the gateway");
+    assert_eq!(&reply[30..34], &GUEST_IP);
+    assert_eq!(u16::from_be_bytes([reply[34], reply[35]]), port);
+    assert_eq!(u16::from_be_bytes([reply[36], reply[37]]), 5000);
+    assert_eq!(&reply[42..], b"answer");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_user_net_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut net = UserNet::new();
+
+    net.send(&tcp_frame(40000, port, 100, 0, SYN, &[]));
+    let (mut host, _) = listener.accept().unwrap();
+    host.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+    let (flags, isn, ack, _) = tcp_fields(&next_frame(&mut net));
+    assert_eq!(flags, SYN | ACK);
+    assert_eq!(ack, 101);
+
+    net.send(&tcp_frame(40000, port, 101, isn + 1, ACK, b"ping"));
+    let (flags, _, ack, _) = tcp_fields(&next_frame(&mut net));
+    assert_eq!((flags, ack), (ACK, 105));
+    let mut buf = [0; 4];
+    host.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"ping");
+
+    host.write_all(b"pong").unwrap();
+    let (_, seq, _, data) = tcp_fields(&next_frame(&mut net));
+    assert_eq!((seq, data.as_slice()), (isn + 1, &b"pong"[..]));
+
+    net.send(&tcp_frame(40000, port, 105, isn + 5, ACK | FIN, &[]));
+    let (flags, _, ack, _) = tcp_fields(&next_frame(&mut net));
+    assert_eq!((flags, ack), (ACK, 106));
+
+    drop(host);
+    let (flags, seq, _, _) = tcp_fields(&next_frame(&mut net));
+    assert_eq!((flags, seq), (FIN | ACK, isn + 5));
+    net.send(&tcp_frame(40000, port, 106, isn + 6, ACK, &[]));
+    assert!(!net.active(), "the connection is gone");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_user_net_tcp_refused() {
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+    let mut net = UserNet::new();
+
+    net.send(&tcp_frame(40001, port, 7, 0, SYN, &[]));
+    let (flags, _, ack, _) = tcp_fields(&next_frame(&mut net));
+    assert_eq!((flags, ack), (RST | ACK, 8));
+}
+
+// ── Input ─────────────────────────────────────────────────────────────────────
+
+/// Post `count` eight-byte event buffers at 0x3000 on, as descriptors 0 on.
+fn post_event_buffers(cpu: &mut RiscvCpu, count: u32) {
+    for i in 0..count {
+        write_desc(cpu, i, 0x3000 + 8 * i, 8, 2, 0);
+        make_available(cpu, i);
+    }
+}
+
+fn event_at(cpu: &mut RiscvCpu, addr: u32) -> (u32, u32, u32) {
+    let word = cpu.bus.read(addr, MemSize::Word).unwrap();
+    let value = cpu.bus.read(addr + 4, MemSize::Word).unwrap();
+    (word & 0xFFFF, word >> 16, value)
+}
+
+#[test]
+fn test_input_config() {
+    let mut cpu = cpu_with(VirtioInput::keyboard());
+    assert_eq!(reg(&mut cpu, 0x008), 18, "input device");
+
+    cpu.bus.write(BASE + 0x100, MemSize::Byte, 1).unwrap();
+    let size = cpu.bus.read(BASE + 0x102, MemSize::Byte).unwrap();
+    let name: Vec<u8> = (0..size)
+        .map(|i| cpu.bus.read(BASE + 0x108 + i, MemSize::Byte).unwrap() as u8)
+        .collect();
+    assert_eq!(name, b"virtio-keyboard");
+
+    // EV_BITS for EV_KEY: every key but KEY_RESERVED.
+    cpu.bus.write(BASE + 0x100, MemSize::Half, 0x0111).unwrap();
+    assert_eq!(cpu.bus.read(BASE + 0x102, MemSize::Byte), Some(32));
+    assert_eq!(cpu.bus.read(BASE + 0x108, MemSize::Byte), Some(0xFE));
+
+    cpu.bus.write(BASE + 0x101, MemSize::Byte, 2).unwrap();
+    assert_eq!(
+        cpu.bus.read(BASE + 0x102, MemSize::Byte),
+        Some(0),
+        "no EV_REL"
+    );
+}
+
+#[test]
+fn test_key_events_wait_for_buffers() {
+    let keyboard = VirtioInput::keyboard();
+    let handle = keyboard.handle();
+    let mut cpu = cpu_with(keyboard);
+    bring_up(&mut cpu);
+
+    handle.key(30, true);
+    post_event_buffers(&mut cpu, 1);
+    assert_eq!(event_at(&mut cpu, 0x3000), (1, 30, 1), "KEY_A down");
+
+    post_event_buffers(&mut cpu, 2);
+    assert_eq!(event_at(&mut cpu, 0x3000), (0, 0, 0), "then a sync");
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(2));
+}
+
+#[test]
+fn test_mouse_events_arrive_while_running() {
+    let mouse = VirtioInput::mouse();
+    let handle = mouse.handle();
+    let mut cpu = cpu_with(mouse);
+    bring_up(&mut cpu);
+    post_event_buffers(&mut cpu, 3);
+
+    handle.move_by(5, -2);
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(0));
+    cpu.bus.tick(1024);
+
+    assert_eq!(cpu.bus.read(USED + 2, MemSize::Half), Some(3));
+    assert_eq!(event_at(&mut cpu, 0x3000), (2, input::REL_X as u32, 5));
+    assert_eq!(
+        event_at(&mut cpu, 0x3008),
+        (2, input::REL_Y as u32, -2i32 as u32)
+    );
+    assert_eq!(
+        reg(&mut cpu, INTERRUPT_STATUS),
+        virtio::INTERRUPT_USED_BUFFER
+    );
+}
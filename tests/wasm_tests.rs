@@ -0,0 +1,46 @@
+#![cfg(feature = "wasm")]
+
+mod common;
+
+use riscv_emulator_rust::wasm::Emulator;
+
+use common::image;
+
+#[test]
+fn test_load_and_step() {
+    let mut emulator = Emulator::new(0x1000).unwrap();
+    emulator
+        .load(&image(
+            "
+            addi t0, zero, 5
+            sw   t0, 0x100(zero)
+            ebreak
+            ",
+        ))
+        .unwrap();
+
+    assert_eq!(emulator.step(), "");
+    assert_eq!(emulator.pc(), 4);
+    assert_eq!(emulator.regs()[5], 5);
+    assert_eq!(emulator.run(10), "EBREAK at 0x8");
+    assert_eq!(emulator.read_memory(0x100, 4).unwrap(), [5, 0, 0, 0]);
+}
+
+#[test]
+fn test_run_uses_up_its_steps() {
+    let mut emulator = Emulator::new(0x1000).unwrap();
+    emulator.load(&image("loop: jal zero, loop")).unwrap();
+
+    assert_eq!(emulator.run(100), "");
+    assert_eq!(emulator.pc(), 0);
+}
+
+#[test]
+fn test_memory_outside_ram_is_an_error() {
+    let mut emulator = Emulator::new(0x1000).unwrap();
+
+    assert!(emulator.read_memory(0xFFC, 8).is_err());
+    assert!(emulator.write_memory(0x2000, &[1]).is_err());
+    emulator.write_memory(0x10, &[1, 2]).unwrap();
+    assert_eq!(emulator.read_memory(0x10, 2).unwrap(), [1, 2]);
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::devices::Clint;
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu};
+
+use common::{image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+/// `main` at 0 and a trap handler at 0x100, with a CLINT for one hart.
+fn cpu_with(main: &str, handler: &str, engine: Engine) -> RiscvCpu {
+    let mut cpu = program(main)
+        .image(0x100, image(handler))
+        .device(Clint::BASE, Clint::SIZE, Clint::new(1))
+        .engine(engine)
+        .build()
+        .unwrap();
+    cpu.csrs.write(csr::MTVEC, 0x100);
+    cpu
+}
+
+fn mtime(cpu: &mut RiscvCpu) -> u64 {
+    let low = cpu.bus.read(0x0200_BFF8, MemSize::Word).unwrap() as u64;
+    let high = cpu.bus.read(0x0200_BFFC, MemSize::Word).unwrap() as u64;
+    (high << 32) | low
+}
+
+/// Arms the timer about a million ticks out, enables it and waits.
+#[cfg(feature = "std")]
+const SLEEP: &str = "
+            lui   t0, 0x200c
+            lw    t1, -8(t0)
+            lui   t2, 0xf4
+            add   t1, t1, t2
+            lui   t0, 0x2004
+            sw    t1, 0(t0)
+            sw    zero, 4(t0)
+            addi  t3, zero, 0x80
+            csrrs zero, mie, t3
+            csrrsi zero, mstatus, 8
+            wfi
+            ebreak
+";
+
+const WOKEN: &str = "addi a0, zero, 1\nebreak";
+
+// ── Fast-forwarding ───────────────────────────────────────────────────────────
+
+#[cfg(feature = "std")]
+#[test]
+fn test_wfi_skips_ahead_to_the_timer() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = cpu_with(SLEEP, WOKEN, engine);
+        cpu.perf_start();
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x104)),
+            "{:?}",
+            engine
+        );
+        assert_eq!(cpu.regs[10], 1);
+        assert_eq!(cpu.csrs.read(csr::MEPC), 0x2C, "woke after the wfi");
+        assert!(mtime(&mut cpu) >= 0xF4000);
+        assert!(cpu.perf_stats().instructions < 20, "didn't spin");
+    }
+}
+
+#[test]
+fn test_wfi_with_nothing_scheduled_is_idle() {
+    let mut cpu = cpu_with("wfi\naddi a0, zero, 5\nebreak", WOKEN, Engine::Interpreter);
+    cpu.csrs.write(csr::MIE, csr::MIP_MSIP);
+
+    assert_eq!(cpu.run(), ExitReason::Idle);
+    assert_eq!(cpu.pc, 0x4);
+    assert!(cpu.is_waiting());
+
+    // With mstatus.MIE clear the interrupt wakes the hart but isn't taken.
+    cpu.raise_interrupt(Interrupt::MachineSoftware);
+    assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(0x8)));
+    assert_eq!(cpu.regs[10], 5);
+}
+
+#[test]
+fn test_interrupt_disabled_in_mie_does_not_wake() {
+    let mut cpu = cpu_with("wfi\nebreak", WOKEN, Engine::Interpreter);
+    cpu.raise_interrupt(Interrupt::MachineSoftware);
+
+    assert_eq!(cpu.run(), ExitReason::Idle);
+}
+
+#[test]
+fn test_stepping_a_waiting_hart_lets_time_pass() {
+    let mut cpu = cpu_with("wfi\nebreak", WOKEN, Engine::Interpreter);
+    cpu.step().unwrap();
+
+    for _ in 0..9 {
+        assert_eq!(cpu.step(), Ok(riscv_emulator_rust::StepOutcome::Executed));
+    }
+
+    assert_eq!(cpu.pc, 0x4);
+    assert_eq!(mtime(&mut cpu), 10);
+}
+
+// ── CLINT ─────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_mtime_counts_instructions() {
+    let mut cpu = cpu_with("top: jal zero, top", WOKEN, Engine::BasicBlocks);
+
+    cpu.run_steps(1234);
+
+    assert_eq!(mtime(&mut cpu), 1234);
+}
+
+#[test]
+fn test_timer_interrupts_a_busy_loop_on_time() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = cpu_with(
+            "
+                    lui   t0, 0x2004
+                    addi  t1, zero, 100
+                    sw    t1, 0(t0)
+                    sw    zero, 4(t0)
+                    addi  t3, zero, 0x80
+                    csrrs zero, mie, t3
+                    csrrsi zero, mstatus, 8
+            spin:   addi  a1, a1, 1
+                    jal   zero, spin
+            ",
+            "lui t0, 0x200c\nlw a0, -8(t0)\nebreak",
+            engine,
+        );
+
+        cpu.run();
+
+        // Seven setup instructions, then the loop until mtime reaches 100.
+        assert_eq!(cpu.regs[11], 47, "{:?}", engine);
+        // Blocks only move time forward when they finish.
+        assert!((101..=102).contains(&cpu.regs[10]), "{:?}", engine);
+    }
+}
+
+#[test]
+fn test_msip_raises_a_software_interrupt() {
+    let mut cpu = cpu_with(
+        "
+        addi  t3, zero, 8
+        csrrs zero, mie, t3
+        csrrsi zero, mstatus, 8
+        lui   t0, 0x2000
+        addi  t1, zero, 1
+        sw    t1, 0(t0)
+        addi  a0, zero, 2
+        ebreak
+        ",
+        WOKEN,
+        Engine::Interpreter,
+    );
+
+    assert_eq!(
+        cpu.run(),
+        ExitReason::Exception(Exception::Breakpoint(0x104))
+    );
+    assert_eq!(
+        cpu.csrs.read(csr::MCAUSE),
+        Interrupt::MachineSoftware.cause()
+    );
+}
+
+#[test]
+fn test_clint_registers() {
+    use riscv_emulator_rust::devices::Device;
+
+    let mut clint = Clint::new(2);
+    clint.write(0x4008, MemSize::Word, 0x10);
+    clint.write(0x400C, MemSize::Word, 0);
+    clint.write(0x0004, MemSize::Word, 1);
+
+    assert_eq!(clint.mtimecmp(1), 0x10);
+    assert_eq!(clint.mtimecmp(0), u64::MAX);
+    assert_eq!(clint.interrupts(1), csr::MIP_MSIP);
+    assert_eq!(clint.next_event(), Some(0x10));
+
+    clint.tick(0x10);
+    assert_eq!(clint.interrupts(1), csr::MIP_MSIP | csr::MIP_MTIP);
+    assert_eq!(clint.interrupts(0), 0);
+    assert_eq!(clint.next_event(), None);
+    assert_eq!(clint.read(0xBFF8, MemSize::Byte), 0x10);
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::csr;
+use riscv_emulator_rust::decode::decode;
+use riscv_emulator_rust::trap::{Exception, Interrupt};
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu};
+
+use common::{image, program};
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+fn cpu_with(source: &str, engine: Engine) -> RiscvCpu {
+    program(source).engine(engine).build().unwrap()
+}
+
+/// Polls the flag at 0x1000 until it's nonzero, waiting between reads.
+const POLL: &str = "
+            lui   a0, 1
+    poll:   lr.w  t0, (a0)
+            bne   t0, zero, done
+            wrs.nto
+            jal   zero, poll
+    done:   ebreak
+";
+
+// ── Waiting ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_wrs_without_a_reservation_does_nothing() {
+    let mut cpu = cpu_with(
+        "wrs.nto\nwrs.sto\naddi a0, zero, 1\nebreak",
+        Engine::Interpreter,
+    );
+
+    assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(0xC)));
+    assert_eq!(cpu.regs[10], 1);
+}
+
+#[test]
+fn test_wrs_nto_waits_for_the_reservation_to_go() {
+    for engine in [Engine::Interpreter, Engine::BasicBlocks] {
+        let mut cpu = cpu_with(POLL, engine);
+
+        assert_eq!(cpu.run(), ExitReason::Idle, "{:?}", engine);
+        assert!(cpu.is_waiting());
+        assert_eq!(cpu.pc, 0x10);
+
+        cpu.bus.write(0x1000, MemSize::Word, 1).unwrap();
+        assert!(!cpu.is_waiting());
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x14))
+        );
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn test_wrs_sto_gives_up_after_a_while() {
+    let mut cpu = cpu_with(
+        "lui a0, 1\nlr.w t0, (a0)\nwrs.sto\nebreak",
+        Engine::Interpreter,
+    );
+    cpu.perf_start();
+
+    assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(0xC)));
+    assert!(cpu.bus.time() >= 64);
+    assert!(cpu.perf_stats().instructions < 10, "didn't spin");
+}
+
+#[test]
+fn test_an_interrupt_ends_the_wait() {
+    let mut cpu = cpu_with(POLL, Engine::Interpreter);
+    cpu.csrs.write(csr::MIE, csr::MIP_MSIP);
+    cpu.run();
+
+    cpu.raise_interrupt(Interrupt::MachineSoftware);
+
+    assert!(!cpu.is_waiting());
+    cpu.step().unwrap();
+    assert_eq!(cpu.pc, 0x4, "back to polling, interrupt not taken");
+}
+
+#[test]
+fn test_another_hart_wakes_a_poller() {
+    let mut machine = RiscvCpu::builder()
+        .image(
+            0,
+            image(
+                "
+                csrrs t0, mhartid, zero
+                beq   t0, zero, poll
+                addi  t1, zero, 50
+        delay:  addi  t1, t1, -1
+                bne   t1, zero, delay
+                lui   a0, 1
+                addi  t1, zero, 9
+                sw    t1, 0(a0)
+        spin:   jal   zero, spin
+        poll:   lui   a0, 1
+        again:  lr.w  a1, (a0)
+                bne   a1, zero, done
+                wrs.nto
+                jal   zero, again
+        done:   ebreak
+                ",
+            ),
+        )
+        .build_machine(2)
+        .unwrap();
+
+    // Parked after its wrs.nto while hart 1 counts down.
+    machine.run_steps(20);
+    assert_eq!(machine.hart(0).pc, 0x34);
+
+    assert_eq!(
+        machine.run(),
+        (0, ExitReason::Exception(Exception::Breakpoint(0x38)))
+    );
+    assert_eq!(machine.hart(0).regs[11], 9);
+}
+
+// ── Encoding ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_wrs_encodings() {
+    assert_eq!(
+        assemble("wrs.nto\nwrs.sto").unwrap(),
+        [0x00D0_0073, 0x01D0_0073]
+    );
+    assert_eq!(decode(0x00D0_0073).unwrap().to_string(), "wrs.nto");
+    assert_eq!(decode(0x01D0_0073).unwrap().to_string(), "wrs.sto");
+}
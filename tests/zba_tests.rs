@@ -0,0 +1,153 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::decode::{DecodeError, Instruction, decode, decode_rv64};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, MemSize, RiscvCpu};
+
+use common::image;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::BasicBlocks];
+
+// ── RV32 ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_shift_and_add() {
+    for engine in ENGINES {
+        let mut cpu = RiscvCpu::builder()
+            .image(
+                0,
+                image(
+                    "
+                    addi   t0, zero, 5
+                    lui    t1, 0x1
+                    sh1add a0, t0, t1
+                    sh2add a1, t0, t1
+                    sh3add a2, t0, t1
+                    addi   t2, zero, -1
+                    sh3add a3, t2, t1
+                    ebreak
+                    ",
+                ),
+            )
+            .engine(engine)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            cpu.run(),
+            ExitReason::Exception(Exception::Breakpoint(0x1C))
+        );
+        assert_eq!(cpu.regs[10], 0x100A, "{:?}", engine);
+        assert_eq!(cpu.regs[11], 0x1014, "{:?}", engine);
+        assert_eq!(cpu.regs[12], 0x1028, "{:?}", engine);
+        assert_eq!(cpu.regs[13], 0xFF8, "wraps at 32 bits");
+    }
+}
+
+#[test]
+fn test_indexing_an_array() {
+    let mut cpu = RiscvCpu::builder()
+        .image(
+            0,
+            image(
+                "
+                    lui    a0, 0x1
+                    addi   t0, zero, 3
+                    addi   t1, zero, 42
+                    sh2add t2, t0, a0
+                    sw     t1, 0(t2)
+                    ebreak
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+
+    cpu.run();
+
+    assert_eq!(cpu.bus.read(0x100C, MemSize::Word), Some(42));
+}
+
+#[test]
+fn test_uw_forms_are_rv64_only() {
+    let add_uw = assemble("add.uw a0, a1, a2").unwrap()[0];
+    let slli_uw = assemble("slli.uw a0, a1, 3").unwrap()[0];
+
+    assert_eq!(decode(add_uw), Err(DecodeError::UnknownOpcode(add_uw)));
+    assert_eq!(decode(slli_uw), Err(DecodeError::UnknownOpcode(slli_uw)));
+    assert_eq!(
+        decode(assemble("sh3add a0, a1, a2").unwrap()[0]),
+        Ok(Instruction::Sh3add {
+            rd: 10,
+            rs1: 11,
+            rs2: 12
+        })
+    );
+}
+
+// ── RV64 ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_uw_forms_zero_extend_the_index() {
+    for engine in ENGINES {
+        let mut cpu = RiscvCpu::builder()
+            .xlen::<Rv64>()
+            .ram_size(0x1000)
+            .image(
+                0,
+                image(
+                    "
+                    addi      t0, zero, -1
+                    addi      t1, zero, 16
+                    add.uw    a0, t0, t1
+                    sh1add.uw a1, t0, t1
+                    sh2add.uw a2, t0, t1
+                    sh3add.uw a3, t0, t1
+                    sh3add    a4, t0, t1
+                    slli.uw   a5, t0, 40
+                    ebreak
+                    ",
+                ),
+            )
+            .engine(engine)
+            .build()
+            .unwrap();
+
+        cpu.run();
+
+        assert_eq!(cpu.regs[10], 0x1_0000_000F, "{:?}", engine);
+        assert_eq!(cpu.regs[11], 0x1_FFFF_FFFE + 16);
+        assert_eq!(cpu.regs[12], 0x3_FFFF_FFFC + 16);
+        assert_eq!(cpu.regs[13], 0x7_FFFF_FFF8 + 16);
+        assert_eq!(cpu.regs[14], 8, "the plain form uses all 64 bits");
+        assert_eq!(cpu.regs[15], 0xFFFF_FF00_0000_0000);
+    }
+}
+
+#[test]
+fn test_rv64_decoding() {
+    let word = assemble("slli.uw a0, a1, 40").unwrap()[0];
+
+    assert_eq!(
+        decode_rv64(word),
+        Ok(Instruction::SlliUw {
+            rd: 10,
+            rs1: 11,
+            shamt: 40
+        })
+    );
+    assert_eq!(
+        decode_rv64(word).unwrap().to_string(),
+        "slli.uw x10, x11, 40"
+    );
+    assert_eq!(
+        decode_rv64(assemble("sh1add.uw a0, a1, a2").unwrap()[0])
+            .unwrap()
+            .to_string(),
+        "sh1add.uw x10, x11, x12"
+    );
+}
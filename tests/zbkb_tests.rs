@@ -0,0 +1,128 @@
+mod common;
+
+use riscv_emulator_rust::asm::assemble;
+use riscv_emulator_rust::decode::{DecodeError, decode, decode_rv64};
+use riscv_emulator_rust::trap::Exception;
+use riscv_emulator_rust::xlen::Rv64;
+use riscv_emulator_rust::{Engine, ExitReason, RiscvCpu};
+
+use common::image;
+
+// ── Helpers ───────────────────────────────────────────────────────────────────
+
+const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::BasicBlocks];
+
+// ── RV32 ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_pack() {
+    for engine in ENGINES {
+        let mut cpu = RiscvCpu::builder()
+            .image(
+                0,
+                image(
+                    "
+                    pack  a2, a0, a1
+                    packh a3, a0, a1
+                    pack  a4, a0, zero
+                    ebreak
+                    ",
+                ),
+            )
+            .engine(engine)
+            .build()
+            .unwrap();
+        cpu.regs[10] = 0x1234_ABCD;
+        cpu.regs[11] = 0x5678_EF01;
+
+        assert_eq!(cpu.run(), ExitReason::Exception(Exception::Breakpoint(0xC)));
+        assert_eq!(cpu.regs[12], 0xEF01_ABCD, "{:?}", engine);
+        assert_eq!(cpu.regs[13], 0x01CD);
+        assert_eq!(cpu.regs[14], 0xABCD, "zext.h");
+    }
+}
+
+#[test]
+fn test_permutations() {
+    let mut cpu = RiscvCpu::builder()
+        .image(
+            0,
+            image(
+                "
+                brev8 a1, a0
+                zip   a2, a0
+                unzip a3, a2
+                zip   a4, a5
+                unzip a6, a7
+                ebreak
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+    cpu.regs[10] = 0x0102_0380;
+    cpu.regs[15] = 0xFFFF_0000;
+    cpu.regs[17] = 0x5555_5555;
+
+    cpu.run();
+
+    assert_eq!(cpu.regs[11], 0x8040_C001);
+    assert_eq!(cpu.regs[13], 0x0102_0380, "unzip undoes zip");
+    assert_eq!(cpu.regs[14], 0xAAAA_AAAA);
+    assert_eq!(cpu.regs[16], 0x0000_FFFF);
+}
+
+// ── RV64 ──────────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_rv64_pack_and_brev8() {
+    let mut cpu = RiscvCpu::builder()
+        .xlen::<Rv64>()
+        .image(
+            0,
+            image(
+                "
+                pack  a2, a0, a1
+                packw a3, a0, a1
+                brev8 a4, a0
+                ebreak
+                ",
+            ),
+        )
+        .build()
+        .unwrap();
+    cpu.regs[10] = 0x1111_2222_3333_8001;
+    cpu.regs[11] = 0x5555_6666_7777_FFFF;
+
+    cpu.run();
+
+    assert_eq!(cpu.regs[12], 0x7777_FFFF_3333_8001);
+    assert_eq!(cpu.regs[13], 0xFFFF_FFFF_FFFF_8001, "sign-extended");
+    assert_eq!(cpu.regs[14], 0x8888_4444_CCCC_0180);
+}
+
+// ── Encoding ──────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_zbkb_encodings() {
+    let cases = [
+        ("pack a0, a1, a2", 0x08C5_C533, "pack x10, x11, x12"),
+        ("packh a0, a1, a2", 0x08C5_F533, "packh x10, x11, x12"),
+        ("brev8 a0, a1", 0x6875_D513, "brev8 x10, x11"),
+        ("zip a0, a1", 0x08F5_9513, "zip x10, x11"),
+        ("unzip a0, a1", 0x08F5_D513, "unzip x10, x11"),
+    ];
+
+    for (source, word, text) in cases {
+        assert_eq!(assemble(source).unwrap(), [word], "{}", source);
+        assert_eq!(decode(word).unwrap().to_string(), text);
+    }
+
+    let zip = 0x08F5_9513;
+    assert_eq!(decode_rv64(zip), Err(DecodeError::IllegalInstruction(zip)));
+    let packw = assemble("packw a0, a1, a2").unwrap()[0];
+    assert_eq!(
+        decode_rv64(packw).unwrap().to_string(),
+        "packw x10, x11, x12"
+    );
+}